@@ -0,0 +1,196 @@
+// Reports summarised over fixed periods
+//
+// Fast devices (8 kHz mice) send far more reports than a terminal can show. Aggregating keeps
+// the minimum, maximum and mean of every numeric field over a period and prints one line per
+// period instead of every report.
+
+use std::fmt::Display;
+
+use hid_parser::{unit::Measurement, usage, FlatInputs, InputValue, Parser, Usage};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldStats {
+    pub usage: Usage,
+    pub unit: Option<String>, // of physical values
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl FieldStats {
+    fn new(usage: Usage, unit: Option<String>, value: f64) -> Self {
+        Self {
+            usage,
+            unit,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+impl Display for FieldStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}..{} mean {}",
+            usage::short_name(self.usage),
+            number(self.min),
+            number(self.max),
+            number(self.mean())
+        )?;
+        if let Some(unit) = &self.unit {
+            write!(f, " {}", unit)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Fields of the reports received during one period, in the order first seen
+#[derive(Debug, Default)]
+pub struct Bucket {
+    pub reports: u64,
+    pub fields: Vec<((usize, usize), FieldStats)>, // index in `Parser::reports`, of the value
+}
+
+impl Bucket {
+    pub fn is_empty(&self) -> bool {
+        self.reports == 0
+    }
+}
+
+impl Display for Bucket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<_> = self
+            .fields
+            .iter()
+            .map(|(_, stats)| stats.to_string())
+            .collect();
+
+        write!(f, "{} reports: {}", self.reports, fields.join(", "))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Aggregator {
+    units: bool, // physical values where fields declare a unit
+    bucket: Bucket,
+}
+
+impl Aggregator {
+    pub fn new(units: bool) -> Self {
+        Self {
+            units,
+            bucket: Bucket::default(),
+        }
+    }
+
+    pub fn add(&mut self, parser: &Parser, inputs: &FlatInputs) {
+        let reports = parser.reports();
+        self.bucket.reports += 1;
+
+        for (index, values) in inputs.items() {
+            let report = reports[index];
+            if report.report_type.flags().array() {
+                continue;
+            }
+
+            for (i, input) in values.iter().enumerate() {
+                let measurement = match self.units {
+                    true => Measurement::of(report, input.value),
+                    false => None,
+                };
+                let value = match (measurement, input.value) {
+                    (Some(measurement), _) => measurement.value,
+                    (None, InputValue::UInt(v)) => v as f64,
+                    (None, InputValue::Int(v)) => v as f64,
+                    _ => continue,
+                };
+
+                let fields = &mut self.bucket.fields;
+                match fields.iter_mut().find(|(key, _)| *key == (index, i)) {
+                    Some((_, stats)) => stats.add(value),
+                    None => {
+                        let unit = measurement.map(|m| m.unit.to_string());
+                        fields.push(((index, i), FieldStats::new(input.usage, unit, value)));
+                    }
+                }
+            }
+        }
+    }
+
+    // The bucket so far, starting a new one
+    pub fn take(&mut self) -> Bucket {
+        std::mem::take(&mut self.bucket)
+    }
+}
+
+// Up to two decimals, without trailing zeros
+fn number(value: f64) -> String {
+    let value = format!("{:.2}", value);
+
+    value
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, FlatInputs, InputItemData};
+
+    use super::Aggregator;
+
+    #[test]
+    fn summarises_fields_over_a_period() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage_page(0x09)
+            .usage(0x01)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .usage_page(0x01)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build()
+            .decode();
+
+        let mut aggregator = Aggregator::new(true);
+        let mut inputs = FlatInputs::new();
+        for report in [[1, 4, 0xff], [0, 0xfe, 0], [1, 1, 0]] {
+            parser.parse_input_flat(&report, &mut inputs);
+            aggregator.add(&parser, &inputs);
+        }
+
+        let bucket = aggregator.take();
+        assert_eq!(
+            bucket.to_string(),
+            "3 reports: X -2..4 mean 1, Y -1..0 mean -0.33"
+        );
+        assert!(aggregator.take().is_empty());
+    }
+}
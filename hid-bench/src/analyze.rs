@@ -0,0 +1,515 @@
+// Offline analysis of capture files
+//
+// Summarises every capture in a directory (report counts and rate, reports the descriptor
+// can't make sense of, value ranges of the input fields, markers inserted while recording) and
+// aggregates the summaries, so a lab recording a fleet of devices can compare them at a glance.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+
+use hid_parser::{
+    capture::{Capture, Direction, Marker},
+    usage, FlatInputs, InputValue,
+};
+
+use crate::{
+    html::{Chart, HtmlReport},
+    plot, regimes,
+};
+
+// Columns of the report rate sparkline, each at least a second long
+const RATE_BINS: usize = 60;
+const VALUE_BUCKETS: usize = 10;
+const HTML_RATE_BINS: usize = 600; // at most 100 ms each
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl Range {
+    fn extend(&mut self, other: Range) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+}
+
+fn add_range(fields: &mut BTreeMap<(u16, u16), Range>, usage: (u16, u16), range: Range) {
+    fields
+        .entry(usage)
+        .and_modify(|r| r.extend(range))
+        .or_insert(range);
+}
+
+#[derive(Debug, Default)]
+pub struct CaptureSummary {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub duration: Duration,
+    pub inputs: u64,
+    pub outputs: u64,
+    pub features: u64,
+    pub errors: u64, // input reports the report descriptor doesn't describe
+    pub fields: BTreeMap<(u16, u16), Range>, // by usage
+    pub markers: Vec<Marker>,
+}
+
+impl CaptureSummary {
+    pub fn new(capture: &Capture) -> Self {
+        let mut summary = CaptureSummary {
+            vendor_id: capture.device.vendor_id,
+            product_id: capture.device.product_id,
+            duration: capture
+                .transfers
+                .last()
+                .map(|t| t.timestamp)
+                .unwrap_or_default(),
+            markers: capture.markers.clone(),
+            ..Default::default()
+        };
+
+        for transfer in &capture.transfers {
+            match transfer.direction {
+                Direction::In => summary.inputs += 1,
+                Direction::Out => summary.outputs += 1,
+                Direction::FeatureIn | Direction::FeatureOut => summary.features += 1,
+            }
+        }
+
+        // the values of each input report, none for reports the descriptors don't describe
+        let values = capture.decode_inputs(|_, flat| {
+            let values: Vec<_> = flat
+                .inputs()
+                .iter()
+                .filter_map(|input| {
+                    let value = match input.value {
+                        InputValue::Bool(b) => b as i64,
+                        InputValue::UInt(u) => u as i64,
+                        InputValue::Int(i) => i as i64,
+                        _ => return None, // Selected, None
+                    };
+
+                    Some((input.usage, value))
+                })
+                .collect();
+
+            (!flat.is_empty()).then_some(values)
+        });
+
+        for values in values {
+            let Some(values) = values else {
+                summary.errors += 1;
+                continue;
+            };
+
+            for (usage, value) in values {
+                let range = Range {
+                    min: value,
+                    max: value,
+                };
+                add_range(&mut summary.fields, usage, range);
+            }
+        }
+
+        summary
+    }
+
+    // Input reports per second
+    pub fn rate(&self) -> Option<f64> {
+        let seconds = self.duration.as_secs_f64();
+
+        (seconds > 0.0).then(|| self.inputs as f64 / seconds)
+    }
+}
+
+impl Display for CaptureSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:04x}, {:.1} s, {} inputs",
+            self.vendor_id,
+            self.product_id,
+            self.duration.as_secs_f64(),
+            self.inputs
+        )?;
+        if let Some(rate) = self.rate() {
+            write!(f, " ({:.1}/s)", rate)?;
+        }
+        writeln!(
+            f,
+            ", {} outputs, {} features, {} errors",
+            self.outputs, self.features, self.errors
+        )?;
+
+        write_fields(f, &self.fields)?;
+        for marker in &self.markers {
+            write!(f, "    marker at {:.3} s", marker.timestamp.as_secs_f64())?;
+            match &marker.text {
+                Some(text) => writeln!(f, ": {}", text)?,
+                None => writeln!(f)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_fields(
+    f: &mut std::fmt::Formatter<'_>,
+    fields: &BTreeMap<(u16, u16), Range>,
+) -> std::fmt::Result {
+    for (usage, range) in fields {
+        writeln!(
+            f,
+            "    {}: {}..{}",
+            usage::short_name(*usage),
+            range.min,
+            range.max
+        )?;
+    }
+
+    Ok(())
+}
+
+// Totals over all analysed captures
+#[derive(Debug, Default)]
+pub struct Aggregate {
+    pub captures: u64,
+    pub unreadable: u64,
+    pub inputs: u64,
+    pub outputs: u64,
+    pub features: u64,
+    pub errors: u64,
+    pub rates: Option<Range>, // input reports per second, rounded down and up
+    pub fields: BTreeMap<(u16, u16), Range>,
+}
+
+impl Aggregate {
+    pub fn add(&mut self, summary: &Result<CaptureSummary>) {
+        self.captures += 1;
+
+        let summary = match summary {
+            Ok(summary) => summary,
+            Err(_) => {
+                self.unreadable += 1;
+                return;
+            }
+        };
+
+        self.inputs += summary.inputs;
+        self.outputs += summary.outputs;
+        self.features += summary.features;
+        self.errors += summary.errors;
+
+        if let Some(rate) = summary.rate() {
+            let range = Range {
+                min: rate.floor() as i64,
+                max: rate.ceil() as i64,
+            };
+            match &mut self.rates {
+                Some(rates) => rates.extend(range),
+                None => self.rates = Some(range),
+            }
+        }
+
+        for (usage, range) in &summary.fields {
+            add_range(&mut self.fields, *usage, *range);
+        }
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} captures, {} unreadable",
+            self.captures, self.unreadable
+        )?;
+        writeln!(
+            f,
+            "Inputs: {}, outputs: {}, features: {}, errors: {}",
+            self.inputs, self.outputs, self.features, self.errors
+        )?;
+        if let Some(rates) = self.rates {
+            writeln!(f, "Input rate: {}..{}/s", rates.min, rates.max)?;
+        }
+        if !self.fields.is_empty() {
+            writeln!(f, "Field ranges:")?;
+        }
+
+        write_fields(f, &self.fields)
+    }
+}
+
+// The input report rate over time and the distribution of every field which isn't a flag
+pub fn plots(capture: &Capture) -> String {
+    let mut output = String::new();
+
+    let timestamps = input_timestamps(capture);
+    let duration = timestamps.last().copied().unwrap_or_default();
+    let rates = plot::rate_over_time(
+        &timestamps,
+        duration,
+        RATE_BINS.min(duration.as_secs_f64().ceil() as usize),
+    );
+    if !rates.is_empty() {
+        let min = rates.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = rates.iter().copied().fold(0.0, f64::max);
+        output.push_str(&format!(
+            "Input rate {:.0}..{:.0}/s over {:.1} s:\n{}\n",
+            min,
+            max,
+            duration.as_secs_f64(),
+            plot::sparkline(&rates)
+        ));
+    }
+
+    for (usage, series) in field_series(capture) {
+        let values = series.iter().map(|(_, value)| *value).collect::<Vec<_>>();
+
+        output.push_str(&format!("\n{}:\n", usage::short_name(usage)));
+        output.push_str(&plot::histogram(&values, VALUE_BUCKETS, ""));
+    }
+
+    output
+}
+
+// The summary with charts of the report intervals, the input rate and the field values over time
+pub fn html_report(capture: &Capture, title: &str) -> HtmlReport {
+    let mut report = HtmlReport::new(title);
+    report.text(&CaptureSummary::new(capture).to_string());
+    report.text(&regimes::format(&regimes::by_interface(capture)));
+
+    let timestamps = input_timestamps(capture);
+    report.chart(Chart::Histogram {
+        title: "Input report intervals".to_string(),
+        unit: "ms".to_string(),
+        samples: timestamps
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).as_secs_f64() * 1000.0)
+            .collect(),
+    });
+
+    let duration = timestamps.last().copied().unwrap_or_default();
+    let bins = HTML_RATE_BINS.min((duration.as_secs_f64() * 10.0).ceil() as usize);
+    let bin = duration.as_secs_f64() / bins.max(1) as f64;
+    report.chart(Chart::Lines {
+        title: "Input rate over time".to_string(),
+        x_label: "s".to_string(),
+        y_label: "reports/s".to_string(),
+        traces: vec![(
+            "Input reports".to_string(),
+            plot::rate_over_time(&timestamps, duration, bins)
+                .into_iter()
+                .enumerate()
+                .map(|(index, rate)| ((index as f64 + 0.5) * bin, rate))
+                .collect(),
+        )],
+    });
+
+    report.chart(Chart::Lines {
+        title: "Field values over time".to_string(),
+        x_label: "s".to_string(),
+        y_label: "value".to_string(),
+        traces: field_series(capture)
+            .into_iter()
+            .map(|(usage, series)| {
+                let points = series
+                    .into_iter()
+                    .map(|(timestamp, value)| (timestamp.as_secs_f64(), value))
+                    .collect();
+
+                (usage::short_name(usage), points)
+            })
+            .collect(),
+    });
+
+    report
+}
+
+fn input_timestamps(capture: &Capture) -> Vec<Duration> {
+    capture
+        .transfers
+        .iter()
+        .filter(|transfer| transfer.direction == Direction::In)
+        .map(|transfer| transfer.timestamp)
+        .collect()
+}
+
+// Values of every input field which isn't a flag, with the time they were reported
+fn field_series(capture: &Capture) -> BTreeMap<(u16, u16), Vec<(Duration, f64)>> {
+    let parsers = capture.descriptors.parsers().collect::<HashMap<_, _>>();
+    let mut series: BTreeMap<(u16, u16), Vec<(Duration, f64)>> = BTreeMap::new();
+    let mut flat = FlatInputs::new();
+
+    for transfer in &capture.transfers {
+        if transfer.direction != Direction::In {
+            continue;
+        }
+        if let Some(parser) = parsers.get(&transfer.interface) {
+            parser.parse_input_flat(&transfer.bytes, &mut flat);
+
+            for input in flat.inputs() {
+                let value = match input.value {
+                    InputValue::UInt(u) => u as f64,
+                    InputValue::Int(i) => i as f64,
+                    _ => continue,
+                };
+                series
+                    .entry(input.usage)
+                    .or_default()
+                    .push((transfer.timestamp, value));
+            }
+        }
+    }
+
+    series.retain(|_, series| {
+        !series
+            .iter()
+            .all(|(_, value)| *value == 0.0 || *value == 1.0)
+    });
+
+    series
+}
+
+pub fn read_capture(path: &Path) -> Result<Capture> {
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+
+    Capture::read(BufReader::new(file)).with_context(|| format!("Cannot read {}", path.display()))
+}
+
+pub fn summarize_file(path: &Path) -> Result<CaptureSummary> {
+    Ok(CaptureSummary::new(&read_capture(path)?))
+}
+
+// Summaries of all captures (*.hbc) in the directory, in file name order
+pub fn analyze_dir(dir: &Path) -> Result<Vec<(PathBuf, Result<CaptureSummary>)>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("Cannot read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "hbc") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths
+        .into_par_iter()
+        .map(|path| {
+            let summary = summarize_file(&path);
+            (path, summary)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::{
+        capture::{Capture, DeviceMetadata, Direction, Marker, Transfer},
+        ReportDescriptor,
+    };
+
+    use super::{plots, Aggregate, CaptureSummary, Range};
+
+    // Mouse with one button and a relative X axis
+    const MOUSE: [u8; 42] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29,
+        0x01, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x05, 0x01, 0x09, 0x30,
+        0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x01, 0x81, 0x06, 0xc0, 0xc0,
+    ];
+
+    fn capture(reports: &[&[u8]]) -> Capture {
+        let mut transfers = reports
+            .iter()
+            .enumerate()
+            .map(|(n, bytes)| Transfer {
+                timestamp: Duration::from_millis(10 * n as u64),
+                interface: 0,
+                direction: Direction::In,
+                bytes: bytes.to_vec(),
+            })
+            .collect::<Vec<_>>();
+        transfers.push(Transfer {
+            timestamp: Duration::from_millis(100),
+            interface: 1,
+            direction: Direction::In,
+            bytes: vec![0x01],
+        });
+
+        Capture {
+            device: DeviceMetadata {
+                vendor_id: 0x046d,
+                product_id: 0xc077,
+                ..Default::default()
+            },
+            descriptors: [(
+                0,
+                ReportDescriptor {
+                    bytes: MOUSE.to_vec(),
+                },
+            )]
+            .into_iter()
+            .collect(),
+            transfers,
+            markers: vec![],
+        }
+    }
+
+    #[test]
+    fn summarizes_captures() {
+        let mut capture = capture(&[&[0x01, 0x05], &[0x00, 0x20], &[]]);
+        capture.markers = vec![
+            Marker {
+                timestamp: Duration::from_millis(15),
+                text: Some("pressed reset".to_string()),
+            },
+            Marker::default(),
+        ];
+        let summary = CaptureSummary::new(&capture);
+
+        assert_eq!(summary.inputs, 4);
+        assert_eq!(summary.errors, 2); // empty report and unknown interface
+        assert_eq!(summary.rate(), Some(40.0));
+        assert_eq!(summary.fields[&(0x09, 0x01)], Range { min: 0, max: 1 });
+        assert_eq!(summary.fields[&(0x01, 0x30)], Range { min: 5, max: 32 });
+        assert!(summary
+            .to_string()
+            .ends_with("    marker at 0.015 s: pressed reset\n    marker at 0.000 s\n"));
+    }
+
+    #[test]
+    fn aggregates_summaries() {
+        let mut aggregate = Aggregate::default();
+        aggregate.add(&Ok(CaptureSummary::new(&capture(&[&[0x00, 0x05]]))));
+        aggregate.add(&Ok(CaptureSummary::new(&capture(&[&[0x00, 0x30][..]; 5]))));
+        aggregate.add(&Err(anyhow::anyhow!("Not a capture file")));
+
+        assert_eq!(aggregate.captures, 3);
+        assert_eq!(aggregate.unreadable, 1);
+        assert_eq!(aggregate.inputs, 8);
+        assert_eq!(aggregate.errors, 2);
+        assert_eq!(aggregate.rates, Some(Range { min: 20, max: 60 }));
+        assert_eq!(aggregate.fields[&(0x01, 0x30)], Range { min: 5, max: 48 });
+    }
+
+    #[test]
+    fn plots_rate_and_value_distributions() {
+        let plots = plots(&capture(&[&[0x01, 0x05], &[0x00, 0x20], &[0x00, 0x05]]));
+
+        assert!(plots.starts_with("Input rate 40..40/s over 0.1 s:\n▅\n"));
+        assert!(plots.contains("\nX:\n"));
+        assert!(!plots.contains("Button"));
+    }
+}
@@ -0,0 +1,342 @@
+// Report I/O of an opened interface
+//
+// The device commands read and write reports through `HidIo` rather than hidapi directly.
+// hidapi devices and interfaces claimed through libusb (see `usb`) implement it, and with the
+// `test-backend` feature so do the mock devices replaying captures (see `mock`), which lets the
+// commands run end to end without hardware. Reports piped in (see `piped`) or sent by a hardware
+// sniffer (see `bridge`) are read through it as well.
+//
+// hidapi hands input reports over framed the way the OS driver does: Windows prefixes reports
+// of devices without report IDs with a 0, and some drivers strip the ID of devices with them.
+// `Framed` puts the reads of a device back into the framing of its report descriptor (the ID
+// byte exactly when the descriptor uses IDs), so the parser reads fields at the right offsets
+// whatever the platform. `Framing::frame` splits a report into its ID and payload.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+use hidapi::HidDevice;
+
+use hid_parser::{Parser, ReportKind};
+
+pub trait HidIo {
+    // Reads an input report (starting with the report ID, if the device uses them), 0 when
+    // the timeout passed first. -1 waits forever.
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize>;
+
+    // Reads an input report, waiting for one in blocking mode, 0 without one otherwise
+    fn read(&self, buf: &mut [u8]) -> Result<usize>;
+
+    // Writes an output report, the first byte is the report ID (0 without IDs)
+    fn write(&self, report: &[u8]) -> Result<usize>;
+
+    // Sends a feature report, the first byte is the report ID (0 without IDs)
+    fn send_feature_report(&self, report: &[u8]) -> Result<()>;
+
+    // Reads a feature report (Get_Report), `buf` starts with the report ID to read (0 without
+    // IDs) and is filled in from there on
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize>;
+
+    fn set_blocking_mode(&self, blocking: bool) -> Result<()>;
+
+    // When the last report read went over the wire, by the clock of a hardware sniffer. None
+    // for reports read on the host.
+    fn wire_time(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl HidIo for HidDevice {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        Ok(HidDevice::read_timeout(self, buf, timeout_ms)?)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(HidDevice::read(self, buf)?)
+    }
+
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        Ok(HidDevice::write(self, report)?)
+    }
+
+    fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        Ok(HidDevice::send_feature_report(self, report)?)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(HidDevice::get_feature_report(self, buf)?)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> Result<()> {
+        Ok(HidDevice::set_blocking_mode(self, blocking)?)
+    }
+}
+
+impl<T: HidIo + ?Sized> HidIo for &T {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        (**self).read_timeout(buf, timeout_ms)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        (**self).write(report)
+    }
+
+    fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        (**self).send_feature_report(report)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        (**self).get_feature_report(buf)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> Result<()> {
+        (**self).set_blocking_mode(blocking)
+    }
+
+    fn wire_time(&self) -> Option<Duration> {
+        (**self).wire_time()
+    }
+}
+
+impl<T: HidIo + ?Sized> HidIo for &mut T {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        (**self).read_timeout(buf, timeout_ms)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        (**self).read(buf)
+    }
+
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        (**self).write(report)
+    }
+
+    fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        (**self).send_feature_report(report)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        (**self).get_feature_report(buf)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> Result<()> {
+        (**self).set_blocking_mode(blocking)
+    }
+
+    fn wire_time(&self) -> Option<Duration> {
+        (**self).wire_time()
+    }
+}
+
+// An input report split into its report ID (None for descriptors without IDs) and the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame<'a> {
+    pub report_id: Option<u8>,
+    pub payload: &'a [u8],
+}
+
+// The input report lengths of a descriptor, with the ID byte if it uses IDs. Without a
+// descriptor reports are left as read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Framing {
+    lengths: BTreeMap<Option<u8>, usize>,
+}
+
+impl Framing {
+    pub fn new(parser: &Parser) -> Self {
+        let lengths = parser
+            .reports()
+            .iter()
+            .filter_map(|report| {
+                let length = parser.report_length(ReportKind::Input, report.report_id)?;
+                Some((report.report_id, length))
+            })
+            .collect();
+
+        Self { lengths }
+    }
+
+    fn with_report_ids(&self) -> bool {
+        self.lengths.keys().any(Option::is_some)
+    }
+
+    // The length of the report starting with `first`, None for report IDs not in the
+    // descriptor
+    pub fn length(&self, first: u8) -> Option<usize> {
+        match self.with_report_ids() {
+            true => self.lengths.get(&Some(first)).copied(),
+            false => self.lengths.get(&None).copied(),
+        }
+    }
+
+    // Puts the `n` bytes read into `buf` into the descriptor's framing, returning the new
+    // length. A 0 prefix is dropped from reports one byte longer than the only report of a
+    // descriptor without IDs, an ID is put back in front of reports one byte shorter than the
+    // only report ID of that length. Anything else is left alone.
+    pub fn normalize(&self, buf: &mut [u8], n: usize) -> usize {
+        match self.with_report_ids() {
+            false => match self.lengths.get(&None) {
+                Some(&length) if n == length + 1 && buf[0] == 0 => {
+                    buf.copy_within(1..n, 0);
+                    n - 1
+                }
+                _ => n,
+            },
+            true => {
+                if self.lengths.get(&buf.first().copied()) == Some(&n) {
+                    return n;
+                }
+
+                let mut stripped = self
+                    .lengths
+                    .iter()
+                    .filter(|(_, length)| **length == n + 1)
+                    .filter_map(|(id, _)| *id);
+                match (stripped.next(), stripped.next()) {
+                    (Some(id), None) if n < buf.len() => {
+                        buf.copy_within(0..n, 1);
+                        buf[0] = id;
+                        n + 1
+                    }
+                    _ => n,
+                }
+            }
+        }
+    }
+
+    // A normalized report split into its ID and payload
+    pub fn frame<'a>(&self, report: &'a [u8]) -> Frame<'a> {
+        match (self.with_report_ids(), report.split_first()) {
+            (true, Some((id, payload))) => Frame {
+                report_id: Some(*id),
+                payload,
+            },
+            _ => Frame {
+                report_id: None,
+                payload: report,
+            },
+        }
+    }
+}
+
+// A device whose input reads are normalized to a framing
+pub struct Framed<D: HidIo> {
+    device: D,
+    framing: Framing,
+}
+
+impl<D: HidIo> Framed<D> {
+    pub fn new(device: D, framing: Framing) -> Self {
+        Self { device, framing }
+    }
+
+    pub fn framing(&self) -> &Framing {
+        &self.framing
+    }
+}
+
+impl<D: HidIo> HidIo for Framed<D> {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let n = self.device.read_timeout(buf, timeout_ms)?;
+
+        Ok(self.framing.normalize(buf, n))
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.device.read(buf)?;
+
+        Ok(self.framing.normalize(buf, n))
+    }
+
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        self.device.write(report)
+    }
+
+    fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        self.device.send_feature_report(report)
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        self.device.get_feature_report(buf)
+    }
+
+    fn set_blocking_mode(&self, blocking: bool) -> Result<()> {
+        self.device.set_blocking_mode(blocking)
+    }
+
+    fn wire_time(&self) -> Option<Duration> {
+        self.device.wire_time()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData, Parser};
+
+    use super::{Frame, Framing};
+
+    // X and Y, in report 2 when given
+    fn mouse(report_id: Option<u8>) -> Parser {
+        let builder = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application);
+        let builder = match report_id {
+            Some(id) => builder.report_id(id),
+            None => builder,
+        };
+
+        builder
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    fn normalized(framing: &Framing, report: &[u8]) -> Vec<u8> {
+        let mut buf = [0u8; 8];
+        buf[..report.len()].copy_from_slice(report);
+        let n = framing.normalize(&mut buf, report.len());
+
+        buf[..n].to_vec()
+    }
+
+    #[test]
+    fn normalizes_report_id_prefixes() {
+        let framing = Framing::new(&mouse(None));
+        assert_eq!(normalized(&framing, &[5, 0xfb]), [5, 0xfb]);
+        assert_eq!(normalized(&framing, &[0, 5, 0xfb]), [5, 0xfb]);
+        assert_eq!(normalized(&framing, &[1, 5, 0xfb]), [1, 5, 0xfb]);
+        assert_eq!(
+            framing.frame(&[5, 0xfb]),
+            Frame {
+                report_id: None,
+                payload: &[5, 0xfb]
+            }
+        );
+
+        let framing = Framing::new(&mouse(Some(2)));
+        assert_eq!(normalized(&framing, &[2, 5, 0xfb]), [2, 5, 0xfb]);
+        assert_eq!(normalized(&framing, &[5, 0xfb]), [2, 5, 0xfb]);
+        assert_eq!(normalized(&framing, &[5]), [5]);
+        assert_eq!(
+            framing.frame(&[2, 5, 0xfb]),
+            Frame {
+                report_id: Some(2),
+                payload: &[5, 0xfb]
+            }
+        );
+
+        assert_eq!(normalized(&Framing::default(), &[0, 5, 0xfb]), [0, 5, 0xfb]);
+    }
+}
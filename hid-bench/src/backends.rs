@@ -0,0 +1,341 @@
+// Comparing the reports hidapi and libusb read from the same interface
+//
+// hidapi reads through the OS driver, which has its own idea of report IDs: Windows prefixes
+// reports of devices without IDs with a 0, some platforms strip the ID of devices with them, and
+// OS drivers queue and batch reports. libusb takes the interface over and reads the interrupt
+// endpoint as the device sends it. The interface is read through hidapi first, then claimed and
+// read through libusb, so the device should be used the same way during both windows.
+//
+// Every report is classified against the input report lengths of the descriptor: as described,
+// with a 0 prefix, without its report ID, padded (e.g. to the endpoint's packet size) or
+// unexplained. Differences in the classes, lengths and report intervals of the two backends are
+// reported as discrepancies.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use hid_parser::{Parser, ReportKind};
+
+use crate::{backend::HidIo, latency::spread, usb::ClaimedInterface};
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Median intervals closer than this are the same rate
+const INTERVAL_TOLERANCE: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reads {
+    pub backend: &'static str,
+    pub reports: Vec<(Duration, Vec<u8>)>, // arrival since reading started and the bytes
+}
+
+pub fn read_hidapi(device: &dyn HidIo, size: usize, window: Duration) -> Result<Reads> {
+    read("hidapi", size, window, |buf| {
+        device.read_timeout(buf, READ_TIMEOUT.as_millis() as i32)
+    })
+}
+
+pub fn read_usb(interface: &ClaimedInterface, size: usize, window: Duration) -> Result<Reads> {
+    read("libusb", size, window, |buf| {
+        Ok(interface.read(buf, READ_TIMEOUT)?)
+    })
+}
+
+fn read(
+    backend: &'static str,
+    size: usize,
+    window: Duration,
+    mut read: impl FnMut(&mut [u8]) -> Result<usize>,
+) -> Result<Reads> {
+    let mut buf = vec![0u8; size];
+    let mut reports = vec![];
+
+    let start = Instant::now();
+    while start.elapsed() < window {
+        let n = read(&mut buf)?;
+        if n > 0 {
+            reports.push((start.elapsed(), buf[..n].to_vec()));
+        }
+    }
+
+    Ok(Reads { backend, reports })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Shape {
+    Described,   // an input report of the descriptor, with its ID if it has one
+    ZeroPrefix,  // a report of a descriptor without IDs behind a 0 byte
+    IdStripped,  // a report of a descriptor with IDs missing its ID byte
+    Padded,      // a described report followed by more bytes
+    Unexplained, // none of the above
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shape = match self {
+            Shape::Described => "as described",
+            Shape::ZeroPrefix => "with a 0 report ID prefix",
+            Shape::IdStripped => "without the report ID",
+            Shape::Padded => "padded",
+            Shape::Unexplained => "unexplained",
+        };
+
+        write!(f, "{}", shape)
+    }
+}
+
+// Lengths of the input reports, with the ID byte for descriptors with IDs
+fn input_lengths(parser: &Parser) -> BTreeMap<Option<u8>, usize> {
+    parser
+        .reports()
+        .iter()
+        .filter_map(|report| {
+            let length = parser.report_length(ReportKind::Input, report.report_id)?;
+            Some((report.report_id, length))
+        })
+        .collect()
+}
+
+fn shape(lengths: &BTreeMap<Option<u8>, usize>, bytes: &[u8]) -> Shape {
+    let with_ids = lengths.keys().any(Option::is_some);
+    let first = bytes.first().copied();
+
+    match with_ids {
+        true => match lengths.get(&first) {
+            Some(&length) if bytes.len() == length => Shape::Described,
+            Some(&length) if bytes.len() > length => Shape::Padded,
+            _ if lengths.values().any(|length| bytes.len() + 1 == *length) => Shape::IdStripped,
+            _ => Shape::Unexplained,
+        },
+        false => match lengths.get(&None) {
+            Some(&length) if bytes.len() == length => Shape::Described,
+            Some(&length) if bytes.len() == length + 1 && first == Some(0) => Shape::ZeroPrefix,
+            Some(&length) if bytes.len() > length => Shape::Padded,
+            _ => Shape::Unexplained,
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendSummary {
+    pub backend: &'static str,
+    pub reports: usize,
+    pub lengths: BTreeSet<usize>,
+    pub shapes: BTreeMap<Shape, usize>,
+    pub intervals: Option<(Duration, Duration, Duration)>, // min, median, max
+}
+
+impl BackendSummary {
+    fn new(reads: &Reads, lengths: &BTreeMap<Option<u8>, usize>) -> Self {
+        let mut shapes = BTreeMap::new();
+        for (_, bytes) in &reads.reports {
+            *shapes.entry(shape(lengths, bytes)).or_default() += 1;
+        }
+        let intervals: Vec<_> = reads
+            .reports
+            .windows(2)
+            .map(|pair| pair[1].0 - pair[0].0)
+            .collect();
+
+        BackendSummary {
+            backend: reads.backend,
+            reports: reads.reports.len(),
+            lengths: reads.reports.iter().map(|(_, bytes)| bytes.len()).collect(),
+            shapes,
+            intervals: spread(&intervals),
+        }
+    }
+}
+
+impl Display for BackendSummary {
+    // e.g. `hidapi: 412 reports of 4 bytes, every 8.0 ms (0.1 - 16.2), 412 with a 0 report ID prefix`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lengths: Vec<_> = self.lengths.iter().map(usize::to_string).collect();
+        write!(
+            f,
+            "{}: {} reports of {} bytes",
+            self.backend,
+            self.reports,
+            lengths.join(", ")
+        )?;
+        if let Some((min, median, max)) = self.intervals {
+            write!(
+                f,
+                ", every {:.1} ms ({:.1} - {:.1})",
+                median.as_secs_f64() * 1000.0,
+                min.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0
+            )?;
+        }
+        for (shape, count) in &self.shapes {
+            write!(f, ", {} {}", count, shape)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub summaries: Vec<BackendSummary>,
+    pub discrepancies: Vec<String>,
+}
+
+pub fn compare(parser: &Parser, reads: &[Reads]) -> Comparison {
+    let lengths = input_lengths(parser);
+    let summaries: Vec<_> = reads
+        .iter()
+        .map(|reads| BackendSummary::new(reads, &lengths))
+        .collect();
+
+    let mut discrepancies = vec![];
+    for summary in &summaries {
+        for (shape, count) in &summary.shapes {
+            if *shape != Shape::Described {
+                discrepancies.push(format!(
+                    "{} reads {} of {} reports {}",
+                    summary.backend, count, summary.reports, shape
+                ));
+            }
+        }
+    }
+
+    // backends without any reports were left untouched or failed, there is nothing to compare
+    let read: Vec<_> = summaries.iter().filter(|s| s.reports > 0).collect();
+    for pair in read.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if a.lengths != b.lengths {
+            discrepancies.push(format!(
+                "{} and {} read reports of different lengths",
+                a.backend, b.backend
+            ));
+        }
+        if let (Some((_, a_median, _)), Some((_, b_median, _))) = (a.intervals, b.intervals) {
+            if a_median.abs_diff(b_median) > INTERVAL_TOLERANCE {
+                discrepancies.push(format!(
+                    "{} reads a report every {:.1} ms, {} every {:.1} ms",
+                    a.backend,
+                    a_median.as_secs_f64() * 1000.0,
+                    b.backend,
+                    b_median.as_secs_f64() * 1000.0
+                ));
+            }
+        }
+    }
+
+    Comparison {
+        summaries,
+        discrepancies,
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for summary in &self.summaries {
+            writeln!(f, "{}", summary)?;
+        }
+        match self.discrepancies.is_empty() {
+            true => writeln!(f, "No discrepancies"),
+            false => self
+                .discrepancies
+                .iter()
+                .try_for_each(|discrepancy| writeln!(f, "  {}", discrepancy)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData, Parser};
+
+    use super::{compare, Reads, Shape};
+
+    fn mouse(report_id: Option<u8>) -> Parser {
+        let builder = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application);
+        let builder = match report_id {
+            Some(id) => builder.report_id(id),
+            None => builder,
+        };
+
+        builder
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    fn reads(backend: &'static str, every_ms: u64, reports: &[&[u8]]) -> Reads {
+        Reads {
+            backend,
+            reports: reports
+                .iter()
+                .enumerate()
+                .map(|(n, bytes)| (Duration::from_millis(n as u64 * every_ms), bytes.to_vec()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn finds_report_id_prefixes_and_timing_differences() {
+        // Windows prefixes reports of devices without IDs
+        let comparison = compare(
+            &mouse(None),
+            &[
+                reads("hidapi", 8, &[&[0, 1, 2], &[0, 3, 4]]),
+                reads("libusb", 8, &[&[1, 2], &[3, 4], &[5, 6]]),
+            ],
+        );
+        assert_eq!(
+            comparison.summaries[0].shapes.get(&Shape::ZeroPrefix),
+            Some(&2)
+        );
+        assert_eq!(
+            comparison.discrepancies,
+            [
+                "hidapi reads 2 of 2 reports with a 0 report ID prefix",
+                "hidapi and libusb read reports of different lengths"
+            ]
+        );
+
+        let comparison = compare(
+            &mouse(Some(2)),
+            &[
+                reads("hidapi", 4, &[&[1, 2], &[3, 4], &[5, 6]]),
+                reads("libusb", 1, &[&[2, 1, 2], &[2, 3, 4, 0, 0], &[7, 1, 1]]),
+            ],
+        );
+        assert_eq!(
+            comparison.discrepancies,
+            [
+                "hidapi reads 3 of 3 reports without the report ID",
+                "libusb reads 1 of 3 reports padded",
+                "libusb reads 1 of 3 reports unexplained",
+                "hidapi and libusb read reports of different lengths",
+                "hidapi reads a report every 4.0 ms, libusb every 1.0 ms"
+            ]
+        );
+
+        let same = reads("hidapi", 8, &[&[2, 1, 2], &[2, 3, 4]]);
+        let comparison = compare(&mouse(Some(2)), &[same.clone(), same]);
+        assert!(comparison.discrepancies.is_empty());
+        assert!(comparison
+            .to_string()
+            .starts_with("hidapi: 2 reports of 3 bytes, every 8.0 ms (8.0 - 8.0), 2 as described"));
+    }
+}
@@ -0,0 +1,348 @@
+// Report rates a descriptor and its endpoint allow, against the rates measured
+//
+// Input reports go out on the interrupt IN endpoint, which the host polls every bInterval: in
+// frames of 1 ms at low and full speed, in 2^(bInterval - 1) microframes of 125 µs at high
+// speed. Each poll moves one packet of at most wMaxPacketSize, so a longer report takes a poll
+// per packet. That caps the rate of every report regardless of the firmware:
+//
+//     Input #1: 8 bytes in 1 packet, at most 1000/s, 21.0 kB/s of the bus (1.4%)
+//         measured 998.1/s (100%), limited by the polling interval
+//
+// A measured rate near the cap means the device is limited by its design (interval, packet
+// size or report length), a rate well below it points at the firmware. Rates are measured from
+// the median interval between the reports of a capture, so idle periods don't count.
+
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use hid_parser::{
+    capture::{Capture, Direction},
+    Parser, ReportKind,
+};
+
+// Measured rates at least this share of the cap are limited by the polling interval
+const AT_CAP: f64 = 0.9;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    Low,
+    Full,
+    High,
+}
+
+impl Speed {
+    // Largest wMaxPacketSize of an interrupt endpoint
+    fn max_packet(self) -> usize {
+        match self {
+            Speed::Low => 8,
+            Speed::Full => 64,
+            Speed::High => 1024,
+        }
+    }
+
+    // Bus time of an interrupt transaction besides its data (USB 2.0, section 5.7.3)
+    fn overhead(self) -> usize {
+        match self {
+            Speed::Low | Speed::Full => 13,
+            Speed::High => 55,
+        }
+    }
+
+    fn bytes_per_second(self) -> f64 {
+        match self {
+            Speed::Low => 1.5e6 / 8.0,
+            Speed::Full => 12e6 / 8.0,
+            Speed::High => 480e6 / 8.0,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Speed::Low => "low",
+            Speed::Full => "full",
+            Speed::High => "high",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Endpoint {
+    pub speed: Speed,
+    pub interval: u8, // bInterval
+    pub max_packet: usize,
+}
+
+impl Endpoint {
+    // The largest packets the speed allows without `max_packet`
+    pub fn new(speed: Speed, interval: u8, max_packet: Option<usize>) -> Result<Self> {
+        let intervals = match speed {
+            Speed::Low | Speed::Full => 1..=255,
+            Speed::High => 1..=16,
+        };
+        if !intervals.contains(&interval) {
+            return Err(anyhow!(
+                "bInterval must be between {} and {} at {} speed",
+                intervals.start(),
+                intervals.end(),
+                speed.name()
+            ));
+        }
+
+        let max_packet = max_packet.unwrap_or(speed.max_packet());
+        if max_packet == 0 || max_packet > speed.max_packet() {
+            return Err(anyhow!(
+                "Interrupt packets are 1 to {} bytes at {} speed",
+                speed.max_packet(),
+                speed.name()
+            ));
+        }
+
+        Ok(Self {
+            speed,
+            interval,
+            max_packet,
+        })
+    }
+
+    // Time between polls
+    pub fn period(&self) -> Duration {
+        match self.speed {
+            Speed::Low | Speed::Full => Duration::from_millis(self.interval as u64),
+            Speed::High => Duration::from_micros(125) * (1 << (self.interval - 1)),
+        }
+    }
+}
+
+// What the endpoint allows for one input report, sent on its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportBudget {
+    pub report_id: Option<u8>,
+    pub bytes: usize, // with the report ID
+    pub packets: usize,
+    pub max_rate: f64,         // reports per second
+    pub bandwidth: f64,        // bus bytes per second at the highest rate, with overhead
+    pub measured: Option<f64>, // reports per second
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub endpoint: Endpoint,
+    pub reports: Vec<ReportBudget>,
+}
+
+pub fn estimate(parser: &Parser, endpoint: Endpoint) -> Estimate {
+    let mut report_ids: Vec<_> = parser
+        .reports()
+        .iter()
+        .filter(|report| report.report_type.kind() == ReportKind::Input)
+        .map(|report| report.report_id)
+        .collect();
+    report_ids.sort();
+    report_ids.dedup();
+
+    let polls = 1.0 / endpoint.period().as_secs_f64();
+    let reports = report_ids
+        .into_iter()
+        .filter_map(|report_id| {
+            let bytes = parser.report_length(ReportKind::Input, report_id)?;
+            let packets = bytes.div_ceil(endpoint.max_packet).max(1);
+            let max_rate = polls / packets as f64;
+
+            Some(ReportBudget {
+                report_id,
+                bytes,
+                packets,
+                max_rate,
+                bandwidth: (bytes + packets * endpoint.speed.overhead()) as f64 * max_rate,
+                measured: None,
+            })
+        })
+        .collect();
+
+    Estimate { endpoint, reports }
+}
+
+impl Estimate {
+    // Adds the rates of the input reports of the interface in the capture
+    pub fn measure(&mut self, capture: &Capture, interface: u8) {
+        let report_ids = self.reports.iter().any(|report| report.report_id.is_some());
+
+        let mut arrivals: BTreeMap<Option<u8>, Vec<Duration>> = BTreeMap::new();
+        for transfer in &capture.transfers {
+            if transfer.interface != interface || transfer.direction != Direction::In {
+                continue;
+            }
+            let report_id = match report_ids {
+                true => transfer.bytes.first().copied(),
+                false => None,
+            };
+            arrivals
+                .entry(report_id)
+                .or_default()
+                .push(transfer.timestamp);
+        }
+
+        for report in &mut self.reports {
+            report.measured = arrivals
+                .get(&report.report_id)
+                .and_then(|times| rate(times));
+        }
+    }
+}
+
+// Reports per second from the median interval
+fn rate(times: &[Duration]) -> Option<f64> {
+    let mut intervals: Vec<_> = times.windows(2).map(|w| w[1] - w[0]).collect();
+    intervals.sort();
+    let median = intervals.get(intervals.len() / 2)?;
+
+    match median.is_zero() {
+        true => None,
+        false => Some(1.0 / median.as_secs_f64()),
+    }
+}
+
+impl Display for Estimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let endpoint = &self.endpoint;
+        writeln!(
+            f,
+            "Interrupt IN every {:.3} ms (bInterval {}, {} speed), up to {} bytes a packet",
+            endpoint.period().as_secs_f64() * 1000.0,
+            endpoint.interval,
+            endpoint.speed.name(),
+            endpoint.max_packet
+        )?;
+        if self.reports.is_empty() {
+            return writeln!(f, "No input reports");
+        }
+        writeln!(f)?;
+
+        for report in &self.reports {
+            writeln!(
+                f,
+                "Input{}: {} bytes in {} packet{}, at most {:.0}/s, {:.1} kB/s of the bus ({:.1}%)",
+                report
+                    .report_id
+                    .map_or(String::new(), |id| format!(" #{}", id)),
+                report.bytes,
+                report.packets,
+                if report.packets == 1 { "" } else { "s" },
+                report.max_rate,
+                report.bandwidth / 1000.0,
+                report.bandwidth * 100.0 / endpoint.speed.bytes_per_second()
+            )?;
+
+            if let Some(measured) = report.measured {
+                let share = measured / report.max_rate;
+                let verdict = if share > 1.0 / AT_CAP {
+                    "faster than the endpoint allows, check the interval and speed"
+                } else if share >= AT_CAP {
+                    "limited by the polling interval"
+                } else {
+                    "below what the endpoint allows, limited by the firmware"
+                };
+                writeln!(
+                    f,
+                    "    measured {:.1}/s ({:.0}%), {}",
+                    measured,
+                    share * 100.0,
+                    verdict
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::{
+        capture::{Capture, Direction, Transfer},
+        CollectionType, DescriptorBuilder, DeviceModel, InputItemData,
+    };
+
+    use super::{estimate, Endpoint, Speed};
+
+    #[test]
+    fn caps_rates_by_interval_and_packets() {
+        // a short report and one longer than a full speed packet
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0xff00)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_id(1)
+            .usage(0x02)
+            .report_count(7)
+            .input(InputItemData { data: 0x02 })
+            .report_id(2)
+            .usage(0x03)
+            .report_count(69)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build();
+        let endpoint = Endpoint::new(Speed::Full, 1, None).unwrap();
+
+        let mut estimate = estimate(&descriptor.decode(), endpoint);
+        let caps: Vec<_> = estimate
+            .reports
+            .iter()
+            .map(|report| {
+                (
+                    report.report_id,
+                    report.bytes,
+                    report.packets,
+                    report.max_rate,
+                )
+            })
+            .collect();
+        assert_eq!(caps, [(Some(1), 8, 1, 1000.0), (Some(2), 70, 2, 500.0)]);
+        assert_eq!(estimate.reports[0].bandwidth, 21_000.0);
+
+        // report 1 every millisecond with a pause, report 2 every 8
+        let mut transfers = vec![];
+        for (report_id, times) in [(1, [0, 1, 2, 3, 500]), (2, [0, 8, 16, 24, 32])] {
+            for ms in times {
+                transfers.push(Transfer {
+                    timestamp: Duration::from_millis(ms),
+                    interface: 0,
+                    direction: Direction::In,
+                    bytes: vec![report_id],
+                });
+            }
+        }
+        transfers.sort_by_key(|transfer| transfer.timestamp);
+        let capture = Capture {
+            device: Default::default(),
+            descriptors: [(0, descriptor)].into_iter().collect::<DeviceModel>(),
+            transfers,
+            markers: vec![],
+        };
+        estimate.measure(&capture, 0);
+        assert_eq!(estimate.reports[0].measured, Some(1000.0));
+        assert_eq!(estimate.reports[1].measured, Some(125.0));
+
+        let text = estimate.to_string();
+        assert!(text.contains("measured 1000.0/s (100%), limited by the polling interval"));
+        assert!(text.contains("measured 125.0/s (25%), below what the endpoint allows"));
+    }
+
+    #[test]
+    fn validates_endpoints() {
+        let high = Endpoint::new(Speed::High, 4, None).unwrap();
+        assert_eq!(high.period(), Duration::from_millis(1));
+        assert_eq!(high.max_packet, 1024);
+
+        assert!(Endpoint::new(Speed::High, 17, None).is_err());
+        assert!(Endpoint::new(Speed::Full, 0, None).is_err());
+        assert!(Endpoint::new(Speed::Low, 10, Some(64)).is_err());
+    }
+}
@@ -0,0 +1,229 @@
+// Bit level activity of captured input reports
+//
+// For every bit of every report (per interface and report ID) counts how often it toggles
+// between consecutive reports and how its value correlates with time. Shown as a heatmap,
+// this points at the counters, buttons and axes of undocumented vendor reports.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+};
+
+use hid_parser::capture::{Capture, Direction};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Sums {
+    n: f64,
+    x: f64,  // time
+    xx: f64, // time squared
+    y: f64,  // bit value, y² is the same
+    xy: f64,
+}
+
+impl Sums {
+    fn add(&mut self, x: f64, y: bool) {
+        let y = y as u8 as f64;
+
+        self.n += 1.0;
+        self.x += x;
+        self.xx += x * x;
+        self.y += y;
+        self.xy += x * y;
+    }
+
+    // Pearson correlation, None for constant bits
+    fn correlation(&self) -> Option<f64> {
+        let covariance = self.n * self.xy - self.x * self.y;
+        let variance_x = self.n * self.xx - self.x * self.x;
+        let variance_y = self.n * self.y - self.y * self.y;
+        let denominator = (variance_x * variance_y).sqrt();
+
+        (denominator > 0.0).then(|| covariance / denominator)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BitStats {
+    pub reports: u64,
+    toggles: Vec<u64>, // by bit, counted from the first byte's least significant bit
+    compared: Vec<u64>, // reports each bit could be compared with the previous one in
+    sums: Vec<Sums>,
+    previous: Vec<u8>,
+}
+
+impl BitStats {
+    fn add(&mut self, time: f64, report: &[u8]) {
+        let bits = report.len() * 8;
+        if self.toggles.len() < bits {
+            self.toggles.resize(bits, 0);
+            self.compared.resize(bits, 0);
+            self.sums.resize(bits, Sums::default());
+        }
+
+        for bit in 0..bits {
+            let value = report[bit / 8] >> (bit % 8) & 1 == 1;
+
+            if self.reports > 0 {
+                if let Some(previous) = self.previous.get(bit / 8) {
+                    self.compared[bit] += 1;
+                    if (previous >> (bit % 8) & 1 == 1) != value {
+                        self.toggles[bit] += 1;
+                    }
+                }
+            }
+            self.sums[bit].add(time, value);
+        }
+
+        self.reports += 1;
+        self.previous = report.to_vec();
+    }
+
+    // Fraction of consecutive reports in which the bit changed
+    pub fn toggle_frequency(&self, bit: usize) -> f64 {
+        match self.compared.get(bit) {
+            Some(&compared) if compared > 0 => self.toggles[bit] as f64 / compared as f64,
+            _ => 0.0,
+        }
+    }
+
+    pub fn time_correlation(&self, bit: usize) -> Option<f64> {
+        self.sums.get(bit)?.correlation()
+    }
+
+    pub fn bits(&self) -> usize {
+        self.toggles.len()
+    }
+}
+
+// Statistics of the input reports, by interface and report ID
+pub fn bit_stats(capture: &Capture) -> BTreeMap<(u8, Option<u8>), BitStats> {
+    let with_report_ids = capture
+        .descriptors
+        .parsers()
+        .map(|(interface, parser)| {
+            let with_ids = parser.reports().iter().any(|r| r.report_id.is_some());
+
+            (interface, with_ids)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut stats = BTreeMap::<_, BitStats>::new();
+
+    for transfer in &capture.transfers {
+        if transfer.direction != Direction::In {
+            continue;
+        }
+
+        let report_id = match with_report_ids.get(&transfer.interface) {
+            Some(true) => transfer.bytes.first().copied(),
+            _ => None,
+        };
+
+        stats
+            .entry((transfer.interface, report_id))
+            .or_default()
+            .add(transfer.timestamp.as_secs_f64(), &transfer.bytes);
+    }
+
+    stats
+}
+
+// Bits which follow time closely enough to be worth pointing out
+const CORRELATION_THRESHOLD: f64 = 0.5;
+
+fn shade(frequency: f64) -> char {
+    match frequency {
+        0.0 => '·',
+        f if f <= 0.01 => '░',
+        f if f <= 0.1 => '▒',
+        f if f <= 0.5 => '▓',
+        _ => '█',
+    }
+}
+
+// One row per byte, most significant bit first
+pub fn heatmap(interface: u8, report_id: Option<u8>, stats: &BitStats) -> String {
+    let mut output = String::new();
+
+    let _ = write!(output, "Interface #{}", interface);
+    if let Some(id) = report_id {
+        let _ = write!(output, ", report ID {:#04x}", id);
+    }
+    let _ = writeln!(output, ", {} reports", stats.reports);
+    let _ = writeln!(output, "          76543210");
+
+    for byte in 0..stats.bits() / 8 {
+        let row = (0..8)
+            .rev()
+            .map(|bit| shade(stats.toggle_frequency(byte * 8 + bit)))
+            .collect::<String>();
+
+        let _ = writeln!(output, "byte {:4} {}", byte, row);
+    }
+
+    let correlated = (0..stats.bits())
+        .filter_map(|bit| {
+            let correlation = stats.time_correlation(bit)?;
+
+            (correlation.abs() >= CORRELATION_THRESHOLD)
+                .then(|| format!("{}.{} ({:+.2})", bit / 8, bit % 8, correlation))
+        })
+        .collect::<Vec<_>>();
+
+    if !correlated.is_empty() {
+        let _ = writeln!(
+            output,
+            "Time correlated bits (byte.bit): {}",
+            correlated.join(", ")
+        );
+    }
+
+    output
+}
+
+pub const LEGEND: &str = "Toggle frequency: · never, ░ ≤1%, ▒ ≤10%, ▓ ≤50%, █ >50%";
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::capture::{Capture, Direction, Transfer};
+
+    use super::{bit_stats, heatmap};
+
+    #[test]
+    fn maps_bit_activity() {
+        // constant byte, counter, and a flag which turns on halfway through
+        let transfers = (0..20u8)
+            .map(|n| Transfer {
+                timestamp: Duration::from_millis(n as u64 * 10),
+                interface: 1,
+                direction: Direction::In,
+                bytes: vec![0x5a, n, if n < 10 { 0x00 } else { 0x80 }],
+            })
+            .collect();
+        let capture = Capture {
+            transfers,
+            ..Default::default()
+        };
+
+        let stats = bit_stats(&capture);
+        let stats = &stats[&(1, None)];
+
+        assert_eq!(stats.reports, 20);
+        assert_eq!(stats.toggle_frequency(0), 0.0);
+        assert_eq!(stats.toggle_frequency(8), 1.0);
+        assert_eq!(stats.toggle_frequency(23), 1.0 / 19.0);
+        assert_eq!(stats.time_correlation(0), None);
+
+        assert_eq!(
+            heatmap(1, None, stats),
+            "Interface #1, 20 reports\n          \
+             76543210\n\
+             byte    0 ········\n\
+             byte    1 ···▒▓▓▓█\n\
+             byte    2 ▒·······\n\
+             Time correlated bits (byte.bit): 1.4 (+0.69), 2.7 (+0.87)\n"
+        );
+    }
+}
@@ -0,0 +1,252 @@
+// Reports from a hardware USB sniffer or passthrough board on a serial port (log --serial)
+//
+// A sniffer sitting between the device and the host timestamps reports as they go over the
+// wire, so intervals and latencies measured with it don't include the host's USB stack, driver
+// and scheduling. hid-bench reads what it saw over a serial port, in frames of:
+//
+//   0xa5                sync byte
+//   interface           u8, the interface number the report was sent on
+//   length              u16, little endian, of the report
+//   timestamp           u32, little endian, microseconds on the sniffer's clock (it may wrap)
+//   report              `length` bytes, starting with the report ID if the device uses them
+//
+// Only input reports are sent. Frames of other interfaces are skipped, and bytes which don't
+// start a frame are dropped until the next sync byte, so a bridge can be plugged in while it
+// is already sending.
+
+use std::{
+    cell::Cell,
+    fs::File,
+    io::Read,
+    path::Path,
+    process::Command,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{backend::HidIo, output::note, piped::EndOfInput};
+
+const SYNC: u8 = 0xa5;
+const HEADER_SIZE: usize = 8;
+// Longer frames are taken for a sync byte in the middle of a report
+const MAX_REPORT: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeFrame {
+    pub interface: u8,
+    pub timestamp: Duration,
+    pub report: Vec<u8>,
+}
+
+// Splits the serial stream into frames, unwrapping the sniffer's timestamps
+#[derive(Debug, Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+    previous: Option<u32>,
+    wraps: u64,
+}
+
+impl Decoder {
+    // The frames completed by `bytes`, and how many bytes were dropped looking for them
+    pub fn push(&mut self, bytes: &[u8]) -> (Vec<BridgeFrame>, usize) {
+        self.pending.extend(bytes);
+
+        let mut frames = vec![];
+        let mut skipped = 0;
+        let mut start = 0;
+        while let Some(header) = self.pending.get(start..start + HEADER_SIZE) {
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            if header[0] != SYNC || length > MAX_REPORT {
+                start += 1;
+                skipped += 1;
+                continue;
+            }
+            let Some(report) = self
+                .pending
+                .get(start + HEADER_SIZE..start + HEADER_SIZE + length)
+            else {
+                break;
+            };
+
+            let raw = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            if self.previous.is_some_and(|previous| raw < previous) {
+                self.wraps += 1;
+            }
+            self.previous = Some(raw);
+
+            frames.push(BridgeFrame {
+                interface: header[1],
+                timestamp: Duration::from_micros((self.wraps << 32) + raw as u64),
+                report: report.to_vec(),
+            });
+            start += HEADER_SIZE + length;
+        }
+        self.pending.drain(..start);
+
+        (frames, skipped)
+    }
+}
+
+pub struct Bridge {
+    frames: Receiver<BridgeFrame>,
+    last: Cell<Option<Duration>>,
+}
+
+impl Bridge {
+    // Reads the reports of `interface` from the port at `baud`
+    pub fn open(port: &Path, baud: u32, interface: u8) -> Result<Self> {
+        configure(port, baud)?;
+        let mut file =
+            File::open(port).with_context(|| format!("Cannot open {}", port.display()))?;
+
+        let (sender, frames) = mpsc::channel();
+        thread::spawn(move || {
+            let mut decoder = Decoder::default();
+            let mut chunk = [0u8; 4096];
+            let mut other_interfaces = false;
+            while let Ok(n @ 1..) = file.read(&mut chunk) {
+                let (decoded, skipped) = decoder.push(&chunk[..n]);
+                if skipped > 0 {
+                    note!(
+                        "Dropped {} bytes from the bridge looking for a frame",
+                        skipped
+                    );
+                }
+                for frame in decoded {
+                    if frame.interface != interface {
+                        if !other_interfaces {
+                            note!(
+                                "Skipping reports of interface {}, logging interface {}",
+                                frame.interface,
+                                interface
+                            );
+                            other_interfaces = true;
+                        }
+                        continue;
+                    }
+                    if sender.send(frame).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Bridge {
+            frames,
+            last: Cell::new(None),
+        })
+    }
+}
+
+// Raw mode at the given speed, through stty
+pub fn configure(port: &Path, baud: u32) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    let device_flag = "-F";
+    #[cfg(not(target_os = "linux"))]
+    let device_flag = "-f";
+
+    let status = Command::new("stty")
+        .arg(device_flag)
+        .arg(port)
+        .args([&baud.to_string(), "raw", "-echo"])
+        .status()
+        .context("Cannot run stty to set up the serial port")?;
+    if !status.success() {
+        return Err(anyhow!(
+            "Cannot set {} to {} baud: stty {}",
+            port.display(),
+            baud,
+            status
+        ));
+    }
+
+    Ok(())
+}
+
+impl HidIo for Bridge {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let frame = match timeout_ms {
+            ..0 => self.frames.recv().map_err(|_| EndOfInput)?,
+            ms => match self.frames.recv_timeout(Duration::from_millis(ms as u64)) {
+                Ok(frame) => frame,
+                Err(RecvTimeoutError::Timeout) => return Ok(0),
+                Err(RecvTimeoutError::Disconnected) => return Err(EndOfInput.into()),
+            },
+        };
+        let n = frame.report.len().min(buf.len());
+        buf[..n].copy_from_slice(&frame.report[..n]);
+        self.last.set(Some(frame.timestamp));
+
+        Ok(n)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_timeout(buf, -1)
+    }
+
+    fn write(&self, _report: &[u8]) -> Result<usize> {
+        Err(anyhow!("The bridge only passes input reports on"))
+    }
+
+    fn send_feature_report(&self, _report: &[u8]) -> Result<()> {
+        Err(anyhow!("The bridge only passes input reports on"))
+    }
+
+    fn get_feature_report(&self, _buf: &mut [u8]) -> Result<usize> {
+        Err(anyhow!("The bridge only passes input reports on"))
+    }
+
+    fn set_blocking_mode(&self, _blocking: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn wire_time(&self) -> Option<Duration> {
+        self.last.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{BridgeFrame, Decoder};
+
+    fn frame(interface: u8, timestamp: u32, report: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xa5, interface];
+        bytes.extend((report.len() as u16).to_le_bytes());
+        bytes.extend(timestamp.to_le_bytes());
+        bytes.extend(report);
+        bytes
+    }
+
+    #[test]
+    fn decodes_frames_across_reads() {
+        let mut stream = vec![0x00, 0x17]; // joined in the middle of a frame
+        stream.extend(frame(0, 1_000, &[0x01, 0x05]));
+        stream.extend(frame(1, 0xffff_ff00, &[0x02]));
+        stream.extend(frame(0, 0x0000_0100, &[0x01, 0x06]));
+
+        let mut decoder = Decoder::default();
+        let (first, skipped) = decoder.push(&stream[..14]);
+        assert_eq!(skipped, 2);
+        assert_eq!(
+            first,
+            [BridgeFrame {
+                interface: 0,
+                timestamp: Duration::from_micros(1_000),
+                report: vec![0x01, 0x05],
+            }]
+        );
+
+        let (rest, skipped) = decoder.push(&stream[14..]);
+        assert_eq!(skipped, 0);
+        assert_eq!(rest.len(), 2);
+        assert_eq!(rest[0].interface, 1);
+        // the sniffer's clock wrapped
+        assert_eq!(rest[1].timestamp, Duration::from_micros((1 << 32) + 0x100));
+        assert_eq!(rest[1].report, [0x01, 0x06]);
+    }
+}
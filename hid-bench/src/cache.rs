@@ -0,0 +1,191 @@
+// Report descriptors of known devices, cached on disk
+//
+// Reading the descriptors with control transfers takes a while and can disturb a device (the
+// fetch strategies claim its interface), so the descriptors of every device read are kept in
+// ~/.cache/hid-bench/descriptors, one JSON file per device fingerprint: VID, PID, serial number
+// and bcdDevice. A firmware update changes bcdDevice and the descriptors are read again.
+// --refresh reads them anyway, e.g. from firmware in development keeping its version, and
+// --no-cache neither reads nor writes the cache.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use anyhow::Result;
+use hidapi::HidApi;
+
+use hid_parser::{DeviceModel, ReportDescriptor};
+
+use crate::config::DeviceSpec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    Use,
+    Refresh, // read from the device, then cached
+    Off,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_mode(mode: CacheMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+fn mode() -> CacheMode {
+    match MODE.load(Ordering::Relaxed) {
+        0 => CacheMode::Use,
+        1 => CacheMode::Refresh,
+        _ => CacheMode::Off,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial: Option<String>,
+    pub release: u16, // bcdDevice
+}
+
+impl Fingerprint {
+    // The device as hidapi lists it, without talking to it
+    pub fn of(api: &HidApi, spec: &DeviceSpec) -> Option<Self> {
+        let info = api.device_list().find(|info| {
+            info.vendor_id() == spec.vid
+                && info.product_id() == spec.pid
+                && (spec.serial.is_none() || info.serial_number() == spec.serial.as_deref())
+        })?;
+
+        Some(Fingerprint {
+            vid: spec.vid,
+            pid: spec.pid,
+            serial: info
+                .serial_number()
+                .filter(|s| !s.is_empty())
+                .map(str::to_string),
+            release: info.release_number(),
+        })
+    }
+
+    // e.g. `046d-c08b-1a2b3c-0102.json`
+    fn file_name(&self) -> String {
+        // serial numbers can be anything, only the safe characters make it into the name
+        let serial = match &self.serial {
+            Some(serial) => serial
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect(),
+            None => "none".to_string(),
+        };
+
+        format!(
+            "{:04x}-{:04x}-{}-{:04x}.json",
+            self.vid, self.pid, serial, self.release
+        )
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+    Some(cache_home.join("hid-bench").join("descriptors"))
+}
+
+// The cached descriptors of the device, unless the cache is refreshed or off
+pub fn load(fingerprint: &Fingerprint) -> Option<DeviceModel> {
+    if mode() != CacheMode::Use {
+        return None;
+    }
+
+    load_from(&cache_dir()?, fingerprint)
+}
+
+pub fn store(fingerprint: &Fingerprint, descriptors: &DeviceModel) -> Result<()> {
+    match (mode(), cache_dir()) {
+        (CacheMode::Off, _) | (_, None) => Ok(()),
+        (_, Some(dir)) => store_in(&dir, fingerprint, descriptors),
+    }
+}
+
+// Descriptor bytes by interface number
+type Entry = BTreeMap<u8, Vec<Vec<u8>>>;
+
+fn load_from(dir: &Path, fingerprint: &Fingerprint) -> Option<DeviceModel> {
+    let text = fs::read_to_string(dir.join(fingerprint.file_name())).ok()?;
+    // a damaged entry is read from the device again and replaced
+    let entry: Entry = serde_json::from_str(&text).ok()?;
+
+    let mut descriptors = DeviceModel::new();
+    for (interface, bytes) in entry {
+        let report_descriptors = bytes
+            .into_iter()
+            .map(|bytes| ReportDescriptor { bytes })
+            .collect();
+        descriptors.replace(interface, report_descriptors);
+    }
+
+    Some(descriptors)
+}
+
+fn store_in(dir: &Path, fingerprint: &Fingerprint, descriptors: &DeviceModel) -> Result<()> {
+    let entry: Entry = descriptors
+        .iter()
+        .map(|(interface, report_descriptors)| {
+            let bytes = report_descriptors.iter().map(|d| d.bytes.clone()).collect();
+            (interface, bytes)
+        })
+        .collect();
+
+    fs::create_dir_all(dir)?;
+    fs::write(
+        dir.join(fingerprint.file_name()),
+        serde_json::to_string(&entry)?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use hid_parser::{DeviceModel, ReportDescriptor};
+
+    use super::{load_from, store_in, Fingerprint};
+
+    #[test]
+    fn keeps_descriptors_by_fingerprint() {
+        let dir = env::temp_dir().join(format!("hid-bench-cache-{}", std::process::id()));
+        let fingerprint = Fingerprint {
+            vid: 0x046d,
+            pid: 0xc08b,
+            serial: Some("AB/12 34".to_string()),
+            release: 0x0102,
+        };
+        assert_eq!(fingerprint.file_name(), "046d-c08b-AB1234-0102.json");
+
+        let mut descriptors = DeviceModel::new();
+        descriptors.replace(
+            1,
+            vec![ReportDescriptor {
+                bytes: vec![0x05, 0x01, 0x09, 0x02],
+            }],
+        );
+        store_in(&dir, &fingerprint, &descriptors).unwrap();
+        assert_eq!(load_from(&dir, &fingerprint), Some(descriptors));
+
+        // another firmware version isn't cached
+        let updated = Fingerprint {
+            release: 0x0103,
+            ..fingerprint
+        };
+        assert_eq!(load_from(&dir, &updated), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
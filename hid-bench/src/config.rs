@@ -0,0 +1,588 @@
+// User configuration, read from ~/.config/hid-bench/config.toml by default
+//
+//   [defaults]
+//   report_format = "parsed"
+//   log_format = "full"
+//   color = "never"
+//   theme = "light"
+//   backend = "libusb"
+//   descriptor_timeout_ms = 2000
+//
+//   [devices]
+//   mouse1 = { vid = "046d", pid = "c08b", serial = "1234ABCD", interface = 1 }
+//
+//   [devices.pad]
+//   vid = "054c"
+//   pid = "09cc"
+//   interface = 3
+//   quirks = { descriptor = "pad-fixed.bin", no_decoder = true }
+//
+//   [gamepads.pad]
+//   buttons = { south = 2, east = 3, west = 1, north = 4, guide = 13 }
+//   axes = { right_x = "Z", right_y = "Rz", left_trigger = "Rx", right_trigger = "Ry" }
+//
+// Quirks: `descriptor` is a raw report descriptor file used instead of the one read from the
+// device (relative to the config file), `no_decoder` turns off vendor protocol decoding,
+// `descriptor_timeout_ms` and `descriptor_retries` tune reading descriptors from slow devices.
+//
+// Every command taking --device accepts either VID:PID or an alias.
+//
+// The defaults stand in for flags left out: --format of report and log, --color, --theme and
+// log --backend, and the descriptor quirks of devices without their own. Environment variables
+// named after them (HID_BENCH_LOG_FORMAT, HID_BENCH_COLOR, ...) override the file, and
+// HID_BENCH_CONFIG names the file instead of --config. `config show` prints the result.
+//
+// Gamepads are mapped to the standard layout (log --standard-gamepad) by guessing from their
+// usages, the `gamepads` entries (by alias or VID:PID) correct the guess: buttons by their number
+// on the Button page, axes by their Generic Desktop usage name or as "PAGE/USAGE". `hat = false`
+// stops using the hat switch as the d-pad.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use hid_parser::{
+    gamepad::{Axis, Button, GamepadMapping},
+    usage::BUTTON,
+    Parser,
+};
+
+use crate::find::UsageFilter;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub devices: HashMap<String, DeviceAlias>,
+    #[serde(default)]
+    pub gamepads: HashMap<String, GamepadEntry>,
+    #[serde(skip)]
+    pub path: Option<PathBuf>, // None without a config file
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    pub report_format: Option<String>,
+    pub log_format: Option<String>,
+    pub color: Option<String>,
+    pub theme: Option<String>,
+    pub backend: Option<String>, // of log, "hidapi" or "libusb"
+    pub descriptor_timeout_ms: Option<u64>,
+    pub descriptor_retries: Option<u32>,
+    #[serde(skip)]
+    pub from_env: BTreeSet<&'static str>, // the defaults set by environment variables
+}
+
+// What the commands use without a flag or default, shown by `config show`
+const BUILT_IN: [(&str, &str); 7] = [
+    ("report_format", "items"),
+    ("log_format", "compact"),
+    ("color", "auto"),
+    ("theme", "dark"),
+    ("backend", "hidapi"),
+    ("descriptor_timeout_ms", "500"),
+    ("descriptor_retries", "2"),
+];
+
+impl Defaults {
+    // Overrides the defaults with HID_BENCH_<NAME> variables, `var` looks them up
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        for (name, _) in BUILT_IN {
+            let variable = format!("HID_BENCH_{}", name.to_uppercase());
+            let Some(value) = var(&variable) else {
+                continue;
+            };
+            let invalid = |value: &str| anyhow!("{} must be a number, not '{}'", variable, value);
+
+            match name {
+                "report_format" => self.report_format = Some(value),
+                "log_format" => self.log_format = Some(value),
+                "color" => self.color = Some(value),
+                "theme" => self.theme = Some(value),
+                "backend" => self.backend = Some(value),
+                "descriptor_timeout_ms" => {
+                    self.descriptor_timeout_ms = Some(value.parse().map_err(|_| invalid(&value))?)
+                }
+                _ => self.descriptor_retries = Some(value.parse().map_err(|_| invalid(&value))?),
+            }
+            self.from_env.insert(name);
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "report_format" => self.report_format.clone(),
+            "log_format" => self.log_format.clone(),
+            "color" => self.color.clone(),
+            "theme" => self.theme.clone(),
+            "backend" => self.backend.clone(),
+            "descriptor_timeout_ms" => self.descriptor_timeout_ms.map(|ms| ms.to_string()),
+            _ => self.descriptor_retries.map(|retries| retries.to_string()),
+        }
+    }
+}
+
+// A value enum flag, or its default from the config if it was left out
+pub fn flag_or<T: ValueEnum>(flag: Option<T>, configured: Option<&str>, default: T) -> Result<T> {
+    match (flag, configured) {
+        (Some(flag), _) => Ok(flag),
+        (None, Some(name)) => T::from_str(name, true)
+            .map_err(|_| anyhow!("Unknown value '{}' in the config file or environment", name)),
+        (None, None) => Ok(default),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceAlias {
+    pub vid: String,
+    pub pid: String,
+    pub serial: Option<String>,
+    pub interface: Option<u8>,
+    pub report_format: Option<String>,
+    pub log_format: Option<String>,
+    #[serde(default)]
+    pub quirks: Quirks,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GamepadEntry {
+    #[serde(default)]
+    pub buttons: BTreeMap<String, u16>,
+    #[serde(default)]
+    pub axes: BTreeMap<String, String>,
+    pub hat: Option<bool>,
+}
+
+// Per-device overrides for misbehaving devices
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quirks {
+    pub descriptor: Option<PathBuf>,
+    #[serde(default)]
+    pub no_decoder: bool,
+    pub descriptor_timeout_ms: Option<u64>,
+    pub descriptor_retries: Option<u32>,
+}
+
+// A device given on the command line, resolved through the aliases
+#[derive(Debug, Default, Clone)]
+pub struct DeviceSpec {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial: Option<String>,
+    pub interface: Option<u8>,
+    pub report_format: Option<String>,
+    pub log_format: Option<String>,
+    pub quirks: Quirks,
+}
+
+impl Config {
+    // Loads the config file, a missing file at the default location is an empty config, then
+    // applies the environment
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut config = Self::load_file(path)?;
+        config.defaults.apply_env(|name| env::var(name).ok())?;
+
+        Ok(config)
+    }
+
+    fn load_file(path: Option<&Path>) -> Result<Self> {
+        let path = match path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os("HID_BENCH_CONFIG").map(PathBuf::from))
+        {
+            Some(path) => path,
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Self::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("Cannot read config file {}", path.display()))?;
+        let mut config = Self::parse(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+        // descriptor overrides are relative to the config file
+        let base = path.parent().unwrap_or(Path::new("."));
+        for alias in config.devices.values_mut() {
+            if let Some(descriptor) = &alias.quirks.descriptor {
+                alias.quirks.descriptor = Some(base.join(descriptor));
+            }
+        }
+        config.path = Some(path);
+
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_home.join("hid-bench").join("config.toml"))
+    }
+
+    // Resolves an alias or a VID:PID pair
+    pub fn device(&self, device: &str) -> Result<DeviceSpec> {
+        let alias = match self.devices.get(device) {
+            Some(alias) => alias,
+            None => {
+                let (vid, pid) = parse_vid_pid(device)?;
+
+                return Ok(DeviceSpec {
+                    vid,
+                    pid,
+                    report_format: self.defaults.report_format.clone(),
+                    log_format: self.defaults.log_format.clone(),
+                    quirks: self.default_quirks(Quirks::default()),
+                    ..Default::default()
+                });
+            }
+        };
+
+        let hex = |value: &str| {
+            u16::from_str_radix(value, 16).map_err(|_| {
+                anyhow!(
+                    "Device alias '{}' must use 4-digit hexadecimal vid and pid",
+                    device
+                )
+            })
+        };
+
+        Ok(DeviceSpec {
+            vid: hex(&alias.vid)?,
+            pid: hex(&alias.pid)?,
+            serial: alias.serial.clone(),
+            interface: alias.interface,
+            report_format: alias
+                .report_format
+                .clone()
+                .or_else(|| self.defaults.report_format.clone()),
+            log_format: alias
+                .log_format
+                .clone()
+                .or_else(|| self.defaults.log_format.clone()),
+            quirks: self.default_quirks(alias.quirks.clone()),
+        })
+    }
+
+    fn default_quirks(&self, quirks: Quirks) -> Quirks {
+        Quirks {
+            descriptor_timeout_ms: quirks
+                .descriptor_timeout_ms
+                .or(self.defaults.descriptor_timeout_ms),
+            descriptor_retries: quirks
+                .descriptor_retries
+                .or(self.defaults.descriptor_retries),
+            ..quirks
+        }
+    }
+
+    // The effective configuration in the config file format, where each default comes from
+    pub fn show(&self) -> String {
+        let mut lines = vec![match &self.path {
+            Some(path) => format!("# {}", path.display()),
+            None => "# No config file".to_string(),
+        }];
+
+        lines.push("[defaults]".to_string());
+        for (name, built_in) in BUILT_IN {
+            let (value, source) = match self.defaults.get(name) {
+                Some(value) if self.defaults.from_env.contains(name) => {
+                    (value, format!("HID_BENCH_{}", name.to_uppercase()))
+                }
+                Some(value) => (value, "config file".to_string()),
+                None => (built_in.to_string(), "built in".to_string()),
+            };
+            let value = match value.parse::<u64>() {
+                Ok(_) => value,
+                Err(_) => format!("\"{}\"", value),
+            };
+            lines.push(format!("{} = {}  # {}", name, value, source));
+        }
+
+        let mut aliases: Vec<_> = self.devices.iter().collect();
+        aliases.sort_by_key(|(name, _)| *name);
+        if !aliases.is_empty() {
+            lines.push(String::new());
+            lines.push("[devices]".to_string());
+        }
+        for (name, alias) in aliases {
+            let mut fields = vec![
+                format!("vid = \"{}\"", alias.vid),
+                format!("pid = \"{}\"", alias.pid),
+            ];
+            if let Some(serial) = &alias.serial {
+                fields.push(format!("serial = \"{}\"", serial));
+            }
+            if let Some(interface) = alias.interface {
+                fields.push(format!("interface = {}", interface));
+            }
+            if let Some(format) = &alias.report_format {
+                fields.push(format!("report_format = \"{}\"", format));
+            }
+            if let Some(format) = &alias.log_format {
+                fields.push(format!("log_format = \"{}\"", format));
+            }
+            lines.push(format!("{} = {{ {} }}", name, fields.join(", ")));
+        }
+
+        let mut gamepads: Vec<_> = self.gamepads.keys().map(String::as_str).collect();
+        gamepads.sort();
+        if !gamepads.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("# Gamepad mappings: {}", gamepads.join(", ")));
+        }
+
+        lines.join("\n")
+    }
+
+    // Standard gamepad mapping of a device, the guess corrected by its `gamepads` entry. None if
+    // it doesn't look like a gamepad and has no entry.
+    pub fn gamepad_mapping(
+        &self,
+        spec: &DeviceSpec,
+        parser: &Parser,
+    ) -> Result<Option<GamepadMapping>> {
+        let guess = GamepadMapping::guess(parser);
+        let entry = self.gamepads.iter().find(|(device, _)| {
+            self.device(device)
+                .is_ok_and(|entry| (entry.vid, entry.pid) == (spec.vid, spec.pid))
+        });
+        let (device, entry) = match entry {
+            Some(entry) => entry,
+            None => return Ok(guess),
+        };
+
+        let mut mapping = guess.unwrap_or_default();
+        let invalid = || format!("Invalid gamepad mapping for '{}'", device);
+
+        for (name, number) in &entry.buttons {
+            let button = name.parse::<Button>().with_context(invalid)?;
+
+            mapping.buttons.retain(|(b, _)| *b != button);
+            mapping.buttons.push((button, (BUTTON, *number)));
+        }
+
+        for (name, usage) in &entry.axes {
+            let axis = name.parse::<Axis>().with_context(invalid)?;
+            let usage = match usage.contains('/') {
+                true => usage.parse::<UsageFilter>(),
+                false => format!("Generic Desktop/{}", usage).parse::<UsageFilter>(),
+            }
+            .with_context(invalid)?;
+            let id = usage
+                .id
+                .ok_or_else(|| anyhow!("Axis {} needs a usage, not a usage page", axis))
+                .with_context(invalid)?;
+
+            mapping.axes.retain(|(a, _)| *a != axis);
+            mapping.axes.push((axis, (usage.page, id)));
+        }
+
+        if entry.hat == Some(false) {
+            mapping.hat = None;
+        }
+
+        mapping.buttons.sort();
+        mapping.axes.sort();
+
+        Ok(Some(mapping))
+    }
+}
+
+pub fn parse_vid_pid(vidpid: &str) -> Result<(u16, u16)> {
+    let error = || {
+        anyhow!(
+            "Device must be two 4-digit hexadecimal numbers separated by ':', e.g. 046d:c08b, or a device alias"
+        )
+    };
+
+    let (vid, pid) = vidpid.split_once(':').ok_or_else(error)?;
+    let vid = u16::from_str_radix(vid, 16).map_err(|_| error())?;
+    let pid = u16::from_str_radix(pid, 16).map_err(|_| error())?;
+
+    Ok((vid, pid))
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{
+        gamepad::{Axis, Button},
+        CollectionType, DescriptorBuilder, InputItemData,
+    };
+
+    use super::Config;
+
+    const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+
+    const CONFIG: &str = r#"
+        [defaults]
+        log_format = "full"
+
+        [devices]
+        mouse1 = { vid = "046d", pid = "c08b", serial = "1234ABCD", interface = 1 }
+
+        [devices.pad]
+        vid = "054c"
+        pid = "09cc"
+        log_format = "raw"
+        quirks = { no_decoder = true }
+    "#;
+
+    #[test]
+    fn resolves_aliases() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        let mouse = config.device("mouse1").unwrap();
+        assert_eq!((mouse.vid, mouse.pid), (0x046d, 0xc08b));
+        assert_eq!(mouse.serial.as_deref(), Some("1234ABCD"));
+        assert_eq!(mouse.interface, Some(1));
+        assert_eq!(mouse.log_format.as_deref(), Some("full"));
+        assert!(!mouse.quirks.no_decoder);
+
+        let pad = config.device("pad").unwrap();
+        assert_eq!(pad.log_format.as_deref(), Some("raw"));
+        assert!(pad.quirks.no_decoder);
+    }
+
+    #[test]
+    fn layers_the_environment_over_the_file() {
+        let mut config = Config::parse(CONFIG).unwrap();
+        config
+            .defaults
+            .apply_env(|name| match name {
+                "HID_BENCH_LOG_FORMAT" => Some("raw".to_string()),
+                "HID_BENCH_DESCRIPTOR_RETRIES" => Some("5".to_string()),
+                _ => None,
+            })
+            .unwrap();
+
+        let device = config.device("1234:abcd").unwrap();
+        assert_eq!(device.log_format.as_deref(), Some("raw"));
+        assert_eq!(device.quirks.descriptor_retries, Some(5));
+
+        let show = config.show();
+        assert!(show.contains("log_format = \"raw\"  # HID_BENCH_LOG_FORMAT"));
+        assert!(show.contains("descriptor_retries = 5  # HID_BENCH_DESCRIPTOR_RETRIES"));
+        assert!(show.contains("color = \"auto\"  # built in"));
+        assert!(show.contains(
+            "mouse1 = { vid = \"046d\", pid = \"c08b\", serial = \"1234ABCD\", interface = 1 }"
+        ));
+
+        let invalid = config.defaults.apply_env(|name| {
+            (name == "HID_BENCH_DESCRIPTOR_TIMEOUT_MS").then(|| "slow".to_string())
+        });
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    fn falls_back_to_vid_pid() {
+        let config = Config::parse(CONFIG).unwrap();
+
+        let device = config.device("1234:abcd").unwrap();
+        assert_eq!((device.vid, device.pid), (0x1234, 0xabcd));
+        assert_eq!(device.serial, None);
+        assert_eq!(device.log_format.as_deref(), Some("full"));
+
+        assert!(config.device("mouse2").is_err());
+        assert!(config.device("1234").is_err());
+    }
+
+    #[test]
+    fn corrects_guessed_gamepad_mappings() {
+        let config = Config::parse(
+            r#"
+            [devices]
+            pad = { vid = "054c", pid = "09cc" }
+
+            [gamepads.pad]
+            buttons = { south = 2, east = 3 }
+            axes = { left_trigger = "Rx", right_trigger = "0x02/0xc4" }
+            hat = false
+            "#,
+        )
+        .unwrap();
+
+        // two buttons, a hat switch and X, Y, Rx
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x05)
+            .collection(CollectionType::Application)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(3)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(3)
+            .input(VARIABLE)
+            .usage_page(0x01)
+            .usage(0x39)
+            .logical_maximum(7)
+            .report_size(5)
+            .report_count(1)
+            .input(VARIABLE)
+            .usage(0x30)
+            .usage(0x31)
+            .usage(0x33)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(3)
+            .input(VARIABLE)
+            .end_collection()
+            .build()
+            .decode();
+
+        let pad = config.device("pad").unwrap();
+        let mapping = config.gamepad_mapping(&pad, &parser).unwrap().unwrap();
+        assert_eq!(
+            mapping.buttons,
+            vec![
+                (Button::South, (0x09, 2)),
+                (Button::East, (0x09, 3)),
+                (Button::West, (0x09, 3)),
+            ]
+        );
+        assert_eq!(
+            mapping.axes,
+            vec![
+                (Axis::LeftX, (0x01, 0x30)),
+                (Axis::LeftY, (0x01, 0x31)),
+                (Axis::LeftTrigger, (0x01, 0x33)),
+                (Axis::RightTrigger, (0x02, 0xc4)),
+            ]
+        );
+        assert_eq!(mapping.hat, None);
+
+        let other = config.device("1234:abcd").unwrap();
+        let guess = config.gamepad_mapping(&other, &parser).unwrap().unwrap();
+        assert_eq!(guess.hat, Some((0x01, 0x39)));
+
+        let invalid = Config::parse("[gamepads.\"1234:abcd\"]\nbuttons = { a = 1 }").unwrap();
+        assert!(invalid.gamepad_mapping(&other, &parser).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(Config::parse("[devices]\nmouse = { vid = \"046d\", pdi = \"c08b\" }").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+// Runtime controls of the log and record commands
+//
+// Long debugging sessions shouldn't need a restart, which loses the device state. Commands typed
+// on the terminal (a letter and Enter) or sent as signals pause the output, insert markers,
+// rotate the output file or switch the format:
+//
+//   p         pause or resume the output, reports are still read
+//   m [TEXT]  insert a marker line, SIGUSR1 inserts one without text
+//   r         rotate the output file, also SIGUSR2
+//   v         switch between the raw, compact and full formats
+//
+// Where the reports themselves come in on stdin, only the signals work: a second reader of stdin
+// would take lines of the reports for commands.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use anyhow::{Context, Result};
+
+use crate::{
+    output::{out, outln},
+    record,
+    signals::{self, UserSignal},
+};
+
+pub const HELP: &str =
+    "Controls: p pauses or resumes, m [TEXT] inserts a marker, r rotates the output file, \
+     v switches the format, each followed by Enter";
+pub const SIGNALS_HELP: &str =
+    "Controls: SIGUSR1 inserts a marker, SIGUSR2 rotates the output file";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Control {
+    Pause, // or resume
+    Marker(Option<String>),
+    Rotate,
+    Verbosity,
+}
+
+impl Control {
+    // Lines which aren't a command are ignored, they may be typed on the device being logged
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let (command, text) = line.split_once(' ').unwrap_or((line, ""));
+        let text = text.trim();
+
+        match command {
+            "p" => Some(Control::Pause),
+            "m" => Some(Control::Marker(
+                (!text.is_empty()).then(|| text.to_string()),
+            )),
+            "r" => Some(Control::Rotate),
+            "v" => Some(Control::Verbosity),
+            _ => None,
+        }
+    }
+}
+
+// Where the controls come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlInput {
+    Terminal, // commands typed on stdin when it is a terminal, and the signals
+    Signals,  // only the signals, stdin is read for something else
+}
+
+impl ControlInput {
+    fn reads_stdin(self, terminal: bool) -> bool {
+        self == ControlInput::Terminal && terminal
+    }
+}
+
+pub struct Controls {
+    commands: Option<Receiver<Control>>, // typed on the terminal
+    signals: bool,                       // whether the platform has the user signals
+}
+
+impl Controls {
+    // Listens to the user signals, and reads commands from stdin if the input allows
+    pub fn start(input: ControlInput) -> Self {
+        let signals = signals::listen();
+
+        let commands = input.reads_stdin(io::stdin().is_terminal()).then(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    let Ok(line) = line else { break };
+                    if let Some(control) = Control::parse(&line) {
+                        if sender.send(control).is_err() {
+                            break;
+                        }
+                    }
+                }
+            });
+
+            receiver
+        });
+
+        Self { commands, signals }
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.commands.is_some()
+    }
+
+    pub fn signals(&self) -> bool {
+        self.signals
+    }
+
+    // Commands received since the last call
+    pub fn poll(&self) -> Vec<Control> {
+        let mut controls = vec![];
+        if UserSignal::Usr1.take() {
+            controls.push(Control::Marker(None));
+        }
+        if UserSignal::Usr2.take() {
+            controls.push(Control::Rotate);
+        }
+        if let Some(commands) = &self.commands {
+            controls.extend(commands.try_iter());
+        }
+
+        controls
+    }
+}
+
+// Where the log goes: stdout, or a file which can be rotated
+pub struct LogSink {
+    file: Option<(PathBuf, BufWriter<File>)>,
+    rotations: usize,
+}
+
+impl LogSink {
+    pub fn new(path: Option<&Path>) -> Result<Self> {
+        let file = match path {
+            Some(path) => Some((path.to_path_buf(), create(path)?)),
+            None => None,
+        };
+
+        Ok(Self { file, rotations: 0 })
+    }
+
+    pub fn line(&mut self, line: &str) -> Result<()> {
+        match &mut self.file {
+            Some((_, file)) => writeln!(file, "{}", line)?,
+            None => outln!("{}", line),
+        }
+
+        Ok(())
+    }
+
+    // Text without a newline, shown right away
+    pub fn write(&mut self, text: &str) -> Result<()> {
+        match &mut self.file {
+            Some((_, file)) => {
+                write!(file, "{}", text)?;
+                file.flush()?;
+            }
+            None => {
+                out!("{}", text);
+                io::stdout().flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Moves the file written so far to the next numbered name (log-1.txt, ...) and starts a new
+    // one. None when logging to stdout.
+    pub fn rotate(&mut self) -> Result<Option<PathBuf>> {
+        let Some((path, file)) = &mut self.file else {
+            return Ok(None);
+        };
+        file.flush()?;
+
+        self.rotations += 1;
+        let rotated = record::numbered(path, self.rotations);
+        fs::rename(&*path, &rotated)
+            .with_context(|| format!("Cannot rename {}", path.display()))?;
+        *file = create(path)?;
+
+        Ok(Some(rotated))
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        if let Some((_, file)) = &mut self.file {
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn create(path: &Path) -> Result<BufWriter<File>> {
+    let file = File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+
+    Ok(BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use super::{Control, ControlInput, Controls, LogSink};
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Control::parse("p"), Some(Control::Pause));
+        assert_eq!(
+            Control::parse("m  pressed reset "),
+            Some(Control::Marker(Some("pressed reset".to_string())))
+        );
+        assert_eq!(Control::parse("m"), Some(Control::Marker(None)));
+        assert_eq!(Control::parse(" v\n"), Some(Control::Verbosity));
+        assert_eq!(Control::parse("password"), None);
+        assert_eq!(Control::parse(""), None);
+    }
+
+    #[test]
+    fn leaves_stdin_alone_for_signals_only() {
+        assert!(ControlInput::Terminal.reads_stdin(true));
+        assert!(!ControlInput::Terminal.reads_stdin(false));
+        assert!(!ControlInput::Signals.reads_stdin(true));
+        assert!(!Controls::start(ControlInput::Signals).interactive());
+    }
+
+    #[test]
+    fn rotates_log_files() {
+        let dir = env::temp_dir().join(format!("hid-bench-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("log.txt");
+
+        let mut sink = LogSink::new(Some(&path)).unwrap();
+        sink.line("first").unwrap();
+        let rotated = sink.rotate().unwrap().unwrap();
+        sink.line("second").unwrap();
+        sink.flush().unwrap();
+
+        assert_eq!(rotated, dir.join("log-1.txt"));
+        assert_eq!(fs::read_to_string(&rotated).unwrap(), "first\n");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second\n");
+        assert_eq!(LogSink::new(None).unwrap().rotate().unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
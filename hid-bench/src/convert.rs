@@ -0,0 +1,595 @@
+// Converting captured data between formats
+//
+// Every format is read into and written from a `Capture`, the session model record, analyze
+// and replay share: captures (.hbc), frame logs (.hbf), JSON lines and hid-recorder
+// recordings are read, and all but frame logs written, along with CSV and pcapng
+// (see `pcapng`). JSON lines hold one record per line, in the order of a capture file:
+//
+//   {"type":"device","vendor_id":1133,"product_id":49291,"product":"Mouse"}
+//   {"type":"descriptor","interface":0,"bytes":"05 01 09 02 ..."}
+//   {"type":"transfer","timestamp_us":1500,"interface":0,"direction":"in","bytes":"01 00 05"}
+//   {"type":"marker","timestamp_us":2000,"text":"unplugged"}
+//
+// hid-recorder recordings only keep input reports and the order of the interfaces, unless they
+// were written here: the interface numbers and the other reports are kept in comments.
+
+use std::{
+    fs,
+    io::{BufRead, Write},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use hid_parser::{
+    capture::{self, Capture, DeviceMetadata, Direction, Marker, Transfer},
+    ReportDescriptor,
+};
+
+use crate::{framelog, pcapng};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertFormat {
+    /// hid-tools recording, replayable with hid-replay
+    HidRecorder,
+    /// hid-bench capture, e.g. of a frame log (.hbf)
+    #[value(alias = "hbc")]
+    Capture,
+    /// One JSON record per line
+    Jsonl,
+    /// One row per report: seconds, interface, direction, bytes, and rows for the markers
+    Csv,
+    /// USB packets for Wireshark
+    Pcapng,
+}
+
+// Reads any of the formats, told apart by their contents (frame logs by the .hbf extension)
+pub fn read_file(path: &Path) -> Result<Capture> {
+    if path.extension().is_some_and(|e| e == "hbf") {
+        return framelog::read_file(path);
+    }
+
+    let bytes = fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    read(&bytes).with_context(|| format!("Cannot read {}", path.display()))
+}
+
+fn read(bytes: &[u8]) -> Result<Capture> {
+    if bytes.starts_with(capture::MAGIC) {
+        return Capture::read(bytes);
+    }
+
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| anyhow!("Not a capture, JSON lines or a hid-recorder recording"))?;
+    match text.trim_start().starts_with('{') {
+        true => read_jsonl(text.as_bytes()),
+        false => read_hid_recorder(text),
+    }
+}
+
+// Linux bus type of USB devices (BUS_USB)
+const BUS_USB: u16 = 0x03;
+
+pub fn convert(capture: &Capture, format: ConvertFormat, writer: &mut impl Write) -> Result<()> {
+    match format {
+        ConvertFormat::HidRecorder => hid_recorder(capture, writer),
+        ConvertFormat::Capture => capture.write(writer),
+        ConvertFormat::Jsonl => jsonl(capture, writer),
+        ConvertFormat::Csv => csv(capture, writer),
+        ConvertFormat::Pcapng => pcapng::write(capture, writer),
+    }
+}
+
+// hid-recorder describes every interface as a separate hidraw device, numbered with `D:` lines
+// when there is more than one. Only input reports can be replayed, the others become comments.
+fn hid_recorder(capture: &Capture, writer: &mut impl Write) -> Result<()> {
+    let device = &capture.device;
+    let name = [device.manufacturer.as_deref(), device.product.as_deref()]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let multiple = capture.descriptors.len() > 1;
+    let index = |interface: u8| {
+        capture
+            .descriptors
+            .interfaces()
+            .position(|i| i == interface)
+    };
+
+    writeln!(writer, "# {}", name)?;
+    writeln!(writer, "# Recorded with hid-bench")?;
+
+    for (n, interface) in capture.descriptors.interfaces().enumerate() {
+        // one descriptor per hidraw device
+        let Some(descriptor) = capture.descriptors.descriptor(interface) else {
+            continue;
+        };
+
+        if multiple {
+            writeln!(writer, "D: {}", n)?;
+        }
+        writeln!(writer, "# Interface #{}", interface)?;
+        writeln!(
+            writer,
+            "R: {} {}",
+            descriptor.bytes.len(),
+            hex(&descriptor.bytes)
+        )?;
+        writeln!(writer, "N: {}", name)?;
+        writeln!(
+            writer,
+            "I: {:x} {:04x} {:04x}",
+            BUS_USB, device.vendor_id, device.product_id
+        )?;
+    }
+
+    for transfer in &capture.transfers {
+        let index = match index(transfer.interface) {
+            Some(index) => index,
+            None => continue, // no descriptor, hid-replay couldn't create the device
+        };
+        let timestamp = format!(
+            "{:06}.{:06}",
+            transfer.timestamp.as_secs(),
+            transfer.timestamp.subsec_micros()
+        );
+
+        // the device of the reports kept as comments too, for reading them back
+        if multiple {
+            writeln!(writer, "D: {}", index)?;
+        }
+        if transfer.direction != Direction::In {
+            writeln!(
+                writer,
+                "# {:?} {} {} {}",
+                transfer.direction,
+                timestamp,
+                transfer.bytes.len(),
+                hex(&transfer.bytes)
+            )?;
+            continue;
+        }
+
+        writeln!(
+            writer,
+            "E: {} {} {}",
+            timestamp,
+            transfer.bytes.len(),
+            hex(&transfer.bytes)
+        )?;
+    }
+
+    Ok(())
+}
+
+// Reads a recording, by hid-recorder or `hid_recorder` above
+fn read_hid_recorder(text: &str) -> Result<Capture> {
+    let mut capture = Capture::default();
+    let mut interfaces: Vec<u8> = vec![]; // by device index
+    let mut device = 0;
+
+    for (n, line) in text.lines().enumerate() {
+        let invalid = || anyhow!("Invalid recording at line {}: {}", n + 1, line);
+        let line = line.trim();
+        let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        match tag {
+            "D:" => device = rest.trim().parse().map_err(|_| invalid())?,
+            "#" if rest.starts_with("Interface #") => {
+                let interface = rest["Interface #".len()..].parse().map_err(|_| invalid())?;
+                if interfaces.len() <= device {
+                    interfaces.resize(device + 1, interface);
+                }
+                interfaces[device] = interface;
+            }
+            "#" => {
+                // reports hid-replay can't replay, as written by `hid_recorder`
+                let direction = match rest.split(' ').next() {
+                    Some("Out") => Direction::Out,
+                    Some("FeatureIn") => Direction::FeatureIn,
+                    Some("FeatureOut") => Direction::FeatureOut,
+                    _ => continue,
+                };
+                let Some((timestamp, bytes)) = rest
+                    .split_once(' ')
+                    .and_then(|(_, event)| recorded_event(event))
+                else {
+                    continue;
+                };
+                capture.transfers.push(Transfer {
+                    timestamp,
+                    interface: interface_of(&interfaces, device),
+                    direction,
+                    bytes,
+                });
+            }
+            "R:" => {
+                let (_, bytes) = rest.split_once(' ').unwrap_or((rest, ""));
+                let bytes = parse_hex(bytes).ok_or_else(invalid)?;
+                if interfaces.len() <= device {
+                    let next = interfaces.len() as u8;
+                    interfaces.resize(device + 1, next);
+                }
+                capture
+                    .descriptors
+                    .add(interfaces[device], ReportDescriptor { bytes });
+            }
+            "N:" => capture.device.product = Some(rest.to_string()),
+            "I:" => {
+                let ids: Vec<_> = rest.split_whitespace().collect();
+                let id = |i: usize| {
+                    ids.get(i)
+                        .and_then(|id| u16::from_str_radix(id, 16).ok())
+                        .ok_or_else(invalid)
+                };
+                capture.device.vendor_id = id(1)?;
+                capture.device.product_id = id(2)?;
+            }
+            "E:" => {
+                let (timestamp, bytes) = recorded_event(rest).ok_or_else(invalid)?;
+                capture.transfers.push(Transfer {
+                    timestamp,
+                    interface: interface_of(&interfaces, device),
+                    direction: Direction::In,
+                    bytes,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(capture)
+}
+
+fn interface_of(interfaces: &[u8], device: usize) -> u8 {
+    interfaces.get(device).copied().unwrap_or(device as u8)
+}
+
+// `000002.001000 3 10 ff 01`
+fn recorded_event(event: &str) -> Option<(Duration, Vec<u8>)> {
+    let mut parts = event.splitn(3, ' ');
+    let (secs, micros) = parts.next()?.split_once('.')?;
+    let timestamp =
+        Duration::from_secs(secs.parse().ok()?) + Duration::from_micros(micros.parse().ok()?);
+    let length: usize = parts.next()?.parse().ok()?;
+    let bytes = parse_hex(parts.next().unwrap_or(""))?;
+
+    (bytes.len() == length).then_some((timestamp, bytes))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonRecord {
+    Device {
+        vendor_id: u16,
+        product_id: u16,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        manufacturer: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        product: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        serial: Option<String>,
+    },
+    Descriptor {
+        interface: u8,
+        bytes: String,
+    },
+    Transfer {
+        timestamp_us: u64,
+        interface: u8,
+        direction: JsonDirection,
+        bytes: String,
+    },
+    Marker {
+        timestamp_us: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonDirection {
+    In,
+    Out,
+    FeatureIn,
+    FeatureOut,
+}
+
+impl From<Direction> for JsonDirection {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::In => JsonDirection::In,
+            Direction::Out => JsonDirection::Out,
+            Direction::FeatureIn => JsonDirection::FeatureIn,
+            Direction::FeatureOut => JsonDirection::FeatureOut,
+        }
+    }
+}
+
+impl From<JsonDirection> for Direction {
+    fn from(direction: JsonDirection) -> Self {
+        match direction {
+            JsonDirection::In => Direction::In,
+            JsonDirection::Out => Direction::Out,
+            JsonDirection::FeatureIn => Direction::FeatureIn,
+            JsonDirection::FeatureOut => Direction::FeatureOut,
+        }
+    }
+}
+
+fn jsonl(capture: &Capture, writer: &mut impl Write) -> Result<()> {
+    let device = &capture.device;
+    let mut records = vec![JsonRecord::Device {
+        vendor_id: device.vendor_id,
+        product_id: device.product_id,
+        manufacturer: device.manufacturer.clone(),
+        product: device.product.clone(),
+        serial: device.serial.clone(),
+    }];
+    for (interface, descriptors) in capture.descriptors.iter() {
+        for descriptor in descriptors {
+            records.push(JsonRecord::Descriptor {
+                interface,
+                bytes: hex(&descriptor.bytes),
+            });
+        }
+    }
+    for transfer in &capture.transfers {
+        records.push(JsonRecord::Transfer {
+            timestamp_us: transfer.timestamp.as_micros() as u64,
+            interface: transfer.interface,
+            direction: transfer.direction.into(),
+            bytes: hex(&transfer.bytes),
+        });
+    }
+    for marker in &capture.markers {
+        records.push(JsonRecord::Marker {
+            timestamp_us: marker.timestamp.as_micros() as u64,
+            text: marker.text.clone(),
+        });
+    }
+
+    for record in records {
+        writeln!(writer, "{}", serde_json::to_string(&record)?)?;
+    }
+
+    Ok(())
+}
+
+fn read_jsonl(reader: impl BufRead) -> Result<Capture> {
+    let mut capture = Capture::default();
+
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: JsonRecord = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid record at line {}", n + 1))?;
+        let bytes = |hex: &str| {
+            parse_hex(hex).ok_or_else(|| anyhow!("Invalid bytes at line {}: {}", n + 1, hex))
+        };
+
+        match record {
+            JsonRecord::Device {
+                vendor_id,
+                product_id,
+                manufacturer,
+                product,
+                serial,
+            } => {
+                capture.device = DeviceMetadata {
+                    vendor_id,
+                    product_id,
+                    manufacturer,
+                    product,
+                    serial,
+                }
+            }
+            JsonRecord::Descriptor {
+                interface,
+                bytes: hex,
+            } => {
+                let bytes = bytes(&hex)?;
+                capture
+                    .descriptors
+                    .add(interface, ReportDescriptor { bytes });
+            }
+            JsonRecord::Transfer {
+                timestamp_us,
+                interface,
+                direction,
+                bytes: hex,
+            } => capture.transfers.push(Transfer {
+                timestamp: Duration::from_micros(timestamp_us),
+                interface,
+                direction: direction.into(),
+                bytes: bytes(&hex)?,
+            }),
+            JsonRecord::Marker { timestamp_us, text } => capture.markers.push(Marker {
+                timestamp: Duration::from_micros(timestamp_us),
+                text,
+            }),
+        }
+    }
+
+    Ok(capture)
+}
+
+fn csv(capture: &Capture, writer: &mut impl Write) -> Result<()> {
+    writeln!(writer, "seconds,interface,direction,bytes,marker")?;
+
+    // reports and markers in time order, markers after the reports at the same time
+    let mut rows: Vec<(Duration, String)> = capture
+        .transfers
+        .iter()
+        .map(|transfer| {
+            let direction = match transfer.direction {
+                Direction::In => "in",
+                Direction::Out => "out",
+                Direction::FeatureIn => "feature_in",
+                Direction::FeatureOut => "feature_out",
+            };
+            let row = format!(
+                "{:.6},{},{},{},",
+                transfer.timestamp.as_secs_f64(),
+                transfer.interface,
+                direction,
+                hex(&transfer.bytes)
+            );
+            (transfer.timestamp, row)
+        })
+        .collect();
+    for marker in &capture.markers {
+        // quoted, with quotes doubled
+        let text = marker.text.as_deref().unwrap_or("").replace('"', "\"\"");
+        let row = format!("{:.6},,,,\"{}\"", marker.timestamp.as_secs_f64(), text);
+        rows.push((marker.timestamp, row));
+    }
+    rows.sort_by_key(|(timestamp, _)| *timestamp);
+
+    for (_, row) in rows {
+        writeln!(writer, "{}", row)?;
+    }
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    text.split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::{
+        capture::{Capture, DeviceMetadata, Direction, Marker, Transfer},
+        ReportDescriptor,
+    };
+
+    use super::{convert, read, ConvertFormat};
+
+    fn transfer(micros: u64, interface: u8, direction: Direction, bytes: &[u8]) -> Transfer {
+        Transfer {
+            timestamp: Duration::from_micros(micros),
+            interface,
+            direction,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    fn receiver() -> Capture {
+        Capture {
+            device: DeviceMetadata {
+                vendor_id: 0x046d,
+                product_id: 0xc52b,
+                manufacturer: Some("Logitech".to_string()),
+                product: Some("USB Receiver".to_string()),
+                serial: None,
+            },
+            descriptors: [
+                (
+                    0,
+                    ReportDescriptor {
+                        bytes: vec![0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0xc0],
+                    },
+                ),
+                (
+                    2,
+                    ReportDescriptor {
+                        bytes: vec![0x06, 0x00, 0xff, 0xa1, 0x01, 0xc0],
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+            transfers: vec![
+                transfer(1500, 0, Direction::In, &[0x00, 0x04]),
+                transfer(2_000_250, 2, Direction::Out, &[0x10, 0xff]),
+                transfer(2_001_000, 2, Direction::In, &[0x10, 0xff, 0x01]),
+                transfer(3_000_000, 5, Direction::In, &[0x01]),
+            ],
+            markers: vec![],
+        }
+    }
+
+    #[test]
+    fn converts_to_hid_recorder() {
+        let capture = receiver();
+
+        let mut output = vec![];
+        convert(&capture, ConvertFormat::HidRecorder, &mut output).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "# Logitech USB Receiver\n\
+             # Recorded with hid-bench\n\
+             D: 0\n\
+             # Interface #0\n\
+             R: 7 05 01 09 06 a1 01 c0\n\
+             N: Logitech USB Receiver\n\
+             I: 3 046d c52b\n\
+             D: 1\n\
+             # Interface #2\n\
+             R: 6 06 00 ff a1 01 c0\n\
+             N: Logitech USB Receiver\n\
+             I: 3 046d c52b\n\
+             D: 0\n\
+             E: 000000.001500 2 00 04\n\
+             D: 1\n\
+             # Out 000002.000250 2 10 ff\n\
+             D: 1\n\
+             E: 000002.001000 3 10 ff 01\n"
+        );
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let mut capture = receiver();
+        capture.markers.push(Marker {
+            timestamp: Duration::from_millis(2500),
+            text: Some("pressed \"A\"".to_string()),
+        });
+
+        for format in [ConvertFormat::Capture, ConvertFormat::Jsonl] {
+            let mut output = vec![];
+            convert(&capture, format, &mut output).unwrap();
+            assert_eq!(read(&output).unwrap(), capture, "{:?}", format);
+        }
+
+        // recordings lose the device strings, markers and reports of undescribed interfaces
+        let mut output = vec![];
+        convert(&capture, ConvertFormat::HidRecorder, &mut output).unwrap();
+        let recording = read(&output).unwrap();
+        assert_eq!(recording.descriptors, capture.descriptors);
+        assert_eq!(recording.transfers, capture.transfers[..3]);
+        assert_eq!(recording.device.vendor_id, 0x046d);
+
+        let mut output = vec![];
+        convert(&capture, ConvertFormat::Csv, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "seconds,interface,direction,bytes,marker\n\
+             0.001500,0,in,00 04,\n\
+             2.000250,2,out,10 ff,\n\
+             2.001000,2,in,10 ff 01,\n\
+             2.500000,,,,\"pressed \"\"A\"\"\"\n\
+             3.000000,5,in,01,\n"
+        );
+    }
+}
@@ -0,0 +1,614 @@
+// Values derived from the fields of every report, for quick experiments while logging
+//
+// A derivation names an expression over fields, e.g. `speed = sqrt(X^2 + Y^2)`. Fields are
+// written as field paths: bare when they are a single word (X, Wheel), in braces otherwise
+// ({Button 1}, {report#2/Joystick/X}). Expressions have
+//
+// - numbers, + - * / % and ^ (power), with the usual precedence
+// - comparisons < <= > >= == != and logic && || !, which are 1 when true and 0 when false
+// - sqrt, abs, min, max, hypot and atan2 (in degrees)
+// - count(e), how many times e became true, and total(e), the running sum of e
+//
+// so `combos = count({Button 1} && {Button 2})` counts the times both buttons went down
+// together. Fields keep their value from the last report carrying them, a derivation has no
+// value until every field it uses was reported. A field has to be an input field of the
+// interface logged, resolved by the rules of field paths.
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::{FieldAddress, FieldPath, FlatInputs, InputValue, Parser, ReportKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Derivation {
+    pub name: String,
+    expr: Expr,
+    paths: Vec<FieldPath>, // of the fields, by `Expr::Field` index
+    stateful: usize,       // slots of state of count() and total()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Number(f64),
+    Field(usize),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Count(usize, Box<Expr>), // slots of the count and whether the condition held
+    Total(usize, Box<Expr>), // slot of the sum
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or,
+    And,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Sqrt,
+    Abs,
+    Min,
+    Max,
+    Hypot,
+    Atan2,
+}
+
+impl FromStr for Derivation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, expr) = s
+            .split_once('=')
+            .filter(|(name, _)| !name.trim().is_empty())
+            .ok_or_else(|| anyhow!("Expected NAME = EXPRESSION, found '{}'", s))?;
+
+        let mut parser = ExprParser {
+            tokens: tokenize(expr)?,
+            at: 0,
+            paths: vec![],
+            stateful: 0,
+        };
+        let expr = parser
+            .or()
+            .and_then(|expr| match parser.peek() {
+                None => Ok(expr),
+                Some(token) => Err(anyhow!("Unexpected {}", token)),
+            })
+            .with_context(|| format!("Invalid expression '{}'", expr.trim()))?;
+
+        Ok(Derivation {
+            name: name.trim().to_string(),
+            expr,
+            paths: parser.paths,
+            stateful: parser.stateful,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Word(String),
+    Path(String), // in braces
+    Symbol(&'static str),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Path(path) => write!(f, "{{{}}}", path),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+        }
+    }
+}
+
+// Longer symbols first
+const SYMBOLS: [&str; 18] = [
+    "||", "&&", "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "%", "^", "!", "(", ")", ",",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = s.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        let length = if c.is_ascii_digit() || c == '.' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = &rest[..length];
+            let number = number
+                .parse()
+                .map_err(|_| anyhow!("Invalid number '{}'", number))?;
+            tokens.push(Token::Number(number));
+            length
+        } else if c.is_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..length].to_string()));
+            length
+        } else if c == '{' {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| anyhow!("Unterminated field path '{}'", rest))?;
+            tokens.push(Token::Path(rest[1..end].to_string()));
+            end + 1
+        } else {
+            let symbol = SYMBOLS
+                .into_iter()
+                .find(|symbol| rest.starts_with(symbol))
+                .ok_or_else(|| anyhow!("Unexpected '{}'", c))?;
+            tokens.push(Token::Symbol(symbol));
+            symbol.len()
+        };
+
+        rest = rest[length..].trim_start();
+    }
+
+    Ok(tokens)
+}
+
+// Recursive descent, from the lowest precedence
+struct ExprParser {
+    tokens: Vec<Token>,
+    at: usize,
+    paths: Vec<FieldPath>,
+    stateful: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end"))?;
+        self.at += 1;
+
+        Ok(token)
+    }
+
+    // The operator next, if it is one of `ops`
+    fn op(&mut self, ops: &[(&str, Op)]) -> Option<Op> {
+        let Some(Token::Symbol(symbol)) = self.peek() else {
+            return None;
+        };
+        let (_, op) = ops.iter().find(|(s, _)| s == symbol)?;
+        self.at += 1;
+
+        Some(*op)
+    }
+
+    // Skips the symbol if it is next
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let next = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if next {
+            self.at += 1;
+        }
+
+        next
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<()> {
+        match self.next()? {
+            Token::Symbol(s) if s == symbol => Ok(()),
+            token => Err(anyhow!("Expected '{}', found {}", symbol, token)),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[(&str, Op)],
+        operand: fn(&mut Self) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut expr = operand(self)?;
+        while let Some(op) = self.op(ops) {
+            expr = Expr::Binary(op, Box::new(expr), Box::new(operand(self)?));
+        }
+
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        self.binary(&[("||", Op::Or)], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        self.binary(&[("&&", Op::And)], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let ops = [
+            ("<", Op::Less),
+            ("<=", Op::LessEqual),
+            (">", Op::Greater),
+            (">=", Op::GreaterEqual),
+            ("==", Op::Equal),
+            ("!=", Op::NotEqual),
+        ];
+        let expr = self.sum()?;
+        match self.op(&ops) {
+            Some(op) => Ok(Expr::Binary(op, Box::new(expr), Box::new(self.sum()?))),
+            None => Ok(expr),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr> {
+        self.binary(&[("+", Op::Add), ("-", Op::Subtract)], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr> {
+        let ops = [("*", Op::Multiply), ("/", Op::Divide), ("%", Op::Remainder)];
+        self.binary(&ops, Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Symbol("-")) => {
+                self.at += 1;
+                Ok(Expr::Negate(Box::new(self.unary()?)))
+            }
+            Some(Token::Symbol("!")) => {
+                self.at += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            _ => self.power(),
+        }
+    }
+
+    // Right associative, binding tighter than negation: -X^2 is -(X^2)
+    fn power(&mut self) -> Result<Expr> {
+        let base = self.atom()?;
+        match self.op(&[("^", Op::Power)]) {
+            Some(op) => Ok(Expr::Binary(op, Box::new(base), Box::new(self.unary()?))),
+            None => Ok(base),
+        }
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next()? {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Symbol("(") => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Word(word) if self.peek() == Some(&Token::Symbol("(")) => {
+                self.at += 1;
+                self.call(&word)
+            }
+            Token::Word(path) | Token::Path(path) => {
+                self.paths.push(path.parse()?);
+                Ok(Expr::Field(self.paths.len() - 1))
+            }
+            token => Err(anyhow!("Unexpected {}", token)),
+        }
+    }
+
+    // After the opening parenthesis
+    fn call(&mut self, name: &str) -> Result<Expr> {
+        let mut args = vec![self.or()?];
+        while self.symbol(",") {
+            args.push(self.or()?);
+        }
+        self.expect(")")?;
+
+        let (function, arity) = match name {
+            "count" | "total" => {
+                if args.len() != 1 {
+                    return Err(anyhow!("{}() takes one argument", name));
+                }
+                let slot = self.stateful;
+                let arg = Box::new(args.remove(0));
+
+                return Ok(match name {
+                    "count" => {
+                        self.stateful += 2;
+                        Expr::Count(slot, arg)
+                    }
+                    _ => {
+                        self.stateful += 1;
+                        Expr::Total(slot, arg)
+                    }
+                });
+            }
+            "sqrt" => (Function::Sqrt, 1),
+            "abs" => (Function::Abs, 1),
+            "min" => (Function::Min, 2),
+            "max" => (Function::Max, 2),
+            "hypot" => (Function::Hypot, 2),
+            "atan2" => (Function::Atan2, 2),
+            _ => return Err(anyhow!("Unknown function {}()", name)),
+        };
+        if args.len() != arity {
+            return Err(anyhow!(
+                "{}() takes {} argument{}",
+                name,
+                arity,
+                if arity == 1 { "" } else { "s" }
+            ));
+        }
+
+        Ok(Expr::Call(function, args))
+    }
+}
+
+// Derivations evaluated report by report
+pub struct Derived {
+    derivations: Vec<(Derivation, Vec<FieldAddress>)>,
+    fields: Vec<Vec<Option<f64>>>, // last values, by derivation and field
+    state: Vec<Vec<f64>>,          // of count() and total(), by derivation
+    results: Vec<Option<f64>>,
+}
+
+impl Derived {
+    // Resolves the fields of the derivations against the input fields of the parser
+    pub fn new(parser: &Parser, derivations: &[Derivation]) -> Result<Self> {
+        let mut resolved = vec![];
+        for derivation in derivations {
+            let addresses = derivation
+                .paths
+                .iter()
+                .map(|path| parser.field(path, ReportKind::Input))
+                .collect::<Result<Vec<_>>>()
+                .with_context(|| format!("Cannot derive {}", derivation.name))?;
+            resolved.push((derivation.clone(), addresses));
+        }
+
+        Ok(Self {
+            fields: resolved.iter().map(|(_, a)| vec![None; a.len()]).collect(),
+            state: resolved
+                .iter()
+                .map(|(d, _)| vec![0.0; d.stateful])
+                .collect(),
+            results: vec![None; resolved.len()],
+            derivations: resolved,
+        })
+    }
+
+    // The derived values after the report. A derivation is evaluated on the reports carrying
+    // any of its fields, so totals of relative fields count every report once, and has no value
+    // until all its fields were reported.
+    pub fn update(&mut self, flat: &FlatInputs) -> Vec<(&str, Option<f64>)> {
+        for (i, (derivation, addresses)) in self.derivations.iter().enumerate() {
+            let fields = &mut self.fields[i];
+            let mut reported = addresses.is_empty();
+            for (field, address) in fields.iter_mut().zip(addresses) {
+                if let Some(value) = address.value(flat).and_then(number) {
+                    *field = Some(value);
+                    reported = true;
+                }
+            }
+            if !reported {
+                continue;
+            }
+
+            if let Some(fields) = fields.iter().copied().collect::<Option<Vec<_>>>() {
+                let value = evaluate(&derivation.expr, &fields, &mut self.state[i]);
+                self.results[i] = Some(value);
+            }
+        }
+
+        self.derivations
+            .iter()
+            .zip(&self.results)
+            .map(|((derivation, _), value)| (derivation.name.as_str(), *value))
+            .collect()
+    }
+}
+
+fn number(value: InputValue) -> Option<f64> {
+    match value {
+        InputValue::Bool(b) => Some(b as u8 as f64),
+        InputValue::UInt(u) => Some(u as f64),
+        InputValue::Int(i) => Some(i as f64),
+        InputValue::Selected => Some(1.0),
+        _ => None,
+    }
+}
+
+fn truth(value: bool) -> f64 {
+    value as u8 as f64
+}
+
+fn evaluate(expr: &Expr, fields: &[f64], state: &mut [f64]) -> f64 {
+    match expr {
+        Expr::Number(n) => *n,
+        Expr::Field(index) => fields[*index],
+        Expr::Not(expr) => truth(evaluate(expr, fields, state) == 0.0),
+        Expr::Negate(expr) => -evaluate(expr, fields, state),
+        Expr::Binary(op, a, b) => {
+            let a = evaluate(a, fields, state);
+            let b = evaluate(b, fields, state);
+            match op {
+                Op::Or => truth(a != 0.0 || b != 0.0),
+                Op::And => truth(a != 0.0 && b != 0.0),
+                Op::Less => truth(a < b),
+                Op::LessEqual => truth(a <= b),
+                Op::Greater => truth(a > b),
+                Op::GreaterEqual => truth(a >= b),
+                Op::Equal => truth(a == b),
+                Op::NotEqual => truth(a != b),
+                Op::Add => a + b,
+                Op::Subtract => a - b,
+                Op::Multiply => a * b,
+                Op::Divide => a / b,
+                Op::Remainder => a % b,
+                Op::Power => a.powf(b),
+            }
+        }
+        Expr::Call(function, args) => {
+            let args: Vec<_> = args
+                .iter()
+                .map(|arg| evaluate(arg, fields, state))
+                .collect();
+            match function {
+                Function::Sqrt => args[0].sqrt(),
+                Function::Abs => args[0].abs(),
+                Function::Min => args[0].min(args[1]),
+                Function::Max => args[0].max(args[1]),
+                Function::Hypot => args[0].hypot(args[1]),
+                Function::Atan2 => args[0].atan2(args[1]).to_degrees(),
+            }
+        }
+        Expr::Count(slot, condition) => {
+            let holds = evaluate(condition, fields, state) != 0.0;
+            if holds && state[slot + 1] == 0.0 {
+                state[*slot] += 1.0;
+            }
+            state[slot + 1] = truth(holds);
+
+            state[*slot]
+        }
+        Expr::Total(slot, expr) => {
+            state[*slot] += evaluate(expr, fields, state);
+            state[*slot]
+        }
+    }
+}
+
+// `speed 7.07, combos 3`, with - for values not known yet
+pub fn format(values: &[(&str, Option<f64>)]) -> String {
+    values
+        .iter()
+        .map(|(name, value)| match value {
+            Some(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+                format!("{} {}", name, *value as i64)
+            }
+            Some(value) => format!("{} {:.2}", name, value),
+            None => format!("{} -", name),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, FlatInputs, InputItemData, Parser};
+
+    use super::{format, Derivation, Derived};
+
+    // Two buttons and X, Y in report 1, a wheel in report 2
+    fn mouse() -> Parser {
+        DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(2)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(2)
+            .input(InputItemData { data: 0x02 })
+            .report_count(6)
+            .input(InputItemData { data: 0x01 })
+            .usage_page(0x01)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 })
+            .report_id(2)
+            .usage(0x38)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn derives_values_from_fields() {
+        let parser = mouse();
+        let derivations: Vec<Derivation> = [
+            "speed = sqrt(X^2 + Y^2)",
+            "combos = count({Button 1} && {Button 2})",
+            "scrolled = total(abs(Wheel))",
+            "mixed = -2^2 + 7 % 4 * (1 < 2)",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let mut derived = Derived::new(&parser, &derivations).unwrap();
+        let mut flat = FlatInputs::new();
+
+        let mut lines = vec![];
+        for report in [
+            [0x01, 0b11, 3, 4].as_slice(),
+            &[0x02, 0xfe],
+            &[0x01, 0b11, 0, 0],
+            &[0x01, 0b01, 0, 0],
+            &[0x01, 0b11, 6, 8],
+            &[0x02, 0x03],
+        ] {
+            parser.parse_input_flat(report, &mut flat);
+            lines.push(format(&derived.update(&flat)));
+        }
+
+        assert_eq!(
+            lines,
+            [
+                "speed 5, combos 1, scrolled -, mixed -1",
+                "speed 5, combos 1, scrolled 2, mixed -1",
+                "speed 0, combos 1, scrolled 2, mixed -1",
+                "speed 0, combos 1, scrolled 2, mixed -1",
+                "speed 10, combos 2, scrolled 2, mixed -1",
+                "speed 10, combos 2, scrolled 5, mixed -1",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_derivations() {
+        let error = |s: &str| match s.parse::<Derivation>() {
+            Ok(derivation) => Derived::new(&mouse(), &[derivation])
+                .err()
+                .map(|e| format!("{:#}", e)),
+            Err(e) => Some(format!("{:#}", e)),
+        };
+
+        assert_eq!(
+            error("a = sqrt(X").unwrap(),
+            "Invalid expression 'sqrt(X': Unexpected end"
+        );
+        assert_eq!(
+            error("a = max(X)").unwrap(),
+            "Invalid expression 'max(X)': max() takes 2 arguments"
+        );
+        assert_eq!(
+            error("a = X Y").unwrap(),
+            "Invalid expression 'X Y': Unexpected 'Y'"
+        );
+        assert_eq!(
+            error("a = Z").unwrap(),
+            "Cannot derive a: No input field matches 'Z'"
+        );
+        assert_eq!(error("a = X * 2"), None);
+    }
+}
@@ -0,0 +1,330 @@
+// Report descriptors of a device, from the platform's best source
+//
+// Platforms keeping the descriptors of their devices (macOS) are asked first. On Windows the
+// OS owns HID interfaces and libusb usually cannot open them, so the descriptors come from
+// hidapi (rebuilt by Windows from the cached preparsed data). Elsewhere they are read from the
+// device with USB control transfers, falling back to hidapi for devices libusb cannot reach
+// (e.g. Bluetooth).
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use hidapi::{DeviceInfo, HidApi, HidDevice};
+
+use hid_parser::{DeviceModel, ReportDescriptor, TransferPolicy};
+
+use crate::{cache, config::DeviceSpec, dump, exit::Exit, permissions, platform, trace, usb};
+
+// Where a report descriptor belongs, the configuration (its bConfigurationValue) and alternate
+// setting are only known for descriptors read over USB
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Setting {
+    pub configuration: Option<u8>,
+    pub interface: u8,
+    pub alternate: Option<u8>,
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.configuration {
+            Some(configuration) => write!(
+                f,
+                "Configuration {}, interface #{}",
+                configuration, self.interface
+            )?,
+            None => write!(f, "Interface #{}", self.interface)?,
+        }
+        if let Some(alternate) = self.alternate {
+            write!(f, ", alternate setting {}", alternate)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Setting {
+    // e.g. `config-1-interface-0-alt-0`
+    fn file_stem(&self) -> String {
+        let mut stem = String::new();
+        if let Some(configuration) = self.configuration {
+            stem.push_str(&format!("config-{}-", configuration));
+        }
+        stem.push_str(&format!("interface-{}", self.interface));
+        if let Some(alternate) = self.alternate {
+            stem.push_str(&format!("-alt-{}", alternate));
+        }
+
+        stem
+    }
+}
+
+// Writes the raw descriptor bytes. A single descriptor goes to `path` (unless it is a directory),
+// several are written into the directory `path` one file each. Returns the written files.
+pub fn write(
+    descriptors: &BTreeMap<Setting, Vec<ReportDescriptor>>,
+    path: &Path,
+) -> Result<Vec<PathBuf>> {
+    let count = descriptors.values().map(Vec::len).sum::<usize>();
+    if count == 0 {
+        return Err(anyhow!("There are no report descriptors to write"));
+    }
+
+    let mut files = vec![];
+    for (setting, report_descriptors) in descriptors {
+        for (n, descriptor) in report_descriptors.iter().enumerate() {
+            let file = match count == 1 && !path.is_dir() {
+                true => path.to_path_buf(),
+                false if report_descriptors.len() == 1 => {
+                    path.join(format!("{}.bin", setting.file_stem()))
+                }
+                false => path.join(format!("{}-{}.bin", setting.file_stem(), n)),
+            };
+
+            files.push((file, &descriptor.bytes));
+        }
+    }
+
+    if count > 1 {
+        fs::create_dir_all(path)
+            .map_err(|e| anyhow!("Cannot create directory {}: {}", path.display(), e))?;
+    }
+
+    for (file, bytes) in &files {
+        fs::write(file, bytes).map_err(|e| anyhow!("Cannot write {}: {}", file.display(), e))?;
+    }
+
+    Ok(files.into_iter().map(|(file, _)| file).collect())
+}
+
+// Report descriptors by interface number, with the descriptor quirk applied, from the cache
+// for devices read before
+pub fn report_descriptors(api: &HidApi, spec: &DeviceSpec) -> Result<DeviceModel> {
+    report_descriptors_keeping(api, spec, &mut |_, _| ())
+}
+
+// Like `report_descriptors`, handing the devices hidapi opened to read them over to `keep`
+pub fn report_descriptors_keeping(
+    api: &HidApi,
+    spec: &DeviceSpec,
+    keep: &mut dyn FnMut(u8, HidDevice),
+) -> Result<DeviceModel> {
+    let fingerprint = cache::Fingerprint::of(api, spec);
+    let mut descriptors = match fingerprint.as_ref().and_then(cache::load) {
+        Some(descriptors) => descriptors,
+        None => {
+            let descriptors =
+                from_best_source(api, spec, from_usb, |descriptors| descriptors, keep)?;
+            if let Some(fingerprint) = &fingerprint {
+                if !descriptors.is_empty() {
+                    // the descriptors are still good without the cache
+                    let _ = cache::store(fingerprint, &descriptors);
+                }
+            }
+            descriptors
+        }
+    };
+
+    if let Some((interface, descriptor)) = quirk_descriptor(spec)? {
+        descriptors.replace(interface, vec![descriptor]);
+    }
+
+    Ok(descriptors)
+}
+
+// Report descriptors of all configurations and alternate settings the source knows about,
+// with the descriptor quirk applied
+pub fn all_report_descriptors(
+    api: &HidApi,
+    spec: &DeviceSpec,
+) -> Result<BTreeMap<Setting, Vec<ReportDescriptor>>> {
+    let mut descriptors =
+        from_best_source(api, spec, from_usb_settings, by_interface, &mut |_, _| ())?;
+
+    if let Some((interface, descriptor)) = quirk_descriptor(spec)? {
+        descriptors.retain(|setting, _| setting.interface != interface);
+        descriptors.extend(by_interface(
+            [(interface, descriptor)].into_iter().collect(),
+        ));
+    }
+
+    Ok(descriptors)
+}
+
+fn from_best_source<T>(
+    api: &HidApi,
+    spec: &DeviceSpec,
+    from_usb: fn(&DeviceSpec) -> Result<T>,
+    by_interface: fn(DeviceModel) -> T,
+    keep: &mut dyn FnMut(u8, HidDevice),
+) -> Result<T> {
+    Ok(match platform::report_descriptors(spec) {
+        Some(Ok(descriptors)) => by_interface(descriptors),
+        _ if cfg!(windows) => by_interface(from_hidapi(api, spec, keep)?),
+        _ => match from_usb(spec) {
+            Ok(descriptors) => descriptors,
+            Err(err) => by_interface(from_hidapi(api, spec, keep).map_err(|_| err)?),
+        },
+    })
+}
+
+fn by_interface(descriptors: DeviceModel) -> BTreeMap<Setting, Vec<ReportDescriptor>> {
+    descriptors
+        .iter()
+        .map(|(interface, descriptors)| {
+            let setting = Setting {
+                configuration: None,
+                interface,
+                alternate: None,
+            };
+
+            (setting, descriptors.to_vec())
+        })
+        .collect()
+}
+
+fn quirk_descriptor(spec: &DeviceSpec) -> Result<Option<(u8, ReportDescriptor)>> {
+    let path = match &spec.quirks.descriptor {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let interface = spec
+        .interface
+        .ok_or_else(|| anyhow!("The descriptor quirk needs the device interface configured"))?;
+
+    Ok(Some((interface, dump::read_descriptor(path)?)))
+}
+
+pub fn from_usb(spec: &DeviceSpec) -> Result<DeviceModel> {
+    let devices = usb::hid_devices()?;
+    let usb_device = usb::find_device(&devices, spec).ok_or_else(|| not_found(spec))?;
+
+    usb::report_descriptors(usb_device, transfer_policy(spec))
+        .map_err(|err| permissions::explain(err, spec.vid, spec.pid))
+}
+
+fn from_usb_settings(spec: &DeviceSpec) -> Result<BTreeMap<Setting, Vec<ReportDescriptor>>> {
+    let devices = usb::hid_devices()?;
+    let usb_device = usb::find_device(&devices, spec).ok_or_else(|| not_found(spec))?;
+
+    usb::all_report_descriptors(usb_device, transfer_policy(spec))
+        .map_err(|err| permissions::explain(err, spec.vid, spec.pid))
+}
+
+fn transfer_policy(spec: &DeviceSpec) -> TransferPolicy {
+    let mut policy = TransferPolicy::default();
+    if let Some(timeout) = spec.quirks.descriptor_timeout_ms {
+        policy.timeout = Duration::from_millis(timeout);
+    }
+    if let Some(retries) = spec.quirks.descriptor_retries {
+        policy.retries = retries;
+    }
+
+    trace::with_tracing(policy)
+}
+
+// hidapi lists every top level collection separately on some platforms, they are kept
+// together under their interface. The opened devices go to `keep`, in the order hidapi lists
+// them.
+fn from_hidapi(
+    api: &HidApi,
+    spec: &DeviceSpec,
+    keep: &mut dyn FnMut(u8, HidDevice),
+) -> Result<DeviceModel> {
+    let mut descriptors = DeviceModel::new();
+    let mut found = false;
+
+    for info in api.device_list().filter(|info| is_device(info, spec)) {
+        found = true;
+
+        let device = info
+            .open_device(api)
+            .map_err(|err| permissions::explain(err, spec.vid, spec.pid))?;
+
+        // non-USB devices have no interface number
+        let interface = info.interface_number().max(0) as u8;
+
+        descriptors.add(interface, ReportDescriptor::from_hidapi(&device)?);
+        keep(interface, device);
+    }
+
+    if !found {
+        return Err(not_found(spec));
+    }
+
+    Ok(descriptors)
+}
+
+pub fn is_device(info: &DeviceInfo, spec: &DeviceSpec) -> bool {
+    info.vendor_id() == spec.vid
+        && info.product_id() == spec.pid
+        && (spec.serial.is_none() || info.serial_number() == spec.serial.as_deref())
+}
+
+pub fn not_found(spec: &DeviceSpec) -> anyhow::Error {
+    Exit::DeviceNotFound.error(format!(
+        "Could not find a HID device with vid {:04x} pid {:04x}",
+        spec.vid, spec.pid
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, env, fs};
+
+    use hid_parser::ReportDescriptor;
+
+    use super::{write, Setting};
+
+    fn setting(interface: u8) -> Setting {
+        Setting {
+            configuration: Some(1),
+            interface,
+            alternate: Some(0),
+        }
+    }
+
+    #[test]
+    fn writes_one_file_per_descriptor() {
+        let dir = env::temp_dir().join(format!("hid-bench-descriptors-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let single = BTreeMap::from([(setting(0), vec![ReportDescriptor { bytes: vec![0xc0] }])]);
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("descriptor.bin");
+        assert_eq!(write(&single, &file).unwrap(), vec![file.clone()]);
+        assert_eq!(fs::read(&file).unwrap(), vec![0xc0]);
+
+        let several = BTreeMap::from([
+            (setting(0), vec![ReportDescriptor { bytes: vec![0x01] }]),
+            (
+                setting(2),
+                vec![
+                    ReportDescriptor { bytes: vec![0x02] },
+                    ReportDescriptor { bytes: vec![0x03] },
+                ],
+            ),
+        ]);
+        let out = dir.join("out");
+        assert_eq!(
+            write(&several, &out).unwrap(),
+            vec![
+                out.join("config-1-interface-0-alt-0.bin"),
+                out.join("config-1-interface-2-alt-0-0.bin"),
+                out.join("config-1-interface-2-alt-0-1.bin"),
+            ]
+        );
+        assert_eq!(
+            fs::read(out.join("config-1-interface-2-alt-0-1.bin")).unwrap(),
+            vec![0x03]
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+// The backends command, comparing the reports read through hidapi and libusb
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    backends,
+    config::{Config, DeviceSpec},
+    descriptors,
+    output::{note, out},
+    permissions, record,
+    session::Session,
+    usb,
+};
+
+#[derive(Debug, Args)]
+pub struct BackendsArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// How long to read through each backend, keep using the device throughout
+    #[arg(value_name = "SECONDS", long, default_value_t = 5)]
+    seconds: u64,
+}
+
+pub fn run(args: BackendsArgs, config: &Config) -> Result<()> {
+    let BackendsArgs {
+        device,
+        interface,
+        seconds,
+    } = args;
+
+    let device = config.device(&device)?;
+    let interface = interface
+        .or(device.interface)
+        .ok_or_else(|| anyhow!("Interface must be given for this device"))?;
+
+    cmd_backends(&device, interface, Duration::from_secs(seconds))
+}
+
+// Read buffers of the backends comparison hold a high speed interrupt packet
+const MAX_PACKET: usize = 1024;
+
+fn cmd_backends(device: &DeviceSpec, interface: u8, window: Duration) -> Result<()> {
+    // the hidapi device is closed before libusb takes the interface from the OS driver
+    let (model, hidapi) = {
+        let mut session = Session::open(device)?;
+        let model = session.descriptors().clone();
+        let size = record::buffer_size(&model, interface).max(MAX_PACKET);
+
+        note!(
+            "Reading interface #{} through hidapi for {} s, keep using the device",
+            interface,
+            window.as_secs()
+        );
+        let reads = backends::read_hidapi(session.device(interface)?, size, window)?;
+
+        (model, reads)
+    };
+    let parser = model
+        .parser(interface)
+        .ok_or_else(|| anyhow!("Interface #{} has no report descriptor", interface))?;
+    let size = record::buffer_size(&model, interface).max(MAX_PACKET);
+
+    let devices = usb::hid_devices()?;
+    let usb_device =
+        usb::find_device(&devices, device).ok_or_else(|| descriptors::not_found(device))?;
+    let mut reads = vec![hidapi];
+    match usb::ClaimedInterface::claim(usb_device, interface) {
+        Ok(claimed) => {
+            note!(
+                "Reading interface #{} through libusb for {} s, keep using the device",
+                interface,
+                window.as_secs()
+            );
+            reads.push(backends::read_usb(&claimed, size, window)?);
+        }
+        // the OS driver keeps the interface on some platforms
+        Err(err) => note!(
+            "Cannot read through libusb, showing hidapi only: {}",
+            permissions::explain(err, device.vid, device.pid)
+        ),
+    }
+
+    out!("{}", backends::compare(parser, &reads));
+
+    Ok(())
+}
@@ -0,0 +1,81 @@
+// The battery command, showing the battery level of the device
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use hid_parser::vendor::DecoderRegistry;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    output::outln,
+    session::Session,
+};
+
+use super::read_battery;
+
+#[derive(Debug, Args)]
+pub struct BatteryArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Device paired with a wireless receiver
+    #[arg(value_name = "INDEX", long)]
+    device_index: Option<u8>,
+    /// Keep logging the battery level every SECONDS
+    #[arg(value_name = "SECONDS", long)]
+    interval: Option<u64>,
+}
+
+pub fn run(args: BatteryArgs, config: &Config) -> Result<()> {
+    let BatteryArgs {
+        device,
+        device_index,
+        interval,
+    } = args;
+    let mut decoders = DecoderRegistry::with_builtin();
+
+    let device = config.device(&device)?;
+    let mut session = Session::open(&device)?;
+
+    if device.quirks.no_decoder {
+        decoders = DecoderRegistry::new();
+    }
+
+    cmd_battery(&mut session, &device, &mut decoders, device_index, interval)
+}
+
+fn cmd_battery(
+    session: &mut Session,
+    device: &DeviceSpec,
+    decoders: &mut DecoderRegistry,
+    device_index: Option<u8>,
+    interval: Option<u64>,
+) -> Result<()> {
+    let start = Instant::now();
+
+    loop {
+        let battery = read_battery(session, device, decoders, device_index)?;
+
+        let interval = match (interval, battery) {
+            (None, Some(battery)) => {
+                outln!("Battery: {}", battery);
+                return Ok(());
+            }
+            (None, None) => return Err(anyhow!("The device does not report its battery level")),
+            (Some(interval), battery) => {
+                let level = battery
+                    .map(|b| b.to_string())
+                    .unwrap_or_else(|| "unknown".to_string());
+                outln!("[+{:06} s]: {}", start.elapsed().as_secs(), level);
+
+                interval
+            }
+        };
+
+        thread::sleep(Duration::from_secs(interval));
+    }
+}
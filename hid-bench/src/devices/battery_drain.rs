@@ -0,0 +1,187 @@
+// The battery-drain command, sampling the battery level for hours at report rates in turn
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use hid_parser::vendor::DecoderRegistry;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    drain::{self, DrainSample, Stage},
+    find,
+    output::{note, outln},
+    session::Session,
+};
+
+use super::read_battery;
+
+#[derive(Debug, Args)]
+pub struct BatteryDrainArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Interface whose input reports are counted, defaults to the interface configured for
+    /// the device alias, or the only HID interface
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Device paired with a wireless receiver
+    #[arg(value_name = "INDEX", long)]
+    device_index: Option<u8>,
+    /// Report rates to compare, in Hz. You are asked to set the device to each in turn,
+    /// without them the device is measured at the rate it has
+    #[arg(value_name = "HZ,...", long, value_delimiter = ',')]
+    rates: Vec<u32>,
+    /// How long to measure at each rate
+    #[arg(value_name = "HOURS", long, default_value_t = 8.0)]
+    stage_hours: f64,
+    /// Time between battery samples
+    #[arg(value_name = "MINUTES", long, default_value_t = 5)]
+    sample_minutes: u64,
+    /// Also write every sample to a CSV file
+    #[arg(value_name = "FILE", long)]
+    csv: Option<PathBuf>,
+}
+
+pub fn run(args: BatteryDrainArgs, config: &Config) -> Result<()> {
+    let BatteryDrainArgs {
+        device,
+        interface,
+        device_index,
+        rates,
+        stage_hours,
+        sample_minutes,
+        csv,
+    } = args;
+    let mut decoders = DecoderRegistry::with_builtin();
+
+    let device = config.device(&device)?;
+    let mut session = Session::open(&device)?;
+
+    if device.quirks.no_decoder {
+        decoders = DecoderRegistry::new();
+    }
+    let interface = match interface.or(device.interface) {
+        Some(interface) => interface,
+        None => {
+            let parsers: Vec<_> = session.parsers().collect();
+            find::select_interface(None, &parsers)?
+        }
+    };
+    let stages = match rates.is_empty() {
+        true => vec![Stage::new(None)],
+        false => rates
+            .into_iter()
+            .map(|rate| Stage::new(Some(rate)))
+            .collect(),
+    };
+    let options = DrainOptions {
+        interface,
+        device_index,
+        stage: Duration::from_secs_f64(stage_hours * 3600.0),
+        sample: Duration::from_secs(sample_minutes * 60),
+        csv,
+    };
+
+    cmd_battery_drain(&mut session, &device, &mut decoders, stages, &options)
+}
+
+struct DrainOptions {
+    interface: u8, // whose input reports are counted
+    device_index: Option<u8>,
+    stage: Duration,
+    sample: Duration,
+    csv: Option<PathBuf>,
+}
+
+fn cmd_battery_drain(
+    session: &mut Session,
+    device: &DeviceSpec,
+    decoders: &mut DecoderRegistry,
+    mut stages: Vec<Stage>,
+    options: &DrainOptions,
+) -> Result<()> {
+    let mut csv = match &options.csv {
+        Some(path) => {
+            let mut file =
+                File::create(path).with_context(|| format!("Cannot write {}", path.display()))?;
+            writeln!(file, "{}", drain::CSV_HEADER)?;
+            Some(file)
+        }
+        None => None,
+    };
+    let mut buf = [0u8; 64];
+    let count = stages.len();
+
+    for (n, stage) in stages.iter_mut().enumerate() {
+        if let Some(target) = stage.target {
+            note!(
+                "Stage {} of {}: set the device to {} Hz, keep it reporting (e.g. with a mouse \
+                 jiggler) and press Enter",
+                n + 1,
+                count,
+                target
+            );
+            io::stdin().read_line(&mut String::new())?;
+        }
+
+        let start = Instant::now();
+        let mut last = start;
+        let mut reports = 0u64;
+        loop {
+            let battery = read_battery(session, device, decoders, options.device_index)?;
+            let now = Instant::now();
+            let sample = DrainSample {
+                elapsed: now - start,
+                level: battery.map(|battery| battery.level),
+                // counted since the previous sample
+                rate: (!stage.samples.is_empty())
+                    .then(|| reports as f64 / (now - last).as_secs_f64()),
+            };
+
+            let level = battery
+                .map(|b| b.to_string())
+                .unwrap_or_else(|| "unknown".to_string());
+            let rate = sample
+                .rate
+                .map(|rate| format!(", {:.1} reports/s", rate))
+                .unwrap_or_default();
+            outln!("[+{:06} s]: {}{}", sample.elapsed.as_secs(), level, rate);
+            if stage.behind(&sample) {
+                note!("The device reported below the stage's rate, is it still kept reporting?");
+            }
+            if let Some(file) = &mut csv {
+                writeln!(file, "{}", sample.csv(stage.target))?;
+            }
+
+            let empty = sample.level == Some(0);
+            stage.samples.push(sample);
+            if empty || now - start >= options.stage {
+                break;
+            }
+
+            // count the input reports until the next sample
+            last = now;
+            reports = 0;
+            let next = now + options.sample;
+            let hid_device = session.device(options.interface)?;
+            while Instant::now() < next {
+                if hid_device.read_timeout(&mut buf, 100)? > 0 {
+                    reports += 1;
+                }
+            }
+        }
+    }
+
+    outln!();
+    for stage in &stages {
+        outln!("{}", stage);
+    }
+
+    Ok(())
+}
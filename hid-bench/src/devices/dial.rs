@@ -0,0 +1,67 @@
+// The dial command, showing a radial controller live
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    dial,
+    session::Session,
+};
+
+use super::wait_for_device;
+
+#[derive(Debug, Args)]
+pub struct DialArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or the first with a dial
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Play a click on the haptic output on every press
+    #[arg(long)]
+    haptics: bool,
+    /// Width of the gauge of one full turn
+    #[arg(value_name = "COLUMNS", long, default_value = "36")]
+    width: usize,
+    /// Wait for the device to be plugged in
+    #[arg(long)]
+    wait: bool,
+}
+
+pub fn run(args: DialArgs, config: &Config) -> Result<()> {
+    let DialArgs {
+        device,
+        interface,
+        haptics,
+        width,
+        wait,
+    } = args;
+
+    let device = config.device(&device)?;
+
+    if wait {
+        wait_for_device(&device)?;
+    }
+    let mut session = Session::open(&device)?;
+
+    cmd_dial(&mut session, &device, interface, haptics, width.max(1))
+}
+
+fn cmd_dial(
+    session: &mut Session,
+    device: &DeviceSpec,
+    interface: Option<u8>,
+    haptics: bool,
+    width: usize,
+) -> Result<()> {
+    let (interface, parser) = match interface.or(device.interface) {
+        Some(interface) => (interface, session.parser(interface)?),
+        None => session
+            .parsers()
+            .find(|(_, parser)| parser.is_radial_controller())
+            .ok_or_else(|| anyhow!("The device is not a radial controller"))?,
+    };
+
+    dial::run(session.device(interface)?, &parser, haptics, width)
+}
@@ -0,0 +1,54 @@
+// The evdev command, comparing raw reports with the evdev events made of them
+
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::{
+    config::Config,
+    evdev,
+    output::{note, outln},
+};
+
+#[derive(Debug, Args)]
+pub struct EvdevArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or the first one with
+    /// input events
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    #[arg(value_name = "SECONDS", long, default_value_t = 10)]
+    seconds: u64,
+}
+
+pub fn run(args: EvdevArgs, config: &Config) -> Result<()> {
+    let EvdevArgs {
+        device,
+        interface,
+        seconds,
+    } = args;
+
+    let device = config.device(&device)?;
+    let nodes = evdev::find(device.vid, device.pid, interface.or(device.interface))?;
+    note!(
+        "Reading {} and {} for {} s, use the device",
+        nodes.hidraw.display(),
+        nodes
+            .events
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        seconds
+    );
+
+    let arrivals = evdev::read(&nodes, Duration::from_secs(seconds))?;
+    outln!(
+        "{}",
+        evdev::correlate(&nodes.descriptor.decode(), &arrivals)
+    );
+
+    Ok(())
+}
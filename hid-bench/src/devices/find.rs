@@ -0,0 +1,105 @@
+// The find command, listing the devices exposing a usage
+
+use anyhow::Result;
+use clap::Args;
+use hidapi::HidApi;
+
+use crate::{
+    config::DeviceSpec,
+    descriptors,
+    find::UsageFilter,
+    output::{note, outln},
+    permissions,
+    render::Renderer,
+};
+
+#[derive(Debug, Args)]
+pub struct FindArgs {
+    /// e.g. "Generic Desktop/Gamepad", "0x0c/0xe9", or a usage page alone
+    #[arg(value_name = "PAGE[/USAGE]", long, short)]
+    usage: UsageFilter,
+}
+
+pub fn run(args: FindArgs, renderer: &Renderer) -> Result<()> {
+    let FindArgs { usage } = args;
+
+    cmd_find(&usage, renderer)
+}
+
+// Devices are read once per vendor and product ID, hidapi lists every interface separately
+fn cmd_find(filter: &UsageFilter, renderer: &Renderer) -> Result<()> {
+    let api = HidApi::new()?;
+    let mut scanned = vec![];
+    let mut found = false;
+    let mut inaccessible = vec![];
+
+    for info in api.device_list() {
+        let (vid, pid) = (info.vendor_id(), info.product_id());
+        if scanned.contains(&(vid, pid)) {
+            continue;
+        }
+        scanned.push((vid, pid));
+
+        let spec = DeviceSpec {
+            vid,
+            pid,
+            ..Default::default()
+        };
+        let interfaces = match descriptors::report_descriptors(&api, &spec) {
+            Ok(interfaces) => interfaces,
+            Err(err) => {
+                note!(
+                    "[{:04X}:{:04X}]: {}",
+                    vid,
+                    pid,
+                    renderer.warning(&format!("<{}>", err))
+                );
+                if permissions::is_access_error(&err) {
+                    inaccessible.push((vid, pid));
+                }
+                continue;
+            }
+        };
+
+        let mut matches = vec![];
+        for (interface, parser) in interfaces.parsers() {
+            for usage in filter.collections(parser) {
+                matches.push(format!(
+                    "Interface #{}: {}",
+                    interface,
+                    renderer.usage(&hid_parser::usage::describe(usage))
+                ));
+            }
+        }
+        if matches.is_empty() {
+            continue;
+        }
+
+        found = true;
+        outln!(
+            "[{:04X}:{:04X}]: \"{}: {}\"",
+            vid,
+            pid,
+            info.manufacturer_string().unwrap_or_default(),
+            info.product_string().unwrap_or_default(),
+        );
+        for line in matches {
+            outln!("    {}", line);
+        }
+    }
+
+    if !found {
+        outln!("No device exposes {}", filter);
+    }
+
+    for (vid, pid) in inaccessible {
+        note!(
+            "\n[{:04X}:{:04X}]: {}",
+            vid,
+            pid,
+            renderer.warning(&permissions::guidance(vid, pid))
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,96 @@
+// The idle command, testing the idle rates set with SET_IDLE
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    descriptors,
+    exit::Exit,
+    idle,
+    output::{note, outln},
+    permissions, usb,
+};
+
+#[derive(Debug, Args)]
+pub struct IdleArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Idle durations in milliseconds, multiples of 4. 0 reports only changes.
+    #[arg(
+        value_name = "MS,...",
+        long,
+        value_delimiter = ',',
+        value_parser = idle::parse_duration,
+        default_value = "0,24,100,500"
+    )]
+    rates: Vec<Duration>,
+    /// 0 sets the idle rate of all input reports
+    #[arg(value_name = "N", long, default_value_t = 0)]
+    report_id: u8,
+    /// How long to listen at every rate
+    #[arg(value_name = "SECONDS", long, default_value_t = 3)]
+    seconds: u64,
+}
+
+pub fn run(args: IdleArgs, config: &Config) -> Result<()> {
+    let IdleArgs {
+        device,
+        interface,
+        rates,
+        report_id,
+        seconds,
+    } = args;
+
+    let device = config.device(&device)?;
+    let interface = interface
+        .or(device.interface)
+        .ok_or_else(|| anyhow!("Interface must be given for this device"))?;
+
+    cmd_idle(
+        &device,
+        interface,
+        &rates,
+        report_id,
+        Duration::from_secs(seconds),
+    )
+}
+
+fn cmd_idle(
+    device: &DeviceSpec,
+    interface: u8,
+    rates: &[Duration],
+    report_id: u8,
+    window: Duration,
+) -> Result<()> {
+    let devices = usb::hid_devices()?;
+    let usb_device =
+        usb::find_device(&devices, device).ok_or_else(|| descriptors::not_found(device))?;
+    let claimed = usb::ClaimedInterface::claim(usb_device, interface)
+        .map_err(|err| permissions::explain(err, device.vid, device.pid))?;
+
+    note!(
+        "Testing idle rates on interface #{}, leave the device untouched",
+        interface
+    );
+    let results = idle::run(&claimed, report_id, rates, window)?;
+
+    let compliant = results
+        .iter()
+        .filter(|result| result.verdict == idle::Verdict::Compliant)
+        .count();
+    outln!("{} of {} idle rates compliant", compliant, results.len());
+    if compliant < results.len() {
+        return Err(Exit::ThresholdExceeded.error(format!(
+            "{} idle rates not compliant",
+            results.len() - compliant
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+// The latency command, comparing the latency after an idle period with the steady state
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use hidapi::HidApi;
+
+use crate::{
+    config::Config,
+    dump,
+    html::{Chart, HtmlReport},
+    latency::{self, LatencyOptions},
+    output::{out, outln},
+    permissions, plot,
+    session::open_interface,
+    write_html,
+};
+
+#[derive(Debug, Args)]
+pub struct LatencyArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Idle period before every measured input
+    #[arg(value_name = "SECONDS", long, default_value_t = 5.0)]
+    idle: f64,
+    #[arg(value_name = "N", long, default_value_t = 5)]
+    rounds: usize,
+    /// Output report the device answers with an input report, in hex starting with the
+    /// report ID (00 without IDs). Without it you are prompted for input.
+    #[arg(value_name = "HEX", long)]
+    trigger: Option<String>,
+    /// Also write the results with charts to a self-contained HTML page
+    #[arg(value_name = "FILE", long)]
+    report: Option<PathBuf>,
+}
+
+pub fn run(args: LatencyArgs, config: &Config) -> Result<()> {
+    let LatencyArgs {
+        device,
+        interface,
+        idle,
+        rounds,
+        trigger,
+        report,
+    } = args;
+
+    let device = config.device(&device)?;
+    let interface = interface
+        .or(device.interface)
+        .ok_or_else(|| anyhow!("Interface must be given for this device"))?;
+    let options = LatencyOptions {
+        idle: Duration::from_secs_f64(idle),
+        rounds,
+        trigger: trigger.as_deref().map(dump::parse_hex).transpose()?,
+    };
+    let api = HidApi::new()?;
+
+    let hid_device = open_interface(&api, &device, interface)
+        .map_err(|err| permissions::explain(err, device.vid, device.pid))?;
+    let comparison = latency::run(&hid_device, &options)?;
+    outln!("{}", comparison);
+
+    for (label, samples) in [
+        ("Steady state", &comparison.steady),
+        ("After idle", &comparison.after_idle),
+    ] {
+        let ms = samples
+            .iter()
+            .map(|sample| sample.as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+        if !ms.is_empty() {
+            out!("\n{}:\n{}", label, plot::histogram(&ms, 10, "ms"));
+        }
+    }
+
+    if let Some(path) = report {
+        let mut html =
+            HtmlReport::new(&format!("Latency of {:04x}:{:04x}", device.vid, device.pid));
+        html.text(&comparison.to_string());
+        for (label, samples) in [
+            ("Steady state", &comparison.steady),
+            ("After idle", &comparison.after_idle),
+        ] {
+            html.chart(Chart::Histogram {
+                title: label.to_string(),
+                unit: "ms".to_string(),
+                samples: samples
+                    .iter()
+                    .map(|sample| sample.as_secs_f64() * 1000.0)
+                    .collect(),
+            });
+        }
+        write_html(&path, &html)?;
+    }
+
+    Ok(())
+}
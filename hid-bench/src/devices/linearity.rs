@@ -0,0 +1,86 @@
+// The linearity command, fitting a line through the values of an axis at marked positions
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use hid_parser::{FieldPath, ReportKind};
+
+use crate::{
+    config::Config,
+    find::{self, InterfaceSelector},
+    linearity,
+    output::{note, outln},
+    session::Session,
+};
+
+#[derive(Debug, Args)]
+pub struct LinearityArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Number, or usage of a top level collection (e.g. "joystick"). Defaults to the
+    /// interface configured for the device alias, or the only HID interface
+    #[arg(value_name = "INTERFACE", long, short)]
+    interface: Option<InterfaceSelector>,
+    /// The axis field, e.g. "Joystick/X"
+    #[arg(value_name = "FIELD", long)]
+    axis: FieldPath,
+    /// Positions to move the axis to, in order from one end to the other
+    #[arg(
+        value_name = "POSITION,...",
+        long,
+        value_delimiter = ',',
+        required_unless_present = "rig",
+        conflicts_with = "rig"
+    )]
+    positions: Vec<f64>,
+    /// Times to go through the positions and back
+    #[arg(value_name = "COUNT", long, default_value_t = 2)]
+    passes: usize,
+    /// Serial port of a motorized rig sending `P <position>` when settled at a position and
+    /// `END` when done, instead of being asked for the positions
+    #[arg(value_name = "PORT", long)]
+    rig: Option<PathBuf>,
+    #[arg(value_name = "BAUD", long, default_value_t = 115200)]
+    baud: u32,
+}
+
+pub fn run(args: LinearityArgs, config: &Config) -> Result<()> {
+    let LinearityArgs {
+        device,
+        interface,
+        axis,
+        positions,
+        passes,
+        rig,
+        baud,
+    } = args;
+
+    let device = config.device(&device)?;
+    let mut session = Session::open(&device)?;
+    let interface = match (interface, device.interface) {
+        (None, Some(interface)) => interface,
+        (selector, _) => {
+            let parsers: Vec<_> = session.parsers().collect();
+            find::select_interface(selector.as_ref(), &parsers)?
+        }
+    };
+    let parser = session.parser(interface)?;
+    let field = parser.field(&axis, ReportKind::Input)?;
+    if field.value.is_none() {
+        return Err(anyhow!("'{}' is an array field, not an axis", axis));
+    }
+
+    let positions = match rig {
+        Some(port) => {
+            note!("Waiting for positions from the rig at {}", port.display());
+            linearity::rig(&port, baud)?
+        }
+        None => linearity::prompted(linearity::sweep(&positions, passes)),
+    };
+    let linearity = linearity::run(session.device(interface)?, &parser, field, positions)?;
+    outln!("{}", linearity);
+
+    Ok(())
+}
@@ -0,0 +1,220 @@
+// The list command, listing USB HID devices and the devices paired with them
+
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use hidapi::HidApi;
+use rusb::{Device, DeviceDescriptor, GlobalContext};
+
+use hid_parser::vendor::{ChildDevice, DecoderRegistry, DeviceInfo};
+
+use crate::{
+    output::{note, outln},
+    permissions,
+    render::Renderer,
+    usb,
+};
+
+use super::HidTransport;
+
+// Wedged devices must not hold up listing the others, they are all queried at once
+const LIST_DEADLINE: Duration = Duration::from_secs(2);
+
+pub fn run(renderer: &Renderer) -> Result<()> {
+    let api = Arc::new(HidApi::new()?);
+
+    // libusb usually can't open HID devices on Windows, hidapi has the strings cached
+    if cfg!(windows) {
+        return list_hidapi(&api, &mut DecoderRegistry::with_builtin());
+    }
+
+    let mut inaccessible = vec![];
+    let mut pending = vec![];
+    let (sender, receiver) = mpsc::channel();
+
+    // Devices, and the devices paired with them, are queried concurrently and listed as they
+    // answer, so a slow receiver only delays itself
+    for device in usb::hid_devices()? {
+        let descriptor = device.device_descriptor()?;
+        let ids = (descriptor.vendor_id(), descriptor.product_id());
+        pending.push(ids);
+
+        let sender = sender.clone();
+        let api = Arc::clone(&api);
+        thread::spawn(move || {
+            let strings = device_strings(&device, &descriptor);
+            let children = match strings {
+                Ok(Some(_)) => {
+                    let mut decoders = DecoderRegistry::with_builtin();
+                    child_devices(&api, &mut decoders, ids.0, ids.1)
+                }
+                _ => vec![],
+            };
+            // nobody is listening any more after the deadline
+            let _ = sender.send((ids, strings, children));
+        });
+    }
+    drop(sender);
+
+    let deadline = Instant::now() + LIST_DEADLINE;
+
+    while !pending.is_empty() {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let ((vid, pid), strings, children) = match receiver.recv_timeout(timeout) {
+            Ok(answer) => answer,
+            Err(_) => break,
+        };
+
+        if let Some(index) = pending.iter().position(|ids| *ids == (vid, pid)) {
+            pending.remove(index);
+        }
+
+        // Keep listing the other devices when one cannot be opened
+        match strings {
+            Ok(Some((vendor_string, product_string))) => {
+                outln!(
+                    "[{:04X}:{:04X}]: \"{}: {}\"",
+                    vid,
+                    pid,
+                    vendor_string,
+                    product_string,
+                );
+            }
+            Ok(None) => {
+                outln!(
+                    "[{:04X}:{:04X}]: {}",
+                    vid,
+                    pid,
+                    renderer.warning("<device does not support text descriptions>")
+                );
+                continue;
+            }
+            Err(err) => {
+                outln!(
+                    "[{:04X}:{:04X}]: {}",
+                    vid,
+                    pid,
+                    renderer.warning(&format!("<{}>", err))
+                );
+
+                if permissions::is_access_error(&err) {
+                    inaccessible.push((vid, pid));
+                }
+                continue;
+            }
+        }
+
+        for child in children {
+            outln!("    {}", child);
+        }
+    }
+
+    for (vid, pid) in pending {
+        outln!(
+            "[{:04X}:{:04X}]: {}",
+            vid,
+            pid,
+            renderer.warning("<device did not respond in time>")
+        );
+    }
+
+    for (vid, pid) in inaccessible {
+        note!(
+            "\n[{:04X}:{:04X}]: {}",
+            vid,
+            pid,
+            renderer.warning(&permissions::guidance(vid, pid))
+        );
+    }
+
+    Ok(())
+}
+
+fn list_hidapi(api: &HidApi, decoders: &mut DecoderRegistry) -> Result<()> {
+    let mut listed = vec![];
+
+    // hidapi lists every interface (and on Windows every top level collection) separately
+    for info in api.device_list() {
+        let key = (info.vendor_id(), info.product_id(), info.serial_number());
+        if listed.contains(&key) {
+            continue;
+        }
+        listed.push(key);
+
+        outln!(
+            "[{:04X}:{:04X}]: \"{}: {}\"",
+            info.vendor_id(),
+            info.product_id(),
+            info.manufacturer_string().unwrap_or_default(),
+            info.product_string().unwrap_or_default(),
+        );
+
+        for child in child_devices(api, decoders, info.vendor_id(), info.product_id()) {
+            outln!("    {}", child);
+        }
+    }
+
+    Ok(())
+}
+
+// Manufacturer and product strings, None if the device has no strings
+fn device_strings(
+    device: &Device<GlobalContext>,
+    descriptor: &DeviceDescriptor,
+) -> Result<Option<(String, String)>> {
+    let timeout = Duration::from_millis(100);
+    let handle = device.open()?;
+
+    let languages = handle.read_languages(timeout)?;
+    let language = match languages.first() {
+        Some(language) => *language,
+        None => return Ok(None),
+    };
+
+    let vendor_string = handle.read_manufacturer_string(language, descriptor, timeout)?;
+    let product_string = handle.read_product_string(language, descriptor, timeout)?;
+
+    Ok(Some((vendor_string, product_string)))
+}
+
+// Devices paired with a wireless receiver, as reported by the vendor decoders
+fn child_devices(
+    api: &HidApi,
+    decoders: &mut DecoderRegistry,
+    vid: u16,
+    pid: u16,
+) -> Vec<ChildDevice> {
+    let mut children = vec![];
+
+    for info in api
+        .device_list()
+        .filter(|info| info.vendor_id() == vid && info.product_id() == pid)
+    {
+        let device_info = DeviceInfo {
+            vendor_id: vid,
+            product_id: pid,
+            usage: (info.usage_page(), info.usage()),
+        };
+
+        let decoder = match decoders.find(&device_info) {
+            Some(decoder) => decoder,
+            None => continue,
+        };
+
+        // Listing should not fail because a receiver could not be queried
+        let hid_device = match info.open_device(api) {
+            Ok(device) => device,
+            Err(_) => continue,
+        };
+
+        if let Ok(devices) = decoder.child_devices(&mut HidTransport(&hid_device)) {
+            children.extend(devices);
+        }
+    }
+
+    children
+}
@@ -0,0 +1,770 @@
+// The log command, logging the input reports of a device or piped in
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use clap::{ArgGroup, Args, ValueEnum};
+
+use hid_parser::{
+    accumulate::Accumulator,
+    gamepad::GamepadMapping,
+    vendor::{keys, DecoderRegistry, DeviceInfo},
+    FlatInputs, Parser,
+};
+
+use crate::{
+    aggregate::Aggregator,
+    backend::{Framed, Framing, HidIo},
+    bridge::Bridge,
+    config::{self, Config, DeviceSpec},
+    controls::{self, Control, ControlInput, Controls, LogSink},
+    derive::{self, Derivation, Derived},
+    descriptors, dump,
+    find::{self, InterfaceSelector},
+    highlight::{self, ChangeTracker, Highlight},
+    output::{note, outln},
+    permissions,
+    piped::{EndOfInput, Piped, StdinFormat},
+    render::{self, ColorChoice, Renderer, Theme},
+    selection::CollectionSelector,
+    send,
+    session::Session,
+    text::{KeyboardLayout, Typist},
+    usb,
+    velocity::Velocities,
+};
+
+use super::{format_or, wait_for_device};
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("piped").args(["from_stdin", "serial"])))]
+pub struct LogArgs {
+    #[arg(
+        value_name = "VID:PID|ALIAS",
+        long,
+        short,
+        required_unless_present = "piped"
+    )]
+    device: Option<String>,
+    /// Number, or usage of a top level collection (e.g. "keyboard"). Defaults to the
+    /// interface configured for the device alias, or the only HID interface
+    #[arg(value_name = "INTERFACE", long, short)]
+    interface: Option<InterfaceSelector>,
+    #[arg(value_enum, long, short)]
+    format: Option<LogFormat>,
+    /// Only show reports of a device paired with a wireless receiver
+    #[arg(value_name = "INDEX", long)]
+    device_index: Option<u8>,
+    /// Wait for the device to be plugged in
+    #[arg(long)]
+    wait: bool,
+    /// Read reports through hidapi (the OS driver) or libusb, which takes the interface from
+    /// the OS driver and reads its interrupt IN endpoint as the device sends reports
+    /// [default: hidapi]
+    #[arg(value_enum, long)]
+    backend: Option<LogBackend>,
+    /// Read reports through libusb from this interrupt IN endpoint of the interface (e.g.
+    /// 0x82) instead of through the OS driver, listing the endpoints of every interface
+    #[arg(value_name = "ADDRESS", long, value_parser = usb::parse_endpoint)]
+    endpoint: Option<u8>,
+    /// Select this alternate setting of the interface and read through libusb
+    #[arg(value_name = "N", long)]
+    alt_setting: Option<u8>,
+    /// Log reports piped in on stdin instead of read from a device, e.g. usbmon text or the
+    /// output of a hardware sniffer, decoding them with --descriptor. --device still picks
+    /// the decoders and vendor keys. Commands typed on the terminal are off, stdin carries the
+    /// reports, SIGUSR1 and SIGUSR2 still insert markers and rotate the output file
+    #[arg(
+        long,
+        requires = "descriptor",
+        conflicts_with_all = ["interface", "wait", "backend", "endpoint", "alt_setting"]
+    )]
+    from_stdin: bool,
+    /// Report descriptor of the piped reports (binary, hex or a dump)
+    #[arg(value_name = "FILE", long, requires = "piped")]
+    descriptor: Option<PathBuf>,
+    /// A report per line in hex (usbmon text too), or binary reports back to back
+    #[arg(value_enum, long, default_value = "hex")]
+    stdin_format: StdinFormat,
+    /// Log the reports a hardware sniffer or passthrough board sends over this serial port
+    /// (e.g. /dev/ttyACM0), timed by the sniffer as they went over the wire. Decoded with
+    /// --descriptor, --interface picks the interface to log [default: 0]
+    #[arg(
+        value_name = "PORT",
+        long,
+        requires = "descriptor",
+        conflicts_with_all = ["wait", "backend", "endpoint", "alt_setting"]
+    )]
+    serial: Option<PathBuf>,
+    #[arg(
+        value_name = "BAUD",
+        long,
+        default_value_t = 115_200,
+        requires = "serial"
+    )]
+    baud: u32,
+    /// Highlight bytes which changed since the previous report with the same ID (raw and compact formats)
+    #[arg(value_enum, long, default_value = "auto")]
+    highlight: Highlight,
+    /// Only log reports of the top level collection with this index (from 0) or usage,
+    /// e.g. "Consumer Control"
+    #[arg(value_name = "N|USAGE", long)]
+    collection: Option<CollectionSelector>,
+    /// Keyboard layout for the text format
+    #[arg(value_enum, long, default_value = "us")]
+    layout: KeyboardLayout,
+    /// Allow the text format, which shows everything typed on the keyboard, passwords
+    /// included
+    #[arg(long)]
+    allow_keystroke_logging: bool,
+    /// Show the state of a gamepad in the standard layout (buttons by position, sticks,
+    /// triggers), mappings can be corrected in the config file
+    #[arg(long, conflicts_with = "format")]
+    standard_gamepad: bool,
+    /// Show logical values of fields declaring a unit, instead of physical values with units
+    /// (compact and full formats)
+    #[arg(long)]
+    logical_values: bool,
+    /// Show how fast the axes change, in counts or physical units per second (compact and full
+    /// formats)
+    #[arg(long, conflicts_with = "standard_gamepad")]
+    velocity: bool,
+    /// Show the running totals of relative fields like mouse movement and wheels (compact and
+    /// full formats)
+    #[arg(long, conflicts_with = "standard_gamepad")]
+    accumulate: bool,
+    /// Show a value computed from the fields of every report, e.g. "speed = sqrt(X^2+Y^2)"
+    /// or "combos = count({Button 1} && {Button 2})", fields named by their path (compact
+    /// and full formats, repeatable)
+    #[arg(
+        value_name = "NAME=EXPRESSION",
+        long,
+        conflicts_with = "standard_gamepad"
+    )]
+    derive: Vec<Derivation>,
+    /// Print the minimum, maximum and mean of every field over each PERIOD instead of every
+    /// report, e.g. 50ms
+    #[arg(
+        value_name = "PERIOD",
+        long,
+        value_parser = send::parse_interval,
+        conflicts_with_all = ["standard_gamepad", "velocity", "accumulate", "derive"]
+    )]
+    aggregate: Option<Duration>,
+    /// Write the log to FILE instead of stdout, without colors. Typing r and Enter, or
+    /// SIGUSR2, moves it to FILE-1, FILE-2, ... and starts a new one.
+    #[arg(value_name = "FILE", long, short)]
+    output: Option<PathBuf>,
+}
+
+pub fn run(args: LogArgs, config: &Config, renderer: &Renderer) -> Result<()> {
+    let LogArgs {
+        device,
+        interface,
+        format,
+        device_index,
+        wait,
+        backend,
+        endpoint,
+        alt_setting,
+        from_stdin,
+        descriptor,
+        stdin_format,
+        serial,
+        baud,
+        highlight,
+        collection,
+        layout,
+        allow_keystroke_logging,
+        standard_gamepad,
+        logical_values,
+        velocity,
+        accumulate,
+        derive,
+        aggregate,
+        output,
+    } = args;
+    let mut decoders = DecoderRegistry::with_builtin();
+
+    // piped reports may still come from a known device
+    let device = match &device {
+        Some(device) => config.device(device)?,
+        None => DeviceSpec::default(),
+    };
+    let format = format_or(format, device.log_format.as_deref(), LogFormat::Compact)?;
+    if format == LogFormat::Text && !allow_keystroke_logging {
+        return Err(anyhow!(
+            "The text format shows everything typed on the keyboard, passwords included. \
+             Add --allow-keystroke-logging to use it"
+        ));
+    }
+    if wait {
+        wait_for_device(&device)?;
+    }
+    let (mut session, interface, parser) = match descriptor {
+        Some(path) => {
+            if from_stdin && path == Path::new("-") {
+                return Err(anyhow!(
+                    "The reports are piped in on stdin, the descriptor must be a file"
+                ));
+            }
+            let interface = match interface {
+                Some(InterfaceSelector::Number(interface)) => interface,
+                Some(InterfaceSelector::Usage { .. }) => {
+                    return Err(anyhow!("Give the interface to log by its number"))
+                }
+                None => 0,
+            };
+            (None, interface, dump::read_descriptor(&path)?.decode())
+        }
+        None => {
+            let session = Session::open(&device)?;
+            let interface = match (interface, device.interface) {
+                (None, Some(interface)) => interface,
+                (selector, _) => {
+                    let parsers: Vec<_> = session.parsers().collect();
+                    find::select_interface(selector.as_ref(), &parsers)?
+                }
+            };
+            let parser = session.parser(interface)?;
+
+            (Some(session), interface, parser)
+        }
+    };
+    let parser = match &collection {
+        Some(selector) => selector.select(parser)?,
+        None => parser,
+    };
+
+    // reports of the other collections are skipped
+    let report_ids = collection
+        .is_some()
+        .then(|| {
+            parser
+                .reports()
+                .iter()
+                .filter_map(|report| report.report_id)
+                .collect::<HashSet<_>>()
+        })
+        .filter(|report_ids| !report_ids.is_empty());
+
+    if device.quirks.no_decoder {
+        decoders = DecoderRegistry::new();
+    }
+
+    let gamepad = match standard_gamepad {
+        true => Some(config.gamepad_mapping(&device, &parser)?.ok_or_else(|| {
+            anyhow!(
+                "The device doesn't look like a gamepad, map it in the [gamepads] \
+                 section of the config file"
+            )
+        })?),
+        false => None,
+    };
+
+    // no colors in log files
+    let plain = Renderer::new(ColorChoice::Never, Theme::Dark);
+    let renderer = match output {
+        Some(_) => &plain,
+        None => renderer,
+    };
+
+    // libusb takes the interface over from the OS driver, reading the endpoint directly
+    let backend = match (endpoint, alt_setting) {
+        (None, None) => config::flag_or(
+            backend,
+            config.defaults.backend.as_deref(),
+            LogBackend::Hidapi,
+        )?,
+        _ => LogBackend::Libusb,
+    };
+    let claimed = match (backend, &session) {
+        (LogBackend::Libusb, Some(_)) => Some(claim_for_log(
+            &device,
+            interface,
+            alt_setting.unwrap_or(0),
+            endpoint,
+        )?),
+        _ => None,
+    };
+    let piped: Option<Box<dyn HidIo>> = match (from_stdin, &serial) {
+        (true, _) => Some(Box::new(Piped::start(stdin_format, Framing::new(&parser)))),
+        (false, Some(port)) => Some(Box::new(Bridge::open(port, baud, interface)?)),
+        (false, None) => None,
+    };
+    let hid_device: &dyn HidIo = match (&piped, &claimed, session.as_mut()) {
+        (Some(piped), _, _) => piped.as_ref(),
+        (None, Some(claimed), _) => claimed,
+        (None, None, Some(session)) => session.device(interface)?,
+        (None, None, None) => unreachable!("reports come from a device or are piped in"),
+    };
+
+    cmd_log(
+        hid_device,
+        &device,
+        &parser,
+        &mut decoders,
+        &LogOptions {
+            format,
+            device_index,
+            report_ids,
+            highlight: highlight.resolve(renderer),
+            layout,
+            gamepad,
+            units: !logical_values,
+            velocity,
+            accumulate,
+            derive,
+            aggregate,
+            output,
+            // stdin carries the reports
+            controls: match from_stdin {
+                true => ControlInput::Signals,
+                false => ControlInput::Terminal,
+            },
+        },
+        renderer,
+    )
+}
+
+#[derive(ValueEnum, Debug, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+    Raw,
+    Compact,
+    Full,
+    /// Text typed on a keyboard (needs --allow-keystroke-logging)
+    Text,
+    /// Pen position, pressure, tilt and twist, marking when it starts hovering or touching
+    Pen,
+    /// Named axes of wheels, pedals, yokes and throttles, as percentages of their range
+    Simulation,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogBackend {
+    Hidapi,
+    Libusb,
+}
+
+// How often the log command checks for controls while the device is quiet
+const CONTROLS_POLL_MS: i32 = 100;
+
+struct LogOptions {
+    format: LogFormat,
+    device_index: Option<u8>,        // only reports of this child device
+    report_ids: Option<HashSet<u8>>, // only reports with these IDs
+    highlight: Highlight,
+    layout: KeyboardLayout,          // for the text format
+    gamepad: Option<GamepadMapping>, // show the standard gamepad state instead
+    units: bool,                     // physical values with units where fields declare them
+    velocity: bool,                  // append the rates of change of axes
+    accumulate: bool,                // append the totals of relative fields
+    derive: Vec<Derivation>,         // append values computed from the fields
+    aggregate: Option<Duration>,     // summarise reports over periods instead
+    output: Option<PathBuf>,         // log file instead of stdout
+    controls: ControlInput,
+}
+
+fn claim_for_log(
+    device: &DeviceSpec,
+    interface: u8,
+    alt_setting: u8,
+    endpoint: Option<u8>,
+) -> Result<usb::ClaimedInterface> {
+    let devices = usb::hid_devices()?;
+    let usb_device =
+        usb::find_device(&devices, device).ok_or_else(|| descriptors::not_found(device))?;
+    note!(
+        "Endpoints:\n{}",
+        usb::describe_endpoints(&usb::endpoints(usb_device)?)
+    );
+
+    let claimed = usb::ClaimedInterface::claim_with(usb_device, interface, alt_setting, endpoint)
+        .map_err(|err| permissions::explain(err, device.vid, device.pid))?;
+    note!(
+        "Reading interface #{} in alternate setting {} from endpoint {:#04x} through libusb",
+        interface,
+        alt_setting,
+        claimed.endpoint()
+    );
+
+    Ok(claimed)
+}
+
+fn cmd_log(
+    hid_device: &dyn HidIo,
+    device: &DeviceSpec,
+    parser: &Parser,
+    decoders: &mut DecoderRegistry,
+    options: &LogOptions,
+    renderer: &Renderer,
+) -> Result<()> {
+    let device_info = DeviceInfo {
+        vendor_id: device.vid,
+        product_id: device.pid,
+        usage: parser.usage(),
+    };
+
+    if let Some(decoder) = decoders.find(&device_info) {
+        outln!("Decoding reports as {}", decoder.name());
+    }
+    // Fn and top row keys on vendor pages
+    let vendor_keys = keys::vendor(device.vid);
+    if let Some(keys) = vendor_keys {
+        outln!("Naming {} vendor keys", keys.vendor);
+    }
+
+    let with_report_ids = parser.reports().iter().any(|r| r.report_id.is_some());
+    let mut changes = ChangeTracker::new(with_report_ids);
+    let hid_device = Framed::new(hid_device, Framing::new(parser));
+
+    let mut typist = Typist::new(options.layout);
+    let mut pen_state = None;
+    let mut velocities = Velocities::new();
+    let mut accumulator = Accumulator::new();
+    let mut derived = Derived::new(parser, &options.derive)?;
+    let mut flat = FlatInputs::new();
+    if options.format == LogFormat::Text {
+        note!(
+            "{}",
+            renderer.warning("Logging keystrokes, everything typed on the keyboard is shown")
+        );
+    }
+
+    let mut sink = LogSink::new(options.output.as_deref())?;
+    let controls = Controls::start(options.controls);
+    if controls.interactive() {
+        note!("{}", controls::HELP);
+    } else if options.controls == ControlInput::Signals && controls.signals() {
+        note!(
+            "Reports come in on stdin, typed controls are off. {}",
+            controls::SIGNALS_HELP
+        );
+    }
+    let mut format = options.format.clone();
+    let mut paused = false;
+    let mut markers = 0;
+
+    let mut buf = [0u8; 64];
+    let mut last = Instant::now();
+    let mut last_wire = None;
+
+    // period, its end and the reports so far
+    let start = Instant::now();
+    let mut aggregation = options
+        .aggregate
+        .map(|period| (period, start + period, Aggregator::new(options.units)));
+
+    loop {
+        for control in controls.poll() {
+            match control {
+                Control::Pause => {
+                    paused = !paused;
+                    note!("{}", if paused { "Paused" } else { "Resumed" });
+                }
+                Control::Marker(text) => {
+                    markers += 1;
+                    let elapsed = start.elapsed().as_millis();
+                    let text = text.map(|text| format!(" {}", text)).unwrap_or_default();
+                    sink.line(&format!(
+                        "--- marker {} at +{} ms{} ---",
+                        markers, elapsed, text
+                    ))?;
+                }
+                Control::Rotate => match sink.rotate()? {
+                    Some(rotated) => note!("Moved the log so far to {}", rotated.display()),
+                    None => note!("Logging to stdout, add --output to rotate log files"),
+                },
+                Control::Verbosity => {
+                    format = match format {
+                        LogFormat::Raw => LogFormat::Compact,
+                        LogFormat::Compact => LogFormat::Full,
+                        LogFormat::Full => LogFormat::Raw,
+                        LogFormat::Text => LogFormat::Text,
+                        LogFormat::Pen => LogFormat::Pen,
+                        LogFormat::Simulation => LogFormat::Simulation,
+                    };
+                    note!("Logging in the {:?} format", format);
+                }
+            }
+        }
+
+        let read = match &mut aggregation {
+            Some((period, end, aggregator)) => {
+                let now = Instant::now();
+                if now >= *end {
+                    let bucket = aggregator.take();
+                    if !bucket.is_empty() && !paused {
+                        let elapsed = (now - start).as_millis();
+                        sink.line(&format!("[+{:06} ms]: {}", elapsed, bucket))?;
+                    }
+                    // skip periods missed while the output blocked
+                    *end = (*end + *period).max(now);
+                }
+
+                let timeout = end.saturating_duration_since(Instant::now());
+                hid_device.read_timeout(&mut buf, timeout.as_millis().max(1) as i32)
+            }
+            None => hid_device.read_timeout(&mut buf, CONTROLS_POLL_MS),
+        };
+        let n = match read {
+            // piped input ran out, the log ends with the last period
+            Err(err) if err.is::<EndOfInput>() => {
+                if let Some((_, _, aggregator)) = &mut aggregation {
+                    let bucket = aggregator.take();
+                    if !bucket.is_empty() && !paused {
+                        let elapsed = start.elapsed().as_millis();
+                        sink.line(&format!("[+{:06} ms]: {}", elapsed, bucket))?;
+                    }
+                }
+                sink.flush()?;
+
+                return Ok(());
+            }
+            read => read?,
+        };
+        if n == 0 {
+            sink.flush()?;
+            continue; // timed out
+        }
+
+        // timed by a sniffer to the microsecond, when there is one
+        let wire = hid_device.wire_time();
+        let elapsed = match (wire, last_wire) {
+            (Some(wire), Some(last_wire)) => {
+                let interval = wire.saturating_sub(last_wire);
+                format!("{:010.3}", interval.as_secs_f64() * 1000.0)
+            }
+            (Some(_), None) => format!("{:010.3}", 0.0),
+            (None, _) => format!("{:06}", last.elapsed().as_millis()),
+        };
+        let bytes = &buf[0..n];
+
+        if let Some(index) = options.device_index {
+            let report_index = decoders
+                .find(&device_info)
+                .and_then(|decoder| decoder.device_index(bytes));
+
+            if report_index != Some(index) {
+                continue;
+            }
+        }
+
+        let frame = hid_device.framing().frame(bytes);
+        if let Some(report_ids) = &options.report_ids {
+            if frame.report_id.is_none_or(|id| !report_ids.contains(&id)) {
+                continue;
+            }
+        }
+
+        if let Some((_, _, aggregator)) = &mut aggregation {
+            parser.parse_input_flat(bytes, &mut flat);
+            aggregator.add(parser, &flat);
+            continue;
+        }
+
+        let deriving = !options.derive.is_empty();
+        if options.velocity || options.accumulate || deriving || vendor_keys.is_some() {
+            parser.parse_input_flat(bytes, &mut flat);
+        }
+        // totals keep counting while paused
+        if options.accumulate {
+            accumulator.add(parser, &flat);
+        }
+        let values = match deriving {
+            true => derive::format(&derived.update(&flat)),
+            false => String::new(),
+        };
+
+        // keep tracking changes, so the first report after resuming highlights correctly
+        let changed = changes.changes(bytes);
+        if paused {
+            last = Instant::now();
+            last_wire = wire;
+            continue;
+        }
+
+        let mut decoded = match decoders.decode(&device_info, bytes) {
+            Some(report) => format!(" => {}", report),
+            None => String::new(),
+        };
+        if let Some(keys) = vendor_keys {
+            let pressed = keys.pressed(flat.inputs());
+            if !pressed.is_empty() {
+                decoded.push_str(&format!(" | keys {}", pressed.join(", ")));
+            }
+        }
+        if options.velocity {
+            let rates: Vec<_> = velocities
+                .update(parser, &flat, Instant::now())
+                .iter()
+                .map(|velocity| velocity.to_string())
+                .collect();
+            if !rates.is_empty() {
+                decoded.push_str(&format!(" | {}", rates.join(", ")));
+            }
+        }
+        if options.accumulate {
+            let totals: Vec<_> = accumulator.totals().map(|t| t.to_string()).collect();
+            if !totals.is_empty() {
+                decoded.push_str(&format!(" | total {}", totals.join(", ")));
+            }
+        }
+        if deriving {
+            decoded.push_str(&format!(" | {}", values));
+        }
+
+        let prefix = renderer.report_id(frame.report_id, &format!("[+{} ms]:", elapsed));
+        let palette = renderer.palette();
+
+        if let Some(mapping) = &options.gamepad {
+            if let Some(state) = mapping.state(parser, bytes) {
+                sink.line(&format!("{} {}", prefix, state))?;
+            }
+            last = Instant::now();
+            last_wire = wire;
+            continue;
+        }
+
+        let measured = |bytes| match options.units {
+            true => parser.parse_input_measured(bytes),
+            false => render::without_units(&parser.parse_input(bytes)),
+        };
+
+        // TODO better formats
+        match format {
+            LogFormat::Raw => {
+                sink.line(&format!(
+                    "{} {} ",
+                    prefix,
+                    highlight::format_bytes(bytes, &changed, options.highlight, palette)
+                ))?;
+            }
+            LogFormat::Compact => {
+                sink.line(&format!(
+                    "{} {} = {}{}",
+                    prefix,
+                    highlight::format_bytes(bytes, &changed, options.highlight, palette),
+                    renderer.compact(&measured(bytes)),
+                    decoded
+                ))?;
+            }
+            LogFormat::Full => {
+                sink.line(&format!(
+                    "{} {:02x?} = {}{}",
+                    prefix,
+                    bytes,
+                    renderer.full(&measured(bytes)),
+                    decoded
+                ))?;
+            }
+            LogFormat::Text => {
+                parser.parse_input_flat(bytes, &mut flat);
+                sink.write(&typist.type_report(flat.inputs()))?;
+            }
+            LogFormat::Pen => {
+                if let Some(pen) = parser.pen(bytes) {
+                    let state = pen.state();
+                    if let Some(last) = pen_state.filter(|last| *last != state) {
+                        let transition = format!("--- {} -> {} ---", last, state);
+                        sink.line(&renderer.warning(&transition))?;
+                    }
+                    pen_state = Some(state);
+
+                    sink.line(&format!("{} {}", prefix, pen))?;
+                }
+            }
+            LogFormat::Simulation => {
+                if let Some(state) = parser.simulation(bytes) {
+                    sink.line(&format!("{} {}", prefix, state))?;
+                }
+            }
+        }
+
+        last = Instant::now();
+        last_wire = wire;
+    }
+}
+
+#[cfg(all(test, feature = "test-backend"))]
+mod test {
+    use std::{env, fs};
+
+    use hid_parser::vendor::DecoderRegistry;
+
+    use super::{cmd_log, LogFormat, LogOptions};
+    use crate::{
+        config::DeviceSpec,
+        controls::ControlInput,
+        highlight::Highlight,
+        mock::{mouse_capture, MockDevice},
+        render::{ColorChoice, Renderer, Theme},
+        text::KeyboardLayout,
+    };
+
+    fn plain() -> Renderer {
+        Renderer::new(ColorChoice::Never, Theme::Dark)
+    }
+
+    #[test]
+    fn logs_reports_of_mock_devices() {
+        let device = MockDevice::open(&mouse_capture("log"), 0).unwrap();
+        let parser = device.descriptors().parser(0).unwrap();
+        let output = env::temp_dir().join(format!(
+            "hid-bench-{}-logs_reports_of_mock_devices.txt",
+            std::process::id()
+        ));
+        let options = LogOptions {
+            format: LogFormat::Compact,
+            device_index: None,
+            report_ids: None,
+            highlight: Highlight::Off,
+            layout: KeyboardLayout::Us,
+            gamepad: None,
+            units: true,
+            velocity: false,
+            accumulate: true,
+            derive: vec![
+                "clicks = count({Button 1})".parse().unwrap(),
+                "double = 2 * X".parse().unwrap(),
+            ],
+            aggregate: None,
+            output: Some(output.clone()),
+            controls: ControlInput::Signals,
+        };
+
+        // the mock device unplugs after the last report
+        let result = cmd_log(
+            &device,
+            &DeviceSpec::default(),
+            parser,
+            &mut DecoderRegistry::new(),
+            &options,
+            &plain(),
+        );
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("no more input reports"));
+
+        let log = fs::read_to_string(&output).unwrap();
+        let lines: Vec<_> = log
+            .lines()
+            .map(|line| line.split_once(": ").unwrap().1)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "[01, 00, 05] = [false, 5] | total X 5 | clicks 0, double 10",
+                "[01, 01, 00] = [true, 0] | total X 5 | clicks 1, double 0",
+                "[01, 00, fb] = [false, -5] | total X 0 | clicks 1, double -10",
+            ]
+        );
+
+        fs::remove_file(&output).unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+// Commands working with connected devices
+//
+// Everything needing libusb or hidapi is here. Builds without the `usb` feature leave this module
+// out and only have the offline commands (decode, optimize, analyze, synthesize, convert).
+//
+// Every command has its module, with its arguments and a `run` taking them. The helpers
+// several commands share are here.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Subcommand, ValueEnum};
+use hidapi::{HidApi, HidDevice};
+
+use hid_parser::{
+    battery::Battery,
+    capture::DeviceMetadata,
+    vendor::{DecoderRegistry, DeviceInfo, Transport},
+    ReportKind,
+};
+
+use crate::{
+    config::{Config, DeviceSpec},
+    descriptors,
+    output::note,
+    permissions,
+    render::Renderer,
+    session::Session,
+    stress::StressKind,
+    transfers::{TransferFormat, TransferLog},
+    usb,
+};
+
+mod backends;
+mod battery;
+mod battery_drain;
+mod dial;
+mod evdev;
+mod find;
+mod idle;
+mod latency;
+mod linearity;
+mod list;
+mod log;
+mod monitor;
+mod noise;
+mod record;
+mod report;
+mod rig;
+mod scroll;
+mod send;
+mod soak;
+mod sticks;
+mod stress;
+mod touch;
+mod wakeup;
+
+#[derive(Debug, Subcommand)]
+pub enum DeviceCommands {
+    /// Lists USB HID devices
+    List,
+    /// Lists the devices exposing a usage in their report descriptors
+    Find(find::FindArgs),
+    /// Shows a report descriptor of a given device
+    Report(report::ReportArgs),
+    /// Logs input reports from the device
+    Log(log::LogArgs),
+    /// Draws the contacts of a touchpad, touchscreen or pen tablet live
+    Touch(touch::TouchArgs),
+    /// Shows the rotation and button of a radial controller (Surface Dial and similar) live
+    Dial(dial::DialArgs),
+    /// Shows and sets the high-resolution scrolling multiplier, optionally logging wheel movements
+    Scroll(scroll::ScrollArgs),
+    /// Shows the battery level of the device
+    Battery(battery::BatteryArgs),
+    /// Samples the battery level for hours while the device keeps reporting, at each of the
+    /// report rates given in turn, for a curve of battery drain against report rate
+    BatteryDrain(battery_drain::BatteryDrainArgs),
+    /// Logs the device for hours, tracking disconnects, errors and report rate drift
+    Soak(soak::SoakArgs),
+    /// Tests whether the device repeats reports at the idle rates set with SET_IDLE, taking the
+    /// interface over from the OS driver meanwhile
+    Idle(idle::IdleArgs),
+    /// Reads the interface through hidapi and then through libusb, comparing the lengths,
+    /// report ID prefixes and intervals of the reports each backend returns
+    Backends(backends::BackendsArgs),
+    /// Compares the latency of the first report after an idle period with the steady state
+    Latency(latency::LatencyArgs),
+    /// Measures click-to-report latency against an external trigger: a helper board watching
+    /// the actuation (photodiode, switch contact, GPIO) sends a line starting with T over a
+    /// serial port at every click
+    Rig(rig::RigArgs),
+    /// Records the axes of the untouched device, showing their noise (standard deviation and
+    /// peak-to-peak) and drift in counts and physical units
+    Noise(noise::NoiseArgs),
+    /// Guides moving an axis to marked physical positions, up and back down, and fits a line
+    /// through the values it reports, showing its non-linearity and hysteresis. Axes declaring a
+    /// unit are compared in it, give the positions in the same unit
+    Linearity(linearity::LinearityArgs),
+    /// Characterizes the sticks of a gamepad in timed phases: their center and spread at rest,
+    /// the firmware dead zone while they are moved slowly, and the time they take to return to
+    /// the center when let go
+    Sticks(sticks::SticksArgs),
+    /// Reads the raw reports and the evdev events the kernel makes of them (Linux), showing the
+    /// latency the input stack adds and reports it drops or changes
+    Evdev(evdev::EvdevArgs),
+    /// Suspends the device (Linux), waits for input to wake it up and measures the time from
+    /// the resume to the first report
+    Wakeup(wakeup::WakeupArgs),
+    /// Floods the device with output or feature reports, watching its input reports
+    Stress(stress::StressArgs),
+    /// Sends a sequence of reports at a fixed interval, optionally checking the device echoes
+    /// each one back in an input report
+    Send(send::SendArgs),
+    /// Records input reports into a capture file, with the device's report descriptors
+    Record(record::RecordArgs),
+    /// Shows the output and feature reports other software sends to the device, decoded
+    /// (Linux, needs the usbmon module and root)
+    Monitor(monitor::MonitorArgs),
+}
+
+pub fn run(cmd: DeviceCommands, config: &Config, renderer: &Renderer) -> Result<()> {
+    match cmd {
+        DeviceCommands::List => list::run(renderer),
+        DeviceCommands::Find(args) => find::run(args, renderer),
+        DeviceCommands::Report(args) => report::run(args, config, renderer),
+        DeviceCommands::Log(args) => log::run(args, config, renderer),
+        DeviceCommands::Touch(args) => touch::run(args, config, renderer),
+        DeviceCommands::Dial(args) => dial::run(args, config),
+        DeviceCommands::Scroll(args) => scroll::run(args, config),
+        DeviceCommands::Battery(args) => battery::run(args, config),
+        DeviceCommands::BatteryDrain(args) => battery_drain::run(args, config),
+        DeviceCommands::Soak(args) => soak::run(args, config),
+        DeviceCommands::Idle(args) => idle::run(args, config),
+        DeviceCommands::Backends(args) => backends::run(args, config),
+        DeviceCommands::Latency(args) => latency::run(args, config),
+        DeviceCommands::Rig(args) => rig::run(args, config),
+        DeviceCommands::Noise(args) => noise::run(args, config),
+        DeviceCommands::Linearity(args) => linearity::run(args, config),
+        DeviceCommands::Sticks(args) => sticks::run(args, config),
+        DeviceCommands::Evdev(args) => evdev::run(args, config),
+        DeviceCommands::Wakeup(args) => wakeup::run(args, config),
+        DeviceCommands::Stress(args) => stress::run(args, config, renderer),
+        DeviceCommands::Send(args) => send::run(args, config, renderer),
+        DeviceCommands::Record(args) => record::run(args, config),
+        DeviceCommands::Monitor(args) => monitor::run(args, config, renderer),
+    }
+}
+
+// Blocks until the device is plugged in
+fn wait_for_device(device: &DeviceSpec) -> Result<()> {
+    note!("Waiting for {:04x}:{:04x}...", device.vid, device.pid);
+
+    // hidapi only lists devices once Windows has set them up
+    if cfg!(windows) {
+        loop {
+            let api = HidApi::new()?;
+            if api
+                .device_list()
+                .any(|info| descriptors::is_device(info, device))
+            {
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    usb::wait_for_device(device)
+}
+
+// Format given on the command line, configured for the device, or the default
+fn format_or<T: ValueEnum>(format: Option<T>, configured: Option<&str>, default: T) -> Result<T> {
+    match (format, configured) {
+        (Some(format), _) => Ok(format),
+        (None, Some(name)) => T::from_str(name, true)
+            .map_err(|_| anyhow!("Unknown format '{}' in the config file", name)),
+        (None, None) => Ok(default),
+    }
+}
+
+// The interface with the report to send and the report's length without the report ID
+fn report_target(
+    session: &mut Session,
+    interface: Option<u8>,
+    size: Option<usize>,
+    kind: StressKind,
+    report_id: Option<u8>,
+) -> Result<(u8, usize)> {
+    let kind = match kind {
+        StressKind::Output => ReportKind::Output,
+        StressKind::Feature => ReportKind::Feature,
+    };
+    let descriptors = session.descriptors();
+    let missing_report = || {
+        anyhow!(
+            "The device has no {:?} report with ID {:?}",
+            kind,
+            report_id
+        )
+    };
+
+    let interface = match interface {
+        Some(interface) => interface,
+        None => descriptors
+            .find_report(kind, report_id)
+            .ok_or_else(missing_report)?,
+    };
+
+    let length = match size {
+        Some(size) => size,
+        None => {
+            let length = descriptors
+                .report_length(interface, kind, report_id)
+                .ok_or_else(missing_report)?;
+
+            // the report ID is sent separately
+            length - report_id.map_or(0, |_| 1)
+        }
+    };
+
+    Ok((interface, length))
+}
+
+fn metadata(device: &DeviceSpec, hid_device: &HidDevice) -> DeviceMetadata {
+    DeviceMetadata {
+        vendor_id: device.vid,
+        product_id: device.pid,
+        manufacturer: hid_device.get_manufacturer_string().unwrap_or_default(),
+        product: hid_device.get_product_string().unwrap_or_default(),
+        serial: hid_device.get_serial_number_string().unwrap_or_default(),
+    }
+}
+
+// Where the send and stress commands put the reports going both ways
+struct TransferOutput {
+    format: Option<TransferFormat>,
+    capture: Option<PathBuf>,
+    renderer: Renderer,
+}
+
+impl TransferOutput {
+    fn log(&self, session: &Session, interface: u8) -> Option<TransferLog> {
+        (self.format.is_some() || self.capture.is_some()).then(|| {
+            let print = self.format.map(|format| (format, self.renderer));
+
+            TransferLog::new(session.descriptors(), interface, print)
+        })
+    }
+
+    fn finish(
+        &self,
+        session: &mut Session,
+        device: &DeviceSpec,
+        interface: u8,
+        transfers: Option<TransferLog>,
+    ) -> Result<()> {
+        let (Some(path), Some(transfers)) = (&self.capture, transfers) else {
+            return Ok(());
+        };
+
+        let descriptors = session.descriptors().clone();
+        let capture = transfers.capture(metadata(device, session.device(interface)?), descriptors);
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        capture.write(BufWriter::new(file))?;
+        note!(
+            "Wrote {} reports to {}",
+            capture.transfers.len(),
+            path.display()
+        );
+
+        Ok(())
+    }
+}
+
+// Battery state using the vendor protocol if there is one, or the standard battery usages
+fn read_battery(
+    session: &mut Session,
+    device: &DeviceSpec,
+    decoders: &mut DecoderRegistry,
+    device_index: Option<u8>,
+) -> Result<Option<Battery>> {
+    let mut open_error = None;
+
+    let parsers: Vec<_> = session.parsers().collect();
+    for (interface, parser) in parsers {
+        // Other interfaces may still be accessible
+        let hid_device = match session.device(interface) {
+            Ok(hid_device) => hid_device,
+            Err(err) if permissions::is_access_error(&err) => {
+                open_error = Some(err);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let device_info = DeviceInfo {
+            vendor_id: device.vid,
+            product_id: device.pid,
+            usage: parser.usage(),
+        };
+
+        if let Some(decoder) = decoders.find(&device_info) {
+            let battery = decoder.battery(&mut HidTransport(hid_device), device_index)?;
+            if battery.is_some() {
+                return Ok(battery);
+            }
+        }
+
+        for (kind, report_id) in parser.battery_reports() {
+            let length = parser.report_length(kind, report_id).unwrap_or(64);
+            match kind {
+                ReportKind::Feature => {
+                    // hidapi keeps a zero in place of the report ID for devices without them
+                    let mut buf = vec![0u8; length + usize::from(report_id.is_none())];
+                    buf[0] = report_id.unwrap_or(0);
+                    let n = hid_device.get_feature_report(&mut buf)?;
+
+                    // nothing read leaves not even the report ID
+                    let bytes = match report_id {
+                        Some(_) => buf.get(0..n),
+                        None => buf.get(1..n),
+                    };
+
+                    if let Some(battery) = parser.battery(kind, bytes.unwrap_or_default()) {
+                        return Ok(Some(battery));
+                    }
+                }
+                ReportKind::Input => {
+                    // wait for the device to send the report on its own
+                    let deadline = Instant::now() + Duration::from_secs(2);
+                    let mut buf = vec![0u8; length];
+
+                    while Instant::now() < deadline {
+                        let n = hid_device.read_timeout(&mut buf, 100)?;
+                        if let Some(battery) = parser.battery(kind, &buf[0..n]) {
+                            return Ok(Some(battery));
+                        }
+                    }
+                }
+                ReportKind::Output => (),
+            }
+        }
+    }
+
+    match open_error {
+        Some(err) => Err(err),
+        None => Ok(None),
+    }
+}
+
+struct HidTransport<'a>(&'a HidDevice);
+
+impl<'a> Transport for HidTransport<'a> {
+    fn write(&mut self, report: &[u8]) -> Result<usize, hid_parser::Error> {
+        self.0.write(report).map_err(hid_parser::Error::transport)
+    }
+
+    fn read_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout_ms: i32,
+    ) -> Result<usize, hid_parser::Error> {
+        self.0
+            .read_timeout(buf, timeout_ms)
+            .map_err(hid_parser::Error::transport)
+    }
+}
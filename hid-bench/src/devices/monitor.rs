@@ -0,0 +1,102 @@
+// The monitor command, showing the reports other software sends to the device
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use clap::Args;
+
+use hid_parser::ReportKind;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    descriptors,
+    output::{note, outln},
+    render::{self, Renderer},
+    usb,
+    usbmon::Usbmon,
+};
+
+#[derive(Debug, Args)]
+pub struct MonitorArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Only reports to this interface
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+}
+
+pub fn run(args: MonitorArgs, config: &Config, renderer: &Renderer) -> Result<()> {
+    let MonitorArgs { device, interface } = args;
+
+    let device = config.device(&device)?;
+
+    cmd_monitor(&device, interface, renderer)
+}
+
+fn cmd_monitor(device: &DeviceSpec, interface: Option<u8>, renderer: &Renderer) -> Result<()> {
+    let devices = usb::hid_devices()?;
+    let usb_device =
+        usb::find_device(&devices, device).ok_or_else(|| descriptors::not_found(device))?;
+    let (bus, address) = (usb_device.bus_number(), usb_device.address());
+
+    // interrupt OUT endpoints of the HID interfaces
+    let mut out_endpoints = BTreeMap::new();
+    for usb_interface in usb_device.active_config_descriptor()?.interfaces() {
+        for setting in usb_interface.descriptors().filter(|s| s.class_code() == 3) {
+            for endpoint in setting.endpoint_descriptors() {
+                if endpoint.direction() == rusb::Direction::Out
+                    && endpoint.transfer_type() == rusb::TransferType::Interrupt
+                {
+                    out_endpoints.insert(endpoint.address(), setting.interface_number());
+                }
+            }
+        }
+    }
+
+    let model = descriptors::from_usb(device)?;
+    let mut usbmon = Usbmon::open(bus)?;
+    note!(
+        "Monitoring reports sent to bus {} device {}, press Ctrl-C to stop",
+        bus,
+        address
+    );
+
+    let mut start = None;
+    loop {
+        let event = usbmon.next_event()?;
+        if event.bus != bus as u16 || event.device != address {
+            continue;
+        }
+        let Some(report) = event.host_report(&out_endpoints) else {
+            continue;
+        };
+        if interface.is_some_and(|interface| interface != report.interface) {
+            continue;
+        }
+
+        let elapsed = report.timestamp - *start.get_or_insert(report.timestamp);
+        let kind = match report.kind {
+            ReportKind::Feature => "feature",
+            _ => "output",
+        };
+        let decoded = model
+            .parser(report.interface)
+            .map(|parser| match report.kind {
+                ReportKind::Feature => parser.parse_feature(&report.bytes),
+                _ => parser.parse_output(&report.bytes),
+            })
+            .filter(|parsed| !parsed.flatten().is_empty())
+            .map_or("not in the report descriptor".to_string(), |parsed| {
+                renderer.full(&render::without_units(&parsed))
+            });
+
+        outln!(
+            "[+{:06} ms] #{} {} {:02x?} = {}",
+            elapsed.as_millis(),
+            report.interface,
+            kind,
+            report.bytes,
+            decoded
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// The noise command, showing the noise and drift of the axes of the untouched device
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::Config,
+    find::{self, InterfaceSelector},
+    noise,
+    output::outln,
+    session::Session,
+};
+
+#[derive(Debug, Args)]
+pub struct NoiseArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Number, or usage of a top level collection (e.g. "joystick"). Defaults to the
+    /// interface configured for the device alias, or the only HID interface
+    #[arg(value_name = "INTERFACE", long, short)]
+    interface: Option<InterfaceSelector>,
+    #[arg(value_name = "SECONDS", long, default_value_t = 30)]
+    seconds: u64,
+    /// Time to let go of the device before recording, reports meanwhile are ignored
+    #[arg(value_name = "SECONDS", long, default_value_t = 2)]
+    settle: u64,
+}
+
+pub fn run(args: NoiseArgs, config: &Config) -> Result<()> {
+    let NoiseArgs {
+        device,
+        interface,
+        seconds,
+        settle,
+    } = args;
+
+    let device = config.device(&device)?;
+    let mut session = Session::open(&device)?;
+    let interface = match (interface, device.interface) {
+        (None, Some(interface)) => interface,
+        (selector, _) => {
+            let parsers: Vec<_> = session.parsers().collect();
+            find::select_interface(selector.as_ref(), &parsers)?
+        }
+    };
+    let parser = session.parser(interface)?;
+
+    let noise = noise::run(
+        session.device(interface)?,
+        &parser,
+        Duration::from_secs(settle),
+        Duration::from_secs(seconds),
+    )?;
+    if noise.axes.is_empty() {
+        return Err(anyhow!(
+            "No axes were reported, the device has none or only reports changes"
+        ));
+    }
+    for axis in noise.axes.values() {
+        outln!("{}", axis);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+// The record command, recording input reports into a capture file
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    controls::{ControlInput, Controls},
+    output::{note, outln},
+    record::{self, RecordOptions, RingOptions, Trigger},
+    send,
+    session::Session,
+};
+
+use super::{metadata, wait_for_device};
+
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// A capture, or a compact frame log of the input reports when FILE ends in .hbf
+    #[arg(value_name = "FILE", long, short, default_value = "capture.hbc")]
+    output: PathBuf,
+    /// Stop after SECONDS [default: record until interrupted]
+    #[arg(value_name = "SECONDS", long)]
+    seconds: Option<u64>,
+    /// Keep only the last SECONDS of reports in memory, writing them to a numbered file
+    /// (capture-1.hbc, ...) on SIGUSR1, when the trigger matches or when reading fails
+    #[arg(value_name = "SECONDS", long)]
+    ring: Option<f64>,
+    /// Report bytes from the start in hex, xx matching any byte, e.g. "03 xx ff"
+    #[arg(value_name = "HEX", long, requires = "ring")]
+    trigger: Option<Trigger>,
+    /// Read on a dedicated thread queueing the reports for the writer, for devices reporting
+    /// at several kHz, counting reports dropped when writing falls behind
+    #[arg(long, conflicts_with = "ring")]
+    reader_thread: bool,
+    /// Insert a marker every DURATION, e.g. 10s, besides those typed (m TEXT and Enter) or
+    /// sent as SIGUSR1. Analyses show them with the time they were inserted at.
+    #[arg(value_name = "DURATION", long, value_parser = send::parse_interval, conflicts_with = "ring")]
+    mark_every: Option<Duration>,
+    /// Wait for the device to be plugged in
+    #[arg(long)]
+    wait: bool,
+}
+
+pub fn run(args: RecordArgs, config: &Config) -> Result<()> {
+    let RecordArgs {
+        device,
+        interface,
+        output,
+        seconds,
+        ring,
+        trigger,
+        reader_thread,
+        mark_every,
+        wait,
+    } = args;
+
+    let device = config.device(&device)?;
+    let interface = interface
+        .or(device.interface)
+        .ok_or_else(|| anyhow!("Interface must be given for this device"))?;
+
+    if wait {
+        wait_for_device(&device)?;
+    }
+    let mut session = Session::open(&device)?;
+
+    let duration = seconds.map(Duration::from_secs);
+    let ring = ring.map(|window| RingOptions {
+        window: Duration::from_secs_f64(window),
+        trigger,
+        duration,
+    });
+
+    let options = RecordOptions {
+        duration,
+        reader_thread,
+        mark_every,
+    };
+
+    cmd_record(&mut session, &device, interface, &output, ring, options)
+}
+
+fn cmd_record(
+    session: &mut Session,
+    device: &DeviceSpec,
+    interface: u8,
+    output: &Path,
+    ring: Option<RingOptions>,
+    options: RecordOptions,
+) -> Result<()> {
+    let descriptors = session.descriptors().clone();
+    let hid_device = session.device(interface)?;
+
+    let metadata = metadata(device, hid_device);
+
+    if let Some(ring) = ring {
+        if output.extension().is_some_and(|e| e == "hbf") {
+            return Err(anyhow!(
+                "Ring buffer recordings are written as captures (.hbc)"
+            ));
+        }
+        outln!(
+            "Keeping the last {} s of interface #{} for {}, send SIGUSR1 to write them out",
+            ring.window.as_secs_f64(),
+            interface,
+            output.display()
+        );
+        let dumps = record::run_ring(hid_device, metadata, &descriptors, interface, output, &ring)?;
+        outln!("Wrote {} captures", dumps);
+
+        return Ok(());
+    }
+
+    outln!("Recording interface #{} to {}", interface, output.display());
+    let controls = Controls::start(ControlInput::Terminal);
+    if output.extension().is_some_and(|e| e == "hbf") {
+        note!("Frame logs keep only the reports, markers are left out");
+    } else if controls.interactive() {
+        note!("Type m [TEXT] and Enter to insert a marker, SIGUSR1 inserts one without text");
+    }
+
+    let recorded = record::run(
+        session.device_mut(interface)?,
+        metadata,
+        &descriptors,
+        interface,
+        output,
+        &options,
+        Some(controls),
+    )?;
+    match options.reader_thread {
+        true => outln!(
+            "Recorded {} reports and {} markers, dropped {}",
+            recorded.reports,
+            recorded.markers,
+            recorded.dropped
+        ),
+        false => outln!(
+            "Recorded {} reports and {} markers",
+            recorded.reports,
+            recorded.markers
+        ),
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test-backend"))]
+mod test {
+    use std::{env, fs};
+
+    use hid_parser::capture::{Capture, DeviceMetadata, Direction};
+
+    use crate::{
+        framelog,
+        mock::{mouse_capture, MockDevice},
+        record,
+    };
+
+    #[test]
+    fn restores_report_ids_the_platform_stripped() {
+        let file = fs::File::open(mouse_capture("stripped")).unwrap();
+        let mut capture = Capture::read(file).unwrap();
+        for transfer in &mut capture.transfers {
+            if transfer.direction == Direction::In {
+                transfer.bytes.remove(0);
+            }
+        }
+        let mut device = MockDevice::new(&capture, 0);
+        let path = env::temp_dir().join("hid-bench-stripped.hbc");
+
+        let result = record::run(
+            &mut device,
+            DeviceMetadata::default(),
+            &capture.descriptors,
+            0,
+            &path,
+            &Default::default(),
+            None,
+        );
+        assert!(result.is_err());
+
+        let recorded = Capture::read(fs::File::open(&path).unwrap()).unwrap();
+        let reports: Vec<_> = recorded.transfers.iter().map(|t| t.bytes.clone()).collect();
+        assert_eq!(
+            reports,
+            [[0x01, 0x00, 0x05], [0x01, 0x01, 0x00], [0x01, 0x00, 0xfb]]
+        );
+    }
+
+    #[test]
+    fn records_mock_devices_on_a_reader_thread() {
+        for (reader_thread, extension) in [(false, "hbcp"), (true, "hbcp"), (true, "hbf")] {
+            let mut device = MockDevice::open(&mouse_capture("record"), 0).unwrap();
+            let descriptors = device.descriptors().clone();
+            let path =
+                env::temp_dir().join(format!("hid-bench-record-{}.{}", reader_thread, extension));
+
+            // the mock device unplugs after the last report, with everything read written out
+            let result = record::run(
+                &mut device,
+                DeviceMetadata::default(),
+                &descriptors,
+                0,
+                &path,
+                &record::RecordOptions {
+                    reader_thread,
+                    ..Default::default()
+                },
+                None,
+            );
+            assert!(result.is_err());
+
+            let capture = match extension {
+                "hbf" => framelog::read_file(&path).unwrap(),
+                _ => Capture::read(fs::File::open(&path).unwrap()).unwrap(),
+            };
+            let reports: Vec<_> = capture.transfers.iter().map(|t| t.bytes.clone()).collect();
+            assert_eq!(
+                reports,
+                [[0x01, 0x00, 0x05], [0x01, 0x01, 0x00], [0x01, 0x00, 0xfb]]
+            );
+            assert_eq!(record::buffer_size(&descriptors, 0), 64);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+// The report command, showing the report descriptors of a device
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::Result;
+use clap::Args;
+use hidapi::HidApi;
+
+use hid_parser::ReportDescriptor;
+
+use crate::{
+    config::Config,
+    descriptors::{self, Setting},
+    exit::Exit,
+    format_descriptor,
+    output::note,
+    pager,
+    render::Renderer,
+    selection::CollectionSelector,
+    ReportFormat, ReportOptions,
+};
+
+use super::{format_or, wait_for_device};
+
+#[derive(Debug, Args)]
+pub struct ReportArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    #[arg(value_enum, long, short)]
+    format: Option<ReportFormat>,
+    /// Only show the descriptors of this interface
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Collapse parsed collections nested deeper than this
+    #[arg(value_name = "LEVELS", long)]
+    depth: Option<usize>,
+    /// Only show the parsed top level collection with this index (from 0) or usage
+    #[arg(value_name = "N|USAGE", long)]
+    collection: Option<CollectionSelector>,
+    /// Don't page long output
+    #[arg(long)]
+    no_pager: bool,
+    /// Write the binary descriptor to FILE, or one file per descriptor into DIR
+    #[arg(value_name = "FILE|DIR", long, short)]
+    output: Option<PathBuf>,
+    /// Wait for the device to be plugged in
+    #[arg(long)]
+    wait: bool,
+    /// Warn about common mistakes in each descriptor, see `verify`
+    #[arg(long)]
+    lint: bool,
+}
+
+pub fn run(args: ReportArgs, config: &Config, renderer: &Renderer) -> Result<()> {
+    let ReportArgs {
+        device,
+        format,
+        interface,
+        depth,
+        collection,
+        no_pager,
+        output,
+        wait,
+        lint,
+    } = args;
+
+    let device = config.device(&device)?;
+    let format = format_or(format, device.report_format.as_deref(), ReportFormat::Items)?;
+
+    if wait {
+        wait_for_device(&device)?;
+    }
+    let api = HidApi::new()?;
+
+    let mut report_descriptors = descriptors::all_report_descriptors(&api, &device)?;
+    if let Some(interface) = interface {
+        report_descriptors.retain(|setting, _| setting.interface == interface);
+        if report_descriptors.is_empty() {
+            return Err(Exit::DeviceNotFound.error(format!("Cannot find interface #{}", interface)));
+        }
+    }
+
+    if let Some(path) = output {
+        for file in descriptors::write(&report_descriptors, &path)? {
+            note!("Wrote {}", file.display());
+        }
+    }
+
+    let options = ReportOptions {
+        format,
+        depth,
+        collection,
+        pager: !no_pager,
+        lint,
+    };
+
+    cmd_report(&report_descriptors, &options, renderer)
+}
+
+fn cmd_report(
+    descriptors: &BTreeMap<Setting, Vec<ReportDescriptor>>,
+    options: &ReportOptions,
+    renderer: &Renderer,
+) -> Result<()> {
+    pager::page(&report_text(descriptors, options, renderer), options.pager)
+}
+
+fn report_text(
+    descriptors: &BTreeMap<Setting, Vec<ReportDescriptor>>,
+    options: &ReportOptions,
+    renderer: &Renderer,
+) -> String {
+    let mut output = String::new();
+
+    for (setting, report_descriptors) in descriptors {
+        output.push_str(&format!("{}\n", setting));
+        if report_descriptors.is_empty() {
+            output.push_str(&format!(
+                "{}\n",
+                renderer.warning("<not readable while the configuration is inactive>")
+            ));
+        }
+
+        for descriptor in report_descriptors {
+            output.push_str(&format_descriptor(descriptor, options, renderer));
+        }
+    }
+
+    output
+}
+
+#[cfg(all(test, feature = "test-backend"))]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::report_text;
+    use crate::{
+        descriptors::Setting,
+        mock::{mouse_capture, MockDevice},
+        render::{ColorChoice, Renderer, Theme},
+        ReportFormat, ReportOptions,
+    };
+
+    fn plain() -> Renderer {
+        Renderer::new(ColorChoice::Never, Theme::Dark)
+    }
+
+    #[test]
+    fn reports_descriptors_of_mock_devices() {
+        let device = MockDevice::open(&mouse_capture("report"), 0).unwrap();
+        let descriptors: BTreeMap<_, _> = device
+            .descriptors()
+            .iter()
+            .map(|(interface, descriptors)| {
+                let setting = Setting {
+                    configuration: None,
+                    interface,
+                    alternate: None,
+                };
+                (setting, descriptors.to_vec())
+            })
+            .collect();
+
+        let options = ReportOptions {
+            format: ReportFormat::Parsed,
+            depth: None,
+            collection: None,
+            pager: false,
+            lint: true,
+        };
+        let text = report_text(&descriptors, &options, &plain());
+
+        assert!(text.starts_with("Interface #0\n"));
+        assert!(text.contains("Application"));
+        assert!(!text.contains("warning"));
+    }
+}
@@ -0,0 +1,98 @@
+// The rig command, measuring click to report latency against an external trigger
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use hidapi::HidApi;
+
+use crate::{
+    config::Config,
+    html::{Chart, HtmlReport},
+    output::{out, outln},
+    permissions, plot,
+    rig::{self, RigOptions},
+    session::open_interface,
+    write_html,
+};
+
+#[derive(Debug, Args)]
+pub struct RigArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Serial port of the trigger helper, e.g. /dev/ttyACM0
+    #[arg(value_name = "PORT", long)]
+    trigger_port: PathBuf,
+    #[arg(value_name = "BAUD", long, default_value_t = 115_200)]
+    baud: u32,
+    #[arg(value_name = "N", long, default_value_t = 20)]
+    clicks: usize,
+    /// Longest latency still counted, longer ones are missed clicks
+    #[arg(value_name = "MS", long, default_value_t = 200)]
+    window: u64,
+    /// Time the trigger line takes over the serial port, subtracted from every sample
+    #[arg(value_name = "MICROSECONDS", long, default_value_t = 0)]
+    trigger_offset: u64,
+    /// Also write the results with a chart to a self-contained HTML page
+    #[arg(value_name = "FILE", long)]
+    report: Option<PathBuf>,
+}
+
+pub fn run(args: RigArgs, config: &Config) -> Result<()> {
+    let RigArgs {
+        device,
+        interface,
+        trigger_port,
+        baud,
+        clicks,
+        window,
+        trigger_offset,
+        report,
+    } = args;
+
+    let device = config.device(&device)?;
+    let interface = interface
+        .or(device.interface)
+        .ok_or_else(|| anyhow!("Interface must be given for this device"))?;
+    let options = RigOptions {
+        port: trigger_port,
+        baud,
+        clicks,
+        window: Duration::from_millis(window),
+        offset: Duration::from_micros(trigger_offset),
+    };
+    let api = HidApi::new()?;
+
+    let hid_device = open_interface(&api, &device, interface)
+        .map_err(|err| permissions::explain(err, device.vid, device.pid))?;
+    let result = rig::run(&hid_device, &options)?;
+    outln!("{}", result);
+
+    let ms = result
+        .latencies
+        .iter()
+        .map(|sample| sample.as_secs_f64() * 1000.0)
+        .collect::<Vec<_>>();
+    if !ms.is_empty() {
+        out!("\n{}", plot::histogram(&ms, 10, "ms"));
+    }
+
+    if let Some(path) = report {
+        let mut html = HtmlReport::new(&format!(
+            "Click to report latency of {:04x}:{:04x}",
+            device.vid, device.pid
+        ));
+        html.text(&result.to_string());
+        html.chart(Chart::Histogram {
+            title: "Click to report".to_string(),
+            unit: "ms".to_string(),
+            samples: ms,
+        });
+        write_html(&path, &html)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+// The scroll command, showing and setting the high-resolution scrolling multiplier
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    scroll::{self, MultiplierSetting},
+    session::Session,
+};
+
+use super::wait_for_device;
+
+#[derive(Debug, Args)]
+pub struct ScrollArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or the first with a wheel
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Set the resolution multiplier to a logical value, its maximum or its minimum
+    #[arg(value_name = "VALUE|max|off", long)]
+    set: Option<MultiplierSetting>,
+    /// Log wheel movements in reported units and in detents
+    #[arg(long)]
+    log: bool,
+    /// Wait for the device to be plugged in
+    #[arg(long)]
+    wait: bool,
+}
+
+pub fn run(args: ScrollArgs, config: &Config) -> Result<()> {
+    let ScrollArgs {
+        device,
+        interface,
+        set,
+        log,
+        wait,
+    } = args;
+
+    let device = config.device(&device)?;
+
+    if wait {
+        wait_for_device(&device)?;
+    }
+    let mut session = Session::open(&device)?;
+
+    cmd_scroll(&mut session, &device, interface, set, log)
+}
+
+fn cmd_scroll(
+    session: &mut Session,
+    device: &DeviceSpec,
+    interface: Option<u8>,
+    set: Option<MultiplierSetting>,
+    log: bool,
+) -> Result<()> {
+    let (interface, parser) = match interface.or(device.interface) {
+        Some(interface) => (interface, session.parser(interface)?),
+        None => session
+            .parsers()
+            .find(|(_, parser)| !parser.scroll_axes().is_empty())
+            .ok_or_else(|| anyhow!("The device has no scroll wheel"))?,
+    };
+
+    scroll::run(session.device(interface)?, &parser, set, log)
+}
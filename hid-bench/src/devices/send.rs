@@ -0,0 +1,190 @@
+// The send command, sending a sequence of reports at a fixed interval
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::Config,
+    exit::Exit,
+    output::outln,
+    render::Renderer,
+    send::{self, SendOptions, Verify},
+    session::Session,
+    stress::{Pattern, Payloads, StressKind},
+    transfers::TransferFormat,
+};
+
+use super::{report_target, TransferOutput};
+
+#[derive(Debug, Args)]
+pub struct SendArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or the one with the report
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Report ID, leave out for devices without report IDs
+    #[arg(value_name = "N", long)]
+    report_id: Option<u8>,
+    #[arg(value_name = "N", long, default_value_t = 1)]
+    repeat: u64,
+    /// Time between reports, e.g. 5ms
+    #[arg(value_name = "DURATION", long, default_value = "10ms", value_parser = send::parse_interval)]
+    interval: Duration,
+    #[arg(value_enum, long, default_value = "counter")]
+    pattern: Pattern,
+    /// Payloads for the file pattern, one per line in hex without the report ID
+    #[arg(value_name = "FILE", long)]
+    file: Option<PathBuf>,
+    #[arg(value_enum, long, default_value = "output")]
+    kind: StressKind,
+    /// Report length in bytes without the report ID [default: from the report descriptor]
+    #[arg(value_name = "BYTES", long)]
+    size: Option<usize>,
+    /// Check every report is echoed back in an input report, for devices in loopback mode
+    #[arg(long)]
+    verify: bool,
+    /// Report ID of the echoing input report [default: the report ID sent]
+    #[arg(value_name = "N", long, requires = "verify")]
+    echo_report_id: Option<u8>,
+    /// How long to wait for each echo
+    #[arg(value_name = "DURATION", long, default_value = "100ms", value_parser = send::parse_interval)]
+    echo_timeout: Duration,
+    /// Read every feature report back after setting it, checking the device kept it
+    #[arg(long)]
+    read_back: bool,
+    /// Print every report sent and received in order, marked < input, > output, F> feature
+    /// set and F< feature read
+    #[arg(value_enum, long)]
+    log: Option<TransferFormat>,
+    /// Write the reports sent and received to a capture file
+    #[arg(value_name = "FILE", long)]
+    capture: Option<PathBuf>,
+}
+
+pub fn run(args: SendArgs, config: &Config, renderer: &Renderer) -> Result<()> {
+    let SendArgs {
+        device,
+        interface,
+        report_id,
+        repeat,
+        interval,
+        pattern,
+        file,
+        kind,
+        size,
+        verify,
+        echo_report_id,
+        echo_timeout,
+        read_back,
+        log,
+        capture,
+    } = args;
+
+    let device = config.device(&device)?;
+    if read_back && kind != StressKind::Feature {
+        return Err(anyhow!("Only feature reports can be read back"));
+    }
+    let output = TransferOutput {
+        format: log,
+        capture,
+        renderer: *renderer,
+    };
+    let options = SendOptions {
+        kind,
+        report_id,
+        repeat,
+        interval,
+        verify: verify.then(|| Verify {
+            report_id: echo_report_id.or(report_id),
+            timeout: echo_timeout,
+        }),
+        read_back,
+    };
+    let mut session = Session::open(&device)?;
+    let (interface, length) = report_target(
+        &mut session,
+        interface.or(device.interface),
+        size,
+        kind,
+        report_id,
+    )?;
+    let payloads = Payloads::open(pattern, length, file.as_deref())?;
+
+    outln!(
+        "Sending {} {:?} reports of {} bytes every {:?}",
+        repeat,
+        kind,
+        length,
+        interval
+    );
+    let mut transfers = output.log(&session, interface);
+    let summary = send::run(
+        session.device(interface)?,
+        &options,
+        payloads,
+        transfers.as_mut(),
+    )?;
+    output.finish(&mut session, &device, interface, transfers)?;
+    outln!("{}", summary);
+    if !summary.passed() {
+        return Err(Exit::ThresholdExceeded.error(format!(
+            "{} of {} reports failed or weren't echoed back",
+            summary.failed + summary.mismatched + summary.missing,
+            summary.sent
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test-backend"))]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::capture::Direction;
+
+    use crate::{
+        mock::{mouse_capture, MockDevice},
+        render::{ColorChoice, Renderer, Theme},
+        send::{self, SendOptions},
+        stress::{Pattern, Payloads, StressKind},
+        transfers::{TransferFormat, TransferLog},
+    };
+
+    fn plain() -> Renderer {
+        Renderer::new(ColorChoice::Never, Theme::Dark)
+    }
+
+    #[test]
+    fn logs_feature_reports_read_back_from_mock_devices() {
+        let device = MockDevice::open(&mouse_capture("send"), 0).unwrap();
+        let options = SendOptions {
+            kind: StressKind::Feature,
+            report_id: Some(3),
+            repeat: 1,
+            interval: Duration::ZERO,
+            verify: None,
+            read_back: true,
+        };
+        let mut log = TransferLog::new(device.descriptors(), 0, None);
+
+        let payloads = Payloads::new(Pattern::Ones, 1);
+        let summary = send::run(&device, &options, payloads, Some(&mut log)).unwrap();
+
+        // the device kept only the resolution bit
+        assert_eq!((summary.read_back, summary.differing), (1, 1));
+        assert!(!summary.passed());
+        let directions: Vec<_> = log.transfers().iter().map(|t| t.direction).collect();
+        assert_eq!(directions, [Direction::FeatureOut, Direction::FeatureIn]);
+        assert_eq!(
+            log.line(1, TransferFormat::Raw, &plain())
+                .split_once(" ms] ")
+                .unwrap()
+                .1,
+            "F< [03, 01] (reply to #1)"
+        );
+    }
+}
@@ -0,0 +1,60 @@
+// The soak command, logging the device for hours
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::{config::Config, exit::Exit, output::outln, soak};
+
+#[derive(Debug, Args)]
+pub struct SoakArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or any interface
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    #[arg(value_name = "HOURS", long, default_value_t = 12.0)]
+    hours: f64,
+    /// JSON status file, rewritten every status interval
+    #[arg(value_name = "FILE", long, default_value = "soak-status.json")]
+    status_file: PathBuf,
+    #[arg(value_name = "SECONDS", long, default_value_t = 60)]
+    status_interval: u64,
+}
+
+pub fn run(args: SoakArgs, config: &Config) -> Result<()> {
+    let SoakArgs {
+        device,
+        interface,
+        hours,
+        status_file,
+        status_interval,
+    } = args;
+
+    let device = config.device(&device)?;
+
+    let status = soak::run(
+        &device,
+        interface.or(device.interface),
+        Duration::from_secs_f64(hours * 3600.0),
+        &status_file,
+        Duration::from_secs(status_interval),
+    )?;
+
+    outln!(
+        "Soak finished: {} reports, {} disconnects, {} errors, max rate drift {:+.1}%",
+        status.reports,
+        status.disconnects,
+        status.errors,
+        status.max_rate_drift_percent
+    );
+    if status.disconnects > 0 || status.errors > 0 {
+        return Err(Exit::ThresholdExceeded.error(format!(
+            "The device disconnected {} times and failed {} reads",
+            status.disconnects, status.errors
+        )));
+    }
+
+    Ok(())
+}
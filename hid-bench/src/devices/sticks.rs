@@ -0,0 +1,108 @@
+// The sticks command, characterizing the sticks of a gamepad
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::Config,
+    find::{self, InterfaceSelector},
+    html::{Chart, HtmlReport},
+    output::outln,
+    session::Session,
+    sticks::{self, StickOptions},
+    write_html,
+};
+
+#[derive(Debug, Args)]
+pub struct SticksArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Number, or usage of a top level collection (e.g. "joystick"). Defaults to the
+    /// interface configured for the device alias, or the only HID interface
+    #[arg(value_name = "INTERFACE", long, short)]
+    interface: Option<InterfaceSelector>,
+    #[arg(value_name = "SECONDS", long, default_value_t = 5)]
+    rest: u64,
+    #[arg(value_name = "SECONDS", long, default_value_t = 15)]
+    sweep: u64,
+    /// Times to let go of a stick from the edge
+    #[arg(value_name = "COUNT", long, default_value_t = 10)]
+    flicks: usize,
+    /// Longest to wait for the flicks
+    #[arg(value_name = "SECONDS", long, default_value_t = 60)]
+    flick_timeout: u64,
+    /// Also write the results with charts to a self-contained HTML page
+    #[arg(value_name = "FILE", long)]
+    report: Option<PathBuf>,
+}
+
+pub fn run(args: SticksArgs, config: &Config) -> Result<()> {
+    let SticksArgs {
+        device,
+        interface,
+        rest,
+        sweep,
+        flicks,
+        flick_timeout,
+        report,
+    } = args;
+
+    let device = config.device(&device)?;
+    let mut session = Session::open(&device)?;
+    let interface = match (interface, device.interface) {
+        (None, Some(interface)) => interface,
+        (selector, _) => {
+            let parsers: Vec<_> = session.parsers().collect();
+            find::select_interface(selector.as_ref(), &parsers)?
+        }
+    };
+    let parser = session.parser(interface)?;
+    let mapping = config.gamepad_mapping(&device, &parser)?.ok_or_else(|| {
+        anyhow!(
+            "The device doesn't look like a gamepad, map it in the [gamepads] \
+             section of the config file"
+        )
+    })?;
+    let options = StickOptions {
+        rest: Duration::from_secs(rest),
+        sweep: Duration::from_secs(sweep),
+        flicks,
+        timeout: Duration::from_secs(flick_timeout),
+    };
+
+    let axes = sticks::run(session.device(interface)?, &parser, &mapping, &options)?;
+    if axes.is_empty() {
+        return Err(anyhow!("The gamepad mapping has no sticks"));
+    }
+    for axis in &axes {
+        outln!("{}", axis);
+    }
+
+    if let Some(path) = report {
+        let mut html = HtmlReport::new(&format!("Sticks of {:04x}:{:04x}", device.vid, device.pid));
+        for axis in &axes {
+            html.text(&axis.to_string());
+            html.chart(Chart::Histogram {
+                title: format!("{} at rest", axis.axis),
+                unit: "counts".to_string(),
+                samples: axis.rest.iter().map(|v| v / axis.count).collect(),
+            });
+            if !axis.returns.is_empty() {
+                html.chart(Chart::Histogram {
+                    title: format!("{} back to center", axis.axis),
+                    unit: "ms".to_string(),
+                    samples: axis
+                        .returns
+                        .iter()
+                        .map(|sample| sample.as_secs_f64() * 1000.0)
+                        .collect(),
+                });
+            }
+        }
+        write_html(&path, &html)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+// The stress command, flooding the device with output or feature reports
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Result;
+use clap::Args;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    exit::Exit,
+    output::outln,
+    render::Renderer,
+    session::Session,
+    stress::{self, Pattern, Payloads, StressKind, StressOptions},
+    transfers::TransferFormat,
+};
+
+use super::{report_target, TransferOutput};
+
+#[derive(Debug, Args)]
+pub struct StressArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or the one with the report
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// Reports per second
+    #[arg(value_name = "RATE", long, default_value = "100/s")]
+    rate: String,
+    /// Report ID, leave out for devices without report IDs
+    #[arg(value_name = "N", long)]
+    report_id: Option<u8>,
+    #[arg(value_enum, long, default_value = "random")]
+    pattern: Pattern,
+    /// Payloads for the file pattern, one per line in hex without the report ID
+    #[arg(value_name = "FILE", long)]
+    file: Option<PathBuf>,
+    #[arg(value_enum, long, default_value = "output")]
+    kind: StressKind,
+    #[arg(value_name = "SECONDS", long, default_value_t = 10)]
+    seconds: u64,
+    /// Report length in bytes without the report ID [default: from the report descriptor]
+    #[arg(value_name = "BYTES", long)]
+    size: Option<usize>,
+    /// Print every report sent and received in order, marked < input, > output, F> feature
+    /// set and F< feature read
+    #[arg(value_enum, long)]
+    log: Option<TransferFormat>,
+    /// Write the reports sent and received to a capture file
+    #[arg(value_name = "FILE", long)]
+    capture: Option<PathBuf>,
+}
+
+pub fn run(args: StressArgs, config: &Config, renderer: &Renderer) -> Result<()> {
+    let StressArgs {
+        device,
+        interface,
+        rate,
+        report_id,
+        pattern,
+        file,
+        kind,
+        seconds,
+        size,
+        log,
+        capture,
+    } = args;
+
+    let device = config.device(&device)?;
+    let output = TransferOutput {
+        format: log,
+        capture,
+        renderer: *renderer,
+    };
+    let options = StressOptions {
+        kind,
+        report_id,
+        pattern,
+        file,
+        rate: stress::parse_rate(&rate)?,
+        duration: Duration::from_secs(seconds),
+    };
+    let mut session = Session::open(&device)?;
+
+    cmd_stress(
+        &mut session,
+        &device,
+        interface.or(device.interface),
+        size,
+        &options,
+        &output,
+    )
+}
+
+fn cmd_stress(
+    session: &mut Session,
+    device: &DeviceSpec,
+    interface: Option<u8>,
+    size: Option<usize>,
+    options: &StressOptions,
+    output: &TransferOutput,
+) -> Result<()> {
+    let (interface, length) =
+        report_target(session, interface, size, options.kind, options.report_id)?;
+    let payloads = Payloads::open(options.pattern, length, options.file.as_deref())?;
+    let mut transfers = output.log(session, interface);
+
+    outln!(
+        "Sending {:?} reports of {} bytes at {}/s for {} s",
+        options.kind,
+        length,
+        options.rate,
+        options.duration.as_secs()
+    );
+    let summary = stress::run(
+        session.device(interface)?,
+        options,
+        payloads,
+        transfers.as_mut(),
+    )?;
+    output.finish(session, device, interface, transfers)?;
+    outln!("{}", summary);
+    if summary.failed > 0 {
+        return Err(Exit::ThresholdExceeded.error(format!(
+            "{} of {} writes failed",
+            summary.failed, summary.sent
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "test-backend"))]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::capture::Direction;
+
+    use crate::{
+        mock::{mouse_capture, MockDevice},
+        stress::{self, Pattern, Payloads, StressKind, StressOptions},
+    };
+
+    #[test]
+    fn stresses_mock_devices() {
+        let device = MockDevice::open(&mouse_capture("stress"), 0).unwrap();
+        let options = StressOptions {
+            kind: StressKind::Output,
+            report_id: Some(2),
+            pattern: Pattern::Ones,
+            file: None,
+            rate: 1000.0,
+            duration: Duration::from_millis(20),
+        };
+
+        let summary =
+            stress::run(&device, &options, Payloads::new(Pattern::Ones, 1), None).unwrap();
+
+        assert!(summary.sent > 0);
+        assert_eq!(summary.failed, 0);
+        assert_eq!(summary.inputs, 3);
+        let written = device.written();
+        assert_eq!(written.len() as u64, summary.sent);
+        assert!(written
+            .iter()
+            .all(|report| *report == (Direction::Out, vec![0x02, 0xff])));
+    }
+}
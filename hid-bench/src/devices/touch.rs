@@ -0,0 +1,63 @@
+// The touch command, drawing the contacts of a touchpad, touchscreen or pen tablet live
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    render::Renderer,
+    session::Session,
+    touch::{self, CanvasSize},
+};
+
+use super::wait_for_device;
+
+#[derive(Debug, Args)]
+pub struct TouchArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Defaults to the interface configured for the device alias, or the first with contacts
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    #[arg(value_name = "COLUMNSxROWS", long, default_value = "64x20")]
+    size: CanvasSize,
+    /// Wait for the device to be plugged in
+    #[arg(long)]
+    wait: bool,
+}
+
+pub fn run(args: TouchArgs, config: &Config, renderer: &Renderer) -> Result<()> {
+    let TouchArgs {
+        device,
+        interface,
+        size,
+        wait,
+    } = args;
+
+    let device = config.device(&device)?;
+
+    if wait {
+        wait_for_device(&device)?;
+    }
+    let mut session = Session::open(&device)?;
+
+    cmd_touch(&mut session, &device, interface, size, renderer)
+}
+
+fn cmd_touch(
+    session: &mut Session,
+    device: &DeviceSpec,
+    interface: Option<u8>,
+    size: CanvasSize,
+    renderer: &Renderer,
+) -> Result<()> {
+    let (interface, parser) = match interface.or(device.interface) {
+        Some(interface) => (interface, session.parser(interface)?),
+        None => session
+            .parsers()
+            .find(|(_, parser)| parser.has_contacts())
+            .ok_or_else(|| anyhow!("The device has no touch contacts"))?,
+    };
+
+    touch::run(session.device(interface)?, &parser, size, renderer.color())
+}
@@ -0,0 +1,118 @@
+// The wakeup command, measuring the time from a remote wakeup to the first report
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use clap::Args;
+use hidapi::HidApi;
+
+use crate::{
+    config::{Config, DeviceSpec},
+    descriptors,
+    output::{note, outln},
+    permissions,
+    session::open_interface,
+    suspend::UsbPower,
+    usb,
+};
+
+#[derive(Debug, Args)]
+pub struct WakeupArgs {
+    #[arg(value_name = "VID:PID|ALIAS", long, short)]
+    device: String,
+    /// Interface to read the first report from, defaults to the interface configured for the
+    /// device alias, or any interface
+    #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+    interface: Option<u8>,
+    /// How long to wait for input once the device is suspended
+    #[arg(value_name = "SECONDS", long, default_value_t = 30)]
+    timeout: u64,
+}
+
+pub fn run(args: WakeupArgs, config: &Config) -> Result<()> {
+    let WakeupArgs {
+        device,
+        interface,
+        timeout,
+    } = args;
+
+    let device = config.device(&device)?;
+    let api = HidApi::new()?;
+
+    cmd_wakeup(
+        &api,
+        &device,
+        interface.or(device.interface),
+        Duration::from_secs(timeout),
+    )
+}
+
+fn cmd_wakeup(
+    api: &HidApi,
+    device: &DeviceSpec,
+    interface: Option<u8>,
+    timeout: Duration,
+) -> Result<()> {
+    const SUSPEND_TIMEOUT: Duration = Duration::from_secs(10);
+    const REPORT_TIMEOUT_MS: i32 = 1000;
+
+    let devices = usb::hid_devices()?;
+    let usb_device =
+        usb::find_device(&devices, device).ok_or_else(|| descriptors::not_found(device))?;
+    if !usb_device.active_config_descriptor()?.remote_wakeup() {
+        outln!("The device doesn't declare remote wakeup support, input may not wake it up");
+    }
+
+    let interface = match interface {
+        Some(interface) => interface,
+        None => api
+            .device_list()
+            .find(|info| descriptors::is_device(info, device))
+            .map(|info| info.interface_number() as u8)
+            .ok_or_else(|| descriptors::not_found(device))?,
+    };
+
+    let power = UsbPower::allow_suspend(UsbPower::find(
+        usb_device.bus_number(),
+        usb_device.address(),
+    )?)?;
+
+    note!("Waiting for the device to suspend...");
+    let start = Instant::now();
+    let suspended = power
+        .wait_for(|status| status == "suspended", SUSPEND_TIMEOUT)?
+        .ok_or_else(|| {
+            anyhow!(
+                "The device didn't suspend within {} s, something keeps it open \
+                 (e.g. the desktop session reading keyboards and mice)",
+                SUSPEND_TIMEOUT.as_secs()
+            )
+        })?;
+    outln!("Suspended after {} ms", (suspended - start).as_millis());
+
+    note!("Press a key or button on the device");
+    let resumed = power
+        .wait_for(|status| status != "suspended", timeout)?
+        .ok_or_else(|| anyhow!("The device didn't wake up within {} s", timeout.as_secs()))?;
+    outln!(
+        "Woke up {} ms after suspending",
+        (resumed - suspended).as_millis()
+    );
+
+    // opening resumes the device from the host, so only now
+    let hid_device = open_interface(api, device, interface)
+        .map_err(|err| permissions::explain(err, device.vid, device.pid))?;
+    let mut buf = [0u8; 64];
+    let n = hid_device.read_timeout(&mut buf, REPORT_TIMEOUT_MS)?;
+
+    match n {
+        0 => outln!("No report within {} ms of resuming", REPORT_TIMEOUT_MS),
+        n => outln!(
+            "First report ({} bytes) {:.1} ms after resuming",
+            n,
+            resumed.elapsed().as_secs_f64() * 1000.0
+        ),
+    }
+
+    Ok(())
+}
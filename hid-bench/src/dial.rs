@@ -0,0 +1,185 @@
+// Live view of radial controllers
+//
+// The dial is drawn as a gauge of one full turn with a marker at the angle it turned to since
+// the start, followed by the total angle, the rotation of the last report and the button. Dials
+// with a resolution multiplier set report every step as several smaller ones, like wheels, so
+// the totals are divided by the multiplier read from the device. Optionally every press plays a
+// click on the haptic output, to check it works.
+
+use std::{
+    f64::consts::PI,
+    io::{self, Write},
+};
+
+use anyhow::{anyhow, Result};
+use hidapi::HidDevice;
+
+use hid_parser::{
+    dial::{self, DialEvent},
+    unit::{Measurement, System, Unit},
+    Parser, ReportKind,
+};
+
+use crate::{
+    output::{note, out},
+    scroll,
+};
+
+#[derive(Debug)]
+pub struct DialView {
+    factor: f64, // reported steps per physical step
+    angle: f64,  // total, in the unit of the events
+    last: Option<DialEvent>,
+}
+
+impl DialView {
+    pub fn new(factor: f32) -> Self {
+        Self {
+            factor: factor.max(1.0) as f64,
+            angle: 0.0,
+            last: None,
+        }
+    }
+
+    // True when the button was just pressed
+    pub fn update(&mut self, event: DialEvent) -> bool {
+        let was_pressed = self.last.is_some_and(|last| last.pressed);
+
+        self.angle += event.rotation / self.factor;
+        self.last = Some(event);
+
+        event.pressed && !was_pressed
+    }
+
+    // e.g. `[----------o---------] 180 deg | +3.6 deg, pressed`
+    pub fn render(&self, width: usize) -> String {
+        let unit = self.last.and_then(|event| event.unit);
+        // counts are tenths of a degree by convention
+        let turn = match unit {
+            Some(Unit {
+                system: System::SiRotation,
+                ..
+            }) => 2.0 * PI,
+            Some(_) => 360.0,
+            None => 3600.0,
+        };
+
+        let position = (self.angle.rem_euclid(turn) / turn * width as f64) as usize;
+        let gauge: String = (0..width)
+            .map(|i| {
+                if i == position.min(width - 1) {
+                    'o'
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+
+        let total = match unit {
+            Some(unit) => Measurement {
+                value: self.angle,
+                unit,
+            }
+            .to_string(),
+            None => format!("{} counts", self.angle),
+        };
+        let last = match self.last {
+            Some(event) => event.to_string(),
+            None => "turn or press the dial".to_string(),
+        };
+
+        format!("[{}] {} | {}", gauge, total, last)
+    }
+}
+
+// Redraws the dial until interrupted
+pub fn run(hid_device: &HidDevice, parser: &Parser, haptics: bool, width: usize) -> Result<()> {
+    let factor = match parser.dial_multiplier() {
+        Some(multiplier) => {
+            let feature = scroll::get_feature(hid_device, parser, multiplier.report_id())?;
+            let value = multiplier
+                .read(&feature)
+                .ok_or_else(|| anyhow!("Cannot read the resolution multiplier"))?;
+            note!(
+                "Resolution multiplier: {} steps per step",
+                multiplier.factor(value)
+            );
+
+            multiplier.factor(value)
+        }
+        None => 1.0,
+    };
+
+    let click = match (haptics, parser.haptics()) {
+        (false, _) => None,
+        (true, Some(haptics)) => {
+            let length = parser
+                .report_length(ReportKind::Output, haptics.report_id())
+                .ok_or_else(|| anyhow!("The haptic output report is empty"))?;
+            let mut output = vec![0u8; length];
+            if !haptics.write(&mut output, dial::CLICK) {
+                return Err(anyhow!("The haptic output can't play a click"));
+            }
+
+            // hidapi takes the report ID (0 without IDs) in front of the data
+            if haptics.report_id().is_none() {
+                output.insert(0, 0);
+            }
+            Some(output)
+        }
+        (true, None) => return Err(anyhow!("The dial has no haptic output")),
+    };
+
+    let mut view = DialView::new(factor);
+    let mut buf = [0u8; 64];
+    out!("{}", view.render(width));
+    io::stdout().flush()?;
+
+    loop {
+        let n = hid_device.read(&mut buf)?;
+        let Some(event) = parser.dial(&buf[0..n]) else {
+            continue;
+        };
+
+        if view.update(event) {
+            if let Some(click) = &click {
+                hid_device.write(click)?;
+            }
+        }
+
+        // cleared to the end of the line, the previous one may have been longer
+        out!("\r{}\x1b[K", view.render(width));
+        io::stdout().flush()?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{
+        dial::DialEvent,
+        unit::{System, Unit},
+    };
+
+    use super::DialView;
+
+    #[test]
+    fn draws_the_angle_turned_to() {
+        let degrees = Unit {
+            system: System::EnglishRotation,
+            exponents: [1, 0, 0, 0, 0, 0],
+        };
+        let event = |rotation, pressed| DialEvent {
+            rotation,
+            unit: Some(degrees),
+            pressed,
+        };
+
+        let mut view = DialView::new(2.0);
+        assert_eq!(view.render(4), "[o---] 0 counts | turn or press the dial");
+
+        assert!(!view.update(event(360.0, false)));
+        assert!(view.update(event(-90.0, true)));
+        assert!(!view.update(event(0.0, true)));
+        assert_eq!(view.render(4), "[-o--] 135 deg | 0 deg, pressed");
+    }
+}
@@ -0,0 +1,144 @@
+// Battery drain against the input report rate (device battery-drain)
+//
+// Wireless mice and keyboards spend most of their power on the radio, so a battery lasts very
+// differently at 125 Hz and at 1 kHz. The bench runs in stages, one per report rate compared:
+// the device is set to the rate (in its vendor software or with a switch) and kept reporting (a
+// mouse jiggler, a robot, a key held down) while its battery level is sampled. The rate actually
+// reached is counted between samples, and falling well below the stage's rate (the jiggler
+// stopped, the device went to sleep) is warned about, as it makes the stage look better than it
+// is. The drain of a stage is the slope of a line fitted through its battery levels. Most
+// devices report their level in steps of 5 or 10%, so stages need hours for a useful curve.
+
+use std::{fmt::Display, time::Duration};
+
+use crate::stats;
+
+// Stages reaching less of their rate than this are warned about
+pub const RATE_KEPT: f64 = 0.8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrainSample {
+    pub elapsed: Duration, // since the stage started
+    pub level: Option<u8>, // percent, None when the device didn't answer
+    pub rate: Option<f64>, // input reports per second since the previous sample
+}
+
+impl DrainSample {
+    // e.g. `120.0,1000,87,998.2`, see CSV_HEADER
+    pub fn csv(&self, target: Option<u32>) -> String {
+        let field = |value: Option<String>| value.unwrap_or_default();
+
+        format!(
+            "{:.1},{},{},{}",
+            self.elapsed.as_secs_f64(),
+            field(target.map(|t| t.to_string())),
+            field(self.level.map(|l| l.to_string())),
+            field(self.rate.map(|r| format!("{:.1}", r)))
+        )
+    }
+}
+
+pub const CSV_HEADER: &str = "seconds,target_hz,battery_percent,reports_per_second";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stage {
+    pub target: Option<u32>, // Hz, None when the device keeps its rate
+    pub samples: Vec<DrainSample>,
+}
+
+impl Stage {
+    pub fn new(target: Option<u32>) -> Self {
+        Self {
+            target,
+            samples: vec![],
+        }
+    }
+
+    // Whether the rate since the previous sample fell short of the target
+    pub fn behind(&self, sample: &DrainSample) -> bool {
+        match (self.target, sample.rate) {
+            (Some(target), Some(rate)) => rate < target as f64 * RATE_KEPT,
+            _ => false,
+        }
+    }
+
+    pub fn mean_rate(&self) -> Option<f64> {
+        let rates: Vec<_> = self.samples.iter().filter_map(|s| s.rate).collect();
+
+        (!rates.is_empty()).then(|| stats::mean(&rates))
+    }
+
+    // Percent per hour, the negated slope of the least squares line through the levels
+    pub fn drain_per_hour(&self) -> Option<f64> {
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .filter_map(|s| Some((s.elapsed.as_secs_f64() / 3600.0, s.level? as f64)))
+            .collect();
+
+        stats::linear_fit(&points).map(|(_, slope)| -slope)
+    }
+}
+
+impl Display for Stage {
+    // e.g. `1000 Hz (998.2/s measured): 2.50 %/h, about 40 h on a full battery`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.target {
+            Some(target) => write!(f, "{} Hz", target)?,
+            None => write!(f, "Device rate")?,
+        }
+        if let Some(rate) = self.mean_rate() {
+            write!(f, " ({:.1}/s measured)", rate)?;
+        }
+
+        match self.drain_per_hour() {
+            Some(drain) if drain > 0.0 => write!(
+                f,
+                ": {:.2} %/h, about {:.0} h on a full battery",
+                drain,
+                100.0 / drain
+            ),
+            Some(_) => write!(f, ": the battery level didn't drop"),
+            None => write!(f, ": not enough battery samples"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{DrainSample, Stage};
+
+    fn sample(minutes: u64, level: u8, rate: f64) -> DrainSample {
+        DrainSample {
+            elapsed: Duration::from_secs(minutes * 60),
+            level: Some(level),
+            rate: Some(rate),
+        }
+    }
+
+    #[test]
+    fn fits_drain_through_stepped_levels() {
+        // 10% steps, losing 10% every two hours
+        let mut stage = Stage::new(Some(1000));
+        for (minutes, level) in [(0, 90), (60, 90), (120, 80), (180, 80), (240, 70)] {
+            stage.samples.push(sample(minutes, level, 990.0));
+        }
+        stage.samples.push(DrainSample {
+            elapsed: Duration::from_secs(300 * 60),
+            level: None,
+            rate: Some(500.0),
+        });
+
+        let drain = stage.drain_per_hour().unwrap();
+        assert!((drain - 5.0).abs() < 0.01, "{}", drain);
+        assert!(stage.behind(&stage.samples[5]));
+        assert!(!stage.behind(&stage.samples[4]));
+        assert_eq!(
+            stage.to_string(),
+            "1000 Hz (908.3/s measured): 5.00 %/h, about 20 h on a full battery"
+        );
+        assert_eq!(stage.samples[5].csv(stage.target), "18000.0,1000,,500.0");
+    }
+}
@@ -0,0 +1,210 @@
+// Report descriptors pasted from firmware sources and hex dumps
+//
+// Besides raw binary files, descriptors are accepted as C initializers (`0x05, 0x01, ...`,
+// comments allowed), plain hex dumps (`05 01 09 04`) and xxd output. The format is detected
+// from the content.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::ReportDescriptor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Binary,
+    CArray,
+    HexDump,
+    Xxd,
+}
+
+// Reads a descriptor from a file, `-` reads the standard input
+pub fn read_descriptor(path: &Path) -> Result<ReportDescriptor> {
+    let content = match path.to_str() {
+        Some("-") => {
+            let mut content = vec![];
+            io::stdin().read_to_end(&mut content)?;
+
+            content
+        }
+        _ => fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?,
+    };
+
+    let bytes =
+        parse(&content).with_context(|| format!("Invalid descriptor in {}", path.display()))?;
+
+    Ok(ReportDescriptor { bytes })
+}
+
+pub fn detect(content: &[u8]) -> DumpFormat {
+    let text = match std::str::from_utf8(content) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => text,
+        _ => return DumpFormat::Binary,
+    };
+
+    let mut lines = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    if lines.peek().is_some() && lines.all(is_xxd_line) {
+        DumpFormat::Xxd
+    } else if text.contains("0x") || text.contains("0X") {
+        DumpFormat::CArray
+    } else if text.trim().is_empty() {
+        DumpFormat::Binary
+    } else {
+        DumpFormat::HexDump
+    }
+}
+
+pub fn parse(content: &[u8]) -> Result<Vec<u8>> {
+    let text = String::from_utf8_lossy(content);
+
+    match detect(content) {
+        DumpFormat::Binary => Ok(content.to_vec()),
+        DumpFormat::CArray => parse_c_array(&text),
+        DumpFormat::HexDump => parse_hex(&text),
+        DumpFormat::Xxd => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                // offset, hex groups, then the ASCII column after two spaces
+                let (_, rest) = line.split_once(": ").unwrap_or_default();
+                let hex = rest.split("  ").next().unwrap_or_default();
+
+                parse_hex(hex)
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|lines| lines.concat()),
+    }
+}
+
+// 16 bytes per line, e.g. `05 01 09 02 a1 01`, which `parse` reads back
+pub fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .map(|line| {
+            let hex = line
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>();
+
+            format!("{}\n", hex.join(" "))
+        })
+        .collect()
+}
+
+// e.g. `00000010: 0509 1901 2903 1500 2501 9503 7501 8102  ....)...%...u...`
+fn is_xxd_line(line: &str) -> bool {
+    match line.trim_start().split_once(": ") {
+        Some((offset, _)) => !offset.is_empty() && offset.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+fn parse_c_array(text: &str) -> Result<Vec<u8>> {
+    let code = strip_comments(text);
+
+    // the initializer, without the declaration and its array size
+    let body = match (code.find('{'), code.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &code[start + 1..end],
+        _ => &code,
+    };
+
+    body.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .map(|token| {
+            let hex = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .ok_or_else(|| anyhow!("Expected a hex byte, found '{}'", token))?;
+
+            u8::from_str_radix(hex, 16).map_err(|_| anyhow!("Invalid byte '{}'", token))
+        })
+        .collect()
+}
+
+fn strip_comments(text: &str) -> String {
+    let mut code = String::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let line = rest.find("//");
+        let block = rest.find("/*");
+
+        match (line, block) {
+            (Some(l), b) if b.is_none_or(|b| l < b) => {
+                code.push_str(&rest[..l]);
+                rest = rest[l..].find('\n').map_or("", |end| &rest[l + end..]);
+            }
+            (_, Some(b)) => {
+                code.push_str(&rest[..b]);
+                code.push(' ');
+                rest = rest[b..].find("*/").map_or("", |end| &rest[b + end + 2..]);
+            }
+            _ => {
+                code.push_str(rest);
+                rest = "";
+            }
+        }
+    }
+
+    code
+}
+
+// Whitespace or comma separated bytes, pairs of digits may also run together (`0501 0902`)
+pub fn parse_hex(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+
+    for token in text.split(|c: char| c == ',' || c.is_whitespace()) {
+        if token.len() % 2 != 0 || !token.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(anyhow!("Expected hex bytes, found '{}'", token));
+        }
+
+        for pair in token.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair)?;
+            bytes.push(u8::from_str_radix(pair, 16)?);
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{detect, hex_dump, parse, DumpFormat};
+
+    const BYTES: [u8; 8] = [0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0xc0, 0x2e];
+
+    #[test]
+    fn detects_and_parses_dump_formats() {
+        let c_array = "static const uint8_t desc[0x08] = {\n\
+                       \x20   0x05, 0x01, // Usage Page (Generic Desktop)\n\
+                       \x20   0x09, 0x02, /* Usage (Mouse) */ 0xA1, 0x01,\n\
+                       \x20   0xc0, 0x2e\n\
+                       };\n";
+        let plain = "05 01 09 02\na1 01 c0 2e\n";
+        let xxd = "00000000: 0501 0902 a101 c02e                      ........\n";
+
+        assert_eq!(detect(&BYTES), DumpFormat::Binary);
+        assert_eq!(detect(c_array.as_bytes()), DumpFormat::CArray);
+        assert_eq!(detect(plain.as_bytes()), DumpFormat::HexDump);
+        assert_eq!(detect(xxd.as_bytes()), DumpFormat::Xxd);
+
+        for content in [
+            &BYTES[..],
+            c_array.as_bytes(),
+            plain.as_bytes(),
+            xxd.as_bytes(),
+        ] {
+            assert_eq!(parse(content).unwrap(), BYTES);
+        }
+        assert_eq!(parse(b"0501,0902,a101,c02e").unwrap(), BYTES);
+        assert_eq!(parse(hex_dump(&BYTES).as_bytes()).unwrap(), BYTES);
+        assert!(parse(b"05 01 zz").is_err());
+    }
+}
@@ -0,0 +1,506 @@
+// Raw reports versus evdev events (Linux)
+//
+// The kernel hands every input report to hidraw and to its input drivers, which turn it into
+// evdev events ending with a SYN_REPORT (a frame). Reading both at once shows what the input
+// stack does with the reports: how much later the events arrive, reports with changes it makes
+// no events of (fields it doesn't map, or dropped reports) and relative motion it changes on the
+// way (acceleration quirks, wheel multipliers). Both are read straight from their device nodes.
+//
+// Arrivals are timestamped when read, so the latency includes waking up the reading threads.
+// Frames are matched to the earliest unmatched report with changes, skipping to a later one
+// with the same relative motion, or motion on the same axes, if there is one (the skipped
+// reports made no events).
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::{Input, InputValue, Parser, ReportDescriptor, ReportKind};
+
+use crate::latency::spread;
+
+const SYSFS_HIDRAW: &str = "/sys/class/hidraw";
+
+const EV_SYN: u16 = 0x00;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0;
+
+// Relative axes and the codes hid-input gives them
+const RELATIVE: &[((u16, u16), u16, &str)] = &[
+    ((0x01, 0x30), 0x00, "REL_X"),
+    ((0x01, 0x31), 0x01, "REL_Y"),
+    ((0x01, 0x38), 0x08, "REL_WHEEL"),
+    ((0x0c, 0x238), 0x06, "REL_HWHEEL"),
+];
+
+// Reports still in flight when reading stops aren't counted as making no events
+const IN_FLIGHT: Duration = Duration::from_millis(100);
+
+// hidraw and evdev nodes of an interface
+#[derive(Debug, Clone)]
+pub struct Nodes {
+    pub hidraw: PathBuf,
+    pub events: Vec<PathBuf>,
+    pub descriptor: ReportDescriptor,
+}
+
+pub fn find(vid: u16, pid: u16, interface: Option<u8>) -> Result<Nodes> {
+    if !cfg!(target_os = "linux") {
+        return Err(anyhow!("evdev events can only be read on Linux"));
+    }
+
+    let mut entries: Vec<_> = fs::read_dir(SYSFS_HIDRAW)
+        .with_context(|| format!("Cannot list {}", SYSFS_HIDRAW))?
+        .filter_map(|entry| entry.ok())
+        .collect();
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let Ok(hid) = fs::canonicalize(entry.path().join("device")) else {
+            continue;
+        };
+        let name = hid.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        if hid_id(name) != Some((vid, pid)) {
+            continue;
+        }
+
+        // the parent of a USB HID device is its interface
+        let number = hid
+            .parent()
+            .and_then(|usb| fs::read_to_string(usb.join("bInterfaceNumber")).ok())
+            .and_then(|number| u8::from_str_radix(number.trim(), 16).ok());
+        if interface.is_some() && number != interface {
+            continue;
+        }
+
+        let events = event_nodes(&hid);
+        if events.is_empty() && interface.is_none() {
+            continue;
+        }
+
+        let bytes = fs::read(hid.join("report_descriptor"))
+            .with_context(|| format!("Cannot read the report descriptor of {}", name))?;
+
+        return Ok(Nodes {
+            hidraw: Path::new("/dev").join(entry.file_name()),
+            events,
+            descriptor: ReportDescriptor { bytes },
+        });
+    }
+
+    Err(anyhow!(
+        "No hidraw node of {:04x}:{:04x}{} with input events",
+        vid,
+        pid,
+        interface.map_or(String::new(), |i| format!(" interface #{}", i))
+    ))
+}
+
+// Vendor and product ID of a HID device in sysfs, named e.g. `0003:046D:C08B.0005`
+fn hid_id(name: &str) -> Option<(u16, u16)> {
+    let mut parts = name.split(['.', ':']);
+    let (_bus, vid, pid) = (parts.next()?, parts.next()?, parts.next()?);
+
+    Some((
+        u16::from_str_radix(vid, 16).ok()?,
+        u16::from_str_radix(pid, 16).ok()?,
+    ))
+}
+
+// /dev/input/eventN of every input device the drivers made of the HID device
+fn event_nodes(hid: &Path) -> Vec<PathBuf> {
+    let mut events = vec![];
+
+    for input in fs::read_dir(hid.join("input"))
+        .into_iter()
+        .flatten()
+        .flatten()
+    {
+        for node in fs::read_dir(input.path()).into_iter().flatten().flatten() {
+            let name = node.file_name();
+            if name.to_string_lossy().starts_with("event") {
+                events.push(Path::new("/dev/input").join(name));
+            }
+        }
+    }
+    events.sort();
+
+    events
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+// `struct input_event`: a timeval, then type, code and value
+const EVENT_SIZE: usize = 2 * std::mem::size_of::<usize>() + 8;
+
+fn parse_event(bytes: &[u8]) -> InputEvent {
+    let field = &bytes[EVENT_SIZE - 8..];
+
+    InputEvent {
+        kind: u16::from_ne_bytes([field[0], field[1]]),
+        code: u16::from_ne_bytes([field[2], field[3]]),
+        value: i32::from_ne_bytes([field[4], field[5], field[6], field[7]]),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arrival {
+    Report(Instant, Vec<u8>),
+    Frame(Instant, Vec<InputEvent>), // events up to a SYN_REPORT
+}
+
+impl Arrival {
+    fn time(&self) -> Instant {
+        match self {
+            Arrival::Report(time, _) | Arrival::Frame(time, _) => *time,
+        }
+    }
+}
+
+// Reads the hidraw and evdev nodes for `duration`
+pub fn read(nodes: &Nodes, duration: Duration) -> Result<Vec<Arrival>> {
+    let (sender, receiver) = mpsc::channel();
+
+    let mut hidraw = File::open(&nodes.hidraw)
+        .with_context(|| format!("Cannot open {}", nodes.hidraw.display()))?;
+    let reports = sender.clone();
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        while let Ok(n) = hidraw.read(&mut buf) {
+            let arrival = Arrival::Report(Instant::now(), buf[..n].to_vec());
+            if n == 0 || reports.send(arrival).is_err() {
+                break;
+            }
+        }
+    });
+
+    for path in &nodes.events {
+        let mut node = File::open(path).with_context(|| {
+            format!(
+                "Cannot open {}, reading events needs root or the input group",
+                path.display()
+            )
+        })?;
+        let frames = sender.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; EVENT_SIZE * 64];
+            let mut events = vec![];
+            while let Ok(n) = node.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                for bytes in buf[..n].chunks_exact(EVENT_SIZE) {
+                    let event = parse_event(bytes);
+                    if event.kind != EV_SYN {
+                        events.push(event);
+                    } else if event.code == SYN_REPORT {
+                        let frame = Arrival::Frame(Instant::now(), std::mem::take(&mut events));
+                        if frames.send(frame).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    // the threads stay blocked in reads until the process exits
+    let start = Instant::now();
+    let mut arrivals = vec![];
+    while let Some(left) = duration.checked_sub(start.elapsed()) {
+        match receiver.recv_timeout(left) {
+            Ok(arrival) => arrivals.push(arrival),
+            Err(mpsc::RecvTimeoutError::Timeout) => break,
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("The device stopped sending reports and events"))
+            }
+        }
+    }
+    arrivals.sort_by_key(Arrival::time);
+
+    Ok(arrivals)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Correlation {
+    pub reports: usize,
+    pub changes: usize, // reports with relative motion, or absolute values changed since the last
+    pub frames: usize,
+    pub latencies: Vec<Duration>, // from a report to its frame
+    pub silent: usize,            // reports with changes but no events
+    pub transformed: Vec<String>, // relative motion the events don't match
+}
+
+struct Pending {
+    time: Instant,
+    bytes: Vec<u8>,
+    relative: BTreeMap<u16, i32>, // by evdev code
+}
+
+pub fn correlate(parser: &Parser, arrivals: &[Arrival]) -> Correlation {
+    let mut correlation = Correlation::default();
+    let mut previous: BTreeMap<u8, Vec<Input>> = BTreeMap::new(); // by the first byte
+    let mut pending: Vec<Pending> = vec![];
+
+    for arrival in arrivals {
+        match arrival {
+            Arrival::Report(time, bytes) => {
+                correlation.reports += 1;
+                let (absolute, relative) = values(parser, bytes);
+                let key = bytes.first().copied().unwrap_or(0);
+                let changed = previous.get(&key) != Some(&absolute);
+                previous.insert(key, absolute);
+
+                if changed || !relative.is_empty() {
+                    correlation.changes += 1;
+                    pending.push(Pending {
+                        time: *time,
+                        bytes: bytes.clone(),
+                        relative,
+                    });
+                }
+            }
+            Arrival::Frame(time, events) => {
+                correlation.frames += 1;
+                let mut motion = BTreeMap::new();
+                for event in events.iter().filter(|event| event.kind == EV_REL) {
+                    if RELATIVE.iter().any(|(_, code, _)| *code == event.code) {
+                        *motion.entry(event.code).or_insert(0) += event.value;
+                    }
+                }
+
+                // the same motion, else motion on the same axes
+                let chosen = pending
+                    .iter()
+                    .position(|report| report.relative == motion)
+                    .or_else(|| {
+                        pending
+                            .iter()
+                            .position(|report| report.relative.keys().eq(motion.keys()))
+                    })
+                    .unwrap_or(0);
+                if chosen >= pending.len() {
+                    continue; // e.g. events of key repeat
+                }
+
+                correlation.silent += chosen;
+                let report = pending.remove(chosen);
+                pending.drain(..chosen);
+
+                correlation.latencies.push(time.duration_since(report.time));
+                if report.relative != motion && !report.relative.is_empty() {
+                    correlation.transformed.push(format!(
+                        "{}: {}, events {}",
+                        hex(&report.bytes),
+                        describe(&report.relative),
+                        describe(&motion)
+                    ));
+                }
+            }
+        }
+    }
+
+    let end = arrivals.last().map(Arrival::time);
+    correlation.silent += pending
+        .iter()
+        .filter(|report| end.is_some_and(|end| end.duration_since(report.time) > IN_FLIGHT))
+        .count();
+
+    correlation
+}
+
+// Absolute values of an input report, and its relative axes with non-zero values by evdev code
+fn values(parser: &Parser, bytes: &[u8]) -> (Vec<Input>, BTreeMap<u16, i32>) {
+    let mut absolute = vec![];
+    let mut motion = BTreeMap::new();
+
+    for report in parser.reports() {
+        if report.report_type.kind() != ReportKind::Input {
+            continue;
+        }
+        if !report.report_type.flags().relative() {
+            report.parse_into(bytes, &mut absolute);
+            continue;
+        }
+
+        for input in report.parse(bytes).unwrap_or_default() {
+            let value = match input.value {
+                InputValue::Int(value) => value,
+                InputValue::UInt(value) => value as i32,
+                _ => continue,
+            };
+            let code = RELATIVE.iter().find(|(usage, _, _)| *usage == input.usage);
+            if let (Some((_, code, _)), true) = (code, value != 0) {
+                *motion.entry(*code).or_insert(0) += value;
+            }
+        }
+    }
+
+    (absolute, motion)
+}
+
+fn describe(motion: &BTreeMap<u16, i32>) -> String {
+    if motion.is_empty() {
+        return "none".to_string();
+    }
+
+    let values: Vec<_> = motion
+        .iter()
+        .map(|(code, value)| {
+            let name = RELATIVE
+                .iter()
+                .find(|(_, c, _)| c == code)
+                .map_or("?", |(_, _, name)| name);
+            format!("{} {}", name, value)
+        })
+        .collect();
+
+    values.join(", ")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Display for Correlation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} reports, {} with changes, {} evdev frames",
+            self.reports, self.changes, self.frames
+        )?;
+
+        match spread(&self.latencies) {
+            Some((min, median, max)) => writeln!(
+                f,
+                "Added latency: {:.2} ms ({:.2} - {:.2}) over {} frames",
+                median.as_secs_f64() * 1000.0,
+                min.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0,
+                self.latencies.len()
+            )?,
+            None => writeln!(f, "Added latency: no frames matched a report")?,
+        }
+
+        writeln!(
+            f,
+            "Reports with changes but no events: {} (fields the kernel doesn't map, or dropped)",
+            self.silent
+        )?;
+        write!(
+            f,
+            "Relative motion changed by the kernel: {}",
+            self.transformed.len()
+        )?;
+        for example in self.transformed.iter().take(3) {
+            write!(f, "\n  {}", example)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData};
+
+    use super::{correlate, hid_id, parse_event, Arrival, InputEvent, EVENT_SIZE};
+
+    fn rel(code: u16, value: i32) -> InputEvent {
+        InputEvent {
+            kind: 0x02,
+            code,
+            value,
+        }
+    }
+
+    #[test]
+    fn reads_sysfs_names_and_events() {
+        assert_eq!(hid_id("0003:046D:C08B.0005"), Some((0x046d, 0xc08b)));
+        assert_eq!(hid_id("input3"), None);
+
+        let mut bytes = vec![0u8; EVENT_SIZE];
+        bytes[EVENT_SIZE - 8..].copy_from_slice(&[0x02, 0x00, 0x08, 0x00, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(parse_event(&bytes), rel(0x08, -1));
+    }
+
+    #[test]
+    fn correlates_reports_with_frames() {
+        // mouse with a button and relative X
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage_page(0x09)
+            .usage(0x01)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .usage_page(0x01)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build()
+            .decode();
+
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_micros(ms * 100);
+        let arrivals = [
+            Arrival::Report(at(0), vec![0x00, 0x05]),
+            Arrival::Frame(at(2), vec![rel(0x00, 5)]),
+            // the same again: relative motion is a change
+            Arrival::Report(at(10), vec![0x00, 0x05]),
+            Arrival::Frame(at(11), vec![rel(0x00, 5)]),
+            // nothing changed, no events expected
+            Arrival::Report(at(20), vec![0x00, 0x00]),
+            Arrival::Report(at(30), vec![0x00, 0x00]),
+            // a click the kernel made no events of
+            Arrival::Report(at(40), vec![0x01, 0x00]),
+            // accelerated motion
+            Arrival::Report(at(50), vec![0x01, 0x03]),
+            Arrival::Frame(at(53), vec![rel(0x00, 6)]),
+        ];
+
+        let correlation = correlate(&parser, &arrivals);
+        assert_eq!((correlation.reports, correlation.changes), (6, 4));
+        assert_eq!(correlation.frames, 3);
+        assert_eq!(correlation.silent, 1);
+        assert_eq!(
+            correlation.latencies,
+            [200, 100, 300].map(Duration::from_micros)
+        );
+        assert_eq!(
+            correlation.to_string(),
+            "6 reports, 4 with changes, 3 evdev frames\n\
+             Added latency: 0.20 ms (0.10 - 0.30) over 3 frames\n\
+             Reports with changes but no events: 1 (fields the kernel doesn't map, or dropped)\n\
+             Relative motion changed by the kernel: 1\n  \
+             01 03: REL_X 3, events REL_X 6"
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Exit codes for scripts
+//
+// Errors are printed to stderr and end the process with a code telling apart the failures test
+// automation usually handles differently:
+//
+//   0  success
+//   1  any other error
+//   2  invalid arguments
+//   3  device or interface not found
+//   4  permission denied opening the device
+//   5  report descriptor mismatch
+//   6  a test exceeded its threshold, e.g. non-compliant idle rates or failed writes
+
+use std::{fmt::Display, process::ExitCode};
+
+pub const HELP: &str = "Exit codes:
+  0  success
+  1  any other error
+  2  invalid arguments
+  3  device or interface not found
+  4  permission denied opening the device
+  5  report descriptor mismatch
+  6  a test exceeded its threshold";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Error = 1,
+    // 2 is left to clap for invalid arguments
+    DeviceNotFound = 3,
+    PermissionDenied = 4,
+    DescriptorMismatch = 5,
+    ThresholdExceeded = 6,
+}
+
+// Error message tagged with the exit code, found by `Exit::of` under any further context
+#[derive(Debug)]
+struct Failure {
+    exit: Exit,
+    message: String,
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+impl Exit {
+    pub fn error(self, message: impl Into<String>) -> anyhow::Error {
+        anyhow::Error::new(Failure {
+            exit: self,
+            message: message.into(),
+        })
+    }
+
+    // Keeps `err` as the cause of `message`
+    pub fn context(
+        self,
+        err: impl Into<anyhow::Error>,
+        message: impl Into<String>,
+    ) -> anyhow::Error {
+        err.into().context(Failure {
+            exit: self,
+            message: message.into(),
+        })
+    }
+
+    pub fn of(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<Failure>() {
+            Some(failure) => failure.exit,
+            None => Exit::Error,
+        }
+    }
+
+    pub fn code(self) -> ExitCode {
+        ExitCode::from(self as u8)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use anyhow::{anyhow, Context};
+
+    use super::Exit;
+
+    #[test]
+    fn finds_exit_codes_under_context() {
+        assert_eq!(Exit::of(&anyhow!("Something broke")), Exit::Error);
+
+        let not_found: anyhow::Result<()> = Err(Exit::DeviceNotFound.error("No such device"));
+        let err = not_found.context("Cannot record").unwrap_err();
+        assert_eq!(Exit::of(&err), Exit::DeviceNotFound);
+        assert_eq!(format!("{:#}", err), "Cannot record: No such device");
+
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "Access denied");
+        let err = Exit::PermissionDenied.context(denied, "Cannot open the device");
+        assert_eq!(Exit::of(&err), Exit::PermissionDenied);
+        assert_eq!(
+            format!("{:#}", err),
+            "Cannot open the device: Access denied"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+// Finding devices by the usages their report descriptors expose
+//
+// A usage is given as "PAGE/USAGE", e.g. "Generic Desktop/Gamepad" or "0x0c/0xe9", or as a
+// usage page alone to match any usage on it. Names are matched ignoring case.
+//
+// Interfaces are picked the same way, or by the name of a top level collection, e.g. "keyboard".
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+use hid_parser::{usage, Collection, CollectionItem, Parser, Report};
+
+use crate::exit::Exit;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsageFilter {
+    pub page: u16,
+    pub id: Option<u16>, // any usage on the page if None
+}
+
+impl FromStr for UsageFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (page, id) = match s.split_once('/') {
+            Some((page, id)) => (page.trim(), Some(id.trim())),
+            None => (s.trim(), None),
+        };
+
+        let page = number(page)
+            .or_else(|| (0..=u16::MAX).find(|p| is_name(usage::page_name(*p), page)))
+            .ok_or_else(|| anyhow!("Unknown usage page '{}'", page))?;
+
+        let id = match id {
+            Some(id) => Some(
+                number(id)
+                    .or_else(|| (0..=u16::MAX).find(|i| is_name(usage::usage_name((page, *i)), id)))
+                    .ok_or_else(|| anyhow!("Unknown usage '{}' on page {:#04x}", id, page))?,
+            ),
+            None => None,
+        };
+
+        Ok(UsageFilter { page, id })
+    }
+}
+
+impl Display for UsageFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.id, usage::page_name(self.page)) {
+            (Some(id), _) => write!(f, "{}", usage::describe((self.page, id))),
+            (None, Some(name)) => write!(f, "{}", name),
+            (None, None) => write!(f, "{:#06x}", self.page),
+        }
+    }
+}
+
+impl UsageFilter {
+    // Usages of the top level collections which expose the usage, in themselves, a nested
+    // collection or an item
+    pub fn collections(&self, parser: &Parser) -> Vec<(u16, u16)> {
+        parser
+            .collections()
+            .iter()
+            .filter(|collection| self.exposed_by(collection))
+            .map(|collection| collection.usage)
+            .collect()
+    }
+
+    fn exposed_by(&self, collection: &Collection<Report>) -> bool {
+        self.matches(collection.usage)
+            || collection.items.iter().any(|item| match item {
+                CollectionItem::Collection(collection) => self.exposed_by(collection),
+                CollectionItem::Item(report) => self.used_by(report),
+            })
+    }
+
+    fn used_by(&self, report: &Report) -> bool {
+        if report.usages.iter().any(|usage| self.matches(*usage)) {
+            return true;
+        }
+
+        match (report.usage_minimum, report.usage_maximum) {
+            (Some(min), Some(max)) => {
+                min.0 == self.page && self.id.is_none_or(|id| (min.1..=max.1).contains(&id))
+            }
+            _ => false,
+        }
+    }
+
+    fn matches(&self, usage: (u16, u16)) -> bool {
+        usage.0 == self.page && self.id.is_none_or(|id| usage.1 == id)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceSelector {
+    Number(u8),
+    Usage {
+        name: String,
+        filter: Option<UsageFilter>, // when the name also parses as a usage filter
+    },
+}
+
+impl FromStr for InterfaceSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Ok(number) = s.trim().parse() {
+            return Ok(InterfaceSelector::Number(number));
+        }
+
+        Ok(InterfaceSelector::Usage {
+            name: s.trim().to_string(),
+            filter: s.parse().ok(),
+        })
+    }
+}
+
+impl InterfaceSelector {
+    fn matches(&self, interface: u8, parser: &Parser) -> bool {
+        match self {
+            InterfaceSelector::Number(number) => *number == interface,
+            InterfaceSelector::Usage { name, filter } => {
+                parser
+                    .collections()
+                    .iter()
+                    .any(|collection| is_name(usage::usage_name(collection.usage), name))
+                    || filter.is_some_and(|filter| !filter.collections(parser).is_empty())
+            }
+        }
+    }
+}
+
+// The interface picked by the selector, or the only interface if there is no selector. Fails
+// listing the interfaces to choose from otherwise.
+pub fn select_interface(
+    selector: Option<&InterfaceSelector>,
+    interfaces: &[(u8, Parser)],
+) -> Result<u8> {
+    let matching: Vec<_> = interfaces
+        .iter()
+        .filter(|(interface, parser)| selector.is_none_or(|s| s.matches(*interface, parser)))
+        .collect();
+
+    if let [(interface, _)] = matching[..] {
+        return Ok(*interface);
+    }
+
+    let message = match (selector, matching.is_empty()) {
+        (Some(InterfaceSelector::Number(number)), _) => {
+            return Err(Exit::DeviceNotFound.error(format!("Cannot find interface #{}", number)))
+        }
+        (Some(InterfaceSelector::Usage { name, .. }), true) => {
+            format!("No interface of the device has a '{}' collection", name)
+        }
+        (None, true) => return Err(Exit::DeviceNotFound.error("The device has no HID interfaces")),
+        (_, false) => "The device has several matching interfaces".to_string(),
+    };
+    let (exit, candidates) = match matching.is_empty() {
+        true => (Exit::DeviceNotFound, interfaces.iter().collect()),
+        false => (Exit::Error, matching),
+    };
+
+    let mut message = format!("{}, pick one with --interface:", message);
+    for (interface, parser) in candidates {
+        let usages: Vec<_> = parser
+            .collections()
+            .iter()
+            .map(|collection| usage::short_name(collection.usage))
+            .collect();
+        message.push_str(&format!("\n  {:>3}  {}", interface, usages.join(", ")));
+    }
+
+    Err(exit.error(message))
+}
+
+// Decimal or 0x prefixed hexadecimal
+fn number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn is_name<S: AsRef<str>>(name: Option<S>, wanted: &str) -> bool {
+    name.is_some_and(|name| name.as_ref().eq_ignore_ascii_case(wanted))
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData};
+
+    use super::{select_interface, InterfaceSelector, UsageFilter};
+
+    #[test]
+    fn finds_collections_exposing_a_usage() {
+        const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(8)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(8)
+            .input(VARIABLE)
+            .end_collection()
+            .usage_page(0x0c)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .report_id(2)
+            .usage(0xe9)
+            .report_count(1)
+            .input(VARIABLE)
+            .end_collection()
+            .build()
+            .decode();
+
+        let filter = "generic desktop / mouse".parse::<UsageFilter>().unwrap();
+        assert_eq!(
+            filter,
+            UsageFilter {
+                page: 0x01,
+                id: Some(0x02)
+            }
+        );
+        assert_eq!(filter.collections(&parser), vec![(0x01, 0x02)]);
+
+        let volume = "0x0c/0xe9".parse::<UsageFilter>().unwrap();
+        assert_eq!(volume.collections(&parser), vec![(0x0c, 0x01)]);
+        assert_eq!(volume.to_string(), "Consumer / Volume Increment");
+
+        let button = "Button/Button 8".parse::<UsageFilter>().unwrap();
+        assert_eq!(button.collections(&parser), vec![(0x01, 0x02)]);
+        assert!("Button/Button 9"
+            .parse::<UsageFilter>()
+            .unwrap()
+            .collections(&parser)
+            .is_empty());
+
+        let consumer = "Consumer".parse::<UsageFilter>().unwrap();
+        assert_eq!(consumer.id, None);
+        assert_eq!(consumer.collections(&parser), vec![(0x0c, 0x01)]);
+
+        assert!("Generic Desktop/Flux Capacitor"
+            .parse::<UsageFilter>()
+            .is_err());
+    }
+
+    #[test]
+    fn selects_interfaces_by_number_or_collection() {
+        let collection = |page, usage| {
+            DescriptorBuilder::new()
+                .usage_page(page)
+                .usage(usage)
+                .collection(CollectionType::Application)
+                .end_collection()
+                .build()
+                .decode()
+        };
+        let keyboard = (0, collection(0x01, 0x06));
+        let mouse = (1, collection(0x01, 0x02));
+
+        assert_eq!(
+            select_interface(None, std::slice::from_ref(&mouse)).unwrap(),
+            1
+        );
+        assert!(select_interface(None, &[]).is_err());
+
+        let interfaces = [keyboard, mouse];
+        let error = select_interface(None, &interfaces).unwrap_err().to_string();
+        assert!(error.contains("pick one with --interface"));
+        assert!(error.contains("Keyboard"));
+
+        let number = "1".parse::<InterfaceSelector>().unwrap();
+        assert_eq!(number, InterfaceSelector::Number(1));
+        assert_eq!(select_interface(Some(&number), &interfaces).unwrap(), 1);
+        assert!(select_interface(Some(&InterfaceSelector::Number(2)), &interfaces).is_err());
+
+        let keyboard = "keyboard".parse::<InterfaceSelector>().unwrap();
+        assert_eq!(select_interface(Some(&keyboard), &interfaces).unwrap(), 0);
+        let desktop = "Generic Desktop/Mouse"
+            .parse::<InterfaceSelector>()
+            .unwrap();
+        assert_eq!(select_interface(Some(&desktop), &interfaces).unwrap(), 1);
+        let joystick = "joystick".parse::<InterfaceSelector>().unwrap();
+        assert!(select_interface(Some(&joystick), &interfaces).is_err());
+    }
+}
@@ -0,0 +1,377 @@
+// Frame logs: input reports of one interface, written as fast as a device can send them
+//
+// Captures (.hbc) are records with a type and a length each, written through a buffered file.
+// A frame log (.hbf) keeps only what a recording at several kHz needs. A header, then one frame
+// per report:
+//
+//   header  magic "HBFL", version u16, vid u16, pid u16, interface u8, report descriptor
+//           length u16, report descriptor
+//   frame   report length u16, microseconds since the previous frame u32, report bytes
+//
+// Integers are little endian. A frame without bytes only moves the time on (gaps longer than
+// a u32 of microseconds), an all zero frame header ends the log. Where it can, the writer maps
+// the file into memory a segment at a time and copies the frames in: no write calls, the
+// kernel writes the pages back on its own, so the disk never holds up reading the device. A
+// recording killed before it finishes leaves the rest of the last segment zeroed, which reads
+// as the end. `convert --to capture` turns frame logs into captures.
+
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::{
+    capture::{Capture, DeviceMetadata, Direction, Transfer},
+    ReportDescriptor,
+};
+
+pub const MAGIC: &[u8; 4] = b"HBFL";
+pub const VERSION: u16 = 1;
+
+const FRAME_HEADER: usize = 6;
+
+pub struct FrameWriter {
+    output: Output,
+    last: Duration, // timestamp of the previous frame
+}
+
+impl FrameWriter {
+    pub fn create(
+        path: &Path,
+        metadata: &DeviceMetadata,
+        interface: u8,
+        descriptor: &ReportDescriptor,
+    ) -> Result<Self> {
+        // read as well, for mapping
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .with_context(|| format!("Cannot create {}", path.display()))?;
+        let length = u16::try_from(descriptor.bytes.len())
+            .map_err(|_| anyhow!("The report descriptor is too long for a frame log"))?;
+
+        let mut output = Output::new(file)?;
+        output.write(MAGIC)?;
+        output.write(&VERSION.to_le_bytes())?;
+        output.write(&metadata.vendor_id.to_le_bytes())?;
+        output.write(&metadata.product_id.to_le_bytes())?;
+        output.write(&[interface])?;
+        output.write(&length.to_le_bytes())?;
+        output.write(&descriptor.bytes)?;
+
+        Ok(Self {
+            output,
+            last: Duration::ZERO,
+        })
+    }
+
+    // Adds a report received at `timestamp`, since the recording started
+    pub fn write(&mut self, timestamp: Duration, bytes: &[u8]) -> Result<()> {
+        let mut delta = timestamp.saturating_sub(self.last).as_micros();
+        self.last = timestamp;
+
+        while delta > u32::MAX as u128 {
+            self.frame(u32::MAX, &[])?;
+            delta -= u32::MAX as u128;
+        }
+
+        self.frame(delta as u32, bytes)
+    }
+
+    fn frame(&mut self, delta: u32, bytes: &[u8]) -> Result<()> {
+        let mut header = [0; FRAME_HEADER];
+        header[..2].copy_from_slice(&(bytes.len() as u16).to_le_bytes());
+        header[2..].copy_from_slice(&delta.to_le_bytes());
+
+        self.output.write(&header)?;
+        self.output.write(bytes)
+    }
+
+    // Cuts the file to the frames written
+    pub fn finish(self) -> Result<()> {
+        self.output.finish()
+    }
+}
+
+// The frame log as a capture, timed from the start of the recording
+pub fn read(bytes: &[u8]) -> Result<Capture> {
+    let truncated = || anyhow!("Truncated frame log");
+    let u16_at = |at: usize| -> Result<u16> {
+        let bytes = bytes.get(at..at + 2).ok_or_else(truncated)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+
+    if !bytes.starts_with(MAGIC) {
+        return Err(anyhow!("Not a frame log"));
+    }
+    let version = u16_at(4)?;
+    if version != VERSION {
+        return Err(anyhow!("Unsupported frame log version {}", version));
+    }
+    let interface = *bytes.get(10).ok_or_else(truncated)?;
+    let length = u16_at(11)? as usize;
+    let descriptor = bytes.get(13..13 + length).ok_or_else(truncated)?;
+
+    let mut capture = Capture {
+        device: DeviceMetadata {
+            vendor_id: u16_at(6)?,
+            product_id: u16_at(8)?,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    capture.descriptors.add(
+        interface,
+        ReportDescriptor {
+            bytes: descriptor.to_vec(),
+        },
+    );
+
+    let mut at = 13 + length;
+    let mut timestamp = Duration::ZERO;
+    while at < bytes.len() {
+        let header = bytes.get(at..at + FRAME_HEADER).ok_or_else(truncated)?;
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        let length = u16::from_le_bytes([header[0], header[1]]) as usize;
+        let delta = u32::from_le_bytes(header[2..].try_into().unwrap());
+        at += FRAME_HEADER;
+
+        timestamp += Duration::from_micros(delta as u64);
+        if length == 0 {
+            continue;
+        }
+        let report = bytes.get(at..at + length).ok_or_else(truncated)?;
+        at += length;
+
+        capture.transfers.push(Transfer {
+            timestamp,
+            interface,
+            direction: Direction::In,
+            bytes: report.to_vec(),
+        });
+    }
+
+    Ok(capture)
+}
+
+pub fn read_file(path: &Path) -> Result<Capture> {
+    let bytes = fs::read(path).with_context(|| format!("Cannot read {}", path.display()))?;
+
+    read(&bytes).with_context(|| format!("Cannot read {}", path.display()))
+}
+
+#[cfg(all(unix, target_pointer_width = "64"))]
+use mapped::Output;
+
+// The file mapped a segment at a time, grown as the frames fill it
+#[cfg(all(unix, target_pointer_width = "64"))]
+mod mapped {
+    use std::{
+        ffi::c_void,
+        fs::File,
+        os::{fd::AsRawFd, raw::c_int},
+        ptr,
+    };
+
+    use anyhow::{anyhow, Result};
+
+    // A multiple of every page size
+    const SEGMENT: usize = 4 << 20;
+
+    const PROT_READ: c_int = 1;
+    const PROT_WRITE: c_int = 2;
+    const MAP_SHARED: c_int = 1;
+    const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+    extern "C" {
+        fn mmap(
+            addr: *mut c_void,
+            len: usize,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: i64,
+        ) -> *mut c_void;
+        fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    }
+
+    pub struct Output {
+        file: File,
+        map: *mut u8,
+        start: u64,      // file offset of the mapped segment
+        position: usize, // next byte in the segment
+    }
+
+    impl Output {
+        pub fn new(file: File) -> Result<Self> {
+            let mut output = Self {
+                file,
+                map: ptr::null_mut(),
+                start: 0,
+                position: 0,
+            };
+            output.map(0)?;
+
+            Ok(output)
+        }
+
+        pub fn write(&mut self, mut bytes: &[u8]) -> Result<()> {
+            while !bytes.is_empty() {
+                if self.position == SEGMENT {
+                    self.map(self.start + SEGMENT as u64)?;
+                }
+
+                let n = bytes.len().min(SEGMENT - self.position);
+                // the segment is mapped for writing and `position + n` stays within it
+                unsafe {
+                    ptr::copy_nonoverlapping(bytes.as_ptr(), self.map.add(self.position), n);
+                }
+                self.position += n;
+                bytes = &bytes[n..];
+            }
+
+            Ok(())
+        }
+
+        pub fn finish(mut self) -> Result<()> {
+            self.unmap();
+            self.file.set_len(self.start + self.position as u64)?;
+
+            Ok(())
+        }
+
+        fn map(&mut self, start: u64) -> Result<()> {
+            self.unmap();
+            self.file.set_len(start + SEGMENT as u64)?;
+
+            let map = unsafe {
+                mmap(
+                    ptr::null_mut(),
+                    SEGMENT,
+                    PROT_READ | PROT_WRITE,
+                    MAP_SHARED,
+                    self.file.as_raw_fd(),
+                    start as i64,
+                )
+            };
+            if map == MAP_FAILED {
+                return Err(anyhow!(
+                    "Cannot map the frame log: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+
+            self.map = map as *mut u8;
+            self.start = start;
+            self.position = 0;
+
+            Ok(())
+        }
+
+        fn unmap(&mut self) {
+            if !self.map.is_null() {
+                unsafe { munmap(self.map as *mut c_void, SEGMENT) };
+                self.map = ptr::null_mut();
+            }
+        }
+    }
+
+    impl Drop for Output {
+        fn drop(&mut self) {
+            self.unmap();
+        }
+    }
+}
+
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+use buffered::Output;
+
+// Elsewhere through a large buffer
+#[cfg(not(all(unix, target_pointer_width = "64")))]
+mod buffered {
+    use std::{
+        fs::File,
+        io::{BufWriter, Write},
+    };
+
+    use anyhow::Result;
+
+    pub struct Output(BufWriter<File>);
+
+    impl Output {
+        pub fn new(file: File) -> Result<Self> {
+            Ok(Self(BufWriter::with_capacity(1 << 20, file)))
+        }
+
+        pub fn write(&mut self, bytes: &[u8]) -> Result<()> {
+            Ok(self.0.write_all(bytes)?)
+        }
+
+        pub fn finish(mut self) -> Result<()> {
+            Ok(self.0.flush()?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs, time::Duration};
+
+    use hid_parser::{capture::DeviceMetadata, ReportDescriptor};
+
+    use super::{read, read_file, FrameWriter};
+
+    #[test]
+    fn reads_frame_logs_back_as_captures() {
+        let path = env::temp_dir().join(format!("hid-bench-{}.hbf", std::process::id()));
+        let metadata = DeviceMetadata {
+            vendor_id: 0x1234,
+            product_id: 0x5678,
+            ..Default::default()
+        };
+        let descriptor = ReportDescriptor {
+            bytes: vec![0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0xc0],
+        };
+
+        // 8 kHz for longer than a mapped segment, then a gap longer than a u32 of microseconds
+        let mut writer = FrameWriter::create(&path, &metadata, 2, &descriptor).unwrap();
+        let reports = 80_000;
+        for i in 0..reports {
+            let timestamp = Duration::from_micros(125 * i as u64);
+            writer.write(timestamp, &[0x01; 64]).unwrap();
+        }
+        writer.write(Duration::from_secs(5000), &[0x02]).unwrap();
+        writer.finish().unwrap();
+
+        let capture = read_file(&path).unwrap();
+        assert_eq!(capture.device, metadata);
+        assert_eq!(capture.descriptors.descriptor(2), Some(descriptor));
+        assert_eq!(capture.transfers.len(), reports + 1);
+        assert_eq!(capture.transfers[1].timestamp, Duration::from_micros(125));
+        let last = capture.transfers.last().unwrap();
+        assert_eq!(
+            (last.timestamp, last.interface),
+            (Duration::from_secs(5000), 2)
+        );
+        assert_eq!(last.bytes, [0x02]);
+
+        // a recording killed halfway ends at the zeroed rest of the segment
+        let mut bytes = fs::read(&path).unwrap();
+        let length = bytes.len();
+        bytes.truncate(length - 7);
+        bytes.resize(length + 100, 0);
+        assert_eq!(read(&bytes).unwrap().transfers.len(), reports);
+        bytes.truncate(length - 10);
+        assert!(read(&bytes).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+// Highlighting bytes which changed since the previous report with the same report ID
+//
+// Moving fields of undocumented vendor reports stand out this way, which helps a lot when
+// reverse engineering a protocol.
+
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+
+use crate::render::{self, Palette, Renderer};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    /// Colors when the output is colored
+    Auto,
+    Color,
+    /// Marks changed bytes with a '*', for logs written to files
+    Markers,
+    Off,
+}
+
+impl Highlight {
+    pub fn resolve(self, renderer: &Renderer) -> Self {
+        match self {
+            Highlight::Auto if renderer.color() => Highlight::Color,
+            Highlight::Auto => Highlight::Off,
+            highlight => highlight,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    with_report_ids: bool,
+    previous: HashMap<Option<u8>, Vec<u8>>,
+}
+
+impl ChangeTracker {
+    // Reports start with their ID if the device uses report IDs
+    pub fn new(with_report_ids: bool) -> Self {
+        Self {
+            with_report_ids,
+            previous: HashMap::new(),
+        }
+    }
+
+    // Which bytes differ from the previous report with the same ID, nothing has changed in
+    // the first one
+    pub fn changes(&mut self, report: &[u8]) -> Vec<bool> {
+        let report_id = match self.with_report_ids {
+            true => report.first().copied(),
+            false => None,
+        };
+
+        let changes = match self.previous.get(&report_id) {
+            Some(previous) => (0..report.len())
+                .map(|i| previous.get(i) != Some(&report[i]))
+                .collect(),
+            None => vec![false; report.len()],
+        };
+
+        self.previous.insert(report_id, report.to_vec());
+
+        changes
+    }
+}
+
+// Formats the bytes like `{:02x?}` does, highlighting the changed ones
+pub fn format_bytes(
+    report: &[u8],
+    changes: &[bool],
+    highlight: Highlight,
+    palette: &Palette,
+) -> String {
+    let bytes = report
+        .iter()
+        .zip(changes)
+        .map(|(byte, changed)| match (highlight, changed) {
+            (Highlight::Color, true) => render::paint(palette.changed, &format!("{:02x}", byte)),
+            (Highlight::Markers, true) => format!("{:02x}*", byte),
+            _ => format!("{:02x}", byte),
+        })
+        .collect::<Vec<_>>();
+
+    format!("[{}]", bytes.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_bytes, ChangeTracker, Highlight};
+    use crate::render::{DARK, LIGHT};
+
+    #[test]
+    fn tracks_changes_per_report_id() {
+        let mut tracker = ChangeTracker::new(true);
+
+        assert_eq!(tracker.changes(&[1, 0, 5]), vec![false; 3]);
+        assert_eq!(tracker.changes(&[2, 0, 5]), vec![false; 3]);
+        assert_eq!(tracker.changes(&[1, 0, 6]), vec![false, false, true]);
+        assert_eq!(
+            tracker.changes(&[2, 1, 5, 9]),
+            vec![false, true, false, true]
+        );
+
+        let mut tracker = ChangeTracker::new(false);
+        tracker.changes(&[1, 0]);
+        assert_eq!(tracker.changes(&[2, 0]), vec![true, false]);
+    }
+
+    #[test]
+    fn formats_changed_bytes() {
+        let report = [0x01, 0xff, 0x0a];
+        let changes = [false, true, false];
+
+        assert_eq!(
+            format_bytes(&report, &changes, Highlight::Off, &DARK),
+            format!("{:02x?}", report)
+        );
+        assert_eq!(
+            format_bytes(&report, &changes, Highlight::Markers, &DARK),
+            "[01, ff*, 0a]"
+        );
+        assert_eq!(
+            format_bytes(&report, &changes, Highlight::Color, &DARK),
+            "[01, \x1b[1;33mff\x1b[0m, 0a]"
+        );
+        assert_eq!(
+            format_bytes(&report, &changes, Highlight::Color, &LIGHT),
+            "[01, \x1b[1;31mff\x1b[0m, 0a]"
+        );
+    }
+}
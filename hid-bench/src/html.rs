@@ -0,0 +1,363 @@
+// Self-contained HTML reports
+//
+// Charts are inline SVG with a few lines of script: hovering a chart shows the values under the
+// pointer, clicking a legend entry hides or shows its trace. Nothing is loaded from elsewhere,
+// so the file can be attached to a ticket or mailed around as it is.
+
+use std::fmt::Write;
+
+use crate::plot;
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 260.0;
+const LEFT: f64 = 60.0; // room for the value axis labels
+const BOTTOM: f64 = 30.0;
+const PADDING: f64 = 10.0;
+const TICKS: usize = 5;
+const HISTOGRAM_BUCKETS: usize = 30;
+const MAX_POINTS: usize = 2000; // per trace, longer traces are thinned out
+
+const COLORS: [&str; 8] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f",
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em auto;max-width:840px;color:#222}\
+pre{background:#f4f4f4;padding:1em;overflow-x:auto}\
+svg{width:100%;height:auto;background:#fff;border:1px solid #ddd}\
+.axis{stroke:#999}.grid{stroke:#eee}text{font-size:11px;fill:#555}\
+.bar{fill:#1f77b4}.bar:hover{fill:#ff7f0e}.trace{fill:none;stroke-width:1.2}\
+.legend span{cursor:pointer;margin-right:1em}.legend .off{opacity:.35}\
+.readout{font-size:12px;color:#555;height:1.2em}";
+
+const SCRIPT: &str = r#"
+document.querySelectorAll('.legend span').forEach(entry => entry.addEventListener('click', () => {
+  const trace = document.getElementById(entry.dataset.trace);
+  const hidden = trace.style.display === 'none';
+  trace.style.display = hidden ? '' : 'none';
+  entry.classList.toggle('off', !hidden);
+}));
+document.querySelectorAll('svg[data-x]').forEach(svg => {
+  const readout = svg.parentElement.querySelector('.readout');
+  const [x0, x1] = svg.dataset.x.split(',').map(Number);
+  const [y0, y1] = svg.dataset.y.split(',').map(Number);
+  const [left, top, right, bottom] = svg.dataset.area.split(',').map(Number);
+  svg.addEventListener('mousemove', event => {
+    const box = svg.getBoundingClientRect();
+    const px = (event.clientX - box.left) / box.width * svg.viewBox.baseVal.width;
+    const py = (event.clientY - box.top) / box.height * svg.viewBox.baseVal.height;
+    if (px < left || px > right || py < top || py > bottom) { readout.textContent = ''; return; }
+    const x = x0 + (px - left) / (right - left) * (x1 - x0);
+    const y = y1 - (py - top) / (bottom - top) * (y1 - y0);
+    readout.textContent = `${svg.dataset.xLabel} ${x.toPrecision(4)}, ${svg.dataset.yLabel} ${y.toPrecision(4)}`;
+  });
+  svg.addEventListener('mouseleave', () => readout.textContent = '');
+});
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Chart {
+    Histogram {
+        title: String,
+        unit: String,
+        samples: Vec<f64>,
+    },
+    Lines {
+        title: String,
+        x_label: String,
+        y_label: String,
+        traces: Vec<(String, Vec<(f64, f64)>)>, // name, points
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct HtmlReport {
+    title: String,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+enum Section {
+    Text(String),
+    Chart(Chart),
+}
+
+impl HtmlReport {
+    pub fn new(title: &str) -> Self {
+        HtmlReport {
+            title: title.to_string(),
+            sections: vec![],
+        }
+    }
+
+    // Shown as it is, e.g. the summary printed in the terminal
+    pub fn text(&mut self, text: &str) {
+        self.sections.push(Section::Text(text.to_string()));
+    }
+
+    pub fn chart(&mut self, chart: Chart) {
+        self.sections.push(Section::Chart(chart));
+    }
+
+    pub fn render(&self) -> String {
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+            escape(&self.title),
+            STYLE,
+            escape(&self.title)
+        );
+
+        for (index, section) in self.sections.iter().enumerate() {
+            match section {
+                Section::Text(text) => {
+                    let _ = writeln!(html, "<pre>{}</pre>", escape(text));
+                }
+                Section::Chart(chart) => html.push_str(&render_chart(index, chart)),
+            }
+        }
+
+        let _ = write!(html, "<script>{}</script>\n</body>\n</html>\n", SCRIPT);
+
+        html
+    }
+}
+
+// The chart area in the SVG, and the values at its edges
+struct Frame {
+    x: (f64, f64),
+    y: (f64, f64),
+}
+
+impl Frame {
+    fn new(x: (f64, f64), y: (f64, f64)) -> Self {
+        let widen = |(min, max): (f64, f64)| match max > min {
+            true => (min, max),
+            false => (min - 1.0, max + 1.0),
+        };
+
+        Frame {
+            x: widen(x),
+            y: widen(y),
+        }
+    }
+
+    fn px(&self, x: f64) -> f64 {
+        LEFT + (x - self.x.0) / (self.x.1 - self.x.0) * (WIDTH - LEFT - PADDING)
+    }
+
+    fn py(&self, y: f64) -> f64 {
+        HEIGHT - BOTTOM - (y - self.y.0) / (self.y.1 - self.y.0) * (HEIGHT - BOTTOM - PADDING)
+    }
+
+    // Opening <svg> tag with what the script needs for the readout, grid and axis labels
+    fn open(&self, x_label: &str, y_label: &str) -> String {
+        let mut svg = format!(
+            "<svg viewBox=\"0 0 {} {}\" data-x=\"{},{}\" data-y=\"{},{}\" data-area=\"{},{},{},{}\" \
+             data-x-label=\"{}\" data-y-label=\"{}\">\n",
+            WIDTH,
+            HEIGHT,
+            self.x.0,
+            self.x.1,
+            self.y.0,
+            self.y.1,
+            LEFT,
+            PADDING,
+            WIDTH - PADDING,
+            HEIGHT - BOTTOM,
+            escape(x_label),
+            escape(y_label)
+        );
+
+        for tick in 0..=TICKS {
+            let fraction = tick as f64 / TICKS as f64;
+            let x = self.x.0 + (self.x.1 - self.x.0) * fraction;
+            let y = self.y.0 + (self.y.1 - self.y.0) * fraction;
+            let (px, py) = (self.px(x), self.py(y));
+
+            let _ = writeln!(
+                svg,
+                "<line class=\"grid\" x1=\"{left}\" y1=\"{py:.1}\" x2=\"{right}\" y2=\"{py:.1}\"/>\
+                 <text x=\"{label:.1}\" y=\"{py:.1}\" text-anchor=\"end\" dy=\"4\">{}</text>\
+                 <text x=\"{px:.1}\" y=\"{bottom}\" text-anchor=\"middle\">{}</text>",
+                number(y),
+                number(x),
+                left = LEFT,
+                right = WIDTH - PADDING,
+                label = LEFT - 6.0,
+                bottom = HEIGHT - BOTTOM + 16.0,
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<line class=\"axis\" x1=\"{left}\" y1=\"{bottom}\" x2=\"{right}\" y2=\"{bottom}\"/>\
+             <line class=\"axis\" x1=\"{left}\" y1=\"{top}\" x2=\"{left}\" y2=\"{bottom}\"/>",
+            left = LEFT,
+            right = WIDTH - PADDING,
+            top = PADDING,
+            bottom = HEIGHT - BOTTOM,
+        );
+
+        svg
+    }
+}
+
+fn render_chart(index: usize, chart: &Chart) -> String {
+    let mut html = String::new();
+
+    match chart {
+        Chart::Histogram {
+            title,
+            unit,
+            samples,
+        } => {
+            let buckets = plot::buckets(samples, HISTOGRAM_BUCKETS);
+            let (first, last) = match (buckets.first(), buckets.last()) {
+                (Some(first), Some(last)) => (first, last),
+                _ => return html,
+            };
+            let largest = buckets.iter().map(|bucket| bucket.count).max().unwrap_or(1);
+            let frame = Frame::new(
+                (first.start, last.start + last.width.max(1e-9)),
+                (0.0, largest as f64),
+            );
+
+            let _ = writeln!(html, "<h2>{}</h2>\n<div>", escape(title));
+            html.push_str(&frame.open(unit, "count"));
+            for bucket in &buckets {
+                let (x0, x1) = (
+                    frame.px(bucket.start),
+                    frame.px(bucket.start + bucket.width),
+                );
+                let y = frame.py(bucket.count as f64);
+
+                let _ = writeln!(
+                    html,
+                    "<rect class=\"bar\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\">\
+                     <title>{} - {} {}: {}</title></rect>",
+                    x0,
+                    y,
+                    (x1 - x0 - 1.0).max(1.0),
+                    HEIGHT - BOTTOM - y,
+                    number(bucket.start),
+                    number(bucket.start + bucket.width),
+                    escape(unit),
+                    bucket.count
+                );
+            }
+            html.push_str("</svg>\n<div class=\"readout\"></div>\n</div>\n");
+        }
+        Chart::Lines {
+            title,
+            x_label,
+            y_label,
+            traces,
+        } => {
+            let points = || traces.iter().flat_map(|(_, points)| points.iter());
+            let range = |values: Vec<f64>| {
+                let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                (min, max)
+            };
+            if points().next().is_none() {
+                return html;
+            }
+            let frame = Frame::new(
+                range(points().map(|(x, _)| *x).collect()),
+                range(points().map(|(_, y)| *y).collect()),
+            );
+
+            let _ = writeln!(html, "<h2>{}</h2>\n<div>", escape(title));
+            html.push_str(&frame.open(x_label, y_label));
+            for (trace, (_, points)) in traces.iter().enumerate() {
+                let step = points.len().div_ceil(MAX_POINTS).max(1);
+                let coordinates = points
+                    .iter()
+                    .step_by(step)
+                    .map(|(x, y)| format!("{:.1},{:.1}", frame.px(*x), frame.py(*y)))
+                    .collect::<Vec<_>>();
+
+                let _ = writeln!(
+                    html,
+                    "<polyline id=\"chart{}-{}\" class=\"trace\" stroke=\"{}\" points=\"{}\"/>",
+                    index,
+                    trace,
+                    COLORS[trace % COLORS.len()],
+                    coordinates.join(" ")
+                );
+            }
+            html.push_str("</svg>\n<div class=\"legend\">");
+            for (trace, (name, _)) in traces.iter().enumerate() {
+                let _ = write!(
+                    html,
+                    "<span data-trace=\"chart{}-{}\" style=\"color:{}\">&#9632; {}</span>",
+                    index,
+                    trace,
+                    COLORS[trace % COLORS.len()],
+                    escape(name)
+                );
+            }
+            html.push_str("</div>\n<div class=\"readout\"></div>\n</div>\n");
+        }
+    }
+
+    html
+}
+
+// Compact axis labels
+fn number(value: f64) -> String {
+    match value.abs() {
+        v if v >= 100.0 || v == 0.0 => format!("{:.0}", value),
+        v if v >= 1.0 => format!("{:.1}", value),
+        _ => format!("{:.3}", value),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Chart, HtmlReport};
+
+    #[test]
+    fn renders_self_contained_reports() {
+        let mut report = HtmlReport::new("Latency <mouse>");
+        report.text("Steady state: latency 1.0 ms");
+        report.chart(Chart::Histogram {
+            title: "Latency".to_string(),
+            unit: "ms".to_string(),
+            samples: vec![1.0, 1.5, 2.0, 2.0],
+        });
+        report.chart(Chart::Lines {
+            title: "Axes".to_string(),
+            x_label: "s".to_string(),
+            y_label: "value".to_string(),
+            traces: vec![
+                ("X".to_string(), vec![(0.0, 1.0), (1.0, -1.0)]),
+                ("Y".to_string(), vec![(0.0, 0.0), (1.0, 5.0)]),
+            ],
+        });
+        report.chart(Chart::Histogram {
+            title: "Empty".to_string(),
+            unit: "ms".to_string(),
+            samples: vec![],
+        });
+
+        let html = report.render();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Latency &lt;mouse&gt;</title>"));
+        assert!(html.contains("<pre>Steady state: latency 1.0 ms</pre>"));
+        assert_eq!(html.matches("class=\"bar\"").count(), 30);
+        assert!(html.contains("<title>2.0 - 2.0 ms: 2</title>"));
+        assert!(html.contains("id=\"chart2-1\" class=\"trace\""));
+        assert!(html.contains("data-trace=\"chart2-1\" style=\"color:#ff7f0e\">&#9632; Y</span>"));
+        assert!(!html.contains("Empty"));
+        assert!(!html.contains("src="));
+    }
+}
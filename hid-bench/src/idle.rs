@@ -0,0 +1,241 @@
+// Idle rate compliance test
+//
+// The idle rate (HID 1.11, section 7.2.4) sets how often a device repeats its last input report
+// while nothing changes, in 4 ms steps. Idle 0 means reporting changes only. The test takes the
+// interface over from the OS driver, which sets an idle rate of its own, then sets every rate
+// in turn and listens to the untouched device.
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    latency::spread,
+    output::outln,
+    usb::{ClaimedInterface, IDLE_STEP},
+};
+
+// Repeats may be late or early by one idle step, or 10% of longer durations
+const TOLERANCE_PERCENT: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Compliant,
+    Unsupported,   // SET_IDLE stalled
+    Repeating,     // reports repeated with idle 0
+    NotRepeating,  // no repeats with a duration set
+    WrongInterval, // repeats, but not at the requested duration
+    Touched,       // the reports changed, the device wasn't left alone
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdleResult {
+    pub duration: Duration,
+    pub read_back: Option<Duration>, // GET_IDLE after setting
+    pub window: Duration,
+    pub reports: usize,
+    pub intervals: Option<(Duration, Duration, Duration)>, // min, median, max
+    pub verdict: Verdict,
+}
+
+impl IdleResult {
+    fn unsupported(duration: Duration) -> Self {
+        IdleResult {
+            duration,
+            read_back: None,
+            window: Duration::ZERO,
+            reports: 0,
+            intervals: None,
+            verdict: Verdict::Unsupported,
+        }
+    }
+
+    // `arrivals` of the reports since the idle rate was set, `changes` is how many of them
+    // differed from the report before
+    pub fn evaluate(
+        duration: Duration,
+        read_back: Option<Duration>,
+        window: Duration,
+        arrivals: &[Duration],
+        changes: usize,
+    ) -> Self {
+        let intervals = spread(
+            &arrivals
+                .windows(2)
+                .map(|pair| pair[1] - pair[0])
+                .collect::<Vec<_>>(),
+        );
+
+        let tolerance = IDLE_STEP.max(duration * TOLERANCE_PERCENT / 100);
+        let verdict = match (duration.is_zero(), intervals) {
+            _ if changes > 0 => Verdict::Touched,
+            // a single report right after the request is the device's current state
+            (true, _) if arrivals.len() > 1 => Verdict::Repeating,
+            (true, _) => Verdict::Compliant,
+            (false, None) => Verdict::NotRepeating,
+            (false, Some((_, median, _))) if median.abs_diff(duration) <= tolerance => {
+                Verdict::Compliant
+            }
+            (false, Some(_)) => Verdict::WrongInterval,
+        };
+
+        IdleResult {
+            duration,
+            read_back,
+            window,
+            reports: arrivals.len(),
+            intervals,
+            verdict,
+        }
+    }
+}
+
+impl Display for IdleResult {
+    // e.g. `Idle  100 ms: 30 reports in 3.0 s, every 100.1 ms (98.0 - 102.3) - compliant`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Idle {:>4} ms: ", self.duration.as_millis())?;
+        if self.verdict == Verdict::Unsupported {
+            return write!(f, "SET_IDLE not supported (stalled)");
+        }
+
+        write!(
+            f,
+            "{} reports in {:.1} s",
+            self.reports,
+            self.window.as_secs_f64()
+        )?;
+        if let Some((min, median, max)) = self.intervals {
+            write!(
+                f,
+                ", every {:.1} ms ({:.1} - {:.1})",
+                median.as_secs_f64() * 1000.0,
+                min.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0
+            )?;
+        }
+        if let Some(read_back) = self
+            .read_back
+            .filter(|read_back| *read_back != self.duration)
+        {
+            write!(f, ", GET_IDLE says {} ms", read_back.as_millis())?;
+        }
+
+        let verdict = match self.verdict {
+            Verdict::Compliant => "compliant",
+            Verdict::Unsupported => unreachable!(),
+            Verdict::Repeating => "repeats reports although it should only report changes",
+            Verdict::NotRepeating => "doesn't repeat reports",
+            Verdict::WrongInterval => "repeats reports at the wrong interval",
+            Verdict::Touched => "inconclusive, the device was touched",
+        };
+        write!(f, " - {}", verdict)
+    }
+}
+
+// Parses `0,24,100` milliseconds, each a multiple of 4 ms up to 1020 ms
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let error = || anyhow!("Idle rates must be multiples of 4 ms up to 1020 ms");
+
+    let ms = s.trim().parse::<u64>().map_err(|_| error())?;
+    if ms % IDLE_STEP.as_millis() as u64 != 0 || ms > 1020 {
+        return Err(error());
+    }
+
+    Ok(Duration::from_millis(ms))
+}
+
+pub fn run(
+    interface: &ClaimedInterface,
+    report_id: u8,
+    durations: &[Duration],
+    window: Duration,
+) -> Result<Vec<IdleResult>> {
+    // restore whatever was set before the test
+    let original = interface.get_idle(report_id).ok();
+    let mut results = vec![];
+    let mut buf = [0u8; 64];
+    let mut last = None;
+
+    for &duration in durations {
+        match interface.set_idle(report_id, duration) {
+            Err(rusb::Error::Pipe) => {
+                results.push(IdleResult::unsupported(duration));
+                continue;
+            }
+            result => result?,
+        }
+        let read_back = interface.get_idle(report_id).ok();
+
+        let start = Instant::now();
+        let mut arrivals = vec![];
+        let mut changes = 0;
+
+        while start.elapsed() < window {
+            let n = interface.read(&mut buf, window.saturating_sub(start.elapsed()))?;
+            if n == 0 {
+                continue;
+            }
+
+            arrivals.push(start.elapsed());
+            let report = buf[0..n].to_vec();
+            if last.as_ref().is_some_and(|last| *last != report) {
+                changes += 1;
+            }
+            last = Some(report);
+        }
+
+        let result = IdleResult::evaluate(duration, read_back, window, &arrivals, changes);
+        outln!("{}", result);
+        results.push(result);
+    }
+
+    if let Some(original) = original {
+        interface.set_idle(report_id, original)?;
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{parse_duration, IdleResult, Verdict};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn arrivals(every: u64, count: u64) -> Vec<Duration> {
+        (0..count).map(|n| ms(n * every)).collect()
+    }
+
+    #[test]
+    fn judges_idle_rates() {
+        let window = ms(1000);
+        let verdict = |duration, arrivals: &[Duration], changes| {
+            IdleResult::evaluate(ms(duration), None, window, arrivals, changes).verdict
+        };
+
+        assert_eq!(verdict(0, &[], 0), Verdict::Compliant);
+        assert_eq!(verdict(0, &[ms(3)], 0), Verdict::Compliant);
+        assert_eq!(verdict(0, &arrivals(8, 100), 0), Verdict::Repeating);
+        assert_eq!(verdict(100, &arrivals(103, 10), 0), Verdict::Compliant);
+        assert_eq!(verdict(100, &arrivals(8, 100), 0), Verdict::WrongInterval);
+        assert_eq!(verdict(100, &[ms(2)], 0), Verdict::NotRepeating);
+        assert_eq!(verdict(100, &arrivals(100, 10), 2), Verdict::Touched);
+
+        let result = IdleResult::evaluate(ms(24), Some(ms(24)), window, &arrivals(24, 41), 0);
+        assert_eq!(
+            result.to_string(),
+            "Idle   24 ms: 41 reports in 1.0 s, every 24.0 ms (24.0 - 24.0) - compliant"
+        );
+
+        assert_eq!(parse_duration("500").unwrap(), ms(500));
+        assert!(parse_duration("10").is_err());
+        assert!(parse_duration("1024").is_err());
+    }
+}
@@ -0,0 +1,400 @@
+// Guessing the fields of undocumented reports from captured data
+//
+// Splits every report (per interface and report ID) into constant regions, counters, axes
+// which move smoothly, button-like bits and whatever is left. The guesses are heuristics, the
+// layout is written as TOML so it can be corrected by hand.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use hid_parser::capture::{Capture, Direction};
+
+// Mean step between consecutive reports relative to the whole range, random data is around 1/3
+const SMOOTH_8: f64 = 0.15;
+const SMOOTH_16: f64 = 0.1;
+// Share of consecutive reports in which a counter moves by the same step
+const COUNTER_SHARE: f64 = 0.9;
+// Buttons don't change in most reports
+const BUTTON_TOGGLES: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum FieldKind {
+    ReportId,
+    Constant { value: Vec<u8> },
+    Counter { step: i64 },
+    Axis { signed: bool, min: i64, max: i64 },
+    Buttons { bits: Vec<u8> }, // bit numbers that changed, 0 is the least significant
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    pub offset: usize, // bytes, including the report ID
+    pub size: usize,   // bytes
+    #[serde(flatten)]
+    pub kind: FieldKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportLayout {
+    pub interface: u8,
+    pub report_id: Option<u8>,
+    pub reports: usize, // how many reports the guess is based on
+    pub length: usize,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Layout {
+    pub reports: Vec<ReportLayout>,
+}
+
+// Layouts of the input reports in the capture
+pub fn infer(capture: &Capture) -> Layout {
+    let with_report_ids = capture
+        .descriptors
+        .parsers()
+        .map(|(interface, parser)| {
+            (
+                interface,
+                parser.reports().iter().any(|r| r.report_id.is_some()),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut groups = BTreeMap::<_, Vec<&[u8]>>::new();
+    for transfer in &capture.transfers {
+        if transfer.direction != Direction::In || transfer.bytes.is_empty() {
+            continue;
+        }
+
+        let report_id = match with_report_ids.get(&transfer.interface) {
+            Some(true) => transfer.bytes.first().copied(),
+            _ => None,
+        };
+        groups
+            .entry((transfer.interface, report_id))
+            .or_default()
+            .push(&transfer.bytes);
+    }
+
+    let reports = groups
+        .into_iter()
+        .map(|((interface, report_id), reports)| {
+            // reports are usually all the same length, a few may be shorter
+            let length = reports.iter().map(|r| r.len()).min().unwrap_or_default();
+            let start = report_id.map_or(0, |_| 1);
+
+            let mut fields = vec![];
+            if report_id.is_some() {
+                fields.push(Field {
+                    offset: 0,
+                    size: 1,
+                    kind: FieldKind::ReportId,
+                });
+            }
+            fields.extend(segment(&reports, start, length));
+
+            ReportLayout {
+                interface,
+                report_id,
+                reports: reports.len(),
+                length,
+                fields,
+            }
+        })
+        .collect();
+
+    Layout { reports }
+}
+
+fn segment(reports: &[&[u8]], start: usize, length: usize) -> Vec<Field> {
+    let mut fields: Vec<Field> = vec![];
+    let mut offset = start;
+
+    while offset < length {
+        let (size, kind) = guess(reports, offset, length);
+
+        // neighbouring constant and unknown bytes form one region
+        match (fields.last_mut(), &kind) {
+            (
+                Some(Field {
+                    kind: FieldKind::Constant { value },
+                    size: last_size,
+                    ..
+                }),
+                FieldKind::Constant { value: next },
+            ) => {
+                value.extend(next);
+                *last_size += size;
+            }
+            (
+                Some(Field {
+                    kind: FieldKind::Unknown,
+                    size: last_size,
+                    ..
+                }),
+                FieldKind::Unknown,
+            ) => *last_size += size,
+            _ => fields.push(Field { offset, size, kind }),
+        }
+
+        offset += size;
+    }
+
+    fields
+}
+
+// Size and kind of the field starting at `offset`
+fn guess(reports: &[&[u8]], offset: usize, length: usize) -> (usize, FieldKind) {
+    let byte = |r: &&[u8]| r[offset] as i64;
+    let first = reports[0][offset];
+
+    if reports.iter().all(|r| r[offset] == first) {
+        return (1, FieldKind::Constant { value: vec![first] });
+    }
+
+    let values = reports.iter().map(byte).collect::<Vec<_>>();
+    let signed_values = reports
+        .iter()
+        .map(|r| r[offset] as i8 as i64)
+        .collect::<Vec<_>>();
+
+    if let Some(step) = counter_step(&values, 1 << 8) {
+        return (1, FieldKind::Counter { step });
+    }
+
+    if let Some(bits) = buttons(reports, offset) {
+        return (1, FieldKind::Buttons { bits });
+    }
+
+    // a 16 bit value wins when it is smoother than its low byte alone
+    let high_byte_changes = offset + 1 < length
+        && reports
+            .iter()
+            .any(|r| r[offset + 1] != reports[0][offset + 1]);
+    if high_byte_changes {
+        let words = reports
+            .iter()
+            .map(|r| u16::from_le_bytes([r[offset], r[offset + 1]]) as i64)
+            .collect::<Vec<_>>();
+        let signed_words = reports
+            .iter()
+            .map(|r| i16::from_le_bytes([r[offset], r[offset + 1]]) as i64)
+            .collect::<Vec<_>>();
+
+        if let Some(step) = counter_step(&words, 1 << 16) {
+            return (2, FieldKind::Counter { step });
+        }
+
+        let low_byte = smoothness(&values).unwrap_or(f64::MAX);
+        let word = smoothness(&words)
+            .unwrap_or(f64::MAX)
+            .min(smoothness(&signed_words).unwrap_or(f64::MAX));
+        if word < low_byte {
+            if let Some(kind) = axis(&words, &signed_words, SMOOTH_16) {
+                return (2, kind);
+            }
+        }
+    }
+
+    if let Some(kind) = axis(&values, &signed_values, SMOOTH_8) {
+        return (1, kind);
+    }
+
+    (1, FieldKind::Unknown)
+}
+
+// The step most consecutive values differ by, wrapping at `modulo`
+fn counter_step(values: &[i64], modulo: i64) -> Option<i64> {
+    if values.len() < 3 {
+        return None;
+    }
+
+    let mut steps = HashMap::<i64, usize>::new();
+    for pair in values.windows(2) {
+        let step = (pair[1] - pair[0]).rem_euclid(modulo);
+        *steps.entry(step).or_default() += 1;
+    }
+
+    let (step, count) = steps.into_iter().max_by_key(|(_, count)| *count)?;
+    let share = count as f64 / (values.len() - 1) as f64;
+
+    (step != 0 && step < 16 && share >= COUNTER_SHARE).then_some(step)
+}
+
+// Mean absolute step relative to the range
+fn smoothness(values: &[i64]) -> Option<f64> {
+    let min = *values.iter().min()?;
+    let max = *values.iter().max()?;
+    if max == min || values.len() < 2 {
+        return None;
+    }
+
+    let steps = values
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .sum::<i64>();
+
+    Some(steps as f64 / (values.len() - 1) as f64 / (max - min) as f64)
+}
+
+fn range(values: &[i64]) -> (i64, i64) {
+    let min = values.iter().copied().min().unwrap_or_default();
+    let max = values.iter().copied().max().unwrap_or_default();
+
+    (min, max)
+}
+
+// Axes crossing zero have a much smaller range when read as signed
+fn axis(values: &[i64], signed_values: &[i64], threshold: f64) -> Option<FieldKind> {
+    if values.iter().collect::<HashSet<_>>().len() <= 2 {
+        return None;
+    }
+
+    let (min, max) = range(values);
+    let (signed_min, signed_max) = range(signed_values);
+    let signed = signed_max - signed_min < max - min;
+
+    let (values, min, max) = match signed {
+        true => (signed_values, signed_min, signed_max),
+        false => (values, min, max),
+    };
+
+    (smoothness(values)? < threshold).then_some(FieldKind::Axis { signed, min, max })
+}
+
+// Bits which change rarely and independently of each other
+fn buttons(reports: &[&[u8]], offset: usize) -> Option<Vec<u8>> {
+    let mut toggles = [0usize; 8];
+    for pair in reports.windows(2) {
+        let changed = pair[0][offset] ^ pair[1][offset];
+        for (bit, toggles) in toggles.iter_mut().enumerate() {
+            *toggles += (changed >> bit & 1) as usize;
+        }
+    }
+
+    let pairs = (reports.len() - 1).max(1) as f64;
+    let bits = (0..8u8)
+        .filter(|bit| toggles[*bit as usize] > 0)
+        .collect::<Vec<_>>();
+
+    let rare = toggles
+        .iter()
+        .all(|toggles| (*toggles as f64 / pairs) <= BUTTON_TOGGLES);
+
+    (rare && !bits.is_empty()).then_some(bits)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::capture::{Capture, Direction, Transfer};
+
+    use super::{infer, Field, FieldKind};
+
+    #[test]
+    fn infers_vendor_report_fields() {
+        // constant header, counter, signed 16 bit axis, buttons, noise
+        let transfers = (0..200i32)
+            .map(|n| {
+                let axis = ((n as f64 / 20.0).sin() * 300.0) as i16;
+                let buttons =
+                    if n % 40 < 10 { 0x01 } else { 0x00 } | if n > 100 { 0x04 } else { 0 };
+                let noise = (n.wrapping_mul(2_654_435_761u32 as i32) >> 13) as u8;
+
+                let mut bytes = vec![0xaa, 0x55, n as u8];
+                bytes.extend(axis.to_le_bytes());
+                bytes.extend([buttons, noise]);
+
+                Transfer {
+                    timestamp: Duration::from_millis(n as u64 * 8),
+                    interface: 0,
+                    direction: Direction::In,
+                    bytes,
+                }
+            })
+            .collect();
+        let capture = Capture {
+            transfers,
+            ..Default::default()
+        };
+
+        let layout = infer(&capture);
+        let report = &layout.reports[0];
+
+        assert_eq!(report.reports, 200);
+        assert_eq!(report.length, 7);
+        assert_eq!(
+            report.fields,
+            vec![
+                Field {
+                    offset: 0,
+                    size: 2,
+                    kind: FieldKind::Constant {
+                        value: vec![0xaa, 0x55]
+                    }
+                },
+                Field {
+                    offset: 2,
+                    size: 1,
+                    kind: FieldKind::Counter { step: 1 }
+                },
+                Field {
+                    offset: 3,
+                    size: 2,
+                    kind: FieldKind::Axis {
+                        signed: true,
+                        min: -299,
+                        max: 299
+                    }
+                },
+                Field {
+                    offset: 5,
+                    size: 1,
+                    kind: FieldKind::Buttons { bits: vec![0, 2] }
+                },
+                Field {
+                    offset: 6,
+                    size: 1,
+                    kind: FieldKind::Unknown
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_layouts_as_toml() {
+        let layout = super::Layout {
+            reports: vec![super::ReportLayout {
+                interface: 2,
+                report_id: Some(0x11),
+                reports: 10,
+                length: 3,
+                fields: vec![
+                    Field {
+                        offset: 0,
+                        size: 1,
+                        kind: FieldKind::ReportId,
+                    },
+                    Field {
+                        offset: 1,
+                        size: 2,
+                        kind: FieldKind::Axis {
+                            signed: false,
+                            min: 0,
+                            max: 1023,
+                        },
+                    },
+                ],
+            }],
+        };
+
+        let toml = toml::to_string(&layout).unwrap();
+        assert!(toml.contains("kind = \"axis\""));
+        assert_eq!(toml::from_str::<super::Layout>(&toml).unwrap(), layout);
+    }
+}
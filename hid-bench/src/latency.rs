@@ -0,0 +1,277 @@
+// First report after idle versus steady state
+//
+// Devices saving power slow down or switch off their sensors when left alone, which delays the
+// first report after they are used again. With a trigger (an output report the device answers
+// with an input report, e.g. loopback firmware) the time from the trigger to the answer is
+// measured. Without one the user is prompted to move the device, there is no reference to measure
+// the latency against then, so the intervals between the first reports are compared with the
+// intervals while the device is in use (which needs a device reporting continuously when moved,
+// e.g. a mouse or a stick).
+
+use std::{
+    fmt::Display,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use hidapi::HidDevice;
+
+use crate::output::note;
+
+const TRIGGER_TIMEOUT_MS: i32 = 1000;
+const TRIGGER_SPACING: Duration = Duration::from_millis(10);
+const STEADY_TRIGGERS: usize = 20;
+const STEADY_INPUT: Duration = Duration::from_secs(3);
+const REPORTS_AFTER_IDLE: usize = 5;
+
+// The device is considered sleeping when the first reports after idle take this many times
+// longer than in the steady state
+const SLEEP_FACTOR: u32 = 2;
+
+#[derive(Debug)]
+pub struct LatencyOptions {
+    pub idle: Duration,
+    pub rounds: usize,
+    pub trigger: Option<Vec<u8>>, // report ID (or 0) first, as hidapi sends it
+}
+
+// Smallest, median and largest sample
+pub fn spread(samples: &[Duration]) -> Option<(Duration, Duration, Duration)> {
+    let mut samples = samples.to_vec();
+    samples.sort();
+
+    match (samples.first(), samples.last()) {
+        (Some(min), Some(max)) => Some((*min, samples[samples.len() / 2], *max)),
+        _ => None,
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub triggered: bool, // latencies, or intervals between reports
+    pub idle: Duration,
+    pub steady: Vec<Duration>,
+    pub after_idle: Vec<Duration>,
+}
+
+impl Comparison {
+    pub fn sleeps(&self) -> Option<bool> {
+        let (_, steady, _) = spread(&self.steady)?;
+        let (_, after_idle, _) = spread(&self.after_idle)?;
+
+        Some(after_idle > steady * SLEEP_FACTOR)
+    }
+}
+
+impl Display for Comparison {
+    // e.g. `After 5 s idle: latency 35.0 ms (33.1 - 40.2) over 5 samples`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.triggered {
+            true => "latency",
+            false => "reports every",
+        };
+        let line = |label: &str, samples: &[Duration]| match spread(samples) {
+            Some((min, median, max)) => format!(
+                "{}: {} {:.1} ms ({:.1} - {:.1}) over {} samples",
+                label,
+                what,
+                ms(median),
+                ms(min),
+                ms(max),
+                samples.len()
+            ),
+            None => format!("{}: no reports", label),
+        };
+
+        writeln!(f, "{}", line("Steady state", &self.steady))?;
+        writeln!(
+            f,
+            "{}",
+            line(
+                &format!("After {} s idle", self.idle.as_secs_f64()),
+                &self.after_idle
+            )
+        )?;
+
+        match self.sleeps() {
+            Some(true) => write!(f, "The device seems to sleep while idle"),
+            Some(false) => write!(f, "The device responds as fast after idle"),
+            None => write!(f, "Not enough reports to compare"),
+        }
+    }
+}
+
+pub fn run(device: &HidDevice, options: &LatencyOptions) -> Result<Comparison> {
+    match &options.trigger {
+        Some(trigger) => run_triggered(device, trigger, options),
+        None => run_prompted(device, options),
+    }
+}
+
+fn run_triggered(
+    device: &HidDevice,
+    trigger: &[u8],
+    options: &LatencyOptions,
+) -> Result<Comparison> {
+    let mut steady = vec![];
+    for _ in 0..STEADY_TRIGGERS {
+        steady.extend(trigger_latency(device, trigger)?);
+        thread::sleep(TRIGGER_SPACING);
+    }
+    if steady.is_empty() {
+        return Err(anyhow!(
+            "The device doesn't answer the trigger with an input report"
+        ));
+    }
+
+    let mut after_idle = vec![];
+    for round in 1..=options.rounds {
+        note!(
+            "Round {} of {}: idle for {} s",
+            round,
+            options.rounds,
+            options.idle.as_secs_f64()
+        );
+        thread::sleep(options.idle);
+        after_idle.extend(trigger_latency(device, trigger)?);
+    }
+
+    Ok(Comparison {
+        triggered: true,
+        idle: options.idle,
+        steady,
+        after_idle,
+    })
+}
+
+// Time from sending the trigger to the next input report
+fn trigger_latency(device: &HidDevice, trigger: &[u8]) -> Result<Option<Duration>> {
+    let mut buf = [0u8; 64];
+
+    // reports which arrived meanwhile aren't answers
+    while device.read_timeout(&mut buf, 0)? > 0 {}
+
+    let sent = Instant::now();
+    device.write(trigger)?;
+
+    match device.read_timeout(&mut buf, TRIGGER_TIMEOUT_MS)? {
+        0 => Ok(None),
+        _ => Ok(Some(sent.elapsed())),
+    }
+}
+
+fn run_prompted(device: &HidDevice, options: &LatencyOptions) -> Result<Comparison> {
+    let mut buf = [0u8; 64];
+
+    note!("Keep moving the device for {} s", STEADY_INPUT.as_secs());
+    device.read(&mut buf)?;
+    let start = Instant::now();
+    let mut last = start;
+    let mut steady = vec![];
+    while start.elapsed() < STEADY_INPUT {
+        if device.read_timeout(&mut buf, 100)? > 0 {
+            steady.push(last.elapsed());
+            last = Instant::now();
+        }
+    }
+
+    let mut after_idle = vec![];
+    for round in 1..=options.rounds {
+        note!(
+            "Round {} of {}: leave the device alone for {} s",
+            round,
+            options.rounds,
+            options.idle.as_secs_f64()
+        );
+        wait_for_quiet(device, options.idle)?;
+
+        note!("Now move the device");
+        device.read(&mut buf)?;
+        let mut last = Instant::now();
+        for _ in 0..REPORTS_AFTER_IDLE {
+            if device.read_timeout(&mut buf, 1000)? == 0 {
+                break;
+            }
+            after_idle.push(last.elapsed());
+            last = Instant::now();
+        }
+    }
+
+    Ok(Comparison {
+        triggered: false,
+        idle: options.idle,
+        steady,
+        after_idle,
+    })
+}
+
+// Returns once no report arrived for `idle`
+fn wait_for_quiet(device: &HidDevice, idle: Duration) -> Result<()> {
+    let mut buf = [0u8; 64];
+    let mut quiet_since = Instant::now();
+
+    while quiet_since.elapsed() < idle {
+        let left = idle.saturating_sub(quiet_since.elapsed());
+        if device.read_timeout(&mut buf, left.as_millis() as i32)? > 0 {
+            quiet_since = Instant::now();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{spread, Comparison};
+
+    fn ms(samples: &[u64]) -> Vec<Duration> {
+        samples
+            .iter()
+            .map(|ms| Duration::from_millis(*ms))
+            .collect()
+    }
+
+    #[test]
+    fn compares_steady_state_with_after_idle() {
+        assert_eq!(spread(&[]), None);
+        assert_eq!(
+            spread(&ms(&[3, 1, 2])),
+            Some((ms(&[1])[0], ms(&[2])[0], ms(&[3])[0]))
+        );
+
+        let comparison = Comparison {
+            triggered: true,
+            idle: Duration::from_secs(5),
+            steady: ms(&[1, 2, 1, 1]),
+            after_idle: ms(&[30, 35]),
+        };
+        assert_eq!(comparison.sleeps(), Some(true));
+        assert_eq!(
+            comparison.to_string(),
+            "Steady state: latency 1.0 ms (1.0 - 2.0) over 4 samples\n\
+             After 5 s idle: latency 35.0 ms (30.0 - 35.0) over 2 samples\n\
+             The device seems to sleep while idle"
+        );
+
+        let awake = Comparison {
+            triggered: false,
+            after_idle: ms(&[1, 2]),
+            ..comparison.clone()
+        };
+        assert_eq!(awake.sleeps(), Some(false));
+        assert!(awake.to_string().contains("reports every 2.0 ms"));
+
+        let silent = Comparison {
+            after_idle: vec![],
+            ..comparison
+        };
+        assert_eq!(silent.sleeps(), None);
+    }
+}
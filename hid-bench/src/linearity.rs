@@ -0,0 +1,358 @@
+// Linearity and hysteresis of an axis (device linearity)
+//
+// The axis is moved to known physical positions (marks on a ruler or a protractor) and its
+// reported value recorded at each. Positions are visited up and down, so each is reached from
+// both sides, either by hand when prompted, or by a motorized rig sending a line `P <position>`
+// over a serial port whenever it has settled at a position (and `END` when done).
+//
+// A line is fitted through the values against the positions. Non-linearity is the largest
+// distance of a value from the line, hysteresis the largest difference between the values at a
+// position reached going up and going down, both in percent of the full scale output. Axes
+// declaring a unit are compared in physical units, positions being in the same unit, so the
+// line's slope and offset show the gain and offset errors of the descriptor's physical range.
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::{
+    unit::{Measurement, Unit},
+    usage, FieldAddress, FlatInputs, InputValue, Parser,
+};
+
+use crate::{backend::HidIo, bridge, output::note, stats};
+
+// Values are averaged over this long once at a position
+const SAMPLE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub position: f64,
+    pub value: f64, // physical with a unit, counts without
+    pub rising: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Linearity {
+    pub usage: (u16, u16),
+    pub unit: Option<Unit>,
+    pub points: Vec<Point>,
+}
+
+impl Linearity {
+    // Offset and slope of the least squares line of values against positions
+    pub fn fit(&self) -> Option<(f64, f64)> {
+        let points: Vec<_> = self.points.iter().map(|p| (p.position, p.value)).collect();
+
+        stats::linear_fit(&points)
+    }
+
+    // The output range the line covers over the positions
+    fn full_scale(&self) -> Option<f64> {
+        let (_, slope) = self.fit()?;
+        let min = self
+            .points
+            .iter()
+            .map(|p| p.position)
+            .fold(f64::INFINITY, f64::min);
+        let max = self
+            .points
+            .iter()
+            .map(|p| p.position)
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        Some((slope * (max - min)).abs()).filter(|scale| *scale > 0.0)
+    }
+
+    // Largest distance from the line and the position where it is
+    pub fn non_linearity(&self) -> Option<(f64, f64)> {
+        let (offset, slope) = self.fit()?;
+
+        self.points
+            .iter()
+            .map(|p| ((p.value - (offset + slope * p.position)).abs(), p.position))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    // Largest difference between the mean values going up and down to a position, and the
+    // position, None without positions reached both ways
+    pub fn hysteresis(&self) -> Option<(f64, f64)> {
+        let mean = |position: f64, rising: bool| {
+            let values: Vec<_> = self
+                .points
+                .iter()
+                .filter(|p| p.position == position && p.rising == rising)
+                .map(|p| p.value)
+                .collect();
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+
+        self.points
+            .iter()
+            .filter_map(|p| {
+                Some((
+                    (mean(p.position, true)? - mean(p.position, false)?).abs(),
+                    p.position,
+                ))
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+    }
+
+    fn amount(&self, value: f64) -> String {
+        match self.unit {
+            Some(unit) => Measurement { value, unit }.to_string(),
+            None => format!("{:.2} counts", value),
+        }
+    }
+}
+
+impl Display for Linearity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = usage::short_name(self.usage);
+        let (Some((offset, slope)), Some(scale)) = (self.fit(), self.full_scale()) else {
+            return write!(f, "{}: not enough positions for a line", name);
+        };
+
+        write!(f, "{}: {} points", name, self.points.len())?;
+        match self.unit {
+            Some(_) => write!(
+                f,
+                "\n    gain error {:+.2}%, offset {}",
+                (slope - 1.0) * 100.0,
+                self.amount(offset)
+            )?,
+            None => write!(
+                f,
+                "\n    {:.3} counts per unit, {:.1} counts at 0",
+                slope, offset
+            )?,
+        }
+        if let Some((distance, position)) = self.non_linearity() {
+            write!(
+                f,
+                "\n    non-linearity {:.2}% of full scale ({} at {})",
+                distance / scale * 100.0,
+                self.amount(distance),
+                position
+            )?;
+        }
+        match self.hysteresis() {
+            Some((difference, position)) => write!(
+                f,
+                "\n    hysteresis {:.2}% of full scale ({} at {})",
+                difference / scale * 100.0,
+                self.amount(difference),
+                position
+            ),
+            None => write!(f, "\n    no position was reached both ways for hysteresis"),
+        }
+    }
+}
+
+// Up through the positions and back down, `passes` times
+pub fn sweep(positions: &[f64], passes: usize) -> Vec<f64> {
+    let mut sweep: Vec<f64> = positions.first().into_iter().copied().collect();
+    for _ in 0..passes {
+        sweep.extend(positions.iter().skip(1));
+        sweep.extend(positions.iter().rev().skip(1));
+    }
+
+    sweep
+}
+
+// Asks for every position of the sweep in turn, sending it once Enter is pressed
+pub fn prompted(sweep: Vec<f64>) -> Receiver<f64> {
+    let (sender, positions) = mpsc::channel();
+
+    thread::spawn(move || {
+        let total = sweep.len();
+        for (n, position) in sweep.into_iter().enumerate() {
+            note!(
+                "{}/{}: move the axis to {} and press Enter",
+                n + 1,
+                total,
+                position
+            );
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).is_err() || sender.send(position).is_err() {
+                return;
+            }
+        }
+    });
+
+    positions
+}
+
+// `P <position>` when the rig settled at a position, None for the end and other lines
+pub fn parse_rig_line(line: &str) -> Option<Option<f64>> {
+    let line = line.trim();
+    if line == "END" {
+        return Some(None);
+    }
+
+    line.strip_prefix('P')?.trim().parse().ok().map(Some)
+}
+
+// Positions reported by a motorized rig on a serial port
+pub fn rig(port: &Path, baud: u32) -> Result<Receiver<f64>> {
+    bridge::configure(port, baud)?;
+    let file = File::open(port).with_context(|| format!("Cannot open {}", port.display()))?;
+    let (sender, positions) = mpsc::channel();
+
+    thread::spawn(move || {
+        for line in BufReader::new(file).lines().map_while(Result::ok) {
+            match parse_rig_line(&line) {
+                Some(Some(position)) if sender.send(position).is_err() => return,
+                Some(None) => return,
+                _ => {}
+            }
+        }
+    });
+
+    Ok(positions)
+}
+
+fn number(value: InputValue) -> Option<f64> {
+    match value {
+        InputValue::UInt(v) => Some(v as f64),
+        InputValue::Int(v) => Some(v as f64),
+        _ => None,
+    }
+}
+
+// Records the axis at every position sent until the sender finishes
+pub fn run(
+    device: &dyn HidIo,
+    parser: &Parser,
+    field: FieldAddress,
+    positions: Receiver<f64>,
+) -> Result<Linearity> {
+    let report = parser.reports()[field.item];
+    let unit = Measurement::of(report, InputValue::Int(report.logical_minimum)).map(|m| m.unit);
+    // physical with a unit, counts without
+    let value = |value: InputValue| match unit {
+        Some(_) => Measurement::of(report, value).map(|m| m.value),
+        None => number(value),
+    };
+
+    let mut linearity = Linearity {
+        usage: field.usage,
+        unit,
+        points: vec![],
+    };
+    let mut buf = [0u8; 64];
+    let mut flat = FlatInputs::new();
+    let mut last = None;
+    let mut read = |last: &mut Option<f64>, timeout_ms| -> Result<Option<f64>> {
+        let n = device.read_timeout(&mut buf, timeout_ms)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        parser.parse_input_flat(&buf[..n], &mut flat);
+        let read = field.value(&flat).and_then(value);
+        *last = read.or(*last);
+
+        Ok(read)
+    };
+
+    loop {
+        // keep up with the axis while it is moved
+        let position = match positions.recv_timeout(Duration::from_millis(20)) {
+            Ok(position) => position,
+            Err(RecvTimeoutError::Timeout) => {
+                read(&mut last, 10)?;
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+
+        // devices reporting only changes may send nothing at rest
+        let mut values = vec![];
+        let start = Instant::now();
+        while start.elapsed() < SAMPLE {
+            values.extend(read(&mut last, 10)?);
+        }
+        let value = match (values.is_empty(), last) {
+            (false, _) => values.iter().sum::<f64>() / values.len() as f64,
+            (true, Some(last)) => last,
+            (true, None) => {
+                return Err(anyhow!(
+                    "The device hasn't reported the axis yet, move it a little and try again"
+                ))
+            }
+        };
+
+        let rising = linearity
+            .points
+            .last()
+            .is_none_or(|previous| position >= previous.position);
+        linearity.points.push(Point {
+            position,
+            value,
+            rising,
+        });
+        note!("{}: {:.3}", position, value);
+    }
+
+    Ok(linearity)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_rig_line, sweep, Linearity, Point};
+
+    #[test]
+    fn sweeps_up_and_down() {
+        assert_eq!(
+            sweep(&[0.0, 50.0, 100.0], 2),
+            [0.0, 50.0, 100.0, 50.0, 0.0, 50.0, 100.0, 50.0, 0.0]
+        );
+        assert_eq!(parse_rig_line("P 12.5"), Some(Some(12.5)));
+        assert_eq!(parse_rig_line("END"), Some(None));
+        assert_eq!(parse_rig_line("homing"), None);
+    }
+
+    #[test]
+    fn finds_non_linearity_and_hysteresis() {
+        // 10 counts per unit, bowing up in the middle, lagging 1 count going down
+        let points = [
+            (0.0, 0.0, true),
+            (50.0, 502.0, true),
+            (100.0, 1000.0, true),
+            (50.0, 501.0, false),
+            (0.0, -1.0, false),
+        ];
+        let linearity = Linearity {
+            usage: (0x01, 0x30),
+            unit: None,
+            points: points
+                .iter()
+                .map(|&(position, value, rising)| Point {
+                    position,
+                    value,
+                    rising,
+                })
+                .collect(),
+        };
+
+        let (offset, slope) = linearity.fit().unwrap();
+        assert!((slope - 10.01).abs() < 1e-9, "{}", slope);
+        assert!(offset.abs() < 1e-9, "{}", offset);
+        assert_eq!(linearity.hysteresis(), Some((1.0, 0.0)));
+        assert_eq!(
+            linearity.to_string(),
+            "X: 5 points\n    \
+             10.010 counts per unit, 0.0 counts at 0\n    \
+             non-linearity 0.15% of full scale (1.50 counts at 50)\n    \
+             hysteresis 0.10% of full scale (1.00 counts at 0)"
+        );
+    }
+}
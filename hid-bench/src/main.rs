@@ -1,45 +1,334 @@
+// Config, rendering and exit codes are shared with the device commands, which builds without
+// the usb feature leave out
+#![cfg_attr(not(feature = "usb"), allow(dead_code))]
+
+#[cfg(feature = "usb")]
+mod aggregate;
+mod analyze;
+#[cfg(feature = "usb")]
+mod backend;
+#[cfg(feature = "usb")]
+mod backends;
+mod bandwidth;
+mod bits;
+#[cfg(feature = "usb")]
+mod bridge;
+#[cfg(feature = "usb")]
+mod cache;
+mod config;
+#[cfg(feature = "usb")]
+mod controls;
+mod convert;
+mod derive;
+#[cfg(feature = "usb")]
+mod descriptors;
+#[cfg(feature = "usb")]
+mod devices;
+#[cfg(feature = "usb")]
+mod dial;
+#[cfg(feature = "usb")]
+mod drain;
+mod dump;
+#[cfg(feature = "usb")]
+mod evdev;
+mod exit;
+mod find;
+mod framelog;
+#[cfg(feature = "usb")]
+mod highlight;
+mod html;
+#[cfg(feature = "usb")]
+mod idle;
+mod infer;
+#[cfg(feature = "usb")]
+mod latency;
+#[cfg(feature = "usb")]
+mod linearity;
+#[cfg(all(test, feature = "test-backend"))]
+mod mock;
+#[cfg(feature = "usb")]
+mod noise;
+mod output;
+mod pager;
+mod pcapng;
+#[cfg(feature = "usb")]
+mod permissions;
+#[cfg(feature = "usb")]
+mod piped;
+#[cfg(feature = "usb")]
+mod platform;
+mod plot;
+#[cfg(feature = "usb")]
+mod record;
+mod regimes;
+mod render;
+#[cfg(feature = "usb")]
+mod rig;
+#[cfg(feature = "usb")]
+mod scroll;
+mod selection;
+#[cfg(feature = "usb")]
+mod send;
+#[cfg(feature = "usb")]
+mod session;
+#[cfg(feature = "usb")]
+mod signals;
+mod simulate;
+#[cfg(feature = "usb")]
+mod soak;
+#[cfg(feature = "usb")]
+mod stats;
+#[cfg(feature = "usb")]
+mod sticks;
+#[cfg(feature = "usb")]
+mod stress;
+#[cfg(feature = "usb")]
+mod suspend;
+mod synthesize;
+#[cfg(feature = "usb")]
+mod text;
+mod timeline;
+#[cfg(feature = "usb")]
+mod touch;
+#[cfg(feature = "usb")]
+mod trace;
+#[cfg(feature = "usb")]
+mod transfers;
+mod udev;
+#[cfg(target_os = "linux")]
+mod uhid;
+#[cfg(feature = "usb")]
+mod usb;
+#[cfg(feature = "usb")]
+mod usbmon;
+#[cfg(feature = "usb")]
+mod velocity;
+
 use std::{
-    collections::HashMap,
-    time::{Duration, Instant},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use clap::{Parser as ClapParser, Subcommand, ValueEnum};
-use hidapi::HidApi;
-use rusb::{Device, GlobalContext};
 
+use analyze::Aggregate;
+use bandwidth::{Endpoint, Speed};
+use config::{Config, DeviceSpec};
+use convert::ConvertFormat;
+use exit::Exit;
 use hid_parser::{
-    Collection, CollectionItem, HidDescriptor, Input, InputValue, Parser, ReportDescriptor,
+    capture::{Capture, DeviceMetadata, Direction, Transfer},
+    lint, ReportDescriptor, Strictness,
 };
+use html::HtmlReport;
+use infer::Layout;
+use output::{note, out, outln};
+use render::{ColorChoice, Renderer, Theme};
+use selection::CollectionSelector;
+use timeline::TimelineFormat;
 
 #[derive(Debug, ClapParser)]
 #[command(name = "hid-bencch")]
 #[command(about = "USB HID test bencch", long_about = None)]
+#[command(after_help = exit::HELP)]
 struct Cli {
+    /// Config file with device aliases and defaults, or $HID_BENCH_CONFIG [default:
+    /// ~/.config/hid-bench/config.toml]
+    #[arg(value_name = "FILE", long, global = true)]
+    config: Option<PathBuf>,
+    /// [default: auto]
+    #[arg(value_enum, long, global = true)]
+    color: Option<ColorChoice>,
+    /// Colors for dark or light terminal backgrounds [default: dark]
+    #[arg(value_enum, long, global = true)]
+    theme: Option<Theme>,
+    /// Only print errors, for scripts checking the exit code
+    #[arg(long, short, global = true)]
+    quiet: bool,
+    /// Print every control transfer made through libusb (descriptor, report and idle
+    /// requests) with its setup fields and data to stderr
+    #[cfg(feature = "usb")]
+    #[arg(long, global = true)]
+    trace_usb: bool,
+    /// Read report descriptors from the device instead of the cache of known devices, and
+    /// don't cache them
+    #[cfg(feature = "usb")]
+    #[arg(long, global = true)]
+    no_cache: bool,
+    /// Read report descriptors from the device again, replacing the cached ones
+    #[cfg(feature = "usb")]
+    #[arg(long, global = true, conflicts_with = "no_cache")]
+    refresh: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    /// Lists USB HID devices
-    List,
-    /// Shows a report descriptor of a given device
-    Report {
-        #[arg(value_name = "VID:PID", long, short)]
-        device: String,
-        #[arg(value_enum, long, short)]
-        format: Option<ReportFormat>,
+    #[cfg(feature = "usb")]
+    #[command(flatten)]
+    Device(devices::DeviceCommands),
+    /// Shows a report descriptor from a file: binary, a C array, a hex dump or xxd output
+    Decode {
+        /// `-` reads the standard input
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        #[arg(value_enum, long, short, default_value = "parsed")]
+        format: ReportFormat,
+        /// Collapse parsed collections nested deeper than this
+        #[arg(value_name = "LEVELS", long)]
+        depth: Option<usize>,
+        /// Only show the parsed top level collection with this index (from 0) or usage
+        #[arg(value_name = "N|USAGE", long)]
+        collection: Option<CollectionSelector>,
+        /// Don't page long output
+        #[arg(long)]
+        no_pager: bool,
+        /// How broken descriptors are treated: strict, spec or permissive (decodes what it can)
+        #[arg(value_name = "PROFILE", long, default_value = "permissive")]
+        strictness: Strictness,
     },
-    /// Logs input reports from the device
-    Log {
-        #[arg(value_name = "VID:PID", long, short)]
-        device: String,
+    /// Re-encodes a report descriptor in as few bytes as possible
+    Optimize {
+        /// Binary, C array, hex dump or xxd output, `-` reads the standard input
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        /// Write the binary descriptor to FILE instead of showing a hex dump
+        #[arg(value_name = "FILE", long, short)]
+        output: Option<PathBuf>,
+        /// Sort the local items before each main item by tag, where it keeps the meaning
+        #[arg(long)]
+        sort_locals: bool,
+    },
+    /// Checks a report descriptor for common mistakes, failing if it finds any
+    Verify {
+        /// Binary, C array, hex dump or xxd output, `-` reads the standard input
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        /// Fail on warnings too, e.g. a usage reused for more values than it was given for
+        #[arg(long)]
+        strict: bool,
+        /// Decode the descriptor as strict (reserved items fail too), spec or permissive
+        #[arg(value_name = "PROFILE", long, default_value = "spec")]
+        strictness: Strictness,
+    },
+    /// Writes variations of a report descriptor, each changing one item (dropped, duplicated,
+    /// flags flipped, sizes and extents at edge values), for negative testing of host stacks
+    Mutate {
+        /// Binary, C array, hex dump or xxd output, `-` reads the standard input
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        /// Directory for the binary descriptors, created if missing
+        #[arg(value_name = "DIR", long)]
+        out: PathBuf,
+    },
+    /// Summarises all capture files in a directory, or analyses a single capture
+    Analyze {
+        #[arg(value_name = "DIR|CAPTURE")]
+        path: PathBuf,
+        /// Show a heatmap of how often every bit of the input reports changes
+        #[arg(long)]
+        bits: bool,
+        /// Guess the fields of the input reports, printing the layout as TOML
+        #[arg(long, conflicts_with = "bits")]
+        infer: bool,
+        /// Plot the input report rate over time and the distribution of the field values
+        #[arg(long, conflicts_with_all = ["bits", "infer"])]
+        plot: bool,
+        /// Write the summary with interactive charts of a single capture to an HTML page
+        #[arg(value_name = "FILE", long, conflicts_with_all = ["bits", "infer"])]
+        report: Option<PathBuf>,
+        /// Extract the values of one input field over time, named by its path, e.g. X,
+        /// "Generic Desktop/X", "Joystick/Pointer/X" or "report#3/Button 5"
+        #[arg(value_name = "USAGE", long, conflicts_with_all = ["bits", "infer", "plot", "report"])]
+        field: Option<String>,
+        /// How to show the field's values: CSV with an empty value where reports lack the
+        /// field, or a plot
+        #[arg(value_enum, long, default_value = "csv", requires = "field")]
+        format: TimelineFormat,
+    },
+    /// Estimates the highest input report rates and bus bandwidth the endpoint allows, comparing
+    /// them with the rates measured in a capture
+    Bandwidth {
+        /// A report descriptor (binary, C array, hex dump or xxd output) or a capture (.hbc)
+        #[arg(value_name = "DESCRIPTOR|CAPTURE")]
+        input: PathBuf,
+        /// bInterval of the interrupt IN endpoint
+        #[arg(value_name = "N", long)]
+        interval: u8,
+        #[arg(value_enum, long, default_value = "full")]
+        speed: Speed,
+        /// wMaxPacketSize of the endpoint [default: the largest the speed allows]
+        #[arg(value_name = "BYTES", long)]
+        max_packet: Option<usize>,
+        /// Interface of the capture, defaults to the first one with input reports
         #[arg(value_name = "INTERFACE_NUMBER", long, short)]
-        interface: String,
-        #[arg(value_enum, long, short)]
-        format: Option<LogFormat>,
+        interface: Option<u8>,
     },
+    /// Generates a report descriptor from guessed fields (analyze --infer) or a capture
+    Synthesize {
+        #[arg(value_name = "LAYOUT.toml|CAPTURE")]
+        input: PathBuf,
+        /// Defaults to the first interface in the layout
+        #[arg(value_name = "INTERFACE_NUMBER", long, short)]
+        interface: Option<u8>,
+        /// Write the binary descriptor to FILE
+        #[arg(value_name = "FILE", long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Converts captured data between formats, e.g. a capture to pcapng for Wireshark or a
+    /// frame log (.hbf) to a capture
+    Convert {
+        /// Capture, frame log (.hbf), JSON lines or hid-recorder recording
+        #[arg(value_name = "FILE")]
+        input: PathBuf,
+        #[arg(value_enum, long)]
+        to: ConvertFormat,
+        /// Defaults to the standard output
+        #[arg(value_name = "FILE", long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Plays a script setting the fields of the input reports of a report descriptor
+    Simulate {
+        /// Binary, C array, hex dump or xxd output
+        #[arg(value_name = "FILE", long)]
+        descriptor: PathBuf,
+        /// JSON steps setting fields by path, e.g. {"steps": [{"set": {"Button/1": 1}}]}
+        #[arg(value_name = "SCRIPT.json", long)]
+        script: PathBuf,
+        /// Write the reports to a capture file instead of printing them
+        #[arg(value_name = "FILE", long, short)]
+        output: Option<PathBuf>,
+        /// Create a virtual device playing the reports in real time (Linux, needs /dev/uhid)
+        #[arg(long, conflicts_with = "output")]
+        uhid: bool,
+    },
+    /// Generates a udev rule giving the current user access to the device (Linux)
+    SetupPermissions {
+        #[arg(value_name = "VID:PID|ALIAS", long, short)]
+        device: String,
+        /// Grant access to a group instead of the logged in user
+        #[arg(value_name = "GROUP", long)]
+        group: Option<String>,
+        /// Install the rule into /etc/udev/rules.d and reload udev (needs root)
+        #[arg(long)]
+        install: bool,
+    },
+    /// Configuration file and environment
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ConfigCommands {
+    /// Prints the effective configuration: the defaults with where each comes from, and the
+    /// device aliases
+    Show,
 }
 
 #[derive(ValueEnum, Debug, Clone, PartialEq, Eq)]
@@ -49,272 +338,605 @@ enum ReportFormat {
     Parsed,
 }
 
-#[derive(ValueEnum, Debug, Clone, PartialEq, Eq)]
-enum LogFormat {
-    Raw,
-    Compact,
-    Full,
+fn main() -> ExitCode {
+    let args = Cli::parse();
+    output::set_quiet(args.quiet);
+    #[cfg(feature = "usb")]
+    {
+        trace::set_enabled(args.trace_usb);
+        cache::set_mode(match (args.no_cache, args.refresh) {
+            (true, _) => cache::CacheMode::Off,
+            (_, true) => cache::CacheMode::Refresh,
+            _ => cache::CacheMode::Use,
+        });
+    }
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            Exit::of(&err).code()
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+fn run(args: Cli) -> Result<()> {
     let cmd = args.command;
+    let config = Config::load(args.config.as_deref())?;
+
+    let color = config::flag_or(
+        args.color,
+        config.defaults.color.as_deref(),
+        ColorChoice::Auto,
+    )?;
+    let theme = config::flag_or(args.theme, config.defaults.theme.as_deref(), Theme::Dark)?;
+    let renderer = Renderer::new(color, theme);
+
+    match cmd {
+        #[cfg(feature = "usb")]
+        Commands::Device(cmd) => devices::run(cmd, &config, &renderer),
+        Commands::Decode {
+            input,
+            format,
+            depth,
+            collection,
+            no_pager,
+            strictness,
+        } => {
+            let options = ReportOptions {
+                format,
+                depth,
+                collection,
+                pager: !no_pager,
+                lint: false,
+            };
+
+            cmd_decode(&input, strictness, &options, &renderer)
+        }
+        Commands::Optimize {
+            input,
+            output,
+            sort_locals,
+        } => cmd_optimize(&input, output.as_deref(), sort_locals),
+        Commands::Verify {
+            input,
+            strict,
+            strictness,
+        } => cmd_verify(&input, strict, strictness, &renderer),
+        Commands::Mutate { input, out } => cmd_mutate(&input, &out),
+        Commands::Analyze {
+            path,
+            bits,
+            infer,
+            plot,
+            report,
+            field,
+            format,
+        } => match field {
+            Some(field) => cmd_timeline(&path, &field, format),
+            None => cmd_analyze(&path, bits, infer, plot, report.as_deref()),
+        },
+        Commands::Bandwidth {
+            input,
+            interval,
+            speed,
+            max_packet,
+            interface,
+        } => cmd_bandwidth(
+            &input,
+            Endpoint::new(speed, interval, max_packet)?,
+            interface,
+        ),
+        Commands::Synthesize {
+            input,
+            interface,
+            output,
+        } => cmd_synthesize(&input, interface, output.as_deref()),
+        Commands::Convert { input, to, output } => cmd_convert(&input, to, output.as_deref()),
+        Commands::Simulate {
+            descriptor,
+            script,
+            output,
+            uhid,
+        } => cmd_simulate(&descriptor, &script, output.as_deref(), uhid),
+        Commands::SetupPermissions {
+            device,
+            group,
+            install,
+        } => {
+            let device = config.device(&device)?;
+
+            cmd_setup_permissions(&device, group.as_deref(), install)
+        }
+        Commands::Config {
+            command: ConfigCommands::Show,
+        } => {
+            outln!("{}", config.show());
+
+            Ok(())
+        }
+    }
+}
+
+fn cmd_analyze(
+    path: &Path,
+    bits: bool,
+    infer: bool,
+    plot: bool,
+    report: Option<&Path>,
+) -> Result<()> {
+    if infer {
+        let layout = infer::infer(&analyze::read_capture(path)?);
+
+        outln!(
+            "# Field guesses for {}, check them before relying on them",
+            path.display()
+        );
+        out!("{}", toml::to_string(&layout)?);
 
-    if let Commands::List = cmd {
-        return cmd_list();
+        return Ok(());
     }
 
-    let hid_devices = hid_devices()?;
+    if bits {
+        let capture = analyze::read_capture(path)?;
 
-    if let Commands::Report { device, format } = cmd {
-        let format = format.unwrap_or(ReportFormat::Items);
-        let (vid, pid) = parse_vid_pid(&device)?;
+        for ((interface, report_id), stats) in bits::bit_stats(&capture) {
+            outln!("{}", bits::heatmap(interface, report_id, &stats));
+        }
+        outln!("{}", bits::LEGEND);
+
+        return Ok(());
+    }
 
-        let usb_device = find_device(&hid_devices, vid, pid)
-            .ok_or_else(|| anyhow!("Could not find a HID device with vid {vid} pid {pid}"))?;
-        let report_descriptors = get_report_descriptors(usb_device)?;
+    if let Some(report) = report {
+        if !path.is_file() {
+            return Err(anyhow!(
+                "HTML reports are written for a single capture file"
+            ));
+        }
+        let capture = analyze::read_capture(path)?;
 
-        return cmd_report(&report_descriptors, format);
+        let title = format!("Capture {}", path.display());
+        write_html(report, &analyze::html_report(&capture, &title))?;
+        if !plot {
+            return Ok(());
+        }
     }
 
-    if let Commands::Log {
-        device,
-        interface,
-        format,
-    } = cmd
-    {
-        let format = format.unwrap_or(LogFormat::Compact);
-        let (vid, pid) = parse_vid_pid(&device)?;
-        let interface: u8 =
-            str::parse(&interface).map_err(|_| anyhow!("Interface must be a number"))?;
-
-        let usb_device = find_device(&hid_devices, vid, pid)
-            .ok_or_else(|| anyhow!("Could not find a HID device with vid {vid} pid {pid}"))?;
-        let report_descriptors = get_report_descriptors(usb_device)?;
-        let parser = report_descriptors
-            .get(&interface)
-            .ok_or_else(|| anyhow!("Cannot find interface #{}", interface))?
-            .first()
-            .ok_or_else(|| anyhow!("No report descriptors for interface #{}", interface))?
-            .decode();
+    if plot {
+        if !path.is_file() {
+            return Err(anyhow!("Plots are drawn for a single capture file"));
+        }
+        let capture = analyze::read_capture(path)?;
+
+        out!("{}", analyze::CaptureSummary::new(&capture));
+        out!("{}", regimes::format(&regimes::by_interface(&capture)));
+        outln!();
+        out!("{}", analyze::plots(&capture));
+
+        return Ok(());
+    }
 
-        cmd_log(vid, pid, &parser, format)?;
+    if path.is_file() {
+        let capture = analyze::read_capture(path)?;
+
+        out!("{}", analyze::CaptureSummary::new(&capture));
+        out!("{}", regimes::format(&regimes::by_interface(&capture)));
+
+        return Ok(());
+    }
+
+    let summaries = analyze::analyze_dir(path)?;
+    let mut aggregate = Aggregate::default();
+
+    for (path, summary) in &summaries {
+        match summary {
+            Ok(summary) => out!("{}: {}", path.display(), summary),
+            Err(err) => eprintln!("{}: {:#}", path.display(), err),
+        }
+
+        aggregate.add(summary);
     }
 
+    outln!();
+    out!("{}", aggregate);
+
     Ok(())
 }
 
-fn cmd_list() -> Result<()> {
-    // FIXME do this with rusb instead
-    for device in hid_devices()?.iter() {
-        let descriptor = device.device_descriptor()?;
+fn cmd_timeline(path: &Path, field: &str, format: TimelineFormat) -> Result<()> {
+    if !path.is_file() {
+        return Err(anyhow!("Fields are extracted from a single capture file"));
+    }
+    let timeline = timeline::field_timeline(&analyze::read_capture(path)?, field)?;
 
-        let handle = device.open()?;
+    match format {
+        TimelineFormat::Csv => out!("{}", timeline.csv()),
+        TimelineFormat::Plot => out!("{}", timeline.plot()),
+    }
 
-        let languages = handle.read_languages(Duration::from_millis(100))?;
+    Ok(())
+}
 
-        if languages.is_empty() {
-            println!(
-                "[{:04X}:{:04X}]: <device does not support text descriptions>",
-                descriptor.vendor_id(),
-                descriptor.product_id(),
-            );
-            continue;
-        }
+fn cmd_bandwidth(input: &Path, endpoint: Endpoint, interface: Option<u8>) -> Result<()> {
+    if input.extension().is_none_or(|e| e != "hbc") {
+        let descriptor = dump::read_descriptor(input)?;
+        out!("{}", bandwidth::estimate(&descriptor.decode(), endpoint));
 
-        let language = languages
-            .first()
-            .expect("languages should not be empty at this point");
-
-        let vendor_string =
-            handle.read_manufacturer_string(*language, &descriptor, Duration::from_millis(100))?;
-        let product_string =
-            handle.read_product_string(*language, &descriptor, Duration::from_millis(100))?;
-
-        println!(
-            "[{:04X}:{:04X}]: \"{}: {}\"",
-            descriptor.vendor_id(),
-            descriptor.product_id(),
-            vendor_string,
-            product_string,
-        );
+        return Ok(());
     }
 
+    let capture = analyze::read_capture(input)?;
+    let interface = match interface {
+        Some(interface) => interface,
+        None => capture
+            .transfers
+            .iter()
+            .find(|transfer| transfer.direction == Direction::In)
+            .map(|transfer| transfer.interface)
+            .ok_or_else(|| anyhow!("There are no input reports in the capture"))?,
+    };
+    let parser = capture.descriptors.parser(interface).ok_or_else(|| {
+        anyhow!(
+            "The capture has no report descriptor of interface #{}",
+            interface
+        )
+    })?;
+
+    let mut estimate = bandwidth::estimate(parser, endpoint);
+    estimate.measure(&capture, interface);
+    out!("{}", estimate);
+
     Ok(())
 }
 
-fn cmd_report(descriptors: &HashMap<u8, Vec<ReportDescriptor>>, fmt: ReportFormat) -> Result<()> {
-    for (interface_number, report_descriptors) in descriptors {
-        println!("Interface #{}", interface_number);
-
-        for descriptor in report_descriptors {
-            // TODO better formats
-            match fmt {
-                ReportFormat::Raw => println!("{:?}", descriptor.bytes),
-                ReportFormat::Items => {
-                    println!("{:?}", descriptor.basic_items().collect::<Vec<_>>())
-                }
-                ReportFormat::Parsed => println!("{:?}", descriptor.decode()),
-            }
-        }
+fn cmd_synthesize(input: &Path, interface: Option<u8>, output: Option<&Path>) -> Result<()> {
+    let layout: Layout = if input.extension().is_some_and(|e| e == "toml") {
+        let text = fs::read_to_string(input)
+            .with_context(|| format!("Cannot read {}", input.display()))?;
+
+        toml::from_str(&text).with_context(|| format!("Invalid layout in {}", input.display()))?
+    } else {
+        infer::infer(&analyze::read_capture(input)?)
+    };
+
+    let interface = match interface {
+        Some(interface) => interface,
+        None => layout
+            .reports
+            .first()
+            .map(|report| report.interface)
+            .ok_or_else(|| anyhow!("There are no input reports to describe"))?,
+    };
+    let reports = layout
+        .reports
+        .iter()
+        .filter(|report| report.interface == interface)
+        .collect::<Vec<_>>();
+    if reports.is_empty() {
+        return Err(anyhow!(
+            "There are no input reports of interface #{}",
+            interface
+        ));
+    }
+
+    let descriptor = synthesize::descriptor(&reports);
+    out!("{}", descriptor.decode());
+
+    if let Some(path) = output {
+        fs::write(path, &descriptor.bytes)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
     }
 
     Ok(())
 }
 
-fn cmd_log(vid: u16, pid: u16, parser: &Parser, fmt: LogFormat) -> Result<()> {
-    let api = HidApi::new()?;
-    let hid_device = api.open(vid, pid)?;
-
-    let mut buf = [0u8; 64];
-    let mut last = Instant::now();
-
-    loop {
-        let n = hid_device.read(&mut buf)?;
-
-        let elapsed = last.elapsed().as_millis();
-        let bytes = &buf[0..n];
-
-        // TODO better formats
-        match fmt {
-            LogFormat::Raw => {
-                println!("[+{:06} ms]: {:02x?} ", elapsed, bytes);
-            }
-            LogFormat::Compact => {
-                println!(
-                    "[+{:06} ms]: {:02x?} = {}",
-                    elapsed,
-                    bytes,
-                    print_report(&parser.parse_input(&buf[0..n]))
-                );
-            }
-            LogFormat::Full => {
-                println!(
-                    "[+{:06} ms]: {:02x?} = {:?}",
-                    elapsed,
-                    bytes,
-                    &parser.parse_input(&buf[0..n])
-                );
-            }
-        }
+fn cmd_convert(input: &Path, format: ConvertFormat, output: Option<&Path>) -> Result<()> {
+    let capture = convert::read_file(input)?;
+
+    match output {
+        Some(path) => {
+            let file =
+                File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+            let mut writer = BufWriter::new(file);
 
-        last = Instant::now();
+            convert::convert(&capture, format, &mut writer)?;
+            writer.flush()?;
+        }
+        None => convert::convert(&capture, format, &mut io::stdout().lock())?,
     }
+
+    Ok(())
 }
 
-fn parse_vid_pid(vidpid: &str) -> Result<(u16, u16)> {
-    let parts: Vec<u16> = vidpid
-        .split(':')
-        .map(|part| {
-            u16::from_str_radix(part, 16).map_err(|_| {
-                anyhow!("Device must be two 4-digit hexadecimal numbers separated by ':', e.g.  ")
+fn cmd_simulate(descriptor: &Path, script: &Path, output: Option<&Path>, uhid: bool) -> Result<()> {
+    let descriptor = dump::read_descriptor(descriptor)?;
+    let text =
+        fs::read_to_string(script).with_context(|| format!("Cannot read {}", script.display()))?;
+    let script = simulate::parse_script(&text)?;
+    let (vid, pid) = match &script.device {
+        Some(device) => config::parse_vid_pid(device)?,
+        None => (0, 0),
+    };
+    let name = script
+        .name
+        .clone()
+        .unwrap_or_else(|| "hid-bench simulated device".to_string());
+
+    let reports = simulate::play(&descriptor.decode(), &script)?;
+
+    if uhid {
+        return simulate_uhid(&name, vid, pid, &descriptor, &reports);
+    }
+
+    let Some(path) = output else {
+        for report in &reports {
+            let bytes: Vec<_> = report.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            outln!(
+                "[+{:06} ms]: {}",
+                report.timestamp.as_millis(),
+                bytes.join(" ")
+            );
+        }
+
+        return Ok(());
+    };
+
+    let capture = Capture {
+        device: DeviceMetadata {
+            vendor_id: vid,
+            product_id: pid,
+            product: Some(name),
+            ..Default::default()
+        },
+        descriptors: [(0, descriptor)].into_iter().collect(),
+        transfers: reports
+            .into_iter()
+            .map(|report| Transfer {
+                timestamp: report.timestamp,
+                interface: 0,
+                direction: Direction::In,
+                bytes: report.bytes,
             })
-        })
-        .collect::<Result<_>>()?;
+            .collect(),
+        markers: vec![],
+    };
+    let file = File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+    capture.write(BufWriter::new(file))?;
+    note!(
+        "Wrote {} input reports to {}",
+        capture.transfers.len(),
+        path.display()
+    );
 
-    Ok((parts[0], parts[1]))
+    Ok(())
 }
 
-fn find_device(
-    devices: &[Device<GlobalContext>],
+#[cfg(target_os = "linux")]
+fn simulate_uhid(
+    name: &str,
     vid: u16,
     pid: u16,
-) -> Option<&Device<GlobalContext>> {
-    devices.iter().find(|d| match d.device_descriptor() {
-        Ok(desc) => desc.vendor_id() == vid && desc.product_id() == pid,
-        _ => false,
-    })
+    descriptor: &ReportDescriptor,
+    reports: &[simulate::Timed],
+) -> Result<()> {
+    let mut device = uhid::UhidDevice::create(name, vid, pid, &descriptor.bytes)?;
+    note!("Created {}, playing {} input reports", name, reports.len());
+
+    let start = std::time::Instant::now();
+    for report in reports {
+        if let Some(wait) = report.timestamp.checked_sub(start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        device.input(&report.bytes)?;
+    }
+
+    Ok(())
 }
 
-fn print_report(collection: &Collection<Vec<Input>>) -> String {
-    format!(
-        "[{}]",
-        collection
-            .items
-            .iter()
-            .filter_map(|item| match item {
-                CollectionItem::Collection(c) => Some(print_report(c)),
-                CollectionItem::Item(inputs) => {
-                    if inputs.is_empty() {
-                        return None;
-                    }
-
-                    Some(
-                        inputs
-                            .iter()
-                            .map(|i| match i.value {
-                                InputValue::Bool(v) => format!("{}", v),
-                                InputValue::UInt(v) => format!("{}", v),
-                                InputValue::Int(v) => format!("{}", v),
-                                InputValue::None => "None".to_string(),
-                            })
-                            .collect::<Vec<_>>()
-                            .join(","),
-                    )
-                }
-            })
-            .collect::<Vec<_>>()
-            .join(", ")
+#[cfg(not(target_os = "linux"))]
+fn simulate_uhid(
+    _name: &str,
+    _vid: u16,
+    _pid: u16,
+    _descriptor: &ReportDescriptor,
+    _reports: &[simulate::Timed],
+) -> Result<()> {
+    Err(anyhow!("Virtual devices need uhid, which only Linux has"))
+}
+
+fn cmd_setup_permissions(device: &DeviceSpec, group: Option<&str>, install: bool) -> Result<()> {
+    let rule = udev::rule(device.vid, device.pid, group);
+
+    if !install {
+        outln!("# {}", udev::rule_path(device.vid, device.pid).display());
+        out!("{}", rule);
+
+        return Ok(());
+    }
+
+    let path = udev::install(device.vid, device.pid, &rule)?;
+    outln!(
+        "Installed {}, replug the device if it is still not accessible",
+        path.display()
+    );
+
+    Ok(())
+}
+
+struct ReportOptions {
+    format: ReportFormat,
+    depth: Option<usize>,
+    collection: Option<CollectionSelector>,
+    pager: bool,
+    lint: bool, // follow each descriptor with its mistakes
+}
+
+fn cmd_decode(
+    input: &Path,
+    strictness: Strictness,
+    options: &ReportOptions,
+    renderer: &Renderer,
+) -> Result<()> {
+    let descriptor = dump::read_descriptor(input)?;
+    // shown permissively either way, the other profiles only stop at problems
+    descriptor
+        .decode_with(strictness)
+        .map_err(|e| Exit::DescriptorMismatch.error(format!("Cannot decode: {}", e)))?;
+
+    pager::page(
+        &format_descriptor(&descriptor, options, renderer),
+        options.pager,
     )
 }
 
-fn hid_devices() -> Result<Vec<Device<GlobalContext>>> {
-    let mut devices = vec![];
+fn cmd_optimize(input: &Path, output: Option<&Path>, sort_locals: bool) -> Result<()> {
+    let descriptor = dump::read_descriptor(input)?;
+    let optimized = descriptor.optimize(sort_locals);
 
-    for device in rusb::devices()?.iter() {
-        if !is_hid_device(&device)? {
-            continue;
-        }
+    // the parsed layout covers everything the items mean
+    if optimized.decode().to_string() != descriptor.decode().to_string() {
+        return Err(Exit::DescriptorMismatch
+            .error("Optimizing would change the meaning of the descriptor, please report this"));
+    }
 
-        devices.push(device);
+    let (before, after) = (descriptor.bytes.len(), optimized.bytes.len());
+    // nothing saved where the optimized descriptor isn't shorter
+    let saved = before.saturating_sub(after);
+    note!(
+        "Optimized {} to {} bytes, saved {} ({:.1}%)",
+        before,
+        after,
+        saved,
+        saved as f64 * 100.0 / before.max(1) as f64
+    );
+
+    match output {
+        Some(path) => fs::write(path, &optimized.bytes)
+            .with_context(|| format!("Cannot write {}", path.display()))?,
+        None => out!("{}", dump::hex_dump(&optimized.bytes)),
     }
 
-    Ok(devices)
+    Ok(())
 }
 
-fn is_hid_device(usb_device: &Device<GlobalContext>) -> Result<bool> {
-    let usb_device_descriptor = usb_device.device_descriptor()?;
+fn cmd_verify(
+    input: &Path,
+    strict: bool,
+    strictness: Strictness,
+    renderer: &Renderer,
+) -> Result<()> {
+    let descriptor = dump::read_descriptor(input)?;
+    if let Err(e) = descriptor.decode_with(strictness) {
+        outln!("error: {}", renderer.warning(&e.to_string()));
+        return Err(Exit::DescriptorMismatch.error(format!("Cannot decode as {}", strictness)));
+    }
+
+    let lints = lint::lint(&descriptor);
+    for lint in &lints {
+        let level = if lint.is_warning() {
+            "warning"
+        } else {
+            "error"
+        };
+        outln!("{}: {}", level, renderer.warning(&lint.to_string()));
+    }
+
+    let problems = lints
+        .iter()
+        .filter(|lint| strict || !lint.is_warning())
+        .count();
+    match (problems, lints.len()) {
+        (0, 0) => note!("No problems found"),
+        (0, warnings) => note!("No problems found, warnings: {}", warnings),
+        (problems, _) => {
+            return Err(Exit::DescriptorMismatch.error(format!("Found problems: {}", problems)))
+        }
+    }
 
-    for cidx in 0..usb_device_descriptor.num_configurations() {
-        let config_descriptor = usb_device.config_descriptor(cidx)?;
+    Ok(())
+}
 
-        for interface in config_descriptor.interfaces() {
-            for interface_descriptor in interface.descriptors() {
-                if interface_descriptor.class_code() == 3 {
-                    return Ok(true);
-                }
-            }
+// Files are numbered in the order of the items changed and named by the change, e.g.
+// 0007-report-size-1-at-byte-16-set-to-0.bin
+fn cmd_mutate(input: &Path, out: &Path) -> Result<()> {
+    let descriptor = dump::read_descriptor(input)?;
+    let mutations = descriptor.mutations();
+    fs::create_dir_all(out).with_context(|| format!("Cannot create {}", out.display()))?;
+
+    let mut invalid = 0;
+    for (n, mutation) in mutations.iter().enumerate() {
+        let name: String = mutation
+            .description
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let path = out.join(format!("{:04}-{}.bin", n + 1, name));
+        fs::write(&path, &mutation.descriptor.bytes)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+
+        let valid = mutation.descriptor.decode_with(Strictness::Spec).is_ok();
+        if !valid {
+            invalid += 1;
         }
+        outln!(
+            "{:04} {}{}",
+            n + 1,
+            mutation.description,
+            if valid { "" } else { " (invalid)" }
+        );
     }
+    note!(
+        "Wrote {} mutations to {}, {} of them invalid according to the spec",
+        mutations.len(),
+        out.display(),
+        invalid
+    );
 
-    Ok(false)
+    Ok(())
 }
 
-fn get_report_descriptors(
-    usb_device: &Device<GlobalContext>,
-) -> Result<HashMap<u8, Vec<ReportDescriptor>>> {
-    let mut descriptors = HashMap::new();
-
-    let usb_device_descriptor = usb_device.device_descriptor()?;
-    let device_handle = usb_device.open()?;
-
-    for cidx in 0..usb_device_descriptor.num_configurations() {
-        let config_descriptor = usb_device.config_descriptor(cidx)?;
-
-        for interface in config_descriptor.interfaces() {
-            for interface_descriptor in interface.descriptors() {
-                if interface_descriptor.class_code() == 3 {
-                    let interface_num = interface_descriptor.interface_number();
-                    let hid_descriptor =
-                        HidDescriptor::from_interface_descriptor(&interface_descriptor);
-                    let report_descriptors =
-                        hid_descriptor.report_descriptors(&device_handle).collect();
-
-                    descriptors.insert(interface_num, report_descriptors);
-                }
-            }
+fn format_descriptor(
+    descriptor: &ReportDescriptor,
+    options: &ReportOptions,
+    renderer: &Renderer,
+) -> String {
+    // TODO better formats
+    let mut output = match options.format {
+        ReportFormat::Raw => format!("{:?}\n", descriptor.bytes),
+        ReportFormat::Items => format!("{:?}\n", descriptor.basic_items().collect::<Vec<_>>()),
+        ReportFormat::Parsed => {
+            let parser = descriptor.decode();
+            // descriptors without the selected collection show nothing
+            let selected = options
+                .collection
+                .as_ref()
+                .map(|selector| selector.find(&parser).ok());
+
+            parser
+                .collections()
+                .iter()
+                .enumerate()
+                .filter(|(n, _)| selected.is_none_or(|index| index == Some(*n)))
+                .map(|(_, collection)| renderer.layout(collection, options.depth))
+                .collect()
+        }
+    };
+
+    if options.lint {
+        for lint in lint::lint(descriptor) {
+            output.push_str(&format!("{}\n", renderer.warning(&lint.to_string())));
         }
     }
 
-    Ok(descriptors)
+    output
+}
+
+fn write_html(path: &Path, report: &HtmlReport) -> Result<()> {
+    fs::write(path, report.render()).with_context(|| format!("Cannot write {}", path.display()))?;
+    note!("Report written to {}", path.display());
+
+    Ok(())
 }
@@ -0,0 +1,189 @@
+// Mock devices for testing the device commands without hardware
+//
+// A mock device serves the report descriptors of a capture file and replays its input reports
+// of one interface in order, as fast as they are read, and its feature reports read (FeatureIn)
+// as answers to Get_Report. Reports written to it are kept for checking. Once the inputs run
+// out it behaves like an unplugged device: reads fail, which ends commands reading until
+// interrupted.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    env,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::{
+    capture::{Capture, DeviceMetadata, Direction, Transfer},
+    CollectionType, DescriptorBuilder, DeviceModel, FeatureItemData, InputItemData, OutputItemData,
+};
+
+use crate::backend::HidIo;
+
+pub struct MockDevice {
+    descriptors: DeviceModel,
+    inputs: RefCell<VecDeque<Vec<u8>>>,
+    features: RefCell<VecDeque<Vec<u8>>>,
+    written: RefCell<Vec<(Direction, Vec<u8>)>>,
+}
+
+impl MockDevice {
+    pub fn new(capture: &Capture, interface: u8) -> Self {
+        let transfers = |direction| {
+            capture
+                .transfers
+                .iter()
+                .filter(|t| t.interface == interface && t.direction == direction)
+                .map(|t| t.bytes.clone())
+                .collect()
+        };
+
+        Self {
+            descriptors: capture.descriptors.clone(),
+            inputs: RefCell::new(transfers(Direction::In)),
+            features: RefCell::new(transfers(Direction::FeatureIn)),
+            written: RefCell::new(vec![]),
+        }
+    }
+
+    // Mock of an interface of the device recorded in a capture file
+    pub fn open(path: &Path, interface: u8) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+
+        Ok(Self::new(&Capture::read(BufReader::new(file))?, interface))
+    }
+
+    pub fn descriptors(&self) -> &DeviceModel {
+        &self.descriptors
+    }
+
+    // Output (Out) and feature (FeatureOut) reports written so far, with their report ID byte
+    pub fn written(&self) -> Vec<(Direction, Vec<u8>)> {
+        self.written.borrow().clone()
+    }
+}
+
+impl HidIo for MockDevice {
+    fn read_timeout(&self, buf: &mut [u8], _timeout_ms: i32) -> Result<usize> {
+        let report = self
+            .inputs
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| anyhow!("The mock device has no more input reports"))?;
+
+        let n = report.len().min(buf.len());
+        buf[..n].copy_from_slice(&report[..n]);
+
+        Ok(n)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_timeout(buf, -1)
+    }
+
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        self.written
+            .borrow_mut()
+            .push((Direction::Out, report.to_vec()));
+
+        Ok(report.len())
+    }
+
+    fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        self.written
+            .borrow_mut()
+            .push((Direction::FeatureOut, report.to_vec()));
+
+        Ok(())
+    }
+
+    // The next feature report of the capture, whichever report ID is asked for
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        let report = self
+            .features
+            .borrow_mut()
+            .pop_front()
+            .ok_or_else(|| anyhow!("The mock device has no more feature reports"))?;
+
+        let n = report.len().min(buf.len());
+        buf[..n].copy_from_slice(&report[..n]);
+
+        Ok(n)
+    }
+
+    fn set_blocking_mode(&self, _blocking: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+// A mouse with a button and X in report 1, an LED output in report 2 and a resolution
+// feature in report 3, which moved right and clicked, and had its feature read
+pub fn mouse_capture(name: &str) -> PathBuf {
+    let descriptor = DescriptorBuilder::new()
+        .usage_page(0x01)
+        .usage(0x02)
+        .collection(CollectionType::Application)
+        .report_id(1)
+        .usage_page(0x09)
+        .usage(0x01)
+        .logical_minimum(0)
+        .logical_maximum(1)
+        .report_size(1)
+        .report_count(1)
+        .input(InputItemData { data: 0x02 })
+        .report_count(7)
+        .input(InputItemData { data: 0x01 })
+        .usage_page(0x01)
+        .usage(0x30)
+        .logical_minimum(-127)
+        .logical_maximum(127)
+        .report_size(8)
+        .report_count(1)
+        .input(InputItemData { data: 0x06 })
+        .report_id(2)
+        .usage_page(0x08)
+        .usage(0x01)
+        .logical_minimum(0)
+        .logical_maximum(1)
+        .output(OutputItemData { data: 0x02 })
+        .report_id(3)
+        .usage_page(0x01)
+        .usage(0x48)
+        .feature(FeatureItemData { data: 0x02 })
+        .end_collection()
+        .build();
+
+    let input = |ms, bytes: &[u8]| Transfer {
+        timestamp: Duration::from_millis(ms),
+        interface: 0,
+        direction: Direction::In,
+        bytes: bytes.to_vec(),
+    };
+    let capture = Capture {
+        device: DeviceMetadata {
+            vendor_id: 0x1234,
+            product_id: 0x5678,
+            ..Default::default()
+        },
+        descriptors: [(0, descriptor)].into_iter().collect(),
+        transfers: vec![
+            input(0, &[0x01, 0x00, 0x05]),
+            input(8, &[0x01, 0x01, 0x00]),
+            input(16, &[0x01, 0x00, 0xfb]),
+            Transfer {
+                direction: Direction::FeatureIn,
+                ..input(20, &[0x03, 0x01])
+            },
+        ],
+        markers: vec![],
+    };
+
+    let path = env::temp_dir().join(format!("hid-bench-mock-{}.hbcp", name));
+    capture.write(File::create(&path).unwrap()).unwrap();
+    path
+}
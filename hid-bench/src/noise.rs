@@ -0,0 +1,247 @@
+// Noise floor of the axes of an untouched device (device noise)
+//
+// A sensor or stick left alone should report the same position, in practice its values jitter
+// and wander. After the device settles every axis is recorded for a while, giving the standard
+// deviation and peak-to-peak spread of its values and their drift, the change of a line fitted
+// through them over the recording, in counts and in physical units for axes declaring a unit.
+// Relative axes (mouse movement) should report nothing at all: their noise is in the counts
+// they report, their drift is those counts added up, i.e. how far the cursor crept.
+//
+// Devices reporting only changes send nothing while they are quiet, the statistics are over
+// the reports received.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use hid_parser::{
+    unit::{self, Measurement, Unit},
+    usage, FlatInputs, InputValue, Parser, Usage,
+};
+
+use crate::{backend::HidIo, output::note, stats, velocity::is_axis};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AxisNoise {
+    pub usage: Usage,
+    pub relative: bool,
+    pub unit: Option<(Unit, f64)>, // and the physical value of a count
+    pub samples: Vec<(f64, f64)>,  // seconds into the recording, value
+}
+
+impl AxisNoise {
+    fn values(&self) -> Vec<f64> {
+        self.samples.iter().map(|(_, value)| *value).collect()
+    }
+
+    pub fn deviation(&self) -> f64 {
+        stats::deviation(&self.values())
+    }
+
+    pub fn peak_to_peak(&self) -> f64 {
+        let min = self.values().into_iter().fold(f64::INFINITY, f64::min);
+        let max = self.values().into_iter().fold(f64::NEG_INFINITY, f64::max);
+
+        (max - min).max(0.0)
+    }
+
+    // Counts the axis moved over the recording: the sum of relative counts, or the slope of the
+    // least squares line through absolute values times the recording's length
+    pub fn drift(&self) -> f64 {
+        if self.relative {
+            return self.values().iter().sum();
+        }
+
+        let length = match (self.samples.first(), self.samples.last()) {
+            (Some((first, _)), Some((last, _))) => last - first,
+            _ => 0.0,
+        };
+
+        stats::linear_fit(&self.samples).map_or(0.0, |(_, slope)| slope * length)
+    }
+
+    // e.g. `3 counts (0.0762 mm)`
+    fn counts(&self, counts: f64, signed: bool) -> String {
+        let text = match signed {
+            true => format!("{:+.2} counts", counts),
+            false => format!("{:.2} counts", counts),
+        };
+
+        match self.unit {
+            Some((unit, resolution)) => {
+                let physical = Measurement {
+                    value: counts * resolution,
+                    unit,
+                };
+                format!("{} ({})", text, physical)
+            }
+            None => text,
+        }
+    }
+}
+
+impl Display for AxisNoise {
+    // e.g. `X: σ 0.42 counts, peak-to-peak 3.00 counts, drift +1.50 counts, 3000 reports`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = usage::short_name(self.usage);
+        let relative = if self.relative { " (relative)" } else { "" };
+
+        write!(
+            f,
+            "{}{}: σ {}, peak-to-peak {}, drift {}, {} reports",
+            name,
+            relative,
+            self.counts(self.deviation(), false),
+            self.counts(self.peak_to_peak(), false),
+            self.counts(self.drift(), true),
+            self.samples.len()
+        )
+    }
+}
+
+// The values of every axis, by index in `Parser::reports` and of the value
+#[derive(Debug, Default)]
+pub struct NoiseFloor {
+    pub axes: BTreeMap<(usize, usize), AxisNoise>,
+}
+
+impl NoiseFloor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, parser: &Parser, inputs: &FlatInputs, seconds: f64) {
+        let reports = parser.reports();
+
+        for (index, values) in inputs.items() {
+            let report = reports[index];
+            let flags = report.report_type.flags();
+            if flags.array() {
+                continue;
+            }
+
+            for (i, input) in values.iter().enumerate() {
+                let value = match input.value {
+                    InputValue::UInt(v) => v as f64,
+                    InputValue::Int(v) => v as f64,
+                    _ => continue,
+                };
+                if !is_axis(input.usage) {
+                    continue;
+                }
+
+                self.axes
+                    .entry((index, i))
+                    .or_insert_with(|| AxisNoise {
+                        usage: input.usage,
+                        relative: flags.relative(),
+                        unit: Measurement::of(report, input.value)
+                            .zip(unit::resolution(report))
+                            .map(|(measurement, resolution)| (measurement.unit, resolution)),
+                        samples: vec![],
+                    })
+                    .samples
+                    .push((seconds, value));
+            }
+        }
+    }
+}
+
+// Records the axes for `duration` after leaving the device to settle
+pub fn run(
+    device: &dyn HidIo,
+    parser: &Parser,
+    settle: Duration,
+    duration: Duration,
+) -> Result<NoiseFloor> {
+    let mut buf = [0u8; 64];
+
+    note!(
+        "Don't touch the device, recording starts in {} s",
+        settle.as_secs_f64()
+    );
+    let settled = Instant::now() + settle;
+    while Instant::now() < settled {
+        device.read_timeout(&mut buf, 100)?;
+    }
+
+    note!("Recording for {} s", duration.as_secs_f64());
+    let start = Instant::now();
+    let mut noise = NoiseFloor::new();
+    let mut flat = FlatInputs::new();
+    while start.elapsed() < duration {
+        let n = device.read_timeout(&mut buf, 100)?;
+        if n > 0 {
+            parser.parse_input_flat(&buf[..n], &mut flat);
+            noise.add(parser, &flat, start.elapsed().as_secs_f64());
+        }
+    }
+
+    Ok(noise)
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, FlatInputs, InputItemData};
+
+    use super::NoiseFloor;
+
+    #[test]
+    fn measures_jitter_and_drift() {
+        // a relative X and an absolute Z counting 10 µm
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x04)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .usage(0x32)
+            .logical_minimum(0)
+            .logical_maximum(1000)
+            .physical_minimum(0)
+            .physical_maximum(100)
+            .unit_exponent(0x0e) // -2
+            .unit(0x11) // cm
+            .report_size(16)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+
+        let mut noise = NoiseFloor::new();
+        let mut flat = FlatInputs::new();
+        // Z creeps up by a count per second around 500, X jitters by a count
+        for (second, x, z) in [(0, 1i8, 499u16), (1, -1, 501), (2, 1, 501), (3, 0, 503)] {
+            let [z_low, z_high] = z.to_le_bytes();
+            parser.parse_input_flat(&[x as u8, z_low, z_high], &mut flat);
+            noise.add(&parser, &flat, second as f64);
+        }
+
+        let axes: Vec<_> = noise.axes.values().collect();
+        let (x, z) = (axes[0], axes[1]);
+        assert!(x.relative);
+        assert_eq!(x.drift(), 1.0);
+        assert_eq!(x.peak_to_peak(), 2.0);
+        assert_eq!(
+            x.to_string(),
+            "X (relative): σ 0.83 counts, peak-to-peak 2.00 counts, drift +1.00 counts, 4 reports"
+        );
+
+        assert!(!z.relative);
+        assert_eq!(z.peak_to_peak(), 4.0);
+        assert!((z.drift() - 3.6).abs() < 1e-9, "{}", z.drift());
+        assert_eq!(
+            z.to_string(),
+            "Z: σ 1.41 counts (0.0014 cm), peak-to-peak 4.00 counts (0.004 cm), \
+             drift +3.60 counts (0.0036 cm), 4 reports"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// Human output, left out with --quiet
+//
+// Results and progress messages go through these macros, errors don't: scripts running with
+// --quiet only see errors on stderr and the exit code.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+// `print!` to stdout
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if !$crate::output::quiet() {
+            print!($($arg)*);
+        }
+    };
+}
+
+// `println!` to stdout
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if !$crate::output::quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Progress messages, prompts and warnings to stderr
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if !$crate::output::quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {note, out, outln};
@@ -0,0 +1,62 @@
+// Paging of long output
+//
+// Output to a terminal goes through `$PAGER` (less by default). Like git, less is told to quit
+// by itself when everything fits on one screen and to pass the colors through.
+
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use anyhow::Result;
+
+use crate::output;
+
+const DEFAULT_PAGER: &str = "less";
+const DEFAULT_LESS: &str = "FRX";
+
+pub fn page(text: &str, enabled: bool) -> Result<()> {
+    if output::quiet() {
+        return Ok(());
+    }
+
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    let mut args = pager.split_whitespace();
+
+    let program = match args.next() {
+        Some(program) if enabled && program != "cat" && io::stdout().is_terminal() => program,
+        _ => return print(text),
+    };
+
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::piped());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", DEFAULT_LESS);
+    }
+
+    // without a working pager the output is printed directly
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(_) => return print(text),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(text.as_bytes()) {
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => (), // quit before the end
+            result => result?,
+        }
+    }
+    child.wait()?;
+
+    Ok(())
+}
+
+fn print(text: &str) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    match stdout.write_all(text.as_bytes()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
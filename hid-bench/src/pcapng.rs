@@ -0,0 +1,257 @@
+// Captures as pcapng, for Wireshark
+//
+// Reports are written as the URBs usbmon would have seen (LINKTYPE_USB_LINUX_MMAPPED, a 64 byte
+// header before the data), so Wireshark's USB and HID dissectors pick them up. Captures don't
+// record endpoint addresses: interface N is given endpoint N + 1, input reports arrive on the
+// interrupt IN endpoint, output reports leave on the interrupt OUT one, and feature reports go
+// over the control endpoint as Get_Report and Set_Report requests.
+
+use std::io::Write;
+
+use anyhow::Result;
+
+use hid_parser::capture::{Capture, Direction, Transfer};
+
+const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const LINKTYPE_USB_LINUX_MMAPPED: u16 = 220;
+const SNAPLEN: u32 = 0x0004_0000;
+
+// usbmon transfer types
+const XFER_INTERRUPT: u8 = 1;
+const XFER_CONTROL: u8 = 2;
+
+// Made up, the capture doesn't know where the device was
+const BUS: u16 = 1;
+const DEVICE: u8 = 1;
+
+// HID class requests (HID 1.11, section 7.2)
+const GET_REPORT: u8 = 0x01;
+const SET_REPORT: u8 = 0x09;
+const FEATURE_REPORT: u16 = 3;
+
+pub fn write(capture: &Capture, writer: &mut impl Write) -> Result<()> {
+    // section header: byte order magic, version 1.0, unknown section length
+    let mut body = vec![];
+    body.extend(BYTE_ORDER_MAGIC.to_le_bytes());
+    body.extend(1u16.to_le_bytes());
+    body.extend(0u16.to_le_bytes());
+    body.extend((-1i64).to_le_bytes());
+    block(writer, SECTION_HEADER, &body)?;
+
+    // one interface, timestamps in microseconds (the default resolution)
+    let mut body = vec![];
+    body.extend(LINKTYPE_USB_LINUX_MMAPPED.to_le_bytes());
+    body.extend(0u16.to_le_bytes());
+    body.extend(SNAPLEN.to_le_bytes());
+    block(writer, INTERFACE_DESCRIPTION, &body)?;
+
+    for (n, transfer) in capture.transfers.iter().enumerate() {
+        for urb in urbs(n as u64, transfer) {
+            let micros = transfer.timestamp.as_micros() as u64;
+
+            let mut body = vec![];
+            body.extend(0u32.to_le_bytes()); // interface ID
+            body.extend(((micros >> 32) as u32).to_le_bytes());
+            body.extend((micros as u32).to_le_bytes());
+            body.extend((urb.len() as u32).to_le_bytes());
+            body.extend((urb.len() as u32).to_le_bytes());
+            body.extend(&urb);
+            block(writer, ENHANCED_PACKET, &body)?;
+        }
+    }
+
+    Ok(())
+}
+
+// A block with its type and total length around the body, padded to 32 bits
+fn block(writer: &mut impl Write, block_type: u32, body: &[u8]) -> Result<()> {
+    let padding = (4 - body.len() % 4) % 4;
+    let length = (12 + body.len() + padding) as u32;
+
+    writer.write_all(&block_type.to_le_bytes())?;
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(body)?;
+    writer.write_all(&[0; 3][..padding])?;
+    writer.write_all(&length.to_le_bytes())?;
+
+    Ok(())
+}
+
+struct Urb<'a> {
+    event: u8, // b'S'ubmission or b'C'ompletion
+    transfer_type: u8,
+    endpoint: u8,
+    setup: Option<[u8; 8]>,
+    data: &'a [u8],
+    length: u32, // of the transfer, more than the data for submissions of IN transfers
+}
+
+// The submission and completion usbmon would show for the transfer, `id` pairs them up
+fn urbs(id: u64, transfer: &Transfer) -> Vec<Vec<u8>> {
+    let endpoint = transfer.interface.wrapping_add(1) & 0x0f;
+    let bytes = &transfer.bytes[..];
+    let report_id = bytes.first().copied().unwrap_or(0) as u16;
+    let setup = |request_type: u8, request: u8, length: usize| {
+        let value = FEATURE_REPORT << 8 | report_id;
+        let mut setup = [request_type, request, 0, 0, 0, 0, 0, 0];
+        setup[2..4].copy_from_slice(&value.to_le_bytes());
+        setup[4..6].copy_from_slice(&(transfer.interface as u16).to_le_bytes());
+        setup[6..8].copy_from_slice(&(length as u16).to_le_bytes());
+        setup
+    };
+    let length = bytes.len() as u32;
+
+    let urbs = match transfer.direction {
+        Direction::In => vec![Urb {
+            event: b'C',
+            transfer_type: XFER_INTERRUPT,
+            endpoint: 0x80 | endpoint,
+            setup: None,
+            data: bytes,
+            length,
+        }],
+        Direction::Out => vec![Urb {
+            event: b'S',
+            transfer_type: XFER_INTERRUPT,
+            endpoint,
+            setup: None,
+            data: bytes,
+            length,
+        }],
+        Direction::FeatureIn => vec![
+            Urb {
+                event: b'S',
+                transfer_type: XFER_CONTROL,
+                endpoint: 0x80,
+                setup: Some(setup(0xa1, GET_REPORT, bytes.len())),
+                data: &[],
+                length,
+            },
+            Urb {
+                event: b'C',
+                transfer_type: XFER_CONTROL,
+                endpoint: 0x80,
+                setup: None,
+                data: bytes,
+                length,
+            },
+        ],
+        Direction::FeatureOut => vec![
+            Urb {
+                event: b'S',
+                transfer_type: XFER_CONTROL,
+                endpoint: 0,
+                setup: Some(setup(0x21, SET_REPORT, bytes.len())),
+                data: bytes,
+                length,
+            },
+            Urb {
+                event: b'C',
+                transfer_type: XFER_CONTROL,
+                endpoint: 0,
+                setup: None,
+                data: &[],
+                length,
+            },
+        ],
+    };
+
+    urbs.iter()
+        .map(|urb| {
+            let micros = transfer.timestamp.as_micros();
+            let mut packet = vec![];
+            packet.extend(id.to_le_bytes());
+            packet.extend([urb.event, urb.transfer_type, urb.endpoint, DEVICE]);
+            packet.extend(BUS.to_le_bytes());
+            // 0 when the setup and data are there, a reason character when not
+            packet.push(match urb.setup {
+                Some(_) => 0,
+                None => b'-',
+            });
+            packet.push(match urb.data.is_empty() {
+                true => b'<',
+                false => 0,
+            });
+            packet.extend(((micros / 1_000_000) as i64).to_le_bytes());
+            packet.extend(((micros % 1_000_000) as i32).to_le_bytes());
+            packet.extend(0i32.to_le_bytes()); // status
+            packet.extend(urb.length.to_le_bytes());
+            packet.extend((urb.data.len() as u32).to_le_bytes());
+            packet.extend(urb.setup.unwrap_or_default());
+            packet.extend(1i32.to_le_bytes()); // interval
+            packet.extend(0i32.to_le_bytes()); // start frame
+            packet.extend(0u32.to_le_bytes()); // transfer flags
+            packet.extend(0u32.to_le_bytes()); // isochronous descriptors
+            packet.extend(urb.data);
+            packet
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::capture::{Capture, Direction, Transfer};
+
+    use super::write;
+
+    #[test]
+    fn writes_urbs_in_blocks() {
+        let capture = Capture {
+            transfers: vec![
+                Transfer {
+                    timestamp: Duration::from_micros(1_000_002),
+                    interface: 0,
+                    direction: Direction::In,
+                    bytes: vec![0x01, 0x02, 0x03],
+                },
+                Transfer {
+                    timestamp: Duration::from_micros(2_000_000),
+                    interface: 1,
+                    direction: Direction::FeatureOut,
+                    bytes: vec![0x05, 0xff],
+                },
+            ],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&capture, &mut output).unwrap();
+
+        // blocks by type and length
+        let mut blocks = vec![];
+        let mut rest = &output[..];
+        while !rest.is_empty() {
+            let block_type = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+            let length = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+            assert_eq!(rest[length - 4..length], rest[4..8]);
+            blocks.push((block_type, &rest[..length]));
+            rest = &rest[length..];
+        }
+        let types: Vec<_> = blocks.iter().map(|(block_type, _)| *block_type).collect();
+        assert_eq!(types, [0x0a0d0d0a, 1, 6, 6, 6]);
+
+        // the input report: 64 byte header and its data, padded
+        let (_, input) = blocks[2];
+        assert_eq!(u32::from_le_bytes(input[20..24].try_into().unwrap()), 67);
+        let urb = &input[28..];
+        assert_eq!(urb[8..12], [b'C', 1, 0x81, 1]);
+        assert_eq!(i64::from_le_bytes(urb[16..24].try_into().unwrap()), 1);
+        assert_eq!(i32::from_le_bytes(urb[24..28].try_into().unwrap()), 2);
+        assert_eq!(urb[64..67], [0x01, 0x02, 0x03]);
+
+        // the feature report goes out with a Set_Report(Feature 5) to interface 1
+        let (_, set_report) = blocks[3];
+        let urb = &set_report[28..];
+        assert_eq!(urb[8..12], [b'S', 2, 0x00, 1]);
+        assert_eq!(
+            urb[40..48],
+            [0x21, 0x09, 0x05, 0x03, 0x01, 0x00, 0x02, 0x00]
+        );
+        assert_eq!(urb[64..66], [0x05, 0xff]);
+    }
+}
@@ -0,0 +1,101 @@
+// Diagnostics for devices which cannot be opened
+//
+// Failing to open a device is nearly always a missing permission, and what to do about it
+// depends on the platform.
+
+use std::io::ErrorKind;
+
+use anyhow::Error;
+use hidapi::HidError;
+
+use crate::exit::Exit;
+
+pub fn is_access_error(err: &Error) -> bool {
+    if let Some(err) = err.downcast_ref::<rusb::Error>() {
+        return matches!(err, rusb::Error::Access);
+    }
+
+    match err.downcast_ref::<HidError>() {
+        Some(HidError::HidApiError { message }) => {
+            let message = message.to_lowercase();
+
+            message.contains("permission")
+                || message.contains("access")
+                || message.contains("not permitted")
+        }
+        Some(HidError::IoError { error }) => error.kind() == ErrorKind::PermissionDenied,
+        // hidapi doesn't say why opening a device failed on Linux
+        Some(HidError::HidApiErrorEmpty) => true,
+        _ => false,
+    }
+}
+
+pub fn guidance(vid: u16, pid: u16) -> String {
+    if cfg!(target_os = "linux") {
+        format!(
+            "Your user cannot access the device. Run `sudo hid-bench setup-permissions --device {:04x}:{:04x} --install` \
+             to add a udev rule for it, or run hid-bench as root.",
+            vid, pid
+        )
+    } else if cfg!(target_os = "macos") {
+        "macOS needs the Input Monitoring permission for the terminal running hid-bench, \
+         see System Settings > Privacy & Security > Input Monitoring."
+            .to_string()
+    } else if cfg!(windows) {
+        "Windows does not let applications open keyboards and mice it uses itself, \
+         other devices may need hid-bench to run as administrator."
+            .to_string()
+    } else {
+        "Check the permissions of the device nodes, or run hid-bench as root.".to_string()
+    }
+}
+
+// Adds the platform specific guidance to access errors
+pub fn explain(err: impl Into<Error>, vid: u16, pid: u16) -> Error {
+    let err = err.into();
+
+    if is_access_error(&err) {
+        let message = format!(
+            "Cannot open the device {:04x}:{:04x}. {}",
+            vid,
+            pid,
+            guidance(vid, pid)
+        );
+
+        return Exit::PermissionDenied.context(err, message);
+    }
+
+    err
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::anyhow;
+    use hidapi::HidError;
+
+    use super::{explain, is_access_error};
+
+    #[test]
+    fn recognises_access_errors() {
+        assert!(is_access_error(&rusb::Error::Access.into()));
+        assert!(!is_access_error(&rusb::Error::Timeout.into()));
+        assert!(is_access_error(
+            &HidError::HidApiError {
+                message: "Access is denied.".to_string()
+            }
+            .into()
+        ));
+        assert!(!is_access_error(&anyhow!("Cannot find interface #2")));
+    }
+
+    #[test]
+    fn explains_only_access_errors() {
+        let err = explain(rusb::Error::Access, 0x046d, 0xc08b);
+        assert!(err
+            .to_string()
+            .starts_with("Cannot open the device 046d:c08b."));
+
+        let err = explain(rusb::Error::Pipe, 0x046d, 0xc08b);
+        assert_eq!(err.to_string(), rusb::Error::Pipe.to_string());
+    }
+}
@@ -0,0 +1,249 @@
+// Input reports piped in on stdin, e.g. from usbmon or a hardware sniffer (log --from-stdin)
+//
+// Hex input has a report per line: bytes separated by spaces, colons or commas, or not at all,
+// with or without 0x. usbmon text lines (`... C Ii:1:005:1 0:8 8 = 01020304 05060708`) give
+// the data of completed interrupt IN transfers. Lines without a report (comments, submissions,
+// other transfers) are skipped. Raw input is the reports back to back, split by the input
+// report lengths of the descriptor: with report IDs each report is as long as its ID says.
+//
+// Reports are read on a thread as they arrive, so the log keeps up with a live capture and
+// ends when the input does.
+
+use std::{
+    fmt::Display,
+    io::{self, BufRead, Read},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use crate::{backend::Framing, backend::HidIo, output::note};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StdinFormat {
+    /// A report per line in hex, or usbmon text
+    Hex,
+    /// Binary reports back to back
+    Raw,
+}
+
+// Returned by reads once the input ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndOfInput;
+
+impl Display for EndOfInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The input ended")
+    }
+}
+
+impl std::error::Error for EndOfInput {}
+
+pub struct Piped {
+    reports: Receiver<Vec<u8>>,
+}
+
+impl Piped {
+    pub fn start(format: StdinFormat, framing: Framing) -> Self {
+        let (sender, reports) = mpsc::channel();
+
+        thread::spawn(move || {
+            let stdin = io::stdin().lock();
+            match format {
+                StdinFormat::Hex => {
+                    let mut skipped = false;
+                    for (n, line) in stdin.lines().map_while(Result::ok).enumerate() {
+                        let Some(report) = parse_line(&line) else {
+                            if !skipped && !line.trim().is_empty() {
+                                note!("Skipping lines without a report, e.g. line {}", n + 1);
+                                skipped = true;
+                            }
+                            continue;
+                        };
+                        if sender.send(report).is_err() {
+                            return;
+                        }
+                    }
+                }
+                StdinFormat::Raw => {
+                    let mut pending = vec![];
+                    let mut chunk = [0u8; 4096];
+                    let mut stdin = stdin;
+                    while let Ok(n @ 1..) = stdin.read(&mut chunk) {
+                        pending.extend(&chunk[..n]);
+                        let (frames, skipped) = split_frames(&mut pending, &framing);
+                        if skipped > 0 {
+                            note!("Skipped {} bytes without a known report ID", skipped);
+                        }
+                        for frame in frames {
+                            if sender.send(frame).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Piped { reports }
+    }
+}
+
+// A hex report, None for lines without one
+pub fn parse_line(line: &str) -> Option<Vec<u8>> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    // usbmon: URB tag, timestamp, event, address, status, length, then the data. Only the
+    // completions of interrupt IN transfers carry input reports.
+    let data = match line.split_once(" = ") {
+        Some((urb, data)) => {
+            let fields: Vec<_> = urb.split_whitespace().collect();
+            if fields.get(2) != Some(&"C") || !fields.get(3)?.starts_with("Ii:") {
+                return None;
+            }
+            data
+        }
+        None => line,
+    };
+
+    let mut bytes = vec![];
+    for token in data.split(|c: char| c.is_whitespace() || c == ':' || c == ',') {
+        let token = token.trim_start_matches("0x");
+        if token.len() % 2 != 0 {
+            return None;
+        }
+        for i in (0..token.len()).step_by(2) {
+            bytes.push(u8::from_str_radix(token.get(i..i + 2)?, 16).ok()?);
+        }
+    }
+
+    (!bytes.is_empty()).then_some(bytes)
+}
+
+// Takes the complete reports off the front of `pending`, and how many bytes were dropped to
+// find the next report ID the descriptor knows
+pub fn split_frames(pending: &mut Vec<u8>, framing: &Framing) -> (Vec<Vec<u8>>, usize) {
+    let mut frames = vec![];
+    let mut skipped = 0;
+    let mut start = 0;
+
+    while let Some(&first) = pending.get(start) {
+        let Some(length) = framing.length(first) else {
+            start += 1;
+            skipped += 1;
+            continue;
+        };
+        if pending.len() - start < length {
+            break;
+        }
+        frames.push(pending[start..start + length].to_vec());
+        start += length;
+    }
+    pending.drain(..start);
+
+    (frames, skipped)
+}
+
+impl HidIo for Piped {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        let report = match timeout_ms {
+            ..0 => self.reports.recv().map_err(|_| EndOfInput)?,
+            ms => match self.reports.recv_timeout(Duration::from_millis(ms as u64)) {
+                Ok(report) => report,
+                Err(RecvTimeoutError::Timeout) => return Ok(0),
+                Err(RecvTimeoutError::Disconnected) => return Err(EndOfInput.into()),
+            },
+        };
+        let n = report.len().min(buf.len());
+        buf[..n].copy_from_slice(&report[..n]);
+
+        Ok(n)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_timeout(buf, -1)
+    }
+
+    fn write(&self, _report: &[u8]) -> Result<usize> {
+        Err(anyhow!("Reports can't be sent to piped input"))
+    }
+
+    fn send_feature_report(&self, _report: &[u8]) -> Result<()> {
+        Err(anyhow!("Reports can't be sent to piped input"))
+    }
+
+    fn get_feature_report(&self, _buf: &mut [u8]) -> Result<usize> {
+        Err(anyhow!("Piped input has no feature reports"))
+    }
+
+    fn set_blocking_mode(&self, _blocking: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData};
+
+    use crate::backend::Framing;
+
+    use super::{parse_line, split_frames};
+
+    #[test]
+    fn parses_hex_and_usbmon_lines() {
+        assert_eq!(parse_line("01 00 05"), Some(vec![0x01, 0x00, 0x05]));
+        assert_eq!(parse_line("0x01,0x00,0xfb"), Some(vec![0x01, 0x00, 0xfb]));
+        assert_eq!(parse_line("01:ff"), Some(vec![0x01, 0xff]));
+        assert_eq!(parse_line("0100fb"), Some(vec![0x01, 0x00, 0xfb]));
+        assert_eq!(
+            parse_line("ffff8880 3575914555 C Ii:1:005:1 0:8 8 = 01020304 05060708"),
+            Some(vec![1, 2, 3, 4, 5, 6, 7, 8])
+        );
+
+        assert_eq!(
+            parse_line("ffff8880 3575914555 S Ii:1:005:1 -115:8 8 <"),
+            None
+        );
+        assert_eq!(
+            parse_line("ffff8880 3575914560 S Io:1:005:2 -115:8 1 = 02"),
+            None
+        );
+        assert_eq!(parse_line("# recorded at noon"), None);
+        assert_eq!(parse_line("1 2 3"), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn splits_raw_reports_by_id() {
+        // report 1 has a byte after the ID, report 2 two
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage(0x30)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .report_id(2)
+            .usage(0x31)
+            .report_count(2)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+        let framing = Framing::new(&parser);
+
+        let mut pending = vec![0x01, 0x05, 0x07, 0x02, 0x0a, 0x0b, 0x01];
+        let (frames, skipped) = split_frames(&mut pending, &framing);
+        assert_eq!(frames, [vec![0x01, 0x05], vec![0x02, 0x0a, 0x0b]]);
+        assert_eq!(skipped, 1);
+        // the start of the next report waits for the rest
+        assert_eq!(pending, [0x01]);
+    }
+}
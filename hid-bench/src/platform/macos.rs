@@ -0,0 +1,219 @@
+// Report descriptors from IOKit
+//
+// Every IOHIDDevice has its report descriptor in the "ReportDescriptor" registry property,
+// reading it doesn't need the device to be opened, so it works for devices claimed by macOS
+// (keyboards, trackpads) and without the Input Monitoring permission.
+
+use std::{
+    ffi::{c_void, CString},
+    os::raw::c_char,
+    ptr, slice,
+};
+
+use anyhow::{anyhow, Result};
+
+use hid_parser::{DeviceModel, ReportDescriptor};
+
+use crate::config::DeviceSpec;
+
+type CFTypeRef = *const c_void;
+type CFAllocatorRef = *const c_void;
+type CFStringRef = *const c_void;
+type CFSetRef = *const c_void;
+type CFDictionaryRef = *const c_void;
+type CFIndex = isize;
+type CFTypeID = usize;
+type Boolean = u8;
+type IOHIDManagerRef = *mut c_void;
+type IOHIDDeviceRef = *mut c_void;
+type IOOptionBits = u32;
+type IoService = u32;
+
+const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+const CF_NUMBER_SINT32_TYPE: CFIndex = 3;
+const IO_REGISTRY_ITERATE_RECURSIVELY: IOOptionBits = 1;
+const IO_REGISTRY_ITERATE_PARENTS: IOOptionBits = 2;
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFAllocatorDefault: CFAllocatorRef;
+
+    fn CFRelease(cf: CFTypeRef);
+    fn CFGetTypeID(cf: CFTypeRef) -> CFTypeID;
+    fn CFStringCreateWithCString(
+        alloc: CFAllocatorRef,
+        c_str: *const c_char,
+        encoding: u32,
+    ) -> CFStringRef;
+    fn CFStringGetTypeID() -> CFTypeID;
+    fn CFStringGetCString(
+        string: CFStringRef,
+        buffer: *mut c_char,
+        buffer_size: CFIndex,
+        encoding: u32,
+    ) -> Boolean;
+    fn CFNumberGetTypeID() -> CFTypeID;
+    fn CFNumberGetValue(number: CFTypeRef, number_type: CFIndex, value: *mut c_void) -> Boolean;
+    fn CFDataGetTypeID() -> CFTypeID;
+    fn CFDataGetLength(data: CFTypeRef) -> CFIndex;
+    fn CFDataGetBytePtr(data: CFTypeRef) -> *const u8;
+    fn CFSetGetCount(set: CFSetRef) -> CFIndex;
+    fn CFSetGetValues(set: CFSetRef, values: *mut *const c_void);
+}
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOHIDManagerCreate(allocator: CFAllocatorRef, options: IOOptionBits) -> IOHIDManagerRef;
+    fn IOHIDManagerSetDeviceMatching(manager: IOHIDManagerRef, matching: CFDictionaryRef);
+    fn IOHIDManagerCopyDevices(manager: IOHIDManagerRef) -> CFSetRef;
+    fn IOHIDDeviceGetProperty(device: IOHIDDeviceRef, key: CFStringRef) -> CFTypeRef;
+    fn IOHIDDeviceGetService(device: IOHIDDeviceRef) -> IoService;
+    fn IORegistryEntrySearchCFProperty(
+        entry: IoService,
+        plane: *const c_char,
+        key: CFStringRef,
+        allocator: CFAllocatorRef,
+        options: IOOptionBits,
+    ) -> CFTypeRef;
+}
+
+// A CoreFoundation object we own and release
+struct Owned(CFTypeRef);
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            unsafe { CFRelease(self.0) }
+        }
+    }
+}
+
+fn cf_string(value: &str) -> Owned {
+    let value = CString::new(value).expect("property names have no NUL bytes");
+
+    unsafe {
+        Owned(CFStringCreateWithCString(
+            kCFAllocatorDefault,
+            value.as_ptr(),
+            CF_STRING_ENCODING_UTF8,
+        ))
+    }
+}
+
+unsafe fn number(value: CFTypeRef) -> Option<i32> {
+    if value.is_null() || CFGetTypeID(value) != CFNumberGetTypeID() {
+        return None;
+    }
+
+    let mut number = 0i32;
+    let ok = CFNumberGetValue(
+        value,
+        CF_NUMBER_SINT32_TYPE,
+        &mut number as *mut i32 as *mut c_void,
+    );
+
+    (ok != 0).then_some(number)
+}
+
+unsafe fn string(value: CFTypeRef) -> Option<String> {
+    if value.is_null() || CFGetTypeID(value) != CFStringGetTypeID() {
+        return None;
+    }
+
+    let mut buf = [0 as c_char; 256];
+    if CFStringGetCString(
+        value,
+        buf.as_mut_ptr(),
+        buf.len() as CFIndex,
+        CF_STRING_ENCODING_UTF8,
+    ) == 0
+    {
+        return None;
+    }
+
+    let bytes: Vec<u8> = buf
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8)
+        .collect();
+
+    String::from_utf8(bytes).ok()
+}
+
+unsafe fn data(value: CFTypeRef) -> Option<Vec<u8>> {
+    if value.is_null() || CFGetTypeID(value) != CFDataGetTypeID() {
+        return None;
+    }
+
+    let length = CFDataGetLength(value) as usize;
+
+    Some(slice::from_raw_parts(CFDataGetBytePtr(value), length).to_vec())
+}
+
+unsafe fn property(device: IOHIDDeviceRef, key: &str) -> CFTypeRef {
+    let key = cf_string(key);
+
+    IOHIDDeviceGetProperty(device, key.0)
+}
+
+// USB interface number from the parent interface in the IO registry, 0 for other devices
+unsafe fn interface_number(device: IOHIDDeviceRef) -> u8 {
+    let key = cf_string("bInterfaceNumber");
+    let value = Owned(IORegistryEntrySearchCFProperty(
+        IOHIDDeviceGetService(device),
+        b"IOService\0".as_ptr() as *const c_char,
+        key.0,
+        kCFAllocatorDefault,
+        IO_REGISTRY_ITERATE_RECURSIVELY | IO_REGISTRY_ITERATE_PARENTS,
+    ));
+
+    number(value.0).unwrap_or(0) as u8
+}
+
+pub fn report_descriptors(spec: &DeviceSpec) -> Result<DeviceModel> {
+    let mut descriptors = DeviceModel::new();
+
+    unsafe {
+        let manager = Owned(IOHIDManagerCreate(kCFAllocatorDefault, 0) as CFTypeRef);
+        if manager.0.is_null() {
+            return Err(anyhow!("Cannot create an IOHIDManager"));
+        }
+
+        // match all devices, filtering here keeps the FFI surface small
+        IOHIDManagerSetDeviceMatching(manager.0 as IOHIDManagerRef, ptr::null());
+        let devices = Owned(IOHIDManagerCopyDevices(manager.0 as IOHIDManagerRef));
+        if devices.0.is_null() {
+            return Err(anyhow!("No HID devices found by IOKit"));
+        }
+
+        let count = CFSetGetCount(devices.0) as usize;
+        let mut values: Vec<*const c_void> = vec![ptr::null(); count];
+        CFSetGetValues(devices.0, values.as_mut_ptr());
+
+        for device in values.into_iter().map(|d| d as IOHIDDeviceRef) {
+            let vid = number(property(device, "VendorID"));
+            let pid = number(property(device, "ProductID"));
+            if (vid, pid) != (Some(spec.vid as i32), Some(spec.pid as i32)) {
+                continue;
+            }
+
+            if spec.serial.is_some() && string(property(device, "SerialNumber")) != spec.serial {
+                continue;
+            }
+
+            if let Some(bytes) = data(property(device, "ReportDescriptor")) {
+                descriptors.add(interface_number(device), ReportDescriptor { bytes });
+            }
+        }
+    }
+
+    if descriptors.is_empty() {
+        return Err(anyhow!(
+            "IOKit has no HID device with vid {:04x} pid {:04x}",
+            spec.vid,
+            spec.pid
+        ));
+    }
+
+    Ok(descriptors)
+}
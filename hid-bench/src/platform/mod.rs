@@ -0,0 +1,24 @@
+// Descriptor sources native to the platform
+//
+// Where the OS keeps the report descriptors of the devices it manages, they can be read
+// without opening (or claiming) the device.
+
+use anyhow::Result;
+
+use hid_parser::DeviceModel;
+
+use crate::config::DeviceSpec;
+
+#[cfg(target_os = "macos")]
+mod macos;
+
+// Report descriptors by interface number, None if the platform has no native source
+#[cfg(target_os = "macos")]
+pub fn report_descriptors(spec: &DeviceSpec) -> Option<Result<DeviceModel>> {
+    Some(macos::report_descriptors(spec))
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn report_descriptors(_spec: &DeviceSpec) -> Option<Result<DeviceModel>> {
+    None
+}
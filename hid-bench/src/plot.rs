@@ -0,0 +1,173 @@
+// Plots drawn with text, for commands summarising measurements
+//
+// Sparklines show a series (e.g. the report rate over time) in a single line, histograms show
+// how samples (latencies, values of an axis) are distributed over equally sized buckets.
+
+use std::{fmt::Write, time::Duration};
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+const BAR_WIDTH: usize = 40;
+
+// One character per value, scaled from the smallest to the largest value. NaN (no value) is
+// left blank.
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    values
+        .iter()
+        .map(|value| {
+            if value.is_nan() {
+                return ' ';
+            }
+            if max <= min {
+                return SPARKS[SPARKS.len() / 2];
+            }
+            let level = (value - min) / (max - min) * (SPARKS.len() - 1) as f64;
+
+            SPARKS[level.round() as usize]
+        })
+        .collect()
+}
+
+// Events per second in `bins` equal parts of `duration`, from the event timestamps
+pub fn rate_over_time(timestamps: &[Duration], duration: Duration, bins: usize) -> Vec<f64> {
+    if bins == 0 || duration.is_zero() {
+        return vec![];
+    }
+
+    let bin = duration.as_secs_f64() / bins as f64;
+    let mut counts = vec![0u64; bins];
+    for timestamp in timestamps {
+        let index = (timestamp.as_secs_f64() / bin) as usize;
+        counts[index.min(bins - 1)] += 1;
+    }
+
+    counts.into_iter().map(|count| count as f64 / bin).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    pub start: f64,
+    pub width: f64, // 1 for buckets of a single whole number
+    pub count: usize,
+}
+
+// Samples counted in equally sized buckets. Whole number samples spanning fewer values than
+// buckets get a bucket per value.
+pub fn buckets(samples: &[f64], buckets: usize) -> Vec<Bucket> {
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if samples.is_empty() || buckets == 0 {
+        return vec![];
+    }
+
+    let integers = samples.iter().all(|sample| sample.fract() == 0.0);
+    let (buckets, width) = match integers && max - min < buckets as f64 {
+        true => ((max - min) as usize + 1, 1.0),
+        false if max > min => (buckets, (max - min) / buckets as f64),
+        false => (1, 0.0),
+    };
+
+    let mut counts = vec![0usize; buckets];
+    for sample in samples {
+        let index = match width > 0.0 {
+            true => ((sample - min) / width) as usize,
+            false => 0,
+        };
+        counts[index.min(buckets - 1)] += 1;
+    }
+
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(index, count)| Bucket {
+            start: min + width * index as f64,
+            width,
+            count,
+        })
+        .collect()
+}
+
+// A bar per bucket, e.g. `    1.00 -     1.50 ms|████████▍ 17`
+pub fn histogram(samples: &[f64], buckets: usize, unit: &str) -> String {
+    let mut output = String::new();
+    let buckets = self::buckets(samples, buckets);
+
+    let integers = samples.iter().all(|sample| sample.fract() == 0.0);
+    let largest = buckets
+        .iter()
+        .map(|bucket| bucket.count)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let precision = if integers { 0 } else { 2 };
+    for bucket in buckets {
+        let label = match integers && bucket.width == 1.0 {
+            true => format!("{:>8}", bucket.start),
+            false => format!(
+                "{:>8.precision$} - {:>8.precision$}",
+                bucket.start,
+                bucket.start + bucket.width,
+                precision = precision
+            ),
+        };
+
+        let _ = writeln!(
+            output,
+            "{} {}|{} {}",
+            label,
+            unit,
+            bar(bucket.count as f64 / largest as f64),
+            bucket.count
+        );
+    }
+
+    output
+}
+
+// 0 - 1 of the bar width, in eighths of a character
+fn bar(fraction: f64) -> String {
+    let eighths = (fraction * (BAR_WIDTH * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(EIGHTHS[eighths % 8]);
+    }
+
+    format!("{:<width$}", bar, width = BAR_WIDTH)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{histogram, rate_over_time, sparkline};
+
+    #[test]
+    fn plots_series_and_distributions() {
+        assert_eq!(sparkline(&[0.0, 1.0, 7.0, 3.5]), "▁▂█▅");
+        assert_eq!(sparkline(&[2.0, 2.0]), "▅▅");
+
+        let timestamps = [0, 100, 200, 900, 1999].map(Duration::from_millis);
+        assert_eq!(
+            rate_over_time(&timestamps, Duration::from_secs(2), 2),
+            vec![4.0, 1.0]
+        );
+
+        let latencies = histogram(&[1.0, 1.2, 1.3, 2.0, 3.0], 2, "ms");
+        assert_eq!(
+            latencies,
+            format!(
+                "    1.00 -     2.00 ms|{} 3\n    2.00 -     3.00 ms|{:<40} 2\n",
+                "█".repeat(40),
+                format!("{}{}", "█".repeat(26), '▋')
+            )
+        );
+
+        // a bucket per value for narrow ranges of whole numbers
+        let values = histogram(&[-1.0, 0.0, 0.0, 1.0], 10, "");
+        assert_eq!(values.lines().count(), 3);
+        assert!(values.lines().nth(1).unwrap().starts_with("       0 |"));
+    }
+}
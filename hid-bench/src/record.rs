@@ -0,0 +1,628 @@
+// Recording input reports into a capture file
+//
+// A ring buffer recording keeps only the last seconds of reports in memory and writes them out
+// when something interesting happens: a report matching a trigger, SIGUSR1, or the device
+// failing. Glitches showing up after hours are captured without writing gigabytes.
+//
+// Gaming mice report at up to 8 kHz, and hidraw queues only 64 reports per reader (hidapi's
+// libusb backend 30), so the read loop must get back to reading within a few milliseconds. Reports are read into a
+// preallocated buffer and written without any allocation or formatting, the file is flushed at
+// most every 100 ms. With a reader thread reading is all the loop does: reports go to the
+// writer through a bounded queue of preallocated slots, and when the writer falls behind and
+// every slot is taken the report is dropped and counted instead of stalling the reads. Reports
+// are written in the framing of the descriptor, whatever the platform did to their report IDs
+// (see `backend::Framed`).
+//
+// Markers typed on the terminal (`m TEXT`), sent as SIGUSR1 or inserted every few seconds go
+// into captures between the reports, checked when the file is flushed, so the moment someone
+// started rubbing the cable can be found in the analysis.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use hidapi::HidDevice;
+
+use hid_parser::{
+    capture::{CaptureWriter, DeviceMetadata, Direction, Marker, Record, Transfer},
+    DeviceModel, ReportKind,
+};
+
+use crate::{
+    backend::{Framed, Framing, HidIo},
+    controls::{Control, Controls},
+    framelog::FrameWriter,
+    output::outln,
+    signals::{self, UserSignal},
+};
+
+const READ_TIMEOUT_MS: i32 = 100;
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+// Reports in flight from the reader thread to the writer, a second at 8 kHz
+const QUEUE_SLOTS: usize = 8192;
+
+// Report bytes to match from the start, `xx` matches any byte, e.g. `03 xx ff`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trigger(Vec<Option<u8>>);
+
+impl FromStr for Trigger {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = s
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .map(|token| match token {
+                "xx" | "XX" => Ok(None),
+                _ => u8::from_str_radix(token, 16)
+                    .ok()
+                    .filter(|_| token.len() == 2)
+                    .map(Some)
+                    .ok_or_else(|| anyhow!("Expected hex bytes or xx, found '{}'", token)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if bytes.is_empty() {
+            return Err(anyhow!("The trigger needs at least one byte"));
+        }
+
+        Ok(Trigger(bytes))
+    }
+}
+
+impl Trigger {
+    pub fn matches(&self, report: &[u8]) -> bool {
+        self.0.len() <= report.len()
+            && self
+                .0
+                .iter()
+                .zip(report)
+                .all(|(wanted, byte)| wanted.is_none_or(|wanted| wanted == *byte))
+    }
+}
+
+// The last `window` of transfers
+#[derive(Debug)]
+pub struct Ring {
+    window: Duration,
+    transfers: VecDeque<Transfer>,
+}
+
+impl Ring {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            transfers: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, transfer: Transfer) {
+        while self
+            .transfers
+            .front()
+            .is_some_and(|first| transfer.timestamp - first.timestamp > self.window)
+        {
+            self.transfers.pop_front();
+        }
+        self.transfers.push_back(transfer);
+    }
+
+    // The transfers so far, timed from the first one, emptying the ring
+    pub fn drain(&mut self) -> Vec<Transfer> {
+        let start = self
+            .transfers
+            .front()
+            .map(|first| first.timestamp)
+            .unwrap_or_default();
+
+        self.transfers
+            .drain(..)
+            .map(|transfer| Transfer {
+                timestamp: transfer.timestamp - start,
+                ..transfer
+            })
+            .collect()
+    }
+}
+
+fn create(
+    path: &Path,
+    metadata: DeviceMetadata,
+    descriptors: &DeviceModel,
+) -> Result<CaptureWriter<BufWriter<File>>> {
+    let file = File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+    let mut writer = CaptureWriter::new(BufWriter::new(file))?;
+
+    writer.write(&Record::Device(metadata))?;
+
+    for record in descriptors.records() {
+        writer.write(&record)?;
+    }
+
+    Ok(writer)
+}
+
+// `capture.hbc` becomes `capture-1.hbc` for the first dump
+pub fn numbered(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}-{}.{}", stem, n, extension.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+
+    path.with_file_name(name)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Recorded {
+    pub reports: u64,
+    pub dropped: u64, // read but not written, the writer falling behind the reader thread
+    pub markers: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct RecordOptions {
+    pub duration: Option<Duration>, // None to record until interrupted
+    pub reader_thread: bool,
+    pub mark_every: Option<Duration>,
+}
+
+// Where markers come from: the runtime controls and a period
+struct Marks {
+    controls: Option<Controls>,
+    every: Option<Duration>,
+    next: Duration,
+}
+
+impl Marks {
+    fn new(controls: Option<Controls>, every: Option<Duration>) -> Self {
+        Self {
+            controls,
+            every,
+            next: every.unwrap_or_default(),
+        }
+    }
+
+    // Markers due `elapsed` into the recording, typed ones with their text, periodic ones
+    // without any
+    fn due(&mut self, elapsed: Duration) -> Vec<Marker> {
+        let mut markers: Vec<_> = self
+            .controls
+            .iter()
+            .flat_map(|controls| controls.poll())
+            .filter_map(|control| match control {
+                Control::Marker(text) => Some(Marker {
+                    timestamp: elapsed,
+                    text,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        if let Some(every) = self.every.filter(|every| !every.is_zero()) {
+            while elapsed >= self.next {
+                markers.push(Marker {
+                    timestamp: self.next,
+                    text: None,
+                });
+                self.next += every;
+            }
+        }
+
+        markers
+    }
+}
+
+// A report on its way from the reader thread to the writer
+struct Slot {
+    timestamp: Duration,
+    length: usize,
+    bytes: Box<[u8]>,
+}
+
+// Read buffer size: the longest input report of the interface, at least the 64 bytes of a
+// full speed packet
+pub fn buffer_size(descriptors: &DeviceModel, interface: u8) -> usize {
+    let longest = descriptors.parser(interface).and_then(|parser| {
+        parser
+            .reports()
+            .iter()
+            .filter_map(|report| parser.report_length(ReportKind::Input, report.report_id))
+            .max()
+    });
+
+    longest.unwrap_or_default().max(64)
+}
+
+// Where a recording goes: a capture, or a frame log (see `framelog`) for paths ending in .hbf
+enum Output {
+    Capture(CaptureWriter<BufWriter<File>>, u8), // with the interface
+    Frames(FrameWriter),
+}
+
+impl Output {
+    fn create(
+        path: &Path,
+        metadata: DeviceMetadata,
+        descriptors: &DeviceModel,
+        interface: u8,
+    ) -> Result<Self> {
+        if path.extension().is_none_or(|e| e != "hbf") {
+            return Ok(Output::Capture(
+                create(path, metadata, descriptors)?,
+                interface,
+            ));
+        }
+
+        let descriptor = descriptors.descriptor(interface).ok_or_else(|| {
+            anyhow!(
+                "Frame logs need the report descriptor of interface #{}",
+                interface
+            )
+        })?;
+
+        Ok(Output::Frames(FrameWriter::create(
+            path,
+            &metadata,
+            interface,
+            &descriptor,
+        )?))
+    }
+
+    // Frame logs hold nothing but reports, markers are dropped
+    fn mark(&mut self, marker: Marker) -> Result<()> {
+        match self {
+            Output::Capture(writer, _) => writer.write(&Record::Marker(marker)),
+            Output::Frames(_) => Ok(()),
+        }
+    }
+
+    fn write(&mut self, timestamp: Duration, bytes: &[u8]) -> Result<()> {
+        match self {
+            Output::Capture(writer, interface) => {
+                writer.write_transfer(timestamp, *interface, Direction::In, bytes)
+            }
+            Output::Frames(writer) => writer.write(timestamp, bytes),
+        }
+    }
+
+    // Frame logs are mapped, the kernel writes them back on its own
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Output::Capture(writer, _) => writer.flush(),
+            Output::Frames(_) => Ok(()),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Output::Capture(mut writer, _) => writer.flush(),
+            Output::Frames(writer) => writer.finish(),
+        }
+    }
+}
+
+// Records until `duration` passes, or forever, on a reader thread if asked to, inserting the
+// markers typed or signalled through `controls`. The file is finished even when reading fails.
+pub fn run(
+    hid_device: &mut (dyn HidIo + Send),
+    metadata: DeviceMetadata,
+    descriptors: &DeviceModel,
+    interface: u8,
+    path: &Path,
+    options: &RecordOptions,
+    controls: Option<Controls>,
+) -> Result<Recorded> {
+    let mut output = Output::create(path, metadata, descriptors, interface)?;
+    let size = buffer_size(descriptors, interface);
+    let mut marks = Marks::new(controls, options.mark_every);
+    let framing = descriptors
+        .parser(interface)
+        .map(Framing::new)
+        .unwrap_or_default();
+    let mut hid_device = Framed::new(hid_device, framing);
+    let hid_device = &mut hid_device;
+
+    let duration = options.duration;
+    let recorded = match options.reader_thread {
+        true => record_threaded(hid_device, &mut output, duration, size, &mut marks),
+        false => record_inline(hid_device, &mut output, duration, size, &mut marks),
+    };
+    output.finish()?;
+
+    recorded
+}
+
+fn record_inline(
+    hid_device: &dyn HidIo,
+    output: &mut Output,
+    duration: Option<Duration>,
+    size: usize,
+    marks: &mut Marks,
+) -> Result<Recorded> {
+    let start = Instant::now();
+    let mut last_flush = start;
+    let mut buf = vec![0u8; size];
+    let mut recorded = Recorded::default();
+
+    while duration.is_none_or(|duration| start.elapsed() < duration) {
+        let n = hid_device.read_timeout(&mut buf, READ_TIMEOUT_MS)?;
+        if n > 0 {
+            output.write(start.elapsed(), &buf[..n])?;
+            recorded.reports += 1;
+        }
+
+        // keep the file usable when the recording is interrupted
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            recorded.markers += write_marks(output, marks, start.elapsed())?;
+            output.flush()?;
+            last_flush = Instant::now();
+        }
+    }
+
+    Ok(recorded)
+}
+
+fn record_threaded(
+    hid_device: &mut (dyn HidIo + Send),
+    output: &mut Output,
+    duration: Option<Duration>,
+    size: usize,
+    marks: &mut Marks,
+) -> Result<Recorded> {
+    let (queue, queued) = mpsc::sync_channel::<Slot>(QUEUE_SLOTS);
+    let (free, freed) = mpsc::channel::<Slot>();
+    for _ in 0..QUEUE_SLOTS {
+        free.send(Slot {
+            timestamp: Duration::ZERO,
+            length: 0,
+            bytes: vec![0; size].into_boxed_slice(),
+        })?;
+    }
+
+    let start = Instant::now();
+    thread::scope(|scope| {
+        // there are only as many slots as the queue holds, so sending never blocks
+        let reader = scope.spawn(move || -> Result<u64> {
+            let mut buf = vec![0u8; size];
+            let mut dropped = 0;
+
+            while duration.is_none_or(|duration| start.elapsed() < duration) {
+                let n = hid_device.read_timeout(&mut buf, READ_TIMEOUT_MS)?;
+                if n == 0 {
+                    continue;
+                }
+                let timestamp = start.elapsed();
+
+                let mut slot = match freed.try_recv() {
+                    Ok(slot) => slot,
+                    Err(TryRecvError::Empty) => {
+                        dropped += 1;
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => break, // the writer failed
+                };
+                slot.timestamp = timestamp;
+                slot.length = n;
+                slot.bytes[..n].copy_from_slice(&buf[..n]);
+                if queue.send(slot).is_err() {
+                    break;
+                }
+            }
+
+            Ok(dropped)
+        });
+
+        let written = write_slots(output, queued, free, start, marks);
+        let dropped = reader.join().expect("the reader thread panicked");
+        let (reports, markers) = written?;
+
+        Ok(Recorded {
+            reports,
+            dropped: dropped?,
+            markers,
+        })
+    })
+}
+
+// Writes the reports the reader thread queues until it stops, handing the slots back. Returns
+// the number of reports and markers written.
+fn write_slots(
+    output: &mut Output,
+    queued: Receiver<Slot>,
+    free: Sender<Slot>,
+    start: Instant,
+    marks: &mut Marks,
+) -> Result<(u64, u64)> {
+    let mut reports = 0;
+    let mut markers = 0;
+    let mut last_flush = Instant::now();
+
+    loop {
+        match queued.recv_timeout(FLUSH_INTERVAL) {
+            Ok(slot) => {
+                output.write(slot.timestamp, &slot.bytes[..slot.length])?;
+                reports += 1;
+                let _ = free.send(slot);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if last_flush.elapsed() >= FLUSH_INTERVAL {
+            markers += write_marks(output, marks, start.elapsed())?;
+            output.flush()?;
+            last_flush = Instant::now();
+        }
+    }
+
+    Ok((reports, markers))
+}
+
+fn write_marks(output: &mut Output, marks: &mut Marks, elapsed: Duration) -> Result<u64> {
+    let markers = marks.due(elapsed);
+    let count = markers.len() as u64;
+    for marker in markers {
+        output.mark(marker)?;
+    }
+
+    Ok(count)
+}
+
+#[derive(Debug)]
+pub struct RingOptions {
+    pub window: Duration,
+    pub trigger: Option<Trigger>,
+    pub duration: Option<Duration>, // None to record until interrupted
+}
+
+// Keeps the last `window` of reports, writing them to numbered files next to `path` when the
+// trigger matches a report (at most once per window), on SIGUSR1 or when reading fails. Returns
+// the number of files written.
+pub fn run_ring(
+    hid_device: &HidDevice,
+    metadata: DeviceMetadata,
+    descriptors: &DeviceModel,
+    interface: u8,
+    path: &Path,
+    options: &RingOptions,
+) -> Result<usize> {
+    let window = options.window;
+    let mut ring = Ring::new(window);
+    let mut dumps = 0;
+    let mut last_trigger: Option<Instant> = None;
+    let mut dump = |ring: &mut Ring, reason: &str| -> Result<()> {
+        let transfers = ring.drain();
+        dumps += 1;
+        let path = numbered(path, dumps);
+
+        let mut writer = create(&path, metadata.clone(), descriptors)?;
+        for transfer in &transfers {
+            writer.write(&Record::Transfer(transfer.clone()))?;
+        }
+        writer.flush()?;
+
+        outln!(
+            "{}, wrote the last {} reports to {}",
+            reason,
+            transfers.len(),
+            path.display()
+        );
+
+        Ok(())
+    };
+
+    if !signals::listen() {
+        outln!("SIGUSR1 isn't available, only triggers and failures write the reports out");
+    }
+
+    let start = Instant::now();
+    let mut buf = [0u8; 64];
+
+    while options
+        .duration
+        .is_none_or(|duration| start.elapsed() < duration)
+    {
+        if UserSignal::Usr1.take() {
+            dump(&mut ring, "SIGUSR1")?;
+        }
+
+        let n = match hid_device.read_timeout(&mut buf, READ_TIMEOUT_MS) {
+            Ok(n) => n,
+            Err(err) => {
+                dump(&mut ring, &format!("Reading failed ({})", err))?;
+                return Err(err.into());
+            }
+        };
+        if n == 0 {
+            continue;
+        }
+
+        ring.push(Transfer {
+            timestamp: start.elapsed(),
+            interface,
+            direction: Direction::In,
+            bytes: buf[0..n].to_vec(),
+        });
+
+        let triggered = options
+            .trigger
+            .as_ref()
+            .is_some_and(|trigger| trigger.matches(&buf[0..n]));
+        if triggered && last_trigger.is_none_or(|last| last.elapsed() >= window) {
+            last_trigger = Some(Instant::now());
+            dump(&mut ring, "Triggered")?;
+        }
+    }
+
+    Ok(dumps)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, time::Duration};
+
+    use hid_parser::capture::{Direction, Transfer};
+
+    use super::{numbered, Marks, Ring, Trigger};
+
+    #[test]
+    fn inserts_periodic_markers() {
+        let mut marks = Marks::new(None, Some(Duration::from_secs(10)));
+        let due: Vec<_> = marks
+            .due(Duration::from_secs(25))
+            .iter()
+            .map(|marker| marker.timestamp.as_secs())
+            .collect();
+        assert_eq!(due, [10, 20]);
+        assert!(marks.due(Duration::from_secs(29)).is_empty());
+        assert!(Marks::new(None, None).due(Duration::MAX).is_empty());
+    }
+
+    #[test]
+    fn matches_triggers() {
+        let trigger = "03 xx ff".parse::<Trigger>().unwrap();
+        assert!(trigger.matches(&[0x03, 0x10, 0xff, 0x00]));
+        assert!(!trigger.matches(&[0x03, 0x10, 0xfe]));
+        assert!(!trigger.matches(&[0x03, 0x10]));
+
+        assert!("".parse::<Trigger>().is_err());
+        assert!("3 ff".parse::<Trigger>().is_err());
+    }
+
+    #[test]
+    fn keeps_the_last_window_of_reports() {
+        let transfer = |ms| Transfer {
+            timestamp: Duration::from_millis(ms),
+            interface: 0,
+            direction: Direction::In,
+            bytes: vec![ms as u8],
+        };
+
+        let mut ring = Ring::new(Duration::from_millis(100));
+        for ms in [0, 50, 120, 140, 210] {
+            ring.push(transfer(ms));
+        }
+
+        let kept: Vec<_> = ring
+            .drain()
+            .iter()
+            .map(|t| (t.timestamp.as_millis(), t.bytes[0]))
+            .collect();
+        assert_eq!(kept, [(0, 120), (20, 140), (90, 210)]);
+        assert!(ring.drain().is_empty());
+
+        assert_eq!(
+            numbered(Path::new("out/capture.hbc"), 2),
+            Path::new("out/capture-2.hbc")
+        );
+    }
+}
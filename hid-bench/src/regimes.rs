@@ -0,0 +1,327 @@
+// Polling regimes, stalls and drift of the input report rate
+//
+// A single average rate hides what a device actually does: a mouse switching between 125 Hz
+// and 1 kHz averages to a rate it never polls at, and stalls of a few frames disappear in it.
+// The intervals between the input reports of every interface are cut into windows, windows
+// with about the same median interval are merged into regimes, and each regime is checked for
+// stalls (intervals several times its median, which may recur at a fixed period) and for the
+// interval drifting from its start to its end, e.g. as the device warms up.
+//
+// Gaps longer than IDLE_GAP are taken for the device having nothing to report (a mouse lying
+// still) rather than for a stall, and are left out.
+
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use hid_parser::capture::{Capture, Direction};
+
+const IDLE_GAP: Duration = Duration::from_millis(250);
+const WINDOW: usize = 64; // intervals
+                          // Windows whose median interval is this close to a regime's belong to it
+const SAME_RATE: f64 = 0.25;
+const STALL_FACTOR: u32 = 4;
+// Stall spacings varying less than this are periodic
+const PERIODIC: f64 = 0.1;
+// Drift is measured between the first and last fifth of regimes at least this long, and shown
+// from this much
+const DRIFT_DURATION: Duration = Duration::from_secs(60);
+const DRIFT_SHOWN: f64 = 0.001;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regime {
+    pub start: Duration,
+    pub end: Duration,
+    pub interval: Duration, // median
+    pub reports: usize,
+    pub drift: Option<f64>, // of the mean interval, relative
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateAnalysis {
+    pub regimes: Vec<Regime>,
+    pub stalls: Vec<(Duration, Duration)>, // when and how long
+}
+
+fn median(intervals: &mut [Duration]) -> Duration {
+    intervals.sort();
+    intervals
+        .get(intervals.len() / 2)
+        .copied()
+        .unwrap_or_default()
+}
+
+fn mean(intervals: &[(Duration, Duration)]) -> f64 {
+    let total: f64 = intervals.iter().map(|(_, i)| i.as_secs_f64()).sum();
+
+    total / intervals.len().max(1) as f64
+}
+
+fn close(a: Duration, b: Duration) -> bool {
+    (a.as_secs_f64() - b.as_secs_f64()).abs() <= b.as_secs_f64() * SAME_RATE
+}
+
+fn hz(interval: Duration) -> f64 {
+    1.0 / interval.as_secs_f64().max(f64::MIN_POSITIVE)
+}
+
+impl RateAnalysis {
+    // Of input reports arriving at `timestamps`, in order
+    pub fn new(timestamps: &[Duration]) -> Self {
+        // (report time, interval before it), without idle gaps
+        let intervals: Vec<_> = timestamps
+            .windows(2)
+            .map(|pair| (pair[1], pair[1].saturating_sub(pair[0])))
+            .filter(|(_, interval)| *interval <= IDLE_GAP)
+            .collect();
+
+        // consecutive windows of about the same median interval
+        let mut groups: Vec<(Duration, Vec<(Duration, Duration)>)> = vec![];
+        for window in intervals.chunks(WINDOW) {
+            let window_median = median(&mut window.iter().map(|(_, i)| *i).collect::<Vec<_>>());
+            let mut window = window.to_vec();
+            match groups.last_mut() {
+                Some((anchor, group)) if close(window_median, *anchor) => group.extend(window),
+                Some((anchor, group)) => {
+                    // the rate rarely changes on a window boundary
+                    let old = |i: Duration| close(i, *anchor) && !close(i, window_median);
+                    let new = |i: Duration| close(i, window_median) && !close(i, *anchor);
+                    let lead = window.iter().take_while(|(_, i)| old(*i)).count();
+                    group.extend(window.drain(..lead));
+                    while group.last().is_some_and(|(_, i)| new(*i)) {
+                        window.insert(0, group.pop().unwrap_or_default());
+                    }
+                    groups.push((window_median, window));
+                }
+                None => groups.push((window_median, window)),
+            }
+        }
+        groups.retain(|(_, group)| !group.is_empty());
+
+        let mut analysis = RateAnalysis::default();
+        for (_, group) in groups {
+            let interval = median(&mut group.iter().map(|(_, i)| *i).collect::<Vec<_>>());
+            let start = group[0].0 - group[0].1;
+            let end = group[group.len() - 1].0;
+
+            let fifth = group.len() / 5;
+            let drift = (end - start >= DRIFT_DURATION && fifth > 0).then(|| {
+                let first = mean(&group[..fifth]);
+                (mean(&group[group.len() - fifth..]) - first) / first
+            });
+
+            analysis.stalls.extend(
+                group
+                    .iter()
+                    .filter(|(_, i)| *i > interval * STALL_FACTOR)
+                    .map(|(at, i)| (*at - *i, *i)),
+            );
+            analysis.regimes.push(Regime {
+                start,
+                end,
+                interval,
+                reports: group.len(),
+                drift,
+            });
+        }
+
+        analysis
+    }
+
+    // The distinct rates the device switched between, by their median interval
+    pub fn modes(&self) -> Vec<Duration> {
+        let mut modes: Vec<Duration> = vec![];
+        for regime in &self.regimes {
+            if !modes.iter().any(|mode| close(regime.interval, *mode)) {
+                modes.push(regime.interval);
+            }
+        }
+
+        modes
+    }
+
+    // The period of stalls recurring at regular spacings
+    pub fn stall_period(&self) -> Option<Duration> {
+        if self.stalls.len() < 3 {
+            return None;
+        }
+        let spacings: Vec<f64> = self
+            .stalls
+            .windows(2)
+            .map(|pair| (pair[1].0 - pair[0].0).as_secs_f64())
+            .collect();
+        let mean = spacings.iter().sum::<f64>() / spacings.len() as f64;
+        let variance = spacings.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / mean.powi(2);
+        let deviation = (variance / spacings.len() as f64).sqrt();
+
+        (deviation < PERIODIC).then(|| Duration::from_secs_f64(mean))
+    }
+}
+
+impl Display for RateAnalysis {
+    // e.g. `    0.000 - 12.345 s: 1000 Hz (1.000 ms), 12345 reports`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.regimes.is_empty() {
+            return writeln!(f, "Not enough input reports for their rate");
+        }
+
+        writeln!(f, "Input rate regimes:")?;
+        for regime in &self.regimes {
+            writeln!(
+                f,
+                "    {:.3} - {:.3} s: {:.0} Hz ({:.3} ms), {} reports",
+                regime.start.as_secs_f64(),
+                regime.end.as_secs_f64(),
+                hz(regime.interval),
+                regime.interval.as_secs_f64() * 1000.0,
+                regime.reports
+            )?;
+            if let Some(drift) = regime.drift.filter(|drift| drift.abs() >= DRIFT_SHOWN) {
+                writeln!(
+                    f,
+                    "        the interval drifts {:+.2}% from start to end",
+                    drift * 100.0
+                )?;
+            }
+        }
+
+        let modes = self.modes();
+        if modes.len() > 1 {
+            let rates: Vec<_> = modes
+                .iter()
+                .map(|mode| format!("{:.0} Hz", hz(*mode)))
+                .collect();
+            let changes = self.regimes.len() - 1;
+            writeln!(
+                f,
+                "Switches between {}, {} mode change{}",
+                rates.join(", "),
+                changes,
+                if changes == 1 { "" } else { "s" }
+            )?;
+        }
+
+        if let Some(longest) = self.stalls.iter().map(|(_, length)| *length).max() {
+            write!(
+                f,
+                "{} stalls of up to {:.1} ms",
+                self.stalls.len(),
+                longest.as_secs_f64() * 1000.0
+            )?;
+            match self.stall_period() {
+                Some(period) => write!(f, ", every {:.3} s", period.as_secs_f64())?,
+                None => {
+                    let at: Vec<_> = self
+                        .stalls
+                        .iter()
+                        .take(5)
+                        .map(|(at, _)| format!("{:.3} s", at.as_secs_f64()))
+                        .collect();
+                    let more = if self.stalls.len() > at.len() {
+                        ", ..."
+                    } else {
+                        ""
+                    };
+                    write!(f, " at {}{}", at.join(", "), more)?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The analysis of every interface with input reports
+pub fn by_interface(capture: &Capture) -> BTreeMap<u8, RateAnalysis> {
+    let mut timestamps: BTreeMap<u8, Vec<Duration>> = BTreeMap::new();
+    for transfer in &capture.transfers {
+        if transfer.direction == Direction::In {
+            timestamps
+                .entry(transfer.interface)
+                .or_default()
+                .push(transfer.timestamp);
+        }
+    }
+
+    timestamps
+        .into_iter()
+        .map(|(interface, timestamps)| (interface, RateAnalysis::new(&timestamps)))
+        .collect()
+}
+
+// Of every interface, headed by its number when there are several
+pub fn format(analyses: &BTreeMap<u8, RateAnalysis>) -> String {
+    match analyses.len() {
+        1 => analyses.values().map(|a| a.to_string()).collect(),
+        _ => analyses
+            .iter()
+            .map(|(interface, analysis)| format!("Interface {}:\n{}", interface, analysis))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::RateAnalysis;
+
+    // Reports every `interval` for `count` reports after `start`
+    fn polling(start: Duration, interval: Duration, count: u32) -> Vec<Duration> {
+        (0..count).map(|n| start + interval * n).collect()
+    }
+
+    #[test]
+    fn finds_regimes_and_periodic_stalls() {
+        // 1 kHz for 2 s, then 125 Hz for 4 s
+        let mut timestamps = polling(Duration::ZERO, Duration::from_millis(1), 2000);
+        timestamps.extend(polling(
+            Duration::from_millis(2000),
+            Duration::from_millis(8),
+            500,
+        ));
+        // a 20 ms stall every 500 ms at 1 kHz
+        for n in [400, 900, 1400, 1900] {
+            let stall = Duration::from_millis(n);
+            timestamps.retain(|t| *t <= stall || *t >= stall + Duration::from_millis(20));
+        }
+        let analysis = RateAnalysis::new(&timestamps);
+
+        let rates: Vec<_> = analysis
+            .regimes
+            .iter()
+            .map(|r| {
+                (
+                    r.start.as_millis(),
+                    r.end.as_millis(),
+                    r.interval.as_millis(),
+                )
+            })
+            .collect();
+        assert_eq!(rates, [(0, 2000, 1), (2000, 5992, 8)]);
+        assert_eq!(analysis.modes().len(), 2);
+        assert_eq!(analysis.stalls.len(), 4);
+        assert_eq!(analysis.stall_period(), Some(Duration::from_millis(500)));
+
+        let text = analysis.to_string();
+        assert!(text.contains("    0.000 - 2.000 s: 1000 Hz (1.000 ms), "));
+        assert!(text.contains("Switches between 1000 Hz, 125 Hz, 1 mode change"));
+        assert!(text.contains("4 stalls of up to 20.0 ms, every 0.500 s"));
+    }
+
+    #[test]
+    fn measures_drift_of_long_regimes() {
+        // 1 kHz slowing down by 1% over two minutes
+        let mut timestamps = vec![];
+        let mut t = Duration::ZERO;
+        for n in 0..120_000u32 {
+            timestamps.push(t);
+            t += Duration::from_micros(1000 + n as u64 / 12_000);
+        }
+        let analysis = RateAnalysis::new(&timestamps);
+
+        assert_eq!(analysis.regimes.len(), 1);
+        let drift = analysis.regimes[0].drift.unwrap();
+        assert!((0.007..0.009).contains(&drift), "{}", drift);
+        assert!(analysis.to_string().contains("the interval drifts +0.8"));
+    }
+}
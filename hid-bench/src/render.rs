@@ -0,0 +1,350 @@
+// Rendering of the human readable output
+//
+// All terminal formatting goes through a `Renderer`, which knows whether to use colors and
+// which palette suits the terminal background.
+
+use std::{
+    env,
+    io::{self, IsTerminal},
+};
+
+use clap::ValueEnum;
+
+use hid_parser::{unit::Measurement, usage, Collection, CollectionItem, Input, InputValue, Report};
+
+// An input with its value in physical units, if its field declares a unit
+pub type Measured = (Input, Option<Measurement>);
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colors when writing to a terminal, unless NO_COLOR is set
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+// ANSI SGR codes
+#[derive(Debug)]
+pub struct Palette {
+    pub usage: &'static str,
+    pub value: &'static str,
+    pub changed: &'static str,
+    pub warning: &'static str,
+    pub report_ids: &'static [&'static str],
+}
+
+// yellow is hard to read on a light background
+pub const DARK: Palette = Palette {
+    usage: "36",
+    value: "1",
+    changed: "1;33",
+    warning: "33",
+    report_ids: &["32", "34", "35", "36", "33", "31"],
+};
+
+pub const LIGHT: Palette = Palette {
+    usage: "34",
+    value: "1",
+    changed: "1;31",
+    warning: "35",
+    report_ids: &["32", "34", "35", "36", "31", "90"],
+};
+
+impl Theme {
+    pub fn palette(self) -> &'static Palette {
+        match self {
+            Theme::Dark => &DARK,
+            Theme::Light => &LIGHT,
+        }
+    }
+}
+
+pub fn paint(code: &str, text: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Renderer {
+    color: bool,
+    palette: &'static Palette,
+}
+
+impl Renderer {
+    pub fn new(choice: ColorChoice, theme: Theme) -> Self {
+        let color = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        };
+
+        Self {
+            color,
+            palette: theme.palette(),
+        }
+    }
+
+    pub fn color(&self) -> bool {
+        self.color
+    }
+
+    pub fn palette(&self) -> &'static Palette {
+        self.palette
+    }
+
+    fn paint(&self, code: &str, text: &str) -> String {
+        match self.color {
+            true => paint(code, text),
+            false => text.to_string(),
+        }
+    }
+
+    pub fn usage(&self, text: &str) -> String {
+        self.paint(self.palette.usage, text)
+    }
+
+    pub fn value(&self, text: &str) -> String {
+        self.paint(self.palette.value, text)
+    }
+
+    pub fn warning(&self, text: &str) -> String {
+        self.paint(self.palette.warning, text)
+    }
+
+    // Reports with the same ID always get the same color
+    pub fn report_id(&self, report_id: Option<u8>, text: &str) -> String {
+        match report_id {
+            Some(id) => {
+                let ids = self.palette.report_ids;
+                self.paint(ids[id as usize % ids.len()], text)
+            }
+            None => text.to_string(),
+        }
+    }
+
+    // Values only, physical values with their units where known, e.g.
+    // `[[true,false,-3], [Volume Increment], [12.5 cm/s]]`
+    pub fn compact(&self, collection: &Collection<Vec<Measured>>) -> String {
+        let items = collection
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                CollectionItem::Collection(c) => Some(self.compact(c)),
+                CollectionItem::Item(inputs) => {
+                    if inputs.is_empty() {
+                        return None;
+                    }
+
+                    Some(
+                        inputs
+                            .iter()
+                            .map(|(i, measurement)| match (i.value, measurement) {
+                                (_, Some(m)) => self.value(&m.to_string()),
+                                (InputValue::Bool(v), _) => self.value(&v.to_string()),
+                                (InputValue::UInt(v), _) => self.value(&v.to_string()),
+                                (InputValue::Int(v), _) => self.value(&v.to_string()),
+                                (InputValue::Selected, _) => {
+                                    self.usage(&usage::short_name(i.usage))
+                                }
+                                (InputValue::None, _) => "None".to_string(),
+                                _ => i.to_string(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    )
+                }
+            })
+            .collect::<Vec<_>>();
+
+        format!("[{}]", items.join(", "))
+    }
+
+    // Named values with their usages, like the parser's Display but colored
+    pub fn full(&self, collection: &Collection<Vec<Measured>>) -> String {
+        let items = collection
+            .items
+            .iter()
+            .map(|item| match item {
+                CollectionItem::Collection(c) => self.full(c),
+                CollectionItem::Item(inputs) => format!(
+                    "[{}]",
+                    inputs
+                        .iter()
+                        .map(|(input, measurement)| self.input(input, measurement.as_ref()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "{:?}({:02x} {:02x})[{}]",
+            collection.collection_type,
+            collection.usage.0,
+            collection.usage.1,
+            items.join(", ")
+        )
+    }
+
+    fn input(&self, input: &Input, measurement: Option<&Measurement>) -> String {
+        let name = match usage::usage_name(input.usage) {
+            Some(name) => format!("{} ", self.usage(&name)),
+            None => String::new(),
+        };
+        let usage = format!("({:02x} {:02x})", input.usage.0, input.usage.1);
+
+        if let Some(measurement) = measurement {
+            let value = self.value(&measurement.to_string());
+            return format!("{}{}: {}", name, usage, value);
+        }
+
+        match input.value {
+            InputValue::Bool(b) => format!("{}{}: {}", name, usage, self.value(&b.to_string())),
+            InputValue::UInt(u) => format!("{}{}: {}", name, usage, self.value(&u.to_string())),
+            InputValue::Int(i) => format!("{}{}: {}", name, usage, self.value(&i.to_string())),
+            InputValue::Selected => format!("{}{}", name, usage),
+            InputValue::None => "None".to_string(),
+            _ => input.to_string(),
+        }
+    }
+
+    // Indented layout of a top level collection, reports colored by their ID. Collections
+    // nested deeper than `max_depth` are collapsed into a single line.
+    pub fn layout(&self, collection: &Collection<Report>, max_depth: Option<usize>) -> String {
+        let mut output = String::new();
+        self.layout_collection(&mut output, collection, 0, max_depth);
+
+        output
+    }
+
+    fn layout_collection(
+        &self,
+        output: &mut String,
+        collection: &Collection<Report>,
+        depth: usize,
+        max_depth: Option<usize>,
+    ) {
+        let indent = "  ".repeat(depth);
+
+        output.push_str(&format!(
+            "{}{:?} collection: {}",
+            indent,
+            collection.collection_type,
+            self.usage(&usage::describe(collection.usage))
+        ));
+
+        if max_depth.is_some_and(|max| depth >= max) {
+            output.push_str(&format!(" ({} reports)\n", collection.flatten().len()));
+            return;
+        }
+        output.push('\n');
+
+        for item in &collection.items {
+            match item {
+                CollectionItem::Collection(c) => {
+                    self.layout_collection(output, c, depth + 1, max_depth)
+                }
+                CollectionItem::Item(report) => output.push_str(&format!(
+                    "{}  {}\n",
+                    indent,
+                    self.report_id(report.report_id, &report.to_string())
+                )),
+            }
+        }
+    }
+}
+
+// Inputs without physical values, rendering logical values only
+pub fn without_units(collection: &Collection<Vec<Input>>) -> Collection<Vec<Measured>> {
+    collection.map(|inputs| Some(inputs.iter().map(|input| (*input, None)).collect()))
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{DescriptorBuilder, InputItemData};
+
+    use super::{without_units, ColorChoice, Renderer, Theme};
+
+    #[test]
+    fn renders_with_and_without_colors() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x0c)
+            .usage(0x01)
+            .collection(hid_parser::CollectionType::Application)
+            .report_id(2)
+            .usage(0xe9)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+        let parsed = parser.parse_input(&[0x02, 0x01]);
+        let inputs = without_units(&parsed);
+
+        let plain = Renderer::new(ColorChoice::Never, Theme::Dark);
+        assert_eq!(plain.compact(&inputs), "[true]");
+        assert_eq!(
+            plain.full(&inputs),
+            parsed.to_string(),
+            "matches the parser's own format"
+        );
+        assert_eq!(
+            plain.layout(&parser.collections()[0], None),
+            parser.to_string()
+        );
+        assert_eq!(
+            plain.layout(&parser.collections()[0], Some(0)),
+            "Application collection: Consumer / Consumer Control (1 reports)\n"
+        );
+
+        let dark = Renderer::new(ColorChoice::Always, Theme::Dark);
+        assert_eq!(dark.compact(&inputs), "[\x1b[1mtrue\x1b[0m]");
+        assert!(dark
+            .full(&inputs)
+            .contains("\x1b[36mVolume Increment\x1b[0m (0c e9): \x1b[1mtrue\x1b[0m"));
+
+        let light = Renderer::new(ColorChoice::Always, Theme::Light);
+        assert_eq!(light.report_id(Some(2), "x"), "\x1b[35mx\x1b[0m");
+        assert_eq!(light.report_id(None, "x"), "x");
+    }
+
+    #[test]
+    fn renders_physical_values_with_units() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x04)
+            .collection(hid_parser::CollectionType::Application)
+            .usage(0x35)
+            .logical_minimum(0)
+            .logical_maximum(359)
+            .unit(0x14)
+            .report_size(16)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+        let report = [0x3b, 0x01];
+
+        let plain = Renderer::new(ColorChoice::Never, Theme::Dark);
+        assert_eq!(
+            plain.compact(&parser.parse_input_measured(&report)),
+            "[315 deg]"
+        );
+        assert!(plain
+            .full(&parser.parse_input_measured(&report))
+            .contains("Rz (01 35): 315 deg"));
+        assert_eq!(
+            plain.compact(&without_units(&parser.parse_input(&report))),
+            "[315]"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+// Click-to-report latency against an external trigger
+//
+// A helper board watching the physical actuation (a photodiode on a screen flash or an LED, a
+// contact on the switch, a GPIO driving a solenoid) sends a line starting with `T` over a serial
+// port the moment it sees it, optionally followed by the microseconds between the edge and
+// sending the line (`T 250`), e.g. after debouncing. Everything else it sends (boot messages,
+// comments) is ignored. The latency is the time from the trigger to the first input report
+// which changes after it, minus --trigger-offset for the time the line takes over the serial
+// port (measure it with a loopback, USB CDC is usually around 1 ms).
+//
+// The serial line can arrive after the report it triggered, so triggers and changed reports
+// are matched in both orders within the window. Triggers without a report in the window are
+// missed, changed reports without a trigger (releases, movement) are counted apart.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::mpsc::{self, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{backend::HidIo, bridge, latency::spread, output::note};
+
+const READ_TIMEOUT_MS: i32 = 5;
+
+#[derive(Debug)]
+pub struct RigOptions {
+    pub port: PathBuf,
+    pub baud: u32,
+    pub clicks: usize,
+    pub window: Duration,
+    pub offset: Duration, // serial transport latency of the trigger line
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RigResult {
+    pub latencies: Vec<Duration>,
+    pub missed: usize,
+    pub untriggered: usize,
+}
+
+impl Display for RigResult {
+    // e.g. `Click to report: 11.2 ms (9.8 - 14.0) over 20 clicks`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        match spread(&self.latencies) {
+            Some((min, median, max)) => write!(
+                f,
+                "Click to report: {:.1} ms ({:.1} - {:.1}) over {} clicks",
+                ms(median),
+                ms(min),
+                ms(max),
+                self.latencies.len()
+            )?,
+            None => write!(f, "Click to report: no clicks measured")?,
+        }
+        if self.missed > 0 {
+            write!(f, "\n{} triggers without a report", self.missed)?;
+        }
+        if self.untriggered > 0 {
+            write!(
+                f,
+                "\n{} reports without a trigger (releases or movement)",
+                self.untriggered
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// The delay the helper reports between the edge and its line, None for lines which aren't
+// triggers
+pub fn parse_trigger(line: &str) -> Option<Duration> {
+    let rest = line.trim().strip_prefix('T')?;
+    match rest.trim() {
+        "" => Some(Duration::ZERO),
+        micros => micros.parse().ok().map(Duration::from_micros),
+    }
+}
+
+// Pairs triggers with changed reports, times from the start of the measurement
+#[derive(Debug)]
+pub struct Correlator {
+    window: Duration,
+    triggers: VecDeque<Duration>,
+    reports: VecDeque<Duration>,
+    pub result: RigResult,
+}
+
+impl Correlator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            triggers: VecDeque::new(),
+            reports: VecDeque::new(),
+            result: RigResult::default(),
+        }
+    }
+
+    pub fn trigger(&mut self, at: Duration) {
+        // a report which came in before the trigger line
+        match self.reports.iter().position(|report| *report >= at) {
+            Some(i) => {
+                let report = self.reports.remove(i).unwrap_or_default();
+                self.result.untriggered += i;
+                self.reports.drain(..i);
+                self.result.latencies.push(report - at);
+            }
+            None => self.triggers.push_back(at),
+        }
+    }
+
+    pub fn report(&mut self, at: Duration) {
+        match self.triggers.pop_front() {
+            Some(trigger) if trigger <= at => self.result.latencies.push(at - trigger),
+            Some(trigger) => {
+                self.triggers.push_front(trigger);
+                self.reports.push_back(at);
+            }
+            None => self.reports.push_back(at),
+        }
+    }
+
+    // Gives up on triggers and reports older than the window
+    pub fn expire(&mut self, now: Duration) {
+        let expired = |at: &Duration| *at + self.window < now;
+        while self.triggers.front().is_some_and(expired) {
+            self.triggers.pop_front();
+            self.result.missed += 1;
+        }
+        while self.reports.front().is_some_and(expired) {
+            self.reports.pop_front();
+            self.result.untriggered += 1;
+        }
+    }
+}
+
+pub fn run(device: &dyn HidIo, options: &RigOptions) -> Result<RigResult> {
+    bridge::configure(&options.port, options.baud)?;
+    let port = File::open(&options.port)
+        .with_context(|| format!("Cannot open {}", options.port.display()))?;
+
+    // stamped as the lines arrive, not when the loop gets to them
+    let (sender, triggers) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(port).lines().map_while(Result::ok) {
+            let arrived = Instant::now();
+            if let Some(delay) = parse_trigger(&line) {
+                if sender
+                    .send(arrived.checked_sub(delay).unwrap_or(arrived))
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    });
+
+    note!(
+        "Waiting for {} clicks on the trigger at {}",
+        options.clicks,
+        options.port.display()
+    );
+    let start = Instant::now();
+    let since_start = |at: Instant| at.saturating_duration_since(start);
+    let mut correlator = Correlator::new(options.window);
+    let mut previous = vec![];
+    let mut buf = [0u8; 64];
+
+    while correlator.result.latencies.len() < options.clicks {
+        let n = device.read_timeout(&mut buf, READ_TIMEOUT_MS)?;
+        let now = Instant::now();
+        if n > 0 && buf[..n] != previous[..] {
+            previous = buf[..n].to_vec();
+            correlator.report(since_start(now));
+        }
+
+        loop {
+            match triggers.try_recv() {
+                Ok(at) => {
+                    let at = since_start(at).saturating_sub(options.offset);
+                    correlator.trigger(at);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return match correlator.result.latencies.is_empty() {
+                        true => Err(anyhow!("The trigger port closed before any click")),
+                        false => Ok(correlator.result),
+                    };
+                }
+            }
+        }
+        correlator.expire(since_start(now));
+    }
+
+    Ok(correlator.result)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{parse_trigger, Correlator};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn parses_trigger_lines() {
+        assert_eq!(parse_trigger("T"), Some(Duration::ZERO));
+        assert_eq!(parse_trigger("T 250\r"), Some(Duration::from_micros(250)));
+        assert_eq!(parse_trigger("rig v1.2 ready"), None);
+        assert_eq!(parse_trigger("T soon"), None);
+    }
+
+    #[test]
+    fn pairs_triggers_with_reports_in_either_order() {
+        let mut correlator = Correlator::new(ms(100));
+
+        // the usual order
+        correlator.trigger(ms(1000));
+        correlator.report(ms(1012));
+        // the report overtook the trigger line
+        correlator.report(ms(2009));
+        correlator.trigger(ms(2000));
+        // a release without a trigger, and a trigger without a report
+        correlator.report(ms(2500));
+        correlator.trigger(ms(3000));
+        correlator.expire(ms(3200));
+
+        assert_eq!(correlator.result.latencies, [ms(12), ms(9)]);
+        assert_eq!(correlator.result.missed, 1);
+        assert_eq!(correlator.result.untriggered, 1);
+    }
+}
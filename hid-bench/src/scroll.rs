@@ -0,0 +1,248 @@
+// Wheels and high-resolution scrolling
+//
+// Shows and sets the Resolution Multiplier features of a device, then logs wheel movements both
+// in the units the device reports and in detents, using the multipliers read back from the
+// device. Operating systems set the multipliers themselves when a device connects (Linux and
+// Windows pick the maximum), so a value set here may not stay for long.
+
+use std::{collections::BTreeMap, str::FromStr, time::Instant};
+
+use anyhow::{anyhow, Result};
+use hidapi::HidDevice;
+
+use hid_parser::{
+    scroll::{ResolutionMultiplier, ScrollAxis},
+    Parser, ReportKind,
+};
+
+use crate::output::outln;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiplierSetting {
+    Off, // the logical minimum
+    Max,
+    Value(i32),
+}
+
+impl FromStr for MultiplierSetting {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "off" => Ok(MultiplierSetting::Off),
+            "max" => Ok(MultiplierSetting::Max),
+            _ => s
+                .parse()
+                .map(MultiplierSetting::Value)
+                .map_err(|_| anyhow!("Multiplier must be a logical value, `max` or `off`")),
+        }
+    }
+}
+
+impl MultiplierSetting {
+    fn value(&self, multiplier: &ResolutionMultiplier) -> i32 {
+        let (min, max) = multiplier.logical_range();
+
+        match self {
+            MultiplierSetting::Off => min,
+            MultiplierSetting::Max => max,
+            MultiplierSetting::Value(value) => *value,
+        }
+    }
+}
+
+// Running totals of wheel movements
+#[derive(Debug, Default)]
+pub struct ScrollLog {
+    factors: BTreeMap<ScrollAxis, f32>, // steps per detent
+    totals: BTreeMap<ScrollAxis, f32>,  // in detents
+}
+
+impl ScrollLog {
+    pub fn new(factors: BTreeMap<ScrollAxis, f32>) -> Self {
+        ScrollLog {
+            factors,
+            totals: BTreeMap::new(),
+        }
+    }
+
+    // e.g. `Wheel: -3 (-0.375 detents, total -1.250)`
+    pub fn line(&mut self, axis: ScrollAxis, units: i32) -> String {
+        let factor = self.factors.get(&axis).copied().unwrap_or(1.0);
+        let detents = units as f32 / factor;
+
+        let total = self.totals.entry(axis).or_default();
+        *total += detents;
+
+        format!(
+            "{}: {:+} ({:+.3} detents, total {:+.3})",
+            axis, units, detents, total
+        )
+    }
+}
+
+pub fn run(
+    hid_device: &HidDevice,
+    parser: &Parser,
+    set: Option<MultiplierSetting>,
+    log: bool,
+) -> Result<()> {
+    let multipliers = parser.resolution_multipliers();
+    if multipliers.is_empty() {
+        if set.is_some() {
+            return Err(anyhow!("The device has no resolution multiplier"));
+        }
+        outln!("No resolution multiplier, the wheels report whole detents");
+    }
+
+    let mut report_ids = multipliers
+        .iter()
+        .map(|multiplier| multiplier.report_id())
+        .collect::<Vec<_>>();
+    report_ids.dedup();
+
+    let mut factors = BTreeMap::new();
+    for report_id in report_ids {
+        // multipliers sharing a feature report are set together
+        let multipliers = multipliers
+            .iter()
+            .filter(|multiplier| multiplier.report_id() == report_id)
+            .collect::<Vec<_>>();
+        let mut feature = get_feature(hid_device, parser, report_id)?;
+
+        if let Some(setting) = set {
+            for multiplier in &multipliers {
+                let value = setting.value(multiplier);
+                if !multiplier.write(&mut feature, value) {
+                    let (min, max) = multiplier.logical_range();
+                    return Err(anyhow!(
+                        "Multiplier {} is outside the logical range {} - {}",
+                        value,
+                        min,
+                        max
+                    ));
+                }
+            }
+            send_feature(hid_device, report_id, &feature)?;
+
+            // show what the device accepted
+            feature = get_feature(hid_device, parser, report_id)?;
+        }
+
+        for multiplier in multipliers {
+            let value = multiplier
+                .read(&feature)
+                .ok_or_else(|| anyhow!("Cannot read the resolution multiplier"))?;
+            let factor = multiplier.factor(value);
+            let (min, max) = multiplier.logical_range();
+
+            let axes = match multiplier.axes.is_empty() {
+                true => "Resolution Multiplier".to_string(),
+                false => multiplier
+                    .axes
+                    .iter()
+                    .map(|axis| axis.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            };
+            outln!(
+                "{}: {} steps per detent (logical {}, range {} - {})",
+                axes,
+                factor,
+                value,
+                min,
+                max
+            );
+
+            for axis in &multiplier.axes {
+                factors.insert(*axis, factor);
+            }
+        }
+    }
+
+    if !log {
+        return Ok(());
+    }
+
+    let mut scroll_log = ScrollLog::new(factors);
+    let mut buf = [0u8; 64];
+    let start = Instant::now();
+
+    loop {
+        let n = hid_device.read(&mut buf)?;
+        let elapsed = start.elapsed().as_millis();
+
+        for (axis, units) in parser.scroll(&buf[0..n]) {
+            outln!("[+{:06} ms]: {}", elapsed, scroll_log.line(axis, units));
+        }
+    }
+}
+
+// Feature report starting with the report ID, if used
+pub fn get_feature(
+    hid_device: &HidDevice,
+    parser: &Parser,
+    report_id: Option<u8>,
+) -> Result<Vec<u8>> {
+    let length = parser
+        .report_length(ReportKind::Feature, report_id)
+        .unwrap_or(64);
+
+    // hidapi keeps a zero in place of the report ID for devices without them
+    let mut buf = vec![0u8; length + 1];
+    buf[0] = report_id.unwrap_or(0);
+    let n = hid_device.get_feature_report(&mut buf)?;
+
+    Ok(match report_id {
+        Some(_) => buf[0..n].to_vec(),
+        None => buf[1..n.max(1)].to_vec(),
+    })
+}
+
+fn send_feature(hid_device: &HidDevice, report_id: Option<u8>, feature: &[u8]) -> Result<()> {
+    let mut report = vec![];
+    if report_id.is_none() {
+        report.push(0);
+    }
+    report.extend(feature);
+
+    Ok(hid_device.send_feature_report(&report)?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use hid_parser::scroll::ScrollAxis;
+
+    use super::{MultiplierSetting, ScrollLog};
+
+    #[test]
+    fn logs_scrolling_in_units_and_detents() {
+        assert_eq!(
+            "max".parse::<MultiplierSetting>().unwrap(),
+            MultiplierSetting::Max
+        );
+        assert_eq!(
+            "4".parse::<MultiplierSetting>().unwrap(),
+            MultiplierSetting::Value(4)
+        );
+        assert!("high".parse::<MultiplierSetting>().is_err());
+
+        let mut log = ScrollLog::new(BTreeMap::from([(ScrollAxis::Vertical, 8.0)]));
+
+        assert_eq!(
+            log.line(ScrollAxis::Vertical, -3),
+            "Wheel: -3 (-0.375 detents, total -0.375)"
+        );
+        assert_eq!(
+            log.line(ScrollAxis::Vertical, -7),
+            "Wheel: -7 (-0.875 detents, total -1.250)"
+        );
+        // without a multiplier every step is a detent
+        assert_eq!(
+            log.line(ScrollAxis::Horizontal, 1),
+            "AC Pan: +1 (+1.000 detents, total +1.000)"
+        );
+    }
+}
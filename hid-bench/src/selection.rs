@@ -0,0 +1,148 @@
+// Choosing one of the top level collections of a descriptor
+//
+// Interfaces like keyboards with media keys describe several application collections, which
+// can be picked by index or by their usage, e.g. "Consumer Control".
+
+use std::{convert::Infallible, fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+use hid_parser::{usage, Parser};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CollectionSelector {
+    Index(usize),
+    Usage(String), // usage name or full description, ignoring case
+}
+
+impl FromStr for CollectionSelector {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(index) => CollectionSelector::Index(index),
+            Err(_) => CollectionSelector::Usage(s.to_string()),
+        })
+    }
+}
+
+impl Display for CollectionSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CollectionSelector::Index(index) => write!(f, "#{}", index),
+            CollectionSelector::Usage(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+impl CollectionSelector {
+    // Index of the selected top level collection
+    pub fn find(&self, parser: &Parser) -> Result<usize> {
+        let collections = parser.collections();
+
+        let found = match self {
+            CollectionSelector::Index(index) => (*index < collections.len()).then_some(*index),
+            CollectionSelector::Usage(name) => collections.iter().position(|collection| {
+                let usage = collection.usage;
+
+                usage::usage_name(usage).is_some_and(|n| n.eq_ignore_ascii_case(name))
+                    || usage::describe(usage).eq_ignore_ascii_case(name)
+            }),
+        };
+
+        found.ok_or_else(|| {
+            anyhow!(
+                "There is no collection {}, the descriptor has {}",
+                self,
+                available(parser)
+            )
+        })
+    }
+
+    // Parser for the selected collection only
+    pub fn select(&self, parser: Parser) -> Result<Parser> {
+        let index = self.find(&parser)?;
+
+        Ok(parser.select(index).expect("found collections exist"))
+    }
+}
+
+// e.g. `0: Generic Desktop / Mouse, 1: Consumer / Consumer Control`
+fn available(parser: &Parser) -> String {
+    parser
+        .collections()
+        .iter()
+        .enumerate()
+        .map(|(index, collection)| format!("{}: {}", index, usage::describe(collection.usage)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData, Parser};
+
+    use super::CollectionSelector;
+
+    fn mouse_and_consumer() -> Parser {
+        const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+
+        DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(8)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(8)
+            .input(VARIABLE)
+            .end_collection()
+            .usage_page(0x0c)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .report_id(2)
+            .usage(0xe9)
+            .report_count(1)
+            .input(VARIABLE)
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn selects_collections_by_index_or_usage() {
+        let parser = mouse_and_consumer();
+
+        let selector = "Consumer Control".parse::<CollectionSelector>().unwrap();
+        assert_eq!(selector.find(&parser).unwrap(), 1);
+        assert_eq!(
+            "generic desktop / mouse"
+                .parse::<CollectionSelector>()
+                .unwrap()
+                .find(&parser)
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            "1".parse::<CollectionSelector>().unwrap(),
+            CollectionSelector::Index(1)
+        );
+
+        assert_eq!(
+            CollectionSelector::Index(2)
+                .find(&parser)
+                .unwrap_err()
+                .to_string(),
+            "There is no collection #2, the descriptor has \
+             0: Generic Desktop / Mouse, 1: Consumer / Consumer Control"
+        );
+
+        let consumer = selector.select(parser).unwrap();
+        assert_eq!(consumer.usage(), (0x0c, 0x01));
+        assert_eq!(consumer.reports().len(), 1);
+    }
+}
@@ -0,0 +1,289 @@
+// Output report sequences for firmware endurance tests
+//
+// Sends a given number of reports at a fixed interval. Devices with a loopback mode echo every
+// output report back in an input report, with `verify` each echo is checked against the payload
+// sent, so lost and corrupted reports show up. Feature reports can be read back right after
+// setting them instead, checking the device kept them.
+
+use std::{
+    fmt::Display,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use hid_parser::capture::Direction;
+
+use crate::{
+    backend::HidIo,
+    stress::{Payloads, StressKind},
+    transfers::TransferLog,
+};
+
+#[derive(Debug)]
+pub struct SendOptions {
+    pub kind: StressKind,
+    pub report_id: Option<u8>, // None for devices without report IDs
+    pub repeat: u64,
+    pub interval: Duration,
+    pub verify: Option<Verify>,
+    pub read_back: bool, // read every feature report back after setting it
+}
+
+// Where to expect the echo of every report sent
+#[derive(Debug)]
+pub struct Verify {
+    pub report_id: Option<u8>, // of the echoing input report, None for devices without IDs
+    pub timeout: Duration,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Echo {
+    Matched,
+    Mismatched(Vec<u8>),
+}
+
+#[derive(Debug, Default)]
+pub struct SendSummary {
+    pub elapsed: Duration,
+    pub sent: u64,
+    pub failed: u64,
+    pub echoed: u64,
+    pub mismatched: u64,
+    pub missing: u64,
+    pub read_back: u64,
+    pub differing: u64, // feature reports read back which aren't the ones set
+    // number of the report, sent and echoed payload
+    pub first_mismatch: Option<(u64, Vec<u8>, Vec<u8>)>,
+    pub last_error: Option<String>,
+}
+
+impl SendSummary {
+    pub fn passed(&self) -> bool {
+        self.failed == 0 && self.mismatched == 0 && self.missing == 0 && self.differing == 0
+    }
+}
+
+impl Display for SendSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Sent {} reports in {:.1} s, failed writes: {}",
+            self.sent,
+            self.elapsed.as_secs_f64(),
+            self.failed
+        )?;
+        if self.echoed + self.mismatched + self.missing > 0 {
+            write!(
+                f,
+                "\nEchoed: {}, mismatched: {}, missing: {}",
+                self.echoed, self.mismatched, self.missing
+            )?;
+        }
+        if self.read_back > 0 {
+            write!(
+                f,
+                "\nRead back: {}, differing: {}",
+                self.read_back, self.differing
+            )?;
+        }
+        if let Some((n, sent, echoed)) = &self.first_mismatch {
+            write!(
+                f,
+                "\nFirst mismatch, report #{}:\n  sent   {}\n  echoed {}",
+                n,
+                hex(sent),
+                hex(echoed)
+            )?;
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, "\nLast error: {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Durations as "5ms", "250us" or "1.5s", plain numbers are milliseconds
+pub fn parse_interval(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "ms"),
+    };
+    let seconds = match unit {
+        "s" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        _ => return Err(anyhow!("Unknown unit '{}', use s, ms or us", unit)),
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 => Ok(Duration::from_secs_f64(number * seconds)),
+        _ => Err(anyhow!("Expected a duration like 5ms, found '{}'", s)),
+    }
+}
+
+// Checks an input report (starting with the report ID on devices with IDs) against a payload
+// sent. None if the input report is a different one.
+pub fn check_echo(sent: &[u8], input: &[u8], report_id: Option<u8>) -> Option<Echo> {
+    let data = match report_id {
+        Some(id) => input.strip_prefix(&[id])?,
+        None => input,
+    };
+
+    // echoes may be padded to a longer report
+    match data.starts_with(sent) {
+        true => Some(Echo::Matched),
+        false => Some(Echo::Mismatched(data.to_vec())),
+    }
+}
+
+// Sends the payloads, logging the reports both ways to `log` if given
+pub fn run(
+    device: &dyn HidIo,
+    options: &SendOptions,
+    payloads: Payloads,
+    mut log: Option<&mut TransferLog>,
+) -> Result<SendSummary> {
+    let mut summary = SendSummary::default();
+    let mut buf = [0u8; 1024];
+    let start = Instant::now();
+
+    for (n, payload) in payloads.take(options.repeat as usize).enumerate() {
+        let due = start + options.interval * n as u32;
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+
+        // an echo of an earlier report arriving late must not count for this one
+        if options.verify.is_some() {
+            loop {
+                let length = device.read_timeout(&mut buf, 0)?;
+                if length == 0 {
+                    break;
+                }
+                if let Some(log) = &mut log {
+                    log.hidapi(Direction::In, &buf[..length]);
+                }
+            }
+        }
+
+        // hidapi takes the report ID (0 without IDs) in front of the data
+        let mut report = vec![options.report_id.unwrap_or(0)];
+        report.extend(&payload);
+
+        let (result, direction) = match options.kind {
+            StressKind::Output => (device.write(&report).map(|_| ()), Direction::Out),
+            StressKind::Feature => (device.send_feature_report(&report), Direction::FeatureOut),
+        };
+        summary.sent += 1;
+        if let Err(err) = result {
+            summary.failed += 1;
+            summary.last_error = Some(err.to_string());
+            continue;
+        }
+        if let Some(log) = &mut log {
+            log.hidapi(direction, &report);
+        }
+
+        if options.read_back && options.kind == StressKind::Feature {
+            buf[0] = report[0];
+            let length = device.get_feature_report(&mut buf)?;
+            if let Some(log) = &mut log {
+                log.hidapi(Direction::FeatureIn, &buf[..length]);
+            }
+            // hidapi gives the report ID (or 0) first, the report may be padded
+            summary.read_back += 1;
+            if !buf[1..length.max(1)].starts_with(&payload) {
+                summary.differing += 1;
+            }
+        }
+
+        let Some(verify) = &options.verify else {
+            continue;
+        };
+        match wait_for_echo(device, verify, &payload, &mut buf, log.as_deref_mut())? {
+            Some(Echo::Matched) => summary.echoed += 1,
+            Some(Echo::Mismatched(echoed)) => {
+                summary.mismatched += 1;
+                summary
+                    .first_mismatch
+                    .get_or_insert((n as u64 + 1, payload, echoed));
+            }
+            None => summary.missing += 1,
+        }
+    }
+
+    summary.elapsed = start.elapsed();
+
+    Ok(summary)
+}
+
+// The first input report with the echo's report ID, None if there is none before the timeout
+fn wait_for_echo(
+    device: &dyn HidIo,
+    verify: &Verify,
+    sent: &[u8],
+    buf: &mut [u8],
+    mut log: Option<&mut TransferLog>,
+) -> Result<Option<Echo>> {
+    let deadline = Instant::now() + verify.timeout;
+
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let length = device.read_timeout(buf, left.as_millis().max(1) as i32)?;
+        if length == 0 {
+            continue;
+        }
+        if let Some(log) = &mut log {
+            log.hidapi(Direction::In, &buf[..length]);
+        }
+        if let Some(echo) = check_echo(sent, &buf[..length], verify.report_id) {
+            return Ok(Some(echo));
+        }
+    }
+
+    Ok(None)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{check_echo, parse_interval, Echo};
+
+    #[test]
+    fn parses_intervals() {
+        assert_eq!(parse_interval("5ms").unwrap(), Duration::from_millis(5));
+        assert_eq!(parse_interval("250us").unwrap(), Duration::from_micros(250));
+        assert_eq!(parse_interval("1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(parse_interval("20").unwrap(), Duration::from_millis(20));
+        assert!(parse_interval("5 minutes").is_err());
+        assert!(parse_interval("-1ms").is_err());
+    }
+
+    #[test]
+    fn checks_echoes() {
+        assert_eq!(
+            check_echo(&[1, 2], &[1, 2, 0, 0], None),
+            Some(Echo::Matched)
+        );
+        assert_eq!(
+            check_echo(&[1, 2], &[5, 1, 2], Some(5)),
+            Some(Echo::Matched)
+        );
+        assert_eq!(check_echo(&[1, 2], &[6, 1, 2], Some(5)), None);
+        assert_eq!(
+            check_echo(&[1, 2], &[5, 1, 3], Some(5)),
+            Some(Echo::Mismatched(vec![1, 3]))
+        );
+    }
+}
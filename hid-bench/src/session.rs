@@ -0,0 +1,105 @@
+// A device opened for a command: its report descriptors and the interfaces for report I/O
+//
+// Where the descriptors come from hidapi (Windows, or devices libusb cannot reach), the devices
+// opened to read them are kept and used for the reports too. Opening an interface a second time
+// fails on platforms granting exclusive access, and some devices reset when opened repeatedly.
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use hidapi::{HidApi, HidDevice};
+
+use hid_parser::{DeviceModel, Parser};
+
+use crate::{config::DeviceSpec, descriptors, exit::Exit, permissions};
+
+pub struct Session {
+    api: HidApi,
+    spec: DeviceSpec,
+    descriptors: DeviceModel,
+    opened: BTreeMap<u8, HidDevice>,
+}
+
+impl Session {
+    pub fn open(spec: &DeviceSpec) -> Result<Self> {
+        let api = HidApi::new()?;
+        let mut opened = BTreeMap::new();
+        let descriptors = descriptors::report_descriptors_keeping(&api, spec, &mut |i, device| {
+            // the first top level collection, like `open_interface` picks
+            opened.entry(i).or_insert(device);
+        })?;
+
+        Ok(Session {
+            api,
+            spec: spec.clone(),
+            descriptors,
+            opened,
+        })
+    }
+
+    pub fn descriptors(&self) -> &DeviceModel {
+        &self.descriptors
+    }
+
+    // Parsers of every interface, of all its report descriptors combined
+    pub fn parsers(&self) -> impl Iterator<Item = (u8, Parser)> + '_ {
+        self.descriptors
+            .parsers()
+            .map(|(interface, parser)| (interface, parser.clone()))
+    }
+
+    pub fn parser(&self, interface: u8) -> Result<Parser> {
+        if !self.descriptors.contains(interface) {
+            return Err(Exit::DeviceNotFound.error(format!("Cannot find interface #{}", interface)));
+        }
+
+        if let Some(collision) = self.descriptors.collision(interface) {
+            return Err(anyhow!(
+                "Cannot combine the report descriptors of interface #{}: {}",
+                interface,
+                collision
+            ));
+        }
+
+        self.descriptors.parser(interface).cloned().ok_or_else(|| {
+            Exit::DeviceNotFound.error(format!(
+                "No report descriptors for interface #{}",
+                interface
+            ))
+        })
+    }
+
+    // The interface for report I/O, opened with hidapi unless it already is
+    pub fn device(&mut self, interface: u8) -> Result<&HidDevice> {
+        if !self.opened.contains_key(&interface) {
+            let device = open_interface(&self.api, &self.spec, interface)
+                .map_err(|err| permissions::explain(err, self.spec.vid, self.spec.pid))?;
+            self.opened.insert(interface, device);
+        }
+
+        Ok(&self.opened[&interface])
+    }
+
+    // Like `device`, for handing the interface to another thread
+    pub fn device_mut(&mut self, interface: u8) -> Result<&mut HidDevice> {
+        self.device(interface)?;
+
+        Ok(self
+            .opened
+            .get_mut(&interface)
+            .expect("`device` opens the interface"))
+    }
+}
+
+pub fn open_interface(api: &HidApi, device: &DeviceSpec, interface: u8) -> Result<HidDevice> {
+    let info = api
+        .device_list()
+        .find(|info| {
+            descriptors::is_device(info, device) && info.interface_number() == interface as i32
+        })
+        .ok_or_else(|| {
+            Exit::DeviceNotFound.error(format!("Cannot find interface #{} with hidapi", interface))
+        })?;
+
+    Ok(info.open_device(api)?)
+}
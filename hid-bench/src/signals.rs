@@ -0,0 +1,74 @@
+// User signals for long running commands
+//
+// SIGUSR1 and SIGUSR2 only set a flag, the commands check for them between reports. Platforms
+// without them (Windows) never see a signal.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static RECEIVED: [AtomicBool; 2] = [AtomicBool::new(false), AtomicBool::new(false)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserSignal {
+    Usr1,
+    Usr2,
+}
+
+impl UserSignal {
+    // Whether the signal arrived since the last call
+    pub fn take(self) -> bool {
+        RECEIVED[self as usize].swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::sync::atomic::Ordering;
+
+    use libc::c_int;
+
+    use super::RECEIVED;
+
+    const SIGNALS: [c_int; 2] = [libc::SIGUSR1, libc::SIGUSR2];
+
+    extern "C" fn handler(signum: c_int) {
+        if let Some(i) = SIGNALS.iter().position(|s| *s == signum) {
+            RECEIVED[i].store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn listen() -> bool {
+        SIGNALS.iter().all(|signum| {
+            let handler = handler as extern "C" fn(c_int) as libc::sighandler_t;
+            // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+            unsafe { libc::signal(*signum, handler) != libc::SIG_ERR }
+        })
+    }
+}
+
+// Starts noting SIGUSR1 and SIGUSR2 instead of terminating on them, false if the platform
+// doesn't have them
+#[cfg(unix)]
+pub fn listen() -> bool {
+    unix::listen()
+}
+
+#[cfg(not(unix))]
+pub fn listen() -> bool {
+    false
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::{listen, UserSignal};
+
+    #[test]
+    fn notes_user_signals() {
+        assert!(listen());
+
+        // SAFETY: the handler listen installed is all raising the signal runs
+        unsafe { libc::raise(libc::SIGUSR2) };
+        assert!(UserSignal::Usr2.take());
+        assert!(!UserSignal::Usr2.take());
+        assert!(!UserSignal::Usr1.take());
+    }
+}
@@ -0,0 +1,268 @@
+// Scripted devices for testing software against devices which don't exist
+//
+// A script lists steps setting fields of the input reports of a report descriptor, e.g.
+//
+//     {
+//         "device": "1234:5678",
+//         "name": "Sticky mouse",
+//         "steps": [
+//             { "set": { "Button/Button 1": 1, "Generic Desktop/X": 5 } },
+//             { "delay_ms": 8, "repeat": 3 },
+//             { "delay_ms": 8, "set": { "Button/Button 1": 0, "Generic Desktop/X": 0 } }
+//         ]
+//     }
+//
+// Fields are named by field paths (`PAGE/USAGE`, `X`, `Mouse/Button 1`, see `FieldPath`), which
+// have to name one variable input field, in the step's report if it names one. They keep their
+// values until set again, so a relative X of 5 keeps moving right. Each step sends the report with the fields
+// it sets (the one it names with `report_id`, or the report of the previous step without
+// fields) `repeat` times, `delay_ms` apart.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use hid_parser::{usage, FieldPath, Parser, Report, ReportKind, UsageName};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    pub device: Option<String>, // VID:PID
+    pub name: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Step {
+    #[serde(default)]
+    pub delay_ms: u64, // before each report
+    pub report_id: Option<u8>,
+    #[serde(default)]
+    pub set: BTreeMap<String, i32>,
+    #[serde(default = "once")]
+    pub repeat: u32,
+}
+
+fn once() -> u32 {
+    1
+}
+
+// An input report at its time since the start of the script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timed {
+    pub timestamp: Duration,
+    pub bytes: Vec<u8>, // starting with the report ID, if used
+}
+
+// A settable value of an input report
+struct Field<'a> {
+    report: &'a Report,
+    index: usize,
+}
+
+pub fn parse_script(text: &str) -> Result<Script> {
+    serde_json::from_str(text).context("Invalid simulation script")
+}
+
+// Plays the script, returning the input reports it sends
+pub fn play(parser: &Parser, script: &Script) -> Result<Vec<Timed>> {
+    let mut reports: BTreeMap<Option<u8>, Vec<u8>> = BTreeMap::new();
+    for report in parser.reports() {
+        if report.report_type.kind() == ReportKind::Input {
+            let length = parser
+                .report_length(ReportKind::Input, report.report_id)
+                .unwrap_or_default();
+            let bytes = reports
+                .entry(report.report_id)
+                .or_insert_with(|| vec![0; length]);
+            if let (Some(id), Some(first)) = (report.report_id, bytes.first_mut()) {
+                *first = id;
+            }
+        }
+    }
+    let mut current = match reports.keys().next() {
+        Some(id) => *id,
+        None => return Err(anyhow!("The report descriptor has no input reports")),
+    };
+
+    let mut timed = vec![];
+    let mut timestamp = Duration::ZERO;
+    for (n, step) in script.steps.iter().enumerate() {
+        let context = || format!("Step {}", n + 1);
+
+        let mut fields = vec![];
+        for (name, value) in &step.set {
+            let field = field(parser, name, step.report_id).with_context(context)?;
+            let report = field.report;
+            if *value < report.logical_minimum || *value > report.logical_maximum {
+                return Err(anyhow!(
+                    "{} must be between {} and {}, not {}",
+                    name,
+                    report.logical_minimum,
+                    report.logical_maximum,
+                    value
+                ))
+                .with_context(context);
+            }
+
+            fields.push((field, *value));
+        }
+
+        current = match (step.report_id, fields.first()) {
+            (Some(id), _) => Some(id),
+            (None, Some((field, _))) => field.report.report_id,
+            (None, None) => current,
+        };
+        if fields
+            .iter()
+            .any(|(field, _)| field.report.report_id != current)
+        {
+            return Err(anyhow!("The fields set are in different reports")).with_context(context);
+        }
+        let bytes = reports
+            .get_mut(&current)
+            .ok_or_else(|| anyhow!("There is no input report {}", describe_id(current)))
+            .with_context(context)?;
+
+        for (field, value) in fields {
+            field.report.write_value(bytes, field.index, value);
+        }
+
+        for _ in 0..step.repeat {
+            timestamp += Duration::from_millis(step.delay_ms);
+            timed.push(Timed {
+                timestamp,
+                bytes: bytes.clone(),
+            });
+        }
+    }
+
+    Ok(timed)
+}
+
+// Finds a variable input field by its path, in the given report if any
+fn field<'a>(parser: &'a Parser, name: &str, report_id: Option<u8>) -> Result<Field<'a>> {
+    let mut path: FieldPath = name.parse()?;
+    path.report_id = path.report_id.or(report_id);
+
+    if parser.find_fields(&path, ReportKind::Input).is_empty() {
+        let field = match &path.field {
+            UsageName::Usage(usage) => usage::describe(*usage),
+            UsageName::Name(name) => name.clone(),
+        };
+
+        return Err(anyhow!(
+            "There is no input field {}{}",
+            field,
+            path.report_id
+                .map_or(String::new(), |id| format!(" in report {}", id))
+        ));
+    }
+
+    let address = parser.field(&path, ReportKind::Input)?;
+    match address.value {
+        Some(index) => Ok(Field {
+            report: parser.reports()[address.item],
+            index,
+        }),
+        None => Err(anyhow!(
+            "{} is only in array items, which can't be set",
+            usage::describe(address.usage)
+        )),
+    }
+}
+
+fn describe_id(report_id: Option<u8>) -> String {
+    report_id.map_or("without an ID".to_string(), |id| id.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData, Parser};
+
+    use super::{parse_script, play};
+
+    fn mouse() -> Parser {
+        DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(3)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(3)
+            .input(InputItemData { data: 0x02 })
+            .report_count(5)
+            .input(InputItemData { data: 0x01 })
+            .usage_page(0x01)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn plays_scripted_fields() {
+        let script = parse_script(
+            r#"{
+                "steps": [
+                    { "set": { "Button/Button 2": 1, "Generic Desktop/X": 5 } },
+                    { "delay_ms": 8, "repeat": 2 },
+                    { "delay_ms": 10, "set": { "Button/2": 0, "0x01/0x31": -1 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let reports = play(&mouse(), &script).unwrap();
+        let reports: Vec<_> = reports
+            .iter()
+            .map(|report| (report.timestamp, report.bytes.as_slice()))
+            .collect();
+        assert_eq!(
+            reports,
+            [
+                (Duration::ZERO, [0x01, 0x02, 0x05, 0x00].as_slice()),
+                (Duration::from_millis(8), &[0x01, 0x02, 0x05, 0x00]),
+                (Duration::from_millis(16), &[0x01, 0x02, 0x05, 0x00]),
+                (Duration::from_millis(26), &[0x01, 0x00, 0x05, 0xff]),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_fields_the_device_lacks() {
+        let play_step = |step: &str| {
+            let script = parse_script(&format!(r#"{{ "steps": [{}] }}"#, step)).unwrap();
+            play(&mouse(), &script).map_err(|e| format!("{:#}", e))
+        };
+
+        assert_eq!(
+            play_step(r#"{ "set": { "Generic Desktop/Z": 1 } }"#).unwrap_err(),
+            "Step 1: There is no input field Generic Desktop / Z"
+        );
+        assert_eq!(
+            play_step(r#"{ "set": { "Generic Desktop/X": 200 } }"#).unwrap_err(),
+            "Step 1: Generic Desktop/X must be between -127 and 127, not 200"
+        );
+        assert_eq!(
+            play_step(r#"{ "report_id": 2 }"#).unwrap_err(),
+            "Step 1: There is no input report 2"
+        );
+        assert!(parse_script(r#"{ "steps": [{ "wait": 1 }] }"#).is_err());
+    }
+}
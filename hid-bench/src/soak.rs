@@ -0,0 +1,218 @@
+// Long running stability test
+//
+// Reads input reports for hours, surviving disconnects, and keeps a JSON status file up to
+// date with report counts, disconnects, errors, the report rate and its drift from the rate
+// measured in the first status interval, and the memory use of hid-bench itself.
+
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use hidapi::{HidApi, HidDevice};
+use serde::Serialize;
+
+use crate::{config::DeviceSpec, descriptors, output::outln};
+
+const READ_TIMEOUT_MS: i32 = 1000;
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default, Serialize)]
+pub struct SoakStatus {
+    pub device: String,
+    pub started: u64, // unix time, seconds
+    pub elapsed_s: u64,
+    pub connected: bool,
+    pub reports: u64,
+    pub disconnects: u64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    pub baseline_rate: Option<f64>, // reports per second in the first interval
+    pub rate: Option<f64>,          // reports per second in the last interval
+    pub rate_drift_percent: Option<f64>,
+    pub max_rate_drift_percent: f64,
+    pub memory_kb: Option<u64>,
+}
+
+impl SoakStatus {
+    // Updates the rates with the reports seen during the last status interval
+    pub fn record_interval(&mut self, reports: u64, interval: Duration) {
+        let rate = reports as f64 / interval.as_secs_f64();
+        self.rate = Some(rate);
+
+        // a device which didn't send anything yet (e.g. idle keyboard) has no baseline
+        let baseline = match self.baseline_rate {
+            Some(baseline) => baseline,
+            None if reports > 0 => {
+                self.baseline_rate = Some(rate);
+                rate
+            }
+            None => return,
+        };
+
+        let drift = (rate - baseline) / baseline * 100.0;
+        self.rate_drift_percent = Some(drift);
+        if drift.abs() > self.max_rate_drift_percent.abs() {
+            self.max_rate_drift_percent = drift;
+        }
+    }
+
+    fn summary(&self) -> String {
+        format!(
+            "[+{:06} s]: {} reports, {:.1}/s (drift {:+.1}%), {} disconnects, {} errors{}",
+            self.elapsed_s,
+            self.reports,
+            self.rate.unwrap_or_default(),
+            self.rate_drift_percent.unwrap_or_default(),
+            self.disconnects,
+            self.errors,
+            match self.memory_kb {
+                Some(kb) => format!(", {} kB", kb),
+                None => String::new(),
+            }
+        )
+    }
+}
+
+pub fn run(
+    spec: &DeviceSpec,
+    interface: Option<u8>,
+    duration: Duration,
+    status_path: &Path,
+    status_interval: Duration,
+) -> Result<SoakStatus> {
+    let start = Instant::now();
+    let mut status = SoakStatus {
+        device: format!("{:04x}:{:04x}", spec.vid, spec.pid),
+        started: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        ..Default::default()
+    };
+
+    let mut device = None;
+    let mut buf = [0u8; 64];
+    let mut interval_start = Instant::now();
+    let mut interval_reports = 0;
+
+    while start.elapsed() < duration {
+        match &device {
+            None => match open(spec, interface) {
+                Ok(opened) => {
+                    status.connected = true;
+                    device = Some(opened);
+                }
+                Err(_) => thread::sleep(RECONNECT_INTERVAL),
+            },
+            Some(hid_device) => match hid_device.read_timeout(&mut buf, READ_TIMEOUT_MS) {
+                Ok(0) => (),
+                Ok(_) => {
+                    status.reports += 1;
+                    interval_reports += 1;
+                }
+                Err(err) => {
+                    // reading only fails when the device went away
+                    status.errors += 1;
+                    status.disconnects += 1;
+                    status.last_error = Some(err.to_string());
+                    status.connected = false;
+                    device = None;
+                }
+            },
+        }
+
+        if interval_start.elapsed() >= status_interval {
+            status.record_interval(interval_reports, interval_start.elapsed());
+            status.elapsed_s = start.elapsed().as_secs();
+            status.memory_kb = memory_kb();
+
+            outln!("{}", status.summary());
+            write_status(status_path, &status)?;
+
+            interval_start = Instant::now();
+            interval_reports = 0;
+        }
+    }
+
+    status.elapsed_s = start.elapsed().as_secs();
+    status.memory_kb = memory_kb();
+    write_status(status_path, &status)?;
+
+    Ok(status)
+}
+
+fn open(spec: &DeviceSpec, interface: Option<u8>) -> Result<HidDevice> {
+    // a fresh device list, the device may have re-enumerated
+    let api = HidApi::new()?;
+    let info = api
+        .device_list()
+        .find(|info| {
+            descriptors::is_device(info, spec)
+                && interface.is_none_or(|i| info.interface_number() == i as i32)
+        })
+        .context("Device not connected")?;
+
+    Ok(info.open_device(&api)?)
+}
+
+// Written next to the status file and renamed, so readers never see a partial file
+fn write_status(path: &Path, status: &SoakStatus) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+
+    fs::write(&temporary, serde_json::to_string_pretty(status)?)
+        .with_context(|| format!("Cannot write {}", temporary.display()))?;
+    fs::rename(&temporary, path).with_context(|| format!("Cannot write {}", path.display()))?;
+
+    Ok(())
+}
+
+// Resident memory of this process
+#[cfg(target_os = "linux")]
+fn memory_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory_kb() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::SoakStatus;
+
+    #[test]
+    fn tracks_rate_drift() {
+        let mut status = SoakStatus::default();
+
+        status.record_interval(0, Duration::from_secs(10));
+        assert_eq!(status.baseline_rate, None);
+
+        status.record_interval(1000, Duration::from_secs(10));
+        assert_eq!(status.baseline_rate, Some(100.0));
+        assert_eq!(status.rate_drift_percent, Some(0.0));
+
+        status.record_interval(900, Duration::from_secs(10));
+        assert_eq!(status.rate, Some(90.0));
+        assert_eq!(status.rate_drift_percent, Some(-10.0));
+
+        status.record_interval(1050, Duration::from_secs(10));
+        assert_eq!(status.rate_drift_percent, Some(5.0));
+        assert_eq!(status.max_rate_drift_percent, -10.0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reads_own_memory_use() {
+        assert!(super::memory_kb().unwrap() > 0);
+    }
+}
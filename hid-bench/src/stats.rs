@@ -0,0 +1,52 @@
+// Statistics of values recorded by the benches: mean, standard deviation and least squares line
+
+// 0 without values
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len().max(1) as f64
+}
+
+// Population standard deviation, 0 without values
+pub fn deviation(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    let variance =
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len().max(1) as f64;
+
+    variance.sqrt()
+}
+
+// Offset and slope of the least squares line of y against x, None unless there are points at
+// two different x at least
+pub fn linear_fit(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / points.len().max(1) as f64;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / points.len().max(1) as f64;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = points
+        .iter()
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+
+    (sxx > 0.0).then(|| {
+        let slope = sxy / sxx;
+        (mean_y - slope * mean_x, slope)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deviation, linear_fit, mean};
+
+    #[test]
+    fn computes_statistics() {
+        assert_eq!(mean(&[1.0, 2.0, 6.0]), 3.0);
+        assert_eq!(mean(&[]), 0.0);
+        assert_eq!(deviation(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), 2.0);
+        assert_eq!(deviation(&[]), 0.0);
+
+        assert_eq!(
+            linear_fit(&[(0.0, 1.0), (1.0, 3.0), (2.0, 5.0)]),
+            Some((1.0, 2.0))
+        );
+        assert_eq!(linear_fit(&[(1.0, 1.0), (1.0, 3.0)]), None);
+        assert_eq!(linear_fit(&[]), None);
+    }
+}
@@ -0,0 +1,324 @@
+// Dead zones and return to center of gamepad sticks (device sticks)
+//
+// The bench runs in three timed phases, on the stick axes of the standard gamepad mapping
+// (from -1 to 1, triggers are left out):
+//
+// - Rest: the sticks are left alone, the spread of their values and how far their center is
+//   from the middle of the range show how well they are centered.
+// - Sweep: the sticks are moved slowly around from the center. Firmware with a dead zone
+//   reports the center until the stick leaves the zone, then jumps to its edge (or rescales the
+//   rest of the range from it), so the smallest distance from the center reported is the dead
+//   zone's extent. Sticks without one leave the center a count or two at a time.
+// - Flicks: the sticks are pushed to the edge and let go. A release is the last report at the
+//   edge before the value drops, the stick is back once it is within the resting spread of the
+//   center (or a couple of counts of it), or stuck if it isn't back within RETURN_LIMIT.
+
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use hid_parser::{
+    gamepad::{Axis, GamepadMapping},
+    Parser, ReportKind,
+};
+
+use crate::{backend::HidIo, latency::spread, output::note, stats};
+
+// Values at least this far out are at the edge
+const EDGE: f64 = 0.9;
+const RETURN_LIMIT: Duration = Duration::from_secs(1);
+// Jumps off the center longer than this many counts are a dead zone
+const DEAD_ZONE_COUNTS: f64 = 3.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StickAxis {
+    pub axis: Axis,
+    pub count: f64, // the normalized size of one count
+    pub rest: Vec<f64>,
+    pub swept: Vec<f64>,
+    pub returns: Vec<Duration>,
+    pub stuck: usize, // flicks which didn't return within RETURN_LIMIT
+    center: f64,
+    band: f64, // around the center where the stick is back
+    edge: Option<Duration>,
+    released: Option<Duration>,
+}
+
+impl StickAxis {
+    pub fn new(axis: Axis, count: f64) -> Self {
+        Self {
+            axis,
+            count,
+            rest: vec![],
+            swept: vec![],
+            returns: vec![],
+            stuck: 0,
+            center: 0.0,
+            band: 0.0,
+            edge: None,
+            released: None,
+        }
+    }
+
+    // Spread of the values at rest
+    pub fn deviation(&self) -> f64 {
+        stats::deviation(&self.rest)
+    }
+
+    // The value reported most at rest, what firmware with a dead zone snaps to
+    pub fn center(&self) -> f64 {
+        let mut values: Vec<i64> = self
+            .rest
+            .iter()
+            .map(|v| (v / self.count).round() as i64)
+            .collect();
+        values.sort();
+
+        let mut best = (0, 0); // count, value
+        for run in values.chunk_by(|a, b| a == b) {
+            if run.len() > best.0 {
+                best = (run.len(), run[0]);
+            }
+        }
+
+        best.1 as f64 * self.count
+    }
+
+    // The smallest distance from the center reported while sweeping, None when the values
+    // leave the center count by count or the stick wasn't moved
+    pub fn dead_zone(&self) -> Option<f64> {
+        let center = self.center();
+        let nearest = self
+            .swept
+            .iter()
+            .map(|v| (v - center).abs())
+            .filter(|distance| *distance > self.count / 2.0)
+            .fold(f64::INFINITY, f64::min);
+
+        (nearest.is_finite() && nearest > DEAD_ZONE_COUNTS * self.count).then_some(nearest)
+    }
+
+    // Fixes the center and the band the stick returns to after the rest phase
+    pub fn settle(&mut self) {
+        self.center = self.center();
+        self.band = (self.deviation() * 3.0).max(self.count * 2.0);
+    }
+
+    pub fn flick(&mut self, at: Duration, value: f64) {
+        if let Some(released) = self.released {
+            if (value - self.center).abs() <= self.band {
+                self.returns.push(at.saturating_sub(released));
+                self.released = None;
+            }
+        }
+
+        match value.abs() >= EDGE {
+            true => {
+                self.edge = Some(at);
+                self.released = None;
+            }
+            false => self.released = self.released.or(self.edge.take()),
+        }
+    }
+
+    // Gives up on a release not back within RETURN_LIMIT
+    pub fn expire(&mut self, now: Duration) {
+        if self
+            .released
+            .is_some_and(|released| released + RETURN_LIMIT < now)
+        {
+            self.released = None;
+            self.stuck += 1;
+        }
+    }
+}
+
+impl Display for StickAxis {
+    // e.g. `left_x: center +0.0039 (1 count, σ 0.0008), dead zone 0.0980 (13 counts),
+    // back to center in 18.2 ms (12.0 - 30.1) after 5 flicks`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let center = self.center();
+        let counts = |value: f64| {
+            let counts = (value / self.count).round().abs();
+            format!("{} count{}", counts, if counts == 1.0 { "" } else { "s" })
+        };
+
+        write!(
+            f,
+            "{}: center {:+.4} ({}, σ {:.4})",
+            self.axis,
+            center,
+            counts(center),
+            self.deviation()
+        )?;
+        match self.dead_zone() {
+            Some(dead_zone) => write!(f, ", dead zone {:.4} ({})", dead_zone, counts(dead_zone))?,
+            None => write!(f, ", no dead zone")?,
+        }
+
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        match spread(&self.returns) {
+            Some((min, median, max)) => write!(
+                f,
+                ", back to center in {:.1} ms ({:.1} - {:.1}) after {} flick{}",
+                ms(median),
+                ms(min),
+                ms(max),
+                self.returns.len(),
+                if self.returns.len() == 1 { "" } else { "s" }
+            )?,
+            None => write!(f, ", no flicks")?,
+        }
+        if self.stuck > 0 {
+            write!(
+                f,
+                ", {} not back within {} s",
+                self.stuck,
+                RETURN_LIMIT.as_secs()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct StickOptions {
+    pub rest: Duration,
+    pub sweep: Duration,
+    pub flicks: usize,
+    pub timeout: Duration, // of the flick phase
+}
+
+// The normalized size of one count of a stick axis
+fn count(parser: &Parser, usage: (u16, u16)) -> Option<f64> {
+    let report = parser.reports().into_iter().find(|report| {
+        let in_range = match (report.usage_minimum, report.usage_maximum) {
+            (Some(min), Some(max)) => min.0 == usage.0 && (min.1..=max.1).contains(&usage.1),
+            _ => false,
+        };
+        report.report_type.kind() == ReportKind::Input
+            && (report.usages.contains(&usage) || in_range)
+    })?;
+    let range = report.logical_maximum as i64 - report.logical_minimum as i64;
+
+    (range > 0).then(|| 2.0 / range as f64)
+}
+
+pub fn run(
+    device: &dyn HidIo,
+    parser: &Parser,
+    mapping: &GamepadMapping,
+    options: &StickOptions,
+) -> Result<Vec<StickAxis>> {
+    let mut axes: Vec<StickAxis> = mapping
+        .axes
+        .iter()
+        .filter(|(axis, _)| !axis.is_trigger())
+        .filter_map(|(axis, usage)| Some(StickAxis::new(*axis, count(parser, *usage)?)))
+        .collect();
+    let mut buf = [0u8; 64];
+    let mut read = |axes: &mut Vec<StickAxis>| -> Result<Vec<(usize, f64)>> {
+        let n = device.read_timeout(&mut buf, 10)?;
+        let state = match mapping.state(parser, &buf[..n]) {
+            Some(state) if n > 0 => state,
+            _ => return Ok(vec![]),
+        };
+
+        Ok(state
+            .axes
+            .iter()
+            .filter_map(|(axis, value)| {
+                let i = axes.iter().position(|a| a.axis == *axis)?;
+                Some((i, *value as f64))
+            })
+            .collect())
+    };
+
+    note!(
+        "Don't touch the sticks, recording their rest for {} s",
+        options.rest.as_secs_f64()
+    );
+    let start = Instant::now();
+    while start.elapsed() < options.rest {
+        for (i, value) in read(&mut axes)? {
+            axes[i].rest.push(value);
+        }
+    }
+    for axis in &mut axes {
+        axis.settle();
+    }
+
+    note!(
+        "Move the sticks slowly around from the center for {} s",
+        options.sweep.as_secs_f64()
+    );
+    let start = Instant::now();
+    while start.elapsed() < options.sweep {
+        for (i, value) in read(&mut axes)? {
+            axes[i].swept.push(value);
+        }
+    }
+
+    note!(
+        "Push the sticks to the edge and let go, {} times",
+        options.flicks
+    );
+    let start = Instant::now();
+    let flicked = |axes: &[StickAxis]| axes.iter().map(|a| a.returns.len()).sum::<usize>();
+    while flicked(&axes) < options.flicks && start.elapsed() < options.timeout {
+        for (i, value) in read(&mut axes)? {
+            axes[i].flick(start.elapsed(), value);
+        }
+        for axis in &mut axes {
+            axis.expire(start.elapsed());
+        }
+    }
+
+    Ok(axes)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::gamepad::Axis;
+
+    use super::StickAxis;
+
+    #[test]
+    fn finds_dead_zone_and_return_time() {
+        // 8 bit axis, resting a count off the middle, with a firmware dead zone of 12 counts
+        let count = 2.0 / 255.0;
+        let mut axis = StickAxis::new(Axis::LeftX, count);
+        axis.rest = [1.0, 1.0, 1.0, 2.0, 1.0, 0.0].map(|c| c * count).to_vec();
+        axis.swept = [1.0, 1.0, 13.0, 14.0, 20.0, -11.0, 1.0]
+            .map(|c| c * count)
+            .to_vec();
+        axis.settle();
+
+        let ms = Duration::from_millis;
+        // pushed, let go at 100 ms, back at 118 ms
+        axis.flick(ms(90), 0.95);
+        axis.flick(ms(100), 1.0);
+        axis.flick(ms(108), 0.4);
+        axis.flick(ms(118), count);
+        // let go at 500 ms, stuck off center
+        axis.flick(ms(500), -1.0);
+        axis.flick(ms(508), -0.2);
+        axis.expire(ms(1600));
+
+        let dead_zone = axis.dead_zone().unwrap();
+        assert!((dead_zone / count - 12.0).abs() < 1e-9, "{}", dead_zone);
+        assert_eq!(axis.returns, [ms(18)]);
+        assert_eq!(axis.stuck, 1);
+        assert_eq!(
+            axis.to_string(),
+            "left_x: center +0.0078 (1 count, σ 0.0045), dead zone 0.0941 (12 counts), \
+             back to center in 18.0 ms (18.0 - 18.0) after 1 flick, 1 not back within 1 s"
+        );
+    }
+}
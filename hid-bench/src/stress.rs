@@ -0,0 +1,332 @@
+// Output and feature report flooding
+//
+// Sends reports at a fixed rate while draining input reports, to see how the firmware copes
+// under load. Writes the device NAKs for too long show up as slow or timed out writes.
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{backend::HidIo, dump, transfers::TransferLog};
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use hid_parser::capture::Direction;
+
+// Writes taking longer than this are counted as slow, the device is NAKing
+const SLOW_WRITE: Duration = Duration::from_millis(50);
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    Zeros,
+    Ones,
+    Counter,
+    Random,
+    /// Payloads read from --file, one per line in hex, repeated
+    File,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StressKind {
+    Output,
+    Feature,
+}
+
+#[derive(Debug)]
+pub struct StressOptions {
+    pub kind: StressKind,
+    pub report_id: Option<u8>, // None for devices without report IDs
+    pub pattern: Pattern,
+    pub file: Option<PathBuf>, // payloads of the file pattern
+    pub rate: f64,             // reports per second
+    pub duration: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct StressSummary {
+    pub elapsed: Duration,
+    pub sent: u64,
+    pub failed: u64,
+    pub slow: u64,
+    pub max_write: Duration,
+    pub inputs: u64,
+    pub max_input_gap: Option<Duration>,
+    pub last_error: Option<String>,
+}
+
+impl Display for StressSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+
+        writeln!(
+            f,
+            "Sent {} reports in {:.1} s ({:.1}/s)",
+            self.sent,
+            seconds,
+            self.sent as f64 / seconds
+        )?;
+        writeln!(
+            f,
+            "Failed writes: {}, slow writes (> {} ms): {}, slowest write: {:.1} ms",
+            self.failed,
+            SLOW_WRITE.as_millis(),
+            self.slow,
+            self.max_write.as_secs_f64() * 1000.0
+        )?;
+        write!(f, "Input reports received: {}", self.inputs)?;
+        if let Some(gap) = self.max_input_gap {
+            write!(f, ", longest gap: {:.1} ms", gap.as_secs_f64() * 1000.0)?;
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, "\nLast error: {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Reports per second, as "500/s" or "500"
+pub fn parse_rate(rate: &str) -> Result<f64> {
+    let number = rate.strip_suffix("/s").unwrap_or(rate);
+
+    match number.trim().parse::<f64>() {
+        Ok(rate) if rate > 0.0 => Ok(rate),
+        _ => Err(anyhow!(
+            "Rate must be a positive number of reports per second, e.g. 500/s"
+        )),
+    }
+}
+
+// Report payloads (without the report ID) following a pattern
+pub struct Payloads {
+    pattern: Pattern,
+    length: usize,
+    counter: u8,
+    random: u64,
+    file: Vec<Vec<u8>>,
+    line: usize,
+}
+
+impl Payloads {
+    pub fn new(pattern: Pattern, length: usize) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_nanos() as u64)
+            .unwrap_or_default();
+
+        Self {
+            pattern,
+            length,
+            counter: 0,
+            random: seed | 1, // xorshift state must not be zero
+            file: vec![],
+            line: 0,
+        }
+    }
+
+    // Payloads of a file, each padded to `length` bytes, starting over after the last one
+    pub fn from_file(path: &Path, length: usize) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut file = vec![];
+
+        // blank lines and # comments are skipped
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut payload = dump::parse_hex(line)
+                .map_err(|err| anyhow!("{}:{}: {}", path.display(), n + 1, err))?;
+            if payload.len() > length {
+                return Err(anyhow!(
+                    "{}:{}: {} bytes don't fit a report of {} bytes",
+                    path.display(),
+                    n + 1,
+                    payload.len(),
+                    length
+                ));
+            }
+            payload.resize(length, 0);
+            file.push(payload);
+        }
+        if file.is_empty() {
+            return Err(anyhow!("{} has no payloads", path.display()));
+        }
+
+        Ok(Self {
+            file,
+            ..Self::new(Pattern::File, length)
+        })
+    }
+
+    // The pattern's payloads, or the payloads of the file for the file pattern
+    pub fn open(pattern: Pattern, length: usize, file: Option<&Path>) -> Result<Self> {
+        match (pattern, file) {
+            (Pattern::File, Some(path)) => Self::from_file(path, length),
+            (Pattern::File, None) => Err(anyhow!("The file pattern needs a --file")),
+            (pattern, _) => Ok(Self::new(pattern, length)),
+        }
+    }
+
+    // xorshift64, good enough for junk data
+    fn next_random(&mut self) -> u8 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+
+        self.random as u8
+    }
+}
+
+impl Iterator for Payloads {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let payload = match self.pattern {
+            Pattern::Zeros => vec![0; self.length],
+            Pattern::Ones => vec![0xff; self.length],
+            Pattern::Counter => {
+                self.counter = self.counter.wrapping_add(1);
+                vec![self.counter; self.length]
+            }
+            Pattern::Random => (0..self.length).map(|_| self.next_random()).collect(),
+            Pattern::File => {
+                let payload = self.file.get(self.line % self.file.len().max(1))?.clone();
+                self.line += 1;
+                payload
+            }
+        };
+
+        Some(payload)
+    }
+}
+
+// Sends payloads of `length` bytes (without the report ID), logging the reports both ways to
+// `log` if given
+pub fn run(
+    device: &dyn HidIo,
+    options: &StressOptions,
+    payloads: Payloads,
+    mut log: Option<&mut TransferLog>,
+) -> Result<StressSummary> {
+    let mut summary = StressSummary::default();
+    let period = Duration::from_secs_f64(1.0 / options.rate);
+    let start = Instant::now();
+
+    device.set_blocking_mode(false)?;
+
+    let mut buf = [0u8; 64];
+    let mut last_input = None;
+
+    for (n, payload) in payloads.enumerate() {
+        if start.elapsed() >= options.duration {
+            break;
+        }
+
+        // hidapi takes the report ID (0 without IDs) in front of the data
+        let mut report = vec![options.report_id.unwrap_or(0)];
+        report.extend(payload);
+
+        let write_start = Instant::now();
+        let (result, direction) = match options.kind {
+            StressKind::Output => (device.write(&report).map(|_| ()), Direction::Out),
+            StressKind::Feature => (device.send_feature_report(&report), Direction::FeatureOut),
+        };
+        let write_time = write_start.elapsed();
+        if let (Ok(()), Some(log)) = (&result, &mut log) {
+            log.hidapi(direction, &report);
+        }
+
+        summary.sent += 1;
+        summary.max_write = summary.max_write.max(write_time);
+        if write_time > SLOW_WRITE {
+            summary.slow += 1;
+        }
+        if let Err(err) = result {
+            summary.failed += 1;
+            summary.last_error = Some(err.to_string());
+        }
+
+        // drain input reports until the next write is due
+        let next = start + period * (n as u32 + 1);
+        loop {
+            match device.read(&mut buf) {
+                Ok(0) => (),
+                Ok(n) => {
+                    if let Some(log) = &mut log {
+                        log.hidapi(Direction::In, &buf[..n]);
+                    }
+                    let now = Instant::now();
+                    if let Some(last) = last_input {
+                        let gap = now - last;
+                        summary.max_input_gap =
+                            Some(summary.max_input_gap.unwrap_or_default().max(gap));
+                    }
+                    last_input = Some(now);
+                    summary.inputs += 1;
+                    continue;
+                }
+                Err(err) => {
+                    summary.last_error = Some(err.to_string());
+                }
+            }
+
+            let now = Instant::now();
+            if now >= next {
+                break;
+            }
+            thread::sleep((next - now).min(Duration::from_millis(1)));
+        }
+    }
+
+    summary.elapsed = start.elapsed();
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use super::{parse_rate, Pattern, Payloads};
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("500/s").unwrap(), 500.0);
+        assert_eq!(parse_rate("2.5").unwrap(), 2.5);
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+
+    #[test]
+    fn generates_patterns() {
+        let mut counter = Payloads::new(Pattern::Counter, 3);
+        assert_eq!(counter.next(), Some(vec![1, 1, 1]));
+        assert_eq!(counter.next(), Some(vec![2, 2, 2]));
+
+        assert_eq!(
+            Payloads::new(Pattern::Ones, 2).next(),
+            Some(vec![0xff, 0xff])
+        );
+
+        let mut random = Payloads::new(Pattern::Random, 16);
+        assert_ne!(random.next(), random.next());
+    }
+
+    #[test]
+    fn repeats_file_payloads() {
+        let path = env::temp_dir().join(format!("hid-bench-payloads-{}", std::process::id()));
+        fs::write(&path, "# warm up\n01 02\n\n0304 05 # full length\n").unwrap();
+
+        let payloads: Vec<_> = Payloads::from_file(&path, 3).unwrap().take(3).collect();
+        assert_eq!(payloads, vec![vec![1, 2, 0], vec![3, 4, 5], vec![1, 2, 0]]);
+        assert!(Payloads::from_file(&path, 2).is_err());
+        assert!(Payloads::open(Pattern::File, 3, None).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+// Suspend and remote wakeup test (Linux)
+//
+// The device is suspended through runtime power management in sysfs: with autosuspend allowed
+// and no delay, the kernel suspends it as soon as nothing has it open. Input on a device with
+// remote wakeup enabled resumes it, which shows in `power/runtime_status`. Reports are only read
+// once the device is awake, opening it earlier would resume it from the host side.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+const SYSFS_USB_DEVICES: &str = "/sys/bus/usb/devices";
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// Runtime power management of a USB device, restored to the previous settings when dropped
+pub struct UsbPower {
+    path: PathBuf,                // the device's sysfs directory
+    saved: Vec<(String, String)>, // power attributes and their values before the test
+}
+
+impl UsbPower {
+    pub fn find(bus: u8, address: u8) -> Result<PathBuf> {
+        if !cfg!(target_os = "linux") {
+            return Err(anyhow!("Devices can only be suspended on Linux"));
+        }
+
+        device_path(Path::new(SYSFS_USB_DEVICES), bus, address)
+    }
+
+    // Lets the kernel suspend the device right away, with remote wakeup enabled
+    pub fn allow_suspend(path: PathBuf) -> Result<Self> {
+        let mut power = UsbPower {
+            path,
+            saved: vec![],
+        };
+
+        for (attribute, value) in [
+            ("wakeup", "enabled"),
+            ("autosuspend_delay_ms", "0"),
+            ("control", "auto"),
+        ] {
+            // `wakeup` only exists for devices capable of remote wakeup
+            if !power.path.join("power").join(attribute).exists() {
+                continue;
+            }
+
+            let saved = power.read(attribute)?;
+            power.write(attribute, value)?;
+            power.saved.push((attribute.to_string(), saved));
+        }
+
+        Ok(power)
+    }
+
+    pub fn runtime_status(&self) -> Result<String> {
+        self.read("runtime_status")
+    }
+
+    // Polls the runtime status until `done` accepts it, returning when that happened
+    pub fn wait_for(
+        &self,
+        done: impl Fn(&str) -> bool,
+        timeout: Duration,
+    ) -> Result<Option<Instant>> {
+        let start = Instant::now();
+
+        while start.elapsed() < timeout {
+            if done(&self.runtime_status()?) {
+                return Ok(Some(Instant::now()));
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        Ok(None)
+    }
+
+    fn read(&self, attribute: &str) -> Result<String> {
+        let path = self.path.join("power").join(attribute);
+        let value =
+            fs::read_to_string(&path).with_context(|| format!("Cannot read {}", path.display()))?;
+
+        Ok(value.trim().to_string())
+    }
+
+    fn write(&self, attribute: &str, value: &str) -> Result<()> {
+        let path = self.path.join("power").join(attribute);
+
+        fs::write(&path, value).with_context(|| {
+            format!(
+                "Cannot write {}, try running the command with sudo",
+                path.display()
+            )
+        })
+    }
+}
+
+impl Drop for UsbPower {
+    fn drop(&mut self) {
+        // in reverse, so autosuspend is turned off before its delay changes back
+        for (attribute, value) in self.saved.iter().rev() {
+            let _ = self.write(attribute, value);
+        }
+    }
+}
+
+// Devices are directories like `1-4.2` or `usb1` (root hubs) with `busnum` and `devnum`
+// attributes, interfaces (`1-4.2:1.0`) have neither
+fn device_path(root: &Path, bus: u8, address: u8) -> Result<PathBuf> {
+    let read_number =
+        |path: PathBuf| -> Option<u8> { fs::read_to_string(path).ok()?.trim().parse().ok() };
+
+    for entry in fs::read_dir(root).with_context(|| format!("Cannot read {}", root.display()))? {
+        let path = entry?.path();
+
+        if read_number(path.join("busnum")) == Some(bus)
+            && read_number(path.join("devnum")) == Some(address)
+        {
+            return Ok(path);
+        }
+    }
+
+    Err(anyhow!(
+        "Cannot find bus {} device {} in {}",
+        bus,
+        address,
+        root.display()
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use super::device_path;
+
+    #[test]
+    fn finds_devices_in_sysfs() {
+        let root = env::temp_dir().join(format!("hid-bench-sysfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for (name, bus, address) in [("usb1", "1", "1"), ("1-4", "1", "3"), ("2-1", "2", "3")] {
+            let dir = root.join(name);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("busnum"), format!("{}\n", bus)).unwrap();
+            fs::write(dir.join("devnum"), format!("{}\n", address)).unwrap();
+        }
+        fs::create_dir_all(root.join("1-4:1.0")).unwrap();
+
+        assert_eq!(device_path(&root, 1, 3).unwrap(), root.join("1-4"));
+        assert_eq!(device_path(&root, 2, 3).unwrap(), root.join("2-1"));
+        assert!(device_path(&root, 3, 1).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+// Report descriptors for inferred report layouts
+//
+// Describes the guessed fields with vendor defined usages (buttons use the Button page), so
+// reports of devices with opaque descriptors can be decoded by the parser and other tools.
+
+use hid_parser::{CollectionType, DescriptorBuilder, InputItemData, ReportDescriptor};
+
+use crate::infer::{FieldKind, ReportLayout};
+
+const VENDOR_PAGE: u16 = 0xff00;
+const BUTTON_PAGE: u16 = 0x09;
+
+const CONSTANT: InputItemData = InputItemData { data: 0x01 };
+const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+
+// Logical extents of a field of `bytes` bytes
+fn extents(bytes: usize, signed: bool) -> (i32, i32) {
+    let bits = (bytes * 8).min(32) as u32;
+
+    match signed {
+        true => (
+            (-1i64 << (bits - 1)) as i32,
+            ((1i64 << (bits - 1)) - 1) as i32,
+        ),
+        false => (0, ((1i64 << bits) - 1).min(i32::MAX as i64) as i32),
+    }
+}
+
+// One descriptor for the reports of an interface
+pub fn descriptor(reports: &[&ReportLayout]) -> ReportDescriptor {
+    let mut builder = DescriptorBuilder::new()
+        .usage_page(VENDOR_PAGE)
+        .usage(0x01)
+        .collection(CollectionType::Application);
+    let mut usage = 0x01;
+
+    for report in reports {
+        if let Some(id) = report.report_id {
+            builder = builder.report_id(id);
+        }
+
+        let mut offset = report.report_id.map_or(0, |_| 1);
+
+        for field in &report.fields {
+            // bytes the layout skips are padding
+            if field.offset > offset {
+                builder = builder
+                    .report_size(8)
+                    .report_count((field.offset - offset) as u32)
+                    .input(CONSTANT);
+            }
+            offset = offset.max(field.offset + field.size);
+
+            let size = field.size as u32;
+            builder = match &field.kind {
+                FieldKind::ReportId => continue,
+                FieldKind::Constant { .. } => {
+                    builder.report_size(8).report_count(size).input(CONSTANT)
+                }
+                FieldKind::Buttons { .. } => builder
+                    .usage_page(BUTTON_PAGE)
+                    .usage_minimum(1)
+                    .usage_maximum((size * 8) as u16)
+                    .logical_minimum(0)
+                    .logical_maximum(1)
+                    .report_size(1)
+                    .report_count(size * 8)
+                    .input(VARIABLE)
+                    .usage_page(VENDOR_PAGE),
+                FieldKind::Counter { .. } | FieldKind::Axis { .. } => {
+                    let signed = matches!(field.kind, FieldKind::Axis { signed: true, .. });
+                    let (minimum, maximum) = extents(field.size, signed);
+                    usage += 1;
+
+                    builder
+                        .usage(usage)
+                        .logical_minimum(minimum)
+                        .logical_maximum(maximum)
+                        .report_size(size * 8)
+                        .report_count(1)
+                        .input(VARIABLE)
+                }
+                FieldKind::Unknown => {
+                    let first = usage + 1;
+                    usage += size as u16;
+
+                    builder
+                        .usage_minimum(first)
+                        .usage_maximum(usage)
+                        .logical_minimum(0)
+                        .logical_maximum(0xff)
+                        .report_size(8)
+                        .report_count(size)
+                        .input(VARIABLE)
+                }
+            };
+        }
+
+        if report.length > offset {
+            builder = builder
+                .report_size(8)
+                .report_count((report.length - offset) as u32)
+                .input(CONSTANT);
+        }
+    }
+
+    builder.end_collection().build()
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::InputValue;
+
+    use super::descriptor;
+    use crate::infer::{Field, FieldKind, ReportLayout};
+
+    fn field(offset: usize, size: usize, kind: FieldKind) -> Field {
+        Field { offset, size, kind }
+    }
+
+    #[test]
+    fn describes_inferred_fields() {
+        let layout = ReportLayout {
+            interface: 0,
+            report_id: Some(0x20),
+            reports: 100,
+            length: 8,
+            fields: vec![
+                field(0, 1, FieldKind::ReportId),
+                field(1, 1, FieldKind::Counter { step: 1 }),
+                field(
+                    2,
+                    2,
+                    FieldKind::Axis {
+                        signed: true,
+                        min: -300,
+                        max: 300,
+                    },
+                ),
+                field(4, 1, FieldKind::Buttons { bits: vec![0, 2] }),
+                field(5, 1, FieldKind::Unknown),
+                field(6, 1, FieldKind::Constant { value: vec![0] }),
+            ],
+        };
+
+        let parser = descriptor(&[&layout]).decode();
+        let inputs = parser.parse_input(&[0x20, 0x07, 0xd4, 0xfe, 0x05, 0x99, 0x00, 0x00]);
+        let values = inputs
+            .flatten()
+            .into_iter()
+            .flatten()
+            .map(|input| (input.usage, format!("{:?}", input.value)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            parser.report_length(hid_parser::ReportKind::Input, Some(0x20)),
+            Some(8)
+        );
+        assert_eq!(
+            values[0],
+            ((0xff00, 0x02), format!("{:?}", InputValue::UInt(7)))
+        );
+        assert_eq!(
+            values[1],
+            ((0xff00, 0x03), format!("{:?}", InputValue::Int(-300)))
+        );
+        assert_eq!(
+            values[2],
+            ((0x09, 0x01), format!("{:?}", InputValue::Bool(true)))
+        );
+        assert_eq!(
+            values[4],
+            ((0x09, 0x03), format!("{:?}", InputValue::Bool(true)))
+        );
+        assert_eq!(
+            values[10],
+            ((0xff00, 0x04), format!("{:?}", InputValue::UInt(0x99)))
+        );
+        assert_eq!(values.len(), 11);
+    }
+}
@@ -0,0 +1,306 @@
+// Text typed on a keyboard, reconstructed from its reports
+//
+// Newly pressed keys on the Keyboard/Keypad page are translated with the selected layout, keys
+// without a character and shortcuts are shown in brackets, e.g. `[Backspace]` or `[Ctrl+C]`.
+// Caps Lock is tracked from its key presses, Num Lock is assumed on.
+//
+// This is keystroke logging, so the log command only uses it when asked explicitly.
+
+use clap::ValueEnum;
+
+use hid_parser::{Input, InputValue};
+
+const KEYBOARD: u16 = 0x07;
+
+// Modifier usages 0xe0 - 0xe7 as bits
+const LEFT_CTRL: u8 = 1 << 0;
+const LEFT_SHIFT: u8 = 1 << 1;
+const LEFT_ALT: u8 = 1 << 2;
+const LEFT_GUI: u8 = 1 << 3;
+const RIGHT_CTRL: u8 = 1 << 4;
+const RIGHT_SHIFT: u8 = 1 << 5;
+const RIGHT_ALT: u8 = 1 << 6; // AltGr outside the US layout
+const RIGHT_GUI: u8 = 1 << 7;
+
+const CAPS_LOCK: u16 = 0x39;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    Us,
+    Uk,
+    De,
+}
+
+// Keys 0x2d - 0x38 (symbols right of the digits and letters), unshifted and shifted
+const US_SYMBOLS: [&str; 2] = ["-=[]\\#;'`,./", "_+{}|~:\"~<>?"];
+const UK_SYMBOLS: [&str; 2] = ["-=[]\\#;'`,./", "_+{}|~:@¬<>?"];
+const DE_SYMBOLS: [&str; 2] = ["ß´ü+##öä^,.-", "?`Ü*''ÖÄ°;:_"];
+
+const KEYPAD: &str = "/*-+\n1234567890.";
+
+enum Key {
+    Char(char),
+    Named(String),
+}
+
+#[derive(Debug)]
+pub struct Typist {
+    layout: KeyboardLayout,
+    pressed: Vec<u16>,
+    caps_lock: bool,
+}
+
+impl Typist {
+    pub fn new(layout: KeyboardLayout) -> Self {
+        Typist {
+            layout,
+            pressed: vec![],
+            caps_lock: false,
+        }
+    }
+
+    // Text typed by the keys pressed since the previous report. Reports without keyboard usages
+    // and phantom states (too many keys pressed) don't change what is held down.
+    pub fn type_report(&mut self, inputs: &[Input]) -> String {
+        let keyboard = inputs
+            .iter()
+            .filter(|input| input.usage.0 == KEYBOARD)
+            .filter(|input| matches!(input.value, InputValue::Bool(true) | InputValue::Selected))
+            .map(|input| input.usage.1)
+            .collect::<Vec<_>>();
+        if !inputs.iter().any(|input| input.usage.0 == KEYBOARD)
+            || keyboard.iter().any(|id| (0x01..=0x03).contains(id))
+        {
+            return String::new();
+        }
+
+        let modifiers = keyboard
+            .iter()
+            .filter(|id| (0xe0..=0xe7).contains(*id))
+            .fold(0u8, |bits, id| bits | 1 << (id - 0xe0));
+        let keys = keyboard
+            .into_iter()
+            .filter(|id| (0x04..0xe0).contains(id))
+            .collect::<Vec<_>>();
+
+        let mut text = String::new();
+        for &id in keys.iter().filter(|id| !self.pressed.contains(id)) {
+            if id == CAPS_LOCK {
+                self.caps_lock = !self.caps_lock;
+                continue;
+            }
+
+            match self.key(id, modifiers) {
+                Some(Key::Char(c)) => text.push(c),
+                Some(Key::Named(name)) => text.push_str(&format!("[{}]", name)),
+                None => (),
+            }
+        }
+        self.pressed = keys;
+
+        text
+    }
+
+    fn key(&self, id: u16, modifiers: u8) -> Option<Key> {
+        let shift = modifiers & (LEFT_SHIFT | RIGHT_SHIFT) != 0;
+        let altgr = modifiers & RIGHT_ALT != 0 && self.layout != KeyboardLayout::Us;
+        let mut shortcut = vec![];
+        if modifiers & (LEFT_CTRL | RIGHT_CTRL) != 0 {
+            shortcut.push("Ctrl");
+        }
+        if modifiers & LEFT_ALT != 0 || (modifiers & RIGHT_ALT != 0 && !altgr) {
+            shortcut.push("Alt");
+        }
+        if modifiers & (LEFT_GUI | RIGHT_GUI) != 0 {
+            shortcut.push("Gui");
+        }
+
+        if !shortcut.is_empty() {
+            if shift {
+                shortcut.push("Shift");
+            }
+            let key = match self.key(id, 0)? {
+                Key::Char(c) => c.to_uppercase().to_string(),
+                Key::Named(name) => name,
+            };
+
+            return Some(Key::Named(format!("{}+{}", shortcut.join("+"), key)));
+        }
+
+        if altgr {
+            return self.altgr(id).map(Key::Char);
+        }
+
+        Some(match self.character(id, shift) {
+            Some(c) => Key::Char(c),
+            None => Key::Named(named(id)),
+        })
+    }
+
+    fn character(&self, id: u16, shift: bool) -> Option<char> {
+        let layout = self.layout;
+        let nth = |chars: &str, index: u16| chars.chars().nth(index as usize);
+
+        match id {
+            0x04..=0x1d => {
+                let mut letter = (b'a' + (id - 0x04) as u8) as char;
+                if layout == KeyboardLayout::De {
+                    letter = match letter {
+                        'y' => 'z',
+                        'z' => 'y',
+                        other => other,
+                    };
+                }
+
+                match shift != self.caps_lock {
+                    true => Some(letter.to_ascii_uppercase()),
+                    false => Some(letter),
+                }
+            }
+            0x1e..=0x27 => {
+                let digits = match (shift, layout) {
+                    (false, _) => "1234567890",
+                    (true, KeyboardLayout::Us) => "!@#$%^&*()",
+                    (true, KeyboardLayout::Uk) => "!\"£$%^&*()",
+                    (true, KeyboardLayout::De) => "!\"§$%&/()=",
+                };
+
+                nth(digits, id - 0x1e)
+            }
+            0x28 | 0x58 => Some('\n'),
+            0x2b => Some('\t'),
+            0x2c => Some(' '),
+            0x2d..=0x38 => {
+                let symbols = match layout {
+                    KeyboardLayout::Us => US_SYMBOLS,
+                    KeyboardLayout::Uk => UK_SYMBOLS,
+                    KeyboardLayout::De => DE_SYMBOLS,
+                };
+
+                nth(symbols[shift as usize], id - 0x2d)
+            }
+            0x54..=0x63 => nth(KEYPAD, id - 0x54),
+            0x64 => match (layout, shift) {
+                (KeyboardLayout::De, false) => Some('<'),
+                (KeyboardLayout::De, true) => Some('>'),
+                (_, false) => Some('\\'),
+                (_, true) => Some('|'),
+            },
+            _ => None,
+        }
+    }
+
+    fn altgr(&self, id: u16) -> Option<char> {
+        match (self.layout, id) {
+            (KeyboardLayout::Uk, 0x21) => Some('€'),
+            (KeyboardLayout::De, 0x14) => Some('@'),
+            (KeyboardLayout::De, 0x08) => Some('€'),
+            (KeyboardLayout::De, 0x10) => Some('µ'),
+            (KeyboardLayout::De, 0x1f) => Some('²'),
+            (KeyboardLayout::De, 0x20) => Some('³'),
+            (KeyboardLayout::De, 0x24) => Some('{'),
+            (KeyboardLayout::De, 0x25) => Some('['),
+            (KeyboardLayout::De, 0x26) => Some(']'),
+            (KeyboardLayout::De, 0x27) => Some('}'),
+            (KeyboardLayout::De, 0x2d) => Some('\\'),
+            (KeyboardLayout::De, 0x30) => Some('~'),
+            (KeyboardLayout::De, 0x64) => Some('|'),
+            _ => None,
+        }
+    }
+}
+
+fn named(id: u16) -> String {
+    let name = match id {
+        0x29 => "Esc",
+        0x2a => "Backspace",
+        0x3a..=0x45 => return format!("F{}", id - 0x3a + 1),
+        0x46 => "PrintScreen",
+        0x47 => "ScrollLock",
+        0x48 => "Pause",
+        0x49 => "Insert",
+        0x4a => "Home",
+        0x4b => "PageUp",
+        0x4c => "Delete",
+        0x4d => "End",
+        0x4e => "PageDown",
+        0x4f => "Right",
+        0x50 => "Left",
+        0x51 => "Down",
+        0x52 => "Up",
+        0x53 => "NumLock",
+        0x65 => "Menu",
+        _ => return format!("Key {:#04x}", id),
+    };
+
+    name.to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{Input, InputItemData, InputValue};
+
+    use super::{KeyboardLayout, Typist};
+
+    // Boot keyboard report parsed: modifier bits, then the key array
+    fn report(modifiers: &[u16], keys: &[u16]) -> Vec<Input> {
+        let modifiers = (0xe0..=0xe7).map(|id| {
+            Input::new(
+                (0x07, id),
+                InputValue::Bool(modifiers.contains(&id)),
+                InputItemData { data: 0x02 },
+            )
+        });
+        let keys = keys.iter().map(|id| {
+            Input::new(
+                (0x07, *id),
+                InputValue::Selected,
+                InputItemData { data: 0x00 },
+            )
+        });
+
+        modifiers.chain(keys).collect()
+    }
+
+    fn type_reports(layout: KeyboardLayout, reports: &[(&[u16], &[u16])]) -> String {
+        let mut typist = Typist::new(layout);
+
+        reports
+            .iter()
+            .map(|(modifiers, keys)| typist.type_report(&report(modifiers, keys)))
+            .collect()
+    }
+
+    #[test]
+    fn reconstructs_typed_text() {
+        const SHIFT: &[u16] = &[0xe1];
+        const ALTGR: &[u16] = &[0xe6];
+
+        let reports: &[(&[u16], &[u16])] = &[
+            (SHIFT, &[0x0b]), // H
+            (&[], &[]),
+            (&[], &[0x0c]),         // i
+            (SHIFT, &[0x0c, 0x1e]), // i held, ! pressed
+            (&[], &[]),
+            (&[], &[0x1d]),             // z, y on German keyboards
+            (&[], &[0x01, 0x01, 0x01]), // phantom state
+            (&[], &[0x2a]),             // Backspace
+            (&[0xe0], &[0x06]),         // Ctrl+C
+            (&[], &[]),
+            (ALTGR, &[0x14]), // @ on German keyboards
+            (&[], &[0x28]),
+        ];
+
+        assert_eq!(
+            type_reports(KeyboardLayout::Us, reports),
+            "Hi!z[Backspace][Ctrl+C][Alt+Q]\n"
+        );
+        assert_eq!(
+            type_reports(KeyboardLayout::De, reports),
+            "Hi!y[Backspace][Ctrl+C]@\n"
+        );
+
+        let caps: &[(&[u16], &[u16])] = &[(&[], &[0x39]), (&[], &[0x04]), (SHIFT, &[0x1f])];
+        assert_eq!(type_reports(KeyboardLayout::Uk, caps), "A\"");
+    }
+}
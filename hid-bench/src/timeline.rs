@@ -0,0 +1,204 @@
+// The values of one input field over the time of a capture
+//
+// A field is named by its path (`X`, `Generic Desktop/X`, `report#2/Joystick/X`, see
+// `FieldPath`), resolved against the input fields of the first interface which has any it
+// matches, and read from the flattened inputs of every input report of that interface.
+//
+// Reports without the field (a report ID without the item, an array not selecting it, a null
+// value) are gaps. In CSV a run of them is one row without a value, so plotting tools break the
+// line there, plots leave the time they cover blank.
+
+use std::{fmt::Write, time::Duration};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+
+use hid_parser::{
+    capture::{Capture, Transfer},
+    usage, FieldPath, FlatInputs, InputValue, ReportKind,
+};
+
+use crate::plot;
+
+// Columns of the plot
+const PLOT_BINS: usize = 60;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimelineFormat {
+    Csv,
+    Plot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeline {
+    pub usage: (u16, u16),
+    pub interface: u8,
+    pub points: Vec<(Duration, Option<f64>)>, // one per input report of the interface
+}
+
+pub fn field_timeline(capture: &Capture, name: &str) -> Result<Timeline> {
+    let path: FieldPath = name.parse()?;
+
+    let interface = capture
+        .descriptors
+        .parsers()
+        .find(|(_, parser)| !parser.find_fields(&path, ReportKind::Input).is_empty());
+    let Some((interface, parser)) = interface else {
+        return Err(anyhow!("No input field '{}' in the capture", path));
+    };
+    let field = parser.field(&path, ReportKind::Input)?;
+
+    let value = |transfer: &Transfer, flat: &FlatInputs| {
+        if transfer.interface != interface {
+            return None;
+        }
+        let value = field.value(flat).and_then(|value| match value {
+            InputValue::Bool(b) => Some(b as u8 as f64),
+            InputValue::UInt(u) => Some(u as f64),
+            InputValue::Int(i) => Some(i as f64),
+            InputValue::Selected => Some(1.0),
+            _ => None, // null state
+        });
+
+        Some((transfer.timestamp, value))
+    };
+
+    Ok(Timeline {
+        usage: field.usage,
+        interface,
+        points: capture.decode_inputs(value).into_iter().flatten().collect(),
+    })
+}
+
+impl Timeline {
+    // Seconds since the start of the capture and the value, without a value for a gap
+    pub fn csv(&self) -> String {
+        let mut csv = format!("seconds,{}\n", usage::short_name(self.usage));
+
+        let mut previous = Some(0.0);
+        for (timestamp, value) in &self.points {
+            match value {
+                Some(value) => writeln!(csv, "{:.6},{}", timestamp.as_secs_f64(), value),
+                None if previous.is_some() => writeln!(csv, "{:.6},", timestamp.as_secs_f64()),
+                None => Ok(()),
+            }
+            .unwrap();
+            previous = *value;
+        }
+
+        csv
+    }
+
+    // A sparkline of the mean value over time, blank where the field was missing throughout
+    pub fn plot(&self) -> String {
+        let values: Vec<_> = self.points.iter().filter_map(|(_, value)| *value).collect();
+        let name = usage::short_name(self.usage);
+        if values.is_empty() {
+            return format!("{} has no values in the capture\n", name);
+        }
+
+        let duration = self.points.last().map(|(t, _)| *t).unwrap_or_default();
+        let bins = PLOT_BINS.min(self.points.len());
+        let bin = duration.as_secs_f64() / bins as f64;
+        let mut sums = vec![(0.0, 0); bins];
+        for (timestamp, value) in &self.points {
+            let index = match bin > 0.0 {
+                true => ((timestamp.as_secs_f64() / bin) as usize).min(bins - 1),
+                false => 0,
+            };
+            if let Some(value) = value {
+                sums[index].0 += value;
+                sums[index].1 += 1;
+            }
+        }
+        let means: Vec<_> = sums
+            .iter()
+            .map(|(sum, count)| match count {
+                0 => f64::NAN,
+                count => sum / *count as f64,
+            })
+            .collect();
+
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        format!(
+            "{} (interface {}) {}..{} over {:.1} s, {} of {} reports without it:\n{}\n",
+            name,
+            self.interface,
+            min,
+            max,
+            duration.as_secs_f64(),
+            self.points.len() - values.len(),
+            self.points.len(),
+            plot::sparkline(&means)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use hid_parser::{
+        capture::{Capture, Direction, Transfer},
+        CollectionType, DescriptorBuilder, DeviceModel, InputItemData,
+    };
+
+    use super::field_timeline;
+
+    #[test]
+    fn extracts_fields_with_gaps() {
+        // X in report 1, a vendor field in report 2
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .report_id(2)
+            .usage_page(0xff00)
+            .usage(0x01)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build();
+        let transfers = [[1, 5], [1, 0xfb], [2, 9], [2, 9], [1, 3]]
+            .into_iter()
+            .enumerate()
+            .map(|(ms, bytes)| Transfer {
+                timestamp: Duration::from_millis(ms as u64),
+                interface: 0,
+                direction: Direction::In,
+                bytes: bytes.to_vec(),
+            })
+            .collect();
+        let capture = Capture {
+            device: Default::default(),
+            descriptors: [(0, descriptor)].into_iter().collect::<DeviceModel>(),
+            transfers,
+            markers: vec![],
+        };
+
+        let timeline = field_timeline(&capture, "x").unwrap();
+        assert_eq!(timeline.usage, (0x01, 0x30));
+        assert_eq!(
+            timeline.points.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            [Some(5.0), Some(-5.0), None, None, Some(3.0)]
+        );
+        assert_eq!(
+            timeline.csv(),
+            "seconds,X\n0.000000,5\n0.001000,-5\n0.002000,\n0.004000,3\n"
+        );
+        assert!(timeline
+            .plot()
+            .contains("-5..5 over 0.0 s, 2 of 5 reports without it"));
+
+        let timeline = field_timeline(&capture, "Generic Desktop/X").unwrap();
+        assert_eq!(timeline.points.len(), 5);
+        assert!(field_timeline(&capture, "Y").is_err());
+    }
+}
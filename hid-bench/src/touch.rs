@@ -0,0 +1,247 @@
+// Live view of digitizer contacts
+//
+// Contacts are drawn on a character canvas scaled from their logical ranges, as the last
+// digit of their contact ID. With colors the pressure sets their brightness. Every contact
+// leaves a trace fading out over a second.
+
+use std::{
+    io::{self, Write},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use hidapi::HidDevice;
+
+use hid_parser::{digitizer::Contact, Parser};
+
+use crate::{output::out, render::paint};
+
+const FADE: Duration = Duration::from_secs(1);
+const TRACE: [char; 5] = ['#', '+', '=', '-', '.']; // from fresh to old
+const FRAME: Duration = Duration::from_millis(33);
+const READ_TIMEOUT_MS: i32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanvasSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl FromStr for CanvasSize {
+    type Err = anyhow::Error;
+
+    // e.g. `80x24`
+    fn from_str(s: &str) -> Result<Self> {
+        let error = || anyhow!("Size must be COLUMNSxROWS, e.g. 80x24");
+
+        let (width, height) = s.split_once('x').ok_or_else(error)?;
+        let size = CanvasSize {
+            width: width.parse().map_err(|_| error())?,
+            height: height.parse().map_err(|_| error())?,
+        };
+        if size.width == 0 || size.height == 0 {
+            return Err(error());
+        }
+
+        Ok(size)
+    }
+}
+
+#[derive(Debug)]
+pub struct Canvas {
+    size: CanvasSize,
+    contacts: Vec<(Contact, Instant)>, // touching, when last reported
+    trace: Vec<(f32, f32, Instant)>,
+}
+
+impl Canvas {
+    pub fn new(size: CanvasSize) -> Self {
+        Canvas {
+            size,
+            contacts: vec![],
+            trace: vec![],
+        }
+    }
+
+    // Devices reporting a few contacts per report update only those, contacts disappear when
+    // lifted or not reported for a while
+    pub fn update(&mut self, contacts: &[Contact], now: Instant) {
+        for contact in contacts {
+            self.contacts.retain(|(c, _)| c.id != contact.id);
+
+            if contact.touching {
+                self.contacts.push((*contact, now));
+                self.trace.push((contact.x, contact.y, now));
+            }
+        }
+
+        self.contacts
+            .retain(|(_, seen)| now.duration_since(*seen) < FADE);
+        self.trace
+            .retain(|(_, _, at)| now.duration_since(*at) < FADE);
+        self.contacts.sort_by_key(|(contact, _)| contact.id);
+    }
+
+    pub fn render(&self, now: Instant, color: bool) -> String {
+        let CanvasSize { width, height } = self.size;
+        let mut cells = vec![vec![" ".to_string(); width]; height];
+        let cell = |x: f32, y: f32| {
+            let column = ((x * width as f32) as usize).min(width - 1);
+            let row = ((y * height as f32) as usize).min(height - 1);
+
+            (row, column)
+        };
+
+        // oldest first, so fresher points overwrite them
+        for (x, y, at) in &self.trace {
+            let age = now.duration_since(*at).as_secs_f32() / FADE.as_secs_f32();
+            let shade = ((age * TRACE.len() as f32) as usize).min(TRACE.len() - 1);
+            let (row, column) = cell(*x, *y);
+
+            cells[row][column] = TRACE[shade].to_string();
+        }
+
+        for (contact, _) in &self.contacts {
+            let mark = match contact.id {
+                Some(id) => char::from_digit(id % 10, 10).unwrap_or('o'),
+                None => 'o',
+            };
+            let (row, column) = cell(contact.x, contact.y);
+
+            cells[row][column] = match (color, contact.pressure) {
+                // grayscale from dark grey to white
+                (true, Some(pressure)) => {
+                    let gray = 240 + (pressure.clamp(0.0, 1.0) * 15.0) as u8;
+                    paint(&format!("1;38;5;{}", gray), &mark.to_string())
+                }
+                (true, None) => paint("1", &mark.to_string()),
+                (false, _) => mark.to_string(),
+            };
+        }
+
+        let border = format!("+{}+", "-".repeat(width));
+        let mut frame = vec![border.clone()];
+        frame.extend(cells.iter().map(|row| format!("|{}|", row.concat())));
+        frame.push(border);
+        frame.push(self.status());
+
+        frame.join("\n")
+    }
+
+    // e.g. `Touching: #3 (0.25, 1.00) 40%, #4 (0.10, 0.50)`
+    fn status(&self) -> String {
+        let contacts = self
+            .contacts
+            .iter()
+            .map(|(contact, _)| {
+                let mut text = match contact.id {
+                    Some(id) => format!("#{} ", id),
+                    None => String::new(),
+                };
+                text.push_str(&format!("({:.2}, {:.2})", contact.x, contact.y));
+                if let Some(pressure) = contact.pressure {
+                    text.push_str(&format!(" {:.0}%", pressure * 100.0));
+                }
+
+                text
+            })
+            .collect::<Vec<_>>();
+
+        let status = match contacts.is_empty() {
+            true => "Touching: nothing".to_string(),
+            false => format!("Touching: {}", contacts.join(", ")),
+        };
+
+        // padded to clear what a longer previous status left behind
+        format!("{:<width$}", status, width = self.size.width + 2)
+    }
+}
+
+// Redraws the canvas until interrupted
+pub fn run(hid_device: &HidDevice, parser: &Parser, size: CanvasSize, color: bool) -> Result<()> {
+    let mut canvas = Canvas::new(size);
+    let mut buf = [0u8; 64];
+    let mut drawn = Instant::now();
+
+    // clear the screen once, frames are then drawn over each other from the top
+    out!("\x1b[2J");
+
+    loop {
+        let n = hid_device.read_timeout(&mut buf, READ_TIMEOUT_MS)?;
+        if n > 0 {
+            canvas.update(&parser.contacts(&buf[0..n]), Instant::now());
+        }
+
+        if drawn.elapsed() >= FRAME {
+            drawn = Instant::now();
+            canvas.update(&[], drawn);
+
+            out!("\x1b[H{}", canvas.render(drawn, color));
+            io::stdout().flush()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use hid_parser::digitizer::Contact;
+
+    use super::{Canvas, CanvasSize};
+
+    fn contact(id: u32, x: f32, y: f32, touching: bool) -> Contact {
+        Contact {
+            id: Some(id),
+            x,
+            y,
+            pressure: None,
+            touching,
+        }
+    }
+
+    #[test]
+    fn draws_contacts_and_fading_traces() {
+        let size = "4x2".parse::<CanvasSize>().unwrap();
+        assert!("4".parse::<CanvasSize>().is_err());
+        assert!("0x2".parse::<CanvasSize>().is_err());
+
+        let start = Instant::now();
+        let mut canvas = Canvas::new(size);
+        canvas.update(&[contact(3, 0.0, 0.0, true)], start);
+        canvas.update(
+            &[contact(3, 1.0, 1.0, true), contact(4, 0.5, 0.0, true)],
+            start + Duration::from_millis(500),
+        );
+
+        assert_eq!(
+            canvas.render(start + Duration::from_millis(500), false),
+            "+----+\n\
+             |= 4 |\n\
+             |   3|\n\
+             +----+\n\
+             Touching: #3 (1.00, 1.00), #4 (0.50, 0.00)"
+        );
+
+        // lifted contacts disappear, traces fade out
+        let later = start + Duration::from_millis(1200);
+        canvas.update(&[contact(4, 0.5, 0.0, false)], later);
+        assert_eq!(
+            canvas.render(later, false),
+            "+----+\n\
+             |  - |\n\
+             |   3|\n\
+             +----+\n\
+             Touching: #3 (1.00, 1.00)"
+        );
+
+        // so do contacts which are no longer reported
+        let idle = start + Duration::from_millis(2000);
+        canvas.update(&[], idle);
+        assert_eq!(
+            canvas.render(idle, false),
+            "+----+\n|    |\n|    |\n+----+\nTouching: nothing"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+// Tracing the control transfers made through libusb, with --trace-usb
+//
+// Every GET_DESCRIPTOR, GET/SET_REPORT and GET/SET_IDLE request goes to stderr with its setup
+// fields and the bytes sent or received, including failed attempts, for debugging devices
+// which don't answer the way they should. Transfers hidapi makes through the OS driver aren't
+// seen here, usbmon shows those.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use hid_parser::{ControlTransfer, TransferPolicy};
+
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    TRACE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    TRACE.load(Ordering::Relaxed)
+}
+
+// Traces the Get_Descriptor requests made with the policy
+pub fn with_tracing(policy: TransferPolicy) -> TransferPolicy {
+    TransferPolicy {
+        trace: enabled().then_some(control as fn(&ControlTransfer)),
+        ..policy
+    }
+}
+
+pub fn control(transfer: &ControlTransfer) {
+    if enabled() {
+        eprintln!("{}", format(transfer));
+    }
+}
+
+fn request_name(request_type: u8, request: u8) -> Option<&'static str> {
+    // type bits: standard or class
+    let name = match (request_type >> 5 & 0b11, request) {
+        (0, 0x06) => "GET_DESCRIPTOR",
+        (0, 0x0a) => "GET_INTERFACE",
+        (0, 0x0b) => "SET_INTERFACE",
+        (1, 0x01) => "GET_REPORT",
+        (1, 0x02) => "GET_IDLE",
+        (1, 0x03) => "GET_PROTOCOL",
+        (1, 0x09) => "SET_REPORT",
+        (1, 0x0a) => "SET_IDLE",
+        (1, 0x0b) => "SET_PROTOCOL",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+// e.g. `USB IN  GET_REPORT bmRequestType=0xa1 bRequest=0x01 wValue=0x0301 wIndex=0 wLength=8:
+// 8 bytes 01 00 7f ...`
+pub fn format(transfer: &ControlTransfer) -> String {
+    let direction = match transfer.request_type & 0x80 {
+        0 => "OUT",
+        _ => "IN ",
+    };
+    let name = request_name(transfer.request_type, transfer.request).unwrap_or("request");
+    let bytes: Vec<_> = transfer
+        .data
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let outcome = match transfer.result {
+        Ok(n) if bytes.is_empty() => format!("{} bytes", n),
+        Ok(n) => format!("{} bytes {}", n, bytes.join(" ")),
+        Err(err) => format!("failed: {}", err),
+    };
+
+    format!(
+        "USB {} {} bmRequestType={:#04x} bRequest={:#04x} wValue={:#06x} wIndex={} wLength={}: {}",
+        direction,
+        name,
+        transfer.request_type,
+        transfer.request,
+        transfer.value,
+        transfer.index,
+        transfer.length,
+        outcome
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::ControlTransfer;
+
+    use super::format;
+
+    #[test]
+    fn formats_requests_and_payloads() {
+        let get_descriptor = ControlTransfer {
+            request_type: 0x81,
+            request: 0x06,
+            value: 0x2200,
+            index: 1,
+            length: 4,
+            data: &[0x05, 0x01, 0x09, 0x02],
+            result: Ok(4),
+        };
+        assert_eq!(
+            format(&get_descriptor),
+            "USB IN  GET_DESCRIPTOR bmRequestType=0x81 bRequest=0x06 wValue=0x2200 wIndex=1 \
+             wLength=4: 4 bytes 05 01 09 02"
+        );
+
+        let set_idle = ControlTransfer {
+            request_type: 0x21,
+            request: 0x0a,
+            value: 0x0200,
+            index: 0,
+            length: 0,
+            data: &[],
+            result: Err(rusb::Error::Pipe),
+        };
+        assert_eq!(
+            format(&set_idle),
+            "USB OUT SET_IDLE bmRequestType=0x21 bRequest=0x0a wValue=0x0200 wIndex=0 \
+             wLength=0: failed: Pipe error"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+// Reports going both ways, in one time-ordered log
+//
+// The commands sending reports (send, stress) read input reports meanwhile. A transfer log keeps
+// every report sent or received with its time, printing each as it happens with a direction
+// marker and writing them all to a capture at the end if asked to:
+//
+//     #1 [+000000 ms] >  [02, ff]
+//     #2 [+000001 ms] <  [01, 00, 05]
+//     #3 [+000010 ms] F> [05, 10]
+//     #4 [+000011 ms] F< [05, 10] (reply to #3)
+//
+// A feature report read back (Get_Report) answers the last feature report set with its report
+// ID, the two are paired. Reports are kept as on the wire: starting with the report ID only if
+// the device uses them.
+
+use std::time::Instant;
+
+use clap::ValueEnum;
+
+use hid_parser::{
+    capture::{Capture, DeviceMetadata, Direction, Transfer},
+    DeviceModel, Parser,
+};
+
+use crate::{
+    output::outln,
+    render::{self, Renderer},
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferFormat {
+    Raw,
+    Compact,
+    Full,
+}
+
+pub struct TransferLog {
+    start: Instant,
+    interface: u8,
+    report_ids: bool, // whether the reports start with the report ID
+    parser: Option<Parser>,
+    print: Option<(TransferFormat, Renderer)>,
+    transfers: Vec<Transfer>,
+    replies: Vec<Option<usize>>, // index of the request each feature report read back answers
+}
+
+impl TransferLog {
+    // Log of an interface, printing the transfers in `print`'s format if given
+    pub fn new(
+        descriptors: &DeviceModel,
+        interface: u8,
+        print: Option<(TransferFormat, Renderer)>,
+    ) -> Self {
+        let parser = descriptors.parser(interface).cloned();
+        let report_ids = parser.as_ref().is_some_and(|parser| {
+            parser
+                .reports()
+                .iter()
+                .any(|report| report.report_id.is_some())
+        });
+
+        Self {
+            start: Instant::now(),
+            interface,
+            report_ids,
+            parser,
+            print,
+            transfers: vec![],
+            replies: vec![],
+        }
+    }
+
+    // Logs a report in the form hidapi takes and gives them: output and feature reports start
+    // with the report ID, 0 without IDs, input reports only with IDs
+    pub fn hidapi(&mut self, direction: Direction, report: &[u8]) {
+        match direction {
+            Direction::In => self.record(direction, report),
+            _ if self.report_ids => self.record(direction, report),
+            _ => self.record(direction, report.get(1..).unwrap_or_default()),
+        }
+    }
+
+    pub fn record(&mut self, direction: Direction, bytes: &[u8]) {
+        let reply = match direction {
+            Direction::FeatureIn => self.request(bytes),
+            _ => None,
+        };
+
+        self.transfers.push(Transfer {
+            timestamp: self.start.elapsed(),
+            interface: self.interface,
+            direction,
+            bytes: bytes.to_vec(),
+        });
+        self.replies.push(reply);
+
+        if let Some((format, renderer)) = &self.print {
+            outln!("{}", self.line(self.transfers.len() - 1, *format, renderer));
+        }
+    }
+
+    // The last feature report set with the report ID which nothing answered yet
+    fn request(&self, reply: &[u8]) -> Option<usize> {
+        let id = |bytes: &[u8]| match self.report_ids {
+            true => bytes.first().copied(),
+            false => None,
+        };
+
+        (0..self.transfers.len()).rev().find(|i| {
+            let request = &self.transfers[*i];
+            request.direction == Direction::FeatureOut
+                && id(&request.bytes) == id(reply)
+                && !self.replies.contains(&Some(*i))
+        })
+    }
+
+    pub fn transfers(&self) -> &[Transfer] {
+        &self.transfers
+    }
+
+    // Index of the request a feature report read back answers
+    pub fn reply_to(&self, index: usize) -> Option<usize> {
+        self.replies.get(index).copied().flatten()
+    }
+
+    pub fn line(&self, index: usize, format: TransferFormat, renderer: &Renderer) -> String {
+        let transfer = &self.transfers[index];
+        let marker = match transfer.direction {
+            Direction::In => "< ",
+            Direction::Out => "> ",
+            Direction::FeatureOut => "F>",
+            Direction::FeatureIn => "F<",
+        };
+        let mut line = format!(
+            "#{} [+{:06} ms] {} {:02x?}",
+            index + 1,
+            transfer.timestamp.as_millis(),
+            marker,
+            transfer.bytes
+        );
+
+        if let (Some(parser), false) = (&self.parser, format == TransferFormat::Raw) {
+            let bytes = &transfer.bytes;
+            let parsed = match transfer.direction {
+                Direction::In => parser.parse_input(bytes),
+                Direction::Out => parser.parse_output(bytes),
+                Direction::FeatureIn | Direction::FeatureOut => parser.parse_feature(bytes),
+            };
+            let parsed = render::without_units(&parsed);
+            let decoded = match format {
+                TransferFormat::Full => renderer.full(&parsed),
+                _ => renderer.compact(&parsed),
+            };
+            line.push_str(&format!(" = {}", decoded));
+        }
+
+        if let Some(request) = self.reply_to(index) {
+            line.push_str(&format!(" (reply to #{})", request + 1));
+        }
+
+        line
+    }
+
+    pub fn capture(&self, device: DeviceMetadata, descriptors: DeviceModel) -> Capture {
+        Capture {
+            device,
+            descriptors,
+            transfers: self.transfers().to_vec(),
+            markers: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hid_parser::{
+        capture::Direction, CollectionType, DescriptorBuilder, DeviceModel, FeatureItemData,
+        InputItemData, OutputItemData,
+    };
+
+    use super::{TransferFormat, TransferLog};
+    use crate::render::{ColorChoice, Renderer, Theme};
+
+    #[test]
+    fn pairs_feature_reports_read_back() {
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0xff00)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage(0x02)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .report_id(5)
+            .usage(0x03)
+            .feature(FeatureItemData { data: 0x02 })
+            .end_collection()
+            .build();
+        let model: DeviceModel = [(0, descriptor)].into_iter().collect();
+        let renderer = Renderer::new(ColorChoice::Never, Theme::Dark);
+
+        let mut log = TransferLog::new(&model, 0, None);
+        log.hidapi(Direction::FeatureOut, &[0x05, 0x10]);
+        log.hidapi(Direction::In, &[0x01, 0x07]);
+        log.hidapi(Direction::FeatureIn, &[0x05, 0x10]);
+        log.hidapi(Direction::FeatureIn, &[0x05, 0x11]);
+
+        assert_eq!(log.reply_to(2), Some(0));
+        assert_eq!(log.reply_to(3), None); // the request was answered already
+        let lines: Vec<_> = (0..4)
+            .map(|i| {
+                let line = log.line(i, TransferFormat::Compact, &renderer);
+                line.split_once(" ms] ").unwrap().1.to_string()
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "F> [05, 10] = [16]",
+                "<  [01, 07] = [7]",
+                "F< [05, 10] = [16] (reply to #1)",
+                "F< [05, 11] = [17]",
+            ]
+        );
+        assert_eq!(log.transfers().len(), 4);
+    }
+
+    #[test]
+    fn keeps_reports_as_on_the_wire() {
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x06)
+            .collection(CollectionType::Application)
+            .usage_page(0x08)
+            .usage_minimum(1)
+            .usage_maximum(3)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(8)
+            .output(OutputItemData { data: 0x02 })
+            .end_collection()
+            .build();
+        let model: DeviceModel = [(0, descriptor)].into_iter().collect();
+
+        let mut log = TransferLog::new(&model, 0, None);
+        log.hidapi(Direction::Out, &[0x00, 0x02]);
+        assert_eq!(log.transfers()[0].bytes, [0x02]);
+    }
+}
@@ -0,0 +1,88 @@
+// udev rules giving the logged in user access to a device (Linux)
+//
+// Both the USB device node (used by rusb to read descriptors) and the hidraw nodes (used by
+// hidapi for reports) need to be accessible. The hidraw rule matches on the parent HID device
+// name (BUS:VID:PID.INSTANCE) so it covers Bluetooth devices as well.
+
+use std::{fs, path::PathBuf, process::Command};
+
+use anyhow::{anyhow, Context, Result};
+
+const RULES_DIR: &str = "/etc/udev/rules.d";
+
+// Access is granted to the user of the active session (systemd-logind), or to a group
+pub fn rule(vid: u16, pid: u16, group: Option<&str>) -> String {
+    let access = match group {
+        Some(group) => format!("MODE=\"0660\", GROUP=\"{}\"", group),
+        None => "TAG+=\"uaccess\"".to_string(),
+    };
+
+    format!(
+        "# hid-bench access to {vid:04x}:{pid:04x}\n\
+         SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{vid:04x}\", ATTR{{idProduct}}==\"{pid:04x}\", {access}\n\
+         SUBSYSTEM==\"hidraw\", KERNELS==\"*:{vid:04X}:{pid:04X}.*\", {access}\n"
+    )
+}
+
+pub fn rule_path(vid: u16, pid: u16) -> PathBuf {
+    PathBuf::from(RULES_DIR).join(format!("70-hid-bench-{:04x}-{:04x}.rules", vid, pid))
+}
+
+// Writes the rule and asks udev to apply it to devices already plugged in
+pub fn install(vid: u16, pid: u16, rule: &str) -> Result<PathBuf> {
+    if !cfg!(target_os = "linux") {
+        return Err(anyhow!("udev rules can only be installed on Linux"));
+    }
+
+    let path = rule_path(vid, pid);
+    fs::write(&path, rule).with_context(|| {
+        format!(
+            "Cannot write {}, try running the command with sudo",
+            path.display()
+        )
+    })?;
+
+    udevadm(&["control", "--reload-rules"])?;
+    udevadm(&["trigger", "--action=add", "--subsystem-match=usb"])?;
+    udevadm(&["trigger", "--action=add", "--subsystem-match=hidraw"])?;
+
+    Ok(path)
+}
+
+fn udevadm(args: &[&str]) -> Result<()> {
+    let status = Command::new("udevadm")
+        .args(args)
+        .status()
+        .context("Cannot run udevadm")?;
+
+    if !status.success() {
+        return Err(anyhow!("udevadm {} failed: {}", args.join(" "), status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{rule, rule_path};
+
+    #[test]
+    fn generates_uaccess_rule() {
+        assert_eq!(
+            rule(0x046d, 0xc08b, None),
+            "# hid-bench access to 046d:c08b\n\
+             SUBSYSTEM==\"usb\", ATTR{idVendor}==\"046d\", ATTR{idProduct}==\"c08b\", TAG+=\"uaccess\"\n\
+             SUBSYSTEM==\"hidraw\", KERNELS==\"*:046D:C08B.*\", TAG+=\"uaccess\"\n"
+        );
+        assert_eq!(
+            rule_path(0x046d, 0xc08b).to_str(),
+            Some("/etc/udev/rules.d/70-hid-bench-046d-c08b.rules")
+        );
+    }
+
+    #[test]
+    fn generates_group_rule() {
+        assert!(rule(0x054c, 0x09cc, Some("plugdev"))
+            .contains("ATTR{idProduct}==\"09cc\", MODE=\"0660\", GROUP=\"plugdev\"\n"));
+    }
+}
@@ -0,0 +1,116 @@
+// Virtual HID devices through the Linux uhid driver
+//
+// Writing events to /dev/uhid creates a HID device the kernel treats like a real one: hidraw,
+// input and the HID drivers pick it up, so software under test sees it without any changes.
+// Every event is a packed `struct uhid_event` (linux/uhid.h), a 4 byte type followed by the
+// largest request, UHID_CREATE2. The device goes away with UHID_DESTROY or when the file is
+// closed.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+const UHID_DESTROY: u32 = 1;
+const UHID_CREATE2: u32 = 11;
+const UHID_INPUT2: u32 = 12;
+
+const EVENT_SIZE: usize = 4 + 128 + 64 + 64 + 2 + 2 + 4 * 4 + DATA_MAX;
+const DATA_MAX: usize = 4096;
+
+const BUS_USB: u16 = 0x03; // so drivers matching USB devices bind to it
+
+pub struct UhidDevice {
+    file: File,
+}
+
+impl UhidDevice {
+    pub fn create(name: &str, vid: u16, pid: u16, descriptor: &[u8]) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/uhid")
+            .context("Cannot open /dev/uhid, simulating a device needs root or access to it")?;
+        file.write_all(&create_event(name, vid, pid, descriptor)?)
+            .context("Cannot create the uhid device")?;
+
+        Ok(Self { file })
+    }
+
+    // Sends an input report, starting with the report ID if used
+    pub fn input(&mut self, report: &[u8]) -> Result<()> {
+        self.file
+            .write_all(&input_event(report)?)
+            .context("Cannot send an input report to the uhid device")
+    }
+}
+
+impl Drop for UhidDevice {
+    fn drop(&mut self) {
+        let _ = self.file.write_all(&event(UHID_DESTROY));
+    }
+}
+
+fn event(kind: u32) -> Vec<u8> {
+    let mut event = vec![0; EVENT_SIZE];
+    event[..4].copy_from_slice(&kind.to_ne_bytes());
+
+    event
+}
+
+fn create_event(name: &str, vid: u16, pid: u16, descriptor: &[u8]) -> Result<Vec<u8>> {
+    if descriptor.len() > DATA_MAX {
+        return Err(anyhow!(
+            "The report descriptor is longer than uhid allows ({} bytes)",
+            DATA_MAX
+        ));
+    }
+
+    let mut event = event(UHID_CREATE2);
+    let name = &name.as_bytes()[..name.len().min(127)]; // keeping the NUL
+    event[4..4 + name.len()].copy_from_slice(name);
+    event[260..262].copy_from_slice(&(descriptor.len() as u16).to_ne_bytes());
+    event[262..264].copy_from_slice(&BUS_USB.to_ne_bytes());
+    event[264..268].copy_from_slice(&(vid as u32).to_ne_bytes());
+    event[268..272].copy_from_slice(&(pid as u32).to_ne_bytes());
+    event[280..280 + descriptor.len()].copy_from_slice(descriptor);
+
+    Ok(event)
+}
+
+fn input_event(report: &[u8]) -> Result<Vec<u8>> {
+    if report.len() > DATA_MAX {
+        return Err(anyhow!("The input report is longer than uhid allows"));
+    }
+
+    let mut event = event(UHID_INPUT2);
+    event[4..6].copy_from_slice(&(report.len() as u16).to_ne_bytes());
+    event[6..6 + report.len()].copy_from_slice(report);
+
+    Ok(event)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{create_event, input_event, EVENT_SIZE};
+
+    #[test]
+    fn packs_events() {
+        assert_eq!(EVENT_SIZE, 4376); // sizeof(struct uhid_event)
+
+        let create = create_event("Sim", 0x1234, 0x5678, &[0x05, 0x01]).unwrap();
+        assert_eq!(create.len(), EVENT_SIZE);
+        assert_eq!(&create[..8], &[11, 0, 0, 0, b'S', b'i', b'm', 0]);
+        assert_eq!(
+            &create[260..272],
+            &[2, 0, 3, 0, 0x34, 0x12, 0, 0, 0x78, 0x56, 0, 0]
+        );
+        assert_eq!(&create[280..283], &[0x05, 0x01, 0]);
+
+        let input = input_event(&[0x01, 0x05]).unwrap();
+        assert_eq!(&input[..9], &[12, 0, 0, 0, 2, 0, 0x01, 0x05, 0]);
+        assert!(input_event(&[0; 4097]).is_err());
+    }
+}
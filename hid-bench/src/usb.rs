@@ -0,0 +1,793 @@
+// USB devices through libusb (rusb)
+
+use std::{collections::BTreeMap, fmt::Display, slice, sync::mpsc, thread, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use rusb::{
+    constants::LIBUSB_REQUEST_SET_INTERFACE, Device, DeviceDescriptor, DeviceHandle, Direction,
+    GlobalContext, Hotplug, HotplugBuilder, Recipient, RequestType, TransferType, UsbContext,
+};
+
+use hid_parser::{
+    ControlTransfer, DescriptorType, DeviceModel, HidDescriptor, ReportDescriptor, Strictness,
+    TransferPolicy,
+};
+
+use crate::{backend::HidIo, config::DeviceSpec, descriptors::Setting, output::note, trace};
+
+pub fn find_device<'d>(
+    devices: &'d [Device<GlobalContext>],
+    spec: &DeviceSpec,
+) -> Option<&'d Device<GlobalContext>> {
+    devices.iter().find(|d| match d.device_descriptor() {
+        Ok(desc) => {
+            desc.vendor_id() == spec.vid
+                && desc.product_id() == spec.pid
+                && (spec.serial.is_none() || serial_number(d, &desc) == spec.serial)
+        }
+        _ => false,
+    })
+}
+
+fn serial_number(device: &Device<GlobalContext>, descriptor: &DeviceDescriptor) -> Option<String> {
+    let handle = device.open().ok()?;
+
+    handle.read_serial_number_string_ascii(descriptor).ok()
+}
+
+// Time for the OS to bind its drivers to a device which just appeared
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+struct Arrivals(mpsc::Sender<Device<GlobalContext>>);
+
+impl Hotplug<GlobalContext> for Arrivals {
+    fn device_arrived(&mut self, device: Device<GlobalContext>) {
+        let _ = self.0.send(device);
+    }
+
+    fn device_left(&mut self, _device: Device<GlobalContext>) {}
+}
+
+// Blocks until the device is plugged in, returns right away if it already is
+pub fn wait_for_device(spec: &DeviceSpec) -> Result<()> {
+    if !rusb::has_hotplug() {
+        loop {
+            if find_device(&hid_devices()?, spec).is_some() {
+                return Ok(());
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    let context = GlobalContext::default();
+    let (sender, receiver) = mpsc::channel();
+
+    let _registration = HotplugBuilder::new()
+        .vendor_id(spec.vid)
+        .product_id(spec.pid)
+        .enumerate(true)
+        .register(context, Box::new(Arrivals(sender)))?;
+
+    loop {
+        context.handle_events(Some(POLL_INTERVAL))?;
+
+        // the serial number can't be read from within the hotplug callback
+        while let Ok(device) = receiver.try_recv() {
+            if find_device(slice::from_ref(&device), spec).is_some() {
+                thread::sleep(SETTLE_TIME);
+
+                return Ok(());
+            }
+        }
+    }
+}
+
+pub fn hid_devices() -> Result<Vec<Device<GlobalContext>>> {
+    let mut devices = vec![];
+
+    for device in rusb::devices()?.iter() {
+        if !is_hid_device(&device)? {
+            continue;
+        }
+
+        devices.push(device);
+    }
+
+    Ok(devices)
+}
+
+fn is_hid_device(usb_device: &Device<GlobalContext>) -> Result<bool> {
+    let usb_device_descriptor = usb_device.device_descriptor()?;
+
+    for cidx in 0..usb_device_descriptor.num_configurations() {
+        let config_descriptor = usb_device.config_descriptor(cidx)?;
+
+        for interface in config_descriptor.interfaces() {
+            for interface_descriptor in interface.descriptors() {
+                if interface_descriptor.class_code() == 3 {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+// Report descriptors of the active configuration in the default alternate setting, which the
+// OS uses, by interface number
+pub fn report_descriptors(
+    usb_device: &Device<GlobalContext>,
+    policy: TransferPolicy,
+) -> Result<DeviceModel> {
+    let active = usb_device
+        .active_config_descriptor()
+        .map(|config| config.number())
+        .ok();
+    let mut descriptors = DeviceModel::new();
+
+    for (setting, report_descriptors) in all_report_descriptors(usb_device, policy)? {
+        if setting.alternate != Some(0) || active.is_some_and(|a| Some(a) != setting.configuration)
+        {
+            continue;
+        }
+
+        if !descriptors.contains(setting.interface) {
+            descriptors.replace(setting.interface, report_descriptors);
+        }
+    }
+
+    Ok(descriptors)
+}
+
+// Report descriptors of every configuration and alternate setting. Interfaces of inactive
+// configurations may not answer, their descriptors are left empty.
+pub fn all_report_descriptors(
+    usb_device: &Device<GlobalContext>,
+    policy: TransferPolicy,
+) -> Result<BTreeMap<Setting, Vec<ReportDescriptor>>> {
+    let mut descriptors = BTreeMap::new();
+
+    let usb_device_descriptor = usb_device.device_descriptor()?;
+    let device_handle = usb_device.open()?;
+    let active = device_handle.active_configuration().ok();
+
+    for cidx in 0..usb_device_descriptor.num_configurations() {
+        let config_descriptor = usb_device.config_descriptor(cidx)?;
+        let configuration = config_descriptor.number();
+
+        for interface in config_descriptor.interfaces() {
+            for interface_descriptor in interface.descriptors() {
+                if interface_descriptor.class_code() != 3 {
+                    continue;
+                }
+
+                let setting = Setting {
+                    configuration: Some(configuration),
+                    interface: interface_descriptor.interface_number(),
+                    alternate: Some(interface_descriptor.setting_number()),
+                };
+                let hid_descriptor =
+                    HidDescriptor::from_interface_descriptor(&interface_descriptor);
+                // only the setting in use can be claimed
+                let claimable = active == Some(configuration) && setting.alternate == Some(0);
+                let fetched = fetch_report_descriptors(
+                    usb_device,
+                    &device_handle,
+                    &hid_descriptor,
+                    policy,
+                    claimable,
+                );
+
+                let report_descriptors = match fetched {
+                    Ok((report_descriptors, strategy)) => {
+                        if strategy != FetchStrategy::Plain {
+                            note!("{}: read the report descriptor {}", setting, strategy);
+                        }
+                        report_descriptors
+                    }
+                    Err(_) if active.is_some_and(|a| a != configuration) => vec![],
+                    Err(err) => {
+                        return Err(err).with_context(|| {
+                            format!("Cannot read the report descriptor of {}", setting)
+                        })
+                    }
+                };
+
+                descriptors.insert(setting, report_descriptors);
+            }
+        }
+    }
+
+    Ok(descriptors)
+}
+
+// Ways of reading the report descriptors of an interface, tried in this order until one
+// returns them complete
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchStrategy {
+    Plain,         // GET_DESCRIPTOR for the length in the HID descriptor
+    LongerRequest, // some devices answer short or stall when asked for the exact length
+    Claimed,       // some only answer once the OS driver let go of the interface
+    AfterSetIdle,  // and some only after the SET_IDLE the OS driver would have sent
+}
+
+impl FetchStrategy {
+    const ALL: [FetchStrategy; 4] = [
+        FetchStrategy::Plain,
+        FetchStrategy::LongerRequest,
+        FetchStrategy::Claimed,
+        FetchStrategy::AfterSetIdle,
+    ];
+}
+
+impl Display for FetchStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchStrategy::Plain => write!(f, "as declared"),
+            FetchStrategy::LongerRequest => write!(f, "asking for {} bytes", LONG_REQUEST),
+            FetchStrategy::Claimed => write!(f, "with the interface claimed"),
+            FetchStrategy::AfterSetIdle => {
+                write!(f, "after SET_IDLE with the interface claimed")
+            }
+        }
+    }
+}
+
+// More than any report descriptor needs, HID descriptors declare their lengths in 16 bits but
+// devices rarely send more than a few hundred bytes
+const LONG_REQUEST: u16 = 4096;
+
+// The report descriptors of an interface, from the first strategy returning them complete. A
+// descriptor shorter than declared is complete if it decodes, devices declaring too much are
+// common. Without a complete answer the first one is kept, without any answer the first error
+// is returned.
+fn fetch_report_descriptors(
+    usb_device: &Device<GlobalContext>,
+    handle: &DeviceHandle<GlobalContext>,
+    hid_descriptor: &HidDescriptor,
+    policy: TransferPolicy,
+    claimable: bool,
+) -> rusb::Result<(Vec<ReportDescriptor>, FetchStrategy)> {
+    let declared = declared_lengths(hid_descriptor);
+    let read = |handle: &DeviceHandle<GlobalContext>, policy| {
+        hid_descriptor
+            .report_descriptors_with(handle, policy)
+            .collect::<rusb::Result<Vec<_>>>()
+    };
+
+    let mut first = None;
+    for strategy in FetchStrategy::ALL {
+        let result = match strategy {
+            FetchStrategy::Plain => read(handle, policy),
+            FetchStrategy::LongerRequest => read(
+                handle,
+                TransferPolicy {
+                    length: Some(LONG_REQUEST),
+                    ..policy
+                },
+            ),
+            FetchStrategy::Claimed | FetchStrategy::AfterSetIdle if claimable => {
+                let Ok(claimed) =
+                    ClaimedInterface::claim(usb_device, hid_descriptor.interface_num())
+                else {
+                    continue;
+                };
+                if strategy == FetchStrategy::AfterSetIdle {
+                    // many devices stall SET_IDLE, the descriptor may come anyway
+                    let _ = claimed.set_idle(0, Duration::ZERO);
+                }
+                read(&claimed.handle, policy)
+            }
+            _ => continue,
+        };
+
+        match result {
+            Ok(descriptors) if is_complete(&descriptors, &declared) => {
+                return Ok((descriptors, strategy));
+            }
+            result => {
+                first.get_or_insert((result, strategy));
+            }
+        }
+    }
+
+    match first {
+        Some((Ok(descriptors), strategy)) => Ok((descriptors, strategy)),
+        Some((Err(err), _)) => Err(err),
+        None => Ok((vec![], FetchStrategy::Plain)),
+    }
+}
+
+// wDescriptorLength of every report descriptor of the interface
+fn declared_lengths(hid_descriptor: &HidDescriptor) -> Vec<u16> {
+    (0..hid_descriptor.num_descriptors() as usize)
+        .filter(|&index| hid_descriptor.descriptor_type(index) == Some(DescriptorType::Report))
+        .filter_map(|index| hid_descriptor.descriptor_length(index))
+        .collect()
+}
+
+fn is_complete(descriptors: &[ReportDescriptor], declared: &[u16]) -> bool {
+    descriptors.len() == declared.len()
+        && descriptors
+            .iter()
+            .zip(declared)
+            .all(|(descriptor, &length)| {
+                descriptor.bytes.len() >= length as usize
+                    || (!descriptor.bytes.is_empty()
+                        && descriptor.decode_with(Strictness::Spec).is_ok())
+            })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Endpoint {
+    pub interface: u8,
+    pub alt_setting: u8,
+    pub address: u8,
+    pub direction: Direction,
+    pub transfer_type: TransferType,
+    pub max_packet_size: u16,
+    pub interval: u8, // bInterval, in frames or microframes depending on the speed
+}
+
+impl Display for Endpoint {
+    // e.g. `0x81 IN interrupt, 8 bytes, interval 1`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = match self.direction {
+            Direction::In => "IN",
+            Direction::Out => "OUT",
+        };
+        let transfer_type = match self.transfer_type {
+            TransferType::Control => "control",
+            TransferType::Isochronous => "isochronous",
+            TransferType::Bulk => "bulk",
+            TransferType::Interrupt => "interrupt",
+        };
+
+        write!(
+            f,
+            "{:#04x} {} {}, {} bytes, interval {}",
+            self.address, direction, transfer_type, self.max_packet_size, self.interval
+        )
+    }
+}
+
+// Endpoints of every HID interface and alternate setting of the active configuration
+pub fn endpoints(usb_device: &Device<GlobalContext>) -> Result<Vec<Endpoint>> {
+    let config = usb_device.active_config_descriptor()?;
+    let mut endpoints = vec![];
+
+    for interface in config.interfaces() {
+        for descriptor in interface.descriptors() {
+            if descriptor.class_code() != 3 {
+                continue;
+            }
+
+            for endpoint in descriptor.endpoint_descriptors() {
+                endpoints.push(Endpoint {
+                    interface: descriptor.interface_number(),
+                    alt_setting: descriptor.setting_number(),
+                    address: endpoint.address(),
+                    direction: endpoint.direction(),
+                    transfer_type: endpoint.transfer_type(),
+                    max_packet_size: endpoint.max_packet_size(),
+                    interval: endpoint.interval(),
+                });
+            }
+        }
+    }
+
+    Ok(endpoints)
+}
+
+// One line per interface and alternate setting, e.g.
+// `Interface #1, alternate setting 0: 0x82 IN interrupt, 16 bytes, interval 1`
+pub fn describe_endpoints(endpoints: &[Endpoint]) -> String {
+    let mut settings: BTreeMap<(u8, u8), Vec<String>> = BTreeMap::new();
+    for endpoint in endpoints {
+        settings
+            .entry((endpoint.interface, endpoint.alt_setting))
+            .or_default()
+            .push(endpoint.to_string());
+    }
+
+    settings
+        .iter()
+        .map(|((interface, alt_setting), endpoints)| {
+            format!(
+                "Interface #{}, alternate setting {}: {}",
+                interface,
+                alt_setting,
+                endpoints.join("; ")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The interrupt IN endpoint to read, the one at `address` or the first of the setting
+pub fn select_endpoint(
+    endpoints: &[Endpoint],
+    interface: u8,
+    alt_setting: u8,
+    address: Option<u8>,
+) -> Result<u8> {
+    let setting: Vec<_> = endpoints
+        .iter()
+        .filter(|e| e.interface == interface && e.alt_setting == alt_setting)
+        .collect();
+    if setting.is_empty() {
+        let of_interface: Vec<_> = endpoints
+            .iter()
+            .filter(|e| e.interface == interface)
+            .copied()
+            .collect();
+        return Err(match of_interface.is_empty() {
+            true => anyhow!("Interface #{} has no endpoints", interface),
+            false => anyhow!(
+                "Interface #{} has no alternate setting {}, it has:\n{}",
+                interface,
+                alt_setting,
+                describe_endpoints(&of_interface)
+            ),
+        });
+    }
+
+    setting
+        .iter()
+        .find(|e| {
+            e.direction == Direction::In
+                && e.transfer_type == TransferType::Interrupt
+                && address.is_none_or(|address| e.address == address)
+        })
+        .map(|e| e.address)
+        .ok_or_else(|| {
+            let setting: Vec<_> = setting.iter().map(|e| **e).collect();
+            let endpoint = match address {
+                Some(address) => format!("interrupt IN endpoint {:#04x}", address),
+                None => "interrupt IN endpoint".to_string(),
+            };
+            anyhow!(
+                "Interface #{} in alternate setting {} has no {}, it has:\n{}",
+                interface,
+                alt_setting,
+                endpoint,
+                describe_endpoints(&setting)
+            )
+        })
+}
+
+// Endpoint addresses in hex (0x81) or decimal (129), IN endpoints have bit 7 set
+pub fn parse_endpoint(text: &str) -> Result<u8> {
+    let address = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| anyhow!("'{}' is not an endpoint address, e.g. 0x81", text))?;
+
+    if address & 0x80 == 0 {
+        return Err(anyhow!(
+            "{:#04x} is an OUT endpoint, reports are read from IN endpoints (0x81 - 0x8f)",
+            address
+        ));
+    }
+
+    Ok(address)
+}
+
+// HID class requests (HID 1.11, section 7.2)
+const HID_GET_REPORT: u8 = 0x01;
+const HID_GET_IDLE: u8 = 0x02;
+const HID_SET_REPORT: u8 = 0x09;
+const HID_SET_IDLE: u8 = 0x0a;
+
+// Report types of Get_Report and Set_Report
+const OUTPUT_REPORT: u16 = 2;
+const FEATURE_REPORT: u16 = 3;
+const CONTROL_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Idle durations are set in steps of 4 ms
+pub const IDLE_STEP: Duration = Duration::from_millis(4);
+
+// An interface taken over from the OS driver, which gets it back when this is dropped. Needed
+// for requests the OS driver would interfere with, e.g. it sets its own idle rate.
+pub struct ClaimedInterface {
+    handle: DeviceHandle<GlobalContext>,
+    interface: u8,
+    endpoint: u8, // interrupt IN
+}
+
+impl ClaimedInterface {
+    pub fn claim(usb_device: &Device<GlobalContext>, interface: u8) -> Result<Self> {
+        Self::claim_with(usb_device, interface, 0, None)
+    }
+
+    // Claims the interface in an alternate setting, reading the interrupt IN endpoint at
+    // `address` or the first one of the setting
+    pub fn claim_with(
+        usb_device: &Device<GlobalContext>,
+        interface: u8,
+        alt_setting: u8,
+        address: Option<u8>,
+    ) -> Result<Self> {
+        let endpoint = select_endpoint(&endpoints(usb_device)?, interface, alt_setting, address)?;
+
+        let handle = usb_device.open()?;
+        // not supported on every platform, claiming fails later if the driver stays
+        let _ = handle.set_auto_detach_kernel_driver(true);
+        handle.claim_interface(interface)?;
+        let claimed = ClaimedInterface {
+            handle,
+            interface,
+            endpoint,
+        };
+        // released again on drop if this fails
+        if alt_setting != 0 {
+            let result = claimed.handle.set_alternate_setting(interface, alt_setting);
+            trace::control(&ControlTransfer {
+                request_type: rusb::request_type(
+                    Direction::Out,
+                    RequestType::Standard,
+                    Recipient::Interface,
+                ),
+                request: LIBUSB_REQUEST_SET_INTERFACE,
+                value: alt_setting as u16,
+                index: interface as u16,
+                length: 0,
+                data: &[],
+                result: result.map(|_| 0),
+            });
+            result.with_context(|| {
+                format!(
+                    "Cannot select alternate setting {} of interface #{}",
+                    alt_setting, interface
+                )
+            })?;
+        }
+
+        Ok(claimed)
+    }
+
+    pub fn endpoint(&self) -> u8 {
+        self.endpoint
+    }
+
+    // Reads an input report, 0 bytes if none arrived before the timeout
+    pub fn read(&self, buf: &mut [u8], timeout: Duration) -> rusb::Result<usize> {
+        match self.handle.read_interrupt(self.endpoint, buf, timeout) {
+            Err(rusb::Error::Timeout) => Ok(0),
+            result => result,
+        }
+    }
+
+    // Report ID 0 sets the duration of all input reports
+    pub fn set_idle(&self, report_id: u8, duration: Duration) -> rusb::Result<()> {
+        let steps = (duration.as_millis() / IDLE_STEP.as_millis()).min(255) as u16;
+        self.write_class(HID_SET_IDLE, steps << 8 | report_id as u16, &[])?;
+
+        Ok(())
+    }
+
+    pub fn get_idle(&self, report_id: u8) -> rusb::Result<Duration> {
+        let mut steps = [0u8];
+        self.read_class(HID_GET_IDLE, report_id as u16, &mut steps)?;
+
+        Ok(IDLE_STEP * steps[0] as u32)
+    }
+
+    // Sends a report over the control endpoint, `report` starts with its ID (0 without IDs),
+    // which only goes out with the report when it isn't 0
+    fn set_report(&self, report_type: u16, report: &[u8]) -> rusb::Result<usize> {
+        let Some((&report_id, data)) = report.split_first() else {
+            return Ok(0);
+        };
+        let bytes = match report_id {
+            0 => data,
+            _ => report,
+        };
+        self.write_class(HID_SET_REPORT, report_type << 8 | report_id as u16, bytes)?;
+
+        Ok(report.len())
+    }
+
+    fn get_report(&self, report_type: u16, buf: &mut [u8]) -> rusb::Result<usize> {
+        let Some(&report_id) = buf.first() else {
+            return Ok(0);
+        };
+        // reports without IDs are read in after the 0
+        let into = match report_id {
+            0 => &mut buf[1..],
+            _ => buf,
+        };
+        let n = self.read_class(HID_GET_REPORT, report_type << 8 | report_id as u16, into)?;
+
+        Ok(match report_id {
+            0 => n + 1,
+            _ => n,
+        })
+    }
+
+    // Class requests to the interface, traced with --trace-usb
+    fn write_class(&self, request: u8, value: u16, data: &[u8]) -> rusb::Result<usize> {
+        let request_type =
+            rusb::request_type(Direction::Out, RequestType::Class, Recipient::Interface);
+        let result = self.handle.write_control(
+            request_type,
+            request,
+            value,
+            self.interface as u16,
+            data,
+            CONTROL_TIMEOUT,
+        );
+        trace::control(&ControlTransfer {
+            request_type,
+            request,
+            value,
+            index: self.interface as u16,
+            length: data.len() as u16,
+            data,
+            result,
+        });
+
+        result
+    }
+
+    fn read_class(&self, request: u8, value: u16, buf: &mut [u8]) -> rusb::Result<usize> {
+        let request_type =
+            rusb::request_type(Direction::In, RequestType::Class, Recipient::Interface);
+        let result = self.handle.read_control(
+            request_type,
+            request,
+            value,
+            self.interface as u16,
+            buf,
+            CONTROL_TIMEOUT,
+        );
+        trace::control(&ControlTransfer {
+            request_type,
+            request,
+            value,
+            index: self.interface as u16,
+            length: buf.len() as u16,
+            data: &buf[..*result.as_ref().unwrap_or(&0)],
+            result,
+        });
+
+        result
+    }
+}
+
+// Reports go through the interrupt IN endpoint and class requests, as the device sends them,
+// without the framing of an OS driver
+impl HidIo for ClaimedInterface {
+    fn read_timeout(&self, buf: &mut [u8], timeout_ms: i32) -> Result<usize> {
+        // libusb waits forever with a zero timeout
+        let timeout = match timeout_ms {
+            ..0 => Duration::ZERO,
+            ms => Duration::from_millis(ms.max(1) as u64),
+        };
+
+        Ok(ClaimedInterface::read(self, buf, timeout)?)
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_timeout(buf, -1)
+    }
+
+    fn write(&self, report: &[u8]) -> Result<usize> {
+        Ok(self.set_report(OUTPUT_REPORT, report)?)
+    }
+
+    fn send_feature_report(&self, report: &[u8]) -> Result<()> {
+        self.set_report(FEATURE_REPORT, report)?;
+
+        Ok(())
+    }
+
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(self.get_report(FEATURE_REPORT, buf)?)
+    }
+
+    // reads always wait up to their timeout
+    fn set_blocking_mode(&self, _blocking: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ClaimedInterface {
+    fn drop(&mut self) {
+        let _ = self.handle.release_interface(self.interface);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rusb::{Direction, TransferType};
+
+    use hid_parser::{CollectionType, DescriptorBuilder, InputItemData, ReportDescriptor};
+
+    use super::{is_complete, parse_endpoint, select_endpoint, Endpoint};
+
+    fn endpoint(alt_setting: u8, address: u8, transfer_type: TransferType) -> Endpoint {
+        Endpoint {
+            interface: 1,
+            alt_setting,
+            address,
+            direction: match address & 0x80 {
+                0 => Direction::Out,
+                _ => Direction::In,
+            },
+            transfer_type,
+            max_packet_size: 64,
+            interval: 1,
+        }
+    }
+
+    #[test]
+    fn selects_interrupt_in_endpoints() {
+        let endpoints = [
+            endpoint(0, 0x02, TransferType::Interrupt),
+            endpoint(0, 0x81, TransferType::Interrupt),
+            endpoint(0, 0x83, TransferType::Interrupt),
+            endpoint(1, 0x84, TransferType::Bulk),
+            endpoint(1, 0x85, TransferType::Interrupt),
+        ];
+
+        assert_eq!(select_endpoint(&endpoints, 1, 0, None).unwrap(), 0x81);
+        assert_eq!(select_endpoint(&endpoints, 1, 0, Some(0x83)).unwrap(), 0x83);
+        assert_eq!(select_endpoint(&endpoints, 1, 1, None).unwrap(), 0x85);
+
+        let err = select_endpoint(&endpoints, 1, 1, Some(0x84)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Interface #1 in alternate setting 1 has no interrupt IN endpoint 0x84, it has:\n\
+             Interface #1, alternate setting 1: 0x84 IN bulk, 64 bytes, interval 1; \
+             0x85 IN interrupt, 64 bytes, interval 1"
+        );
+        assert!(select_endpoint(&endpoints, 1, 2, None)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Interface #1 has no alternate setting 2"));
+        assert!(select_endpoint(&endpoints, 0, 0, None).is_err());
+    }
+
+    #[test]
+    fn parses_endpoint_addresses() {
+        assert_eq!(parse_endpoint("0x81").unwrap(), 0x81);
+        assert_eq!(parse_endpoint("130").unwrap(), 0x82);
+        assert!(parse_endpoint("0x02").is_err());
+        assert!(parse_endpoint("in").is_err());
+    }
+
+    #[test]
+    fn accepts_short_descriptors_which_decode() {
+        let mouse = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build();
+        let length = mouse.bytes.len() as u16;
+        let cut = ReportDescriptor {
+            bytes: mouse.bytes[..mouse.bytes.len() - 3].to_vec(),
+        };
+
+        let mouse = [mouse];
+        let empty = [ReportDescriptor { bytes: vec![] }];
+
+        assert!(is_complete(&mouse, &[length]));
+        // declaring more than it sends
+        assert!(is_complete(&mouse, &[length + 16]));
+        assert!(!is_complete(&[cut], &[length]));
+        assert!(!is_complete(&empty, &[length]));
+        assert!(!is_complete(&mouse, &[length, length]));
+    }
+}
@@ -0,0 +1,228 @@
+// Reports the host sends to a device, seen through usbmon (Linux)
+//
+// The binary usbmon interface (/dev/usbmonN for bus N, from the usbmon module) shows every URB
+// on the bus as it is submitted (S) and completed (C), without claiming the device, so the
+// drivers and applications using it keep working. Reads return one event each: a 48 byte
+// `struct usbmon_packet`, then the captured data.
+//
+// Output reports go out as SET_REPORT(Output) control requests or on the interrupt OUT
+// endpoint of the interface, feature reports as SET_REPORT(Feature). Their data is in the
+// submission, starting with the report ID if the device uses them.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use hid_parser::ReportKind;
+
+const HEADER_SIZE: usize = 48;
+
+const XFER_INTERRUPT: u8 = 1;
+const XFER_CONTROL: u8 = 2;
+
+const SET_REPORT: u8 = 0x09;
+const CLASS_INTERFACE_OUT: u8 = 0x21;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbmonEvent {
+    pub kind: u8, // b'S'ubmission, b'C'allback or b'E'rror
+    pub transfer_type: u8,
+    pub endpoint: u8, // with the direction bit
+    pub device: u8,   // address on the bus
+    pub bus: u16,
+    pub timestamp: Duration, // wall clock
+    pub setup: Option<[u8; 8]>,
+    pub data: Vec<u8>,
+}
+
+// An output or feature report sent to an interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostReport {
+    pub timestamp: Duration,
+    pub interface: u8,
+    pub kind: ReportKind,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Usbmon {
+    file: File,
+    buf: Vec<u8>,
+}
+
+impl Usbmon {
+    pub fn open(bus: u8) -> Result<Self> {
+        if !cfg!(target_os = "linux") {
+            return Err(anyhow!("USB traffic can only be monitored on Linux"));
+        }
+
+        let path = device_path(bus);
+        let file = File::open(&path).with_context(|| {
+            format!(
+                "Cannot open {}, monitoring needs the usbmon module (modprobe usbmon) and root",
+                path.display()
+            )
+        })?;
+
+        Ok(Self {
+            file,
+            buf: vec![0; HEADER_SIZE + 64 * 1024],
+        })
+    }
+
+    // Waits for the next event on the bus
+    pub fn next_event(&mut self) -> Result<UsbmonEvent> {
+        loop {
+            let n = self
+                .file
+                .read(&mut self.buf)
+                .context("Cannot read usbmon")?;
+            if let Some(event) = parse_event(&self.buf[..n]) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+fn device_path(bus: u8) -> PathBuf {
+    Path::new("/dev").join(format!("usbmon{}", bus))
+}
+
+fn parse_event(bytes: &[u8]) -> Option<UsbmonEvent> {
+    if bytes.len() < HEADER_SIZE {
+        return None;
+    }
+    let u32_at = |at: usize| u32::from_ne_bytes(bytes[at..at + 4].try_into().unwrap());
+
+    let seconds = i64::from_ne_bytes(bytes[16..24].try_into().unwrap());
+    let micros = u32_at(24);
+    let captured = u32_at(36) as usize;
+    let setup = (bytes[14] == 0).then(|| bytes[40..48].try_into().unwrap());
+
+    Some(UsbmonEvent {
+        kind: bytes[8],
+        transfer_type: bytes[9],
+        endpoint: bytes[10],
+        device: bytes[11],
+        bus: u16::from_ne_bytes([bytes[12], bytes[13]]),
+        timestamp: Duration::new(seconds.max(0) as u64, micros * 1000),
+        setup,
+        data: bytes[HEADER_SIZE..(HEADER_SIZE + captured).min(bytes.len())].to_vec(),
+    })
+}
+
+impl UsbmonEvent {
+    // The output or feature report a submission to the device carries, with the interrupt OUT
+    // endpoints of its HID interfaces
+    pub fn host_report(&self, out_endpoints: &BTreeMap<u8, u8>) -> Option<HostReport> {
+        if self.kind != b'S' {
+            return None;
+        }
+
+        let (interface, kind) = match (self.transfer_type, self.setup) {
+            (XFER_CONTROL, Some(setup)) => {
+                if setup[0] != CLASS_INTERFACE_OUT || setup[1] != SET_REPORT {
+                    return None;
+                }
+                let kind = match setup[3] {
+                    2 => ReportKind::Output,
+                    3 => ReportKind::Feature,
+                    _ => return None,
+                };
+
+                (setup[4], kind)
+            }
+            (XFER_INTERRUPT, _) if self.endpoint & 0x80 == 0 => {
+                (*out_endpoints.get(&self.endpoint)?, ReportKind::Output)
+            }
+            _ => return None,
+        };
+
+        Some(HostReport {
+            timestamp: self.timestamp,
+            interface,
+            kind,
+            bytes: self.data.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, time::Duration};
+
+    use hid_parser::ReportKind;
+
+    use super::{parse_event, HostReport, HEADER_SIZE};
+
+    fn packet(
+        kind: u8,
+        transfer_type: u8,
+        endpoint: u8,
+        setup: Option<[u8; 8]>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut bytes = vec![0; HEADER_SIZE];
+        bytes[8..12].copy_from_slice(&[kind, transfer_type, endpoint, 7]);
+        bytes[12..14].copy_from_slice(&3u16.to_ne_bytes());
+        bytes[14] = if setup.is_some() { 0 } else { b'-' };
+        bytes[16..24].copy_from_slice(&100i64.to_ne_bytes());
+        bytes[24..28].copy_from_slice(&250_000u32.to_ne_bytes());
+        bytes[32..36].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+        bytes[36..40].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+        if let Some(setup) = setup {
+            bytes[40..48].copy_from_slice(&setup);
+        }
+        bytes.extend_from_slice(data);
+
+        bytes
+    }
+
+    #[test]
+    fn finds_reports_sent_by_the_host() {
+        let endpoints = BTreeMap::from([(0x02, 1)]);
+
+        // SET_REPORT(Feature 5) to interface 1
+        let set_feature = [0x21, 0x09, 0x05, 0x03, 0x01, 0x00, 0x03, 0x00];
+        let event = parse_event(&packet(
+            b'S',
+            2,
+            0x00,
+            Some(set_feature),
+            &[0x05, 0x10, 0x20],
+        ))
+        .unwrap();
+        assert_eq!((event.bus, event.device), (3, 7));
+        assert_eq!(
+            event.host_report(&endpoints),
+            Some(HostReport {
+                timestamp: Duration::from_millis(100_250),
+                interface: 1,
+                kind: ReportKind::Feature,
+                bytes: vec![0x05, 0x10, 0x20],
+            })
+        );
+
+        // keyboard LEDs on the interrupt OUT endpoint
+        let event = parse_event(&packet(b'S', 1, 0x02, None, &[0x02])).unwrap();
+        let report = event.host_report(&endpoints).unwrap();
+        assert_eq!((report.interface, report.kind), (1, ReportKind::Output));
+
+        // input reports, completions and GET_REPORT aren't sent by the host
+        let get_report = [0xa1, 0x01, 0x05, 0x03, 0x01, 0x00, 0x03, 0x00];
+        for bytes in [
+            packet(b'C', 1, 0x81, None, &[0x01]),
+            packet(b'C', 2, 0x00, Some(set_feature), &[]),
+            packet(b'S', 2, 0x80, Some(get_report), &[]),
+            packet(b'S', 1, 0x03, None, &[0x01]),
+        ] {
+            assert_eq!(parse_event(&bytes).unwrap().host_report(&endpoints), None);
+        }
+        assert_eq!(parse_event(&[0; 20]), None);
+    }
+}
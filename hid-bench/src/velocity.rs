@@ -0,0 +1,168 @@
+// Rates of change of axes, for judging the smoothness of sensors and wheel encoders
+//
+// Relative axes (mouse movement, wheels) report the counts since their previous report, their
+// rate is those counts over the time since then. Absolute axes report a position, their rate is
+// the change of position over the time. Wrapping axes (dials, encoders) take the shorter way
+// around their range. Fields declaring a unit are rated in physical units per second, others in
+// counts per second.
+
+use std::{collections::HashMap, fmt::Display, time::Instant};
+
+use hid_parser::{
+    scroll::{AC_PAN, WHEEL},
+    unit::{self, Measurement, Unit},
+    usage::{self, CONSUMER, GENERIC_DESKTOP},
+    FlatInputs, InputValue, Parser, Usage,
+};
+
+const X: u16 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Velocity {
+    pub usage: Usage,
+    pub per_second: f64,
+    pub unit: Option<Unit>, // of the value, None for counts
+}
+
+impl Display for Velocity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = usage::short_name(self.usage);
+
+        match self.unit {
+            Some(mut unit) => {
+                unit.exponents[2] -= 1; // per second
+                let rate = Measurement {
+                    value: self.per_second,
+                    unit,
+                };
+                write!(f, "{} {}", name, rate)
+            }
+            None => write!(f, "{} {:.0} counts/s", name, self.per_second),
+        }
+    }
+}
+
+// X, Y, Z, their rotations, sliders, dials and wheels
+pub fn is_axis(usage: Usage) -> bool {
+    match usage {
+        (GENERIC_DESKTOP, id) => (X..=WHEEL).contains(&id),
+        (CONSUMER, id) => id == AC_PAN,
+        _ => false,
+    }
+}
+
+// Last value of every axis and when it was reported
+#[derive(Debug, Default)]
+pub struct Velocities {
+    last: HashMap<(usize, usize), (i64, Instant)>, // index in `Parser::reports`, of the value
+}
+
+impl Velocities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Velocities of the axes in a report received at `at`, axes seen for the first time have
+    // none yet
+    pub fn update(&mut self, parser: &Parser, inputs: &FlatInputs, at: Instant) -> Vec<Velocity> {
+        let reports = parser.reports();
+        let mut velocities = vec![];
+
+        for (index, values) in inputs.items() {
+            let report = reports[index];
+            if report.report_type.flags().array() {
+                continue;
+            }
+
+            for (i, input) in values.iter().enumerate() {
+                if !is_axis(input.usage) {
+                    continue;
+                }
+                let value = match input.value {
+                    InputValue::UInt(v) => v as i64,
+                    InputValue::Int(v) => v as i64,
+                    _ => continue,
+                };
+
+                let Some((last, then)) = self.last.insert((index, i), (value, at)) else {
+                    continue;
+                };
+                let seconds = at.duration_since(then).as_secs_f64();
+                if seconds <= 0.0 {
+                    continue;
+                }
+
+                let change = report.delta(last, value) as f64;
+                let (change, unit) = match Measurement::of(report, input.value) {
+                    Some(measurement) => (
+                        change * unit::resolution(report).unwrap_or(1.0),
+                        Some(measurement.unit),
+                    ),
+                    None => (change, None),
+                };
+                velocities.push(Velocity {
+                    usage: input.usage,
+                    per_second: change / seconds,
+                    unit,
+                });
+            }
+        }
+
+        velocities
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use hid_parser::{CollectionType, DescriptorBuilder, FlatInputs, InputItemData};
+
+    use super::Velocities;
+
+    #[test]
+    fn rates_relative_and_absolute_axes() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 }) // relative
+            .usage(0x35)
+            .logical_minimum(0)
+            .logical_maximum(359)
+            .unit(0x14)
+            .report_size(16)
+            .input(InputItemData { data: 0x0a }) // absolute, wrap
+            .end_collection()
+            .build()
+            .decode();
+
+        let mut velocities = Velocities::new();
+        let mut inputs = FlatInputs::new();
+        let start = Instant::now();
+
+        parser.parse_input_flat(&[10, 90, 0], &mut inputs);
+        assert!(velocities.update(&parser, &inputs, start).is_empty());
+
+        parser.parse_input_flat(&[0xfb, 100, 0], &mut inputs);
+        let rates: Vec<_> = velocities
+            .update(&parser, &inputs, start + Duration::from_millis(10))
+            .iter()
+            .map(|velocity| velocity.to_string())
+            .collect();
+        assert_eq!(rates, ["X -500 counts/s", "Rz 1000 deg/s"]);
+
+        parser.parse_input_flat(&[0, 0x5e, 0x01], &mut inputs); // 350 deg
+        let rates: Vec<_> = velocities
+            .update(&parser, &inputs, start + Duration::from_millis(20))
+            .iter()
+            .map(|velocity| velocity.to_string())
+            .collect();
+        assert_eq!(rates, ["X 0 counts/s", "Rz -11000 deg/s"]);
+    }
+}
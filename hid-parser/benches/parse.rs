@@ -0,0 +1,201 @@
+// Descriptor parsing and input report decoding for small, medium and huge descriptors
+//
+// Run with `cargo bench -p hid-parser`. Criterion compares every run with the previous one, to
+// check a change against a fixed state save a baseline first and compare with it afterwards:
+//
+//   cargo bench -p hid-parser -- --save-baseline main
+//   cargo bench -p hid-parser -- --baseline main
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use hid_parser::{
+    CollectionType, DescriptorBuilder, FeatureItemData, FlatInputs, InputItemData, ReportDescriptor,
+};
+
+const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+const CONSTANT: InputItemData = InputItemData { data: 0x01 };
+
+const FINGERS: u8 = 10;
+
+// Three buttons and relative X, Y and wheel, without a report ID
+fn mouse() -> (ReportDescriptor, Vec<u8>) {
+    let descriptor = DescriptorBuilder::new()
+        .usage_page(0x01)
+        .usage(0x02)
+        .collection(CollectionType::Application)
+        .usage(0x01)
+        .collection(CollectionType::Physical)
+        .usage_page(0x09)
+        .usage_minimum(1)
+        .usage_maximum(3)
+        .logical_minimum(0)
+        .logical_maximum(1)
+        .report_size(1)
+        .report_count(3)
+        .input(VARIABLE)
+        .report_size(5)
+        .report_count(1)
+        .input(CONSTANT)
+        .usage_page(0x01)
+        .usage(0x30)
+        .usage(0x31)
+        .usage(0x38)
+        .logical_minimum(-127)
+        .logical_maximum(127)
+        .report_size(8)
+        .report_count(3)
+        .input(InputItemData { data: 0x06 })
+        .end_collection()
+        .end_collection()
+        .build();
+
+    (descriptor, vec![0b101, 0x05, 0xfb, 0x01])
+}
+
+// 16 buttons, a hat switch padded to a byte and four 8 bit axes, in report 1
+fn gamepad() -> (ReportDescriptor, Vec<u8>) {
+    let descriptor = DescriptorBuilder::new()
+        .usage_page(0x01)
+        .usage(0x05)
+        .collection(CollectionType::Application)
+        .report_id(1)
+        .usage_page(0x09)
+        .usage_minimum(1)
+        .usage_maximum(16)
+        .logical_minimum(0)
+        .logical_maximum(1)
+        .report_size(1)
+        .report_count(16)
+        .input(VARIABLE)
+        .usage_page(0x01)
+        .usage(0x39)
+        .logical_maximum(7)
+        .report_size(4)
+        .report_count(1)
+        .input(VARIABLE)
+        .input(CONSTANT)
+        .usage(0x30)
+        .usage(0x31)
+        .usage(0x32)
+        .usage(0x35)
+        .logical_maximum(255)
+        .report_size(8)
+        .report_count(4)
+        .input(VARIABLE)
+        .end_collection()
+        .build();
+
+    (
+        descriptor,
+        vec![0x01, 0b1010_0101, 0b0000_0001, 0x03, 0x80, 0x7f, 0x00, 0xff],
+    )
+}
+
+// Touch screen reporting ten fingers at once, each with a tip switch, contact ID and 16 bit
+// coordinates, plus the contact count and a contact count maximum feature
+fn multitouch() -> (ReportDescriptor, Vec<u8>) {
+    let mut builder = DescriptorBuilder::new()
+        .usage_page(0x0d)
+        .usage(0x04)
+        .collection(CollectionType::Application)
+        .report_id(1);
+
+    for _ in 0..FINGERS {
+        builder = builder
+            .usage_page(0x0d)
+            .usage(0x22)
+            .collection(CollectionType::Logical)
+            .usage(0x42)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(1)
+            .input(VARIABLE)
+            .report_size(7)
+            .input(CONSTANT)
+            .usage(0x51)
+            .logical_maximum(255)
+            .report_size(8)
+            .input(VARIABLE)
+            .usage_page(0x01)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_maximum(32767)
+            .report_size(16)
+            .report_count(2)
+            .input(VARIABLE)
+            .end_collection();
+    }
+
+    let descriptor = builder
+        .usage_page(0x0d)
+        .usage(0x54)
+        .logical_maximum(FINGERS as i32)
+        .report_size(8)
+        .report_count(1)
+        .input(VARIABLE)
+        .report_id(2)
+        .usage(0x55)
+        .feature(FeatureItemData { data: 0x02 })
+        .end_collection()
+        .build();
+
+    let mut report = vec![0x01];
+    for finger in 0..FINGERS {
+        report.extend([0x01, finger, 0x00, 0x10, 0x00, 0x20]);
+    }
+    report.push(FINGERS);
+
+    (descriptor, report)
+}
+
+fn fixtures() -> [(&'static str, ReportDescriptor, Vec<u8>); 3] {
+    let (mouse, mouse_report) = mouse();
+    let (gamepad, gamepad_report) = gamepad();
+    let (multitouch, multitouch_report) = multitouch();
+
+    [
+        ("mouse", mouse, mouse_report),
+        ("gamepad", gamepad, gamepad_report),
+        ("multitouch", multitouch, multitouch_report),
+    ]
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+
+    for (name, descriptor, _) in fixtures() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &descriptor, |b, d| {
+            b.iter(|| black_box(d).decode())
+        });
+    }
+
+    group.finish();
+}
+
+fn parse_input(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_input");
+
+    for (name, descriptor, report) in fixtures() {
+        let parser = descriptor.decode();
+        let mut flat = FlatInputs::new();
+        parser.parse_input_flat(&report, &mut flat);
+        assert!(!flat.is_empty(), "the {} report has no values", name);
+
+        group.bench_with_input(BenchmarkId::new("tree", name), &report, |b, report| {
+            b.iter(|| parser.parse_input(black_box(report)))
+        });
+
+        group.bench_with_input(BenchmarkId::new("flat", name), &report, |b, report| {
+            b.iter(|| {
+                parser.parse_input_flat(black_box(report), &mut flat);
+                flat.inputs().len()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, decode, parse_input);
+criterion_main!(benches);
@@ -0,0 +1,127 @@
+//! Running totals of relative fields
+//!
+//! Relative fields (mouse movement, wheels, dials) report the counts since the previous report.
+//! Adding them up across reports gives how far they moved overall, e.g. to check a mouse reports
+//! the same distance for the same movement at different speeds. The totals saturate instead of
+//! overflowing on devices left running.
+
+use std::fmt::Display;
+
+use crate::{usage, FlatInputs, InputValue, Parser, Usage};
+
+/// Counts a relative field moved by overall
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Total {
+    /// Usage of the field
+    pub usage: Usage,
+    /// Sum of the reported counts, saturating
+    pub counts: i64,
+}
+
+impl Display for Total {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", usage::short_name(self.usage), self.counts)
+    }
+}
+
+/// Running totals of the relative fields of a device's reports
+#[derive(Debug, Default)]
+pub struct Accumulator {
+    totals: Vec<((usize, usize), Total)>, // index in `Parser::reports`, of the value
+}
+
+impl Accumulator {
+    /// An accumulator without any totals
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the relative fields of a report, parsed into `inputs`
+    pub fn add(&mut self, parser: &Parser, inputs: &FlatInputs) {
+        let reports = parser.reports();
+
+        for (index, values) in inputs.items() {
+            let report = reports[index];
+            let flags = report.report_type.flags();
+            if flags.array() || flags.absolute() {
+                continue;
+            }
+
+            for (i, input) in values.iter().enumerate() {
+                let value = match input.value {
+                    InputValue::UInt(v) => v as i64,
+                    InputValue::Int(v) => v as i64,
+                    _ => continue,
+                };
+                // relative values are changes already, wrapping ones roll over to negative
+                let change = report.delta(0, value);
+
+                match self.totals.iter_mut().find(|(key, _)| *key == (index, i)) {
+                    Some((_, total)) => total.counts = total.counts.saturating_add(change),
+                    None => self.totals.push((
+                        (index, i),
+                        Total {
+                            usage: input.usage,
+                            counts: change,
+                        },
+                    )),
+                }
+            }
+        }
+    }
+
+    /// The totals, in the order the fields were first seen
+    pub fn totals(&self) -> impl Iterator<Item = &Total> {
+        self.totals.iter().map(|(_, total)| total)
+    }
+
+    /// Forgets all totals
+    pub fn reset(&mut self) {
+        self.totals.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollectionType, DescriptorBuilder, FlatInputs, InputItemData};
+
+    use super::Accumulator;
+
+    #[test]
+    fn adds_up_relative_fields() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 }) // relative
+            .usage(0x38)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_count(1)
+            .input(InputItemData { data: 0x0e }) // relative, wrap
+            .usage(0x32)
+            .input(InputItemData { data: 0x02 }) // absolute
+            .end_collection()
+            .build()
+            .decode();
+
+        let mut accumulator = Accumulator::new();
+        let mut inputs = FlatInputs::new();
+        for report in [[10, 0xfb, 1, 50], [5, 0xfb, 0xff, 60], [0x81, 0, 0xff, 70]] {
+            parser.parse_input_flat(&report, &mut inputs);
+            accumulator.add(&parser, &inputs);
+        }
+
+        let totals: Vec<_> = accumulator.totals().map(|t| t.to_string()).collect();
+        assert_eq!(totals, ["X -112", "Y -10", "Wheel -1"]);
+
+        accumulator.reset();
+        assert_eq!(accumulator.totals().count(), 0);
+    }
+}
@@ -1,7 +1,8 @@
-// 1st level: Parse basic items
+//! 1st level: Parse basic items
 
 use std::fmt::{Debug, Display};
 
+/// Iterator over the short items of a report descriptor
 #[derive(Debug)]
 pub struct BasicItems<'a> {
     bytes: &'a [u8],
@@ -9,6 +10,7 @@ pub struct BasicItems<'a> {
 }
 
 impl<'a> BasicItems<'a> {
+    /// Items of the descriptor `bytes`
     pub fn new(bytes: &'a [u8]) -> Self {
         BasicItems { bytes, offset: 0 }
     }
@@ -26,16 +28,26 @@ impl<'a> BasicItems<'a> {
     }
 }
 
-impl<'a> Iterator for BasicItems<'a> {
-    type Item = BasicItem;
+impl<'a> BasicItems<'a> {
+    /// Byte offset of the next item in the descriptor
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    // Next item as encoded: type, tag, data and data size in bytes
+    pub(crate) fn next_raw(&mut self) -> Option<(u8, u8, u32, usize)> {
         if self.offset >= self.bytes.len() {
             return None;
         }
 
         let (size, item_type, tag) = Self::item_header(self.bytes[self.offset]);
 
+        // an item cut off by the end of the descriptor ends it
+        if self.offset + 1 + size > self.bytes.len() {
+            self.offset = self.bytes.len();
+            return None;
+        }
+
         let mut data = 0u32;
         for byte_idx in 0..size {
             // build up from little-endian ordered bytes
@@ -44,16 +56,30 @@ impl<'a> Iterator for BasicItems<'a> {
 
         self.offset += size + 1;
 
+        Some((item_type, tag, data, size))
+    }
+}
+
+impl<'a> Iterator for BasicItems<'a> {
+    type Item = BasicItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (item_type, tag, data, size) = self.next_raw()?;
+
         Some(BasicItem::new(item_type, tag, data, size))
     }
 }
 
-// NOTE only short items are supported
+/// A short item of a report descriptor, long items aren't supported
 #[derive(Debug)]
 pub enum BasicItem {
+    /// Input, Output, Feature, Collection and End Collection items
     Main(MainItem),
+    /// Items describing the fields of the following main items, until changed
     Global(GlobalItem),
+    /// Items describing only the next main item
     Local(LocalItem),
+    /// The reserved item type
     Reserved,
 }
 
@@ -61,20 +87,27 @@ impl BasicItem {
     fn new(item_type: u8, tag: u8, data: u32, size: usize) -> Self {
         match item_type {
             0 => Self::Main(MainItem::new(tag, data)),
-            1 => Self::Global(GlobalItem::new(tag, data)),
+            1 => Self::Global(GlobalItem::new(tag, data, size)),
             2 => Self::Local(LocalItem::new(tag, data, size)),
             _ => Self::Reserved,
         }
     }
 }
 
+/// A main item, defining fields or grouping them
 #[derive(Debug)]
 pub enum MainItem {
+    /// Fields of input reports
     Input(InputItemData),
+    /// Fields of output reports
     Output(OutputItemData),
+    /// Fields of feature reports
     Feature(FeatureItemData),
+    /// Start of a collection
     Collection(Collection),
+    /// End of the innermost collection
     EndCollection,
+    /// A reserved main item tag
     Reserved,
 }
 
@@ -91,72 +124,90 @@ impl MainItem {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Data of an Input item: the flags of its fields, see HID 1.11 section 6.2.2.5
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct InputItemData {
+    /// The item's data as encoded
     pub data: u32,
 }
 
 impl InputItemData {
+    /// Whether the fields carry data, bit 0 clear
     pub fn data(&self) -> bool {
         self.data & 1 == 0
     }
 
+    /// Whether the fields are constant (usually padding), bit 0 set
     pub fn constant(&self) -> bool {
         !self.data()
     }
 
+    /// Whether the fields are an array of selected usages, bit 1 clear
     pub fn array(&self) -> bool {
         self.data & 2 == 0
     }
 
+    /// Whether every field is a value of its own usage, bit 1 set
     pub fn variable(&self) -> bool {
         !self.array()
     }
 
+    /// Whether values are absolute, bit 2 clear
     pub fn absolute(&self) -> bool {
         self.data & 2_u32.pow(2) == 0
     }
 
+    /// Whether values are changes since the previous report, bit 2 set
     pub fn relative(&self) -> bool {
         !self.absolute()
     }
 
+    /// Whether values stop at the extremes, bit 3 clear
     pub fn no_wrap(&self) -> bool {
         self.data & 2_u32.pow(3) == 0
     }
 
+    /// Whether values roll over at the extremes, e.g. a dial, bit 3 set
     pub fn wrap(&self) -> bool {
         !self.no_wrap()
     }
 
+    /// Whether values are proportional to the measured quantity, bit 4 clear
     pub fn linear(&self) -> bool {
         self.data & 2_u32.pow(4) == 0
     }
 
+    /// Whether values aren't proportional to the measured quantity, bit 4 set
     pub fn non_linear(&self) -> bool {
         !self.linear()
     }
 
+    /// Whether the control returns to a preferred state when let go, bit 5 clear
     pub fn preferred(&self) -> bool {
         self.data & 2_u32.pow(5) == 0
     }
 
+    /// Whether the control stays where it was left, bit 5 set
     pub fn no_preferred(&self) -> bool {
         !self.preferred()
     }
 
+    /// Whether every value in the logical range is valid, bit 6 clear
     pub fn no_null(&self) -> bool {
         self.data & 2_u32.pow(6) == 0
     }
 
+    /// Whether values outside the logical range mean no data, bit 6 set
     pub fn null(&self) -> bool {
         !self.no_null()
     }
 
+    /// Whether the fields are bit fields, bit 8 clear
     pub fn bit_field(&self) -> bool {
         self.data & 2_u32.pow(8) == 0
     }
 
+    /// Whether the fields are buffers of bytes, bit 8 set
     pub fn buffered_bytes(&self) -> bool {
         !self.bit_field()
     }
@@ -173,7 +224,7 @@ impl Display for InputItemData {
         write!(
             f,
             "{}",
-            vec![
+            [
                 if self.data() { "Data" } else { "Const" },
                 if self.array() { "Array" } else { "Variable" },
                 if self.absolute() {
@@ -204,26 +255,41 @@ impl Display for InputItemData {
     }
 }
 
-#[derive(Debug)]
+/// Output and Feature items use the same flag layout as Input items,
+/// with bit 7 (Non Volatile / Volatile) on top
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct OutputItemData {
+    /// The item's data as encoded
     pub data: u32,
 }
 
-#[derive(Debug)]
+/// Data of a Feature item, flags in the layout of `OutputItemData`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FeatureItemData {
+    /// The item's data as encoded
     pub data: u32,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Collection type, HID 1.11 section 6.2.2.6
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Collection {
+    /// A group of axes at a single point, e.g. of a sensor
     Physical,
+    /// A group of controls with one purpose, e.g. a mouse or a keyboard, the top level of a device
     Application,
+    /// A group of controls belonging together, e.g. a buffer and its length
     Logical,
+    /// A collection defining a report, with its own ID
     Report,
+    /// An array of selectors, each naming the usage it selects
     NamedArray,
+    /// A collection changing the meaning of the usage it holds
     UsageSwitch,
+    /// A collection modifying the meaning of the usage containing it
     UsageModifier,
+    /// A reserved collection type
     Reserved,
+    /// A vendor defined collection type
     Vendor(u8),
 }
 
@@ -241,34 +307,64 @@ impl Collection {
             n => Self::Vendor(n),
         }
     }
+
+    pub(crate) fn value(self) -> u8 {
+        match self {
+            Self::Physical => 0,
+            Self::Application => 1,
+            Self::Logical => 2,
+            Self::Report => 3,
+            Self::NamedArray => 4,
+            Self::UsageSwitch => 5,
+            Self::UsageModifier => 6,
+            Self::Reserved => 7,
+            Self::Vendor(n) => n,
+        }
+    }
 }
 
+/// A global item, setting state for all following main items until changed
 #[derive(Debug)]
 pub enum GlobalItem {
+    /// Usage page of the following usages
     UsagePage(u16),
+    /// Smallest value of the following fields
     LogicalMinimum(i32),
+    /// Largest value of the following fields
     LogicalMaximum(i32),
+    /// Physical value of the logical minimum
     PhysicalMinimum(i32),
+    /// Physical value of the logical maximum
     PhysicalMaximum(i32),
+    /// Power of 10 of the unit, as encoded (a 4 bit two's complement nibble)
     UnitExponent(u32),
-    Unit(u32), // TODO decode
+    // TODO decode
+    /// Unit of the physical values, see `unit::Unit`
+    Unit(u32),
+    /// Size of a field in bits
     ReportSize(u32),
+    /// Report ID of the following reports
     ReportID(u8),
+    /// Number of fields the next main items define
     ReportCount(u32),
+    /// Pushes the global item state
     Push,
+    /// Pops the global item state pushed last
     Pop,
+    /// A reserved global item tag
     Reserved,
 }
 
 impl GlobalItem {
-    fn new(tag: u8, data: u32) -> Self {
+    fn new(tag: u8, data: u32, size: usize) -> Self {
         match tag {
             0 => Self::UsagePage(data as u16),
-            1 => Self::LogicalMinimum(data as i32), // FIXME check this works with signs
+            // Minimums are signed, devices commonly encode maximums like 255 in one byte though
+            1 => Self::LogicalMinimum(sign_extend(data, size)),
             2 => Self::LogicalMaximum(data as i32), // FIXME check this works with signs
-            3 => Self::PhysicalMinimum(data as i32), // FIXME check this works with signs
+            3 => Self::PhysicalMinimum(sign_extend(data, size)),
             4 => Self::PhysicalMaximum(data as i32), // FIXME check this works with signs
-            5 => Self::UnitExponent(data as u32),
+            5 => Self::UnitExponent(data),
             6 => Self::Unit(data),
             7 => Self::ReportSize(data),
             8 => Self::ReportID(data as u8),
@@ -280,21 +376,44 @@ impl GlobalItem {
     }
 }
 
+pub(crate) fn sign_extend(data: u32, size: usize) -> i32 {
+    match size {
+        1 => data as u8 as i8 as i32,
+        2 => data as u16 as i16 as i32,
+        _ => data as i32,
+    }
+}
+
+/// A local item, describing the next main item only
 #[derive(Debug)]
 pub enum LocalItem {
+    /// Usage of a field, on the current usage page
     Usage(u16),
+    /// First usage of a range
     UsageMinimum(u16),
+    /// Last usage of a range
     UsageMaximum(u16),
+    /// Usage of a field with its usage page
     ExtendedUsage(u16, u16),
+    /// First usage of a range, with its usage page
     ExtendedUsageMinimum(u16, u16),
+    /// Last usage of a range, with its usage page
     ExtendedUsageMaximum(u16, u16),
+    /// Body part a control is used with
     DesignatorIndex(u32),
+    /// First designator of a range
     DesignatorMinimum(u32),
+    /// Last designator of a range
     DesignatorMaximum(u32),
+    /// String descriptor describing a field
     StringIndex(u32),
+    /// First string descriptor of a range
     StringMinimum(u32),
+    /// Last string descriptor of a range
     StringMaximum(u32),
-    Delimiter(bool), // true - open, false - close
+    /// True - open, false - close
+    Delimiter(bool),
+    /// A reserved local item tag
     Reserved,
 }
 
@@ -0,0 +1,199 @@
+//! Battery level reporting
+//!
+//! Devices report their battery either through standard usages (Generic Device Controls /
+//! Battery Strength, Battery System page) in input or feature reports, or through vendor
+//! protocols, which are handled by the vendor decoders.
+
+use std::fmt::Display;
+
+use super::{
+    input::InputValue,
+    report::{Report, ReportKind},
+    Parser,
+};
+
+/// Generic Device Controls usage page
+pub const GENERIC_DEVICE_CONTROLS_PAGE: u16 = 0x06;
+/// Battery Strength on the Generic Device Controls page, in percent
+pub const BATTERY_STRENGTH: u16 = 0x20;
+
+/// Battery System usage page
+pub const BATTERY_SYSTEM_PAGE: u16 = 0x85;
+/// Charging on the Battery System page
+pub const CHARGING: u16 = 0x44;
+/// Relative State Of Charge on the Battery System page, in percent
+pub const RELATIVE_STATE_OF_CHARGE: u16 = 0x64;
+/// Absolute State Of Charge on the Battery System page
+pub const ABSOLUTE_STATE_OF_CHARGE: u16 = 0x65;
+
+/// Battery level and charging state of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Battery {
+    /// In percent
+    pub level: u8,
+    /// Whether the battery is charging, None if the device doesn't say
+    pub charging: Option<bool>,
+}
+
+impl Display for Battery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}%", self.level)?;
+
+        match self.charging {
+            Some(true) => write!(f, " (charging)"),
+            Some(false) => write!(f, " (discharging)"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a usage reports a battery level
+pub fn is_battery_level(usage: (u16, u16)) -> bool {
+    matches!(
+        usage,
+        (GENERIC_DEVICE_CONTROLS_PAGE, BATTERY_STRENGTH)
+            | (BATTERY_SYSTEM_PAGE, RELATIVE_STATE_OF_CHARGE)
+            | (BATTERY_SYSTEM_PAGE, ABSOLUTE_STATE_OF_CHARGE)
+    )
+}
+
+impl Parser {
+    /// Reports carrying a battery level, as (report kind, report ID)
+    pub fn battery_reports(&self) -> Vec<(ReportKind, Option<u8>)> {
+        let mut reports: Vec<_> = self
+            .reports()
+            .into_iter()
+            .filter(|report| Self::has_battery_level(report))
+            .map(|report| (report.report_type.kind(), report.report_id))
+            .collect();
+        reports.dedup();
+
+        reports
+    }
+
+    /// Battery state from an input or feature report (starting with the report ID, if used)
+    pub fn battery(&self, kind: ReportKind, bytes: &[u8]) -> Option<Battery> {
+        let mut level = None;
+        let mut charging = None;
+
+        for report in self
+            .reports()
+            .into_iter()
+            .filter(|report| report.report_type.kind() == kind)
+        {
+            for input in report.parse(bytes).unwrap_or_default() {
+                match (input.usage, input.value) {
+                    (usage, InputValue::UInt(value)) if is_battery_level(usage) => {
+                        level = Some(Self::percent(report, value as i64));
+                    }
+                    (usage, InputValue::Int(value)) if is_battery_level(usage) => {
+                        level = Some(Self::percent(report, value as i64));
+                    }
+                    ((BATTERY_SYSTEM_PAGE, CHARGING), InputValue::Bool(value)) => {
+                        charging = Some(value);
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        level.map(|level| Battery { level, charging })
+    }
+
+    fn has_battery_level(report: &Report) -> bool {
+        // a range only within one page, like the parser assigns its usages
+        let in_range = match (report.usage_minimum, report.usage_maximum) {
+            (Some((page, min)), Some((max_page, max))) if page == max_page => {
+                (min..=max).any(|id| is_battery_level((page, id)))
+            }
+            _ => false,
+        };
+
+        in_range || report.usages.iter().any(|usage| is_battery_level(*usage))
+    }
+
+    // Scale the logical range to 0 - 100%
+    fn percent(report: &Report, value: i64) -> u8 {
+        let min = report.logical_minimum as i64;
+        let max = report.logical_maximum as i64;
+
+        if max <= min {
+            return value.clamp(0, 100) as u8;
+        }
+
+        ((value.clamp(min, max) - min) * 100 / (max - min)) as u8
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BasicItems, Parser, ReportKind};
+    use super::{Battery, ABSOLUTE_STATE_OF_CHARGE, BATTERY_SYSTEM_PAGE, RELATIVE_STATE_OF_CHARGE};
+    use crate::{CollectionType, DescriptorBuilder, FeatureItemData};
+
+    // Battery strength (logical 0 - 200) and charging state in feature report 3
+    const BATTERY: [u8; 38] = [
+        0x05, 0x06, 0x09, 0x20, 0xa1, 0x01, 0x85, 0x03, 0x09, 0x20, 0x15, 0x00, 0x26, 0xc8, 0x00,
+        0x75, 0x08, 0x95, 0x01, 0xb1, 0x02, 0x05, 0x85, 0x09, 0x44, 0x25, 0x01, 0x75, 0x01, 0x95,
+        0x01, 0xb1, 0x02, 0x95, 0x07, 0xb1, 0x01, 0xc0,
+    ];
+
+    #[test]
+    fn finds_battery_reports() {
+        let parser = Parser::new(BasicItems::new(&BATTERY));
+
+        assert_eq!(
+            parser.battery_reports(),
+            vec![(ReportKind::Feature, Some(3))]
+        );
+    }
+
+    #[test]
+    fn reads_scaled_battery_level() {
+        let parser = Parser::new(BasicItems::new(&BATTERY));
+
+        let battery = parser.battery(ReportKind::Feature, &[0x03, 150, 0x01]);
+        assert_eq!(
+            battery,
+            Some(Battery {
+                level: 75,
+                charging: Some(true)
+            })
+        );
+        assert_eq!(format!("{}", battery.unwrap()), "75% (charging)");
+
+        assert_eq!(parser.battery(ReportKind::Input, &[0x03, 150, 0x01]), None);
+    }
+
+    #[test]
+    fn finds_battery_levels_in_usage_ranges() {
+        // both states of charge (logical 0 - 100) from a usage range in feature report 4
+        let parser = DescriptorBuilder::new()
+            .usage_page(BATTERY_SYSTEM_PAGE)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .report_id(4)
+            .usage_minimum(RELATIVE_STATE_OF_CHARGE)
+            .usage_maximum(ABSOLUTE_STATE_OF_CHARGE)
+            .logical_minimum(0)
+            .logical_maximum(100)
+            .report_size(8)
+            .report_count(2)
+            .feature(FeatureItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+
+        assert_eq!(
+            parser.battery_reports(),
+            vec![(ReportKind::Feature, Some(4))]
+        );
+        assert_eq!(
+            parser.battery(ReportKind::Feature, &[0x04, 80, 80]),
+            Some(Battery {
+                level: 80,
+                charging: None
+            })
+        );
+    }
+}
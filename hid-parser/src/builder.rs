@@ -0,0 +1,209 @@
+//! Building report descriptors
+//!
+//! Encodes short items with the smallest data size holding their value, signed for the
+//! logical and physical extents as the spec requires.
+
+use crate::{basic::Collection, FeatureItemData, InputItemData, OutputItemData, ReportDescriptor};
+
+// Item types, HID 1.11 section 6.2.2.2
+pub(crate) const MAIN: u8 = 0;
+pub(crate) const GLOBAL: u8 = 1;
+pub(crate) const LOCAL: u8 = 2;
+
+/// Builds report descriptors item by item, in descriptor order
+#[derive(Debug, Default)]
+pub struct DescriptorBuilder {
+    bytes: Vec<u8>,
+}
+
+impl DescriptorBuilder {
+    /// An empty descriptor
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn item(mut self, item_type: u8, tag: u8, data: &[u8]) -> Self {
+        let size = match data.len() {
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            _ => 3, // four bytes
+        };
+
+        self.bytes.push(tag << 4 | item_type << 2 | size);
+        self.bytes.extend(data);
+
+        self
+    }
+
+    pub(crate) fn unsigned(self, item_type: u8, tag: u8, value: u32) -> Self {
+        let bytes = value.to_le_bytes();
+
+        match value {
+            0..=0xff => self.item(item_type, tag, &bytes[0..1]),
+            0x100..=0xffff => self.item(item_type, tag, &bytes[0..2]),
+            _ => self.item(item_type, tag, &bytes),
+        }
+    }
+
+    pub(crate) fn signed(self, item_type: u8, tag: u8, value: i32) -> Self {
+        let bytes = value.to_le_bytes();
+
+        match value {
+            -0x80..=0x7f => self.item(item_type, tag, &bytes[0..1]),
+            -0x8000..=0x7fff => self.item(item_type, tag, &bytes[0..2]),
+            _ => self.item(item_type, tag, &bytes),
+        }
+    }
+
+    /// Adds an Input main item with the flags of its fields
+    pub fn input(self, flags: InputItemData) -> Self {
+        self.unsigned(MAIN, 0b1000, flags.data)
+    }
+
+    /// Adds an Output main item with the flags of its fields
+    pub fn output(self, flags: OutputItemData) -> Self {
+        self.unsigned(MAIN, 0b1001, flags.data)
+    }
+
+    /// Adds a Feature main item with the flags of its fields
+    pub fn feature(self, flags: FeatureItemData) -> Self {
+        self.unsigned(MAIN, 0b1011, flags.data)
+    }
+
+    /// Starts a collection
+    pub fn collection(self, collection: Collection) -> Self {
+        self.item(MAIN, 0b1010, &[collection.value()])
+    }
+
+    /// Ends the innermost collection
+    pub fn end_collection(self) -> Self {
+        self.item(MAIN, 0b1100, &[])
+    }
+
+    /// Adds a Usage Page global item
+    pub fn usage_page(self, page: u16) -> Self {
+        self.unsigned(GLOBAL, 0, page as u32)
+    }
+
+    /// Adds a Logical Minimum global item
+    pub fn logical_minimum(self, minimum: i32) -> Self {
+        self.signed(GLOBAL, 1, minimum)
+    }
+
+    /// Adds a Logical Maximum global item
+    pub fn logical_maximum(self, maximum: i32) -> Self {
+        self.signed(GLOBAL, 2, maximum)
+    }
+
+    /// Adds a Physical Minimum global item
+    pub fn physical_minimum(self, minimum: i32) -> Self {
+        self.signed(GLOBAL, 3, minimum)
+    }
+
+    /// Adds a Physical Maximum global item
+    pub fn physical_maximum(self, maximum: i32) -> Self {
+        self.signed(GLOBAL, 4, maximum)
+    }
+
+    /// Adds a Unit Exponent global item, as encoded: a 4 bit two's complement nibble, e.g. 0x0e
+    /// for -2
+    pub fn unit_exponent(self, exponent: u32) -> Self {
+        self.unsigned(GLOBAL, 5, exponent)
+    }
+
+    /// Adds a Unit global item, see `unit::Unit`
+    pub fn unit(self, unit: u32) -> Self {
+        self.unsigned(GLOBAL, 6, unit)
+    }
+
+    /// Adds a Report Size global item, in bits
+    pub fn report_size(self, bits: u32) -> Self {
+        self.unsigned(GLOBAL, 7, bits)
+    }
+
+    /// Adds a Report ID global item
+    pub fn report_id(self, id: u8) -> Self {
+        self.unsigned(GLOBAL, 8, id as u32)
+    }
+
+    /// Adds a Report Count global item
+    pub fn report_count(self, count: u32) -> Self {
+        self.unsigned(GLOBAL, 9, count)
+    }
+
+    /// Adds a Usage local item
+    pub fn usage(self, usage: u16) -> Self {
+        self.unsigned(LOCAL, 0, usage as u32)
+    }
+
+    /// Adds a Usage Minimum local item
+    pub fn usage_minimum(self, usage: u16) -> Self {
+        self.unsigned(LOCAL, 1, usage as u32)
+    }
+
+    /// Adds a Usage Maximum local item
+    pub fn usage_maximum(self, usage: u16) -> Self {
+        self.unsigned(LOCAL, 2, usage as u32)
+    }
+
+    /// The descriptor built so far
+    pub fn build(self) -> ReportDescriptor {
+        ReportDescriptor { bytes: self.bytes }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DescriptorBuilder;
+    use crate::{basic::Collection, InputItemData};
+
+    #[test]
+    fn encodes_minimal_items() {
+        const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+        const RELATIVE: InputItemData = InputItemData { data: 0x06 };
+
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(Collection::Application)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(3)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(3)
+            .input(VARIABLE)
+            .usage_page(0x01)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(RELATIVE)
+            .usage_page(0xff00)
+            .usage(0x01)
+            .logical_minimum(-32768)
+            .logical_maximum(0x10000)
+            .input(VARIABLE)
+            .end_collection()
+            .build();
+
+        assert_eq!(
+            descriptor.bytes,
+            vec![
+                0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15, 0x00,
+                0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x05, 0x01, 0x09, 0x30, 0x15, 0x81,
+                0x25, 0x7f, 0x75, 0x08, 0x95, 0x01, 0x81, 0x06, 0x06, 0x00, 0xff, 0x09, 0x01, 0x16,
+                0x00, 0x80, 0x27, 0x00, 0x00, 0x01, 0x00, 0x81, 0x02, 0xc0,
+            ]
+        );
+
+        let parser = descriptor.decode();
+        let reports = parser.reports();
+        assert_eq!(reports[1].logical_minimum, -127);
+        assert_eq!(reports[2].logical_minimum, -32768);
+        assert_eq!(reports[2].logical_maximum, 0x10000);
+    }
+}
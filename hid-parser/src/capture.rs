@@ -0,0 +1,651 @@
+//! Capture files
+//!
+//! A capture holds everything needed to look at a device session again without the device:
+//! the device identity, the raw report descriptors of its interfaces and the timestamped
+//! reports in both directions. The format is a small length-prefixed binary container, so
+//! other tools can read and write it without pulling in a serialization library.
+//!
+//! All integers are little endian. A file starts with the magic "HBCP" and a u16 version,
+//! followed by records of a u8 type, a u32 payload length and the payload:
+//!
+//! ```text
+//! 1 device      vid u16, pid u16, manufacturer, product, serial (u16 length + UTF-8 each,
+//!               an empty string stands for a missing one)
+//! 2 descriptor  interface u8, report descriptor bytes
+//! 3 transfer    timestamp u64 (microseconds since the capture started), interface u8,
+//!               direction u8, report bytes (starting with the report ID if there is one)
+//! 4 marker      timestamp u64, UTF-8 text (empty for a marker without a name)
+//! ```
+//!
+//! Readers skip records of unknown types, so new record types don't need a new version. Payloads
+//! longer than MAX_RECORD are taken for a corrupt file rather than allocated.
+//!
+//! With the `rayon` feature, `Capture::decode_inputs` decodes the input reports of a capture on
+//! all cores: hours of reports at 1 kHz are split into batches, each decoded into one reused
+//! `FlatInputs`, and the results are put back together in capture order.
+
+use std::{
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{DeviceModel, ReportDescriptor};
+
+#[cfg(feature = "rayon")]
+use {crate::FlatInputs, rayon::prelude::*};
+
+/// Magic bytes starting every capture file
+pub const MAGIC: &[u8; 4] = b"HBCP";
+/// Version of the format written
+pub const VERSION: u16 = 1;
+
+const DEVICE: u8 = 1;
+const DESCRIPTOR: u8 = 2;
+const TRANSFER: u8 = 3;
+const MARKER: u8 = 4;
+
+// Report descriptors are at most 64 KiB and reports a few KiB
+const MAX_RECORD: usize = 1 << 20;
+
+// Input reports decoded together, large enough to outweigh handing the batch to a thread
+#[cfg(feature = "rayon")]
+const BATCH: usize = 4096;
+
+/// Identity of the captured device
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceMetadata {
+    /// USB vendor ID
+    pub vendor_id: u16,
+    /// USB product ID
+    pub product_id: u16,
+    /// Manufacturer string, if the device has one
+    pub manufacturer: Option<String>,
+    /// Product string, if the device has one
+    pub product: Option<String>,
+    /// Serial number string, if the device has one
+    pub serial: Option<String>,
+}
+
+/// Direction and kind of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Input report from the device
+    In,
+    /// Output report to the device
+    Out,
+    /// Feature report read from the device (Get_Report)
+    FeatureIn,
+    /// Feature report sent to the device (Set_Report)
+    FeatureOut,
+}
+
+impl Direction {
+    fn to_byte(self) -> u8 {
+        match self {
+            Direction::In => 0,
+            Direction::Out => 1,
+            Direction::FeatureIn => 2,
+            Direction::FeatureOut => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Direction::In),
+            1 => Ok(Direction::Out),
+            2 => Ok(Direction::FeatureIn),
+            3 => Ok(Direction::FeatureOut),
+            _ => Err(anyhow!("Unknown transfer direction {}", byte)),
+        }
+    }
+}
+
+/// A report sent or received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transfer {
+    /// Since the start of the capture
+    pub timestamp: Duration,
+    /// Interface the report went through
+    pub interface: u8,
+    /// Direction and kind of the report
+    pub direction: Direction,
+    /// The report, starting with the report ID if there is one
+    pub bytes: Vec<u8>,
+}
+
+/// A point in the session someone noted, e.g. "started rubbing the cable"
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Marker {
+    /// Since the start of the capture
+    pub timestamp: Duration,
+    /// What was noted, None for a marker without a name
+    pub text: Option<String>,
+}
+
+/// A record of a capture file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    /// The device the capture is of
+    Device(DeviceMetadata),
+    /// A report descriptor of an interface
+    Descriptor {
+        /// Interface number
+        interface: u8,
+        /// The descriptor
+        descriptor: ReportDescriptor,
+    },
+    /// A report sent or received
+    Transfer(Transfer),
+    /// A point in the session someone noted
+    Marker(Marker),
+}
+
+/// Writes capture files record by record
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the file header
+    pub fn new(mut writer: W) -> Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        Ok(Self { writer })
+    }
+
+    /// Writes a record
+    pub fn write(&mut self, record: &Record) -> Result<()> {
+        let mut payload = vec![];
+
+        let kind = match record {
+            Record::Device(device) => {
+                payload.extend(device.vendor_id.to_le_bytes());
+                payload.extend(device.product_id.to_le_bytes());
+                for string in [&device.manufacturer, &device.product, &device.serial] {
+                    write_string(&mut payload, string.as_deref().unwrap_or_default())?;
+                }
+
+                DEVICE
+            }
+            Record::Descriptor {
+                interface,
+                descriptor,
+            } => {
+                payload.push(*interface);
+                payload.extend(&descriptor.bytes);
+
+                DESCRIPTOR
+            }
+            Record::Transfer(transfer) => {
+                return self.write_transfer(
+                    transfer.timestamp,
+                    transfer.interface,
+                    transfer.direction,
+                    &transfer.bytes,
+                );
+            }
+            Record::Marker(marker) => {
+                payload.extend((marker.timestamp.as_micros() as u64).to_le_bytes());
+                payload.extend(marker.text.as_deref().unwrap_or_default().as_bytes());
+
+                MARKER
+            }
+        };
+
+        self.writer.write_all(&[kind])?;
+        self.writer
+            .write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+
+        Ok(())
+    }
+
+    /// A transfer record straight from the report bytes, without allocating, for recording
+    /// devices reporting at several kHz
+    pub fn write_transfer(
+        &mut self,
+        timestamp: Duration,
+        interface: u8,
+        direction: Direction,
+        bytes: &[u8],
+    ) -> Result<()> {
+        let length = 8 + 1 + 1 + bytes.len();
+
+        self.writer.write_all(&[TRANSFER])?;
+        self.writer.write_all(&(length as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&(timestamp.as_micros() as u64).to_le_bytes())?;
+        self.writer.write_all(&[interface, direction.to_byte()])?;
+        self.writer.write_all(bytes)?;
+
+        Ok(())
+    }
+
+    /// Flushes the records written so far
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Flushes and returns the writer
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+fn write_string(payload: &mut Vec<u8>, string: &str) -> Result<()> {
+    let length = u16::try_from(string.len()).context("String too long for a capture")?;
+
+    payload.extend(length.to_le_bytes());
+    payload.extend(string.as_bytes());
+
+    Ok(())
+}
+
+/// Reads capture files record by record, also as an iterator of records
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    version: u16,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Reads and checks the file header
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; 6];
+        reader
+            .read_exact(&mut header)
+            .context("Not a capture file, it is too short")?;
+
+        if &header[0..4] != MAGIC {
+            return Err(anyhow!("Not a capture file"));
+        }
+
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version > VERSION {
+            return Err(anyhow!(
+                "Capture file version {} is newer than the supported version {}",
+                version,
+                VERSION
+            ));
+        }
+
+        Ok(Self { reader, version })
+    }
+
+    /// Format version of the file
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// None at the end of the file
+    pub fn read_record(&mut self) -> Result<Option<Record>> {
+        loop {
+            let mut kind = [0u8; 1];
+            match self.reader.read_exact(&mut kind) {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err.into()),
+            }
+
+            let mut length = [0u8; 4];
+            self.reader
+                .read_exact(&mut length)
+                .context("Truncated capture record")?;
+
+            let length = u32::from_le_bytes(length) as usize;
+            if length > MAX_RECORD {
+                return Err(anyhow!(
+                    "Capture record of {} bytes, longer than any record can be",
+                    length
+                ));
+            }
+            let mut payload = vec![0u8; length];
+            self.reader
+                .read_exact(&mut payload)
+                .context("Truncated capture record")?;
+
+            let record = match kind[0] {
+                DEVICE => Record::Device(read_device(&payload)?),
+                DESCRIPTOR => match payload.split_first() {
+                    Some((interface, bytes)) => Record::Descriptor {
+                        interface: *interface,
+                        descriptor: ReportDescriptor {
+                            bytes: bytes.to_vec(),
+                        },
+                    },
+                    None => return Err(anyhow!("Empty descriptor record")),
+                },
+                TRANSFER => Record::Transfer(read_transfer(&payload)?),
+                MARKER => Record::Marker(read_marker(&payload)?),
+                // written by a newer version, safe to ignore
+                _ => continue,
+            };
+
+            return Ok(Some(record));
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+// Splits `n` bytes off the front of a record payload
+fn take<'a>(payload: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    if payload.len() < n {
+        return Err(anyhow!("Truncated capture record"));
+    }
+
+    let (head, tail) = payload.split_at(n);
+    *payload = tail;
+
+    Ok(head)
+}
+
+fn take_u16(payload: &mut &[u8]) -> Result<u16> {
+    let bytes = take(payload, 2)?;
+
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn take_string(payload: &mut &[u8]) -> Result<Option<String>> {
+    let length = take_u16(payload)? as usize;
+    let string = String::from_utf8(take(payload, length)?.to_vec())
+        .context("Invalid string in a capture record")?;
+
+    Ok(Some(string).filter(|s| !s.is_empty()))
+}
+
+fn read_device(mut payload: &[u8]) -> Result<DeviceMetadata> {
+    let payload = &mut payload;
+
+    Ok(DeviceMetadata {
+        vendor_id: take_u16(payload)?,
+        product_id: take_u16(payload)?,
+        manufacturer: take_string(payload)?,
+        product: take_string(payload)?,
+        serial: take_string(payload)?,
+    })
+}
+
+fn read_marker(mut payload: &[u8]) -> Result<Marker> {
+    let timestamp = u64::from_le_bytes(take(&mut payload, 8)?.try_into()?);
+    let text = String::from_utf8(payload.to_vec()).context("Invalid marker text")?;
+
+    Ok(Marker {
+        timestamp: Duration::from_micros(timestamp),
+        text: Some(text).filter(|text| !text.is_empty()),
+    })
+}
+
+fn read_transfer(mut payload: &[u8]) -> Result<Transfer> {
+    let payload = &mut payload;
+
+    let timestamp = u64::from_le_bytes(take(payload, 8)?.try_into()?);
+    let header = take(payload, 2)?;
+
+    Ok(Transfer {
+        timestamp: Duration::from_micros(timestamp),
+        interface: header[0],
+        direction: Direction::from_byte(header[1])?,
+        bytes: payload.to_vec(),
+    })
+}
+
+/// A whole capture in memory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capture {
+    /// Identity of the captured device
+    pub device: DeviceMetadata,
+    /// Report descriptors of all interfaces
+    pub descriptors: DeviceModel,
+    /// Reports in capture order
+    pub transfers: Vec<Transfer>,
+    /// Markers in capture order
+    pub markers: Vec<Marker>,
+}
+
+impl Capture {
+    /// Reads a whole capture file
+    pub fn read(reader: impl Read) -> Result<Self> {
+        let mut capture = Capture::default();
+        let mut descriptors = vec![];
+
+        for record in CaptureReader::new(reader)? {
+            match record? {
+                Record::Device(device) => capture.device = device,
+                Record::Descriptor {
+                    interface,
+                    descriptor,
+                } => descriptors.push((interface, descriptor)),
+                Record::Transfer(transfer) => capture.transfers.push(transfer),
+                Record::Marker(marker) => capture.markers.push(marker),
+            }
+        }
+        capture.descriptors = descriptors.into_iter().collect();
+
+        Ok(capture)
+    }
+
+    /// Writes the capture as a file
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        let mut writer = CaptureWriter::new(writer)?;
+
+        writer.write(&Record::Device(self.device.clone()))?;
+        for record in self.descriptors.records() {
+            writer.write(&record)?;
+        }
+        for transfer in &self.transfers {
+            writer.write(&Record::Transfer(transfer.clone()))?;
+        }
+        for marker in &self.markers {
+            writer.write(&Record::Marker(marker.clone()))?;
+        }
+
+        writer.flush()
+    }
+
+    /// Calls `decode` with every input report and the inputs its interface's descriptor splits it
+    /// into, in parallel batches, returning the results in capture order. The inputs are empty
+    /// for reports of interfaces without a descriptor and reports the descriptor doesn't
+    /// describe.
+    #[cfg(feature = "rayon")]
+    pub fn decode_inputs<T, F>(&self, decode: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&Transfer, &FlatInputs) -> T + Sync,
+    {
+        let batches: Vec<Vec<T>> = self
+            .transfers
+            .par_chunks(BATCH)
+            .map(|batch| {
+                let mut flat = FlatInputs::new();
+
+                batch
+                    .iter()
+                    .filter(|transfer| transfer.direction == Direction::In)
+                    .map(|transfer| {
+                        match self.descriptors.parser(transfer.interface) {
+                            Some(parser) => parser.parse_input_flat(&transfer.bytes, &mut flat),
+                            None => flat.clear(),
+                        }
+                        decode(transfer, &flat)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        batches.into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Capture, CaptureReader, DeviceMetadata, Direction, Marker, Record, Transfer};
+    use crate::ReportDescriptor;
+
+    fn capture() -> Capture {
+        Capture {
+            device: DeviceMetadata {
+                vendor_id: 0x046d,
+                product_id: 0xc52b,
+                manufacturer: Some("Logitech".to_string()),
+                product: Some("USB Receiver".to_string()),
+                serial: None,
+            },
+            descriptors: [(
+                2,
+                ReportDescriptor {
+                    bytes: vec![0x06, 0x00, 0xff, 0x09, 0x01, 0xa1, 0x01, 0xc0],
+                },
+            )]
+            .into_iter()
+            .collect(),
+            transfers: vec![
+                Transfer {
+                    timestamp: Duration::from_micros(0),
+                    interface: 2,
+                    direction: Direction::Out,
+                    bytes: vec![0x10, 0xff, 0x00, 0x1a, 0x00, 0x00, 0x00],
+                },
+                Transfer {
+                    timestamp: Duration::from_micros(1250),
+                    interface: 2,
+                    direction: Direction::In,
+                    bytes: vec![0x10, 0xff, 0x00, 0x1a, 0x04, 0x05, 0x00],
+                },
+                Transfer {
+                    timestamp: Duration::from_secs(3),
+                    interface: 2,
+                    direction: Direction::FeatureIn,
+                    bytes: vec![],
+                },
+            ],
+            markers: vec![
+                Marker {
+                    timestamp: Duration::from_millis(500),
+                    text: Some("started rubbing the cable".to_string()),
+                },
+                Marker {
+                    timestamp: Duration::from_secs(2),
+                    text: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn roundtrips_captures() {
+        let mut file = vec![];
+        capture().write(&mut file).unwrap();
+
+        assert_eq!(&file[0..6], b"HBCP\x01\x00");
+        assert_eq!(Capture::read(file.as_slice()).unwrap(), capture());
+    }
+
+    #[test]
+    fn skips_unknown_records() {
+        let mut file = b"HBCP\x01\x00".to_vec();
+        file.extend([0x7f, 3, 0, 0, 0, 1, 2, 3]);
+        file.extend([2, 3, 0, 0, 0, 1, 0xa1, 0x01]);
+
+        let records = CaptureReader::new(file.as_slice())
+            .unwrap()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(
+            records,
+            vec![Record::Descriptor {
+                interface: 1,
+                descriptor: ReportDescriptor {
+                    bytes: vec![0xa1, 0x01]
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(CaptureReader::new(b"HBC".as_slice()).is_err());
+        assert!(CaptureReader::new(b"PCAP\x01\x00".as_slice()).is_err());
+        assert!(CaptureReader::new(b"HBCP\x02\x00".as_slice()).is_err());
+
+        // truncated in the middle of a record
+        let mut file = vec![];
+        capture().write(&mut file).unwrap();
+        file.truncate(file.len() - 1);
+        assert!(Capture::read(file.as_slice()).is_err());
+
+        // a corrupt length isn't allocated
+        let mut file = b"HBCP\x01\x00".to_vec();
+        file.extend([2, 0xff, 0xff, 0xff, 0xff, 1]);
+        let err = Capture::read(file.as_slice()).unwrap_err();
+        assert!(err.to_string().contains("4294967295 bytes"), "{}", err);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn decodes_inputs_in_capture_order() {
+        use crate::{CollectionType, DescriptorBuilder, InputItemData, InputValue};
+
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0xff00)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .usage(0x02)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build();
+
+        // several batches of inputs, between outputs and reports of an unknown interface
+        let transfer = |i: usize, interface, direction| Transfer {
+            timestamp: Duration::from_millis(i as u64),
+            interface,
+            direction,
+            bytes: vec![i as u8],
+        };
+        let mut capture = Capture {
+            descriptors: [(0, descriptor)].into_iter().collect(),
+            ..Default::default()
+        };
+        for i in 0..10_000 {
+            capture.transfers.push(transfer(i, 0, Direction::In));
+            if i % 1000 == 0 {
+                capture.transfers.push(transfer(i, 0, Direction::Out));
+                capture.transfers.push(transfer(i, 1, Direction::In));
+            }
+        }
+
+        let decoded = capture.decode_inputs(|transfer, flat| {
+            let value = flat.inputs().first().map(|input| input.value);
+            (transfer.timestamp.as_millis() as usize, value)
+        });
+
+        assert_eq!(decoded.len(), 10_010);
+        let known: Vec<_> = decoded
+            .iter()
+            .filter(|(_, value)| value.is_some())
+            .collect();
+        assert_eq!(known.len(), 10_000);
+        for (i, (timestamp, value)) in known.into_iter().enumerate() {
+            assert_eq!(*timestamp, i);
+            assert_eq!(*value, Some(InputValue::UInt(i as u8 as u32)));
+        }
+        assert_eq!(decoded[1], (0, None)); // interface 1 has no descriptor
+    }
+}
@@ -1,23 +1,30 @@
 use std::fmt::Display;
 
 use super::input::Input;
+use super::report::Report;
+use super::usage;
 
-// Collection type, reused for reports
-#[derive(Debug)]
+/// Collection type, reused for reports
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Collection<T> {
+    /// Collection item data: physical, application, logical etc.
     pub collection_type: super::basic::Collection,
+    /// Usage page and usage of the collection
     pub usage: (u16, u16),
-    // "String and Physical indices, as well as delimiters may be associated with collections."
+    /// "String and Physical indices, as well as delimiters may be associated with collections."
     // TODO delimiter support (when needed)
     pub designator_index: Option<u32>,
+    /// String index of the collection
     pub string_index: Option<u32>,
+    /// Items and nested collections in descriptor order
     pub items: Vec<CollectionItem<T>>,
 }
 
 impl<T> Collection<T> {
-    pub fn map<O, F: Fn(&T) -> Option<O>>(&self, f: F) -> Collection<O>
+    /// Maps the items, dropping those `f` returns None for
+    pub fn map<O, F>(&self, f: F) -> Collection<O>
     where
-        F: Copy,
+        F: Fn(&T) -> Option<O> + Copy,
     {
         Collection {
             collection_type: self.collection_type,
@@ -40,9 +47,36 @@ impl<T> Collection<T> {
     }
 }
 
-#[derive(Debug)]
+impl<T> Collection<T> {
+    /// Calls `f` with every item in the collection and its sub-collections, in descriptor order,
+    /// without collecting them
+    pub fn visit<F: FnMut(&T)>(&self, f: &mut F) {
+        for item in &self.items {
+            match item {
+                CollectionItem::Collection(c) => c.visit(f),
+                CollectionItem::Item(item) => f(item),
+            }
+        }
+    }
+
+    /// All items in the collection and its sub-collections, in descriptor order
+    pub fn flatten(&self) -> Vec<&T> {
+        self.items
+            .iter()
+            .flat_map(|item| match item {
+                CollectionItem::Collection(c) => c.flatten(),
+                CollectionItem::Item(item) => vec![item],
+            })
+            .collect()
+    }
+}
+
+/// An item of a collection, either a nested collection or an item
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum CollectionItem<T> {
+    /// A nested collection
     Collection(Collection<T>),
+    /// An item
     Item(T),
 }
 
@@ -72,3 +106,33 @@ impl Display for Collection<Vec<Input>> {
         )
     }
 }
+
+// Indented layout of the descriptor, one collection or report per line
+impl Display for Collection<Report> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_layout(f, 0)
+    }
+}
+
+impl Collection<Report> {
+    fn fmt_layout(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        let indent = "  ".repeat(depth);
+
+        writeln!(
+            f,
+            "{}{:?} collection: {}",
+            indent,
+            self.collection_type,
+            usage::describe(self.usage)
+        )?;
+
+        for item in &self.items {
+            match item {
+                CollectionItem::Collection(c) => c.fmt_layout(f, depth + 1)?,
+                CollectionItem::Item(report) => writeln!(f, "{}  {}", indent, report)?,
+            }
+        }
+
+        Ok(())
+    }
+}
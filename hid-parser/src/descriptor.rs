@@ -1,35 +1,172 @@
-use crate::{BasicItems, Parser};
+use std::{collections::BTreeSet, fmt::Display};
 
-#[derive(Debug)]
+use crate::{
+    BasicItem, BasicItems, GlobalItem, MainItem, ParseError, Parser, ReportKind, Strictness,
+};
+
+/// A report descriptor as read from the device
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ReportDescriptor {
+    /// The raw bytes
     pub bytes: Vec<u8>,
 }
 
 impl ReportDescriptor {
+    /// Decodes the descriptor with a permissive parser
     pub fn decode(&self) -> Parser {
         Parser::new(self.basic_items())
     }
 
-    pub fn basic_items(&self) -> BasicItems {
+    /// Decodes the descriptor, failing on the first problem the strictness doesn't tolerate
+    pub fn decode_with(&self, strictness: Strictness) -> Result<Parser, ParseError> {
+        Parser::with_strictness(self.basic_items(), strictness)
+    }
+
+    /// The descriptor's short items in order
+    pub fn basic_items(&self) -> BasicItems<'_> {
         BasicItems::new(&self.bytes)
     }
+
+    /// The report descriptors of an interface with more than one as a single descriptor. Hosts
+    /// read them as one continuous descriptor: global items carry over from one to the next and
+    /// reports with the same ID continue where the previous descriptor left them. A descriptor
+    /// setting a report ID of its own which an earlier one already declared, or numbering its
+    /// reports where an earlier one doesn't, is a collision. None without any descriptors.
+    pub fn combine(
+        descriptors: &[ReportDescriptor],
+    ) -> Result<Option<ReportDescriptor>, Collision> {
+        if descriptors.is_empty() {
+            return Ok(None);
+        }
+        check_collisions(descriptors)?;
+
+        let bytes = descriptors
+            .iter()
+            .flat_map(|descriptor| descriptor.bytes.iter().copied())
+            .collect();
+
+        Ok(Some(ReportDescriptor { bytes }))
+    }
+}
+
+/// Reports of descriptors which can't be combined, see `ReportDescriptor::combine`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collision {
+    /// A descriptor sets a report ID an earlier descriptor declared the kind of report with
+    Report {
+        /// Index of the descriptor declaring the report again
+        descriptor: usize,
+        /// Kind of the report
+        kind: ReportKind,
+        /// Report ID of the report
+        report_id: u8,
+    },
+    /// A descriptor numbers its reports where an earlier one doesn't
+    MixedReportIds {
+        /// Index of the descriptor with report IDs
+        descriptor: usize,
+    },
+}
+
+impl Display for Collision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Collision::Report {
+                descriptor,
+                kind,
+                report_id,
+            } => write!(
+                f,
+                "report descriptor {} declares {:?} report {} again",
+                descriptor + 1,
+                kind,
+                report_id
+            ),
+            Collision::MixedReportIds { descriptor } => write!(
+                f,
+                "report descriptor {} uses report IDs, the ones before it don't",
+                descriptor + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Collision {}
+
+// Walks the descriptors as one, following Push and Pop, and checks the reports each declares
+// with a report ID it set itself against the reports of the descriptors before it
+fn check_collisions(descriptors: &[ReportDescriptor]) -> Result<(), Collision> {
+    let mut earlier: BTreeSet<(ReportKind, Option<u8>)> = BTreeSet::new();
+    // the report ID and the descriptor which set it
+    let mut current: (Option<u8>, Option<usize>) = (None, None);
+    let mut stack = vec![];
+
+    for (index, descriptor) in descriptors.iter().enumerate() {
+        let mut declared = BTreeSet::new();
+
+        for item in descriptor.basic_items() {
+            let kind = match item {
+                BasicItem::Global(GlobalItem::ReportID(id)) => {
+                    current = (Some(id), Some(index));
+                    continue;
+                }
+                BasicItem::Global(GlobalItem::Push) => {
+                    stack.push(current);
+                    continue;
+                }
+                BasicItem::Global(GlobalItem::Pop) => {
+                    current = stack.pop().unwrap_or(current);
+                    continue;
+                }
+                BasicItem::Main(MainItem::Input(_)) => ReportKind::Input,
+                BasicItem::Main(MainItem::Output(_)) => ReportKind::Output,
+                BasicItem::Main(MainItem::Feature(_)) => ReportKind::Feature,
+                _ => continue,
+            };
+
+            let report_id = current.0;
+            if report_id.is_some() && earlier.iter().any(|(_, id)| id.is_none()) {
+                return Err(Collision::MixedReportIds { descriptor: index });
+            }
+            if let (Some(id), Some(owner)) = current {
+                if owner == index && earlier.contains(&(kind, report_id)) {
+                    return Err(Collision::Report {
+                        descriptor: index,
+                        kind,
+                        report_id: id,
+                    });
+                }
+            }
+            declared.insert((kind, report_id));
+        }
+
+        earlier.extend(declared);
+    }
+
+    Ok(())
 }
 
+/// The HID class descriptor of an interface
 #[derive(Debug)]
 pub struct HidDescriptor<'a> {
-    pub(crate) interface_num: u8, // FIXME this is only used by the rusb integration
+    pub(crate) interface_num: u8,
     pub(crate) bytes: &'a [u8],
 }
 
+/// Type of a class descriptor
 #[derive(PartialEq, Eq, Debug)]
 pub enum DescriptorType {
+    /// HID descriptor
     Hid = 0x21,
+    /// Report descriptor
     Report = 0x22,
+    /// Physical descriptor
     Physical = 0x23,
 }
 
 // HID 1.11, section 6.2.1
 impl<'a> HidDescriptor<'a> {
+    /// Reads the descriptor from its bytes as found in the configuration descriptor
     pub fn new(bytes: &'a [u8], interface_num: u8) -> Self {
         Self {
             interface_num,
@@ -37,15 +174,23 @@ impl<'a> HidDescriptor<'a> {
         }
     }
 
+    /// Number of the interface the descriptor belongs to
+    pub fn interface_num(&self) -> u8 {
+        self.interface_num
+    }
+
+    /// HID specification release in BCD
     pub fn hid(&self) -> u16 {
         ((self.bytes[3] as u16) << 8) | self.bytes[2] as u16
     }
 
+    /// Number of class descriptors following
     pub fn num_descriptors(&self) -> u8 {
         self.bytes[5]
     }
 
-    pub(crate) fn descriptor_type(&self, index: usize) -> Option<DescriptorType> {
+    /// Type of the class descriptor at `index`, None past the last or for unknown types
+    pub fn descriptor_type(&self, index: usize) -> Option<DescriptorType> {
         if index >= self.num_descriptors() as usize {
             return None;
         }
@@ -58,7 +203,8 @@ impl<'a> HidDescriptor<'a> {
         }
     }
 
-    pub(crate) fn descriptor_length(&self, index: usize) -> Option<u16> {
+    /// Length of the class descriptor at `index`, None past the last
+    pub fn descriptor_length(&self, index: usize) -> Option<u16> {
         if index >= self.num_descriptors() as usize {
             return None;
         }
@@ -66,3 +212,92 @@ impl<'a> HidDescriptor<'a> {
         Some(((self.bytes[3 * index + 8] as u16) << 8) | self.bytes[3 * index + 7] as u16)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{CollectionType, DescriptorBuilder, InputItemData, ReportKind};
+
+    use super::{Collision, ReportDescriptor};
+
+    #[test]
+    fn combines_descriptors_of_an_interface() {
+        let first = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build();
+        // relies on the global items of the first one
+        let second = DescriptorBuilder::new()
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x38)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build();
+
+        let parser = ReportDescriptor::combine(&[first, second])
+            .unwrap()
+            .unwrap()
+            .decode();
+        assert_eq!(parser.collections().len(), 2);
+        assert_eq!(parser.report_length(ReportKind::Input, Some(1)), Some(3));
+
+        let wheel = parser.reports()[1];
+        assert_eq!(wheel.usages, [(0x01, 0x38)]);
+        assert_eq!((wheel.report_id, wheel.bit_offset), (Some(1), 8));
+        assert_eq!(wheel.logical_minimum, -127);
+
+        assert_eq!(ReportDescriptor::combine(&[]), Ok(None));
+    }
+
+    #[test]
+    fn rejects_reports_declared_twice() {
+        let mouse = |id: Option<u8>| {
+            let builder = DescriptorBuilder::new()
+                .usage_page(0x01)
+                .usage(0x02)
+                .collection(CollectionType::Application);
+            let builder = match id {
+                Some(id) => builder.report_id(id),
+                None => builder,
+            };
+
+            builder
+                .usage(0x30)
+                .report_size(8)
+                .report_count(1)
+                .input(InputItemData { data: 0x06 })
+                .end_collection()
+                .build()
+        };
+
+        assert_eq!(
+            ReportDescriptor::combine(&[mouse(Some(1)), mouse(Some(2)), mouse(Some(1))]),
+            Err(Collision::Report {
+                descriptor: 2,
+                kind: ReportKind::Input,
+                report_id: 1
+            })
+        );
+        assert_eq!(
+            ReportDescriptor::combine(&[mouse(None), mouse(Some(1))]),
+            Err(Collision::MixedReportIds { descriptor: 1 })
+        );
+        // the second continues the report of the first
+        assert!(ReportDescriptor::combine(&[mouse(None), mouse(None)]).is_ok());
+
+        let collision = ReportDescriptor::combine(&[mouse(Some(3)), mouse(Some(3))]).unwrap_err();
+        assert_eq!(
+            collision.to_string(),
+            "report descriptor 2 declares Input report 3 again"
+        );
+    }
+}
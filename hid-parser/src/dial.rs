@@ -0,0 +1,283 @@
+//! Radial controllers (Surface Dial and similar)
+//!
+//! A radial controller is a System Multi-Axis Controller application collection with a Puck
+//! physical collection holding a button and a relative Dial, rotating in tenths of a degree by
+//! convention. Like wheels, dials may describe a Resolution Multiplier feature. Dials with haptic
+//! feedback add a Simple Haptic Controller, whose Manual Trigger output plays a waveform from its
+//! waveform list: ordinal 3 is Click on every compliant device.
+
+use std::fmt::Display;
+
+use super::{
+    collection::{Collection, CollectionItem},
+    input::InputValue,
+    report::{Report, ReportKind},
+    scroll::{self, ResolutionMultiplier},
+    unit::{self, Measurement, Unit},
+    usage::{BUTTON, GENERIC_DESKTOP, HAPTICS},
+    Parser,
+};
+
+/// Generic Desktop usage of radial controller application collections
+pub const SYSTEM_MULTI_AXIS_CONTROLLER: u16 = 0x0e;
+/// Generic Desktop usage of the rotation
+pub const DIAL: u16 = 0x37;
+/// Haptics usage of the waveform to play
+pub const MANUAL_TRIGGER: u16 = 0x21;
+/// Waveform ordinal
+pub const CLICK: i32 = 3;
+
+/// One report of a dial
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DialEvent {
+    /// In the unit, or counts
+    pub rotation: f64,
+    /// None for counts
+    pub unit: Option<Unit>,
+    /// The puck's button
+    pub pressed: bool,
+}
+
+impl Display for DialEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.rotation > 0.0 {
+            write!(f, "+")?;
+        }
+        match self.unit {
+            Some(unit) => {
+                let rotation = Measurement {
+                    value: self.rotation,
+                    unit,
+                };
+                write!(f, "{}", rotation)?
+            }
+            None => write!(f, "{} counts", self.rotation)?,
+        }
+
+        match self.pressed {
+            true => write!(f, ", pressed"),
+            false => write!(f, ", released"),
+        }
+    }
+}
+
+/// The Manual Trigger of a haptic controller
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Haptics {
+    report: Report, // the output item holding the trigger
+    index: usize,   // of the trigger among the item's values
+}
+
+impl Haptics {
+    /// Report ID of the output report holding the trigger
+    pub fn report_id(&self) -> Option<u8> {
+        self.report.report_id
+    }
+
+    /// Sets the waveform ordinal to play in an output report, keeping the other outputs in it.
+    /// False if the ordinal is out of range or the report too short.
+    pub fn write(&self, output: &mut [u8], waveform: i32) -> bool {
+        if waveform < self.report.logical_minimum || waveform > self.report.logical_maximum {
+            return false;
+        }
+
+        self.report.write_value(output, self.index, waveform)
+    }
+}
+
+impl Parser {
+    /// Whether the descriptor has a radial controller
+    pub fn is_radial_controller(&self) -> bool {
+        self.radial_controller().is_some()
+    }
+
+    /// Rotation and button of a dial in an input report (starting with the report ID, if used),
+    /// None for reports without them
+    pub fn dial(&self, bytes: &[u8]) -> Option<DialEvent> {
+        let collection = self.radial_controller()?;
+        let mut event = None;
+        let mut pressed = false;
+
+        for report in collection.flatten() {
+            if report.report_type.kind() != ReportKind::Input {
+                continue;
+            }
+
+            for input in report.parse(bytes).unwrap_or_default() {
+                match (input.usage, input.value) {
+                    ((GENERIC_DESKTOP, DIAL), InputValue::Int(v)) => {
+                        event = Some(rotation(report, v as i64, input.value));
+                    }
+                    ((GENERIC_DESKTOP, DIAL), InputValue::UInt(v)) => {
+                        event = Some(rotation(report, v as i64, input.value));
+                    }
+                    ((BUTTON, 1), InputValue::Bool(b)) => pressed = b,
+                    _ => (),
+                }
+            }
+        }
+
+        event.map(|(rotation, unit)| DialEvent {
+            rotation,
+            unit,
+            pressed,
+        })
+    }
+
+    /// The Resolution Multiplier feature of the radial controller
+    pub fn dial_multiplier(&self) -> Option<ResolutionMultiplier> {
+        let mut multipliers = vec![];
+        scroll::collect_multipliers(self.radial_controller()?, &mut multipliers);
+
+        multipliers.into_iter().next()
+    }
+
+    /// The Manual Trigger output of a haptic controller in the radial controller
+    pub fn haptics(&self) -> Option<Haptics> {
+        find_trigger(self.radial_controller()?)
+    }
+
+    fn radial_controller(&self) -> Option<&Collection<Report>> {
+        self.collections()
+            .iter()
+            .find(|c| c.usage == (GENERIC_DESKTOP, SYSTEM_MULTI_AXIS_CONTROLLER))
+    }
+}
+
+// Relative rotation in the unit of the dial, or counts
+fn rotation(report: &Report, counts: i64, value: InputValue) -> (f64, Option<Unit>) {
+    let counts = report.delta(0, counts) as f64;
+
+    match Measurement::of(report, value) {
+        Some(measurement) => (
+            counts * unit::resolution(report).unwrap_or(1.0),
+            Some(measurement.unit),
+        ),
+        None => (counts, None),
+    }
+}
+
+fn find_trigger(collection: &Collection<Report>) -> Option<Haptics> {
+    for item in &collection.items {
+        match item {
+            CollectionItem::Collection(collection) => {
+                if let Some(haptics) = find_trigger(collection) {
+                    return Some(haptics);
+                }
+            }
+            CollectionItem::Item(report) if report.report_type.kind() == ReportKind::Output => {
+                let index = report
+                    .usages
+                    .iter()
+                    .position(|usage| *usage == (HAPTICS, MANUAL_TRIGGER));
+
+                if let Some(index) = index {
+                    return Some(Haptics {
+                        report: report.clone(),
+                        index: index.min(report.report_count.saturating_sub(1) as usize),
+                    });
+                }
+            }
+            CollectionItem::Item(_) => (),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        CollectionType, DescriptorBuilder, FeatureItemData, InputItemData, OutputItemData, Parser,
+        ReportKind,
+    };
+
+    use super::CLICK;
+
+    // Surface Dial like: report 1 with the button and the dial in tenths of a degree, feature
+    // report 2 with a multiplier, output report 3 with the manual trigger
+    fn dial() -> Parser {
+        DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x0e)
+            .collection(CollectionType::Application)
+            .usage_page(0x0d)
+            .usage(0x21)
+            .collection(CollectionType::Physical)
+            .report_id(1)
+            .usage_page(0x09)
+            .usage(0x01)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .usage_page(0x01)
+            .usage(0x37)
+            .logical_minimum(-3600)
+            .logical_maximum(3600)
+            .unit(0x14)
+            .unit_exponent(0x0f)
+            .report_size(15)
+            .input(InputItemData { data: 0x06 })
+            .report_id(2)
+            .usage(0x48)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .physical_minimum(1)
+            .physical_maximum(4)
+            .unit(0)
+            .unit_exponent(0)
+            .report_size(8)
+            .feature(FeatureItemData { data: 0x02 })
+            .end_collection()
+            .usage_page(0x0e)
+            .usage(0x01)
+            .collection(CollectionType::Logical)
+            .report_id(3)
+            .usage(0x21)
+            .logical_minimum(0)
+            .logical_maximum(7)
+            .physical_minimum(0)
+            .physical_maximum(0)
+            .report_size(8)
+            .output(OutputItemData { data: 0x02 })
+            .end_collection()
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn decodes_rotation_and_button() {
+        let parser = dial();
+        assert!(parser.is_radial_controller());
+
+        // +36 tenths of a degree, pressed
+        let event = parser.dial(&[0x01, 0x49, 0x00]).unwrap();
+        assert_eq!(event.to_string(), "+3.6 deg, pressed");
+
+        // -10 tenths
+        let event = parser.dial(&[0x01, 0xec, 0xff]).unwrap();
+        assert_eq!(event.to_string(), "-1 deg, released");
+
+        assert_eq!(parser.dial(&[0x02, 0x01]), None);
+    }
+
+    #[test]
+    fn finds_multiplier_and_haptics() {
+        let parser = dial();
+
+        let multiplier = parser.dial_multiplier().unwrap();
+        assert_eq!(multiplier.report_id(), Some(2));
+        assert_eq!(multiplier.factor(1), 4.0);
+
+        let haptics = parser.haptics().unwrap();
+        assert_eq!(haptics.report_id(), Some(3));
+        let length = parser.report_length(ReportKind::Output, Some(3)).unwrap();
+        let mut output = vec![0; length];
+        assert!(haptics.write(&mut output, CLICK));
+        assert_eq!(output, [3, 3]);
+        assert!(!haptics.write(&mut output, 8));
+    }
+}
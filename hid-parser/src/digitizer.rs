@@ -0,0 +1,526 @@
+//! Touch contacts of digitizers (touchpads, touchscreens, pens)
+//!
+//! Every finger or stylus collection on the Digitizers page describes one contact. Multitouch
+//! devices repeat the finger collection for every contact reported at once, hybrid devices
+//! report a few contacts per report and send the rest in the following ones.
+//!
+//! Pens report more: whether they are in range (hovering), the tip, barrel and eraser switches,
+//! and the tilt and twist of the pen, usually in degrees. A pen turned around to erase reports
+//! Invert while hovering and Eraser instead of the tip switch while touching.
+
+use std::fmt::Display;
+
+use super::{
+    collection::{Collection, CollectionItem},
+    input::InputValue,
+    report::{Report, ReportKind},
+    unit::{Measurement, Unit},
+    usage::GENERIC_DESKTOP,
+    Parser,
+};
+
+/// Usage page of the digitizer usages below
+pub const DIGITIZERS_PAGE: u16 = 0x0d;
+/// Stylus collection
+pub const STYLUS: u16 = 0x20;
+/// Finger collection, a touch screen or pad contact
+pub const FINGER: u16 = 0x22;
+/// Pressure of the tip
+pub const TIP_PRESSURE: u16 = 0x30;
+/// The pen is near enough to the surface to be tracked
+pub const IN_RANGE: u16 = 0x32;
+/// The eraser end is towards the surface
+pub const INVERT: u16 = 0x3c;
+/// Tilt towards positive X
+pub const X_TILT: u16 = 0x3d;
+/// Tilt towards positive Y
+pub const Y_TILT: u16 = 0x3e;
+/// Rotation around the pen's axis
+pub const TWIST: u16 = 0x41;
+/// The tip is touching
+pub const TIP_SWITCH: u16 = 0x42;
+/// The barrel button is pressed
+pub const BARREL_SWITCH: u16 = 0x44;
+/// The eraser end is touching
+pub const ERASER: u16 = 0x45;
+/// Identifies a contact across reports
+pub const CONTACT_ID: u16 = 0x51;
+
+const X: u16 = 0x30;
+const Y: u16 = 0x31;
+
+/// A finger on a touch screen or pad
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    /// Contact ID, if the device reports one
+    pub id: Option<u32>,
+    /// 0 - 1 across the logical range
+    pub x: f32,
+    /// 0 - 1 across the logical range
+    pub y: f32,
+    /// 0 - 1
+    pub pressure: Option<f32>,
+    /// Tip switch, or in range for devices without one
+    pub touching: bool,
+}
+
+/// Where a pen is relative to the surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PenState {
+    /// Too far to be tracked
+    OutOfRange,
+    /// Tracked above the surface
+    Hovering,
+    /// Touching with the tip or the eraser
+    Touching,
+}
+
+impl Display for PenState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PenState::OutOfRange => write!(f, "out of range"),
+            PenState::Hovering => write!(f, "hovering"),
+            PenState::Touching => write!(f, "touching"),
+        }
+    }
+}
+
+/// A physical value, or the logical one for fields without a unit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Physical {
+    /// In the unit, or logical
+    pub value: f64,
+    /// None for logical values
+    pub unit: Option<Unit>,
+}
+
+impl Physical {
+    fn of(report: &Report, value: InputValue, logical: i64) -> Self {
+        match Measurement::of(report, value) {
+            Some(measurement) => Physical {
+                value: measurement.value,
+                unit: Some(measurement.unit),
+            },
+            None => Physical {
+                value: logical as f64,
+                unit: None,
+            },
+        }
+    }
+}
+
+impl Display for Physical {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.unit {
+            Some(unit) => Measurement {
+                value: self.value,
+                unit,
+            }
+            .fmt(f),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// A stylus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pen {
+    /// 0 - 1 across the logical range
+    pub x: f32,
+    /// 0 - 1 across the logical range
+    pub y: f32,
+    /// Near enough to be tracked
+    pub in_range: bool,
+    /// The tip touching
+    pub tip: bool,
+    /// The barrel button pressed
+    pub barrel: bool,
+    /// The eraser end touching
+    pub eraser: bool,
+    /// The eraser end towards the surface
+    pub inverted: bool,
+    /// 0 - 1
+    pub pressure: Option<f32>,
+    /// Tilt towards positive X
+    pub x_tilt: Option<Physical>,
+    /// Tilt towards positive Y
+    pub y_tilt: Option<Physical>,
+    /// Rotation around the pen's axis
+    pub twist: Option<Physical>,
+}
+
+impl Pen {
+    /// Touching, hovering or out of range
+    pub fn state(&self) -> PenState {
+        match (self.tip || self.eraser, self.in_range) {
+            (true, _) => PenState::Touching,
+            (false, true) => PenState::Hovering,
+            (false, false) => PenState::OutOfRange,
+        }
+    }
+}
+
+// e.g. `touching (0.25, 0.50) 40%, tilt 10 deg -5 deg, twist 90 deg, barrel`
+impl Display for Pen {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:.2}, {:.2})", self.state(), self.x, self.y)?;
+        if let Some(pressure) = self.pressure {
+            write!(f, " {:.0}%", pressure * 100.0)?;
+        }
+        if let (Some(x), Some(y)) = (self.x_tilt, self.y_tilt) {
+            write!(f, ", tilt {} {}", x, y)?;
+        }
+        if let Some(twist) = self.twist {
+            write!(f, ", twist {}", twist)?;
+        }
+        if self.barrel {
+            write!(f, ", barrel")?;
+        }
+        if self.inverted || self.eraser {
+            write!(f, ", eraser")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Parser {
+    /// Whether the descriptor has a stylus
+    pub fn has_pen(&self) -> bool {
+        self.collections().iter().any(|c| find_stylus(c).is_some())
+    }
+
+    /// The first pen in an input report (starting with the report ID, if used), None for
+    /// reports without its position
+    pub fn pen(&self, bytes: &[u8]) -> Option<Pen> {
+        self.collections()
+            .iter()
+            .filter_map(find_stylus)
+            .find_map(|stylus| pen(stylus, bytes))
+    }
+
+    /// Whether the descriptor has finger or stylus collections
+    pub fn has_contacts(&self) -> bool {
+        self.collections().iter().any(has_contacts)
+    }
+
+    /// Contacts in an input report (starting with the report ID, if used), including the
+    /// ones which were lifted. Contact slots not filled by the report are left out.
+    pub fn contacts(&self, bytes: &[u8]) -> Vec<Contact> {
+        let mut contacts = vec![];
+
+        for collection in self.collections() {
+            collect_contacts(collection, bytes, &mut contacts);
+        }
+
+        contacts
+    }
+}
+
+fn has_contacts(collection: &Collection<Report>) -> bool {
+    matches!(collection.usage, (DIGITIZERS_PAGE, FINGER | STYLUS))
+        || collection.items.iter().any(|item| match item {
+            CollectionItem::Collection(collection) => has_contacts(collection),
+            CollectionItem::Item(_) => false,
+        })
+}
+
+fn collect_contacts(collection: &Collection<Report>, bytes: &[u8], contacts: &mut Vec<Contact>) {
+    if matches!(collection.usage, (DIGITIZERS_PAGE, FINGER | STYLUS)) {
+        contacts.extend(contact(collection, bytes));
+        return;
+    }
+
+    for item in &collection.items {
+        if let CollectionItem::Collection(collection) = item {
+            collect_contacts(collection, bytes, contacts);
+        }
+    }
+}
+
+fn contact(collection: &Collection<Report>, bytes: &[u8]) -> Option<Contact> {
+    let mut position = (None, None);
+    let mut contact = Contact {
+        id: None,
+        x: 0.0,
+        y: 0.0,
+        pressure: None,
+        touching: false,
+    };
+    let mut in_range = None;
+    let mut tip = None;
+
+    for report in collection
+        .flatten()
+        .into_iter()
+        .filter(|report| report.report_type.kind() == ReportKind::Input)
+    {
+        for input in report.parse(bytes).unwrap_or_default() {
+            let value = match input.value {
+                InputValue::Bool(b) => b as i64,
+                InputValue::UInt(u) => u as i64,
+                InputValue::Int(i) => i as i64,
+                InputValue::Selected | InputValue::None => continue,
+            };
+
+            match input.usage {
+                (GENERIC_DESKTOP, X) => position.0 = Some(scale(report, value)),
+                (GENERIC_DESKTOP, Y) => position.1 = Some(scale(report, value)),
+                (DIGITIZERS_PAGE, CONTACT_ID) => contact.id = Some(value as u32),
+                (DIGITIZERS_PAGE, TIP_PRESSURE) => contact.pressure = Some(scale(report, value)),
+                (DIGITIZERS_PAGE, TIP_SWITCH) => tip = Some(value != 0),
+                (DIGITIZERS_PAGE, IN_RANGE) => in_range = Some(value != 0),
+                _ => (),
+            }
+        }
+    }
+
+    let (x, y) = match position {
+        (Some(x), Some(y)) => (x, y),
+        _ => return None,
+    };
+    contact.x = x;
+    contact.y = y;
+    contact.touching = tip.or(in_range).unwrap_or(true);
+
+    Some(contact)
+}
+
+fn find_stylus(collection: &Collection<Report>) -> Option<&Collection<Report>> {
+    if collection.usage == (DIGITIZERS_PAGE, STYLUS) {
+        return Some(collection);
+    }
+
+    collection.items.iter().find_map(|item| match item {
+        CollectionItem::Collection(collection) => find_stylus(collection),
+        CollectionItem::Item(_) => None,
+    })
+}
+
+fn pen(collection: &Collection<Report>, bytes: &[u8]) -> Option<Pen> {
+    let mut position = (None, None);
+    let mut pen = Pen {
+        x: 0.0,
+        y: 0.0,
+        in_range: true, // without In Range the pen is only seen near the surface
+        tip: false,
+        barrel: false,
+        eraser: false,
+        inverted: false,
+        pressure: None,
+        x_tilt: None,
+        y_tilt: None,
+        twist: None,
+    };
+
+    for report in collection
+        .flatten()
+        .into_iter()
+        .filter(|report| report.report_type.kind() == ReportKind::Input)
+    {
+        for input in report.parse(bytes).unwrap_or_default() {
+            let value = match input.value {
+                InputValue::Bool(b) => b as i64,
+                InputValue::UInt(u) => u as i64,
+                InputValue::Int(i) => i as i64,
+                InputValue::Selected | InputValue::None => continue,
+            };
+
+            match input.usage {
+                (GENERIC_DESKTOP, X) => position.0 = Some(scale(report, value)),
+                (GENERIC_DESKTOP, Y) => position.1 = Some(scale(report, value)),
+                (DIGITIZERS_PAGE, TIP_PRESSURE) => pen.pressure = Some(scale(report, value)),
+                (DIGITIZERS_PAGE, IN_RANGE) => pen.in_range = value != 0,
+                (DIGITIZERS_PAGE, TIP_SWITCH) => pen.tip = value != 0,
+                (DIGITIZERS_PAGE, BARREL_SWITCH) => pen.barrel = value != 0,
+                (DIGITIZERS_PAGE, ERASER) => pen.eraser = value != 0,
+                (DIGITIZERS_PAGE, INVERT) => pen.inverted = value != 0,
+                (DIGITIZERS_PAGE, X_TILT) => {
+                    pen.x_tilt = Some(Physical::of(report, input.value, value))
+                }
+                (DIGITIZERS_PAGE, Y_TILT) => {
+                    pen.y_tilt = Some(Physical::of(report, input.value, value))
+                }
+                (DIGITIZERS_PAGE, TWIST) => {
+                    pen.twist = Some(Physical::of(report, input.value, value))
+                }
+                _ => (),
+            }
+        }
+    }
+
+    (pen.x, pen.y) = match position {
+        (Some(x), Some(y)) => (x, y),
+        _ => return None,
+    };
+
+    Some(pen)
+}
+
+fn scale(report: &Report, value: i64) -> f32 {
+    let min = report.logical_minimum as i64;
+    let max = report.logical_maximum as i64;
+    if max <= min {
+        return 0.0;
+    }
+
+    (value.clamp(min, max) - min) as f32 / (max - min) as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{CollectionType, DescriptorBuilder, InputItemData, Parser};
+    use super::PenState;
+
+    const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+
+    // Two fingers with tip switch, contact ID and 0 - 1000 coordinates, and the contact count
+    fn touchpad() -> Parser {
+        let mut builder = DescriptorBuilder::new()
+            .usage_page(0x0d)
+            .usage(0x05)
+            .collection(CollectionType::Application)
+            .report_id(1);
+
+        for _ in 0..2 {
+            builder = builder
+                .usage_page(0x0d)
+                .usage(0x22)
+                .collection(CollectionType::Logical)
+                .usage(0x42)
+                .logical_minimum(0)
+                .logical_maximum(1)
+                .report_size(1)
+                .report_count(1)
+                .input(VARIABLE)
+                .usage(0x51)
+                .logical_maximum(127)
+                .report_size(7)
+                .input(VARIABLE)
+                .usage_page(0x01)
+                .usage(0x30)
+                .usage(0x31)
+                .logical_maximum(1000)
+                .report_size(16)
+                .report_count(2)
+                .input(VARIABLE)
+                .end_collection();
+        }
+
+        builder
+            .usage_page(0x0d)
+            .usage(0x54)
+            .logical_maximum(2)
+            .report_size(8)
+            .report_count(1)
+            .input(VARIABLE)
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn reads_contacts() {
+        let parser = touchpad();
+
+        // contact 3 touching at (250, 1000), contact 4 lifted at (0, 500)
+        let contacts = parser.contacts(&[
+            0x01, 0x07, 0xfa, 0x00, 0xe8, 0x03, 0x08, 0x00, 0x00, 0xf4, 0x01, 0x02,
+        ]);
+
+        assert_eq!(contacts.len(), 2);
+        assert_eq!(contacts[0].id, Some(3));
+        assert_eq!((contacts[0].x, contacts[0].y), (0.25, 1.0));
+        assert!(contacts[0].touching);
+        assert_eq!(contacts[1].id, Some(4));
+        assert_eq!((contacts[1].x, contacts[1].y), (0.0, 0.5));
+        assert!(!contacts[1].touching);
+        assert_eq!(contacts[1].pressure, None);
+
+        assert!(parser.contacts(&[0x02, 0x00]).is_empty());
+        assert!(parser.has_contacts());
+    }
+
+    // Tip, barrel, eraser, invert and in range bits, 0 - 1000 coordinates, 0 - 255 pressure,
+    // tilts of -60 - 60 degrees and a twist of 0 - 359 degrees
+    fn pen() -> Parser {
+        DescriptorBuilder::new()
+            .usage_page(0x0d)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x20)
+            .collection(CollectionType::Physical)
+            .usage(0x42)
+            .usage(0x44)
+            .usage(0x45)
+            .usage(0x3c)
+            .usage(0x32)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(5)
+            .input(VARIABLE)
+            .report_count(3)
+            .input(InputItemData { data: 0x01 })
+            .usage_page(0x01)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_maximum(1000)
+            .report_size(16)
+            .report_count(2)
+            .input(VARIABLE)
+            .usage_page(0x0d)
+            .usage(0x30)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(1)
+            .input(VARIABLE)
+            .usage(0x3d)
+            .usage(0x3e)
+            .logical_minimum(-60)
+            .logical_maximum(60)
+            .physical_minimum(-60)
+            .physical_maximum(60)
+            .unit(0x14)
+            .report_count(2)
+            .input(VARIABLE)
+            .usage(0x41)
+            .logical_minimum(0)
+            .logical_maximum(359)
+            .physical_minimum(0)
+            .physical_maximum(359)
+            .report_size(16)
+            .report_count(1)
+            .input(VARIABLE)
+            .end_collection()
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn reads_pens() {
+        let parser = pen();
+        assert!(parser.has_pen());
+
+        // touching with the barrel switch at (250, 500), 50% pressure, tilted 10 and -5
+        // degrees, twisted 90 degrees
+        let pen = parser
+            .pen(&[0x13, 0xfa, 0x00, 0xf4, 0x01, 0x80, 0x0a, 0xfb, 0x5a, 0x00])
+            .unwrap();
+        assert_eq!(pen.state(), PenState::Touching);
+        assert_eq!(
+            pen.to_string(),
+            "touching (0.25, 0.50) 50%, tilt 10 deg -5 deg, twist 90 deg, barrel"
+        );
+
+        // the eraser end hovering, then out of range
+        let pen = parser.pen(&[0x18, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert_eq!(pen.state(), PenState::Hovering);
+        assert!(pen.inverted);
+        let pen = parser.pen(&[0x00; 10]).unwrap();
+        assert_eq!(pen.state(), PenState::OutOfRange);
+
+        assert_eq!(touchpad().pen(&[0x01; 12]), None);
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt::Display;
+
+/// Errors of talking to a device through a [`vendor::Transport`](crate::vendor::Transport)
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Writing or reading a report failed in the application's backend
+    Transport(Box<dyn std::error::Error + Send + Sync>),
+    /// The device didn't answer the request in time
+    Timeout {
+        /// The report sent
+        request: Vec<u8>,
+    },
+    /// Only other reports came while waiting for the answer to the request
+    NoAnswer {
+        /// The report sent
+        request: Vec<u8>,
+    },
+}
+
+impl Error {
+    /// An error of the application's backend
+    pub fn transport(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Transport(err.into())
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Transport(err) => write!(f, "{}", err),
+            Error::Timeout { request } => {
+                write!(f, "Timed out waiting for an answer to {:02x?}", request)
+            }
+            Error::NoAnswer { request } => write!(f, "No answer to {:02x?}", request),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
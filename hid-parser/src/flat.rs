@@ -0,0 +1,125 @@
+//! Parse results without the collection tree
+//!
+//! `Parser::parse_input` builds a tree of vectors for every report, at high report rates the
+//! allocations show up in profiles. `FlatInputs` keeps the values in one buffer and refers to
+//! the items by their index in `Parser::reports`, reusing it across reports doesn't allocate.
+
+use std::ops::Range;
+
+use crate::{Input, Parser, Report, ReportKind};
+
+/// Values of an input report, reused across reports
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlatInputs {
+    inputs: Vec<Input>,
+    items: Vec<(usize, Range<usize>)>, // index in `Parser::reports`, its values in `inputs`
+}
+
+impl FlatInputs {
+    /// An empty buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes the values, keeping the allocation
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+        self.items.clear();
+    }
+
+    /// All values, in descriptor order
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    /// Items contained in the report with their values, by index in `Parser::reports`
+    pub fn items(&self) -> impl Iterator<Item = (usize, &[Input])> + '_ {
+        self.items
+            .iter()
+            .map(|(index, range)| (*index, &self.inputs[range.clone()]))
+    }
+
+    /// Whether the report had no values
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+impl Parser {
+    /// Like `parse_input`, replacing the contents of `flat`
+    pub fn parse_input_flat(&self, input: &[u8], flat: &mut FlatInputs) {
+        self.parse_flat(ReportKind::Input, input, flat)
+    }
+
+    /// Like `parse_feature`, replacing the contents of `flat`
+    pub fn parse_feature_flat(&self, feature: &[u8], flat: &mut FlatInputs) {
+        self.parse_flat(ReportKind::Feature, feature, flat)
+    }
+
+    fn parse_flat(&self, kind: ReportKind, bytes: &[u8], flat: &mut FlatInputs) {
+        flat.clear();
+
+        let mut index = 0;
+        let mut parse = |report: &Report| {
+            let start = flat.inputs.len();
+
+            if report.report_type.kind() == kind && report.parse_into(bytes, &mut flat.inputs) {
+                flat.items.push((index, start..flat.inputs.len()));
+            }
+            index += 1;
+        };
+
+        for collection in self.collections() {
+            collection.visit(&mut parse);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BasicItems, FlatInputs, Input, Parser};
+
+    // Mouse buttons in report 1, battery strength input and feature in report 2
+    const BATTERY_MOUSE: [u8; 51] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x85, 0x01, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15,
+        0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x75, 0x05, 0x95, 0x01, 0x81, 0x01,
+        0x85, 0x02, 0x05, 0x06, 0x09, 0x20, 0x15, 0x00, 0x25, 0x64, 0x75, 0x08, 0x95, 0x01, 0xb1,
+        0x02, 0x09, 0x20, 0x81, 0x02, 0xc0,
+    ];
+
+    fn strings<'a>(inputs: impl IntoIterator<Item = &'a Input>) -> Vec<String> {
+        inputs.into_iter().map(|input| input.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_into_a_reused_buffer() {
+        let parser = Parser::new(BasicItems::new(&BATTERY_MOUSE));
+        let mut flat = FlatInputs::new();
+
+        for report in [&[0x01, 0b101][..], &[0x02, 42]] {
+            parser.parse_input_flat(report, &mut flat);
+
+            let tree = parser.parse_input(report);
+            assert_eq!(
+                strings(flat.inputs()),
+                strings(tree.flatten().into_iter().flatten())
+            );
+        }
+
+        // the battery input follows the buttons, the padding and the feature
+        let items = flat.items().map(|(index, _)| index).collect::<Vec<_>>();
+        assert_eq!(items, vec![3]);
+        assert_eq!(parser.reports()[3].report_id, Some(2));
+
+        parser.parse_feature_flat(&[0x02, 80], &mut flat);
+        let tree = parser.parse_feature(&[0x02, 80]);
+        assert_eq!(
+            strings(flat.inputs()),
+            strings(tree.flatten().into_iter().flatten())
+        );
+        assert_eq!(flat.inputs().len(), 1);
+
+        parser.parse_input_flat(&[0x03, 0x00], &mut flat);
+        assert!(flat.is_empty());
+    }
+}
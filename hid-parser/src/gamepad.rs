@@ -0,0 +1,516 @@
+//! Standard gamepad layout
+//!
+//! Gamepads describe their controls in many different ways. A `GamepadMapping` assigns them to
+//! a standard layout like SDL's game controllers: face buttons by position (South is A on Xbox
+//! pads), shoulders, sticks, triggers and the d-pad. Mappings are either given explicitly, e.g.
+//! from a mapping database, or guessed from the usages with `GamepadMapping::guess`.
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::anyhow;
+
+use super::{
+    input::InputValue,
+    report::{Report, ReportKind},
+    usage::{BUTTON, GENERIC_DESKTOP},
+    Parser,
+};
+
+/// Generic Desktop usage of joystick application collections
+pub const JOYSTICK: u16 = 0x04;
+/// Generic Desktop usage of gamepad application collections
+pub const GAMEPAD: u16 = 0x05;
+
+const X: u16 = 0x30;
+const Y: u16 = 0x31;
+const Z: u16 = 0x32;
+const RX: u16 = 0x33;
+const RY: u16 = 0x34;
+const RZ: u16 = 0x35;
+const HAT_SWITCH: u16 = 0x39;
+const START: u16 = 0x3d;
+const SELECT: u16 = 0x3e;
+const DPAD_UP: u16 = 0x90;
+
+const SIMULATION_CONTROLS: u16 = 0x02;
+const ACCELERATOR: u16 = 0xc4;
+const BRAKE: u16 = 0xc5;
+
+/// A button of the standard layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Button {
+    /// Bottom face button, A on Xbox pads
+    South,
+    /// Right face button, B on Xbox pads
+    East,
+    /// Left face button, X on Xbox pads
+    West,
+    /// Top face button, Y on Xbox pads
+    North,
+    /// Left shoulder button
+    LeftShoulder,
+    /// Right shoulder button
+    RightShoulder,
+    /// Back or select
+    Back,
+    /// Start
+    Start,
+    /// Guide or home
+    Guide,
+    /// Left stick pressed
+    LeftStick,
+    /// Right stick pressed
+    RightStick,
+    /// D-pad up
+    DpadUp,
+    /// D-pad down
+    DpadDown,
+    /// D-pad left
+    DpadLeft,
+    /// D-pad right
+    DpadRight,
+}
+
+/// An axis of the standard layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Axis {
+    /// Left stick, right is positive
+    LeftX,
+    /// Left stick, down is positive
+    LeftY,
+    /// Right stick, right is positive
+    RightX,
+    /// Right stick, down is positive
+    RightY,
+    /// Left trigger
+    LeftTrigger,
+    /// Right trigger
+    RightTrigger,
+}
+
+impl Button {
+    /// All buttons in layout order
+    pub const ALL: [Button; 15] = [
+        Button::South,
+        Button::East,
+        Button::West,
+        Button::North,
+        Button::LeftShoulder,
+        Button::RightShoulder,
+        Button::Back,
+        Button::Start,
+        Button::Guide,
+        Button::LeftStick,
+        Button::RightStick,
+        Button::DpadUp,
+        Button::DpadDown,
+        Button::DpadLeft,
+        Button::DpadRight,
+    ];
+
+    /// Name as in SDL mappings, e.g. `left_shoulder`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Button::South => "south",
+            Button::East => "east",
+            Button::West => "west",
+            Button::North => "north",
+            Button::LeftShoulder => "left_shoulder",
+            Button::RightShoulder => "right_shoulder",
+            Button::Back => "back",
+            Button::Start => "start",
+            Button::Guide => "guide",
+            Button::LeftStick => "left_stick",
+            Button::RightStick => "right_stick",
+            Button::DpadUp => "dpad_up",
+            Button::DpadDown => "dpad_down",
+            Button::DpadLeft => "dpad_left",
+            Button::DpadRight => "dpad_right",
+        }
+    }
+}
+
+impl Axis {
+    /// All axes in layout order
+    pub const ALL: [Axis; 6] = [
+        Axis::LeftX,
+        Axis::LeftY,
+        Axis::RightX,
+        Axis::RightY,
+        Axis::LeftTrigger,
+        Axis::RightTrigger,
+    ];
+
+    /// Name as in SDL mappings, e.g. `left_x`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Axis::LeftX => "left_x",
+            Axis::LeftY => "left_y",
+            Axis::RightX => "right_x",
+            Axis::RightY => "right_y",
+            Axis::LeftTrigger => "left_trigger",
+            Axis::RightTrigger => "right_trigger",
+        }
+    }
+
+    /// Triggers rest at 0, sticks in the middle of their range
+    pub fn is_trigger(&self) -> bool {
+        matches!(self, Axis::LeftTrigger | Axis::RightTrigger)
+    }
+}
+
+impl FromStr for Button {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Button::ALL
+            .into_iter()
+            .find(|button| button.name() == s)
+            .ok_or_else(|| anyhow!("Unknown gamepad button '{}'", s))
+    }
+}
+
+impl FromStr for Axis {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Axis::ALL
+            .into_iter()
+            .find(|axis| axis.name() == s)
+            .ok_or_else(|| anyhow!("Unknown gamepad axis '{}'", s))
+    }
+}
+
+impl Display for Button {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Display for Axis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Usages of the controls making up each part of the standard layout
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GamepadMapping {
+    /// Button usages
+    pub buttons: Vec<(Button, (u16, u16))>,
+    /// Axis usages
+    pub axes: Vec<(Axis, (u16, u16))>,
+    /// Hat switch driving the d-pad
+    pub hat: Option<(u16, u16)>,
+}
+
+/// The controls of the standard layout in an input report
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GamepadState {
+    /// Pressed, in layout order
+    pub buttons: Vec<Button>,
+    /// Sticks from -1 to 1, triggers from 0 to 1
+    pub axes: Vec<(Axis, f32)>,
+}
+
+impl Display for GamepadState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let buttons = self
+            .buttons
+            .iter()
+            .map(Button::name)
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(f, "[{}]", buttons)?;
+
+        for (axis, value) in &self.axes {
+            write!(f, " {}: {:.2}", axis, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl GamepadMapping {
+    /// Mapping for gamepads and joysticks following common conventions: buttons numbered like
+    /// XInput (A, B, X, Y, shoulders, Back, Start, stick buttons, Guide), X/Y as the left stick
+    /// and Rx/Ry as the right stick with Z/Rz as triggers, or Z/Rz as the right stick without
+    /// Rx/Ry. None for other devices.
+    pub fn guess(parser: &Parser) -> Option<GamepadMapping> {
+        let is_gamepad = parser
+            .collections()
+            .iter()
+            .any(|collection| matches!(collection.usage, (GENERIC_DESKTOP, JOYSTICK | GAMEPAD)));
+        if !is_gamepad {
+            return None;
+        }
+
+        let usages = parser
+            .reports()
+            .into_iter()
+            .filter(|report| report.report_type.kind() == ReportKind::Input)
+            .flat_map(all_usages)
+            .collect::<Vec<_>>();
+        let has = |usage: (u16, u16)| usages.contains(&usage);
+        let desktop = |id| (GENERIC_DESKTOP, id);
+
+        let mut mapping = GamepadMapping::default();
+
+        let numbered = [
+            Button::South,
+            Button::East,
+            Button::West,
+            Button::North,
+            Button::LeftShoulder,
+            Button::RightShoulder,
+            Button::Back,
+            Button::Start,
+            Button::LeftStick,
+            Button::RightStick,
+            Button::Guide,
+        ];
+        for (n, button) in numbered.into_iter().enumerate() {
+            let named = match button {
+                Button::Back => Some(desktop(SELECT)),
+                Button::Start => Some(desktop(START)),
+                _ => None,
+            };
+
+            match named.filter(|usage| has(*usage)) {
+                Some(usage) => mapping.buttons.push((button, usage)),
+                None if has((BUTTON, n as u16 + 1)) => {
+                    mapping.buttons.push((button, (BUTTON, n as u16 + 1)))
+                }
+                None => (),
+            }
+        }
+
+        let dpad = [
+            Button::DpadUp,
+            Button::DpadDown,
+            Button::DpadRight,
+            Button::DpadLeft,
+        ];
+        for (n, button) in dpad.into_iter().enumerate() {
+            let usage = desktop(DPAD_UP + n as u16);
+            if has(usage) {
+                mapping.buttons.push((button, usage));
+            }
+        }
+
+        let axes: &[(Axis, u16)] = match has(desktop(RX)) && has(desktop(RY)) {
+            true => &[
+                (Axis::LeftX, X),
+                (Axis::LeftY, Y),
+                (Axis::RightX, RX),
+                (Axis::RightY, RY),
+                (Axis::LeftTrigger, Z),
+                (Axis::RightTrigger, RZ),
+            ],
+            false => &[
+                (Axis::LeftX, X),
+                (Axis::LeftY, Y),
+                (Axis::RightX, Z),
+                (Axis::RightY, RZ),
+            ],
+        };
+        for &(axis, id) in axes {
+            if has(desktop(id)) {
+                mapping.axes.push((axis, desktop(id)));
+            }
+        }
+
+        for (axis, usage) in [
+            (Axis::LeftTrigger, (SIMULATION_CONTROLS, BRAKE)),
+            (Axis::RightTrigger, (SIMULATION_CONTROLS, ACCELERATOR)),
+        ] {
+            if has(usage) && !mapping.axes.iter().any(|(a, _)| *a == axis) {
+                mapping.axes.push((axis, usage));
+            }
+        }
+        mapping.axes.sort();
+
+        mapping.hat = has(desktop(HAT_SWITCH)).then_some(desktop(HAT_SWITCH));
+
+        Some(mapping)
+    }
+
+    /// State of the mapped controls in an input report (starting with the report ID, if used),
+    /// None if the report has none of them
+    pub fn state(&self, parser: &Parser, bytes: &[u8]) -> Option<GamepadState> {
+        let mut state = GamepadState::default();
+        let mut mapped = false;
+
+        for report in parser
+            .reports()
+            .into_iter()
+            .filter(|report| report.report_type.kind() == ReportKind::Input)
+        {
+            for input in report.parse(bytes).unwrap_or_default() {
+                let value = match input.value {
+                    InputValue::Bool(b) => b as i64,
+                    InputValue::UInt(u) => u as i64,
+                    InputValue::Int(i) => i as i64,
+                    InputValue::Selected => 1,
+                    InputValue::None => i64::MIN,
+                };
+
+                for (button, _) in self.buttons.iter().filter(|(_, u)| *u == input.usage) {
+                    mapped = true;
+                    if value > 0 {
+                        state.buttons.push(*button);
+                    }
+                }
+
+                for (axis, _) in self.axes.iter().filter(|(_, u)| *u == input.usage) {
+                    mapped = true;
+                    state.axes.push((*axis, normalize(report, *axis, value)));
+                }
+
+                if self.hat == Some(input.usage) {
+                    mapped = true;
+                    state.buttons.extend(hat_directions(report, value));
+                }
+            }
+        }
+
+        state.buttons.sort();
+        state.buttons.dedup();
+        state.axes.sort_by_key(|(axis, _)| *axis);
+
+        mapped.then_some(state)
+    }
+}
+
+// Usages of a report, with usage ranges expanded
+fn all_usages(report: &Report) -> Vec<(u16, u16)> {
+    let mut usages = report.usages.clone();
+
+    if let (Some(min), Some(max)) = (report.usage_minimum, report.usage_maximum) {
+        usages.extend((min.1..=max.1).map(|id| (min.0, id)));
+    }
+
+    usages
+}
+
+fn normalize(report: &Report, axis: Axis, value: i64) -> f32 {
+    let min = report.logical_minimum as i64;
+    let max = report.logical_maximum as i64;
+    if max <= min {
+        return 0.0;
+    }
+
+    let position = (value.clamp(min, max) - min) as f32 / (max - min) as f32;
+    match axis.is_trigger() {
+        true => position,
+        false => position * 2.0 - 1.0,
+    }
+}
+
+// Hat switches report 8 (or 4) directions clockwise from up, anything outside the logical
+// range is the centre
+fn hat_directions(report: &Report, value: i64) -> Vec<Button> {
+    let min = report.logical_minimum as i64;
+    let max = report.logical_maximum as i64;
+    if value < min || value > max {
+        return vec![];
+    }
+
+    let mut direction = value - min;
+    if max - min == 3 {
+        direction *= 2;
+    }
+
+    match direction {
+        0 => vec![Button::DpadUp],
+        1 => vec![Button::DpadUp, Button::DpadRight],
+        2 => vec![Button::DpadRight],
+        3 => vec![Button::DpadDown, Button::DpadRight],
+        4 => vec![Button::DpadDown],
+        5 => vec![Button::DpadDown, Button::DpadLeft],
+        6 => vec![Button::DpadLeft],
+        7 => vec![Button::DpadUp, Button::DpadLeft],
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{CollectionType, DescriptorBuilder, InputItemData};
+    use super::{Axis, Button, GamepadMapping};
+
+    #[test]
+    fn maps_a_gamepad_to_the_standard_layout() {
+        const VARIABLE: InputItemData = InputItemData { data: 0x02 };
+        const NULL_STATE: InputItemData = InputItemData { data: 0x42 };
+
+        // 12 buttons, a hat switch with a null state and four 8 bit axes
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x05)
+            .collection(CollectionType::Application)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(12)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(12)
+            .input(VARIABLE)
+            .usage_page(0x01)
+            .usage(0x39)
+            .logical_maximum(7)
+            .report_size(4)
+            .report_count(1)
+            .input(NULL_STATE)
+            .usage(0x30)
+            .usage(0x31)
+            .usage(0x32)
+            .usage(0x35)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(4)
+            .input(VARIABLE)
+            .end_collection()
+            .build()
+            .decode();
+
+        let mapping = GamepadMapping::guess(&parser).unwrap();
+        assert_eq!(mapping.buttons[0], (Button::South, (0x09, 1)));
+        assert_eq!(mapping.buttons[10], (Button::Guide, (0x09, 11)));
+        assert_eq!(
+            mapping.axes,
+            vec![
+                (Axis::LeftX, (0x01, 0x30)),
+                (Axis::LeftY, (0x01, 0x31)),
+                (Axis::RightX, (0x01, 0x32)),
+                (Axis::RightY, (0x01, 0x35)),
+            ]
+        );
+        assert_eq!(mapping.hat, Some((0x01, 0x39)));
+
+        // South and Start pressed, hat down-left, left stick pushed left
+        let state = mapping
+            .state(&parser, &[0b1000_0001, 0x50, 0x00, 0x80, 0x80, 0xff])
+            .unwrap();
+        assert_eq!(
+            state.buttons,
+            vec![
+                Button::South,
+                Button::Start,
+                Button::DpadDown,
+                Button::DpadLeft
+            ]
+        );
+        assert_eq!(state.axes[0], (Axis::LeftX, -1.0));
+        assert_eq!(state.axes[3], (Axis::RightY, 1.0));
+        assert_eq!(
+            state.to_string(),
+            "[south start dpad_down dpad_left] left_x: -1.00 left_y: 0.00 right_x: 0.00 right_y: 1.00"
+        );
+
+        // centred hat (null state)
+        let state = mapping.state(&parser, &[0, 0xf0, 0, 0, 0, 0]).unwrap();
+        assert!(state.buttons.is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+use hidapi::{HidDevice, HidResult, MAX_REPORT_DESCRIPTOR_SIZE};
+
+use crate::ReportDescriptor;
+
+impl ReportDescriptor {
+    /// The descriptor of the interface (or top level collection on Windows) hidapi opened.
+    /// Some backends rebuild it from what the OS parsed, so it may differ from the device's bytes.
+    pub fn from_hidapi(device: &HidDevice) -> HidResult<Self> {
+        let mut buf = [0u8; MAX_REPORT_DESCRIPTOR_SIZE];
+        let n = device.get_report_descriptor(&mut buf)?;
+
+        Ok(ReportDescriptor {
+            bytes: buf[0..n].to_vec(),
+        })
+    }
+}
@@ -1,26 +1,59 @@
 use std::fmt::Display;
 
-// Represents a single input item in a report
-#[derive(Debug)]
+use super::{basic::InputItemData, usage};
+
+/// Usage page and usage ID
+pub type Usage = (u16, u16);
+
+/// Represents a single input item in a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Input {
-    pub usage: (u16, u16),
+    /// Usage of the value
+    pub usage: Usage,
+    /// The value
     pub value: InputValue,
+    /// Of the main item, e.g. whether the value wraps
+    pub flags: InputItemData,
 }
 
-#[derive(Debug)]
+/// Value of an input field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
 pub enum InputValue {
+    /// Single bit variable, e.g. a button
     Bool(bool),
+    /// Unsigned value, for logical ranges starting at 0 or above
     UInt(u32),
+    /// Signed value, for logical ranges going below 0
     Int(i32),
-    None, // "Null state"
+    /// The usage is asserted by an array item
+    Selected,
+    /// "Null state"
+    None,
+}
+
+impl Input {
+    /// A value of a field, e.g. for the parse results of a report a test expects
+    pub fn new(usage: Usage, value: InputValue, flags: InputItemData) -> Self {
+        Self {
+            usage,
+            value,
+            flags,
+        }
+    }
 }
 
 impl Display for Input {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(name) = usage::usage_name(self.usage) {
+            write!(f, "{} ", name)?;
+        }
+
         match self.value {
             InputValue::Bool(b) => write!(f, "({:02x} {:02x}): {}", self.usage.0, self.usage.1, b),
             InputValue::UInt(u) => write!(f, "({:02x} {:02x}): {}", self.usage.0, self.usage.1, u),
             InputValue::Int(i) => write!(f, "({:02x} {:02x}): {}", self.usage.0, self.usage.1, i),
+            InputValue::Selected => write!(f, "({:02x} {:02x})", self.usage.0, self.usage.1),
             InputValue::None => write!(f, "None"),
         }
     }
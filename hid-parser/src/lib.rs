@@ -1,17 +1,123 @@
-// TODO hide rusb behind a feature flag
+//! Parsing of USB HID report descriptors and decoding of the reports they describe
+//!
+//! A [`ReportDescriptor`] is decoded into a [`Parser`], which splits raw reports into
+//! [`Input`]s: a usage (page and ID, see [`usage`]) with its value. Without features the crate
+//! only works on bytes and needs no USB backend. The `rusb` and `hidapi` features read
+//! descriptors from devices through those crates, `logitech` and `fido` add vendor decoders,
+//! `rayon` decodes captures on all cores.
+//!
+//! ```
+//! use hid_parser::prelude::*;
+//!
+//! // A mouse with three buttons and relative X and Y
+//! let descriptor = DescriptorBuilder::new()
+//!     .usage_page(usage::GENERIC_DESKTOP)
+//!     .usage(0x02)
+//!     .collection(CollectionType::Application)
+//!     .usage_page(usage::BUTTON)
+//!     .usage_minimum(1)
+//!     .usage_maximum(3)
+//!     .logical_minimum(0)
+//!     .logical_maximum(1)
+//!     .report_size(1)
+//!     .report_count(3)
+//!     .input(InputItemData { data: 0x02 }) // variable
+//!     .report_count(5)
+//!     .input(InputItemData { data: 0x01 }) // padding
+//!     .usage_page(usage::GENERIC_DESKTOP)
+//!     .usage(0x30)
+//!     .usage(0x31)
+//!     .logical_minimum(-127)
+//!     .logical_maximum(127)
+//!     .report_size(8)
+//!     .report_count(2)
+//!     .input(InputItemData { data: 0x06 }) // variable, relative
+//!     .end_collection()
+//!     .build();
+//!
+//! let parser = descriptor.decode();
+//! let mut inputs = FlatInputs::new();
+//! parser.parse_input_flat(&[0b001, 5, 0xfb], &mut inputs);
+//!
+//! let described: Vec<String> = inputs
+//!     .inputs()
+//!     .iter()
+//!     .filter(|input| input.usage.0 == usage::GENERIC_DESKTOP)
+//!     .map(|input| format!("{} = {:?}", usage::short_name(input.usage), input.value))
+//!     .collect();
+//! assert_eq!(described, ["X = Int(5)", "Y = Int(-5)"]);
+//! ```
+//!
+//! Descriptors read elsewhere, e.g. from sysfs, are decoded from their bytes the same way:
+//!
+//! ```
+//! use hid_parser::prelude::*;
+//!
+//! let descriptor = ReportDescriptor {
+//!     bytes: vec![0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0xc0],
+//! };
+//! assert_eq!(descriptor.decode().usage(), (usage::GENERIC_DESKTOP, 0x06)); // Keyboard
+//! ```
+//!
+//! A whole device, with all the descriptors of all its interfaces, is a [`DeviceModel`].
 
+#![warn(missing_docs)]
+
+pub mod accumulate;
 mod basic;
+pub mod battery;
+mod builder;
+pub mod capture;
 mod collection;
 mod descriptor;
+pub mod dial;
+pub mod digitizer;
+mod error;
+mod flat;
+pub mod gamepad;
+#[cfg(feature = "hidapi")]
+mod hidapi;
 mod input;
+pub mod lint;
+mod model;
+mod mutate;
+mod optimize;
 mod parser;
+mod path;
 mod report;
 #[cfg(feature = "rusb")]
 mod rusb;
+pub mod scroll;
+pub mod simulation;
+mod strictness;
+pub mod unit;
+pub mod usage;
+pub mod vendor;
 
-pub use basic::{BasicItem, BasicItems};
+pub use basic::{
+    BasicItem, BasicItems, Collection as CollectionType, FeatureItemData, GlobalItem,
+    InputItemData, LocalItem, MainItem, OutputItemData,
+};
+pub use builder::DescriptorBuilder;
 pub use collection::{Collection, CollectionItem};
-pub use descriptor::{DescriptorType, HidDescriptor, ReportDescriptor};
-pub use input::{Input, InputValue};
+pub use descriptor::{Collision, DescriptorType, HidDescriptor, ReportDescriptor};
+pub use error::Error;
+pub use flat::FlatInputs;
+pub use input::{Input, InputValue, Usage};
+pub use model::DeviceModel;
+pub use mutate::Mutation;
 pub use parser::Parser;
-pub use report::Report;
+pub use path::{FieldAddress, FieldPath, UsageName};
+pub use report::{Report, ReportKind, ReportType};
+#[cfg(feature = "rusb")]
+pub use rusb::{ControlTransfer, ReportDescriptors, TransferPolicy};
+pub use strictness::{ParseError, Problem, Strictness};
+
+/// The types needed to decode descriptors and reports
+pub mod prelude {
+    pub use crate::{
+        usage, Collection, CollectionItem, CollectionType, DescriptorBuilder, FeatureItemData,
+        FieldPath, FlatInputs, Input, InputItemData, InputValue, OutputItemData, Parser, Report,
+        ReportDescriptor, ReportKind, Strictness, Usage,
+    };
+}
@@ -0,0 +1,390 @@
+//! Common mistakes in report descriptors
+//!
+//! Descriptors with these mistakes parse, and often work on one host but not another: reports
+//! which don't add up to whole bytes, small values split across two bytes, and Report ID items
+//! no main item ever uses. Fewer usages than values is allowed by the spec, the last usage
+//! applies to the rest, but is more often a forgotten usage than intended, so it's a warning
+//! only.
+
+use std::{collections::BTreeSet, fmt::Display};
+
+use crate::{
+    usage, BasicItem, GlobalItem, InputItemData, LocalItem, MainItem, ReportDescriptor, ReportKind,
+    Usage,
+};
+
+/// A likely mistake in a report descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lint {
+    /// The fields of a report don't add up to whole bytes
+    Unaligned {
+        /// Kind of the report
+        kind: ReportKind,
+        /// Report ID, None for descriptors without them
+        report_id: Option<u8>,
+        /// Size of the report in bits, without the report ID
+        bits: usize,
+    },
+    /// A value of at most 8 bits split across two bytes of the report
+    Straddling {
+        /// Kind of the report
+        kind: ReportKind,
+        /// Report ID, None for descriptors without them
+        report_id: Option<u8>,
+        /// Usage of the value, if it has one
+        usage: Option<Usage>,
+        /// Of the first split value, after the report ID
+        bit_offset: usize,
+        /// Of the value in bits
+        size: u32,
+    },
+    /// A Report ID item without any main item using the ID
+    UnusedReportId(u8),
+    /// A variable main item with a different number of usages than values, and no usage range
+    UsageCount {
+        /// Of the main item in the descriptor
+        offset: usize,
+        /// Kind of the main item
+        kind: ReportKind,
+        /// Number of usages
+        usages: usize,
+        /// Report Count of the main item
+        count: u32,
+    },
+}
+
+impl Lint {
+    /// Allowed by the spec, but likely a mistake
+    pub fn is_warning(&self) -> bool {
+        matches!(self, Lint::UsageCount { .. })
+    }
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Lint::Unaligned {
+                kind,
+                report_id,
+                bits,
+            } => write!(
+                f,
+                "{} is {} bits, not whole bytes, pad it with {} more",
+                report_name(*kind, *report_id),
+                bits,
+                8 - bits % 8
+            ),
+            Lint::Straddling {
+                kind,
+                report_id,
+                usage,
+                bit_offset,
+                size,
+            } => {
+                let field = usage.map_or("A field".to_string(), usage::short_name);
+                write!(
+                    f,
+                    "{}: {} ({} bits at bit {}) crosses a byte boundary",
+                    report_name(*kind, *report_id),
+                    field,
+                    size,
+                    bit_offset
+                )
+            }
+            Lint::UnusedReportId(id) => {
+                write!(f, "Report ID {} is declared but no item uses it", id)
+            }
+            Lint::UsageCount {
+                offset,
+                kind,
+                usages,
+                count,
+            } => {
+                write!(
+                    f,
+                    "{} item at byte {}: {} usages for {} values, ",
+                    kind_name(*kind),
+                    offset,
+                    usages,
+                    count
+                )?;
+                match *usages < *count as usize {
+                    true => write!(f, "the last usage is reused"),
+                    false => write!(f, "the extra usages are ignored"),
+                }
+            }
+        }
+    }
+}
+
+fn kind_name(kind: ReportKind) -> &'static str {
+    match kind {
+        ReportKind::Input => "Input",
+        ReportKind::Output => "Output",
+        ReportKind::Feature => "Feature",
+    }
+}
+
+fn report_name(kind: ReportKind, report_id: Option<u8>) -> String {
+    let kind = kind_name(kind);
+
+    match report_id {
+        Some(id) => format!("{} report {}", kind, id),
+        None => format!("{} report", kind),
+    }
+}
+
+/// Findings in the order of the reports in the descriptor
+pub fn lint(descriptor: &ReportDescriptor) -> Vec<Lint> {
+    let parser = descriptor.decode();
+    let reports = parser.reports();
+    let mut lints = vec![];
+
+    // total length of each report, in the order first seen
+    let mut lengths: Vec<((ReportKind, Option<u8>), usize)> = vec![];
+    for report in &reports {
+        let key = (report.report_type.kind(), report.report_id);
+        let end = report.bit_offset.saturating_add(report.bit_length());
+        match lengths.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, length)) => *length = (*length).max(end),
+            None => lengths.push((key, end)),
+        }
+    }
+    for ((kind, report_id), bits) in lengths {
+        if bits % 8 != 0 {
+            lints.push(Lint::Unaligned {
+                kind,
+                report_id,
+                bits,
+            });
+        }
+    }
+
+    // padding may go anywhere, values wider than a byte are split anyway
+    for report in &reports {
+        let size = report.report_size as usize;
+        if report.report_type.flags().constant() || size == 0 || size > 8 {
+            continue;
+        }
+
+        // values start at the same bits of a byte again after at most 8 values
+        let split = (0..report.report_count as usize)
+            .take(8)
+            .map(|i| report.bit_offset + i * size)
+            .find(|start| start / 8 != (start + size - 1) / 8);
+        if let Some(bit_offset) = split {
+            lints.push(Lint::Straddling {
+                kind: report.report_type.kind(),
+                report_id: report.report_id,
+                usage: report.usages.first().copied().or(report.usage_minimum),
+                bit_offset,
+                size: report.report_size,
+            });
+        }
+    }
+
+    lints.extend(
+        unused_report_ids(descriptor)
+            .into_iter()
+            .map(Lint::UnusedReportId),
+    );
+    lints.extend(usage_counts(descriptor));
+
+    lints
+}
+
+// Report IDs no Input, Output or Feature item is declared with, following Push and Pop
+fn unused_report_ids(descriptor: &ReportDescriptor) -> BTreeSet<u8> {
+    let mut declared = BTreeSet::new();
+    let mut used = BTreeSet::new();
+    let mut current = None;
+    let mut stack = vec![];
+
+    for item in descriptor.basic_items() {
+        match item {
+            BasicItem::Global(GlobalItem::ReportID(id)) => {
+                declared.insert(id);
+                current = Some(id);
+            }
+            BasicItem::Global(GlobalItem::Push) => stack.push(current),
+            BasicItem::Global(GlobalItem::Pop) => current = stack.pop().unwrap_or(current),
+            BasicItem::Main(MainItem::Input(_) | MainItem::Output(_) | MainItem::Feature(_)) => {
+                used.extend(current);
+            }
+            _ => (),
+        }
+    }
+
+    &declared - &used
+}
+
+// Variable main items whose usages don't match their Report Count
+fn usage_counts(descriptor: &ReportDescriptor) -> Vec<Lint> {
+    let mut lints = vec![];
+    let mut count = 0;
+    let mut stack = vec![];
+    let (mut usages, mut range) = (0, false);
+
+    let mut items = descriptor.basic_items();
+    loop {
+        let offset = items.offset();
+        let Some(item) = items.next() else { break };
+
+        let (kind, flags) = match item {
+            BasicItem::Global(GlobalItem::ReportCount(c)) => {
+                count = c;
+                continue;
+            }
+            BasicItem::Global(GlobalItem::Push) => {
+                stack.push(count);
+                continue;
+            }
+            BasicItem::Global(GlobalItem::Pop) => {
+                count = stack.pop().unwrap_or(count);
+                continue;
+            }
+            BasicItem::Local(LocalItem::Usage(_) | LocalItem::ExtendedUsage(..)) => {
+                usages += 1;
+                continue;
+            }
+            BasicItem::Local(LocalItem::UsageMinimum(_) | LocalItem::ExtendedUsageMinimum(..)) => {
+                range = true;
+                continue;
+            }
+            BasicItem::Main(MainItem::Input(input)) => (ReportKind::Input, input),
+            BasicItem::Main(MainItem::Output(output)) => {
+                (ReportKind::Output, InputItemData { data: output.data })
+            }
+            BasicItem::Main(MainItem::Feature(feature)) => {
+                (ReportKind::Feature, InputItemData { data: feature.data })
+            }
+            // local items apply to the next main item only
+            BasicItem::Main(_) => {
+                (usages, range) = (0, false);
+                continue;
+            }
+            _ => continue,
+        };
+
+        // arrays list the possible usages, padding has none
+        let variable = flags.variable() && !flags.constant();
+        if variable && !range && usages > 0 && usages != count as usize {
+            lints.push(Lint::UsageCount {
+                offset,
+                kind,
+                usages,
+                count,
+            });
+        }
+        (usages, range) = (0, false);
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollectionType, DescriptorBuilder, FeatureItemData, InputItemData, ReportKind};
+
+    use super::{lint, Lint};
+
+    #[test]
+    fn finds_descriptor_mistakes() {
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(3)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(3)
+            .input(InputItemData { data: 0x02 })
+            .report_count(3)
+            .input(InputItemData { data: 0x01 }) // padding short by 2 bits
+            .usage_page(0x01)
+            .usage(0x38)
+            .logical_minimum(-7)
+            .logical_maximum(7)
+            .report_size(4)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .report_id(2)
+            .report_id(3)
+            .usage(0x30)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .feature(FeatureItemData { data: 0x02 })
+            .end_collection()
+            .build();
+
+        let lints: Vec<_> = lint(&descriptor).iter().map(|l| l.to_string()).collect();
+        assert_eq!(
+            lints,
+            [
+                "Input report 1 is 10 bits, not whole bytes, pad it with 6 more",
+                "Input report 1: Wheel (4 bits at bit 6) crosses a byte boundary",
+                "Report ID 2 is declared but no item uses it",
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_aligned_descriptors() {
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-2047)
+            .logical_maximum(2047)
+            .report_size(12)
+            .report_count(2)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build();
+
+        assert!(lint(&descriptor).is_empty());
+    }
+
+    #[test]
+    fn locates_reused_usages() {
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x04)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(3)
+            .input(InputItemData { data: 0x02 })
+            .usage(0x01)
+            .report_count(4)
+            .input(InputItemData { data: 0x00 }) // array
+            .end_collection()
+            .build();
+
+        let lints = lint(&descriptor);
+        assert_eq!(
+            lints,
+            [Lint::UsageCount {
+                offset: 19,
+                kind: ReportKind::Input,
+                usages: 2,
+                count: 3
+            }]
+        );
+        assert!(lints[0].is_warning());
+        assert_eq!(
+            lints[0].to_string(),
+            "Input item at byte 19: 2 usages for 3 values, the last usage is reused"
+        );
+    }
+}
@@ -0,0 +1,455 @@
+//! All the report descriptors of a device
+//!
+//! A device has one or more HID interfaces, each with one or more report descriptors (several
+//! with hidapi on platforms listing every top level collection separately, or where the HID
+//! descriptor lists more than one). The model keeps them by interface number and parses the
+//! descriptors of an interface together, the way hosts do, so reports are looked up by their
+//! interface, kind and report ID.
+//!
+//! The descriptors of an interface are parsed when they are added, not on every lookup, and the
+//! reports of all interfaces are indexed by kind and report ID: monitoring looks up the parser
+//! of every report it sees. Interfaces whose descriptors collide (see
+//! `ReportDescriptor::combine`) keep their descriptors but get no parser.
+//!
+//! Models are stored as the descriptor records of captures (see `capture`), one record per
+//! descriptor, in order.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{Read, Write},
+};
+
+use anyhow::Result;
+
+use crate::{
+    capture::{CaptureReader, CaptureWriter, Record},
+    usage, Collision, Parser, Report, ReportDescriptor, ReportKind,
+};
+
+/// Report descriptors of a device, by interface
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceModel {
+    interfaces: BTreeMap<u8, Vec<ReportDescriptor>>, // by interface number
+    parsers: BTreeMap<u8, Parser>,                   // of the descriptors combined
+    collisions: BTreeMap<u8, Collision>,             // instead of a parser
+    reports: BTreeMap<(ReportKind, Option<u8>), (u8, Vec<Report>)>, // interface and main items
+}
+
+impl DeviceModel {
+    /// A model without interfaces
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a descriptor after the ones the interface already has
+    pub fn add(&mut self, interface: u8, descriptor: ReportDescriptor) {
+        self.interfaces
+            .entry(interface)
+            .or_default()
+            .push(descriptor);
+        self.parse(interface);
+        self.index();
+    }
+
+    /// Replaces all descriptors of the interface
+    pub fn replace(&mut self, interface: u8, descriptors: Vec<ReportDescriptor>) {
+        self.interfaces.insert(interface, descriptors);
+        self.parse(interface);
+        self.index();
+    }
+
+    /// Reads the descriptor records of a capture, skipping any other records
+    pub fn read(reader: impl Read) -> Result<Self> {
+        let mut descriptors = vec![];
+        for record in CaptureReader::new(reader)? {
+            if let Record::Descriptor {
+                interface,
+                descriptor,
+            } = record?
+            {
+                descriptors.push((interface, descriptor));
+            }
+        }
+
+        Ok(descriptors.into_iter().collect())
+    }
+
+    /// Writes the model as a capture of only its descriptor records
+    pub fn write(&self, writer: impl Write) -> Result<()> {
+        let mut writer = CaptureWriter::new(writer)?;
+        for record in self.records() {
+            writer.write(&record)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Whether the model has no interfaces
+    pub fn is_empty(&self) -> bool {
+        self.interfaces.is_empty()
+    }
+
+    /// Number of interfaces
+    pub fn len(&self) -> usize {
+        self.interfaces.len()
+    }
+
+    /// Interface numbers, in order
+    pub fn interfaces(&self) -> impl Iterator<Item = u8> + '_ {
+        self.interfaces.keys().copied()
+    }
+
+    /// Whether the interface has descriptors
+    pub fn contains(&self, interface: u8) -> bool {
+        self.interfaces.contains_key(&interface)
+    }
+
+    /// Descriptors of every interface, in interface order
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &[ReportDescriptor])> + '_ {
+        self.interfaces
+            .iter()
+            .map(|(interface, descriptors)| (*interface, descriptors.as_slice()))
+    }
+
+    /// Descriptors of an interface, in order
+    pub fn descriptors(&self, interface: u8) -> Option<&[ReportDescriptor]> {
+        self.interfaces.get(&interface).map(Vec::as_slice)
+    }
+
+    /// The descriptors of an interface combined, see `ReportDescriptor::combine`. None if they
+    /// collide, see `collision`.
+    pub fn descriptor(&self, interface: u8) -> Option<ReportDescriptor> {
+        ReportDescriptor::combine(self.descriptors(interface)?)
+            .ok()
+            .flatten()
+    }
+
+    /// Why the descriptors of an interface can't be combined, if they can't
+    pub fn collision(&self, interface: u8) -> Option<Collision> {
+        self.collisions.get(&interface).copied()
+    }
+
+    /// Parser of all the descriptors of an interface
+    pub fn parser(&self, interface: u8) -> Option<&Parser> {
+        self.parsers.get(&interface)
+    }
+
+    /// Parsers of every interface with any descriptors
+    pub fn parsers(&self) -> impl Iterator<Item = (u8, &Parser)> + '_ {
+        self.parsers
+            .iter()
+            .map(|(interface, parser)| (*interface, parser))
+    }
+
+    /// The first interface with a report of the kind and ID, and the main items of the report
+    pub fn report(&self, kind: ReportKind, report_id: Option<u8>) -> Option<(u8, &[Report])> {
+        self.reports
+            .get(&(kind, report_id))
+            .map(|(interface, items)| (*interface, items.as_slice()))
+    }
+
+    /// Length of a report of an interface in bytes, including the report ID byte if it has one
+    pub fn report_length(
+        &self,
+        interface: u8,
+        kind: ReportKind,
+        report_id: Option<u8>,
+    ) -> Option<usize> {
+        self.parser(interface)?.report_length(kind, report_id)
+    }
+
+    /// First interface with a report of the kind and ID
+    pub fn find_report(&self, kind: ReportKind, report_id: Option<u8>) -> Option<u8> {
+        self.report(kind, report_id).map(|(interface, _)| interface)
+    }
+
+    /// Descriptor records of a capture, one per descriptor
+    pub fn records(&self) -> impl Iterator<Item = Record> + '_ {
+        self.interfaces.iter().flat_map(|(interface, descriptors)| {
+            descriptors.iter().map(|descriptor| Record::Descriptor {
+                interface: *interface,
+                descriptor: descriptor.clone(),
+            })
+        })
+    }
+
+    // Parses the descriptors of an interface again, after they changed
+    fn parse(&mut self, interface: u8) {
+        self.parsers.remove(&interface);
+        self.collisions.remove(&interface);
+
+        let descriptors = self.descriptors(interface).unwrap_or_default();
+        match ReportDescriptor::combine(descriptors) {
+            Ok(Some(descriptor)) => {
+                self.parsers.insert(interface, descriptor.decode());
+            }
+            Ok(None) => (),
+            Err(collision) => {
+                self.collisions.insert(interface, collision);
+            }
+        }
+    }
+
+    // Indexes the reports of all parsers, the lowest interface first where several have a report
+    fn index(&mut self) {
+        self.reports.clear();
+        for (interface, parser) in &self.parsers {
+            for report in parser.reports() {
+                let key = (report.report_type.kind(), report.report_id);
+                let (first, items) = self
+                    .reports
+                    .entry(key)
+                    .or_insert_with(|| (*interface, vec![]));
+                if first == interface {
+                    items.push(report.clone());
+                }
+            }
+        }
+    }
+}
+
+impl From<BTreeMap<u8, Vec<ReportDescriptor>>> for DeviceModel {
+    fn from(interfaces: BTreeMap<u8, Vec<ReportDescriptor>>) -> Self {
+        let mut model = Self {
+            interfaces,
+            ..Self::default()
+        };
+        let numbers: Vec<_> = model.interfaces().collect();
+        for interface in numbers {
+            model.parse(interface);
+        }
+        model.index();
+
+        model
+    }
+}
+
+impl FromIterator<(u8, ReportDescriptor)> for DeviceModel {
+    fn from_iter<T: IntoIterator<Item = (u8, ReportDescriptor)>>(iter: T) -> Self {
+        let mut interfaces: BTreeMap<u8, Vec<ReportDescriptor>> = BTreeMap::new();
+        for (interface, descriptor) in iter {
+            interfaces.entry(interface).or_default().push(descriptor);
+        }
+
+        Self::from(interfaces)
+    }
+}
+
+// One line per interface with its top level collections and reports, e.g.
+// `Interface #0: Generic Desktop / Mouse, input reports 1, 2, feature report 3`, or the
+// collision of its descriptors
+impl Display for DeviceModel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines: Vec<_> = self
+            .parsers()
+            .map(|(interface, parser)| {
+                let collections: Vec<_> = parser
+                    .collections()
+                    .iter()
+                    .map(|collection| usage::describe(collection.usage))
+                    .collect();
+                let mut line = format!("Interface #{}: {}", interface, collections.join(", "));
+
+                for (kind, name) in [
+                    (ReportKind::Input, "input"),
+                    (ReportKind::Output, "output"),
+                    (ReportKind::Feature, "feature"),
+                ] {
+                    let mut ids = vec![];
+                    for report in parser.reports() {
+                        if report.report_type.kind() == kind && !ids.contains(&report.report_id) {
+                            ids.push(report.report_id);
+                        }
+                    }
+
+                    match ids.as_slice() {
+                        [] => (),
+                        [None] => line.push_str(&format!(", {} report", name)),
+                        ids => {
+                            let ids: Vec<_> = ids
+                                .iter()
+                                .map(|id| id.map_or("-".to_string(), |id| id.to_string()))
+                                .collect();
+                            let plural = if ids.len() > 1 { "s" } else { "" };
+                            line.push_str(&format!(
+                                ", {} report{} {}",
+                                name,
+                                plural,
+                                ids.join(", ")
+                            ));
+                        }
+                    }
+                }
+
+                (interface, line)
+            })
+            .collect();
+        lines.extend(self.collisions.iter().map(|(interface, collision)| {
+            (
+                *interface,
+                format!("Interface #{}: {}", interface, collision),
+            )
+        }));
+        lines.sort_by_key(|(interface, _)| *interface);
+
+        let lines: Vec<_> = lines.into_iter().map(|(_, line)| line).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        capture::Record, CollectionType, Collision, DescriptorBuilder, FeatureItemData,
+        InputItemData, ReportKind,
+    };
+
+    use super::DeviceModel;
+
+    #[test]
+    fn looks_up_reports_of_interfaces() {
+        let keyboard = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x06)
+            .collection(CollectionType::Application)
+            .usage_page(0x07)
+            .usage_minimum(0xe0)
+            .usage_maximum(0xe7)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(8)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build();
+        let mouse = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage(0x30)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x06 })
+            .end_collection()
+            .build();
+        // a second descriptor of the mouse interface, continuing its global state
+        let settings = DescriptorBuilder::new()
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(2)
+            .usage(0x48)
+            .report_count(2)
+            .feature(FeatureItemData { data: 0x02 })
+            .end_collection()
+            .build();
+
+        let model: DeviceModel = [(0, keyboard), (1, mouse), (1, settings)]
+            .into_iter()
+            .collect();
+
+        assert_eq!(model.interfaces().collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(model.descriptors(1).unwrap().len(), 2);
+        assert_eq!(model.find_report(ReportKind::Input, None), Some(0));
+        assert_eq!(model.find_report(ReportKind::Feature, Some(2)), Some(1));
+        assert_eq!(model.find_report(ReportKind::Output, None), None);
+        assert_eq!(
+            model.report_length(1, ReportKind::Feature, Some(2)),
+            Some(3)
+        );
+        let (interface, items) = model.report(ReportKind::Input, Some(1)).unwrap();
+        assert_eq!(interface, 1);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].usages, [(0x01, 0x30)]);
+
+        assert_eq!(
+            model.to_string(),
+            "Interface #0: Generic Desktop / Keyboard, input report\n\
+             Interface #1: Generic Desktop / Mouse, Generic Desktop / Mouse, input report 1, \
+             feature report 2"
+        );
+
+        let records: Vec<_> = model.records().collect();
+        assert_eq!(records.len(), 3);
+        let Record::Descriptor { interface, .. } = &records[2] else {
+            panic!("not a descriptor record");
+        };
+        assert_eq!(*interface, 1);
+    }
+
+    #[test]
+    fn indexes_reports_again_when_descriptors_change() {
+        let descriptor = |id| {
+            DescriptorBuilder::new()
+                .usage_page(0x01)
+                .usage(0x02)
+                .collection(CollectionType::Application)
+                .report_id(id)
+                .usage(0x30)
+                .report_size(8)
+                .report_count(1)
+                .input(InputItemData { data: 0x06 })
+                .end_collection()
+                .build()
+        };
+
+        let mut model = DeviceModel::new();
+        model.add(2, descriptor(1));
+        model.add(1, descriptor(1));
+        // the lowest interface with the report
+        assert_eq!(model.find_report(ReportKind::Input, Some(1)), Some(1));
+
+        model.replace(1, vec![descriptor(3)]);
+        assert_eq!(model.find_report(ReportKind::Input, Some(1)), Some(2));
+        assert_eq!(model.find_report(ReportKind::Input, Some(3)), Some(1));
+        assert_eq!(model.parser(1).unwrap().reports()[0].report_id, Some(3));
+
+        model.replace(1, vec![]);
+        assert!(model.parser(1).is_none());
+        assert_eq!(model.find_report(ReportKind::Input, Some(3)), None);
+
+        // a second descriptor declaring report 1 again
+        model.add(2, descriptor(1));
+        assert!(model.parser(2).is_none());
+        assert_eq!(
+            model.collision(2),
+            Some(Collision::Report {
+                descriptor: 1,
+                kind: ReportKind::Input,
+                report_id: 1
+            })
+        );
+        assert_eq!(model.find_report(ReportKind::Input, Some(1)), None);
+        assert_eq!(
+            model.to_string(),
+            "Interface #2: report descriptor 2 declares Input report 1 again"
+        );
+    }
+
+    #[test]
+    fn reads_what_it_writes() {
+        let descriptor = DescriptorBuilder::new()
+            .usage_page(0x0c)
+            .usage(0x01)
+            .collection(CollectionType::Application)
+            .usage(0xe9)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build();
+        let model: DeviceModel = [(0, descriptor.clone()), (3, descriptor)]
+            .into_iter()
+            .collect();
+
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+
+        assert_eq!(&bytes[..4], b"HBCP");
+        assert_eq!(DeviceModel::read(bytes.as_slice()).unwrap(), model);
+    }
+}
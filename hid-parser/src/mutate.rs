@@ -0,0 +1,295 @@
+//! Mutating report descriptors
+//!
+//! Systematic variations of a descriptor for negative testing of host stacks and of this
+//! parser: every item dropped and duplicated, every flag of the main items flipped, the report
+//! sizes, counts, IDs and extents set to edge values, and the end of the descriptor cut off.
+//! Each mutation changes one thing, so whatever breaks on it points at that item.
+
+use crate::{
+    basic::sign_extend,
+    builder::{DescriptorBuilder, GLOBAL, LOCAL, MAIN},
+    BasicItems, ReportDescriptor,
+};
+
+// Main tags
+const INPUT: u8 = 0b1000;
+const OUTPUT: u8 = 0b1001;
+const COLLECTION: u8 = 0b1010;
+const FEATURE: u8 = 0b1011;
+
+// Global tags
+const USAGE_PAGE: u8 = 0;
+const LOGICAL_MINIMUM: u8 = 1;
+const LOGICAL_MAXIMUM: u8 = 2;
+const REPORT_SIZE: u8 = 7;
+const REPORT_ID: u8 = 8;
+const REPORT_COUNT: u8 = 9;
+
+// Local tags
+const USAGE_MINIMUM: u8 = 1;
+const USAGE_MAXIMUM: u8 = 2;
+
+// Bits of the input, output and feature item data, HID 1.11 section 6.2.2.5
+const FLAGS: [&str; 9] = [
+    "Constant",
+    "Variable",
+    "Relative",
+    "Wrap",
+    "Non Linear",
+    "No Preferred",
+    "Null State",
+    "Volatile",
+    "Buffered Bytes",
+];
+
+/// A descriptor with one item changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mutation {
+    /// Of the item changed
+    pub offset: usize,
+    /// e.g. "Report Size 8 at byte 20 set to 0"
+    pub description: String,
+    /// The changed descriptor
+    pub descriptor: ReportDescriptor,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Item {
+    item_type: u8,
+    tag: u8,
+    data: u32,
+    size: usize,
+    offset: usize,
+    length: usize, // with the prefix byte
+}
+
+impl Item {
+    fn name(&self) -> &'static str {
+        match (self.item_type, self.tag) {
+            (MAIN, INPUT) => "Input",
+            (MAIN, OUTPUT) => "Output",
+            (MAIN, COLLECTION) => "Collection",
+            (MAIN, FEATURE) => "Feature",
+            (MAIN, 0b1100) => "End Collection",
+            (GLOBAL, USAGE_PAGE) => "Usage Page",
+            (GLOBAL, LOGICAL_MINIMUM) => "Logical Minimum",
+            (GLOBAL, LOGICAL_MAXIMUM) => "Logical Maximum",
+            (GLOBAL, 3) => "Physical Minimum",
+            (GLOBAL, 4) => "Physical Maximum",
+            (GLOBAL, 5) => "Unit Exponent",
+            (GLOBAL, 6) => "Unit",
+            (GLOBAL, REPORT_SIZE) => "Report Size",
+            (GLOBAL, REPORT_ID) => "Report ID",
+            (GLOBAL, REPORT_COUNT) => "Report Count",
+            (GLOBAL, 10) => "Push",
+            (GLOBAL, 11) => "Pop",
+            (LOCAL, 0) => "Usage",
+            (LOCAL, USAGE_MINIMUM) => "Usage Minimum",
+            (LOCAL, USAGE_MAXIMUM) => "Usage Maximum",
+            (LOCAL, 9) => "Delimiter",
+            (LOCAL, _) => "Local Item",
+            _ => "Reserved Item",
+        }
+    }
+
+    fn label(&self) -> String {
+        match self.size {
+            0 => format!("{} at byte {}", self.name(), self.offset),
+            _ => format!("{} {} at byte {}", self.name(), self.value(), self.offset),
+        }
+    }
+
+    fn value(&self) -> i64 {
+        match (self.item_type, self.tag) {
+            (GLOBAL, LOGICAL_MINIMUM) => sign_extend(self.data, self.size) as i64,
+            _ => self.data as i64,
+        }
+    }
+
+    // Edge values the item could be set to instead of its own
+    fn edge_values(&self) -> Vec<i64> {
+        let data = self.data as i64;
+        let values = match (self.item_type, self.tag) {
+            (GLOBAL, USAGE_PAGE) => vec![0, 0xffff],
+            (GLOBAL, LOGICAL_MINIMUM) => vec![0, -self.value(), i32::MIN as i64],
+            (GLOBAL, LOGICAL_MAXIMUM) => vec![0, -1, i32::MAX as i64],
+            (GLOBAL, REPORT_SIZE) => vec![0, data + 1, 32, 33, 0x10000, u32::MAX as i64],
+            (GLOBAL, REPORT_ID) => vec![0, 0xff],
+            (GLOBAL, REPORT_COUNT) => vec![0, data + 1, 0xffff, 0x10000, u32::MAX as i64],
+            (LOCAL, USAGE_MINIMUM | USAGE_MAXIMUM) if self.size < 4 => vec![0, 0xffff],
+            (MAIN, COLLECTION) => vec![0x07, 0xff], // reserved and vendor defined types
+            _ => vec![],
+        };
+
+        let mut unique = vec![];
+        for value in values {
+            if value != self.value() && !unique.contains(&value) {
+                unique.push(value);
+            }
+        }
+
+        unique
+    }
+
+    fn encode(&self, data: u32) -> Vec<u8> {
+        let builder = DescriptorBuilder::new();
+        let builder = match (self.item_type, self.tag) {
+            (GLOBAL, LOGICAL_MINIMUM | LOGICAL_MAXIMUM) => {
+                builder.signed(self.item_type, self.tag, data as i32)
+            }
+            _ => builder.unsigned(self.item_type, self.tag, data),
+        };
+
+        builder.build().bytes
+    }
+}
+
+impl ReportDescriptor {
+    /// Every mutation of the descriptor, in the order of the items they change
+    pub fn mutations(&self) -> Vec<Mutation> {
+        let mut raw = BasicItems::new(&self.bytes);
+        let mut items = vec![];
+        loop {
+            let offset = raw.offset();
+            let Some((item_type, tag, data, size)) = raw.next_raw() else {
+                break;
+            };
+            items.push(Item {
+                item_type,
+                tag,
+                data,
+                size,
+                offset,
+                length: raw.offset() - offset,
+            });
+        }
+
+        let mut mutations = vec![];
+        for item in &items {
+            let mutation = |description: String, replacement: &[u8]| {
+                let mut bytes = self.bytes[..item.offset].to_vec();
+                bytes.extend(replacement);
+                bytes.extend(&self.bytes[item.offset + item.length..]);
+
+                Mutation {
+                    offset: item.offset,
+                    description: format!("{} {}", item.label(), description),
+                    descriptor: ReportDescriptor { bytes },
+                }
+            };
+            let original = &self.bytes[item.offset..item.offset + item.length];
+
+            mutations.push(mutation("dropped".to_string(), &[]));
+            mutations.push(mutation("duplicated".to_string(), &original.repeat(2)));
+
+            if item.item_type == MAIN && matches!(item.tag, INPUT | OUTPUT | FEATURE) {
+                for (bit, flag) in FLAGS.iter().enumerate() {
+                    let replacement = item.encode(item.data ^ 1 << bit);
+                    mutations.push(mutation(format!("with {} flipped", flag), &replacement));
+                }
+            }
+
+            for value in item.edge_values() {
+                let replacement = item.encode(value as u32);
+                mutations.push(mutation(format!("set to {}", value), &replacement));
+            }
+        }
+
+        if let Some(last) = items.last() {
+            let end = self.bytes.len();
+            let cut = |bytes: usize, description: &str| Mutation {
+                offset: last.offset,
+                description: format!("{} {}", last.label(), description),
+                descriptor: ReportDescriptor {
+                    bytes: self.bytes[..end - bytes].to_vec(),
+                },
+            };
+
+            mutations.push(cut(1, "cut short by a byte"));
+            if last.length > 2 {
+                mutations.push(cut(last.length - 1, "cut after the prefix"));
+            }
+
+            // the last item claims four bytes of data running past the end
+            let mut bytes = self.bytes.clone();
+            bytes[last.offset] |= 0b11;
+            mutations.push(Mutation {
+                offset: last.offset,
+                description: format!("{} claiming four bytes of data", last.label()),
+                descriptor: ReportDescriptor { bytes },
+            });
+        }
+
+        mutations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lint, ReportDescriptor, ReportKind, Strictness};
+
+    // Mouse with three buttons and a relative X axis
+    const MOUSE: [u8; 38] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15, 0x00, 0x25,
+        0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x95, 0x05, 0x81, 0x01, 0x05, 0x01, 0x09, 0x30,
+        0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x81, 0x06,
+    ];
+
+    #[test]
+    fn mutates_every_item() {
+        let mut bytes = MOUSE.to_vec();
+        bytes.push(0xc0);
+        let descriptor = ReportDescriptor { bytes };
+        let mutations = descriptor.mutations();
+
+        let described = |description: &str| {
+            mutations
+                .iter()
+                .find(|mutation| mutation.description == description)
+                .unwrap_or_else(|| panic!("no mutation '{}'", description))
+                .descriptor
+                .bytes
+                .clone()
+        };
+
+        assert_eq!(
+            described("Usage Page 1 at byte 0 dropped"),
+            descriptor.bytes[2..]
+        );
+        assert_eq!(
+            described("Report Size 1 at byte 16 set to 0")[16..18],
+            [0x75, 0x00]
+        );
+        assert_eq!(
+            described("Input 2 at byte 20 with Variable flipped")[20..22],
+            [0x81, 0x00]
+        );
+        assert_eq!(
+            described("Logical Minimum -127 at byte 30 set to -2147483648")[30..35],
+            [0x17, 0x00, 0x00, 0x00, 0x80]
+        );
+        assert_eq!(
+            described("End Collection at byte 38 cut short by a byte"),
+            MOUSE
+        );
+        assert!(mutations
+            .iter()
+            .all(|mutation| mutation.descriptor != descriptor));
+
+        // whatever the mutation, the parser decodes what it can without panicking
+        for mutation in &mutations {
+            let parser = mutation.descriptor.decode();
+            let _ = mutation.descriptor.decode_with(Strictness::Strict);
+            let _ = parser.to_string();
+            let _ = lint::lint(&mutation.descriptor);
+            for report in parser.reports() {
+                let length = parser.report_length(ReportKind::Input, report.report_id);
+                let mut bytes = vec![0xff; length.unwrap_or(8).min(64)];
+                if let Some(id) = report.report_id {
+                    bytes[0] = id;
+                }
+                parser.parse_input(&bytes);
+            }
+        }
+    }
+}
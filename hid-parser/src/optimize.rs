@@ -0,0 +1,209 @@
+//! Minimizing report descriptors
+//!
+//! Works on the items as encoded: every item is re-encoded in its smallest size, global items
+//! which are overwritten before use or don't change the state are dropped, and the local items
+//! before each main item can be sorted by tag.
+
+use std::collections::HashMap;
+
+use crate::{
+    basic::sign_extend,
+    builder::{DescriptorBuilder, GLOBAL, LOCAL},
+    BasicItems, ReportDescriptor,
+};
+
+// Global tags
+const LOGICAL_MINIMUM: u8 = 1;
+const LOGICAL_MAXIMUM: u8 = 2;
+const PHYSICAL_MINIMUM: u8 = 3;
+const PHYSICAL_MAXIMUM: u8 = 4;
+const PUSH: u8 = 10;
+const POP: u8 = 11;
+
+// Local tags
+const USAGE: u8 = 0;
+const USAGE_MINIMUM: u8 = 1;
+const USAGE_MAXIMUM: u8 = 2;
+const DELIMITER: u8 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Item {
+    item_type: u8,
+    tag: u8,
+    data: u32,
+    size: usize,
+}
+
+impl Item {
+    // Items with the same key have the same meaning
+    fn key(&self) -> (i64, usize) {
+        match self.tag {
+            // the parser reads maximums unsigned, so their size matters
+            LOGICAL_MAXIMUM | PHYSICAL_MAXIMUM => (self.data as i64, self.size),
+            LOGICAL_MINIMUM | PHYSICAL_MINIMUM => (sign_extend(self.data, self.size) as i64, 0),
+            _ => (self.data as i64, 0),
+        }
+    }
+
+    fn encode(&self, builder: DescriptorBuilder) -> DescriptorBuilder {
+        let (item_type, tag) = (self.item_type, self.tag);
+        let bytes = self.data.to_le_bytes();
+
+        match (item_type, tag) {
+            _ if self.size == 0 => builder.item(item_type, tag, &[]),
+            (GLOBAL, LOGICAL_MINIMUM | PHYSICAL_MINIMUM) => {
+                builder.signed(item_type, tag, sign_extend(self.data, self.size))
+            }
+            // shrinking a negative maximum would turn it into a large positive one
+            (GLOBAL, LOGICAL_MAXIMUM | PHYSICAL_MAXIMUM) => {
+                match sign_extend(self.data, self.size) {
+                    value if value >= 0 => builder.signed(item_type, tag, value),
+                    _ => builder.item(item_type, tag, &bytes[..self.size]),
+                }
+            }
+            // four byte usages include their usage page
+            (LOCAL, USAGE | USAGE_MINIMUM | USAGE_MAXIMUM) if self.size == 4 => {
+                builder.item(item_type, tag, &bytes)
+            }
+            _ => builder.unsigned(item_type, tag, self.data),
+        }
+    }
+}
+
+impl ReportDescriptor {
+    /// The same descriptor in as few bytes as possible
+    pub fn optimize(&self, sort_locals: bool) -> ReportDescriptor {
+        let mut items = BasicItems::new(&self.bytes);
+        let mut builder = DescriptorBuilder::new();
+
+        let mut state = HashMap::<u8, (i64, usize)>::new(); // global values by tag
+        let mut stack = vec![];
+        let mut globals: Vec<Item> = vec![]; // not yet written, the last value of each tag
+        let mut locals: Vec<Item> = vec![];
+
+        while let Some((item_type, tag, data, size)) = items.next_raw() {
+            let item = Item {
+                item_type,
+                tag,
+                data,
+                size,
+            };
+
+            if item_type != GLOBAL || matches!(tag, PUSH | POP) {
+                builder = flush_globals(builder, &mut globals, &mut state);
+            }
+            if item_type != LOCAL {
+                builder = flush_locals(builder, &mut locals, sort_locals);
+            }
+
+            match (item_type, tag) {
+                (GLOBAL, PUSH) => stack.push(state.clone()),
+                (GLOBAL, POP) => state = stack.pop().unwrap_or_default(),
+                (GLOBAL, _) => {
+                    match globals.iter_mut().find(|global| global.tag == tag) {
+                        Some(global) => *global = item,
+                        None => globals.push(item),
+                    }
+                    continue;
+                }
+                (LOCAL, _) => {
+                    locals.push(item);
+                    continue;
+                }
+                _ => (),
+            }
+
+            builder = item.encode(builder);
+        }
+
+        builder = flush_globals(builder, &mut globals, &mut state);
+        builder = flush_locals(builder, &mut locals, sort_locals);
+
+        builder.build()
+    }
+}
+
+fn flush_globals(
+    mut builder: DescriptorBuilder,
+    globals: &mut Vec<Item>,
+    state: &mut HashMap<u8, (i64, usize)>,
+) -> DescriptorBuilder {
+    for global in globals.drain(..) {
+        if state.get(&global.tag) == Some(&global.key()) {
+            continue;
+        }
+
+        state.insert(global.tag, global.key());
+        builder = global.encode(builder);
+    }
+
+    builder
+}
+
+// Usages are kept in order, mixing them with usage ranges or delimiters depends on the order too
+fn flush_locals(
+    mut builder: DescriptorBuilder,
+    locals: &mut Vec<Item>,
+    sort: bool,
+) -> DescriptorBuilder {
+    let has = |tag| locals.iter().any(|local| local.tag == tag);
+    let ranges = has(USAGE_MINIMUM) || has(USAGE_MAXIMUM);
+
+    if sort && !has(DELIMITER) && !(has(USAGE) && ranges) {
+        locals.sort_by_key(|local| local.tag);
+    }
+
+    for local in locals.drain(..) {
+        builder = local.encode(builder);
+    }
+
+    builder
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ReportDescriptor;
+
+    #[test]
+    fn shrinks_items_and_drops_redundant_globals() {
+        let descriptor = ReportDescriptor {
+            bytes: vec![
+                0x05, 0x01, // Usage Page (Generic Desktop)
+                0x09, 0x02, // Usage (Mouse)
+                0xa1, 0x01, // Collection (Application)
+                0x06, 0x09, 0x00, // Usage Page (Button), two bytes
+                0x29, 0x03, // Usage Maximum (3)
+                0x19, 0x01, // Usage Minimum (1)
+                0x15, 0x00, // Logical Minimum (0)
+                0x27, 0x01, 0x00, 0x00, 0x00, // Logical Maximum (1), four bytes
+                0x75, 0x01, // Report Size (1)
+                0x95, 0x03, // Report Count (3)
+                0x81, 0x02, // Input (Data, Variable, Absolute)
+                0x15, 0x00, // Logical Minimum (0) again
+                0x75, 0x05, // Report Size (5)
+                0x95, 0x02, // Report Count (2), overwritten below
+                0x95, 0x01, // Report Count (1)
+                0x81, 0x01, // Input (Constant)
+                0xc0, // End Collection
+            ],
+        };
+
+        let optimized = descriptor.optimize(false);
+        assert_eq!(
+            optimized.bytes,
+            vec![
+                0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x05, 0x09, 0x29, 0x03, 0x19, 0x01, 0x15, 0x00,
+                0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x75, 0x05, 0x95, 0x01, 0x81, 0x01,
+                0xc0,
+            ]
+        );
+        assert_eq!(
+            optimized.decode().to_string(),
+            descriptor.decode().to_string()
+        );
+
+        let sorted = descriptor.optimize(true);
+        assert_eq!(sorted.bytes[8..12], [0x19, 0x01, 0x29, 0x03]);
+        assert_eq!(sorted.decode().to_string(), descriptor.decode().to_string());
+    }
+}
@@ -1,126 +1,319 @@
-use std::collections::VecDeque;
+use std::collections::HashMap;
+use std::fmt::Display;
 
-use super::basic::{BasicItem, BasicItems, GlobalItem, InputItemData, LocalItem, MainItem};
+use super::basic::{
+    BasicItem, BasicItems, Collection as CollectionType, GlobalItem, LocalItem, MainItem,
+};
 use super::collection::{Collection, CollectionItem};
+use super::flat::FlatInputs;
 use super::input::Input;
-use super::report::{Report, ReportType};
+use super::report::{Report, ReportKind, ReportType};
+use super::strictness::{ParseError, Problem, Strictness};
+use super::unit::{self, Measurement};
 
-#[derive(Debug)]
+/// Parsers are plain data, parsing only reads them, so one parser can be shared by several
+/// reader threads, e.g. behind an `Arc`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Parser {
-    collection: Collection<Report>,
+    collections: Vec<Collection<Report>>, // top level, at least one
 }
 
+// Fails to compile when the parser or the parse results stop being shareable between threads
+const _: () = {
+    const fn thread_safe<T: Send + Sync>() {}
+
+    thread_safe::<Parser>();
+    thread_safe::<Collection<Vec<Input>>>();
+    thread_safe::<FlatInputs>();
+};
+
 impl Parser {
+    /// Decodes whatever it can, a permissive parser never fails
     pub fn new(basic_items: BasicItems<'_>) -> Self {
-        Parser {
-            collection: Self::read_items(basic_items),
-        }
+        Self::with_strictness(basic_items, Strictness::Permissive)
+            .expect("permissive parsing tolerates every problem")
+    }
+
+    /// Decodes the descriptor, failing on the first problem the strictness doesn't tolerate
+    pub fn with_strictness(
+        basic_items: BasicItems<'_>,
+        strictness: Strictness,
+    ) -> Result<Self, ParseError> {
+        Ok(Parser {
+            collections: Self::read_items(basic_items, strictness)?,
+        })
+    }
+
+    /// Usage of the first top level collection
+    pub fn usage(&self) -> (u16, u16) {
+        self.collections[0].usage
     }
 
+    /// Values of an input report (starting with the report ID, if used) in the collection tree
     pub fn parse_input(&self, input: &[u8]) -> Collection<Vec<Input>> {
-        self.collection.map(|report| report.parse(input))
+        self.parse(|report| match report.report_type {
+            ReportType::Input(_) => report.parse(input),
+            _ => None,
+        })
+    }
+
+    /// Like `parse_input`, with the physical values of inputs of reports declaring a unit
+    pub fn parse_input_measured(
+        &self,
+        input: &[u8],
+    ) -> Collection<Vec<(Input, Option<Measurement>)>> {
+        self.parse(|report| match report.report_type {
+            ReportType::Input(_) => report.parse(input).map(|inputs| {
+                inputs
+                    .into_iter()
+                    .map(|input| (input, Measurement::of(report, input.value)))
+                    .collect()
+            }),
+            _ => None,
+        })
+    }
+
+    /// Parse a feature report as returned by GET_REPORT(Feature), starting with the report ID
+    /// if the device uses them
+    pub fn parse_feature(&self, feature: &[u8]) -> Collection<Vec<Input>> {
+        self.parse(|report| match report.report_type {
+            ReportType::Feature(_) => report.parse(feature),
+            _ => None,
+        })
+    }
+
+    /// Parse an output report as sent by SET_REPORT(Output) or the interrupt OUT endpoint,
+    /// starting with the report ID if the device uses them
+    pub fn parse_output(&self, output: &[u8]) -> Collection<Vec<Input>> {
+        self.parse(|report| match report.report_type {
+            ReportType::Output(_) => report.parse(output),
+            _ => None,
+        })
+    }
+
+    // Report IDs are unique across the descriptor, so only the top level collection holding the
+    // report has any values. Falls back to the first collection.
+    fn parse<T, F>(&self, f: F) -> Collection<Vec<T>>
+    where
+        F: Fn(&Report) -> Option<Vec<T>> + Copy,
+    {
+        let mut parsed = self
+            .collections
+            .iter()
+            .map(|collection| collection.map(f))
+            .collect::<Vec<_>>();
+        let index = parsed
+            .iter()
+            .position(|collection| !collection.flatten().is_empty())
+            .unwrap_or(0);
+
+        parsed.swap_remove(index)
+    }
+
+    /// Top level collections
+    pub fn collections(&self) -> &[Collection<Report>] {
+        &self.collections
+    }
+
+    /// Parser for only one of the top level collections
+    pub fn select(mut self, index: usize) -> Option<Parser> {
+        (index < self.collections.len()).then(|| Parser {
+            collections: vec![self.collections.swap_remove(index)],
+        })
+    }
+
+    /// Every main item, in descriptor order
+    pub fn reports(&self) -> Vec<&Report> {
+        self.collections
+            .iter()
+            .flat_map(|collection| collection.flatten())
+            .collect()
+    }
+
+    /// Length of a report in bytes, including the report ID byte if the device uses them
+    pub fn report_length(&self, kind: ReportKind, report_id: Option<u8>) -> Option<usize> {
+        let bits = self
+            .reports()
+            .into_iter()
+            .filter(|report| report.report_type.kind() == kind && report.report_id == report_id)
+            .map(|report| report.bit_offset.saturating_add(report.bit_length()))
+            .max()?;
+
+        let id_length = if report_id.is_some() { 1 } else { 0 };
+
+        Some(id_length + bits.div_ceil(8))
     }
 
-    // FIXME error handling
-    fn read_items(basic_items: BasicItems) -> Collection<Report> {
+    fn read_items(
+        mut basic_items: BasicItems,
+        strictness: Strictness,
+    ) -> Result<Vec<Collection<Report>>, ParseError> {
         let global = GlobalItems::new();
         let local = LocalItems::new();
         let mut state_table = StateTable { global, local };
 
-        let mut collection_stack: VecDeque<Collection<Report>> = VecDeque::new(); // current collection
-        let mut bit_offset = 0u32;
+        let mut collection_stack: Vec<Collection<Report>> = vec![]; // current collection last
+        let mut collections = vec![]; // closed top level collections
+        let mut bit_offsets: BitOffsets = HashMap::new();
+
+        let mut check = Check {
+            strictness,
+            offset: 0,
+        };
+        loop {
+            check.offset = basic_items.offset();
+            let Some(item) = basic_items.next() else {
+                break;
+            };
 
-        for item in basic_items {
             match item {
                 BasicItem::Global(item) => {
-                    Self::read_global_item(&mut state_table, item);
+                    Self::read_global_item(&mut state_table, item, &check)?;
                 }
-                BasicItem::Local(item) => Self::read_local_item(&mut state_table, item),
+                BasicItem::Local(item) => Self::read_local_item(&mut state_table, item, &check)?,
                 BasicItem::Main(item) => match item {
-                    MainItem::Input(input) => Self::create_input_item(
+                    MainItem::Input(input) => Self::create_main_item(
+                        &mut state_table,
+                        &mut collection_stack,
+                        &mut bit_offsets,
+                        ReportType::Input(input),
+                        &check,
+                    )?,
+                    MainItem::Output(output) => Self::create_main_item(
+                        &mut state_table,
+                        &mut collection_stack,
+                        &mut bit_offsets,
+                        ReportType::Output(output),
+                        &check,
+                    )?,
+                    MainItem::Feature(feature) => Self::create_main_item(
                         &mut state_table,
                         &mut collection_stack,
-                        &mut bit_offset,
-                        input,
-                    ),
-                    // Output and feature items not yet implemented
-                    MainItem::Output(_) => continue,
-                    MainItem::Feature(_) => continue,
+                        &mut bit_offsets,
+                        ReportType::Feature(feature),
+                        &check,
+                    )?,
                     MainItem::Collection(c) => {
-                        if state_table.local.usages.len() != 1 {
-                            panic!("Too many usages for a collection");
+                        let usages = &state_table.local.usages;
+                        if usages.len() != 1 {
+                            check.problem(Problem::CollectionUsages(usages.len()))?;
                         }
-                        let local_usage = state_table.local.usages[0];
+                        let usage = match usages.first() {
+                            Some(usage) => {
+                                Self::qualify_usage(&state_table.global.usage_page, usage, &check)?
+                            }
+                            None => None,
+                        };
 
                         // Start a new collection
-                        let collection_type = c;
-                        let usage =
-                            Self::qualify_usage(&state_table.global.usage_page, &local_usage)
-                                .expect("Bad usage item");
-
                         let collection = Collection {
-                            collection_type,
-                            usage,
+                            collection_type: c,
+                            usage: usage.unwrap_or((0, 0)),
                             designator_index: None,
                             string_index: None,
                             items: vec![],
                         };
 
                         // Make the collection the active one, main items will be pushed into it
-                        collection_stack.push_back(collection);
+                        collection_stack.push(collection);
 
                         // Clear the local state table
                         state_table.local = LocalItems::new();
                     }
                     MainItem::EndCollection => {
-                        // close the collection and add it to its parrent collection items
-                        if collection_stack.len() == 1 {
-                            continue; // nothing to be done about the top level collection
+                        // close the collection and add it to its parent collection items
+                        match collection_stack.pop() {
+                            Some(collection) => {
+                                Self::close_collection(
+                                    collection,
+                                    &mut collection_stack,
+                                    &mut collections,
+                                );
+                            }
+                            None => check.problem(Problem::UnopenedCollection)?,
                         }
-
-                        let top = collection_stack.len() - 2;
-                        let collection = collection_stack
-                            .pop_back()
-                            .expect("can't pop collection of a stack with items");
-
-                        collection_stack[top]
-                            .items
-                            .push(CollectionItem::Collection(collection));
                     }
-                    MainItem::Reserved => continue,
+                    MainItem::Reserved => check.problem(Problem::ReservedItem)?,
                 },
-                BasicItem::Reserved => continue,
+                BasicItem::Reserved => check.problem(Problem::ReservedItem)?,
             }
         }
 
-        collection_stack.pop_front().expect("No collection found!")
+        // unterminated collections still count
+        check.offset = basic_items.offset();
+        if !collection_stack.is_empty() {
+            check.problem(Problem::UnterminatedCollection)?;
+        }
+        while let Some(collection) = collection_stack.pop() {
+            Self::close_collection(collection, &mut collection_stack, &mut collections);
+        }
+        if collections.is_empty() {
+            check.problem(Problem::NoCollection)?;
+            collections.push(Self::implicit_collection());
+        }
+
+        Ok(collections)
+    }
+
+    fn close_collection(
+        collection: Collection<Report>,
+        collection_stack: &mut [Collection<Report>],
+        collections: &mut Vec<Collection<Report>>,
+    ) {
+        match collection_stack.last_mut() {
+            Some(parent) => parent.items.push(CollectionItem::Collection(collection)),
+            None => collections.push(collection),
+        }
+    }
+
+    // Holds items outside of any collection when parsing permissively
+    fn implicit_collection() -> Collection<Report> {
+        Collection {
+            collection_type: CollectionType::Application,
+            usage: (0, 0),
+            designator_index: None,
+            string_index: None,
+            items: vec![],
+        }
     }
 
-    // FIXME error handling
-    fn read_global_item(state_table: &mut StateTable, item: GlobalItem) {
+    fn read_global_item(
+        state_table: &mut StateTable,
+        item: GlobalItem,
+        check: &Check,
+    ) -> Result<(), ParseError> {
         match item {
             GlobalItem::UsagePage(up) => state_table.global.usage_page = Some(up),
             GlobalItem::LogicalMinimum(lm) => state_table.global.logical_minimum = Some(lm),
             GlobalItem::LogicalMaximum(lm) => state_table.global.logical_maximum = Some(lm),
             GlobalItem::PhysicalMinimum(pm) => state_table.global.physical_minimum = Some(pm),
             GlobalItem::PhysicalMaximum(pm) => state_table.global.physical_maximum = Some(pm),
-            GlobalItem::UnitExponent(ue) => state_table.global.unit_exponent = Some(ue),
+            GlobalItem::UnitExponent(ue) => {
+                state_table.global.unit_exponent = Some(unit::exponent(ue))
+            }
             GlobalItem::Unit(u) => state_table.global.unit = Some(u),
             GlobalItem::ReportSize(rs) => state_table.global.report_size = Some(rs),
-            GlobalItem::ReportID(rid) => state_table.global.report_id = Some(rid),
-            GlobalItem::ReportCount(rc) => state_table.global.report_count = Some(rc),
-            GlobalItem::Push => {
-                todo!("Item state table stack is not yet implemented")
-            }
-            GlobalItem::Pop => {
-                todo!("Item state table stack is not yet implemented")
+            GlobalItem::ReportID(rid) => {
+                if rid == 0 {
+                    check.problem(Problem::ReportIdZero)?;
+                }
+                state_table.global.report_id = Some(rid)
             }
-            GlobalItem::Reserved => (),
+            GlobalItem::ReportCount(rc) => state_table.global.report_count = Some(rc),
+            // TODO item state table stack
+            GlobalItem::Push => check.problem(Problem::UnsupportedItem("Push"))?,
+            GlobalItem::Pop => check.problem(Problem::UnsupportedItem("Pop"))?,
+            GlobalItem::Reserved => check.problem(Problem::ReservedItem)?,
         }
+
+        Ok(())
     }
 
-    // FIXME error handling
-    fn read_local_item(state_table: &mut StateTable, item: LocalItem) {
+    fn read_local_item(
+        state_table: &mut StateTable,
+        item: LocalItem,
+        check: &Check,
+    ) -> Result<(), ParseError> {
         match item {
             LocalItem::Usage(usage) => state_table.local.usages.push((None, Some(usage))),
             LocalItem::UsageMinimum(um) => state_table.local.usage_minimum = (None, Some(um)),
@@ -134,7 +327,8 @@ impl Parser {
             LocalItem::ExtendedUsageMaximum(up, um) => {
                 state_table.local.usage_maximum = (Some(up), Some(um))
             }
-            LocalItem::Delimiter(_) => todo!("Delimiters are not yet implemented"),
+            // TODO delimiters, the usages within them are read as if they weren't there
+            LocalItem::Delimiter(_) => check.problem(Problem::UnsupportedItem("Delimiter"))?,
             // Strings and designators not yet implemented
             LocalItem::DesignatorIndex(di) => state_table.local.designator_index = Some(di),
             LocalItem::DesignatorMinimum(dm) => state_table.local.designator_minimum = Some(dm),
@@ -142,48 +336,39 @@ impl Parser {
             LocalItem::StringIndex(si) => state_table.local.string_index = Some(si),
             LocalItem::StringMinimum(sm) => state_table.local.string_minimum = Some(sm),
             LocalItem::StringMaximum(sm) => state_table.local.string_maximum = Some(sm),
-            LocalItem::Reserved => (),
+            LocalItem::Reserved => check.problem(Problem::ReservedItem)?,
         }
+
+        Ok(())
     }
 
-    // FIXME error handling!
-    fn create_input_item(
+    fn create_main_item(
         state_table: &mut StateTable,
-        collection_stack: &mut VecDeque<Collection<Report>>,
-        bit_offset: &mut u32,
-        input: InputItemData,
-    ) {
-        let report_type = ReportType::Input(InputItemData { data: input.data });
+        collection_stack: &mut Vec<Collection<Report>>,
+        bit_offsets: &mut BitOffsets,
+        report_type: ReportType,
+        check: &Check,
+    ) -> Result<(), ParseError> {
         let usage_page = state_table.global.usage_page;
 
-        let usages = state_table
-            .local
-            .usages
-            .iter()
-            .map(|usage| {
-                Self::qualify_usage(&usage_page, usage).expect("Missing usage page for input item")
-            })
-            .collect();
-        let usage_maximum = Self::qualify_usage(&usage_page, &state_table.local.usage_maximum);
-        let usage_minimum = Self::qualify_usage(&usage_page, &state_table.local.usage_minimum);
-
-        let report_size = state_table
-            .global
-            .report_size
-            .expect("Missing report size for input item");
-        let report_count = state_table
-            .global
-            .report_count
-            .expect("Missing report size for input item");
-
-        let logical_minimum = state_table
-            .global
-            .logical_minimum
-            .expect("Missing logical minimum for input item");
-        let logical_maximum = state_table
-            .global
-            .logical_maximum
-            .expect("Missing logical minimum for input item");
+        let mut usages = vec![];
+        for usage in &state_table.local.usages {
+            usages.extend(Self::qualify_usage(&usage_page, usage, check)?);
+        }
+        let usage_maximum =
+            Self::qualify_usage(&usage_page, &state_table.local.usage_maximum, check)?;
+        let usage_minimum =
+            Self::qualify_usage(&usage_page, &state_table.local.usage_minimum, check)?;
+
+        let global = &state_table.global;
+        let report_size = check.required(global.report_size, "report size")?;
+        let report_count = check.required(global.report_count, "report count")?;
+        let mut logical_minimum = check.required(global.logical_minimum, "logical minimum")?;
+        let mut logical_maximum = check.required(global.logical_maximum, "logical maximum")?;
+        if logical_minimum > logical_maximum {
+            check.problem(Problem::LogicalExtents(logical_minimum, logical_maximum))?;
+            (logical_minimum, logical_maximum) = (logical_maximum, logical_minimum);
+        }
 
         let physical_minimum = state_table
             .global
@@ -194,6 +379,17 @@ impl Parser {
             .physical_maximum
             .unwrap_or(logical_maximum);
 
+        // Each report (type and ID) is laid out independently
+        let bit_offset = bit_offsets
+            .entry((report_type.kind(), state_table.global.report_id))
+            .or_insert(0);
+        let end = report_count
+            .checked_mul(report_size)
+            .and_then(|bits| bit_offset.checked_add(bits));
+        if end.is_none() {
+            check.problem(Problem::ReportTooLong)?;
+        }
+
         let report = Report {
             report_type,
             usages,
@@ -211,30 +407,80 @@ impl Parser {
             unit_exponent: state_table.global.unit_exponent,
         };
 
+        if collection_stack.is_empty() {
+            check.problem(Problem::OutsideCollection)?;
+            collection_stack.push(Self::implicit_collection());
+        }
         let top = collection_stack.len() - 1;
         collection_stack[top]
             .items
             .push(CollectionItem::Item(report));
 
-        *bit_offset += report_count * report_size;
+        *bit_offset = end.unwrap_or(u32::MAX);
         state_table.local = LocalItems::new();
+
+        Ok(())
     }
 
-    // FIXME error handling
+    // Usage with its page, usage page 0 when missing
     fn qualify_usage(
         usage_page: &Option<u16>,
         usage: &(Option<u16>, Option<u16>),
-    ) -> Option<(u16, u16)> {
+        check: &Check,
+    ) -> Result<Option<(u16, u16)>, ParseError> {
         match (usage_page, usage) {
-            (_, (None, None)) => None,
-            (_, (Some(up), Some(us))) => Some((*up, *us)),
-            (Some(up), (None, Some(us))) => Some((*up, *us)),
-            (None, (None, Some(_))) => panic!("Missing usage page"),
-            _ => panic!("Missing usage"),
+            (Some(up), (None, Some(us))) | (_, (Some(up), Some(us))) => Ok(Some((*up, *us))),
+            (None, (None, Some(us))) => {
+                check.problem(Problem::MissingUsagePage)?;
+                Ok(Some((0, *us)))
+            }
+            _ => Ok(None),
         }
     }
 }
 
+// Where decoding is and how much it tolerates
+struct Check {
+    strictness: Strictness,
+    offset: usize, // of the item being read
+}
+
+impl Check {
+    // Fails unless the strictness tolerates the problem
+    fn problem(&self, problem: Problem) -> Result<(), ParseError> {
+        match self.strictness.tolerates(&problem) {
+            true => Ok(()),
+            false => Err(ParseError {
+                offset: self.offset,
+                problem,
+            }),
+        }
+    }
+
+    // Value of a global item main items need, zero when missing like in a fresh state table
+    fn required<T: Default>(&self, value: Option<T>, name: &'static str) -> Result<T, ParseError> {
+        match value {
+            Some(value) => Ok(value),
+            None => self
+                .problem(Problem::MissingItem(name))
+                .map(|_| T::default()),
+        }
+    }
+}
+
+// Next free bit in each report, by report type and report ID
+type BitOffsets = HashMap<(ReportKind, Option<u8>), u32>;
+
+impl Display for Parser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for collection in &self.collections {
+            write!(f, "{}", collection)?;
+        }
+
+        Ok(())
+    }
+}
+
 struct StateTable {
     global: GlobalItems,
     local: LocalItems,
@@ -246,7 +492,7 @@ struct GlobalItems {
     logical_maximum: Option<i32>,
     physical_minimum: Option<i32>,
     physical_maximum: Option<i32>,
-    unit_exponent: Option<u32>,
+    unit_exponent: Option<i8>,
     unit: Option<u32>,
     report_size: Option<u32>,
     report_id: Option<u8>,
@@ -301,9 +547,14 @@ impl LocalItems {
 
 #[cfg(test)]
 mod test {
-    use insta::assert_debug_snapshot;
+    use std::{collections::HashSet, sync::Arc, thread};
 
-    use super::super::BasicItems;
+    use insta::{assert_debug_snapshot, assert_snapshot};
+
+    use super::super::{
+        unit::Measurement, BasicItems, Input, InputItemData, InputValue, ParseError, Problem,
+        ReportKind, Strictness,
+    };
     use super::Parser;
 
     const JOYSTICK: [u8; 101] = [
@@ -335,4 +586,353 @@ mod test {
         println!("{:#?}", input);
         assert_debug_snapshot!(input);
     }
+
+    // Mouse buttons in report 1, battery strength input and feature in report 2
+    const BATTERY_MOUSE: [u8; 51] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x85, 0x01, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15,
+        0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x75, 0x05, 0x95, 0x01, 0x81, 0x01,
+        0x85, 0x02, 0x05, 0x06, 0x09, 0x20, 0x15, 0x00, 0x25, 0x64, 0x75, 0x08, 0x95, 0x01, 0xb1,
+        0x02, 0x09, 0x20, 0x81, 0x02, 0xc0,
+    ];
+
+    fn values(parsed: &super::Collection<Vec<super::Input>>) -> Vec<((u16, u16), String)> {
+        parsed
+            .flatten()
+            .into_iter()
+            .flatten()
+            .map(|input| (input.usage, format!("{:?}", input.value)))
+            .collect()
+    }
+
+    #[test]
+    fn parses_reports_by_report_id() {
+        let parser = Parser::new(BasicItems::new(&BATTERY_MOUSE));
+
+        let buttons = parser.parse_input(&[0x01, 0b101]);
+        assert_eq!(
+            values(&buttons),
+            vec![
+                ((0x09, 1), format!("{:?}", InputValue::Bool(true))),
+                ((0x09, 2), format!("{:?}", InputValue::Bool(false))),
+                ((0x09, 3), format!("{:?}", InputValue::Bool(true))),
+            ]
+        );
+
+        // the battery strength input starts right after the report ID
+        let battery = parser.parse_input(&[0x02, 42]);
+        assert_eq!(
+            values(&battery),
+            vec![((0x06, 0x20), format!("{:?}", InputValue::UInt(42)))]
+        );
+
+        // too short reports are ignored rather than read out of bounds
+        assert!(values(&parser.parse_input(&[0x01])).is_empty());
+    }
+
+    // Mouse buttons in report 1 and a consumer control array in report 2, each in its own
+    // application collection
+    const MOUSE_AND_CONSUMER: [u8; 56] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x85, 0x01, 0x05, 0x09, 0x19, 0x01, 0x29, 0x03, 0x15,
+        0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x75, 0x05, 0x95, 0x01, 0x81, 0x01,
+        0xc0, 0x05, 0x0c, 0x09, 0x01, 0xa1, 0x01, 0x85, 0x02, 0x19, 0x00, 0x2a, 0xff, 0x03, 0x15,
+        0x00, 0x26, 0xff, 0x03, 0x75, 0x10, 0x95, 0x01, 0x81, 0x00, 0xc0,
+    ];
+
+    #[test]
+    fn parses_several_top_level_collections() {
+        let parser = Parser::new(BasicItems::new(&MOUSE_AND_CONSUMER));
+
+        let usages = parser
+            .collections()
+            .iter()
+            .map(|c| c.usage)
+            .collect::<Vec<_>>();
+        assert_eq!(usages, vec![(0x01, 0x02), (0x0c, 0x01)]);
+        assert_eq!(parser.usage(), (0x01, 0x02));
+        assert_eq!(parser.reports().len(), 3);
+
+        let consumer = parser.parse_input(&[0x02, 0xe9, 0x00]);
+        assert_eq!(consumer.usage, (0x0c, 0x01));
+        assert_eq!(
+            values(&consumer),
+            vec![((0x0c, 0xe9), format!("{:?}", InputValue::Selected))]
+        );
+
+        let buttons = parser.parse_input(&[0x01, 0b001]);
+        assert_eq!(buttons.usage, (0x01, 0x02));
+        assert_eq!(values(&buttons).len(), 3);
+    }
+
+    #[test]
+    fn scales_by_negative_unit_exponents() {
+        // the joystick's hat in tenths of a degree: physical maximum 3150, Unit Exponent 0x0f
+        let hat = [0x46, 0x3b, 0x1];
+        let start = JOYSTICK.windows(3).position(|w| w == hat).unwrap();
+        let mut descriptor = JOYSTICK[..start].to_vec();
+        descriptor.extend([0x46, 0x4e, 0x0c, 0x55, 0x0f]);
+        descriptor.extend(&JOYSTICK[start + 3..]);
+
+        let parser = Parser::new(BasicItems::new(&descriptor));
+        let reports = parser.reports();
+        let report = reports
+            .iter()
+            .find(|report| report.usages.contains(&(0x01, 0x39)))
+            .unwrap();
+        assert_eq!(report.unit_exponent, Some(-1));
+
+        let north_west = Measurement::of(report, InputValue::UInt(8)).unwrap();
+        assert_eq!(north_west.to_string(), "315 deg");
+        let east = Measurement::of(report, InputValue::UInt(3)).unwrap();
+        assert_eq!(east.to_string(), "90 deg");
+    }
+
+    #[test]
+    fn measures_report_length() {
+        let parser = Parser::new(BasicItems::new(&BATTERY_MOUSE));
+
+        assert_eq!(parser.report_length(ReportKind::Input, Some(1)), Some(2));
+        assert_eq!(parser.report_length(ReportKind::Feature, Some(2)), Some(2));
+        assert_eq!(parser.report_length(ReportKind::Output, Some(2)), None);
+
+        let parser = Parser::new(BasicItems::new(&JOYSTICK));
+        assert_eq!(parser.report_length(ReportKind::Input, None), Some(8));
+    }
+
+    #[test]
+    fn parses_input_on_several_threads() {
+        let parser = Arc::new(Parser::new(BasicItems::new(&JOYSTICK)));
+        let input_report = [0x55u8; 8];
+        let expected = parser.parse_input(&input_report).to_string();
+
+        let threads = (0..4)
+            .map(|_| {
+                let parser = Arc::clone(&parser);
+                thread::spawn(move || parser.parse_input(&input_report).to_string())
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn parses_feature_reports() {
+        let parser = Parser::new(BasicItems::new(&BATTERY_MOUSE));
+
+        let feature = parser.parse_feature(&[0x02, 80]);
+        assert_eq!(
+            values(&feature),
+            vec![((0x06, 0x20), format!("{:?}", InputValue::UInt(80)))]
+        );
+
+        assert!(values(&parser.parse_feature(&[0x01, 0xff])).is_empty());
+    }
+
+    #[test]
+    fn parses_output_reports() {
+        let parser = Parser::new(BasicItems::new(&HEADSET));
+
+        // off-hook and mute LEDs on, ring off
+        let output = parser.parse_output(&[0x02, 0b011]);
+        assert_eq!(
+            values(&output),
+            vec![
+                ((0x08, 0x17), format!("{:?}", InputValue::Bool(true))),
+                ((0x08, 0x09), format!("{:?}", InputValue::Bool(true))),
+                ((0x08, 0x18), format!("{:?}", InputValue::Bool(false))),
+            ]
+        );
+
+        // the input report with the same ID isn't an output
+        assert!(values(&parser.parse_input(&[0x02, 0b011]))
+            .iter()
+            .all(|(usage, _)| usage.0 == 0x0b));
+    }
+
+    #[test]
+    fn decodes_by_strictness() {
+        let decode =
+            |bytes: &[u8], strictness| Parser::with_strictness(BasicItems::new(bytes), strictness);
+        let error = |offset, problem| Err(ParseError { offset, problem });
+
+        // a reserved global item (tag 12) in a keyboard collection
+        let reserved = [0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0xc4, 0xc0];
+        assert_eq!(
+            decode(&reserved, Strictness::Strict),
+            error(6, Problem::ReservedItem)
+        );
+        assert!(decode(&reserved, Strictness::Spec).is_ok());
+
+        // eight buttons without a report size, logical extents the wrong way round
+        let broken = [
+            0x05, 0x09, 0x19, 0x01, 0x29, 0x08, 0x15, 0x01, 0x25, 0x00, 0x95, 0x08, 0x81, 0x02,
+        ];
+        assert_eq!(
+            decode(&broken, Strictness::Spec),
+            error(12, Problem::MissingItem("report size"))
+        );
+        let parser = decode(&broken, Strictness::Permissive).unwrap();
+        assert_eq!(parser, Parser::new(BasicItems::new(&broken)));
+        assert_eq!(parser.usage(), (0, 0)); // the buttons are outside of any collection
+        let report = parser.reports()[0];
+        assert_eq!((report.report_size, report.report_count), (0, 8));
+        assert_eq!((report.logical_minimum, report.logical_maximum), (0, 1));
+
+        // nothing at all
+        assert_eq!(
+            decode(&[], Strictness::Strict),
+            error(0, Problem::NoCollection)
+        );
+        assert_eq!(Parser::new(BasicItems::new(&[])).reports().len(), 0);
+    }
+
+    #[test]
+    fn decodes_reports_too_long_to_lay_out() {
+        // Report Size 0x10000 and Report Count 0x10000, twice: 2^33 bits of X
+        let huge = [
+            0x05, 0x01, 0x09, 0x04, 0xa1, 0x01, 0x09, 0x30, 0x15, 0x00, 0x25, 0x01, 0x77, 0x00,
+            0x00, 0x01, 0x00, 0x97, 0x00, 0x00, 0x01, 0x00, 0x81, 0x02, 0x81, 0x02, 0xc0,
+        ];
+        let parser = Parser::new(BasicItems::new(&huge));
+        assert_eq!(
+            Parser::with_strictness(BasicItems::new(&huge), Strictness::Spec),
+            Err(ParseError {
+                offset: 22,
+                problem: Problem::ReportTooLong
+            })
+        );
+
+        assert!(values(&parser.parse_input(&[0xff; 64])).is_empty());
+        assert!(parser.report_length(ReportKind::Input, None).is_some());
+        assert_eq!(parser.reports()[0].bit_length(), 1 << 32);
+    }
+
+    #[test]
+    fn compares_parsed_reports() {
+        let parser = Parser::new(BasicItems::new(&BATTERY_MOUSE));
+        let pressed = parser.parse_input(&[0x01, 0b001]);
+
+        assert_eq!(parser.parse_input(&[0x01, 0b001]), pressed);
+        assert_ne!(parser.parse_input(&[0x01, 0b011]), pressed);
+        assert_eq!(
+            pressed.flatten()[0][0],
+            Input {
+                usage: (0x09, 0x01),
+                value: InputValue::Bool(true),
+                flags: InputItemData { data: 0x02 },
+            }
+        );
+
+        let copy = parser.clone();
+        assert_eq!(copy, parser);
+
+        let layouts = HashSet::from([parser.reports()[0].clone(), copy.reports()[0].clone()]);
+        assert_eq!(layouts.len(), 1);
+    }
+
+    // Consumer control array, two slots of 16 bit usage IDs
+    const CONSUMER_CONTROL: [u8; 23] = [
+        0x05, 0x0c, 0x09, 0x01, 0xa1, 0x01, 0x19, 0x00, 0x2a, 0xff, 0x03, 0x15, 0x00, 0x26, 0xff,
+        0x03, 0x75, 0x10, 0x95, 0x02, 0x81, 0x00, 0xc0,
+    ];
+
+    #[test]
+    fn parses_consumer_control_array() {
+        let parser = Parser::new(BasicItems::new(&CONSUMER_CONTROL));
+
+        let pressed = parser.parse_input(&[0xe9, 0x00, 0xcd, 0x00]);
+        assert_eq!(
+            values(&pressed),
+            vec![
+                ((0x0c, 0xe9), format!("{:?}", InputValue::Selected)),
+                ((0x0c, 0xcd), format!("{:?}", InputValue::Selected)),
+            ]
+        );
+        assert_eq!(
+            format!("{}", pressed.flatten()[0][0]),
+            "Volume Increment (0c e9)"
+        );
+
+        // empty slots report the unassigned usage 0
+        assert!(values(&parser.parse_input(&[0x00, 0x00, 0x00, 0x00])).is_empty());
+    }
+
+    #[test]
+    fn displays_named_layout() {
+        let parser = Parser::new(BasicItems::new(&CONSUMER_CONTROL));
+
+        assert_eq!(
+            format!("{}", parser),
+            "Application collection: Consumer / Consumer Control\n  \
+             Input @0 16x2: Unassigned ..= Consumer / 0x3ff array 0..1023 [Data,Array,Absolute,No Wrap,Linear,Preferred State,No Null position,Bit Field]\n"
+        );
+    }
+
+    // Keyboard system control keys (power down, sleep, wake up) in report 3
+    const SYSTEM_CONTROL: [u8; 27] = [
+        0x05, 0x01, 0x09, 0x80, 0xa1, 0x01, 0x85, 0x03, 0x19, 0x81, 0x29, 0x83, 0x15, 0x00, 0x25,
+        0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02, 0x95, 0x05, 0x81, 0x01, 0xc0,
+    ];
+
+    // Headset hook switch, mute, flash and redial buttons with off-hook, mute and ring LEDs
+    const HEADSET: [u8; 51] = [
+        0x05, 0x0b, 0x09, 0x05, 0xa1, 0x01, 0x85, 0x02, 0x09, 0x20, 0x15, 0x00, 0x25, 0x01, 0x75,
+        0x01, 0x95, 0x01, 0x81, 0x22, 0x09, 0x2f, 0x09, 0x21, 0x09, 0x24, 0x95, 0x03, 0x81, 0x06,
+        0x95, 0x04, 0x81, 0x01, 0x05, 0x08, 0x09, 0x17, 0x09, 0x09, 0x09, 0x18, 0x95, 0x03, 0x91,
+        0x22, 0x95, 0x05, 0x91, 0x01, 0xc0,
+    ];
+
+    // Wheel and pedals in report 1: steering, accelerator, brake, clutch, rudder and throttle
+    const SIM_RIG: [u8; 53] = [
+        0x05, 0x01, 0x09, 0x04, 0xa1, 0x01, 0x85, 0x01, 0x05, 0x02, 0x09, 0xc8, 0x16, 0x01, 0x80,
+        0x26, 0xff, 0x7f, 0x75, 0x10, 0x95, 0x01, 0x81, 0x02, 0x09, 0xc4, 0x09, 0xc5, 0x09, 0xc6,
+        0x09, 0xba, 0x15, 0x00, 0x26, 0xff, 0x03, 0x95, 0x04, 0x81, 0x02, 0x09, 0xbb, 0x26, 0xff,
+        0x00, 0x75, 0x08, 0x95, 0x01, 0x81, 0x02, 0xc0,
+    ];
+
+    #[test]
+    fn names_system_control_layout() {
+        let parser = Parser::new(BasicItems::new(&SYSTEM_CONTROL));
+
+        assert_snapshot!(format!("{}", parser));
+    }
+
+    #[test]
+    fn names_system_control_input() {
+        let parser = Parser::new(BasicItems::new(&SYSTEM_CONTROL));
+
+        assert_snapshot!(format!("{}", parser.parse_input(&[0x03, 0b010])));
+    }
+
+    #[test]
+    fn names_telephony_layout() {
+        let parser = Parser::new(BasicItems::new(&HEADSET));
+
+        assert_snapshot!(format!("{}", parser));
+    }
+
+    #[test]
+    fn names_telephony_input() {
+        let parser = Parser::new(BasicItems::new(&HEADSET));
+
+        assert_snapshot!(format!("{}", parser.parse_input(&[0x02, 0b0101])));
+    }
+
+    #[test]
+    fn names_simulation_layout() {
+        let parser = Parser::new(BasicItems::new(&SIM_RIG));
+
+        assert_snapshot!(format!("{}", parser));
+    }
+
+    #[test]
+    fn names_simulation_input() {
+        let parser = Parser::new(BasicItems::new(&SIM_RIG));
+
+        assert_snapshot!(format!(
+            "{}",
+            parser.parse_input(&[0x01, 0x00, 0xc0, 0xff, 0x03, 0x00, 0x02, 0, 0, 0x00, 0x02, 0x80])
+        ));
+    }
 }
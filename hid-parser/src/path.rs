@@ -0,0 +1,493 @@
+//! Field paths: one textual way to name a field of a report descriptor
+//!
+//! ```text
+//! X                       the only field with the usage X
+//! Joystick/Pointer/X      X in a Pointer collection inside a Joystick collection
+//! report#3/Button 5       Button 5 in report 3
+//! ff00:0001[2]            the third field with the vendor usage 0xff00 / 0x0001
+//! Generic Desktop/X       X, qualified by its usage page
+//! ```
+//!
+//! The grammar, segments separated by slashes:
+//!
+//! ```text
+//! path   = [ "report#" id "/" ] { usage "/" } usage [ "[" n "]" ]
+//! usage  = page ":" id          page and usage ID in hex, e.g. 0001:0030
+//!        | page-name "/" usage  a usage qualified by its page, the ID in decimal, hex with
+//!                               0x or as the usage name on the page, e.g. Button/1
+//!        | name                 a usage name, e.g. X or Button 5, ignoring case
+//! ```
+//!
+//! Resolution, against the fields of one kind of report (input, output or feature):
+//!
+//! - The last usage names the field, the ones before it the collections around the field, from
+//!   the outside in. Collections between the named ones are skipped, so "Joystick/X" matches X
+//!   anywhere in a Joystick collection.
+//! - A segment naming a usage page followed by one naming a usage on that page is a qualified
+//!   usage, never a collection: page names come first.
+//! - Every value of a variable item is a field, as is every usage an array item can select.
+//!   Constant items (padding) have no fields.
+//! - `report#N` keeps the fields of report N. Without it the path matches fields in all reports.
+//! - The path has to match exactly one field. If it matches several, `[n]` picks the n-th in
+//!   descriptor order, counting from 0.
+//!
+//! Paths are written back in the same form, with usages named by the path kept as names and
+//! qualified usages written in hex, so they read back as the same path.
+
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    usage, Collection, CollectionItem, FlatInputs, InputValue, Parser, Report, ReportKind,
+};
+
+/// A usage in a field path, given by number or by name
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UsageName {
+    /// Usage page and usage, written `page:usage` in hex
+    Usage((u16, u16)),
+    /// Short name of the usage, matched ignoring case
+    Name(String),
+}
+
+impl UsageName {
+    /// Whether the usage is this one
+    pub fn matches(&self, usage: (u16, u16)) -> bool {
+        match self {
+            UsageName::Usage(wanted) => *wanted == usage,
+            UsageName::Name(name) => usage::short_name(usage).eq_ignore_ascii_case(name),
+        }
+    }
+}
+
+impl Display for UsageName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageName::Usage((page, id)) => write!(f, "{:04x}:{:04x}", page, id),
+            UsageName::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// A field in a parsed report, e.g. `2/Mouse/Pointer/X`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FieldPath {
+    /// Report ID, None to match any report
+    pub report_id: Option<u8>,
+    /// Outermost first
+    pub collections: Vec<UsageName>,
+    /// Usage of the field
+    pub field: UsageName,
+    /// Among the fields matching the rest of the path
+    pub index: Option<usize>,
+}
+
+impl FromStr for FieldPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: &str| anyhow!("Invalid field path '{}': {}", s, reason);
+
+        let mut segments: Vec<&str> = s.split('/').map(str::trim).collect();
+
+        let report_id = match segments[0].strip_prefix("report#") {
+            Some(id) => {
+                segments.remove(0);
+                Some(number(id).ok_or_else(|| invalid("expected a report ID after report#"))?)
+            }
+            None => None,
+        };
+
+        let last = segments.last_mut().filter(|last| !last.is_empty());
+        let last = last.ok_or_else(|| invalid("expected a usage at the end"))?;
+        let index = match last.strip_suffix(']').and_then(|l| l.rsplit_once('[')) {
+            Some((rest, index)) => {
+                *last = rest.trim_end();
+                Some(index.trim().parse().map_err(|_| invalid("expected [n]"))?)
+            }
+            None => None,
+        };
+
+        let mut usages = vec![];
+        let mut rest = segments.as_slice();
+        while let Some((segment, tail)) = rest.split_first() {
+            if segment.is_empty() {
+                return Err(invalid("empty segment"));
+            }
+
+            // page name and usage on it
+            if let Some(usage) = tail.first().and_then(|next| qualified(segment, next)) {
+                usages.push(UsageName::Usage(usage));
+                rest = &tail[1..];
+                continue;
+            }
+
+            usages.push(match segment.split_once(':') {
+                Some((page, id)) => {
+                    let hex = |s: &str| u16::from_str_radix(s.trim_start_matches("0x"), 16).ok();
+                    let usage = hex(page).zip(hex(id));
+
+                    UsageName::Usage(usage.ok_or_else(|| invalid("expected page:id in hex"))?)
+                }
+                None => UsageName::Name(segment.to_string()),
+            });
+            rest = tail;
+        }
+
+        let field = usages.pop().ok_or_else(|| invalid("expected a usage"))?;
+
+        Ok(FieldPath {
+            report_id,
+            collections: usages,
+            field,
+            index,
+        })
+    }
+}
+
+// A usage page and a usage on it, by name or number
+fn qualified(page: &str, id: &str) -> Option<(u16, u16)> {
+    let page = match page.starts_with("0x") {
+        true => number(page)?,
+        false => (0..=u16::MAX)
+            .find(|p| usage::page_name(*p).is_some_and(|name| name.eq_ignore_ascii_case(page)))?,
+    };
+    let id = number(id).or_else(|| {
+        (0..=u16::MAX).find(|i| {
+            usage::usage_name((page, *i)).is_some_and(|name| name.eq_ignore_ascii_case(id))
+        })
+    })?;
+
+    Some((page, id))
+}
+
+fn number<T: TryFrom<u32>>(s: &str) -> Option<T> {
+    let n = match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => s.parse().ok()?,
+    };
+
+    T::try_from(n).ok()
+}
+
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(id) = self.report_id {
+            write!(f, "report#{}/", id)?;
+        }
+        for collection in &self.collections {
+            write!(f, "{}/", collection)?;
+        }
+        write!(f, "{}", self.field)?;
+        if let Some(index) = self.index {
+            write!(f, "[{}]", index)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a field is: its item, by index in `Parser::reports`, and the value in the item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FieldAddress {
+    /// Index of the field's item in `Parser::reports`
+    pub item: usize,
+    /// Index of the value in a variable item, None in an array item
+    pub value: Option<usize>,
+    /// Usage of the field
+    pub usage: (u16, u16),
+    /// Report ID of the field's item
+    pub report_id: Option<u8>,
+}
+
+impl FieldAddress {
+    /// The field's value in a parsed report. Array fields are `Selected` while the array
+    /// selects their usage.
+    pub fn value(&self, flat: &FlatInputs) -> Option<InputValue> {
+        let (_, inputs) = flat.items().find(|(item, _)| *item == self.item)?;
+
+        match self.value {
+            Some(value) => inputs.get(value).map(|input| input.value),
+            None => inputs
+                .iter()
+                .find(|input| input.usage == self.usage)
+                .map(|input| input.value),
+        }
+    }
+}
+
+impl Parser {
+    /// All fields of the kind the path matches without its index, in descriptor order
+    pub fn find_fields(&self, path: &FieldPath, kind: ReportKind) -> Vec<FieldAddress> {
+        let mut found = vec![];
+        let mut item = 0;
+        for collection in self.collections() {
+            find_in(collection, path, kind, &mut vec![], &mut item, &mut found);
+        }
+
+        found
+    }
+
+    /// The one field of the kind the path names
+    pub fn field(&self, path: &FieldPath, kind: ReportKind) -> Result<FieldAddress> {
+        let found = self.find_fields(path, kind);
+
+        match (path.index, found.len()) {
+            (_, 0) => Err(anyhow!("No {} field matches '{}'", kind_name(kind), path)),
+            (Some(index), count) => found
+                .get(index)
+                .copied()
+                .ok_or_else(|| anyhow!("'{}' matches only {} fields, counted from 0", path, count)),
+            (None, 1) => Ok(found[0]),
+            (None, count) => Err(anyhow!(
+                "'{}' matches {} fields, pick one with [0] to [{}]",
+                path,
+                count,
+                count - 1
+            )),
+        }
+    }
+}
+
+fn kind_name(kind: ReportKind) -> &'static str {
+    match kind {
+        ReportKind::Input => "input",
+        ReportKind::Output => "output",
+        ReportKind::Feature => "feature",
+    }
+}
+
+fn find_in(
+    collection: &Collection<Report>,
+    path: &FieldPath,
+    kind: ReportKind,
+    ancestors: &mut Vec<(u16, u16)>,
+    item: &mut usize,
+    found: &mut Vec<FieldAddress>,
+) {
+    ancestors.push(collection.usage);
+
+    for child in &collection.items {
+        match child {
+            CollectionItem::Collection(child) => find_in(child, path, kind, ancestors, item, found),
+            CollectionItem::Item(report) => {
+                if report.report_type.kind() == kind
+                    && path.report_id.is_none_or(|id| report.report_id == Some(id))
+                    && within(&path.collections, ancestors)
+                {
+                    found.extend(fields(report).filter_map(|(value, usage)| {
+                        path.field.matches(usage).then_some(FieldAddress {
+                            item: *item,
+                            value,
+                            usage,
+                            report_id: report.report_id,
+                        })
+                    }));
+                }
+                *item += 1;
+            }
+        }
+    }
+
+    ancestors.pop();
+}
+
+// Whether the collections are among the ancestors, in order
+fn within(collections: &[UsageName], ancestors: &[(u16, u16)]) -> bool {
+    let mut ancestors = ancestors.iter();
+
+    collections
+        .iter()
+        .all(|collection| ancestors.any(|usage| collection.matches(*usage)))
+}
+
+// The fields of an item: each value of a variable item, each usage of an array item
+fn fields(report: &Report) -> Box<dyn Iterator<Item = (Option<usize>, (u16, u16))> + '_> {
+    let flags = report.report_type.flags();
+    if flags.constant() {
+        return Box::new(std::iter::empty());
+    }
+
+    if flags.array() {
+        let range = match (report.usage_minimum, report.usage_maximum) {
+            (Some(min), Some(max)) => Some((min.1..=max.1).map(move |id| (min.0, id))),
+            _ => None,
+        };
+        let usages = report
+            .usages
+            .iter()
+            .copied()
+            .chain(range.into_iter().flatten());
+
+        return Box::new(usages.map(|usage| (None, usage)));
+    }
+
+    Box::new((0..report.report_count as usize).filter_map(|index| {
+        let usage = match (report.usages.get(index), report.usage_minimum) {
+            (Some(usage), _) => *usage,
+            (None, Some((page, id))) => (
+                page,
+                id.saturating_add((index - report.usages.len()) as u16),
+            ),
+            (None, None) => *report.usages.last()?,
+        };
+
+        Some((Some(index), usage))
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FieldAddress, FieldPath, UsageName};
+    use crate::{
+        CollectionType, DescriptorBuilder, FlatInputs, InputItemData, InputValue, Parser,
+        ReportKind,
+    };
+
+    // Joystick with a Pointer of X and Y in report 1, buttons and a vendor array in report 2
+    fn joystick() -> Parser {
+        DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x04)
+            .collection(CollectionType::Application)
+            .report_id(1)
+            .usage(0x01)
+            .collection(CollectionType::Physical)
+            .usage(0x30)
+            .usage(0x31)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .report_id(2)
+            .usage_page(0x09)
+            .usage_minimum(1)
+            .usage_maximum(8)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .report_size(1)
+            .report_count(8)
+            .input(InputItemData { data: 0x02 })
+            .usage_page(0xff00)
+            .usage_minimum(1)
+            .usage_maximum(4)
+            .logical_minimum(1)
+            .logical_maximum(4)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x00 })
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn parses_and_writes_paths() {
+        let path: FieldPath = "Joystick/Pointer/X".parse().unwrap();
+        assert_eq!(
+            path.collections,
+            [
+                UsageName::Name("Joystick".to_string()),
+                UsageName::Name("Pointer".to_string())
+            ]
+        );
+        assert_eq!(path.field, UsageName::Name("X".to_string()));
+
+        let path: FieldPath = "report#3/Button 5".parse().unwrap();
+        assert_eq!((path.report_id, path.collections.len()), (Some(3), 0));
+
+        let path: FieldPath = "ff00:0001[2]".parse().unwrap();
+        assert_eq!(
+            (path.field.clone(), path.index),
+            (UsageName::Usage((0xff00, 0x0001)), Some(2))
+        );
+
+        // pages qualify the usage after them
+        for qualified in ["Generic Desktop/X", "generic desktop/0x30", "0x01/48"] {
+            let path: FieldPath = qualified.parse().unwrap();
+            assert_eq!(path.field, UsageName::Usage((0x01, 0x30)));
+            assert!(path.collections.is_empty());
+        }
+        let path: FieldPath = "Button/Button 2".parse().unwrap();
+        assert_eq!(path.field, UsageName::Usage((0x09, 0x02)));
+
+        for text in ["report#3/Joystick/X[1]", "0001:0004/Button 5"] {
+            let path: FieldPath = text.parse().unwrap();
+            assert_eq!(path.to_string(), text);
+            assert_eq!(path.to_string().parse::<FieldPath>().unwrap(), path);
+        }
+
+        for invalid in ["", "X/", "report#x/X", "ff00:zz", "X[a]", "report#1"] {
+            assert!(invalid.parse::<FieldPath>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn resolves_paths_to_fields() {
+        let parser = joystick();
+        let field = |path: &str| {
+            let path: FieldPath = path.parse().unwrap();
+            parser
+                .field(&path, ReportKind::Input)
+                .map_err(|e| e.to_string())
+        };
+
+        let x = FieldAddress {
+            item: 0,
+            value: Some(0),
+            usage: (0x01, 0x30),
+            report_id: Some(1),
+        };
+        for path in [
+            "X",
+            "Joystick/Pointer/X",
+            "Joystick/X",
+            "report#1/Generic Desktop/X",
+        ] {
+            assert_eq!(field(path), Ok(x), "{}", path);
+        }
+        assert_eq!(field("Y").unwrap().value, Some(1));
+        assert_eq!(field("Button 5").unwrap().value, Some(4));
+
+        // array usages and several matches
+        assert_eq!(
+            field("ff00:0003").unwrap(),
+            FieldAddress {
+                item: 2,
+                value: None,
+                usage: (0xff00, 0x03),
+                report_id: Some(2)
+            }
+        );
+        let buttons: FieldPath = "Joystick/Button".parse().unwrap();
+        assert_eq!(parser.find_fields(&buttons, ReportKind::Input).len(), 0);
+
+        assert_eq!(
+            field("Pointer/Button 1").unwrap_err(),
+            "No input field matches 'Pointer/Button 1'"
+        );
+        assert_eq!(
+            field("report#2/X").unwrap_err(),
+            "No input field matches 'report#2/X'"
+        );
+        assert!(field("X[1]").is_err());
+
+        // values from flattened inputs
+        let mut flat = FlatInputs::new();
+        parser.parse_input_flat(&[0x01, 0x05, 0xfb], &mut flat);
+        assert_eq!(x.value(&flat), Some(InputValue::Int(5)));
+        assert_eq!(field("Y").unwrap().value(&flat), Some(InputValue::Int(-5)));
+        parser.parse_input_flat(&[0x02, 0x10, 0x03, 0x00], &mut flat);
+        assert_eq!(x.value(&flat), None);
+        assert_eq!(
+            field("Button 5").unwrap().value(&flat),
+            Some(InputValue::Bool(true))
+        );
+        let array = field("ff00:0003").unwrap();
+        assert_eq!(array.value(&flat), Some(InputValue::Selected));
+        assert_eq!(field("ff00:0001").unwrap().value(&flat), None);
+    }
+}
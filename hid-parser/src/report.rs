@@ -1,92 +1,241 @@
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 
 use super::{
-    basic::InputItemData,
+    basic::{FeatureItemData, InputItemData, OutputItemData},
     input::{Input, InputValue},
+    usage,
 };
 
-// A single report, may read multiple inputs of the same configuration
-#[derive(Debug)]
+/// A single report, may read multiple inputs of the same configuration
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Report {
+    /// Kind of the main item, with its flags
     pub report_type: ReportType,
+    /// Usages of the item, in order
     pub usages: Vec<(u16, u16)>,
+    /// Start of the usage range, if the item has one
     pub usage_minimum: Option<(u16, u16)>,
+    /// End of the usage range, if the item has one
     pub usage_maximum: Option<(u16, u16)>,
+    /// Smallest value a field can report
     pub logical_minimum: i32,
+    /// Largest value a field can report
     pub logical_maximum: i32,
+    /// Physical value of the logical minimum
     pub physical_minimum: i32,
+    /// Physical value of the logical maximum
     pub physical_maximum: i32,
+    /// Unit of the physical values, as the Unit item encodes it
     pub unit: Option<u32>,
-    pub unit_exponent: Option<u32>,
-    pub bit_offset: usize,     // start of the report in the overall report data
-    pub report_id: Option<u8>, // if given, add 8 bits to the offset, check the ID matches
+    /// Power of 10 of the physical values
+    pub unit_exponent: Option<i8>,
+    /// Start of the report in the overall report data
+    pub bit_offset: usize,
+    /// If given, add 8 bits to the offset, check the ID matches
+    pub report_id: Option<u8>,
+    /// Size of each value in bits
     pub report_size: u32,
+    /// Number of values
     pub report_count: u32,
 }
 
 impl Report {
+    /// Length of all the item's values in bits. Saturates rather than overflowing for sizes and
+    /// counts no report could hold.
+    pub fn bit_length(&self) -> usize {
+        (self.report_size as usize).saturating_mul(self.report_count as usize)
+    }
+
+    /// Values of the item in a report (starting with the report ID, if used), None if the report
+    /// is too short or has another report ID
     pub fn parse(&self, report: &[u8]) -> Option<Vec<Input>> {
-        let ReportType::Input(input) = self.report_type;
-        if input.constant() {
-            return None;
+        let mut inputs = vec![];
+
+        self.parse_into(report, &mut inputs).then_some(inputs)
+    }
+
+    /// Like `parse`, appending the values to `inputs`. False if the report doesn't contain
+    /// this item.
+    pub fn parse_into(&self, report: &[u8], inputs: &mut Vec<Input>) -> bool {
+        let flags = self.report_type.flags();
+        if flags.constant() || self.report_size == 0 {
+            return false;
+        }
+
+        // Reports with an ID start with the ID byte, other reports are skipped
+        let id_offset = match self.report_id {
+            Some(id) if report.first() == Some(&id) => 8,
+            Some(_) => return false,
+            None => 0,
+        };
+
+        let end = (id_offset + self.bit_offset).saturating_add(self.bit_length());
+        if end > report.len() * 8 {
+            return false;
+        }
+
+        if flags.array() {
+            self.parse_array(report, id_offset, inputs);
+            return true;
         }
 
         let spec_usages = self.usages.len();
 
-        (0..(self.report_count as usize))
-            .map(|i| {
-                let usage = if i < spec_usages {
-                    self.usages[i]
+        inputs.extend((0..(self.report_count as usize)).map(|i| {
+            let usage = if i < spec_usages {
+                self.usages[i]
+            } else {
+                // Usage Minimum specifies the usage to be associated with the first unassociated control
+                // in the array or bitmap. Usage Maximum specifies the end of the range of usage values
+                // to be associated with item elements.
+                if let Some((up, u)) = self.usage_minimum {
+                    (up, u.saturating_add((i - spec_usages) as u16))
                 } else {
-                    // Usage Minimum specifies the usage to be associated with the first unassociated control
-                    // in the array or bitmap. Usage Maximum specifies the end of the range of usage values
-                    // to be associated with item elements.
-                    if let Some((up, u)) = self.usage_minimum {
-                        (up, u + spec_usages as u16 + i as u16)
+                    // HID 1.11, section 6.2.2.8 Local Items
+                    //
+                    // While Local items do not carry over to the next Main item,
+                    // they may apply to more than one control within a single item.
+                    // For example, if an Input item defining five controls is
+                    // preceded by three Usage tags, the three usages would be
+                    // assigned sequentially to the first three controls, and the
+                    // third usage would also be assigned to the fourth and fifth controls.
+                    self.usages.last().copied().unwrap_or_default()
+                }
+            };
+
+            let offset = id_offset + self.bit_offset + (self.report_size as usize * i);
+            let base_value = Self::extract_value(report, offset, self.report_size);
+
+            let has_null = flags.null();
+
+            let value = match (self.logical_minimum, self.logical_maximum) {
+                (0, 1) => InputValue::Bool(base_value != 0),
+                (a, b) if (a, b) >= (0, 0) => {
+                    if has_null && (base_value as i32) < a || (base_value as i32) > b {
+                        InputValue::None
+                    } else {
+                        InputValue::UInt(base_value)
+                    }
+                }
+                (a, b) => {
+                    let value = Self::signed(base_value, self.report_size);
+
+                    if has_null && value < a || value > b {
+                        InputValue::None
                     } else {
-                        // HID 1.11, section 6.2.2.8 Local Items
-                        //
-                        // While Local items do not carry over to the next Main item,
-                        // they may apply to more than one control within a single item.
-                        // For example, if an Input item defining five controls is
-                        // preceded by three Usage tags, the three usages would be
-                        // assigned sequentially to the first three controls, and the
-                        // third usage would also be assigned to the fourth and fifth controls.
-                        self.usages[self.usages.len() - 1]
+                        InputValue::Int(value)
                     }
-                };
+                }
+            };
 
-                let offset = self.bit_offset + (self.report_size as usize * i);
-                let base_value = Self::extract_value(report, offset, self.report_size);
+            Input {
+                usage,
+                value,
+                flags,
+            }
+        }));
 
-                let has_null = matches!(self.report_type, ReportType::Input(input) if input.null());
+        true
+    }
 
-                let value = match (self.logical_minimum, self.logical_maximum) {
-                    (0, 1) => InputValue::Bool(base_value != 0),
-                    (a, b) if (a, b) >= (0, 0) => {
-                        if has_null && (base_value as i32) < a || (base_value as i32) > b {
-                            InputValue::None
-                        } else {
-                            InputValue::UInt(base_value)
-                        }
-                    }
-                    (a, b) => {
-                        let value = Self::signed(base_value, self.report_size);
-
-                        if has_null && value < a || value > b {
-                            InputValue::None
-                        } else {
-                            InputValue::Int(value)
-                        }
-                    }
-                };
+    /// Change from a previous to the current value: the value itself for relative items, the
+    /// difference for absolute ones. Wrapping items (dials, encoders) roll over at the ends of the
+    /// logical range, so the shorter way around is taken, e.g. 359 to 1 is +2 on a 0..=359 dial
+    /// and 255 is -1 on a relative 0..=255 one.
+    pub fn delta(&self, previous: i64, current: i64) -> i64 {
+        let flags = self.report_type.flags();
+        let change = match flags.relative() {
+            true => current,
+            false => current - previous,
+        };
+
+        let range = self.logical_maximum as i64 - self.logical_minimum as i64 + 1;
+        if flags.no_wrap() || range <= 1 {
+            return change;
+        }
+
+        match change.rem_euclid(range) {
+            change if change > range / 2 => change - range,
+            change => change,
+        }
+    }
+
+    /// Writes the `index`-th value of this item into a report (starting with the report ID, if
+    /// used), keeping the other bits. False, leaving the report as it was, if the report is too
+    /// short or the item has no such value.
+    pub fn write_value(&self, report: &mut [u8], index: usize, value: i32) -> bool {
+        let id_offset = match self.report_id {
+            Some(_) => 8,
+            None => 0,
+        };
+
+        let offset = id_offset + self.bit_offset + self.report_size as usize * index;
+        let length = self.report_size as usize;
+        if index >= self.report_count as usize || offset + length > report.len() * 8 {
+            return false;
+        }
+
+        if let Some(id) = self.report_id {
+            report[0] = id;
+        }
 
-                Some(Input { usage, value })
+        for bit in 0..length {
+            let (byte, shift) = ((offset + bit) / 8, (offset + bit) % 8);
+            match (value >> bit.min(31)) & 1 {
+                1 => report[byte] |= 1 << shift,
+                _ => report[byte] &= !(1 << shift),
+            }
+        }
+
+        true
+    }
+
+    // Array items report the indices of the controls currently asserted (e.g. pressed keys),
+    // each index selects a usage from the usage list or the usage range
+    fn parse_array(&self, report: &[u8], id_offset: usize, inputs: &mut Vec<Input>) {
+        let selected = (0..(self.report_count as usize)).filter_map(|i| {
+            let offset = id_offset + self.bit_offset + (self.report_size as usize * i);
+            let value = Self::extract_value(report, offset, self.report_size) as i64;
+
+            // values outside the logical range mean no control is asserted
+            let (min, max) = (self.logical_minimum as i64, self.logical_maximum as i64);
+            if value < min || value > max {
+                return None;
+            }
+
+            let usage = self.array_usage((value - min) as usize)?;
+
+            // usage 0 is reserved for "no event" (No Button Pressed, Unassigned, ...)
+            if usage.1 == 0 {
+                return None;
+            }
+
+            Some(Input {
+                usage,
+                value: InputValue::Selected,
+                flags: self.report_type.flags(),
             })
-            .collect()
+        });
+
+        inputs.extend(selected);
+    }
+
+    fn array_usage(&self, index: usize) -> Option<(u16, u16)> {
+        if let Some(usage) = self.usages.get(index) {
+            return Some(*usage);
+        }
+
+        let (page, minimum) = self.usage_minimum?;
+        let id = minimum as usize + index - self.usages.len();
+
+        match self.usage_maximum {
+            Some((_, maximum)) if id > maximum as usize => None,
+            _ => Some((page, id as u16)),
+        }
     }
 
     fn signed(value: u32, length: u32) -> i32 {
+        let length = length.clamp(1, 32);
         let sign_mask = 1 << (length - 1);
         let number_mask = !(0xFFFF_FFFF << (length - 1));
 
@@ -100,38 +249,129 @@ impl Report {
         }
     }
 
+    // Values longer than 32 bits are cut to their lowest 32
     fn extract_value(report: &[u8], bit_offset: usize, bit_length: u32) -> u32 {
+        let bit_length = bit_length.clamp(1, 32);
         let first_byte = bit_offset / 8; // first byte in which the value is
         let last_byte = (bit_offset + bit_length as usize - 1) / 8;
         let bit_shift = bit_offset % 8;
 
-        // TODO check bounds!
+        // bounds are checked by `parse`
         let bytes = &report[first_byte..=last_byte];
 
-        let mut value = 0u32;
-        for byte in 0..bytes.len() {
+        let mut value = 0u64;
+        for (idx, byte) in bytes.iter().enumerate() {
             // numbers are little-endian!
-            value |= (bytes[byte as usize] as u32) << (8 * byte);
+            value |= (*byte as u64) << (8 * idx);
         }
 
         value >>= bit_shift;
-        value &= !(0xFFFFFFFFu32 << bit_length);
+        value &= !(u64::MAX << bit_length);
+
+        value as u32
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.report_type.kind())?;
+        if let Some(id) = self.report_id {
+            write!(f, " #{}", id)?;
+        }
+
+        let flags = self.report_type.flags();
+        let mut usages: Vec<_> = self.usages.iter().map(|u| usage::short_name(*u)).collect();
+        match (self.usage_minimum, self.usage_maximum) {
+            (Some(min), Some(max)) => usages.push(format!(
+                "{} ..= {}",
+                usage::short_name(min),
+                usage::short_name(max)
+            )),
+            (Some(min), None) => usages.push(format!("{} ..", usage::short_name(min))),
+            _ => (),
+        }
+
+        write!(
+            f,
+            " @{} {}x{}: ",
+            self.bit_offset, self.report_size, self.report_count
+        )?;
+
+        if flags.constant() {
+            write!(f, "padding")?;
+        } else {
+            write!(
+                f,
+                "{} {} {}..{}",
+                usages.join(", "),
+                if flags.array() { "array" } else { "logical" },
+                self.logical_minimum,
+                self.logical_maximum
+            )?;
+        }
+
+        if (self.physical_minimum, self.physical_maximum)
+            != (self.logical_minimum, self.logical_maximum)
+        {
+            write!(
+                f,
+                ", physical {}..{}",
+                self.physical_minimum, self.physical_maximum
+            )?;
+        }
 
-        value
+        write!(f, " [{}]", flags)
     }
 }
 
-#[derive(Debug)]
+/// A main item with its flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ReportType {
+    /// Input item
     Input(InputItemData),
-    // TODO ready for other types of report
-    //
-    // Output(u32),
-    // Feature(u32),
+    /// Output item
+    Output(OutputItemData),
+    /// Feature item
+    Feature(FeatureItemData),
+}
+
+/// Kind of a main item, without the flags
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReportKind {
+    /// Input, from the device to the host
+    Input,
+    /// Output, from the host to the device
+    Output,
+    /// Feature, both ways with control transfers
+    Feature,
+}
+
+impl ReportType {
+    /// The kind, without the flags
+    pub fn kind(&self) -> ReportKind {
+        match self {
+            ReportType::Input(_) => ReportKind::Input,
+            ReportType::Output(_) => ReportKind::Output,
+            ReportType::Feature(_) => ReportKind::Feature,
+        }
+    }
+
+    /// Main item flags, Output and Feature items share the bit layout with Input items
+    pub fn flags(&self) -> InputItemData {
+        let data = match self {
+            ReportType::Input(input) => input.data,
+            ReportType::Output(output) => output.data,
+            ReportType::Feature(feature) => feature.data,
+        };
+
+        InputItemData { data }
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use crate::{CollectionType, DescriptorBuilder, FeatureItemData, InputItemData};
+
     use super::Report;
 
     #[test]
@@ -226,4 +466,70 @@ mod test {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn takes_the_shorter_way_around_wrapping_values() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x08)
+            .collection(CollectionType::Application)
+            .usage(0x37)
+            .logical_minimum(0)
+            .logical_maximum(359)
+            .report_size(16)
+            .report_count(1)
+            .input(InputItemData { data: 0x0a }) // absolute, wrap
+            .usage(0x38)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .input(InputItemData { data: 0x0e }) // relative, wrap
+            .usage(0x30)
+            .logical_minimum(0)
+            .logical_maximum(359)
+            .report_size(16)
+            .input(InputItemData { data: 0x02 }) // absolute
+            .end_collection()
+            .build()
+            .decode();
+        let reports = parser.reports();
+
+        assert_eq!(reports[0].delta(359, 1), 2);
+        assert_eq!(reports[0].delta(1, 359), -2);
+        assert_eq!(reports[0].delta(90, 100), 10);
+        assert_eq!(reports[1].delta(0, 255), -1);
+        assert_eq!(reports[1].delta(0, 3), 3);
+        assert_eq!(reports[2].delta(359, 1), -358);
+
+        let inputs = reports[0].parse(&[1, 0, 0, 0, 0]).unwrap();
+        assert!(inputs[0].flags.wrap());
+        assert!(!inputs[0].flags.non_linear());
+    }
+
+    #[test]
+    fn leaves_reports_alone_when_a_value_does_not_fit() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .report_id(5)
+            .usage(0x48)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(2)
+            .feature(FeatureItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+        let report = parser.reports()[0];
+
+        let mut bytes = [0xaa; 2];
+        assert!(!report.write_value(&mut bytes, 1, 7));
+        assert!(!report.write_value(&mut bytes, 2, 7));
+        assert_eq!(bytes, [0xaa; 2]);
+
+        assert!(report.write_value(&mut bytes, 0, 7));
+        assert_eq!(bytes, [5, 7]);
+    }
 }
@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{thread, time::Duration};
 
 use rusb::{
     self, constants::LIBUSB_REQUEST_GET_DESCRIPTOR, DeviceHandle, InterfaceDescriptor, UsbContext,
@@ -7,6 +7,7 @@ use rusb::{
 use crate::{DescriptorType, HidDescriptor, ReportDescriptor};
 
 impl<'a> HidDescriptor<'a> {
+    /// The HID descriptor of an interface, from the class descriptors after it
     pub fn from_interface_descriptor(interface_descriptor: &'a InterfaceDescriptor) -> Self {
         Self {
             interface_num: interface_descriptor.interface_number(),
@@ -14,11 +15,23 @@ impl<'a> HidDescriptor<'a> {
         }
     }
 
-    // TODO hide behind rusb flag
+    /// Reads the interface's report descriptors with the default transfer policy
     pub fn report_descriptors<'s, T: UsbContext>(
         &'s self,
         device_handle: &'a DeviceHandle<T>,
-    ) -> ReportDescriptors<'_, T>
+    ) -> ReportDescriptors<'s, T>
+    where
+        'a: 's,
+    {
+        self.report_descriptors_with(device_handle, TransferPolicy::default())
+    }
+
+    /// Reads the interface's report descriptors with a transfer policy
+    pub fn report_descriptors_with<'s, T: UsbContext>(
+        &'s self,
+        device_handle: &'a DeviceHandle<T>,
+        policy: TransferPolicy,
+    ) -> ReportDescriptors<'s, T>
     where
         'a: 's,
     {
@@ -26,19 +39,89 @@ impl<'a> HidDescriptor<'a> {
             index: 0,
             hid_descriptor: self,
             device_handle,
+            policy,
         }
     }
 }
 
+/// A finished control transfer, handed to the trace function of a `TransferPolicy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlTransfer<'a> {
+    /// BmRequestType
+    pub request_type: u8,
+    /// BRequest
+    pub request: u8,
+    /// WValue
+    pub value: u16,
+    /// WIndex
+    pub index: u16,
+    /// WLength
+    pub length: u16,
+    /// Sent, or received before the transfer ended
+    pub data: &'a [u8],
+    /// Bytes transferred, or the error
+    pub result: Result<usize, rusb::Error>,
+}
+
+/// Timeout and retries of the Get_Descriptor control transfers, and a function seeing every
+/// attempt, e.g. to log it
+#[derive(Debug, Clone, Copy)]
+pub struct TransferPolicy {
+    /// Of each attempt
+    pub timeout: Duration,
+    /// Attempts after the first failed one
+    pub retries: u32,
+    /// Doubles after every failed attempt
+    pub backoff: Duration,
+    /// Called after every attempt
+    pub trace: Option<fn(&ControlTransfer)>,
+    /// WLength to ask for instead of the length in the HID descriptor, for devices answering
+    /// short or stalling when asked for the exact length
+    pub length: Option<u16>,
+}
+
+impl Default for TransferPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(500),
+            retries: 2,
+            backoff: Duration::from_millis(50),
+            trace: None,
+            length: None,
+        }
+    }
+}
+
+impl TransferPolicy {
+    /// Delay before the retry following a failed `attempt` (counted from 0)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt)
+    }
+
+    /// Errors a flaky device may recover from, the others fail right away
+    pub fn is_retryable(error: &rusb::Error) -> bool {
+        matches!(
+            error,
+            rusb::Error::Timeout
+                | rusb::Error::Pipe
+                | rusb::Error::Io
+                | rusb::Error::Busy
+                | rusb::Error::Overflow
+                | rusb::Error::Interrupted
+        )
+    }
+}
+
+/// Iterator over the report descriptors of an interface, read one by one from the device
 pub struct ReportDescriptors<'a, T: UsbContext> {
     index: u8,
     hid_descriptor: &'a HidDescriptor<'a>,
     device_handle: &'a DeviceHandle<T>,
+    policy: TransferPolicy,
 }
 
-// TODO hide behind rusb flag
 impl<'a, T: UsbContext> Iterator for ReportDescriptors<'a, T> {
-    type Item = ReportDescriptor;
+    type Item = rusb::Result<ReportDescriptor>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // find next Report descriptor
@@ -59,10 +142,11 @@ impl<'a, T: UsbContext> Iterator for ReportDescriptors<'a, T> {
 
         // Constrcut the Get_Descriptor request
 
-        let descriptor_length = self
-            .hid_descriptor
-            .descriptor_length(self.index as usize)
-            .expect("Index no longer valid");
+        let descriptor_length = self.policy.length.unwrap_or_else(|| {
+            self.hid_descriptor
+                .descriptor_length(self.index as usize)
+                .expect("Index no longer valid")
+        });
         let descriptor_type = self
             .hid_descriptor
             .descriptor_type(self.index as usize)
@@ -79,27 +163,67 @@ impl<'a, T: UsbContext> Iterator for ReportDescriptors<'a, T> {
 
         let mut bytes: Vec<u8> = (0..descriptor_length).map(|_| 0u8).collect();
 
-        // Perform the request
+        // Perform the request, retrying failures the device may recover from
+
+        let mut attempt = 0;
+        let result = loop {
+            let result = self.device_handle.read_control(
+                request_type,
+                request,
+                value,
+                self.hid_descriptor.interface_num as u16,
+                &mut bytes,
+                self.policy.timeout,
+            );
+            if let Some(trace) = self.policy.trace {
+                trace(&ControlTransfer {
+                    request_type,
+                    request,
+                    value,
+                    index: self.hid_descriptor.interface_num as u16,
+                    length: descriptor_length,
+                    data: &bytes[..*result.as_ref().unwrap_or(&0)],
+                    result,
+                });
+            }
 
-        let result = self.device_handle.read_control(
-            request_type,
-            request,
-            value,
-            self.hid_descriptor.interface_num as u16,
-            &mut bytes,
-            Duration::from_millis(500),
-        );
+            match result {
+                Err(err) if attempt < self.policy.retries && TransferPolicy::is_retryable(&err) => {
+                    thread::sleep(self.policy.delay(attempt));
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
 
         self.index += 1;
 
-        match result {
-            Ok(len) => Some(ReportDescriptor {
-                bytes: Vec::from(&bytes[0..len]),
-            }),
-            Err(err) => {
-                println!("Could not read Report descriptor {:?}", err);
-                None
-            }
-        }
+        Some(result.map(|len| ReportDescriptor {
+            bytes: Vec::from(&bytes[0..len]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::TransferPolicy;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = TransferPolicy::default();
+
+        assert_eq!(policy.delay(0), Duration::from_millis(50));
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn retries_only_transient_errors() {
+        assert!(TransferPolicy::is_retryable(&rusb::Error::Timeout));
+        assert!(TransferPolicy::is_retryable(&rusb::Error::Pipe));
+        assert!(!TransferPolicy::is_retryable(&rusb::Error::Access));
+        assert!(!TransferPolicy::is_retryable(&rusb::Error::NoDevice));
     }
 }
@@ -0,0 +1,329 @@
+//! Scroll wheels and high-resolution scrolling
+//!
+//! Vertical wheels report Wheel on the Generic Desktop page, horizontal ones AC Pan on the
+//! Consumer page. Devices capable of high-resolution scrolling describe a Resolution Multiplier
+//! feature, which applies to the wheels in its logical collection, or to the whole application
+//! collection when it isn't in one. Until the host sets it the wheels report whole detents,
+//! afterwards every detent is reported as several smaller steps.
+
+use std::fmt::Display;
+
+use super::{
+    collection::{Collection, CollectionItem},
+    input::InputValue,
+    report::{Report, ReportKind},
+    usage::{CONSUMER, GENERIC_DESKTOP},
+    Parser,
+};
+
+/// Generic Desktop usage of vertical wheels
+pub const WHEEL: u16 = 0x38;
+/// Generic Desktop usage of the resolution multiplier feature
+pub const RESOLUTION_MULTIPLIER: u16 = 0x48;
+/// Consumer usage of horizontal wheels
+pub const AC_PAN: u16 = 0x238;
+
+/// A scrolling direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ScrollAxis {
+    /// Wheel
+    Vertical,
+    /// AC Pan
+    Horizontal,
+}
+
+impl ScrollAxis {
+    /// The axis a usage scrolls, None for other usages
+    pub fn from_usage(usage: (u16, u16)) -> Option<Self> {
+        match usage {
+            (GENERIC_DESKTOP, WHEEL) => Some(ScrollAxis::Vertical),
+            (CONSUMER, AC_PAN) => Some(ScrollAxis::Horizontal),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ScrollAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrollAxis::Vertical => write!(f, "Wheel"),
+            ScrollAxis::Horizontal => write!(f, "AC Pan"),
+        }
+    }
+}
+
+/// A Resolution Multiplier feature and the wheels it applies to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolutionMultiplier {
+    report: Report, // the feature item holding the multiplier
+    index: usize,   // of the multiplier among the item's values
+    /// The wheels it applies to
+    pub axes: Vec<ScrollAxis>,
+}
+
+impl ResolutionMultiplier {
+    /// Report ID of the feature report holding the multiplier
+    pub fn report_id(&self) -> Option<u8> {
+        self.report.report_id
+    }
+
+    /// Smallest and largest logical value of the multiplier
+    pub fn logical_range(&self) -> (i32, i32) {
+        (self.report.logical_minimum, self.report.logical_maximum)
+    }
+
+    /// Logical value in a feature report (starting with the report ID, if used)
+    pub fn read(&self, feature: &[u8]) -> Option<i32> {
+        let inputs = self.report.parse(feature)?;
+
+        match inputs.get(self.index)?.value {
+            InputValue::Bool(b) => Some(b as i32),
+            InputValue::UInt(u) => Some(u as i32),
+            InputValue::Int(i) => Some(i),
+            InputValue::Selected | InputValue::None => None,
+        }
+    }
+
+    /// Sets the logical value in a feature report read from the device, keeping the other
+    /// features in it. False if the value is out of range or the report too short.
+    pub fn write(&self, feature: &mut [u8], value: i32) -> bool {
+        let (min, max) = self.logical_range();
+        if value < min || value > max {
+            return false;
+        }
+
+        self.report.write_value(feature, self.index, value)
+    }
+
+    /// Steps per detent at a logical value: the value scaled to the physical range, or the
+    /// logical value itself when no physical range is given
+    pub fn factor(&self, value: i32) -> f32 {
+        let (min, max) = self.logical_range();
+        let (physical_min, physical_max) = match self.report {
+            Report {
+                physical_minimum: 0,
+                physical_maximum: 0,
+                ..
+            } => (min, max),
+            Report {
+                physical_minimum,
+                physical_maximum,
+                ..
+            } => (physical_minimum, physical_maximum),
+        };
+        if max <= min {
+            return 1.0;
+        }
+
+        let scaled = physical_min as f32
+            + (value.clamp(min, max) - min) as f32 * (physical_max - physical_min) as f32
+                / (max - min) as f32;
+
+        scaled.max(1.0)
+    }
+}
+
+impl Parser {
+    /// Wheels with an input item in the descriptor
+    pub fn scroll_axes(&self) -> Vec<ScrollAxis> {
+        let mut axes = vec![];
+
+        for collection in self.collections() {
+            axes.extend(collection_axes(collection));
+        }
+        axes.sort();
+        axes.dedup();
+
+        axes
+    }
+
+    /// Every Resolution Multiplier feature of the descriptor
+    pub fn resolution_multipliers(&self) -> Vec<ResolutionMultiplier> {
+        let mut multipliers = vec![];
+
+        for collection in self.collections() {
+            collect_multipliers(collection, &mut multipliers);
+        }
+
+        multipliers
+    }
+
+    /// Wheel movements in an input report (starting with the report ID, if used), in the
+    /// units the device reports. Wheels which didn't move are left out.
+    pub fn scroll(&self, bytes: &[u8]) -> Vec<(ScrollAxis, i32)> {
+        let mut deltas = vec![];
+
+        for report in self.reports() {
+            if report.report_type.kind() != ReportKind::Input
+                || !report
+                    .usages
+                    .iter()
+                    .any(|usage| ScrollAxis::from_usage(*usage).is_some())
+            {
+                continue;
+            }
+
+            for input in report.parse(bytes).unwrap_or_default() {
+                let value = match input.value {
+                    InputValue::UInt(u) => u as i32,
+                    InputValue::Int(i) => i,
+                    _ => continue,
+                };
+
+                if let Some(axis) = ScrollAxis::from_usage(input.usage) {
+                    if value != 0 {
+                        deltas.push((axis, value));
+                    }
+                }
+            }
+        }
+
+        deltas
+    }
+}
+
+fn collection_axes(collection: &Collection<Report>) -> Vec<ScrollAxis> {
+    let mut axes = vec![];
+
+    collection.visit(&mut |report: &Report| {
+        if report.report_type.kind() == ReportKind::Input {
+            axes.extend(
+                report
+                    .usages
+                    .iter()
+                    .filter_map(|usage| ScrollAxis::from_usage(*usage)),
+            );
+        }
+    });
+
+    axes
+}
+
+pub(crate) fn collect_multipliers(
+    collection: &Collection<Report>,
+    multipliers: &mut Vec<ResolutionMultiplier>,
+) {
+    for item in &collection.items {
+        match item {
+            CollectionItem::Collection(collection) => collect_multipliers(collection, multipliers),
+            CollectionItem::Item(report) if report.report_type.kind() == ReportKind::Feature => {
+                let index = report
+                    .usages
+                    .iter()
+                    .position(|usage| *usage == (GENERIC_DESKTOP, RESOLUTION_MULTIPLIER));
+
+                if let Some(index) = index {
+                    let mut axes = collection_axes(collection);
+                    axes.sort();
+                    axes.dedup();
+
+                    multipliers.push(ResolutionMultiplier {
+                        report: report.clone(),
+                        index: index.min(report.report_count.saturating_sub(1) as usize),
+                        axes,
+                    });
+                }
+            }
+            CollectionItem::Item(_) => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{CollectionType, DescriptorBuilder, FeatureItemData, InputItemData, Parser};
+    use super::ScrollAxis;
+
+    const RELATIVE: InputItemData = InputItemData { data: 0x06 };
+    const FEATURE: FeatureItemData = FeatureItemData { data: 0x02 };
+
+    // Report 2 with a wheel and AC Pan, each in a logical collection with its own 2 bit
+    // multiplier (logical 0 - 1, physical 1 - 8) in feature report 3
+    fn mouse() -> Parser {
+        DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x02)
+            .collection(CollectionType::Application)
+            .usage(0x01)
+            .collection(CollectionType::Physical)
+            .usage(0x01)
+            .collection(CollectionType::Logical)
+            .report_id(3)
+            .usage(0x48)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .physical_minimum(1)
+            .physical_maximum(8)
+            .report_size(2)
+            .report_count(1)
+            .feature(FEATURE)
+            .report_id(2)
+            .usage(0x38)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .physical_minimum(0)
+            .physical_maximum(0)
+            .report_size(8)
+            .input(RELATIVE)
+            .end_collection()
+            .usage(0x01)
+            .collection(CollectionType::Logical)
+            .report_id(3)
+            .usage(0x48)
+            .logical_minimum(0)
+            .logical_maximum(1)
+            .physical_minimum(1)
+            .physical_maximum(8)
+            .report_size(2)
+            .feature(FEATURE)
+            .report_size(4)
+            .feature(FeatureItemData { data: 0x01 })
+            .report_id(2)
+            .usage_page(0x0c)
+            .usage(0x238)
+            .logical_minimum(-127)
+            .logical_maximum(127)
+            .physical_minimum(0)
+            .physical_maximum(0)
+            .report_size(8)
+            .input(RELATIVE)
+            .end_collection()
+            .end_collection()
+            .end_collection()
+            .build()
+            .decode()
+    }
+
+    #[test]
+    fn reads_and_sets_resolution_multipliers() {
+        let parser = mouse();
+        assert_eq!(
+            parser.scroll_axes(),
+            vec![ScrollAxis::Vertical, ScrollAxis::Horizontal]
+        );
+
+        let multipliers = parser.resolution_multipliers();
+        assert_eq!(multipliers.len(), 2);
+        assert_eq!(multipliers[0].axes, vec![ScrollAxis::Vertical]);
+        assert_eq!(multipliers[1].axes, vec![ScrollAxis::Horizontal]);
+        assert_eq!(multipliers[0].report_id(), Some(3));
+
+        // both multipliers set, the constant padding bits are kept
+        let mut feature = [0x03, 0xf0];
+        assert!(multipliers[0].write(&mut feature, 1));
+        assert!(multipliers[1].write(&mut feature, 1));
+        assert!(!multipliers[1].write(&mut feature, 2));
+        assert_eq!(feature, [0x03, 0xf5]);
+
+        assert_eq!(multipliers[1].read(&feature), Some(1));
+        assert_eq!(multipliers[1].factor(1), 8.0);
+        assert_eq!(multipliers[1].factor(0), 1.0);
+
+        // 3 steps down, 1 step left
+        assert_eq!(
+            parser.scroll(&[0x02, 0xfd, 0xff]),
+            vec![(ScrollAxis::Vertical, -3), (ScrollAxis::Horizontal, -1)]
+        );
+        assert!(parser.scroll(&[0x02, 0x00, 0x00]).is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+//! Simulation controls: wheels, pedals, yokes and throttles
+//!
+//! Sim hardware names its axes with usages of the Simulation Controls page rather than the
+//! generic X, Y and Z. Axes resting in the middle (steering, rudder, aileron, elevator) are shown
+//! from -100% to 100%, the others (pedals, throttles, brakes) from 0 to 100% of their range.
+
+use std::fmt::Display;
+
+use super::{
+    input::InputValue,
+    report::ReportKind,
+    usage::{self, SIMULATION},
+    Parser,
+};
+
+/// Simulation Controls usage
+pub const AILERON: u16 = 0xb0;
+/// Simulation Controls usage
+pub const ANTI_TORQUE_CONTROL: u16 = 0xb2;
+/// Simulation Controls usage
+pub const ELEVATOR: u16 = 0xb8;
+/// Simulation Controls usage
+pub const RUDDER: u16 = 0xba;
+/// Simulation Controls usage
+pub const STEERING: u16 = 0xc8;
+/// Simulation Controls usage
+pub const TURRET_DIRECTION: u16 = 0xc9;
+/// Simulation Controls usage
+pub const HANDLE_BARS: u16 = 0xce;
+
+/// Axes resting in the middle of their range
+pub fn is_centered(id: u16) -> bool {
+    matches!(
+        id,
+        AILERON
+            | ANTI_TORQUE_CONTROL
+            | ELEVATOR
+            | RUDDER
+            | STEERING
+            | TURRET_DIRECTION
+            | HANDLE_BARS
+    )
+}
+
+/// Position of a simulation axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimAxis {
+    /// On the Simulation Controls page
+    pub id: u16,
+    /// -1 - 1 for centered axes, 0 - 1 for the others
+    pub position: f32,
+}
+
+impl Display for SimAxis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:.0}%",
+            usage::short_name((SIMULATION, self.id)),
+            self.position * 100.0
+        )
+    }
+}
+
+/// e.g. `Steering -50%, Accelerator 100%, Brake 0%`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimState {
+    /// Axes in descriptor order
+    pub axes: Vec<SimAxis>,
+}
+
+impl Display for SimState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let axes: Vec<_> = self.axes.iter().map(|axis| axis.to_string()).collect();
+
+        write!(f, "{}", axes.join(", "))
+    }
+}
+
+impl Parser {
+    /// Whether the descriptor has inputs on the Simulation Controls page
+    pub fn has_simulation_controls(&self) -> bool {
+        self.reports().iter().any(|report| {
+            report.report_type.kind() == ReportKind::Input
+                && report.usages.iter().any(|usage| usage.0 == SIMULATION)
+        })
+    }
+
+    /// Simulation axes in an input report (starting with the report ID, if used), None for
+    /// reports without any
+    pub fn simulation(&self, bytes: &[u8]) -> Option<SimState> {
+        let mut axes = vec![];
+
+        for report in self.reports() {
+            if report.report_type.kind() != ReportKind::Input {
+                continue;
+            }
+
+            let (min, max) = (report.logical_minimum as f32, report.logical_maximum as f32);
+            if max <= min {
+                continue;
+            }
+
+            for input in report.parse(bytes).unwrap_or_default() {
+                let value = match (input.usage, input.value) {
+                    ((SIMULATION, _), InputValue::UInt(v)) => v as f32,
+                    ((SIMULATION, _), InputValue::Int(v)) => v as f32,
+                    _ => continue,
+                };
+
+                let fraction = (value.clamp(min, max) - min) / (max - min);
+                let position = match is_centered(input.usage.1) {
+                    true => fraction * 2.0 - 1.0,
+                    false => fraction,
+                };
+                axes.push(SimAxis {
+                    id: input.usage.1,
+                    position,
+                });
+            }
+        }
+
+        (!axes.is_empty()).then_some(SimState { axes })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollectionType, DescriptorBuilder, InputItemData};
+
+    #[test]
+    fn shows_axes_as_positions() {
+        // yoke with aileron and elevator, a throttle lever and toe brakes
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x02)
+            .usage(0x24)
+            .collection(CollectionType::Application)
+            .usage(0xb0)
+            .usage(0xb8)
+            .logical_minimum(-512)
+            .logical_maximum(511)
+            .report_size(16)
+            .report_count(2)
+            .input(InputItemData { data: 0x02 })
+            .usage(0xbb)
+            .usage(0xbf)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .report_size(8)
+            .report_count(2)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+
+        assert!(parser.has_simulation_controls());
+        let state = parser
+            .simulation(&[0x00, 0xff, 0xff, 0x01, 0xff, 0x00])
+            .unwrap();
+        assert_eq!(
+            state.to_string(),
+            "Aileron -50%, Elevator 100%, Throttle 100%, Toe Brake 0%"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! How strictly descriptors are held to the HID specification while decoding
+//!
+//! Real devices ship with all kinds of broken descriptors and hosts cope with most of them, so
+//! a parser debugging a device wants to show as much as it can, while a check of a descriptor
+//! about to ship wants to fail on anything a host could reject. The profiles:
+//!
+//! - strict: every problem is an error, including reserved items and unterminated collections
+//! - spec: whatever the specification requires is an error, reserved items are skipped as the
+//!   specification asks parsers to
+//! - permissive: nothing is an error, missing items default to zero (like the Linux state
+//!   table), swapped logical extents are swapped back and collections are closed or opened as
+//!   needed
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+
+/// How strictly to decode, see the module documentation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Strictness {
+    /// Every problem is an error
+    Strict,
+    /// Whatever the specification requires is an error
+    #[default]
+    Spec,
+    /// Nothing is an error
+    Permissive,
+}
+
+impl Strictness {
+    /// All profiles, strictest first
+    pub const ALL: [Strictness; 3] = [Strictness::Strict, Strictness::Spec, Strictness::Permissive];
+
+    /// Name as given on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strictness::Strict => "strict",
+            Strictness::Spec => "spec",
+            Strictness::Permissive => "permissive",
+        }
+    }
+
+    /// Whether decoding carries on past the problem
+    pub fn tolerates(&self, problem: &Problem) -> bool {
+        match self {
+            Strictness::Strict => false,
+            Strictness::Spec => matches!(problem, Problem::ReservedItem),
+            Strictness::Permissive => true,
+        }
+    }
+}
+
+impl FromStr for Strictness {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Strictness::ALL
+            .into_iter()
+            .find(|strictness| strictness.name() == s)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown strictness '{}', expected strict, spec or permissive",
+                    s
+                )
+            })
+    }
+}
+
+impl Display for Strictness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Something wrong with a descriptor, tolerated or not depending on the strictness
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// e.g. "report size", before a main item
+    MissingItem(&'static str),
+    /// Usage item without a usage page
+    MissingUsagePage,
+    /// Number of usages, collections need exactly one
+    CollectionUsages(usize),
+    /// Item with a reserved tag
+    ReservedItem,
+    /// Item the parser doesn't support, e.g. "Push"
+    UnsupportedItem(&'static str),
+    /// Minimum above maximum
+    LogicalExtents(i32, i32),
+    /// Report ID item with ID 0
+    ReportIdZero,
+    /// Main item before any collection
+    OutsideCollection,
+    /// Main item laying its report out past 2^32 bits, Report Size times Report Count is out of
+    /// range
+    ReportTooLong,
+    /// End Collection without an open collection
+    UnopenedCollection,
+    /// Collection without an End Collection
+    UnterminatedCollection,
+    /// Descriptor without any collection
+    NoCollection,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingItem(item) => write!(f, "Missing {} for main item", item),
+            Problem::MissingUsagePage => write!(f, "Missing usage page"),
+            Problem::CollectionUsages(count) => {
+                write!(f, "Collection with {} usages instead of one", count)
+            }
+            Problem::ReservedItem => write!(f, "Reserved item"),
+            Problem::UnsupportedItem(item) => write!(f, "{} is not supported", item),
+            Problem::LogicalExtents(minimum, maximum) => write!(
+                f,
+                "Logical minimum {} is above the maximum {}",
+                minimum, maximum
+            ),
+            Problem::ReportIdZero => write!(f, "Report ID 0 is reserved"),
+            Problem::OutsideCollection => write!(f, "Main item outside of any collection"),
+            Problem::ReportTooLong => write!(f, "Report longer than 2^32 bits"),
+            Problem::UnopenedCollection => write!(f, "End collection without a collection"),
+            Problem::UnterminatedCollection => write!(f, "Unterminated collection"),
+            Problem::NoCollection => write!(f, "No collection found"),
+        }
+    }
+}
+
+/// A problem the strictness doesn't tolerate, at the offset of its item in the descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Of the item in the descriptor
+    pub offset: usize,
+    /// What is wrong
+    pub problem: Problem,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at byte {})", self.problem, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(test)]
+mod test {
+    use super::{Problem, Strictness};
+
+    #[test]
+    fn parses_profile_names() {
+        for strictness in Strictness::ALL {
+            assert_eq!(strictness.name().parse::<Strictness>().unwrap(), strictness);
+        }
+        assert!("lax".parse::<Strictness>().is_err());
+        assert_eq!(Strictness::default(), Strictness::Spec);
+
+        assert!(Strictness::Spec.tolerates(&Problem::ReservedItem));
+        assert!(!Strictness::Spec.tolerates(&Problem::MissingUsagePage));
+        assert!(!Strictness::Strict.tolerates(&Problem::ReservedItem));
+    }
+}
@@ -0,0 +1,261 @@
+//! Units of measurement declared with the Unit and Unit Exponent items
+//!
+//! HID 1.11, section 6.2.2.7: the Unit item packs a system of measurement and the exponents of
+//! length, mass, time, temperature, current and luminous intensity into nibbles, e.g. 0x14 is
+//! centimeters and 0xf011 centimeters per second. Values are scaled to the physical range and
+//! then by 10 to the power of the Unit Exponent.
+
+use std::fmt::Display;
+
+use super::{input::InputValue, report::Report};
+
+/// System of measurement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum System {
+    /// SI units of length
+    SiLinear,
+    /// SI units, with angles in radians
+    SiRotation,
+    /// English units of length
+    EnglishLinear,
+    /// English units, with angles in degrees
+    EnglishRotation,
+}
+
+/// A unit, as a system and the exponents of its base units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Unit {
+    /// System of measurement
+    pub system: System,
+    /// Length, mass, time, temperature, current, luminous intensity
+    pub exponents: [i8; 6],
+}
+
+impl Unit {
+    /// None for no unit, vendor defined systems and units without any dimension
+    pub fn decode(unit: u32) -> Option<Self> {
+        let system = match unit & 0xf {
+            1 => System::SiLinear,
+            2 => System::SiRotation,
+            3 => System::EnglishLinear,
+            4 => System::EnglishRotation,
+            _ => return None,
+        };
+
+        let mut exponents = [0; 6];
+        for (i, exponent) in exponents.iter_mut().enumerate() {
+            *exponent = nibble((unit >> (4 * (i + 1))) & 0xf);
+        }
+
+        exponents
+            .iter()
+            .any(|e| *e != 0)
+            .then_some(Self { system, exponents })
+    }
+
+    fn symbols(&self) -> [&'static str; 6] {
+        let (length, mass, temperature) = match self.system {
+            System::SiLinear => ("cm", "g", "K"),
+            System::SiRotation => ("rad", "g", "K"),
+            System::EnglishLinear => ("in", "slug", "F"),
+            System::EnglishRotation => ("deg", "slug", "F"),
+        };
+
+        [length, mass, "s", temperature, "A", "cd"]
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let power = |symbol: &str, exponent: i8| match exponent.abs() {
+            1 => symbol.to_string(),
+            e => format!("{}^{}", symbol, e),
+        };
+
+        let symbols = self.symbols();
+        let (mut numerator, mut denominator) = (vec![], vec![]);
+        for (symbol, &exponent) in symbols.iter().zip(&self.exponents) {
+            match exponent {
+                0 => (),
+                e if e > 0 => numerator.push(power(symbol, e)),
+                e => denominator.push(power(symbol, e)),
+            }
+        }
+
+        match numerator.is_empty() {
+            true => write!(f, "1")?,
+            false => write!(f, "{}", numerator.join("*"))?,
+        }
+        match denominator.len() {
+            0 => Ok(()),
+            1 => write!(f, "/{}", denominator[0]),
+            _ => write!(f, "/({})", denominator.join("*")),
+        }
+    }
+}
+
+/// The data of a Unit Exponent item as a power of 10. The spec gives it as a signed nibble, so
+/// 0x0f is 10^-1, not 10^15. Some descriptors use a whole signed byte or more instead, powers
+/// beyond an i8 are clamped, no physical value is that large or small.
+pub fn exponent(unit_exponent: u32) -> i8 {
+    match unit_exponent {
+        0..=0xf => nibble(unit_exponent),
+        0x10..=0xff => unit_exponent as u8 as i8,
+        0x100..=0xffff => (unit_exponent as u16 as i16).clamp(i8::MIN as i16, i8::MAX as i16) as i8,
+        _ => (unit_exponent as i32).clamp(i8::MIN as i32, i8::MAX as i32) as i8,
+    }
+}
+
+fn nibble(value: u32) -> i8 {
+    match value {
+        0..=7 => value as i8,
+        _ => value as i8 - 16,
+    }
+}
+
+/// A value in physical units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// In the unit
+    pub value: f64,
+    /// The unit
+    pub unit: Unit,
+}
+
+impl Measurement {
+    /// The physical value of an input of a report declaring a unit. Non-linear items don't say how
+    /// their values map to physical ones, they have none.
+    pub fn of(report: &Report, value: InputValue) -> Option<Self> {
+        let unit = Unit::decode(report.unit?)?;
+        if report.report_type.flags().non_linear() {
+            return None;
+        }
+        let logical = match value {
+            InputValue::UInt(v) => v as f64,
+            InputValue::Int(v) => v as f64,
+            _ => return None,
+        };
+
+        let (origin, resolution) = scale(report);
+        let physical = origin + (logical - report.logical_minimum as f64) * resolution;
+
+        // dividing keeps e.g. 125 * 10^-1 exact
+        let exponent = report.unit_exponent.unwrap_or(0) as i32;
+        let value = match exponent {
+            e if e >= 0 => physical * 10f64.powi(e),
+            e => physical / 10f64.powi(-e),
+        };
+
+        Some(Self { value, unit })
+    }
+}
+
+/// Physical units per logical count of a report declaring a unit, e.g. to convert a change of a
+/// value, None where `Measurement::of` has no physical values
+pub fn resolution(report: &Report) -> Option<f64> {
+    Unit::decode(report.unit?)?;
+    if report.report_type.flags().non_linear() {
+        return None;
+    }
+
+    let exponent = report.unit_exponent.unwrap_or(0) as i32;
+    Some(scale(report).1 * 10f64.powi(exponent))
+}
+
+// The physical value at the logical minimum and per logical count, before the unit exponent
+fn scale(report: &Report) -> (f64, f64) {
+    // HID 1.11, section 6.2.2.7: without a physical range the logical one applies
+    let (min, max) = (report.logical_minimum, report.logical_maximum);
+    match (report.physical_minimum, report.physical_maximum) {
+        (0, 0) => (min as f64, 1.0),
+        (pmin, pmax) if (pmin, pmax) == (min, max) || max <= min => (min as f64, 1.0),
+        (pmin, pmax) => (pmin as f64, (pmax - pmin) as f64 / (max - min) as f64),
+    }
+}
+
+impl Display for Measurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let value = format!("{:.4}", self.value);
+        let value = value.trim_end_matches('0').trim_end_matches('.');
+
+        write!(f, "{} {}", value, self.unit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{CollectionType, DescriptorBuilder, InputItemData, InputValue};
+
+    use super::{exponent, Measurement, System, Unit};
+
+    #[test]
+    fn decodes_units() {
+        assert_eq!(Unit::decode(0), None);
+        assert_eq!(Unit::decode(0x1), None, "a system without dimensions");
+        assert_eq!(
+            Unit::decode(0xf011),
+            Some(Unit {
+                system: System::SiLinear,
+                exponents: [1, 0, -1, 0, 0, 0]
+            })
+        );
+
+        let names = [
+            (0x11, "cm"),
+            (0x14, "deg"),
+            (0xf011, "cm/s"),
+            (0xe112, "rad*g/s^2"),
+            (0x1001, "s"),
+            (0xf001, "1/s"),
+            (0x00f0e101, "g/(s^2*A)"),
+        ];
+        for (unit, name) in names {
+            assert_eq!(Unit::decode(unit).unwrap().to_string(), name);
+        }
+
+        assert_eq!(exponent(0x0e), -2);
+        assert_eq!(exponent(0x03), 3);
+        assert_eq!(exponent(0xfe), -2);
+        assert_eq!(exponent(0xfffe), -2);
+        assert_eq!(exponent(0x7fff), 127);
+    }
+
+    #[test]
+    fn measures_physical_values() {
+        let parser = DescriptorBuilder::new()
+            .usage_page(0x01)
+            .usage(0x04)
+            .collection(CollectionType::Application)
+            .usage(0x30)
+            .logical_minimum(0)
+            .logical_maximum(255)
+            .physical_minimum(0)
+            .physical_maximum(1275)
+            .unit(0xf011)
+            .unit_exponent(0x0f)
+            .report_size(8)
+            .report_count(1)
+            .input(InputItemData { data: 0x02 })
+            .usage(0x35)
+            .logical_minimum(0)
+            .logical_maximum(359)
+            .physical_minimum(0)
+            .physical_maximum(359)
+            .unit(0x14)
+            .unit_exponent(0)
+            .report_size(16)
+            .input(InputItemData { data: 0x02 })
+            .end_collection()
+            .build()
+            .decode();
+        let reports = parser.reports();
+
+        let speed = Measurement::of(reports[0], InputValue::UInt(25)).unwrap();
+        assert_eq!(speed.to_string(), "12.5 cm/s");
+
+        let angle = Measurement::of(reports[1], InputValue::UInt(315)).unwrap();
+        assert_eq!(angle.to_string(), "315 deg");
+
+        assert_eq!(Measurement::of(reports[1], InputValue::Bool(true)), None);
+    }
+}
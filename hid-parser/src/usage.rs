@@ -0,0 +1,479 @@
+//! Usage page and usage names, HID Usage Tables 1.4
+//!
+//! Only the commonly used parts of the tables are covered, unknown usages are shown by number.
+
+/// Generic Desktop page
+pub const GENERIC_DESKTOP: u16 = 0x01;
+/// Simulation Controls page
+pub const SIMULATION: u16 = 0x02;
+/// Button page
+pub const BUTTON: u16 = 0x09;
+/// Telephony Device page
+pub const TELEPHONY: u16 = 0x0b;
+/// Consumer page
+pub const CONSUMER: u16 = 0x0c;
+/// Haptics page
+pub const HAPTICS: u16 = 0x0e;
+
+/// Name of a usage page, None for pages not covered
+pub fn page_name(page: u16) -> Option<&'static str> {
+    let name = match page {
+        0x01 => "Generic Desktop",
+        0x02 => "Simulation Controls",
+        0x03 => "VR Controls",
+        0x04 => "Sport Controls",
+        0x05 => "Game Controls",
+        0x06 => "Generic Device Controls",
+        0x07 => "Keyboard/Keypad",
+        0x08 => "LED",
+        0x09 => "Button",
+        0x0a => "Ordinal",
+        0x0b => "Telephony",
+        0x0c => "Consumer",
+        0x0d => "Digitizers",
+        0x0e => "Haptics",
+        0x0f => "Physical Input Device",
+        0x10 => "Unicode",
+        0x12 => "Eye and Head Trackers",
+        0x14 => "Auxiliary Display",
+        0x20 => "Sensors",
+        0x40 => "Medical Instrument",
+        0x41 => "Braille Display",
+        0x59 => "Lighting and Illumination",
+        0x80 => "Monitor",
+        0x84 => "Power",
+        0x85 => "Battery System",
+        0x8c => "Barcode Scanner",
+        0x8d => "Scales",
+        0x8e => "Magnetic Stripe Reader",
+        0x90 => "Camera Control",
+        0x91 => "Arcade",
+        0x92 => "Gaming Device",
+        0xf1d0 => "FIDO Alliance",
+        0xff00..=0xffff => "Vendor Defined",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+/// Name of a usage, None for usages not covered
+pub fn usage_name(usage: (u16, u16)) -> Option<String> {
+    let (page, id) = usage;
+
+    match page {
+        GENERIC_DESKTOP => generic_desktop(id).map(str::to_string),
+        SIMULATION => simulation(id).map(str::to_string),
+        BUTTON if id == 0 => Some("No Button Pressed".to_string()),
+        BUTTON => Some(format!("Button {}", id)),
+        TELEPHONY => telephony(id),
+        CONSUMER => consumer(id).map(str::to_string),
+        0x0d if id == 0x21 => Some("Puck".to_string()),
+        HAPTICS => haptics(id).map(str::to_string),
+        _ => None,
+    }
+}
+
+/// Human readable usage, e.g. "Consumer / Volume Increment", falling back to numbers
+pub fn describe(usage: (u16, u16)) -> String {
+    match (page_name(usage.0), usage_name(usage)) {
+        (Some(page), Some(name)) => format!("{} / {}", page, name),
+        (Some(page), None) => format!("{} / {:#04x}", page, usage.1),
+        (None, _) => format!("{:#06x} / {:#04x}", usage.0, usage.1),
+    }
+}
+
+/// Usage name if known, the full description otherwise
+pub fn short_name(usage: (u16, u16)) -> String {
+    usage_name(usage).unwrap_or_else(|| describe(usage))
+}
+
+fn generic_desktop(id: u16) -> Option<&'static str> {
+    let name = match id {
+        0x01 => "Pointer",
+        0x02 => "Mouse",
+        0x04 => "Joystick",
+        0x05 => "Gamepad",
+        0x06 => "Keyboard",
+        0x07 => "Keypad",
+        0x08 => "Multi-axis Controller",
+        0x09 => "Tablet PC System Controls",
+        0x0e => "System Multi-Axis Controller",
+        0x30 => "X",
+        0x31 => "Y",
+        0x32 => "Z",
+        0x33 => "Rx",
+        0x34 => "Ry",
+        0x35 => "Rz",
+        0x36 => "Slider",
+        0x37 => "Dial",
+        0x38 => "Wheel",
+        0x39 => "Hat Switch",
+        0x3a => "Counted Buffer",
+        0x3b => "Byte Count",
+        0x3c => "Motion Wakeup",
+        0x3d => "Start",
+        0x3e => "Select",
+        0x40 => "Vx",
+        0x41 => "Vy",
+        0x42 => "Vz",
+        0x43 => "Vbrx",
+        0x44 => "Vbry",
+        0x45 => "Vbrz",
+        0x46 => "Vno",
+        0x47 => "Feature Notification",
+        0x48 => "Resolution Multiplier",
+        0x80 => "System Control",
+        0x81 => "System Power Down",
+        0x82 => "System Sleep",
+        0x83 => "System Wake Up",
+        0x84 => "System Context Menu",
+        0x85 => "System Main Menu",
+        0x86 => "System App Menu",
+        0x87 => "System Menu Help",
+        0x88 => "System Menu Exit",
+        0x89 => "System Menu Select",
+        0x8a => "System Menu Right",
+        0x8b => "System Menu Left",
+        0x8c => "System Menu Up",
+        0x8d => "System Menu Down",
+        0x8e => "System Cold Restart",
+        0x8f => "System Warm Restart",
+        0x90 => "D-pad Up",
+        0x91 => "D-pad Down",
+        0x92 => "D-pad Right",
+        0x93 => "D-pad Left",
+        0xa0 => "System Dock",
+        0xa1 => "System Undock",
+        0xa2 => "System Setup",
+        0xa3 => "System Break",
+        0xa4 => "System Debugger Break",
+        0xa5 => "Application Break",
+        0xa6 => "Application Debugger Break",
+        0xa7 => "System Speaker Mute",
+        0xa8 => "System Hibernate",
+        0xb0 => "System Display Invert",
+        0xb1 => "System Display Internal",
+        0xb2 => "System Display External",
+        0xb3 => "System Display Both",
+        0xb4 => "System Display Dual",
+        0xb5 => "System Display Toggle Int/Ext",
+        0xb6 => "System Display Swap Primary/Secondary",
+        0xb7 => "System Display Toggle LCD Autoscale",
+        0xc9 => "System Microphone Mute",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+fn simulation(id: u16) -> Option<&'static str> {
+    let name = match id {
+        0x01 => "Flight Simulation Device",
+        0x02 => "Automobile Simulation Device",
+        0x03 => "Tank Simulation Device",
+        0x04 => "Spaceship Simulation Device",
+        0x05 => "Submarine Simulation Device",
+        0x06 => "Sailing Simulation Device",
+        0x07 => "Motorcycle Simulation Device",
+        0x08 => "Sports Simulation Device",
+        0x09 => "Airplane Simulation Device",
+        0x0a => "Helicopter Simulation Device",
+        0x0b => "Magic Carpet Simulation Device",
+        0x0c => "Bicycle Simulation Device",
+        0x20 => "Flight Control Stick",
+        0x21 => "Flight Stick",
+        0x22 => "Cyclic Control",
+        0x23 => "Cyclic Trim",
+        0x24 => "Flight Yoke",
+        0x25 => "Track Control",
+        0xb0 => "Aileron",
+        0xb1 => "Aileron Trim",
+        0xb2 => "Anti-Torque Control",
+        0xb3 => "Autopilot Enable",
+        0xb4 => "Chaff Release",
+        0xb5 => "Collective Control",
+        0xb6 => "Dive Brake",
+        0xb7 => "Electronic Countermeasures",
+        0xb8 => "Elevator",
+        0xb9 => "Elevator Trim",
+        0xba => "Rudder",
+        0xbb => "Throttle",
+        0xbc => "Flight Communications",
+        0xbd => "Flare Release",
+        0xbe => "Landing Gear",
+        0xbf => "Toe Brake",
+        0xc0 => "Trigger",
+        0xc1 => "Weapons Arm",
+        0xc2 => "Weapons Select",
+        0xc3 => "Wing Flaps",
+        0xc4 => "Accelerator",
+        0xc5 => "Brake",
+        0xc6 => "Clutch",
+        0xc7 => "Shifter",
+        0xc8 => "Steering",
+        0xc9 => "Turret Direction",
+        0xca => "Barrel Elevation",
+        0xcb => "Dive Plane",
+        0xcc => "Ballast",
+        0xcd => "Bicycle Crank",
+        0xce => "Handle Bars",
+        0xcf => "Front Brake",
+        0xd0 => "Rear Brake",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+fn telephony(id: u16) -> Option<String> {
+    let name = match id {
+        0x01 => "Phone",
+        0x02 => "Answering Machine",
+        0x03 => "Message Controls",
+        0x04 => "Handset",
+        0x05 => "Headset",
+        0x06 => "Telephony Key Pad",
+        0x07 => "Programmable Button",
+        0x20 => "Hook Switch",
+        0x21 => "Flash",
+        0x22 => "Feature",
+        0x23 => "Hold",
+        0x24 => "Redial",
+        0x25 => "Transfer",
+        0x26 => "Drop",
+        0x27 => "Park",
+        0x28 => "Forward Calls",
+        0x29 => "Alternate Function",
+        0x2a => "Line",
+        0x2b => "Speaker Phone",
+        0x2c => "Conference",
+        0x2d => "Ring Enable",
+        0x2e => "Ring Select",
+        0x2f => "Phone Mute",
+        0x30 => "Caller ID",
+        0x31 => "Send",
+        0x50 => "Speed Dial",
+        0x51 => "Store Number",
+        0x52 => "Recall Number",
+        0x53 => "Phone Directory",
+        0x70 => "Voice Mail",
+        0x71 => "Screen Calls",
+        0x72 => "Do Not Disturb",
+        0x73 => "Message",
+        0x74 => "Answer On/Off",
+        0x90 => "Inside Dial Tone",
+        0x91 => "Outside Dial Tone",
+        0x92 => "Inside Ring Tone",
+        0x93 => "Outside Ring Tone",
+        0x94 => "Priority Ring Tone",
+        0x95 => "Inside Ringback",
+        0x96 => "Priority Ringback",
+        0x97 => "Line Busy Tone",
+        0x98 => "Reorder Tone",
+        0x99 => "Call Waiting Tone",
+        0x9a => "Confirmation Tone 1",
+        0x9b => "Confirmation Tone 2",
+        0x9c => "Tones Off",
+        0x9d => "Outside Ringback",
+        0x9e => "Ringer",
+        // Phone Key 0 - 9, *, #, A - D
+        0xb0..=0xb9 => return Some(format!("Phone Key {}", id - 0xb0)),
+        0xba => "Phone Key Star",
+        0xbb => "Phone Key Pound",
+        0xbc => "Phone Key A",
+        0xbd => "Phone Key B",
+        0xbe => "Phone Key C",
+        0xbf => "Phone Key D",
+        _ => return None,
+    };
+
+    Some(name.to_string())
+}
+
+fn haptics(id: u16) -> Option<&'static str> {
+    let name = match id {
+        0x01 => "Simple Haptic Controller",
+        0x10 => "Waveform List",
+        0x11 => "Duration List",
+        0x20 => "Auto Trigger",
+        0x21 => "Manual Trigger",
+        0x22 => "Auto Trigger Associated Control",
+        0x23 => "Intensity",
+        0x24 => "Repeat Count",
+        0x25 => "Retrigger Period",
+        0x26 => "Waveform Vendor Page",
+        0x27 => "Waveform Vendor ID",
+        0x28 => "Waveform Cutoff Time",
+        0x1001 => "Waveform None",
+        0x1002 => "Waveform Stop",
+        0x1003 => "Waveform Click",
+        0x1004 => "Waveform Buzz Continuous",
+        0x1005 => "Waveform Rumble Continuous",
+        0x1006 => "Waveform Press",
+        0x1007 => "Waveform Release",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+fn consumer(id: u16) -> Option<&'static str> {
+    let name = match id {
+        0x00 => "Unassigned",
+        0x01 => "Consumer Control",
+        0x02 => "Numeric Key Pad",
+        0x03 => "Programmable Buttons",
+        0x04 => "Microphone",
+        0x05 => "Headphone",
+        0x06 => "Graphic Equalizer",
+        0x20 => "+10",
+        0x21 => "+100",
+        0x22 => "AM/PM",
+        0x30 => "Power",
+        0x31 => "Reset",
+        0x32 => "Sleep",
+        0x33 => "Sleep After",
+        0x34 => "Sleep Mode",
+        0x35 => "Illumination",
+        0x36 => "Function Buttons",
+        0x40 => "Menu",
+        0x41 => "Menu Pick",
+        0x42 => "Menu Up",
+        0x43 => "Menu Down",
+        0x44 => "Menu Left",
+        0x45 => "Menu Right",
+        0x46 => "Menu Escape",
+        0x47 => "Menu Value Increase",
+        0x48 => "Menu Value Decrease",
+        0x60 => "Data On Screen",
+        0x61 => "Closed Caption",
+        0x63 => "VCR/TV",
+        0x65 => "Snapshot",
+        0x6f => "Display Brightness Increment",
+        0x70 => "Display Brightness Decrement",
+        0x72 => "Display Backlight Toggle",
+        0x80 => "Selection",
+        0x81 => "Assign Selection",
+        0x82 => "Mode Step",
+        0x83 => "Recall Last",
+        0x89 => "Media Select TV",
+        0x8d => "Media Select Program Guide",
+        0x9c => "Channel Increment",
+        0x9d => "Channel Decrement",
+        0xb0 => "Play",
+        0xb1 => "Pause",
+        0xb2 => "Record",
+        0xb3 => "Fast Forward",
+        0xb4 => "Rewind",
+        0xb5 => "Scan Next Track",
+        0xb6 => "Scan Previous Track",
+        0xb7 => "Stop",
+        0xb8 => "Eject",
+        0xb9 => "Random Play",
+        0xbc => "Repeat",
+        0xcd => "Play/Pause",
+        0xce => "Play/Skip",
+        0xcf => "Voice Command",
+        0xe0 => "Volume",
+        0xe1 => "Balance",
+        0xe2 => "Mute",
+        0xe3 => "Bass",
+        0xe4 => "Treble",
+        0xe5 => "Bass Boost",
+        0xe9 => "Volume Increment",
+        0xea => "Volume Decrement",
+        0x183 => "AL Consumer Control Configuration",
+        0x184 => "AL Word Processor",
+        0x186 => "AL Spreadsheet",
+        0x18a => "AL Email Reader",
+        0x18e => "AL Calendar/Schedule",
+        0x192 => "AL Calculator",
+        0x194 => "AL Local Machine Browser",
+        0x196 => "AL Internet Browser",
+        0x19e => "AL Terminal Lock/Screensaver",
+        0x1a7 => "AL Documents",
+        0x1ae => "AL Keyboard Layout",
+        0x1b6 => "AL Image Browser",
+        0x1b7 => "AL Audio Browser",
+        0x1b8 => "AL Movie Browser",
+        0x201 => "AC New",
+        0x202 => "AC Open",
+        0x203 => "AC Close",
+        0x204 => "AC Exit",
+        0x207 => "AC Save",
+        0x208 => "AC Print",
+        0x21a => "AC Undo",
+        0x21b => "AC Copy",
+        0x21c => "AC Cut",
+        0x21d => "AC Paste",
+        0x21f => "AC Find",
+        0x221 => "AC Search",
+        0x223 => "AC Home",
+        0x224 => "AC Back",
+        0x225 => "AC Forward",
+        0x226 => "AC Stop",
+        0x227 => "AC Refresh",
+        0x22a => "AC Bookmarks",
+        0x22d => "AC Zoom In",
+        0x22e => "AC Zoom Out",
+        0x22f => "AC Zoom",
+        0x232 => "AC View Toggle",
+        0x233 => "AC Scroll Up",
+        0x234 => "AC Scroll Down",
+        0x238 => "AC Pan",
+        0x279 => "AC Redo/Repeat",
+        0x29d => "AC Desktop Show All Windows",
+        0x29f => "AC Desktop Show All Applications",
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe, usage_name};
+
+    #[test]
+    fn names_consumer_usages() {
+        assert_eq!(
+            usage_name((0x0c, 0xe9)),
+            Some("Volume Increment".to_string())
+        );
+        assert_eq!(usage_name((0x0c, 0xcd)), Some("Play/Pause".to_string()));
+        assert_eq!(usage_name((0x0c, 0x224)), Some("AC Back".to_string()));
+        assert_eq!(usage_name((0x0c, 0x3ff)), None);
+    }
+
+    #[test]
+    fn names_system_control_and_telephony_usages() {
+        assert_eq!(usage_name((0x01, 0x82)), Some("System Sleep".to_string()));
+        assert_eq!(usage_name((0x0b, 0x20)), Some("Hook Switch".to_string()));
+        assert_eq!(usage_name((0x0b, 0xb7)), Some("Phone Key 7".to_string()));
+        assert_eq!(usage_name((0x0b, 0xff)), None);
+    }
+
+    #[test]
+    fn names_radial_controller_usages() {
+        assert_eq!(
+            usage_name((0x01, 0x0e)),
+            Some("System Multi-Axis Controller".to_string())
+        );
+        assert_eq!(usage_name((0x0d, 0x21)), Some("Puck".to_string()));
+        assert_eq!(usage_name((0x0e, 0x21)), Some("Manual Trigger".to_string()));
+        assert_eq!(
+            usage_name((0x0e, 0x1003)),
+            Some("Waveform Click".to_string())
+        );
+    }
+
+    #[test]
+    fn describes_usages() {
+        assert_eq!(describe((0x01, 0x30)), "Generic Desktop / X");
+        assert_eq!(describe((0x09, 3)), "Button / Button 3");
+        assert_eq!(describe((0x0c, 0x3ff)), "Consumer / 0x3ff");
+        assert_eq!(describe((0xff00, 0x01)), "Vendor Defined / 0x01");
+        assert_eq!(describe((0x1234, 0x01)), "0x1234 / 0x01");
+    }
+}
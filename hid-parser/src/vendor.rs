@@ -0,0 +1,320 @@
+//! Vendor protocol decoders, layered above the generic parser
+//!
+//! Many devices tunnel proprietary protocols (Logitech HID++, Razer extensions, ...) through
+//! vendor defined reports, which the generic parser can only show as opaque bytes. A
+//! `VendorDecoder` recognises such a device and turns the raw payloads into named fields.
+
+use std::fmt::Display;
+
+use crate::{battery::Battery, Error};
+
+#[cfg(feature = "fido")]
+pub mod fido;
+pub mod keys;
+#[cfg(feature = "logitech")]
+pub mod logitech;
+
+/// Identifies the device (interface) a report came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// USB vendor ID
+    pub vendor_id: u16,
+    /// USB product ID
+    pub product_id: u16,
+    /// Usage page and usage of the top level collection
+    pub usage: (u16, u16),
+}
+
+/// Decodes the reports of a vendor protocol, keeping any state the protocol needs across reports
+pub trait VendorDecoder {
+    /// Short human readable name of the protocol, e.g. "HID++"
+    fn name(&self) -> &str;
+
+    /// Whether this decoder understands reports from the given device
+    fn matches(&self, device: &DeviceInfo) -> bool;
+
+    /// Decode a raw report (including the report ID byte, if any).
+    /// Returns None for reports the decoder does not recognise.
+    fn decode(&mut self, report: &[u8]) -> Option<DecodedReport>;
+
+    /// For receivers multiplexing several wireless devices, the index of the
+    /// paired device the report belongs to
+    fn device_index(&self, _report: &[u8]) -> Option<u8> {
+        None
+    }
+
+    /// Ask a receiver for its paired devices. Devices which are not receivers have none.
+    fn child_devices(&mut self, _transport: &mut dyn Transport) -> Result<Vec<ChildDevice>, Error> {
+        Ok(vec![])
+    }
+
+    /// Query the battery state using the vendor protocol. `device_index` selects
+    /// a device paired with a receiver.
+    fn battery(
+        &mut self,
+        _transport: &mut dyn Transport,
+        _device_index: Option<u8>,
+    ) -> Result<Option<Battery>, Error> {
+        Ok(None)
+    }
+}
+
+/// Report I/O with the device, provided by the application's USB backend
+pub trait Transport {
+    /// Write an output report, the first byte is the report ID
+    fn write(&mut self, report: &[u8]) -> Result<usize, Error>;
+
+    /// Read an input report, returns 0 on timeout
+    fn read_timeout(&mut self, buf: &mut [u8], timeout_ms: i32) -> Result<usize, Error>;
+}
+
+/// A device paired with a receiver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildDevice {
+    /// Index on the receiver
+    pub index: u8,
+    /// Wireless product ID
+    pub product_id: u16,
+    /// Kind of device, e.g. "Mouse"
+    pub kind: String,
+    /// Name, if the receiver knows it
+    pub name: Option<String>,
+}
+
+impl Display for ChildDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} [{:04X}] {}", self.index, self.product_id, self.kind)?;
+
+        if let Some(name) = &self.name {
+            write!(f, " \"{}\"", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A report decoded by a vendor decoder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedReport {
+    /// Name of the protocol, e.g. "HID++"
+    pub protocol: String,
+    /// Fields in report order
+    pub fields: Vec<DecodedField>,
+}
+
+/// A named value of a decoded report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedField {
+    /// Name of the field
+    pub name: String,
+    /// Value of the field
+    pub value: DecodedValue,
+}
+
+impl DecodedField {
+    /// A field with the given name
+    pub fn new(name: &str, value: DecodedValue) -> Self {
+        Self {
+            name: name.to_string(),
+            value,
+        }
+    }
+}
+
+/// Value of a decoded field
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedValue {
+    /// A flag
+    Bool(bool),
+    /// A number
+    UInt(u32),
+    /// A number shown in hex, e.g. an ID or an error code
+    Hex(u32),
+    /// A signed number
+    Int(i32),
+    /// Raw bytes
+    Bytes(Vec<u8>),
+    /// Text
+    Text(String),
+}
+
+impl Display for DecodedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodedValue::Bool(b) => write!(f, "{}", b),
+            DecodedValue::UInt(u) => write!(f, "{}", u),
+            DecodedValue::Hex(h) => write!(f, "{:#04x}", h),
+            DecodedValue::Int(i) => write!(f, "{}", i),
+            DecodedValue::Bytes(bytes) => write!(f, "{:02x?}", bytes),
+            DecodedValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl Display for DecodedField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.value)
+    }
+}
+
+impl Display for DecodedReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}({})",
+            self.protocol,
+            self.fields
+                .iter()
+                .map(|field| format!("{}", field))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// Set of decoders available to the application, populated at startup
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn VendorDecoder>>,
+}
+
+impl DecoderRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with all the decoders enabled by crate features
+    pub fn with_builtin() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = Self::new();
+
+        #[cfg(feature = "logitech")]
+        registry.register(Box::new(logitech::HidPlusPlus::new()));
+        #[cfg(feature = "fido")]
+        registry.register(Box::new(fido::CtapHid::new()));
+
+        registry
+    }
+
+    /// Registers a decoder after all the ones registered so far
+    pub fn register(&mut self, decoder: Box<dyn VendorDecoder>) {
+        self.decoders.push(decoder);
+    }
+
+    /// Registers a decoder ahead of all the ones registered so far, e.g. an out-of-tree plugin
+    /// overriding a built-in decoder for the same devices
+    pub fn register_first(&mut self, decoder: Box<dyn VendorDecoder>) {
+        self.decoders.insert(0, decoder);
+    }
+
+    /// Names of the registered decoders, in order
+    pub fn names(&self) -> Vec<&str> {
+        self.decoders.iter().map(|d| d.name()).collect()
+    }
+
+    /// First decoder matching the device. Decoders registered earlier win, so plugins
+    /// overriding the built-in ones are added with `register_first`.
+    pub fn find(&mut self, device: &DeviceInfo) -> Option<&mut dyn VendorDecoder> {
+        self.decoders
+            .iter_mut()
+            .find(|d| d.matches(device))
+            .map(|d| d.as_mut() as &mut dyn VendorDecoder)
+    }
+
+    /// Decodes a report with the first decoder matching the device
+    pub fn decode(&mut self, device: &DeviceInfo, report: &[u8]) -> Option<DecodedReport> {
+        self.find(device)?.decode(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::VendorDecoder;
+    use super::{DecodedField, DecodedReport, DecodedValue, DecoderRegistry, DeviceInfo};
+
+    struct Counter {
+        vendor_id: u16,
+        seen: u32,
+    }
+
+    impl VendorDecoder for Counter {
+        fn name(&self) -> &str {
+            "Counter"
+        }
+
+        fn matches(&self, device: &DeviceInfo) -> bool {
+            device.vendor_id == self.vendor_id
+        }
+
+        fn decode(&mut self, report: &[u8]) -> Option<DecodedReport> {
+            self.seen += 1;
+
+            Some(DecodedReport {
+                protocol: self.name().to_string(),
+                fields: vec![
+                    DecodedField::new("seen", DecodedValue::UInt(self.seen)),
+                    DecodedField::new("first", DecodedValue::UInt(*report.first()? as u32)),
+                ],
+            })
+        }
+    }
+
+    fn device(vendor_id: u16) -> DeviceInfo {
+        DeviceInfo {
+            vendor_id,
+            product_id: 0x0001,
+            usage: (0xff00, 0x01),
+        }
+    }
+
+    #[test]
+    fn decodes_with_matching_decoder() {
+        let mut registry = DecoderRegistry::new();
+        registry.register(Box::new(Counter {
+            vendor_id: 0x1234,
+            seen: 0,
+        }));
+
+        assert_eq!(registry.decode(&device(0x4321), &[1, 2]), None);
+
+        let decoded = registry.decode(&device(0x1234), &[1, 2]).unwrap();
+        assert_eq!(decoded.fields[1].value, DecodedValue::UInt(1));
+
+        // decoders keep state between reports
+        let decoded = registry.decode(&device(0x1234), &[3]).unwrap();
+        assert_eq!(format!("{}", decoded), "Counter(seen: 2, first: 3)");
+    }
+
+    #[test]
+    fn first_registered_decoder_wins() {
+        let mut registry = DecoderRegistry::new();
+        registry.register(Box::new(Counter {
+            vendor_id: 0x1234,
+            seen: 10,
+        }));
+        registry.register(Box::new(Counter {
+            vendor_id: 0x1234,
+            seen: 0,
+        }));
+
+        let decoded = registry.decode(&device(0x1234), &[1]).unwrap();
+        assert_eq!(decoded.fields[0].value, DecodedValue::UInt(11));
+    }
+
+    #[test]
+    fn decoder_registered_first_overrides_earlier_ones() {
+        let mut registry = DecoderRegistry::new();
+        registry.register(Box::new(Counter {
+            vendor_id: 0x1234,
+            seen: 10,
+        }));
+        registry.register_first(Box::new(Counter {
+            vendor_id: 0x1234,
+            seen: 0,
+        }));
+
+        let decoded = registry.decode(&device(0x1234), &[1]).unwrap();
+        assert_eq!(decoded.fields[0].value, DecodedValue::UInt(1));
+    }
+}
@@ -0,0 +1,252 @@
+// Compares the report layouts the parser decodes with hidreport's, an independent parser
+//
+// Every .bin file in tests/descriptors is a report descriptor, the .layout file next to it the
+// layout hidreport 0.6 lays out for it: each report with its length in bits (counting the
+// report ID byte) and the bits of its fields, one per value of variable items, one per array
+// or constant item. The layouts are checked in, so a change in either parser shows up as a
+// diff to review. `HID_REFERENCE_UPDATE=1 cargo test --test reference` writes them anew.
+//
+// The descriptors in the directory are the fixtures of the parser's unit tests and the ones
+// of the captures in tests/captures. Mutations of them (see `ReportDescriptor::mutations`)
+// are compared with hidreport directly, without their usages: the two assign the usages of
+// malformed usage ranges differently, but must agree on where every field is.
+
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use hid_parser::{lint, BasicItems, Parser, ReportDescriptor, ReportKind, Strictness};
+use hidreport::{Field, Report};
+use proptest::{prelude::*, test_runner::FileFailurePersistence};
+
+// Reports longer than the kernel accepts (16 KiB) aren't compared, hidreport allocates every
+// value of a variable item and overflows on such lengths
+const MAX_BITS: usize = 16 * 1024 * 8;
+
+fn descriptors() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/descriptors");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "bin"))
+        .collect();
+    paths.sort();
+
+    paths
+}
+
+fn heading(text: &mut String, kind: &str, report_id: Option<u8>, bits: usize) {
+    match report_id {
+        Some(id) => writeln!(text, "{} {}: {} bits", kind, id, bits).unwrap(),
+        None => writeln!(text, "{}: {} bits", kind, bits).unwrap(),
+    }
+}
+
+// The layout of the reports hidreport decodes, None for descriptors it rejects
+fn reference(bytes: &[u8], usages: bool) -> Option<String> {
+    let descriptor = hidreport::ReportDescriptor::try_from(bytes).ok()?;
+    let mut text = String::new();
+
+    reference_reports(&mut text, "input", descriptor.input_reports(), usages);
+    reference_reports(&mut text, "output", descriptor.output_reports(), usages);
+    reference_reports(&mut text, "feature", descriptor.feature_reports(), usages);
+
+    Some(text)
+}
+
+fn reference_reports(text: &mut String, kind: &str, reports: &[impl Report], usages: bool) {
+    for report in reports {
+        let report_id = report.report_id().map(u8::from);
+        heading(text, kind, report_id, report.size_in_bits());
+
+        for field in report.fields() {
+            let bits = field.bits();
+            match field {
+                Field::Variable(variable) if usages => writeln!(
+                    text,
+                    "  {}..{} {:04x}:{:04x}",
+                    bits.start,
+                    bits.end,
+                    u16::from(variable.usage.usage_page),
+                    u16::from(variable.usage.usage_id)
+                )
+                .unwrap(),
+                Field::Variable(_) => writeln!(text, "  {}..{}", bits.start, bits.end).unwrap(),
+                Field::Array(_) => writeln!(text, "  {}..{} array", bits.start, bits.end).unwrap(),
+                Field::Constant(_) => {
+                    writeln!(text, "  {}..{} constant", bits.start, bits.end).unwrap()
+                }
+            }
+        }
+    }
+}
+
+// The same layout from the parser's reports, in the order hidreport lists them: by kind, then
+// by report in the order each report ID first appears. Items without any bits aren't fields.
+fn layout(parser: &Parser, usages: bool) -> String {
+    let kinds = [
+        (ReportKind::Input, "input"),
+        (ReportKind::Output, "output"),
+        (ReportKind::Feature, "feature"),
+    ];
+    let mut text = String::new();
+
+    for (kind, name) in kinds {
+        let items: Vec<_> = parser
+            .reports()
+            .into_iter()
+            .filter(|report| report.report_type.kind() == kind && report.bit_length() > 0)
+            .collect();
+        let mut report_ids = vec![];
+        for item in &items {
+            if !report_ids.contains(&item.report_id) {
+                report_ids.push(item.report_id);
+            }
+        }
+
+        for report_id in report_ids {
+            let id_bits = if report_id.is_some() { 8 } else { 0 };
+            let items: Vec<_> = items
+                .iter()
+                .filter(|item| item.report_id == report_id)
+                .collect();
+            let bits = items
+                .iter()
+                .map(|item| id_bits + item.bit_offset + item.bit_length())
+                .max()
+                .unwrap_or(id_bits);
+            heading(&mut text, name, report_id, bits);
+
+            for item in items {
+                let start = id_bits + item.bit_offset;
+                let end = start + item.bit_length();
+                let flags = item.report_type.flags();
+                if flags.constant() {
+                    writeln!(text, "  {}..{} constant", start, end).unwrap();
+                    continue;
+                }
+                if flags.array() {
+                    writeln!(text, "  {}..{} array", start, end).unwrap();
+                    continue;
+                }
+
+                // the usages of the values as parsed from an empty report
+                let mut report = vec![0; end.div_ceil(8)];
+                if let Some(id) = report_id {
+                    report[0] = id;
+                }
+                let size = item.report_size as usize;
+                for (i, input) in item.parse(&report).unwrap().iter().enumerate() {
+                    let start = start + i * size;
+                    match usages {
+                        true => writeln!(
+                            text,
+                            "  {}..{} {:04x}:{:04x}",
+                            start,
+                            start + size,
+                            input.usage.0,
+                            input.usage.1
+                        )
+                        .unwrap(),
+                        false => writeln!(text, "  {}..{}", start, start + size).unwrap(),
+                    }
+                }
+            }
+        }
+    }
+
+    text
+}
+
+#[test]
+fn matches_reference_layouts() {
+    let descriptors = descriptors();
+    assert!(!descriptors.is_empty());
+
+    for path in descriptors {
+        let bytes = fs::read(&path).unwrap();
+        let reference_path = path.with_extension("layout");
+        if env::var_os("HID_REFERENCE_UPDATE").is_some() {
+            let reference = reference(&bytes, true).expect("hidreport rejects the descriptor");
+            fs::write(&reference_path, reference).unwrap();
+        }
+
+        let parser = Parser::with_strictness(BasicItems::new(&bytes), Strictness::Strict).unwrap();
+        assert_eq!(
+            layout(&parser, true),
+            fs::read_to_string(&reference_path).unwrap(),
+            "{} differs from the reference",
+            path.display()
+        );
+    }
+}
+
+// A descriptor of the directory after a chain of mutations, each picked by its index among
+// the mutations of the previous descriptor
+fn mutated(descriptor: usize, picks: &[usize]) -> (ReportDescriptor, Vec<String>) {
+    let paths = descriptors();
+    let bytes = fs::read(&paths[descriptor % paths.len()]).unwrap();
+    let mut descriptor = ReportDescriptor { bytes };
+    let mut descriptions = vec![];
+
+    for pick in picks {
+        let mut mutations = descriptor.mutations();
+        if mutations.is_empty() {
+            break;
+        }
+        let mutation = mutations.swap_remove(pick % mutations.len());
+        descriptions.push(mutation.description);
+        descriptor = mutation.descriptor;
+    }
+
+    (descriptor, descriptions)
+}
+
+proptest! {
+    // failing cases are kept in reference.proptest-regressions and tried first from then on
+    #![proptest_config(ProptestConfig {
+        cases: 512,
+        failure_persistence: Some(Box::new(FileFailurePersistence::WithSource("regressions"))),
+        ..ProptestConfig::default()
+    })]
+
+    #[test]
+    fn decodes_mutated_descriptors(
+        descriptor in any::<usize>(),
+        picks in prop::collection::vec(any::<usize>(), 1..4),
+    ) {
+        let (descriptor, descriptions) = mutated(descriptor, &picks);
+
+        // whatever the mutations, decoding doesn't panic and permissive decoding doesn't fail
+        let parser = descriptor.decode();
+        for strictness in [Strictness::Strict, Strictness::Spec] {
+            if let Ok(strict) = descriptor.decode_with(strictness) {
+                prop_assert_eq!(&strict, &parser, "{:?}", descriptions);
+            }
+        }
+        let _ = parser.to_string();
+        let _ = lint::lint(&descriptor);
+        for report in parser.reports() {
+            let length = parser.report_length(ReportKind::Input, report.report_id);
+            let mut bytes = vec![0xff; length.unwrap_or(8).clamp(1, 64)];
+            if let Some(id) = report.report_id {
+                bytes[0] = id;
+            }
+            parser.parse_input(&bytes);
+        }
+
+        // and descriptors both parsers accept are laid out the same
+        let too_long = parser
+            .reports()
+            .iter()
+            .any(|report| report.bit_offset.saturating_add(report.bit_length()) > MAX_BITS);
+        if let (Ok(parser), false) = (descriptor.decode_with(Strictness::Spec), too_long) {
+            if let Some(reference) = reference(&descriptor.bytes, false) {
+                prop_assert_eq!(layout(&parser, false), reference, "{:?}", descriptions);
+            }
+        }
+    }
+}
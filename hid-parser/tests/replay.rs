@@ -0,0 +1,100 @@
+// Replays the captures in tests/captures through the parser and compares the inputs decoded
+// from every input report with a snapshot in tests/snapshots
+//
+// Synthetic descriptors only cover what their authors thought of. Replaying recorded reports
+// catches changes in value decoding (sign extension of odd sizes, null states, arrays out of
+// range, reports of unknown IDs) on the data devices actually send. Every .hbc file in the
+// directory is replayed, so a recording made with `hid-bench device record` only needs to be
+// copied there and its snapshot reviewed with `cargo insta review`.
+//
+// The captures so far were written for well-known layouts rather than recorded: a joystick
+// with 10 bit axes and a hat with a null state, a boot keyboard with its key array including
+// rollover errors and keys out of range, and a mouse with 12 bit relative axes, a consumer
+// control array, reports too short and of an unknown ID, and an interface without a
+// descriptor.
+
+use std::{
+    fmt::Write,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use hid_parser::{
+    capture::{Capture, Direction},
+    usage, FlatInputs, InputValue,
+};
+
+fn captures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/captures");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "hbc"))
+        .collect();
+    paths.sort();
+
+    paths
+}
+
+// One line per input report: time, interface, bytes and the inputs decoded from them
+fn replay(capture: &Capture) -> String {
+    let mut flat = FlatInputs::new();
+    let mut text = String::new();
+
+    let inputs = capture
+        .transfers
+        .iter()
+        .filter(|transfer| transfer.direction == Direction::In);
+    for transfer in inputs {
+        match capture.descriptors.parser(transfer.interface) {
+            Some(parser) => parser.parse_input_flat(&transfer.bytes, &mut flat),
+            None => flat.clear(),
+        }
+
+        let values: Vec<_> = flat
+            .inputs()
+            .iter()
+            .map(|input| {
+                let value = match input.value {
+                    InputValue::Bool(b) => b.to_string(),
+                    InputValue::UInt(u) => u.to_string(),
+                    InputValue::Int(i) => i.to_string(),
+                    InputValue::Selected => "selected".to_string(),
+                    InputValue::None => "null".to_string(),
+                    value => format!("{:?}", value),
+                };
+
+                format!("{} {}", usage::short_name(input.usage), value)
+            })
+            .collect();
+        let values = match values.is_empty() {
+            true => "no inputs".to_string(),
+            false => values.join(", "),
+        };
+
+        writeln!(
+            text,
+            "{:9.3} ms #{} {:02x?}: {}",
+            transfer.timestamp.as_secs_f64() * 1000.0,
+            transfer.interface,
+            transfer.bytes,
+            values
+        )
+        .unwrap();
+    }
+
+    text
+}
+
+#[test]
+fn replays_captures() {
+    let captures = captures();
+    assert!(!captures.is_empty());
+
+    for path in captures {
+        let capture = Capture::read(File::open(&path).unwrap()).unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy();
+
+        insta::assert_snapshot!(format!("replay_{}", name), replay(&capture));
+    }
+}